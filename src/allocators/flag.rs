@@ -5,7 +5,7 @@
 //!     - it is not used elsewhere
 
 use crate::intermediate::analysis;
-use std::collections::HashMap;
+use hashbrown::HashMap;
 
 use crate::{
     codegen::assembly::Condition,
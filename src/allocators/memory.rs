@@ -1,6 +1,8 @@
 //! First step of code generation is to get the stack memory usage for the code
 
-use std::collections::{HashMap, HashSet};
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
 
 use crate::intermediate::{BasicBlock, Binding, BlockBinding, Statement, Value, IR};
 
@@ -15,7 +17,7 @@ pub type MemoryMap = HashMap<Binding, assembly::Memory>;
 
 // assumes to != 0
 pub fn align(value: usize, to: usize) -> usize {
-    if value % to == 0 {
+    if value.is_multiple_of(to) {
         value
     } else {
         to * (value / to + 1)
@@ -27,21 +29,46 @@ pub fn align(value: usize, to: usize) -> usize {
 //  - if the place is not taken, assign that index
 use crate::intermediate::analysis;
 
-type AllocMap = HashMap<Binding, usize>;
+/// The size and alignment of each piece of memory
+type AllocMap = HashMap<Binding, (usize, usize)>;
 
 pub fn figure_out_allocations(
     ir: &IR,
     allocations_needed: AllocMap,
     collision_map: &analysis::lifetimes::CollisionMap,
-) -> (MemoryMap, usize) {
+) -> (MemoryMap, usize, usize) {
+    // once the address of a piece of memory is taken it can be used through pointers at any
+    // point, so it can't share its place with any other
+    let escaped: HashSet<Binding> = ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                value: Value::Address { mem_binding: base } | Value::ElementAddress { base, .. },
+                ..
+            } if allocations_needed.contains_key(base) => Some(*base),
+            _ => None,
+        })
+        .collect();
     let mut local_collisions: Vec<(_, HashSet<_>)> = collision_map
         .iter()
         .filter_map(|(k, set)| {
-            if allocations_needed.contains_key(k) {
+            if escaped.contains(k) {
+                Some((
+                    *k,
+                    allocations_needed
+                        .keys()
+                        .filter(|other| *other != k)
+                        .copied()
+                        .collect(),
+                ))
+            } else if allocations_needed.contains_key(k) {
                 Some((
                     *k,
                     set.iter()
-                        .filter(|k| allocations_needed.contains_key(k))
+                        .chain(&escaped)
+                        .filter(|k| allocations_needed.contains_key(*k))
                         .copied()
                         .collect(),
                 ))
@@ -50,7 +77,8 @@ pub fn figure_out_allocations(
             }
         })
         .collect();
-    local_collisions.sort_by_key(|(_, v)| v.len());
+    // the order of the maps changes from one compilation to another, but the output shouldn't
+    local_collisions.sort_by_key(|(binding, v)| (v.len(), *binding));
     let mut blocks: Vec<HashSet<_>> = Vec::new();
 
     for (binding, collisions) in local_collisions {
@@ -72,21 +100,40 @@ pub fn figure_out_allocations(
         }
     }
 
-    // allocation is done, now we gotta transform the data into offsets
+    // allocation is done, now we gotta transform the data into offsets. The slots are laid out
+    // from the most aligned to the least, so that no padding is needed between them.
+    let mut slots: Vec<_> = blocks
+        .into_iter()
+        .map(|block| {
+            let block_size = block
+                .iter()
+                .map(|binding| allocations_needed[binding].0)
+                .max()
+                .unwrap();
+            let block_align = block
+                .iter()
+                .map(|binding| allocations_needed[binding].1)
+                .max()
+                .unwrap();
+            (block, block_size, block_align)
+        })
+        .collect();
+    slots.sort_by_key(|(_, _, block_align)| core::cmp::Reverse(*block_align));
 
     let mut offsets = HashMap::new();
     let mut size = 0;
+    // the strictest alignment of all, which the base of the allocations must have
+    let mut max_align = 1;
+
+    for (block, block_size, block_align) in slots {
+        size = align(size, block_align);
+        max_align = max_align.max(block_align);
 
-    for block in blocks {
         // assign the offset as the current size
         offsets.extend(block.iter().copied().map(|binding| (binding, size)));
 
         // now we bump the size by the max value of those allocations
-        size += block
-            .into_iter()
-            .map(|binding| allocations_needed[&binding])
-            .max()
-            .unwrap();
+        size += block_size;
     }
 
     (
@@ -103,6 +150,7 @@ pub fn figure_out_allocations(
             })
             .collect(),
         size,
+        max_align,
     )
 }
 
@@ -114,16 +162,55 @@ pub fn make_alloc_map(code: &[BasicBlock]) -> AllocMap {
 }
 
 /// List the memory allocations that the block defines
-fn list_memory_defs(block: &[Statement]) -> impl Iterator<Item = (Binding, usize)> + '_ {
+fn list_memory_defs(block: &[Statement]) -> impl Iterator<Item = (Binding, (usize, usize))> + '_ {
     block.iter().filter_map(|statement| {
         if let Statement::Assign {
             index,
-            value: Value::Allocate { size },
+            value: Value::Allocate { size, align },
         } = statement
         {
-            Some((*index, *size))
+            Some((*index, (*size, *align)))
         } else {
             None
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::*;
+
+    #[test]
+    fn slots_are_aligned_without_padding_between_them() {
+        // the addresses are taken, so each piece of memory needs a slot of its own
+        let sizes = [(1, 1), (8, 8), (1, 1), (16, 16), (4, 4)];
+        let mut statements = Vec::new();
+        for (i, &(size, align)) in sizes.iter().enumerate() {
+            statements.push(Statement::Assign {
+                index: Binding(2 * i),
+                value: Value::Allocate { size, align },
+            });
+            statements.push(Statement::Assign {
+                index: Binding(2 * i + 1),
+                value: Value::Address {
+                    mem_binding: Binding(2 * i),
+                },
+            });
+        }
+        let ir = IR::from(alloc::vec![BasicBlock {
+            statements,
+            end: BlockEnd::Return(Binding(1)),
+        }]);
+        let collisions = analysis::compute_lifetime_collisions(&ir);
+        let (memory, size, max_align) =
+            figure_out_allocations(&ir, make_alloc_map(&ir.code), &collisions);
+        for (i, &(_, align)) in sizes.iter().enumerate() {
+            let assembly::Offset::Determined(offset) = memory[&Binding(2 * i)].offset else {
+                panic!("the offsets are known once allocated");
+            };
+            assert!(offset.is_multiple_of(align));
+        }
+        assert_eq!((size, max_align), (30, 16));
+    }
+}
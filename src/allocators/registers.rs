@@ -1,11 +1,10 @@
 //! Register analysis of the code
-use crate::codegen::assembly::RegisterID;
-use crate::intermediate::{analysis::CollisionMap, Binding, BlockEnd, IR};
-use std::collections::HashMap;
-use std::collections::HashSet;
+use alloc::vec::Vec;
 
-// TODO: when failing to produce same register on phi nodes, make sure after allocations
-// that those registers that go to phi nodes are put correctly before each branch.
+use crate::codegen::assembly::RegisterID;
+use crate::intermediate::{analysis, analysis::CollisionMap, Binding, BlockEnd, IR};
+use hashbrown::HashMap;
+use hashbrown::HashSet;
 
 pub type RegisterMap = HashMap<Binding, RegisterID>;
 
@@ -15,6 +14,9 @@ pub enum AllocatorHints {
         used_in_return: bool,
         is_zero: bool,
         used_through_call: bool,
+        /// the value arrives in (or is best placed in) this register, like arguments or call
+        /// results
+        preferred_register: Option<u8>,
         from_phi_node: Option<HashSet<Binding>>,
         is_phi_node_with: HashSet<Binding>,
     },
@@ -26,6 +28,7 @@ enum ImmediateHint {
         used_in_return: bool,
         is_zero: bool,
         used_through_call: bool,
+        preferred_register: Option<u8>,
     },
 }
 
@@ -41,6 +44,7 @@ impl HintBuilder {
             hints: AllocatorHints::Usable {
                 is_phi_node_with: HashSet::new(),
                 used_through_call: false,
+                preferred_register: None,
                 used_in_return: false,
                 is_zero: false,
                 from_phi_node: None,
@@ -66,6 +70,15 @@ impl HintBuilder {
         }
     }
 
+    pub fn caught_in_register(&mut self, register: u8) {
+        if let AllocatorHints::Usable {
+            preferred_register, ..
+        } = &mut self.hints
+        {
+            *preferred_register = Some(register);
+        }
+    }
+
     pub fn caught_returned(&mut self) {
         if let AllocatorHints::Usable { used_in_return, .. } = &mut self.hints {
             *used_in_return = true;
@@ -115,12 +128,15 @@ impl Default for HintBuilder {
 }
 
 pub fn make_allocator_hints(code: &IR) -> HashMap<Binding, AllocatorHints> {
+    use crate::intermediate::analysis::lifetimes::{self, BlockAddress};
     let mut map = HashMap::<Binding, HintBuilder>::new();
+    let mut calls = Vec::new();
     for block in crate::intermediate::analysis::TopBottomTraversal::from(code) {
-        for statement in &code[block].statements {
+        for (statement_index, statement) in code[block].statements.iter().enumerate() {
             if let crate::intermediate::Statement::Assign { index, value } = statement {
                 match value {
-                    crate::intermediate::Value::Constant(0) => {
+                    crate::intermediate::Value::Constant(0)
+                    | crate::intermediate::Value::Undefined => {
                         map.entry(*index).or_default().caught_zero();
                     }
                     crate::intermediate::Value::Phi { nodes } => {
@@ -139,23 +155,65 @@ pub fn make_allocator_hints(code: &IR) -> HashMap<Binding, AllocatorHints> {
                     crate::intermediate::Value::Allocate { .. } => {
                         map.entry(*index).or_default().value_is_memory()
                     }
+                    crate::intermediate::Value::Argument(argument_index)
+                    | crate::intermediate::Value::FloatArgument(argument_index) => {
+                        map.entry(*index)
+                            .or_default()
+                            .caught_in_register(*argument_index as u8);
+                    }
+                    // a struct returned through memory goes where `x8` points
+                    crate::intermediate::Value::ResultAddress { .. } => {
+                        map.entry(*index).or_default().caught_in_register(8);
+                    }
+                    crate::intermediate::Value::Call { .. }
+                    | crate::intermediate::Value::CallPointer { .. }
+                    | crate::intermediate::Value::CallStruct { .. }
+                    | crate::intermediate::Value::CallFloat { .. } => {
+                        map.entry(*index).or_default().caught_in_register(0);
+                        calls.push(BlockAddress {
+                            block,
+                            statement: statement_index,
+                        });
+                    }
                     _ => (),
                 }
-                // TODO: match call
             }
         }
         if let BlockEnd::Return(binding) = code[block].end {
             map.entry(binding).or_default().caught_returned();
         }
     }
+    // the caller-saved registers are clobbered by calls, so whatever lives through one should
+    // stay in a callee-saved register
+    if !calls.is_empty() {
+        for (binding, lifetime) in lifetimes::compute_lifetimes(code) {
+            if calls.iter().any(|&call| lifetime.is_live_at(call, code)) {
+                map.entry(binding).or_default().caught_used_through_call();
+            }
+        }
+    }
     map.into_iter()
         .map(|(binding, builder)| (binding, builder.finish()))
         .collect()
 }
 
-#[allow(dead_code)] // this function will be used upon having calls, don't worry
-fn is_callee_saved(register: u8) -> bool {
-    (9..=15).contains(&register)
+/// Registers that the AAPCS64 requires to be preserved across calls (x19-x28). Registers x16 to
+/// x18 are reserved for the linker and the platform, while x29 and x30 hold the frame record.
+pub fn is_callee_saved(register: u8) -> bool {
+    (19..=28).contains(&register)
+}
+
+/// Floating-point registers whose lower 64 bits the AAPCS64 requires to be preserved across calls
+/// (d8-d15)
+pub fn is_float_callee_saved(register: u8) -> bool {
+    (8..=15).contains(&register)
+}
+
+/// The register a global register variable can be kept in, by its name (`x18` to `x28`). Those
+/// are never clobbered by calls, and the allocator can do without them.
+pub fn global_register(name: &str) -> Option<u8> {
+    let index: u8 = name.strip_prefix('x')?.parse().ok()?;
+    (index == 18 || is_callee_saved(index)).then_some(index)
 }
 
 #[derive(Debug)]
@@ -165,7 +223,6 @@ pub struct CodegenHints {
     pub need_move_to_return_reg: HashSet<Binding>,
     /// list of bindings that couldn't be allocated a callee-saved register so they need to be
     /// pre-saved before a call is met.
-    #[allow(dead_code)]
     pub save_upon_call: HashSet<Binding>,
 
     /// set of bindings that could not be allocated, for whatever reason
@@ -190,20 +247,26 @@ pub struct AllocatorState {
     got_zero: HashSet<Binding>,
     /// buckets for allocations
     buckets: [HashSet<Binding>; 31],
+    /// buckets for allocations in the floating-point registers
+    float_buckets: [HashSet<Binding>; 32],
+    /// bindings that hold floating-point values, which go to the floating-point registers
+    floats: HashSet<Binding>,
+    /// registers that hold global register variables, which nothing else may use
+    reserved: HashSet<u8>,
 }
 
-impl std::fmt::Debug for AllocatorState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for AllocatorState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         struct DebugBuckets<'s>(&'s [HashSet<Binding>]);
         struct DebugBucket<'s>(usize, &'s HashSet<Binding>);
-        impl std::fmt::Debug for DebugBucket<'_> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl core::fmt::Debug for DebugBucket<'_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
                 write!(f, "r{} => {:?}", self.0, self.1)
             }
         }
 
-        impl std::fmt::Debug for DebugBuckets<'_> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl core::fmt::Debug for DebugBuckets<'_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
                 f.debug_list()
                     .entries(
                         self.0
@@ -226,6 +289,9 @@ impl std::fmt::Debug for AllocatorState {
         if self.buckets.iter().any(|s| !s.is_empty()) {
             dbg_st.field("allocations", &DebugBuckets(&self.buckets));
         }
+        if self.float_buckets.iter().any(|s| !s.is_empty()) {
+            dbg_st.field("float allocations", &DebugBuckets(&self.float_buckets));
+        }
         dbg_st.finish()
     }
 }
@@ -245,15 +311,39 @@ impl AllocatorState {
             spills: HashSet::new(),
             got_zero: HashSet::new(),
             buckets: Default::default(),
+            float_buckets: Default::default(),
+            floats: HashSet::new(),
+            reserved: HashSet::new(),
         }
     }
 
-    fn get_sorted_indices(&self, range: impl Iterator<Item = u8>) -> impl Iterator<Item = u8> {
+    pub fn with_reserved(reserved: &[u8]) -> Self {
+        Self {
+            reserved: reserved.iter().copied().collect(),
+            ..Self::new()
+        }
+    }
+
+    /// The buckets of the register file that the binding goes to
+    fn buckets_of(&self, binding: Binding) -> &[HashSet<Binding>] {
+        if self.floats.contains(&binding) {
+            &self.float_buckets
+        } else {
+            &self.buckets
+        }
+    }
+
+    fn get_sorted_indices(
+        &self,
+        binding: Binding,
+        range: impl Iterator<Item = u8>,
+    ) -> impl Iterator<Item = u8> {
+        let buckets = self.buckets_of(binding);
         let mut all: Vec<_> = range.into_iter().collect();
         all.sort_by(|a, b| {
-            self.buckets[*a as usize]
+            buckets[*a as usize]
                 .len()
-                .cmp(&self.buckets[*b as usize].len())
+                .cmp(&buckets[*b as usize].len())
                 .reverse()
         });
         all.into_iter()
@@ -272,7 +362,7 @@ impl AllocatorState {
     ) -> Option<RegisterID> {
         match alloc {
             RegisterID::ZeroRegister => Some(self.is_zero(binding)),
-            RegisterID::GeneralPurpose { index } => {
+            RegisterID::GeneralPurpose { index } | RegisterID::Float { index } => {
                 self.try_register(binding, collides_with, index)
             }
             RegisterID::StackPointer => Some(self.spill(binding)),
@@ -285,7 +375,17 @@ impl AllocatorState {
         collides_with: &HashSet<Binding>,
         register: u8,
     ) -> Option<RegisterID> {
-        if self.buckets[register as usize].is_disjoint(collides_with) {
+        if self.floats.contains(&binding) {
+            // global register variables are integers, they don't reserve floating-point registers
+            let bucket = &mut self.float_buckets[register as usize];
+            return bucket.is_disjoint(collides_with).then(|| {
+                bucket.insert(binding);
+                RegisterID::Float { index: register }
+            });
+        }
+        if !self.reserved.contains(&register)
+            && self.buckets[register as usize].is_disjoint(collides_with)
+        {
             self.buckets[register as usize].insert(binding);
             Some(RegisterID::GeneralPurpose { index: register })
         } else {
@@ -298,8 +398,14 @@ impl AllocatorState {
         binding: Binding,
         collides_with: &HashSet<Binding>,
     ) -> Option<RegisterID> {
-        self.get_sorted_indices((0..9).chain(16..31))
-            .find_map(|bucket| self.try_register(binding, collides_with, bucket as u8))
+        // v16-v31 are as free as v0-v7
+        let registers: Vec<u8> = if self.floats.contains(&binding) {
+            (0..8).chain(16..32).collect()
+        } else {
+            (0..16).collect()
+        };
+        self.get_sorted_indices(binding, registers.into_iter())
+            .find_map(|bucket| self.try_register(binding, collides_with, bucket))
     }
 
     pub fn try_saved_register(
@@ -307,8 +413,13 @@ impl AllocatorState {
         binding: Binding,
         collides_with: &HashSet<Binding>,
     ) -> Option<RegisterID> {
-        self.get_sorted_indices(9..=15)
-            .find_map(|bucket| self.try_register(binding, collides_with, bucket as u8))
+        let registers = if self.floats.contains(&binding) {
+            8..=15
+        } else {
+            19..=28
+        };
+        self.get_sorted_indices(binding, registers)
+            .find_map(|bucket| self.try_register(binding, collides_with, bucket))
     }
 
     // try non-saved; then follow by saved
@@ -339,6 +450,15 @@ impl AllocatorState {
                     }
                 })
             })
+            .or_else(|| {
+                self.float_buckets
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, set)| {
+                        set.contains(&binding)
+                            .then_some(RegisterID::Float { index: index as u8 })
+                    })
+            })
     }
 }
 
@@ -347,10 +467,14 @@ pub fn alloc_registers(
     collisions: &CollisionMap,
     need_allocation: Vec<Binding>,
     alloc_hints: HashMap<Binding, AllocatorHints>,
+    reserved_registers: &[u8],
 ) -> CodegenHints {
     // TODO: reserve phi nodes hints for later, when all of its dependencies are allocated.
     // and so on.
-    let mut state = AllocatorState::new();
+    let mut state = AllocatorState {
+        floats: analysis::float_bindings(ir),
+        ..AllocatorState::with_reserved(reserved_registers)
+    };
     let mut might_need_call_save = HashSet::new();
     let mut might_need_move_to_x0 = HashSet::new();
     let (immediate_alloc_hints, mut phi_nodes, mut phi_edges) = {
@@ -367,6 +491,7 @@ pub fn alloc_registers(
                     used_in_return,
                     is_zero,
                     used_through_call,
+                    preferred_register,
                     from_phi_node,
                     is_phi_node_with,
                 } => {
@@ -382,6 +507,7 @@ pub fn alloc_registers(
                             used_in_return,
                             is_zero,
                             used_through_call,
+                            preferred_register,
                         },
                     );
                     if let Some(nodes) = from_phi_node {
@@ -410,6 +536,7 @@ pub fn alloc_registers(
                     used_in_return,
                     is_zero,
                     used_through_call,
+                    preferred_register,
                 } => {
                     if used_through_call {
                         // if the binding is returned, we *probably* assigned a non-return register, so set it
@@ -420,20 +547,24 @@ pub fn alloc_registers(
                         // we're going to look through the buckets and check our collisions to find if we can
                         // allocate a callee-saved register
                         state.try_saved_register(binding, collisions).or_else(|| {
-                            state.try_nonsaved_register(binding, collisions).map(|x| {
-                                state.save_when_call.insert(binding);
-                                x
-                            })
+                            state
+                                .try_nonsaved_register(binding, collisions)
+                                .inspect(|_| {
+                                    state.save_when_call.insert(binding);
+                                })
                         })
+                    } else if let Some(register) = preferred_register {
+                        state.try_register(binding, collisions, register)
                     } else if used_in_return {
                         state.try_register(binding, collisions, 0).or_else(|| {
-                            state.try_standard_alloc(binding, collisions).map(|x| {
+                            state.try_standard_alloc(binding, collisions).inspect(|_| {
                                 state.need_move_to_return_reg.insert(binding);
-                                x
                             })
                         })
                     } else {
-                        is_zero.then(|| state.is_zero(binding))
+                        // there's no floating-point zero register
+                        (is_zero && !state.floats.contains(&binding))
+                            .then(|| state.is_zero(binding))
                     }
                 }
             }
@@ -452,67 +583,29 @@ pub fn alloc_registers(
         }
         let collisions = collisions.get(&binding).unwrap();
         allocated.insert(binding);
-        let phi_alloc = phi_edges
+        // a phi node goes in the same register as its values when it can, which saves moving
+        // them there at the end of the blocks they come from. Whatever got a register before has
+        // it already.
+        let final_alloc = starting_allocs
             .remove(&binding)
-            .and_then(|edges| {
-                let allocs: HashSet<_> = edges
-                    .iter()
-                    .flat_map(|binding| state.get_allocation(*binding))
+            .or_else(|| {
+                let candidates: Vec<_> = phi_edges
+                    .remove(&binding)
+                    .into_iter()
+                    .chain(phi_nodes.remove(&binding))
+                    .flatten()
+                    .filter_map(|other| state.get_allocation(other))
+                    .filter(|alloc| {
+                        matches!(
+                            alloc,
+                            RegisterID::GeneralPurpose { .. } | RegisterID::Float { .. }
+                        )
+                    })
                     .collect();
-
-                if allocs.is_empty() {
-                    None // not going to do anything, use another hint
-                } else {
-                    // allocate the same as the rest of bindings that it is target to
-                    debug_assert_eq!(
-                        allocs.len(),
-                        1,
-                        "Expecting all allocated edges to be in the same place"
-                    );
-                    let unique_alloc = allocs.into_iter().next().unwrap();
-                    debug_assert!(
-                        state.try_alloc(binding, collisions, unique_alloc).is_some(),
-                        "Must be able to allocate binding on same register as its phi edges"
-                    );
-
-                    Some(unique_alloc)
-                }
+                candidates
+                    .into_iter()
+                    .find_map(|alloc| state.try_alloc(binding, collisions, alloc))
             })
-            .or_else(|| {
-                phi_nodes.remove(&binding).map(|phi_nodes| {
-                    // NOTE: might have to reorder allocations so that collisions are resolved
-                    // must have allocated everyone
-                    let allocs: Vec<_> = phi_nodes
-                        .iter()
-                        .flat_map(|binding| state.get_allocation(*binding))
-                        .collect();
-
-                    debug_assert_eq!(
-                        allocs.len(),
-                        phi_nodes.len(),
-                        "All phi nodes must have been previously allocated"
-                    );
-
-                    let allocs: HashSet<_> = allocs.into_iter().collect();
-
-                    // NOTE: this will be removed if needed
-                    debug_assert_eq!(
-                        allocs.len(),
-                        1,
-                        "All allocated phi nodes must be on the same register"
-                    );
-
-                    let unique_alloc = allocs.into_iter().next().unwrap();
-
-                    debug_assert!(
-                        state.try_alloc(binding, collisions, unique_alloc).is_some(),
-                        "Must be able to allocate binding on same register as its phi nodes"
-                    );
-                    unique_alloc
-                })
-            });
-        let final_alloc = phi_alloc
-            .or_else(|| starting_allocs.remove(&binding))
             .or_else(|| state.try_standard_alloc(binding, collisions));
 
         if might_need_move_to_x0.contains(&binding)
@@ -526,10 +619,10 @@ pub fn alloc_registers(
         if might_need_call_save.contains(&binding)
             && final_alloc
                 .filter(|alloc| {
-                    if let RegisterID::GeneralPurpose { index } = alloc {
-                        !is_callee_saved(*index)
-                    } else {
-                        false /* non-gp registers are not read-only */
+                    match alloc {
+                        RegisterID::GeneralPurpose { index } => !is_callee_saved(*index),
+                        RegisterID::Float { index } => !is_float_callee_saved(*index),
+                        _ => false, /* non-gp registers are not read-only */
                     }
                 })
                 .is_some()
@@ -560,6 +653,22 @@ pub fn alloc_registers(
                     )
                 })
             })
+            .chain(
+                state
+                    .float_buckets
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(register, bindings)| {
+                        bindings.into_iter().map(move |binding| {
+                            (
+                                binding,
+                                RegisterID::Float {
+                                    index: register as u8,
+                                },
+                            )
+                        })
+                    }),
+            )
             .chain(
                 state
                     .got_zero
@@ -625,8 +734,9 @@ mod tests {
     fn compile_source_into_ir(source: &str) -> anyhow::Result<crate::intermediate::IR> {
         let meta = crate::error::SourceMetadata::new(source).with_file("<test program>".into());
         let program = crate::grammar::Parser::new(&meta).parse()?;
-        let (_function_name, ir) = crate::intermediate::generate::compile_program(program, &meta)?;
-        Ok(ir)
+        let (mut module, _warnings) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        Ok(module.functions.remove(0).ir)
     }
 
     #[test]
@@ -646,6 +756,7 @@ int main() {
             &collisions,
             collisions.keys().cloned().collect(),
             hints,
+            &[],
         );
         assert!(
             result.need_move_to_return_reg.is_empty(),
@@ -657,4 +768,32 @@ int main() {
             "There should be no left outs for this program"
         );
     }
+
+    #[test]
+    fn reserved_registers_are_never_allocated() {
+        let ir = compile_source_into_ir(
+            r#"
+int f(int x);
+int main() {
+  int a = f(1);
+  int b = f(2);
+  return a + b + f(3);
+}"#,
+        )
+        .unwrap();
+        let collisions = crate::intermediate::analysis::compute_lifetime_collisions(&ir);
+        let hints = make_allocator_hints(&ir);
+        let reserved = [19, 20, 28];
+        let result = alloc_registers(
+            &ir,
+            &collisions,
+            collisions.keys().cloned().collect(),
+            hints,
+            &reserved,
+        );
+        assert!(result.registers.values().all(|register| !matches!(
+            register,
+            RegisterID::GeneralPurpose { index } if reserved.contains(index)
+        )));
+    }
 }
@@ -1,31 +1,222 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codegen::target::{self, ScalarLayout};
 use crate::error::Span;
 use crate::grammar::lexer::Operator;
 use crate::grammar::lexer::Source;
 
-use std::fmt;
+use alloc::sync::Arc;
+use core::fmt;
 
 // TODO: spans
 
-pub struct Program<'source>(pub Vec<Function<'source>>);
+pub struct Program<'source> {
+    pub functions: Vec<Function<'source>>,
+    /// the variables declared at file scope, in order
+    pub globals: Vec<GlobalVariable<'source>>,
+    pub global_registers: Vec<GlobalRegister<'source>>,
+    /// the enumerators of the enums defined at file scope, one list per enum
+    pub enums: Vec<Vec<Enumerator<'source>>>,
+}
 
 impl fmt::Debug for Program<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        for GlobalRegister { ty, name, register } in &self.global_registers {
+            writeln!(
+                f,
+                "register {} {}@{:?} in {:?}",
+                ty,
+                name.source,
+                name.span.as_range(),
+                register.source
+            )?;
+        }
+        for enumerators in &self.enums {
+            writeln!(f, "enum")?;
+            format_enumerators(enumerators, f, 1)?;
+        }
+        for GlobalVariable {
+            declarator: Declarator { ty, name, init },
+            linkage,
+            is_extern,
+            align,
+        } in &self.globals
+        {
+            writeln!(
+                f,
+                "global {} {}@{:?} ({:?}{})",
+                ty,
+                name.source,
+                name.span.as_range(),
+                linkage,
+                if *is_extern { ", extern" } else { "" }
+            )?;
+            for (align_expr, align_span) in align {
+                write!(f, "  align: ")?;
+                format_expr(align_expr, *align_span, f, 2)?;
+            }
+            if let Some((init, init_span)) = init {
+                format_initializer(init, *init_span, f, 1)?;
+            }
+        }
+        self.functions.fmt(f)
     }
 }
 
+/// A named `int` constant declared in an `enum`
+#[derive(Debug)]
+pub struct Enumerator<'source> {
+    pub name: Source<'source>,
+    /// the value it's given, otherwise it's one more than the previous one (or 0 for the first)
+    pub value: Option<(Expr<'source>, Span)>,
+}
+
+/// A variable declared at file scope, which lives in memory for the whole program
+#[derive(Debug)]
+pub struct GlobalVariable<'source> {
+    pub declarator: Declarator<'source>,
+    pub linkage: Linkage,
+    /// whether it's declared `extern`, which only declares it if it has no initializer: it's
+    /// defined elsewhere then
+    pub is_extern: bool,
+    /// the alignments asked for with `_Alignas`, like those of [`Statement::DeclareVars`]
+    pub align: Vec<(Expr<'source>, Span)>,
+}
+
+/// A global variable that lives in a register instead of memory (the GNU
+/// `register int x asm("x28");` extension)
+#[derive(Debug, Clone)]
+pub struct GlobalRegister<'source> {
+    pub ty: Type,
+    pub name: Source<'source>,
+    /// the name of the register, without the quotes
+    pub register: Source<'source>,
+}
+
 pub struct Function<'source> {
-    pub name: Identifier<'source>,
-    pub body: Block<'source>,
+    pub name: Source<'source>,
+    pub linkage: Linkage,
+    /// whether it's declared `inline`, a hint that its calls should be replaced by its body
+    pub inline: bool,
+    /// `None` for functions returning `void`
+    pub return_type: Option<Type>,
+    pub params: Vec<Parameter<'source>>,
+    /// whether the parameters were left unspecified with `()`, unlike `(void)`, which declares
+    /// there are none. Calls to such functions aren't checked.
+    pub params_unspecified: bool,
+    /// whether the parameters end in `...`, so that calls may pass more arguments after them
+    pub variadic: bool,
+    /// `None` for prototypes, which only declare the function
+    pub body: Option<Block<'source>>,
+    /// the attributes given with `__attribute__((...))`, before or after the declarator
+    pub attributes: Vec<(Attribute<'source>, Span)>,
+}
+
+/// An attribute of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute<'source> {
+    /// `weak`: the symbol may be left undefined, and a definition in another file takes the place
+    /// of this one
+    Weak,
+    /// `alias("target")`: the function is another name for `target`, which is defined in the same
+    /// file
+    Alias(Source<'source>),
 }
 
 impl fmt::Debug for Function<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Function {:?}", self.name)?;
-        format_block(&self.body.statements, f, 1)
+        writeln!(f, "Function {:?} ({:?})", self.name.source, self.linkage)?;
+        match &self.return_type {
+            Some(ty) => writeln!(f, "  returns {}", ty)?,
+            None => writeln!(f, "  returns void")?,
+        }
+        if self.params_unspecified {
+            writeln!(f, "  (unspecified params)")?;
+        }
+        if self.variadic {
+            writeln!(f, "  (variadic)")?;
+        }
+        if self.inline {
+            writeln!(f, "  (inline)")?;
+        }
+        for (attribute, span) in &self.attributes {
+            match attribute {
+                Attribute::Weak => writeln!(f, "  attribute weak@{:?}", span.as_range())?,
+                Attribute::Alias(target) => writeln!(
+                    f,
+                    "  attribute alias({:?})@{:?}",
+                    target.source,
+                    span.as_range()
+                )?,
+            }
+        }
+        for Parameter { ty, name } in &self.params {
+            writeln!(
+                f,
+                "  param {} {}@{:?}",
+                ty,
+                name.source,
+                name.span.as_range()
+            )?;
+        }
+        match &self.body {
+            Some(body) => format_block(&body.statements, f, 1),
+            None => writeln!(f, "  (prototype)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter<'source> {
+    pub ty: Type,
+    pub name: Source<'source>,
+}
+
+/// The revision of the C standard the source is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Standard {
+    C89,
+    #[default]
+    C99,
+    C11,
+}
+
+impl core::str::FromStr for Standard {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "c89" | "c90" => Self::C89,
+            "c99" => Self::C99,
+            "c11" => Self::C11,
+            _ => return Err(format!("unknown standard `{}`", s)),
+        })
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::C89 => "c89",
+            Self::C99 => "c99",
+            Self::C11 => "c11",
+        })
     }
 }
 
+/// Whether a symbol can be seen from other translation units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Linkage {
+    External,
+    /// declared `static`
+    Internal,
+    /// external, but declared `weak`: a definition in another translation unit takes its place
+    Weak,
+}
+
 //#[derive(Debug)]
 pub struct Block<'source> {
     pub statements: Vec<(Statement<'source>, Span)>,
@@ -41,14 +232,37 @@ impl fmt::Debug for Block<'_> {
     }
 }
 
+/// One of the variables a declaration declares, with the type its declarator gives it
+#[derive(Debug)]
+pub struct Declarator<'source> {
+    pub ty: Type,
+    pub name: Source<'source>,
+    pub init: Option<(Initializer<'source>, Span)>,
+}
+
+/// The value a variable is declared with
+#[derive(Debug)]
+pub enum Initializer<'source> {
+    /// `= expr`
+    Expr(Expr<'source>),
+    /// `= { init, ... }`, which initializes the elements of an array or the members of a struct in
+    /// order, and zero the rest. The braces around an inner aggregate can be left out.
+    List(Vec<(Initializer<'source>, Span)>),
+}
+
 #[derive(Debug)]
 pub enum Statement<'source> {
-    Return((Expr<'source>, Span)),
+    /// `None` for a bare `return;`
+    Return(Option<(Expr<'source>, Span)>),
     SingleExpr((Expr<'source>, Span)),
-    DeclareVar {
-        name: Source<'source>,
-        init: Option<(Expr<'source>, Span)>,
-    }, // TODO: add multiple var declarations
+    /// `type declarator = init, declarator, ...;`, which declares each variable in turn, so an
+    /// initializer can use the variables declared before it
+    DeclareVars {
+        declarators: Vec<Declarator<'source>>,
+        /// the alignments asked for with `_Alignas`, where `_Alignas(type)` is `_Alignof(type)`,
+        /// which all the variables have
+        align: Vec<(Expr<'source>, Span)>,
+    },
     Block(Vec<(Statement<'source>, Span)>),
     IfStatement {
         condition: (Expr<'source>, Span),
@@ -60,6 +274,16 @@ pub enum Statement<'source> {
         body: Vec<(Statement<'source>, Span)>,
         is_do_while: bool,
     },
+    Switch {
+        scrutinee: (Expr<'source>, Span),
+        body: (Box<Statement<'source>>, Span),
+    },
+    /// `case value:`, which labels the statements after it
+    Case((Expr<'source>, Span)),
+    /// `default:`
+    Default,
+    /// the enumerators of an enum defined in the block
+    DeclareEnum(Vec<Enumerator<'source>>),
     LoopBreak,
     LoopContinue,
 }
@@ -82,10 +306,11 @@ fn format_statement(
 ) -> fmt::Result {
     let spacing = " ".repeat(depth);
     match stmt {
-        Statement::Return((expr, expr_span)) => {
+        Statement::Return(Some((expr, expr_span))) => {
             write!(f, "Return@{:?}\n{}", stmt_span.as_range(), spacing + "    ")?;
             format_expr(expr, *expr_span, f, depth + 1)
         }
+        Statement::Return(None) => write!(f, "Return@{:?}", stmt_span.as_range()),
         Statement::Loop {
             condition: (expr, expr_span),
             body,
@@ -110,22 +335,30 @@ fn format_statement(
             )?;
             format_expr(expr, *expr_span, f, depth + 1)
         }
-        Statement::DeclareVar {
-            name:
-                Source {
-                    source: name,
-                    span: name_span,
-                },
-            init,
-        } => {
-            writeln!(f, "DeclareVar@{:?}", stmt_span.as_range())?;
-            writeln!(f, "{}  name: {}@{:?}", spacing, name, name_span.as_range())?;
-            if let Some((init_expr, init_span)) = init {
-                write!(f, "{}  init: ", spacing)?;
-                format_expr(init_expr, *init_span, f, depth + 4)
-            } else {
-                Ok(())
+        Statement::DeclareVars { declarators, align } => {
+            writeln!(f, "DeclareVars@{:?}", stmt_span.as_range())?;
+            for (align_expr, align_span) in align {
+                write!(f, "{}  align: ", spacing)?;
+                format_expr(align_expr, *align_span, f, depth + 4)?;
             }
+            for Declarator {
+                ty,
+                name:
+                    Source {
+                        source: name,
+                        span: name_span,
+                    },
+                init,
+            } in declarators
+            {
+                writeln!(f, "{}  type: {}", spacing, ty)?;
+                writeln!(f, "{}  name: {}@{:?}", spacing, name, name_span.as_range())?;
+                if let Some((init, init_span)) = init {
+                    write!(f, "{}  init: ", spacing)?;
+                    format_initializer(init, *init_span, f, depth + 4)?;
+                }
+            }
+            Ok(())
         }
         Statement::Block(block) => {
             writeln!(f, "Block@{:?}", stmt_span.as_range())?;
@@ -163,6 +396,29 @@ fn format_statement(
                 Ok(())
             }
         }
+        Statement::Switch {
+            scrutinee: (expr, expr_span),
+            body: (body, body_span),
+        } => {
+            write!(
+                f,
+                "Switch@{:?}\n{}",
+                stmt_span.as_range(),
+                spacing.clone() + "    "
+            )?;
+            format_expr(expr, *expr_span, f, depth + 1)?;
+            write!(f, "{}  body:\n{}", spacing.clone(), spacing + "    ")?;
+            format_statement(body, *body_span, f, depth + 2)
+        }
+        Statement::Case((expr, expr_span)) => {
+            write!(f, "Case@{:?}\n{}", stmt_span.as_range(), spacing + "    ")?;
+            format_expr(expr, *expr_span, f, depth + 1)
+        }
+        Statement::Default => writeln!(f, "Default@{:?}", stmt_span.as_range()),
+        Statement::DeclareEnum(enumerators) => {
+            writeln!(f, "DeclareEnum@{:?}", stmt_span.as_range())?;
+            format_enumerators(enumerators, f, depth + 1)
+        }
         Statement::LoopBreak => write!(
             f,
             "LoopBreak@{:?}\n{}",
@@ -178,6 +434,45 @@ fn format_statement(
     }
 }
 
+fn format_enumerators(
+    enumerators: &[Enumerator],
+    f: &mut fmt::Formatter,
+    depth: usize,
+) -> fmt::Result {
+    let spacing = " ".repeat(depth);
+    for Enumerator { name, value } in enumerators {
+        write!(f, "{}  {}@{:?}", spacing, name.source, name.span.as_range())?;
+        match value {
+            Some((expr, expr_span)) => {
+                f.write_str(" = ")?;
+                format_expr(expr, *expr_span, f, depth + 4)?;
+            }
+            None => writeln!(f)?,
+        }
+    }
+    Ok(())
+}
+
+fn format_initializer(
+    init: &Initializer,
+    init_span: Span,
+    f: &mut fmt::Formatter,
+    depth: usize,
+) -> fmt::Result {
+    match init {
+        Initializer::Expr(expr) => format_expr(expr, init_span, f, depth),
+        Initializer::List(inits) => {
+            writeln!(f, "InitializerList@{:?}", init_span.as_range())?;
+            let spacing = " ".repeat(depth);
+            for (init, init_span) in inits {
+                write!(f, "{}  ", spacing)?;
+                format_initializer(init, *init_span, f, depth + 2)?;
+            }
+            Ok(())
+        }
+    }
+}
+
 fn format_expr(expr: &Expr, expr_span: Span, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
     let spacing = " ".repeat(depth);
     match expr {
@@ -213,6 +508,29 @@ fn format_expr(expr: &Expr, expr_span: Span, f: &mut fmt::Formatter, depth: usiz
             name: Source { source: name, .. },
         } => writeln!(f, "Variable@{:?} {:?}", expr_span.as_range(), name),
         Expr::Constant(c) => writeln!(f, "Constant@{:?} {}", expr_span.as_range(), c),
+        Expr::LongConstant(c) => writeln!(f, "LongConstant@{:?} {}", expr_span.as_range(), c),
+        Expr::UnsignedConstant(c) => {
+            writeln!(f, "UnsignedConstant@{:?} {}", expr_span.as_range(), c)
+        }
+        Expr::UnsignedLongConstant(c) => {
+            writeln!(f, "UnsignedLongConstant@{:?} {}", expr_span.as_range(), c)
+        }
+        Expr::FloatConstant { bits, ty } => writeln!(
+            f,
+            "FloatConstant@{:?} {} {}",
+            expr_span.as_range(),
+            f64::from_bits(*bits),
+            ty
+        ),
+        Expr::AlignOf(ty) => writeln!(f, "AlignOf@{:?} {}", expr_span.as_range(), ty),
+        Expr::StringLiteral(contents) => {
+            writeln!(
+                f,
+                "StringLiteral@{:?} {:?}",
+                expr_span.as_range(),
+                String::from_utf8_lossy(contents)
+            )
+        }
         Expr::Unary {
             operator,
             expr: (expr, expr_span),
@@ -222,6 +540,69 @@ fn format_expr(expr: &Expr, expr_span: Span, f: &mut fmt::Formatter, depth: usiz
             writeln!(f, "{}  expr:", spacing)?;
             format_expr(expr, *expr_span, f, depth + 2)
         }
+        Expr::Cast {
+            target,
+            expr: (expr, expr_span),
+        } => {
+            writeln!(f, "Cast@{:?}", expr_span.as_range())?;
+            writeln!(f, "{}  target: {}", spacing, target)?;
+            writeln!(f, "{}  expr:", spacing)?;
+            format_expr(expr, *expr_span, f, depth + 2)
+        }
+        Expr::Call { function, args } => {
+            writeln!(f, "Call@{:?} {:?}", expr_span.as_range(), function.source)?;
+            for (arg, arg_span) in args {
+                write!(f, "{}  arg: ", spacing)?;
+                format_expr(arg, *arg_span, f, depth + 2)?;
+            }
+            Ok(())
+        }
+        Expr::CallPointer {
+            pointer: (pointer, pointer_span),
+            args,
+        } => {
+            writeln!(f, "CallPointer@{:?}", expr_span.as_range())?;
+            write!(f, "{}  pointer: ", spacing)?;
+            format_expr(pointer, *pointer_span, f, depth + 2)?;
+            for (arg, arg_span) in args {
+                write!(f, "{}  arg: ", spacing)?;
+                format_expr(arg, *arg_span, f, depth + 2)?;
+            }
+            Ok(())
+        }
+        Expr::Index {
+            array: (array, array_span),
+            index: (index, index_span),
+        } => {
+            writeln!(f, "Index@{:?}", expr_span.as_range())?;
+            write!(f, "{}  array: ", spacing)?;
+            format_expr(array, *array_span, f, depth + 2)?;
+            write!(f, "{}  index: ", spacing)?;
+            format_expr(index, *index_span, f, depth + 2)
+        }
+        Expr::Member {
+            base: (base, base_span),
+            member,
+        } => {
+            writeln!(f, "Member@{:?} {:?}", expr_span.as_range(), member.source)?;
+            write!(f, "{}  base: ", spacing)?;
+            format_expr(base, *base_span, f, depth + 2)
+        }
+        Expr::Arrow {
+            pointer: (pointer, pointer_span),
+            member,
+        } => {
+            writeln!(f, "Arrow@{:?} {:?}", expr_span.as_range(), member.source)?;
+            write!(f, "{}  pointer: ", spacing)?;
+            format_expr(pointer, *pointer_span, f, depth + 2)
+        }
+        Expr::Parenthesized {
+            expr: (expr, inner_span),
+        } => {
+            writeln!(f, "Parenthesized@{:?}", expr_span.as_range())?;
+            write!(f, "{}  expr: ", spacing)?;
+            format_expr(expr, *inner_span, f, depth + 2)
+        }
         Expr::Binary { operator, lhs, rhs } => {
             writeln!(f, "BinaryExpression@{:?}", expr_span.as_range())?;
             writeln!(f, "{}  operator: {:?}", spacing, operator)?;
@@ -237,12 +618,28 @@ fn format_expr(expr: &Expr, expr_span: Span, f: &mut fmt::Formatter, depth: usiz
 pub struct Identifier<'source>(pub &'source str);
 
 // NOTE: should I make a processed expr type?
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expr<'source> {
     Variable {
         name: Source<'source>,
     },
     Constant(i32),
+    /// A constant of type `long`: one with an `l` suffix, or too big for an `int`
+    LongConstant(i64),
+    /// A constant of type `unsigned int`: one with a `u` suffix, or a hexadecimal, octal or
+    /// binary one too big for an `int`
+    UnsignedConstant(u32),
+    /// A constant of type `unsigned long`, with both suffixes, or too big for the types before it
+    UnsignedLongConstant(u64),
+    /// A floating constant, already rounded to its type. The value is kept as the bits of an
+    /// `f64` so that expressions can still be compared for equality.
+    FloatConstant {
+        bits: u64,
+        ty: FloatType,
+    },
+    /// The bytes of a string literal, with its escape sequences decoded. It's an array of `char`s
+    /// with a null terminator after them.
+    StringLiteral(Vec<u8>),
     Unary {
         operator: UnaryOp,
         expr: (Box<Expr<'source>>, Span),
@@ -257,12 +654,518 @@ pub enum Expr<'source> {
         value_true: (Box<Expr<'source>>, Span),
         value_false: (Box<Expr<'source>>, Span),
     },
+    Cast {
+        target: Type,
+        expr: (Box<Expr<'source>>, Span),
+    },
+    /// A call to a function by its name, or through a function pointer variable of that name
+    Call {
+        function: Source<'source>,
+        args: Vec<(Expr<'source>, Span)>,
+    },
+    /// A call through a function pointer given by any other expression, like `(*f)(x)`
+    CallPointer {
+        pointer: (Box<Expr<'source>>, Span),
+        args: Vec<(Expr<'source>, Span)>,
+    },
+    /// `array[index]`
+    Index {
+        array: (Box<Expr<'source>>, Span),
+        index: (Box<Expr<'source>>, Span),
+    },
+    /// `base.member`
+    Member {
+        base: (Box<Expr<'source>>, Span),
+        member: Source<'source>,
+    },
+    /// `pointer->member`
+    Arrow {
+        pointer: (Box<Expr<'source>>, Span),
+        member: Source<'source>,
+    },
+    /// `_Alignof(type)`
+    AlignOf(Type),
+    /// `(expr)`. It means the same as `expr`, but is kept so that diagnostics know which grouping
+    /// was written out.
+    Parenthesized {
+        expr: (Box<Expr<'source>>, Span),
+    },
 }
 
 impl Expr<'_> {
+    /// The expression inside any number of parentheses
+    pub fn without_parens(&self) -> &Self {
+        match self {
+            Self::Parenthesized { expr: (expr, _) } => expr.without_parens(),
+            expr => expr,
+        }
+    }
+    pub fn into_without_parens(self) -> Self {
+        match self {
+            Self::Parenthesized { expr: (expr, _) } => expr.into_without_parens(),
+            expr => expr,
+        }
+    }
     pub fn is_writable(&self) -> bool {
-        matches!(self, Self::Variable { .. })
+        matches!(
+            self.without_parens(),
+            Self::Variable { .. }
+                | Self::Index { .. }
+                | Self::Member { .. }
+                | Self::Arrow { .. }
+                | Self::Unary {
+                    operator: UnaryOp::Deref,
+                    ..
+                }
+        )
+    }
+}
+
+/// The types a value can have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// `_Bool`, which only ever holds 0 or 1
+    Bool,
+    /// `char`. It is unsigned, as the AArch64 ABI mandates, so `unsigned char` is the same type.
+    Char,
+    /// `signed char`
+    SignedChar,
+    /// `short`
+    Short,
+    /// `unsigned short`
+    UnsignedShort,
+    /// `int`
+    Int,
+    /// `unsigned int`
+    UnsignedInt,
+    /// `long`, which is as wide as `long long` on AArch64
+    Long,
+    /// `unsigned long`, and `unsigned long long`
+    UnsignedLong,
+    /// `float`, single precision
+    Float,
+    /// `double`, double precision. `long double` is the same for now.
+    Double,
+    /// `element[len]`. A flexible array member, `element[]`, has a length of 0.
+    Array { element: Box<Type>, len: usize },
+    /// `pointee *`
+    Pointer(Box<Type>),
+    /// `struct tag` or `union tag`, which is laid out when it's defined
+    Struct(Arc<StructType>),
+    /// `return_type (*)(params)`. Functions are only ever used through a pointer to them: the
+    /// name of a function and the function a pointer points to are both pointers to it as values.
+    FunctionPointer(Box<FunctionType>),
+    /// `volatile ty`, an object that every access to is kept as written. Only objects are
+    /// volatile: reading one gives a value of the [unqualified](Type::unqualified) type.
+    Volatile(Box<Type>),
+}
+
+impl Type {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Some(match keyword {
+            "_Bool" => Self::Bool,
+            "char" => Self::Char,
+            "short" => Self::Short,
+            "int" => Self::Int,
+            "long" => Self::Long,
+            "float" => Self::Float,
+            "double" => Self::Double,
+            _ => return None,
+        })
+    }
+    /// Size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Array { element, len } => element.size() * len,
+            Self::Struct(struct_type) => struct_type.size,
+            Self::Volatile(ty) => ty.size(),
+            scalar => scalar.scalar_layout().size,
+        }
+    }
+    /// Alignment in bytes
+    pub fn align(&self) -> usize {
+        match self {
+            Self::Array { element, .. } => element.align(),
+            Self::Struct(struct_type) => struct_type.align,
+            Self::Volatile(ty) => ty.align(),
+            scalar => scalar.scalar_layout().align,
+        }
+    }
+    /// How the target lays out a type that isn't an aggregate
+    fn scalar_layout(&self) -> ScalarLayout {
+        let layout = &target::current().data_layout;
+        match self {
+            Self::Bool => layout.bool,
+            Self::Char | Self::SignedChar => layout.char,
+            Self::Short | Self::UnsignedShort => layout.short,
+            Self::Int | Self::UnsignedInt => layout.int,
+            Self::Long | Self::UnsignedLong => layout.long,
+            Self::Float => layout.float,
+            Self::Double => layout.double,
+            Self::Pointer(_) | Self::FunctionPointer(_) => layout.pointer,
+            Self::Array { .. } | Self::Struct(_) | Self::Volatile(_) => {
+                unreachable!("aggregates have a layout of their own")
+            }
+        }
+    }
+    /// Whether the keyword is an alignment specifier, which may start a declaration
+    pub fn is_alignment_keyword(keyword: &str) -> bool {
+        matches!(keyword, "_Alignas" | "alignas")
+    }
+    /// Whether the keyword starts a type
+    pub fn is_type_keyword(keyword: &str) -> bool {
+        Self::is_tagged_keyword(keyword)
+            || Self::from_keyword(keyword).is_some()
+            || Self::is_signedness_keyword(keyword)
+            || Self::is_qualifier_keyword(keyword)
+    }
+    /// Whether the keyword tells the signedness of an integer type, which is an `int` if it's
+    /// alone
+    pub fn is_signedness_keyword(keyword: &str) -> bool {
+        matches!(keyword, "signed" | "unsigned")
+    }
+    /// The `unsigned` (or `signed`) version of an integer type, if it's one that has one
+    pub fn with_signedness(&self, unsigned: bool) -> Option<Type> {
+        Some(match (self, unsigned) {
+            (Self::Char | Self::SignedChar, true) => Self::Char,
+            (Self::Char | Self::SignedChar, false) => Self::SignedChar,
+            (Self::Short | Self::UnsignedShort, true) => Self::UnsignedShort,
+            (Self::Short | Self::UnsignedShort, false) => Self::Short,
+            (Self::Int | Self::UnsignedInt, true) => Self::UnsignedInt,
+            (Self::Int | Self::UnsignedInt, false) => Self::Int,
+            (Self::Long | Self::UnsignedLong, true) => Self::UnsignedLong,
+            (Self::Long | Self::UnsignedLong, false) => Self::Long,
+            _ => return None,
+        })
+    }
+    /// Whether values of this type are integers, which `_Bool` is too
+    pub fn is_integer(&self) -> bool {
+        self.unqualified().with_signedness(true).is_some() || *self.unqualified() == Self::Bool
+    }
+    /// Whether values of this type are integers that are never negative
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            Self::Bool | Self::Char | Self::UnsignedShort | Self::UnsignedInt | Self::UnsignedLong
+        )
+    }
+    /// Whether the keyword qualifies the type it's next to, or the pointer it follows. Only
+    /// `volatile` changes how the code is compiled: `const` and `restrict` just limit what the
+    /// program may do, which isn't checked.
+    pub fn is_qualifier_keyword(keyword: &str) -> bool {
+        matches!(
+            keyword,
+            "volatile" | "const" | "restrict" | "__restrict" | "__restrict__"
+        )
+    }
+    /// The type without its qualifiers, which is the type of the value of an object
+    pub fn unqualified(&self) -> &Type {
+        match self {
+            Self::Volatile(ty) => ty.unqualified(),
+            ty => ty,
+        }
+    }
+    /// How the type is written with its signedness spelled out: a plain `char` is unsigned on
+    /// AArch64, which is the same type as `unsigned char`
+    pub fn with_explicit_signedness(&self) -> String {
+        match self {
+            Self::Char => "unsigned char".to_string(),
+            Self::Volatile(ty) if !matches!(**ty, Self::Pointer(_)) => {
+                format!("volatile {}", ty.with_explicit_signedness())
+            }
+            ty => ty.to_string(),
+        }
+    }
+    /// Whether accesses to an object of this type are volatile
+    pub const fn is_volatile(&self) -> bool {
+        matches!(self, Self::Volatile(_))
+    }
+    /// The `volatile` version of the type. A qualified array is an array of qualified elements.
+    pub fn volatile(self) -> Type {
+        match self {
+            volatile @ Self::Volatile(_) => volatile,
+            Self::Array { element, len } => Self::Array {
+                element: Box::new(element.volatile()),
+                len,
+            },
+            ty => Self::Volatile(Box::new(ty)),
+        }
+    }
+    /// Whether the keyword starts a type named by a tag, which may be defined right there
+    pub fn is_tagged_keyword(keyword: &str) -> bool {
+        AggregateKind::from_keyword(keyword).is_some() || keyword == "enum"
+    }
+    /// Whether values of this type are floating-point numbers, kept in the SIMD&FP registers
+    pub fn is_floating(&self) -> bool {
+        matches!(self.unqualified(), Self::Float | Self::Double)
+    }
+    /// Whether this is the type of a flexible array member, which takes no room of its own
+    pub fn is_flexible_array(&self) -> bool {
+        matches!(self, Self::Array { len: 0, .. })
+    }
+    /// Whether the type holds `value` without changing it
+    pub fn can_represent(&self, value: i64) -> bool {
+        match self {
+            Self::Bool => value == 0 || value == 1,
+            Self::Char => 0 <= value && value <= u8::MAX as i64,
+            Self::SignedChar => i8::try_from(value).is_ok(),
+            Self::Short => i16::try_from(value).is_ok(),
+            Self::UnsignedShort => u16::try_from(value).is_ok(),
+            Self::Int => i32::try_from(value).is_ok(),
+            Self::UnsignedInt => u32::try_from(value).is_ok(),
+            Self::Long | Self::UnsignedLong if self.size() < 8 => {
+                Self::Int.with_signedness(self.is_unsigned()).unwrap().can_represent(value)
+            }
+            Self::Long => true,
+            Self::UnsignedLong => value >= 0,
+            // integers are exact as long as they fit in the significand
+            Self::Float => value.unsigned_abs() <= 1 << f32::MANTISSA_DIGITS,
+            Self::Double => value.unsigned_abs() <= 1 << f64::MANTISSA_DIGITS,
+            Self::Volatile(ty) => ty.can_represent(value),
+            Self::Array { .. } | Self::Pointer(_) | Self::Struct(_) | Self::FunctionPointer(_) => {
+                false
+            }
+        }
+    }
+    /// Whether the floating-point `value` is kept exactly by a value of this type
+    pub fn can_represent_float(&self, value: f64) -> bool {
+        match self {
+            Self::Float => f64::from(value as f32) == value || value.is_nan(),
+            Self::Double => true,
+            _ => {
+                (i64::MIN as f64..i64::MAX as f64).contains(&value)
+                    && value as i64 as f64 == value
+                    && self.can_represent(value as i64)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool => f.write_str("_Bool"),
+            Self::Char => f.write_str("char"),
+            Self::SignedChar => f.write_str("signed char"),
+            Self::Short => f.write_str("short"),
+            Self::UnsignedShort => f.write_str("unsigned short"),
+            Self::Int => f.write_str("int"),
+            Self::UnsignedInt => f.write_str("unsigned int"),
+            Self::Long => f.write_str("long"),
+            Self::UnsignedLong => f.write_str("unsigned long"),
+            Self::Float => f.write_str("float"),
+            Self::Double => f.write_str("double"),
+            Self::Array { element, len: 0 } => write!(f, "{}[]", element),
+            Self::Array { element, len } => write!(f, "{}[{}]", element, len),
+            Self::Pointer(pointee) => write!(f, "{} *", pointee),
+            Self::Struct(struct_type) => struct_type.fmt(f),
+            Self::FunctionPointer(function_type) => function_type.fmt(f),
+            // a pointer is qualified after its `*`
+            Self::Volatile(ty) if matches!(**ty, Self::Pointer(_)) => write!(f, "{}volatile", ty),
+            Self::Volatile(ty) => write!(f, "volatile {}", ty),
+        }
+    }
+}
+
+/// The type of a floating constant, given by its suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatType {
+    /// `f`
+    Float,
+    /// no suffix
+    Double,
+    /// `l`. It's a quadruple precision float on AArch64, but constants are only kept as precise
+    /// as a `double` for now.
+    LongDouble,
+}
+
+impl FloatType {
+    /// The type of a constant with this suffix
+    pub const fn ty(self) -> Type {
+        match self {
+            Self::Float => Type::Float,
+            Self::Double | Self::LongDouble => Type::Double,
+        }
+    }
+}
+
+impl fmt::Display for FloatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Float => "float",
+            Self::Double => "double",
+            Self::LongDouble => "long double",
+        })
+    }
+}
+
+/// The layout of a `struct` or `union`. Struct members are placed in order, each one at the next
+/// offset that satisfies its alignment, while union members all overlap at the start. Either way
+/// the size is padded to a multiple of the largest alignment so that arrays of them keep every
+/// element aligned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructType {
+    pub kind: AggregateKind,
+    /// empty for a struct defined without a tag
+    pub tag: String,
+    pub members: Vec<Member>,
+    pub size: usize,
+    pub align: usize,
+}
+
+/// What a function takes and returns, as far as its callers are concerned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    /// `None` for functions returning `void`
+    pub return_type: Option<Type>,
+    /// the types of the parameters, or `None` if they were left unspecified with `()`
+    pub params: Option<Vec<Type>>,
+    /// whether the parameters end in `...`
+    pub variadic: bool,
+}
+
+impl FunctionType {
+    /// The type of a function, in which the parameters have no qualifiers: those only matter to
+    /// the body of the function
+    pub fn of(function: &Function) -> Self {
+        Self {
+            return_type: function.return_type.clone(),
+            params: (!function.params_unspecified).then(|| {
+                function
+                    .params
+                    .iter()
+                    .map(|param| param.ty.unqualified().clone())
+                    .collect()
+            }),
+            variadic: function.variadic,
+        }
+    }
+}
+
+impl fmt::Display for FunctionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.return_type {
+            Some(ty) => write!(f, "{} (*)(", ty)?,
+            None => f.write_str("void (*)(")?,
+        }
+        match &self.params {
+            Some(params) if params.is_empty() && !self.variadic => f.write_str("void")?,
+            Some(params) => {
+                for (index, param) in params.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                if self.variadic {
+                    f.write_str(", ...")?;
+                }
+            }
+            None => (),
+        }
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+    Struct,
+    Union,
+}
+
+impl AggregateKind {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Some(match keyword {
+            "struct" => Self::Struct,
+            "union" => Self::Union,
+            _ => return None,
+        })
+    }
+    pub const fn keyword(self) -> &'static str {
+        match self {
+            Self::Struct => "struct",
+            Self::Union => "union",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// empty for an anonymous struct or union, whose members are members of the enclosing one
+    pub name: String,
+    pub ty: Type,
+    /// Bytes from the start of the struct
+    pub offset: usize,
+}
+
+impl StructType {
+    pub fn new(kind: AggregateKind, tag: String, members: Vec<(String, Type)>) -> Self {
+        let mut size = 0;
+        let mut align = 1;
+        let members = members
+            .into_iter()
+            .map(|(name, ty)| {
+                let offset = match kind {
+                    AggregateKind::Struct => align_to(size, ty.align()),
+                    AggregateKind::Union => 0,
+                };
+                size = size.max(offset + ty.size());
+                align = align.max(ty.align());
+                Member { name, ty, offset }
+            })
+            .collect();
+        Self {
+            kind,
+            tag,
+            members,
+            size: align_to(size, align),
+            align,
+        }
+    }
+    /// The member with the given name, which may be a member of an anonymous struct or union
+    /// member, with its offset from the start of this one
+    pub fn member(&self, name: &str) -> Option<Member> {
+        self.members.iter().find_map(|member| match &member.ty {
+            Type::Struct(inner) if member.name.is_empty() => {
+                inner.member(name).map(|found| Member {
+                    offset: member.offset + found.offset,
+                    ..found
+                })
+            }
+            _ => (member.name == name).then(|| member.clone()),
+        })
     }
+    /// The names of the members, with those of anonymous members in place of them
+    pub fn member_names(&self) -> Vec<&str> {
+        self.members
+            .iter()
+            .flat_map(|member| match &member.ty {
+                Type::Struct(inner) if member.name.is_empty() => inner.member_names(),
+                _ => vec![member.name.as_str()],
+            })
+            .collect()
+    }
+    /// Whether the struct ends in a flexible array member, which stops it from being nested in
+    /// other types
+    pub fn has_flexible_array(&self) -> bool {
+        self.members
+            .last()
+            .is_some_and(|member| member.ty.is_flexible_array())
+    }
+}
+
+impl fmt::Display for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.tag.is_empty() {
+            write!(f, "{} <anonymous>", self.kind.keyword())
+        } else {
+            write!(f, "{} {}", self.kind.keyword(), self.tag)
+        }
+    }
+}
+
+const fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
 }
 
 // NOTE: unary operators also have different predecences (14 or 13, depending on them), it's just
@@ -272,15 +1175,20 @@ pub enum UnaryOp {
     Negate,
     BitNot,
     LogicNot,
+    /// `&`
+    AddressOf,
+    /// `*`
+    Deref,
 }
 
 impl UnaryOp {
-    pub fn from_operator((op, _): (Operator, bool)) -> Option<Self> {
+    pub fn from_operator((op, has_equal): (Operator, bool)) -> Option<Self> {
         Some(match op {
             Operator::Minus => Self::Negate,
             Operator::ExclamationMark => Self::LogicNot,
             Operator::Tilde => Self::BitNot,
-            // NOTE: star operator will have to be enabled for pointer access
+            Operator::And if !has_equal => Self::AddressOf,
+            Operator::Star if !has_equal => Self::Deref,
             Operator::Plus
             | Operator::Star
             | Operator::And
@@ -315,9 +1223,7 @@ pub enum ArithmeticOp {
     Modulo,
 }
 
-impl Eq for ArithmeticOp {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl Eq for ArithmeticOp {}
 
 impl PartialEq for ArithmeticOp {
     fn eq(&self, other: &Self) -> bool {
@@ -347,9 +1253,7 @@ pub enum BitOp {
     LeftShift,
 }
 
-impl Eq for BitOp {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl Eq for BitOp {}
 
 impl PartialEq for BitOp {
     fn eq(&self, other: &Self) -> bool {
@@ -372,9 +1276,7 @@ pub enum LogicOp {
     Or,
 }
 
-impl Eq for LogicOp {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl Eq for LogicOp {}
 
 impl PartialEq for LogicOp {
     fn eq(&self, other: &Self) -> bool {
@@ -429,6 +1331,43 @@ pub enum AssignmentEnabledOp {
     Bit(BitOp),
 }
 
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = |op: AssignmentEnabledOp| match op {
+            AssignmentEnabledOp::Arithmetic(op) => match op {
+                ArithmeticOp::Add => "+",
+                ArithmeticOp::Subtract => "-",
+                ArithmeticOp::Multiply => "*",
+                ArithmeticOp::Divide => "/",
+                ArithmeticOp::Modulo => "%",
+            },
+            AssignmentEnabledOp::Bit(op) => match op {
+                BitOp::And => "&",
+                BitOp::Or => "|",
+                BitOp::Xor => "^",
+                BitOp::RightShift => ">>",
+                BitOp::LeftShift => "<<",
+            },
+        };
+        f.write_str(match self {
+            Self::Arithmetic(op) => operator((*op).into()),
+            Self::Bit(op) => operator((*op).into()),
+            Self::Logic(LogicOp::And) => "&&",
+            Self::Logic(LogicOp::Or) => "||",
+            Self::Relational(relational) => match relational {
+                Relational::Less => "<",
+                Relational::LessEqual => "<=",
+                Relational::Greater => ">",
+                Relational::GreaterEqual => ">=",
+                Relational::Equals => "==",
+                Relational::NotEquals => "!=",
+            },
+            Self::Assignment { op: None } => "=",
+            Self::Assignment { op: Some(op) } => return write!(f, "{}=", operator(*op)),
+        })
+    }
+}
+
 impl From<AssignmentEnabledOp> for BinaryOp {
     fn from(op: AssignmentEnabledOp) -> Self {
         match op {
@@ -621,6 +1560,12 @@ impl BinaryOp {
             None
         }
     }
+    /// Whether a chain of these operators, like `a + b < c`, is walked down its left operands in
+    /// a loop rather than recursing into them, so that a long one isn't nested any deeper than
+    /// its operands
+    pub const fn chains(self) -> bool {
+        matches!(self, Self::Arithmetic(_) | Self::Bit(_) | Self::Relational(_))
+    }
     pub const fn associativity(self) -> Associativity {
         match self {
             Self::Arithmetic(_) | Self::Logic(_) | Self::Bit(_) | Self::Relational(_) => {
@@ -806,3 +1751,98 @@ impl BinaryOp {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn offsets(struct_type: &StructType) -> Vec<usize> {
+        struct_type
+            .members
+            .iter()
+            .map(|member| member.offset)
+            .collect()
+    }
+
+    #[test]
+    fn struct_members_are_placed_at_their_alignment() {
+        let pointer = Type::Pointer(Box::new(Type::Int));
+        let mixed = StructType::new(
+            AggregateKind::Struct,
+            "mixed".into(),
+            vec![
+                ("c".into(), Type::Char),
+                ("i".into(), Type::Int),
+                ("d".into(), Type::Char),
+                ("p".into(), pointer),
+                ("e".into(), Type::Char),
+            ],
+        );
+        assert_eq!(offsets(&mixed), [0, 4, 8, 16, 24]);
+        // the size is padded to the alignment of the strictest member
+        assert_eq!((mixed.size, mixed.align), (32, 8));
+
+        let chars = StructType::new(
+            AggregateKind::Struct,
+            "chars".into(),
+            vec![("a".into(), Type::Char), ("b".into(), Type::Char)],
+        );
+        assert_eq!(offsets(&chars), [0, 1]);
+        assert_eq!((chars.size, chars.align), (2, 1));
+
+        // nested structs and arrays are aligned like their members and elements
+        let nested = StructType::new(
+            AggregateKind::Struct,
+            "nested".into(),
+            vec![
+                ("c".into(), Type::Char),
+                ("inner".into(), Type::Struct(Arc::new(mixed))),
+                (
+                    "a".into(),
+                    Type::Array {
+                        element: Box::new(Type::Int),
+                        len: 3,
+                    },
+                ),
+            ],
+        );
+        assert_eq!(offsets(&nested), [0, 8, 40]);
+        assert_eq!((nested.size, nested.align), (56, 8));
+        assert_eq!(nested.member("a").map(|member| member.offset), Some(40));
+        assert!(nested.member("missing").is_none());
+    }
+
+    #[test]
+    fn union_members_overlap_at_the_start() {
+        let union_type = StructType::new(
+            AggregateKind::Union,
+            "u".into(),
+            vec![
+                ("c".into(), Type::Char),
+                ("p".into(), Type::Pointer(Box::new(Type::Char))),
+                (
+                    "a".into(),
+                    Type::Array {
+                        element: Box::new(Type::Char),
+                        len: 9,
+                    },
+                ),
+            ],
+        );
+        assert_eq!(offsets(&union_type), [0, 0, 0]);
+        // as big as the biggest member, padded to the strictest alignment
+        assert_eq!((union_type.size, union_type.align), (16, 8));
+        assert_eq!(Type::Struct(Arc::new(union_type)).to_string(), "union u");
+    }
+
+    #[test]
+    fn members_of_anonymous_members_belong_to_the_enclosing_struct() {
+        let source = "struct s { int kind; union { int i; struct { short lo; short hi; }; }; };\n\
+                      int main() { struct s s = {1, {2}}; s.hi = 3; return s.kind + s.lo; }\n";
+        assert!(crate::check_str(source).is_empty());
+        let duplicate = "struct s { int i; union { int j; int i; }; };\n";
+        let error = crate::check_str(duplicate).iter().next().cloned();
+        assert!(error.is_some_and(|error| error.message.contains("duplicate member")));
+    }
+}
@@ -1,6 +1,11 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
 use crate::write_instruction;
 // use bitflags::bitflags;
-use std::fmt;
+use core::fmt;
 
 // NOTE: could do something in the compiler to make
 // instructions that may modify state "adds", "subs" etc
@@ -50,11 +55,40 @@ impl From<Branch> for Assembly {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Directive {
     Global(String),
+    /// Make a symbol global, but let a non-weak definition elsewhere take its place, or leave it
+    /// undefined
+    Weak(String),
+    /// Define a symbol with the value of another, as in `.set name, value`
+    Set(String, String),
     Type(String, String),
     Architecture(String),
+    /// The source file the assembly was generated from
+    File(String),
+    /// Identification of the compiler, which the assembler puts in the `.comment` section
+    Ident(String),
+    /// Switch to a section, with its flags and (when it isn't implied by the name) its type
+    Section {
+        name: String,
+        flags: String,
+        kind: Option<String>,
+    },
+    /// Align the location counter to 2 to the given power
+    P2Align(u8),
+    /// A 32-bit value
+    Word(u32),
+    /// A string, followed by its null terminator
+    String(String),
+    /// Bytes, in order
+    Byte(Vec<u8>),
+    /// The given number of zero bytes
+    Zero(usize),
+    /// The size in bytes of the object a symbol is, as in `.size name, size`
+    Size(String, usize),
+    /// Switch back to the code section
+    Text,
 }
 
 impl From<Directive> for Assembly {
@@ -67,13 +101,40 @@ impl fmt::Display for Directive {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Global(name) => write!(f, "global {}", name),
+            Self::Weak(name) => write!(f, "weak {}", name),
+            Self::Set(name, value) => write!(f, "set {}, {}", name, value),
             Self::Type(name, t) => write!(f, "type {}, %{}", name, t),
             Self::Architecture(arch) => write!(f, "arch {}", arch),
+            Self::File(file) => write!(f, "file {:?}", file),
+            Self::Ident(ident) => write!(f, "ident {:?}", ident),
+            Self::Section { name, flags, kind } => {
+                write!(f, "section {},{:?}", name, flags)?;
+                match kind {
+                    Some(kind) => write!(f, ",%{}", kind),
+                    None => Ok(()),
+                }
+            }
+            Self::P2Align(power) => write!(f, "p2align {}", power),
+            Self::Word(word) => write!(f, "word {}", word),
+            Self::String(string) => write!(f, "string {:?}", string),
+            Self::Byte(bytes) => {
+                f.write_str("byte ")?;
+                for (index, byte) in bytes.iter().enumerate() {
+                    if index != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", byte)?;
+                }
+                Ok(())
+            }
+            Self::Zero(len) => write!(f, "zero {}", len),
+            Self::Size(name, size) => write!(f, "size {}, {}", name, size),
+            Self::Text => f.write_str("text"),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Instruction {
     /// Return from a function
     Ret,
@@ -81,6 +142,30 @@ pub enum Instruction {
     Mov { target: Register, source: Data },
     /// Move (with applied not) data to a register
     MvN { target: Register, source: Data },
+    /// Move a 16-bit immediate, shifted by `shift` bits, zeroing the rest of the register
+    Movz {
+        target: Register,
+        immediate: u16,
+        shift: u8,
+    },
+    /// Move the negation of a 16-bit immediate, shifted by `shift` bits
+    Movn {
+        target: Register,
+        immediate: u16,
+        shift: u8,
+    },
+    /// Replace 16 bits of the register, at `shift` bits, keeping the rest
+    Movk {
+        target: Register,
+        immediate: u16,
+        shift: u8,
+    },
+    /// Sign extend the 32-bit source into the 64-bit target
+    Sxtw { target: Register, source: Register },
+    /// Sign extend the lowest 16 bits of the source into the target
+    Sxth { target: Register, source: Register },
+    /// Sign extend the lowest byte of the source into the target
+    Sxtb { target: Register, source: Register },
 
     /// Compare a register with some data
     Cmp { register: Register, data: Data },
@@ -89,6 +174,20 @@ pub enum Instruction {
         target: Register,
         condition: Condition,
     },
+    /// Sets the register to `if_true` if the condition holds, and to `if_false` if it doesn't
+    Csel {
+        target: Register,
+        if_true: Register,
+        if_false: Register,
+        condition: Condition,
+    },
+    /// Like `csel`, but adding one to `if_false`
+    Csinc {
+        target: Register,
+        if_true: Register,
+        if_false: Register,
+        condition: Condition,
+    },
     /// Negate a register
     Neg { target: Register, source: Register },
     /// Add a register and a source of data into a register
@@ -97,6 +196,14 @@ pub enum Instruction {
         lhs: Register,
         rhs: Data,
     },
+    /// Address of the 4KB page that holds a label
+    Adrp { target: Register, label: Label },
+    /// Add the offset of a label within its 4KB page, completing its address after an `adrp`
+    AddPageOffset {
+        target: Register,
+        source: Register,
+        label: Label,
+    },
     /// subtract the contents of a register from some data into a register
     Sub {
         target: Register,
@@ -116,6 +223,20 @@ pub enum Instruction {
         lhs: Register,
         rhs: Data,
     },
+    /// Signed multiply of two 32-bit registers, added to a 64-bit register
+    Smaddl {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+        addend: Register,
+    },
+    /// Multiply two registers and add a third one
+    Madd {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+        addend: Register,
+    },
     /// Divide two signed numbers
     Div {
         target: Register,
@@ -123,26 +244,114 @@ pub enum Instruction {
         rhs: Data,
         signed: bool,
     },
-    /// Logical shift left
-    // NOTE: `s` suffix is available
+    /// Logical shift left. An amount in a register is taken modulo the width of the registers,
+    /// while an immediate one has to be less than it.
     Lsl {
         target: Register,
         lhs: Register,
-        // if it is a register, only the lsbyte is used
-        // if it is an immediate, range of values permitted is 0-31
         rhs: Data,
     },
-    /// Logical shift right
-    // NOTE: `s` suffix is available
+    /// Logical shift right, with the same amounts as `lsl`
     Lsr {
         target: Register,
         lhs: Register,
         rhs: Data,
     },
+    /// Arithmetic shift right, which shifts in copies of the sign bit, with the same amounts as
+    /// `lsl`
+    Asr {
+        target: Register,
+        lhs: Register,
+        rhs: Data,
+    },
     /// Store a register into memory
     Str { register: Register, address: Memory },
     /// Load a register from memory
     Ldr { register: Register, address: Memory },
+    /// Store the lowest byte of a register into memory
+    Strb { register: Register, address: Memory },
+    /// Load a byte from memory, zero-extending it into the register
+    Ldrb { register: Register, address: Memory },
+    /// Store the lowest 16 bits of a register into memory
+    Strh { register: Register, address: Memory },
+    /// Load 16 bits from memory, sign-extending them into the register
+    Ldrsh { register: Register, address: Memory },
+    /// Load 16 bits from memory, zero-extending them into the register
+    Ldrh { register: Register, address: Memory },
+    /// Load a byte from memory, sign-extending it into the register
+    Ldrsb { register: Register, address: Memory },
+    /// Load a register from the memory `base` points to, before any of the loads and stores that
+    /// come after it (load-acquire)
+    Ldar { register: Register, base: Register },
+    /// Store a register into the memory `base` points to, after all the loads and stores that
+    /// come before it (store-release)
+    Stlr { register: Register, base: Register },
+    /// Load-acquire a register, and watch the memory for an exclusive store to it
+    Ldaxr { register: Register, base: Register },
+    /// Store-release a register, only if nothing else stored to the memory since the `ldaxr` that
+    /// watched it. `status` is set to 0 when it stored, and to 1 when it didn't.
+    Stlxr {
+        status: Register,
+        register: Register,
+        base: Register,
+    },
+    /// Add `addend` to the memory `base` points to at once, loading what it held into `target`,
+    /// with the ordering of both `ldar` and `stlr`. It needs the large system extensions (LSE).
+    Ldaddal {
+        addend: Register,
+        target: Register,
+        base: Register,
+    },
+    /// Store a pair of registers into consecutive memory
+    Stp {
+        first: Register,
+        second: Register,
+        address: PairAddress,
+    },
+    /// Load a pair of registers from consecutive memory
+    Ldp {
+        first: Register,
+        second: Register,
+        address: PairAddress,
+    },
+    /// Move between floating-point registers, or between a general purpose one and a
+    /// floating-point one, keeping the bits as they are
+    Fmov { target: Register, source: Register },
+    /// Floating-point addition
+    Fadd {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Floating-point subtraction
+    Fsub {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Floating-point multiplication
+    Fmul {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Floating-point division
+    Fdiv {
+        target: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    /// Floating-point negation
+    Fneg { target: Register, source: Register },
+    /// Compare two floating-point registers. When either of them is NaN, the comparison is
+    /// unordered: only `ne` holds among the conditions that aren't `lt` or `le`.
+    Fcmp { lhs: Register, rhs: Register },
+    /// Convert a signed integer into a floating-point value
+    Scvtf { target: Register, source: Register },
+    /// Convert a floating-point value into a signed integer, rounding towards zero
+    Fcvtzs { target: Register, source: Register },
+    /// Convert between floating-point precisions
+    Fcvt { target: Register, source: Register },
     /// Bitwise AND
     // NOTE: `s` suffix is available
     // NOTE: register and immediate controlled LS(R|L)/ROR/ASR is available
@@ -164,14 +373,27 @@ pub enum Instruction {
         target: Register,
         lhs: Register,
         rhs: Data,
-        bitmask: u64,
     },
 
+    /// Landing pad for indirect calls (`bti c`)
+    Bti,
+    /// Sign the return address in the link register, using `sp` as the modifier. It's also a
+    /// landing pad for indirect calls.
+    Paciasp,
+    /// Authenticate the return address signed by `paciasp`
+    Autiasp,
+    /// Authenticate the return address like `autiasp`, and return to it. It needs pointer
+    /// authentication, beyond the hints.
+    Retaa,
+    /// Stop in the debugger, or with `SIGTRAP` without one. It doesn't move past itself, so the
+    /// debugger has to step over it to go on.
+    Brk(u16),
+
     /// Branch for different situations
     Branch(Branch),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Branch {
     /// normal (unconditional) branch, always executed
     Unconditional {
@@ -180,28 +402,79 @@ pub enum Branch {
     },
     /// branch with link (aka call)
     Linked { label: Label },
+    /// branch with link to the address in a register (aka call through a pointer)
+    LinkedRegister { register: Register },
     /// Conditional branch
     Conditional { condition: Condition, label: Label },
+    /// Branch if the register isn't zero, without touching the flags
+    NonZero { register: Register, label: Label },
 }
 
+/// Addressing modes for the register pair instructions
 #[derive(Debug, Clone, Copy)]
+pub enum PairAddress {
+    /// `[base, #offset]`
+    Offset { base: Register, offset: i32 },
+    /// `[base, #offset]!`: the base register is updated before accessing memory
+    PreIndex { base: Register, offset: i32 },
+    /// `[base], #offset`: the base register is updated after accessing memory
+    PostIndex { base: Register, offset: i32 },
+}
+
+impl fmt::Display for PairAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Offset { base, offset: 0 } => write!(f, "[{}]", base),
+            Self::Offset { base, offset } => write!(f, "[{}, {}]", base, Data::Immediate(*offset)),
+            Self::PreIndex { base, offset } => {
+                write!(f, "[{}, {}]!", base, Data::Immediate(*offset))
+            }
+            Self::PostIndex { base, offset } => {
+                write!(f, "[{}], {}", base, Data::Immediate(*offset))
+            }
+        }
+    }
+}
+
+/// Block labels are numbered per function, since they all end up in the same assembly file
+#[derive(Debug, Clone)]
 pub enum Label {
-    Block { num: usize },
-    Epilogue,
+    Block {
+        function: usize,
+        num: usize,
+    },
+    Epilogue {
+        function: usize,
+    },
+    /// The symbol of a function, the target of calls
+    Function(String),
+    /// The symbol of a variable defined at file scope, where its memory is
+    Global(String),
+    /// The read-only data of a function, numbered like its blocks
+    ReadOnly {
+        function: usize,
+        num: usize,
+    },
+    /// Right after a conditional branch that was relaxed, which the opposite branch skips to
+    Relaxed {
+        function: usize,
+        num: usize,
+    },
+    /// The start of the loop that retries an atomic operation, numbered by its binding
+    Atomic {
+        function: usize,
+        num: usize,
+    },
 }
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Eor {
-                target,
-                lhs,
-                rhs,
-                bitmask,
-            } => write_instruction!(f, "eor", target, lhs, rhs, Data::Immediate(*bitmask as i32)),
+            Self::Eor { target, lhs, rhs } => write_instruction!(f, "eor", target, lhs, rhs),
             Self::Orr { target, lhs, rhs } => write_instruction!(f, "orr", target, lhs, rhs),
             Self::And { target, lhs, rhs } => write_instruction!(f, "and", target, lhs, rhs),
             Self::Lsr { target, lhs, rhs } => write_instruction!(f, "lsr", target, lhs, rhs),
+            Self::Asr { target, lhs, rhs } => write_instruction!(f, "asr", target, lhs, rhs),
             Self::Lsl { target, lhs, rhs } => write_instruction!(f, "lsl", target, lhs, rhs),
             Self::MSub {
                 target,
@@ -210,20 +483,104 @@ impl fmt::Display for Instruction {
                 minuend,
             } => write_instruction!(f, "msub", target, multiplicand, multiplier, minuend),
             Self::Ret => write_instruction!(f, "ret"),
+            Self::Retaa => write_instruction!(f, "retaa"),
             Self::Mov { target, source } => {
                 write_instruction!(f, "mov", target, source)
             }
             Self::MvN { target, source } => {
                 write_instruction!(f, "mvn", target, source)
             }
+            Self::Movz {
+                target,
+                immediate,
+                shift,
+            } => write_mov_wide(f, "movz", target, *immediate, *shift),
+            Self::Movn {
+                target,
+                immediate,
+                shift,
+            } => write_mov_wide(f, "movn", target, *immediate, *shift),
+            Self::Movk {
+                target,
+                immediate,
+                shift,
+            } => write_mov_wide(f, "movk", target, *immediate, *shift),
+            Self::Sxtw { target, source } => write_instruction!(f, "sxtw", target, source),
+            Self::Sxth { target, source } => write_instruction!(f, "sxth", target, source),
+            Self::Sxtb { target, source } => write_instruction!(f, "sxtb", target, source),
             Self::Cmp { register, data } => write_instruction!(f, "cmp", register, data),
             Self::Cset { target, condition } => write_instruction!(f, "cset", target, condition),
+            Self::Csel {
+                target,
+                if_true,
+                if_false,
+                condition,
+            } => write_instruction!(f, "csel", target, if_true, if_false, condition),
+            Self::Csinc {
+                target,
+                if_true,
+                if_false,
+                condition,
+            } => write_instruction!(f, "csinc", target, if_true, if_false, condition),
             Self::Neg { target, source } => write_instruction!(f, "neg", target, source),
             Self::Add { target, lhs, rhs } => write_instruction!(f, "add", target, lhs, rhs),
+            Self::Adrp { target, label } => write_instruction!(f, "adrp", target, label),
+            Self::AddPageOffset {
+                target,
+                source,
+                label,
+            } => write_instruction!(f, "add", target, source, format!(":lo12:{}", label)),
             Self::Sub { target, lhs, rhs } => write_instruction!(f, "sub", target, lhs, rhs),
             Self::Str { register, address } => write_instruction!(f, "str", register, address),
             Self::Ldr { register, address } => write_instruction!(f, "ldr", register, address),
+            Self::Strb { register, address } => write_instruction!(f, "strb", register, address),
+            Self::Ldrb { register, address } => write_instruction!(f, "ldrb", register, address),
+            Self::Strh { register, address } => write_instruction!(f, "strh", register, address),
+            Self::Ldrsh { register, address } => write_instruction!(f, "ldrsh", register, address),
+            Self::Ldrh { register, address } => write_instruction!(f, "ldrh", register, address),
+            Self::Ldrsb { register, address } => write_instruction!(f, "ldrsb", register, address),
+            Self::Ldar { register, base } => {
+                write_instruction!(f, "ldar", register, format!("[{}]", base))
+            }
+            Self::Stlr { register, base } => {
+                write_instruction!(f, "stlr", register, format!("[{}]", base))
+            }
+            Self::Ldaxr { register, base } => {
+                write_instruction!(f, "ldaxr", register, format!("[{}]", base))
+            }
+            Self::Stlxr {
+                status,
+                register,
+                base,
+            } => write_instruction!(f, "stlxr", status, register, format!("[{}]", base)),
+            Self::Ldaddal {
+                addend,
+                target,
+                base,
+            } => write_instruction!(f, "ldaddal", addend, target, format!("[{}]", base)),
+            Self::Stp {
+                first,
+                second,
+                address,
+            } => write_instruction!(f, "stp", first, second, address),
+            Self::Ldp {
+                first,
+                second,
+                address,
+            } => write_instruction!(f, "ldp", first, second, address),
             Self::Mul { target, lhs, rhs } => write_instruction!(f, "mul", target, lhs, rhs),
+            Self::Smaddl {
+                target,
+                lhs,
+                rhs,
+                addend,
+            } => write_instruction!(f, "smaddl", target, lhs, rhs, addend),
+            Self::Madd {
+                target,
+                lhs,
+                rhs,
+                addend,
+            } => write_instruction!(f, "madd", target, lhs, rhs, addend),
             Self::Div {
                 target,
                 lhs,
@@ -236,16 +593,54 @@ impl fmt::Display for Instruction {
                 lhs,
                 rhs
             ),
+            Self::Fmov { target, source } => write_instruction!(f, "fmov", target, source),
+            Self::Fadd { target, lhs, rhs } => write_instruction!(f, "fadd", target, lhs, rhs),
+            Self::Fsub { target, lhs, rhs } => write_instruction!(f, "fsub", target, lhs, rhs),
+            Self::Fmul { target, lhs, rhs } => write_instruction!(f, "fmul", target, lhs, rhs),
+            Self::Fdiv { target, lhs, rhs } => write_instruction!(f, "fdiv", target, lhs, rhs),
+            Self::Fneg { target, source } => write_instruction!(f, "fneg", target, source),
+            Self::Fcmp { lhs, rhs } => write_instruction!(f, "fcmp", lhs, rhs),
+            Self::Scvtf { target, source } => write_instruction!(f, "scvtf", target, source),
+            Self::Fcvtzs { target, source } => write_instruction!(f, "fcvtzs", target, source),
+            Self::Fcvt { target, source } => write_instruction!(f, "fcvt", target, source),
+            // spelled as the hints they are, so that cores without the extensions (and
+            // assemblers targeting them) take them as `nop`s
+            Self::Bti => write_instruction!(f, "hint", "#34 // bti c"),
+            Self::Paciasp => write_instruction!(f, "hint", "#25 // paciasp"),
+            Self::Autiasp => write_instruction!(f, "hint", "#29 // autiasp"),
+            Self::Brk(immediate) => {
+                write_instruction!(f, "brk", Data::Immediate(*immediate as i32))
+            }
             Self::Branch(branch) => branch.fmt(f),
         }
     }
 }
 
+/// `movz`, `movn` and `movk` only show the shift when there's one
+fn write_mov_wide(
+    f: &mut fmt::Formatter,
+    name: &str,
+    target: &Register,
+    immediate: u16,
+    shift: u8,
+) -> fmt::Result {
+    let immediate = format!("#{:#x}", immediate);
+    if shift == 0 {
+        write_instruction!(f, name, target, immediate)
+    } else {
+        write_instruction!(f, name, target, immediate, format!("lsl #{}", shift))
+    }
+}
+
 impl fmt::Display for Label {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Block { num } => write!(f, ".LBB{}", num),
-            Self::Epilogue => f.write_str(".epilogue"),
+            Self::Block { function, num } => write!(f, ".LBB{}_{}", function, num),
+            Self::Epilogue { function } => write!(f, ".epilogue{}", function),
+            Self::Function(name) | Self::Global(name) => f.write_str(name),
+            Self::ReadOnly { function, num } => write!(f, ".Lrodata{}_{}", function, num),
+            Self::Relaxed { function, num } => write!(f, ".Lrelax{}_{}", function, num),
+            Self::Atomic { function, num } => write!(f, ".Latomic{}_{}", function, num),
         }
     }
 }
@@ -271,6 +666,8 @@ impl fmt::Display for Branch {
                 label,
             } => write_instruction!(f, "b", label),
             Self::Linked { label } => write_instruction!(f, "bl", label),
+            Self::LinkedRegister { register } => write_instruction!(f, "blr", register),
+            Self::NonZero { register, label } => write_instruction!(f, "cbnz", register, label),
         }
     }
 }
@@ -312,23 +709,76 @@ impl Instruction {
             Self::Ldr {
                 ref mut address, ..
             } => mapper(address),
+            Self::Strb {
+                ref mut address, ..
+            } => mapper(address),
+            Self::Ldrb {
+                ref mut address, ..
+            } => mapper(address),
+            Self::Strh {
+                ref mut address, ..
+            } => mapper(address),
+            Self::Ldrsh {
+                ref mut address, ..
+            } => mapper(address),
+            Self::Ldrh {
+                ref mut address, ..
+            } => mapper(address),
+            Self::Ldrsb {
+                ref mut address, ..
+            } => mapper(address),
             Self::Add { .. }
+            | Self::Adrp { .. }
+            | Self::AddPageOffset { .. }
+            | Self::Stp { .. }
+            | Self::Ldp { .. }
+            | Self::Ldar { .. }
+            | Self::Stlr { .. }
+            | Self::Ldaxr { .. }
+            | Self::Stlxr { .. }
+            | Self::Ldaddal { .. }
             | Self::And { .. }
             | Self::Orr { .. }
             | Self::Eor { .. }
             | Self::Lsl { .. }
             | Self::Lsr { .. }
+            | Self::Asr { .. }
             | Self::MSub { .. }
             | Self::Mov { .. }
             | Self::Sub { .. }
             | Self::Neg { .. }
             | Self::Cset { .. }
+            | Self::Csel { .. }
+            | Self::Csinc { .. }
             | Self::MvN { .. }
             | Self::Cmp { .. }
             | Self::Mul { .. }
+            | Self::Smaddl { .. }
+            | Self::Madd { .. }
+            | Self::Movz { .. }
+            | Self::Movn { .. }
+            | Self::Movk { .. }
+            | Self::Sxtw { .. }
+            | Self::Sxth { .. }
+            | Self::Sxtb { .. }
             | Self::Div { .. }
+            | Self::Fmov { .. }
+            | Self::Fadd { .. }
+            | Self::Fsub { .. }
+            | Self::Fmul { .. }
+            | Self::Fdiv { .. }
+            | Self::Fneg { .. }
+            | Self::Fcmp { .. }
+            | Self::Scvtf { .. }
+            | Self::Fcvtzs { .. }
+            | Self::Fcvt { .. }
+            | Self::Bti
+            | Self::Paciasp
+            | Self::Autiasp
+            | Self::Brk(_)
             | Self::Branch(_)
-            | Self::Ret => {}
+            | Self::Ret
+            | Self::Retaa => {}
         }
     }
 }
@@ -374,6 +824,14 @@ pub enum Condition {
     GreaterEqual,
     LessThan,
     LessEqual,
+    /// Greater than, comparing unsigned values
+    Higher,
+    /// Greater than or equal, comparing unsigned values
+    HigherSame,
+    /// Less than, comparing unsigned values
+    Lower,
+    /// Less than or equal, comparing unsigned values
+    LowerSame,
 }
 
 impl fmt::Display for Condition {
@@ -385,15 +843,29 @@ impl fmt::Display for Condition {
             Self::LessThan => write!(f, "lt"),
             Self::GreaterThan => write!(f, "gt"),
             Self::GreaterEqual => write!(f, "ge"),
+            Self::Higher => write!(f, "hi"),
+            Self::HigherSame => write!(f, "hs"),
+            Self::Lower => write!(f, "lo"),
+            Self::LowerSame => write!(f, "ls"),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum Register {
-    GeneralPurpose { index: u8, bit_size: BitSize },
-    ZeroRegister { bit_size: BitSize },
+    GeneralPurpose {
+        index: u8,
+        bit_size: BitSize,
+    },
+    ZeroRegister {
+        bit_size: BitSize,
+    },
     StackPointer,
+    /// The lower 32 or 64 bits of a SIMD&FP register, as a `float` or a `double`
+    Float {
+        index: u8,
+        bit_size: BitSize,
+    },
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -401,6 +873,7 @@ pub enum RegisterID {
     GeneralPurpose { index: u8 },
     StackPointer,
     ZeroRegister,
+    Float { index: u8 },
 }
 
 impl fmt::Display for Register {
@@ -409,6 +882,7 @@ impl fmt::Display for Register {
             Self::GeneralPurpose { index, bit_size } => write!(f, "{}{}", bit_size.char(), index),
             Self::ZeroRegister { bit_size } => write!(f, "{}zr", bit_size.char()),
             Self::StackPointer => write!(f, "sp"),
+            Self::Float { index, bit_size } => write!(f, "{}{}", bit_size.float_char(), index),
         }
     }
 }
@@ -419,11 +893,12 @@ impl Register {
             RegisterID::GeneralPurpose { index } => Self::GeneralPurpose { index, bit_size },
             RegisterID::StackPointer => Self::StackPointer,
             RegisterID::ZeroRegister => Self::ZeroRegister { bit_size },
+            RegisterID::Float { index } => Self::Float { index, bit_size },
         }
     }
     pub const fn bit_size(self) -> BitSize {
         match self {
-            Self::GeneralPurpose { bit_size, .. } => bit_size,
+            Self::GeneralPurpose { bit_size, .. } | Self::Float { bit_size, .. } => bit_size,
             Self::ZeroRegister { bit_size } => bit_size,
             Self::StackPointer => BitSize::Bit64,
         }
@@ -444,12 +919,22 @@ impl BitSize {
             Self::Bit64 => 0xFFFFFFFFFFFFFFFF,
         }
     }
+    /// How many bits the registers have
+    pub const fn bits(self) -> u32 {
+        self.full_bits().count_ones()
+    }
     const fn char(&self) -> char {
         match self {
             Self::Bit32 => 'w',
             Self::Bit64 => 'x',
         }
     }
+    const fn float_char(&self) -> char {
+        match self {
+            Self::Bit32 => 's',
+            Self::Bit64 => 'd',
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -488,7 +973,7 @@ pub enum Offset {
 }
 
 fn partition(block_size: usize, mut offset_begin: usize) -> impl Iterator<Item = usize> {
-    std::iter::from_fn(move || {
+    core::iter::from_fn(move || {
         let curr = offset_begin;
         offset_begin += block_size;
         Some(curr)
@@ -508,7 +993,7 @@ impl Offset {
             }
         }
     }
-    fn determined_size(&self) -> usize {
+    pub fn determined_size(&self) -> usize {
         match self {
             Self::Determined(det) => *det,
             _ => unreachable!(),
@@ -528,6 +1013,20 @@ impl Condition {
             Self::GreaterEqual => Self::LessThan,
             Self::LessThan => Self::GreaterEqual,
             Self::LessEqual => Self::GreaterThan,
+            Self::Higher => Self::LowerSame,
+            Self::HigherSame => Self::Lower,
+            Self::Lower => Self::HigherSame,
+            Self::LowerSame => Self::Higher,
+        }
+    }
+    /// The same comparison made between unsigned values
+    pub fn unsigned(self) -> Self {
+        match self {
+            Self::GreaterThan => Self::Higher,
+            Self::GreaterEqual => Self::HigherSame,
+            Self::LessThan => Self::Lower,
+            Self::LessEqual => Self::LowerSame,
+            condition => condition,
         }
     }
 }
@@ -0,0 +1,918 @@
+//! Machine code for the instructions, for the internal linker ([`super::link`]). Only the forms
+//! that code generation emits are encoded, and each the way an assembler would: an immediate
+//! that doesn't fit the instruction gives no encoding, rather than a different instruction.
+
+use super::assembly::{BitSize, Branch, Condition, Data, Instruction, Label, Memory, Offset};
+use super::assembly::{PairAddress, Register};
+
+/// The label an instruction refers to, whose address [`encode`] needs
+pub fn label(instruction: &Instruction) -> Option<&Label> {
+    match instruction {
+        Instruction::Adrp { label, .. }
+        | Instruction::AddPageOffset { label, .. }
+        | Instruction::Branch(
+            Branch::Unconditional {
+                register: None,
+                label,
+            }
+            | Branch::Linked { label }
+            | Branch::Conditional { label, .. }
+            | Branch::NonZero { label, .. },
+        ) => Some(label),
+        _ => None,
+    }
+}
+
+/// Encode an instruction found at `address`, given the address of the label it refers to, if
+/// any. Gives `None` if the instruction can't be encoded as it is.
+pub fn encode(instruction: &Instruction, address: u64, target: Option<u64>) -> Option<u32> {
+    Some(match *instruction {
+        Instruction::Ret => 0xd65f_03c0,
+        Instruction::Retaa => 0xd65f_0bff,
+        Instruction::Mov {
+            target,
+            source: Data::Register(source),
+        } => {
+            if is_float(target) || is_float(source) {
+                return None;
+            }
+            if is_sp(target) || is_sp(source) {
+                sf(target) | 0x1100_0000 | n(source) << 5 | n(target)
+            } else {
+                sf(target) | 0x2a00_0000 | n(source) << 16 | 31 << 5 | n(target)
+            }
+        }
+        Instruction::Mov {
+            target,
+            source: Data::Immediate(value),
+        } => mov_immediate(target, value)?,
+        Instruction::MvN {
+            target,
+            source: Data::Register(source),
+        } => sf(target) | 0x2a20_0000 | n(source) << 16 | 31 << 5 | n(target),
+        Instruction::Movn {
+            target,
+            immediate,
+            shift,
+        } => move_wide(0x1280_0000, target, immediate, shift)?,
+        Instruction::Movz {
+            target,
+            immediate,
+            shift,
+        } => move_wide(0x5280_0000, target, immediate, shift)?,
+        Instruction::Movk {
+            target,
+            immediate,
+            shift,
+        } => move_wide(0x7280_0000, target, immediate, shift)?,
+        Instruction::Sxtw { target, source } => 0x9340_7c00 | n(source) << 5 | n(target),
+        Instruction::Sxth { target, source } => {
+            sf(target) | bitfield_n(target) | 0x1300_3c00 | n(source) << 5 | n(target)
+        }
+        Instruction::Sxtb { target, source } => {
+            sf(target) | bitfield_n(target) | 0x1300_1c00 | n(source) << 5 | n(target)
+        }
+        Instruction::Cmp { register, data } => add_sub(
+            (true, true),
+            Register::ZeroRegister {
+                bit_size: register.bit_size(),
+            },
+            register,
+            data,
+        )?,
+        // `csinc` of the zero register with itself, on the opposite condition
+        Instruction::Cset { target, condition } => {
+            sf(target) | 0x1a9f_07e0 | (condition_code(condition) ^ 1) << 12 | n(target)
+        }
+        Instruction::Csel {
+            target,
+            if_true,
+            if_false,
+            condition,
+        } => conditional_select(0x1a80_0000, target, (if_true, if_false), condition),
+        Instruction::Csinc {
+            target,
+            if_true,
+            if_false,
+            condition,
+        } => conditional_select(0x1a80_0400, target, (if_true, if_false), condition),
+        Instruction::Neg { target, source } => {
+            sf(target) | 0x4b00_0000 | n(source) << 16 | 31 << 5 | n(target)
+        }
+        Instruction::Add { target, lhs, rhs } => add_sub((false, false), target, lhs, rhs)?,
+        Instruction::Sub { target, lhs, rhs } => add_sub((true, false), target, lhs, rhs)?,
+        Instruction::Adrp { target: rd, .. } => {
+            let pages = (target? as i64 >> 12) - (address as i64 >> 12);
+            if !(-(1 << 20)..1 << 20).contains(&pages) {
+                return None;
+            }
+            let pages = pages as u32;
+            0x9000_0000 | (pages & 3) << 29 | (pages >> 2 & 0x7ffff) << 5 | n(rd)
+        }
+        Instruction::AddPageOffset {
+            target: rd, source, ..
+        } => sf(rd) | 0x1100_0000 | (target? as u32 & 0xfff) << 10 | n(source) << 5 | n(rd),
+        Instruction::MSub {
+            target,
+            multiplicand,
+            multiplier,
+            minuend,
+        } => multiply(0x1b00_8000, target, (multiplicand, multiplier), minuend),
+        Instruction::Mul {
+            target,
+            lhs,
+            rhs: Data::Register(rhs),
+        } => multiply(
+            0x1b00_0000,
+            target,
+            (lhs, rhs),
+            Register::ZeroRegister {
+                bit_size: target.bit_size(),
+            },
+        ),
+        Instruction::Smaddl {
+            target,
+            lhs,
+            rhs,
+            addend,
+        } => 0x9b20_0000 | n(rhs) << 16 | n(addend) << 10 | n(lhs) << 5 | n(target),
+        Instruction::Madd {
+            target,
+            lhs,
+            rhs,
+            addend,
+        } => multiply(0x1b00_0000, target, (lhs, rhs), addend),
+        Instruction::Div {
+            target,
+            lhs,
+            rhs: Data::Register(rhs),
+            signed,
+        } => {
+            sf(target)
+                | 0x1ac0_0800
+                | (signed as u32) << 10
+                | n(rhs) << 16
+                | n(lhs) << 5
+                | n(target)
+        }
+        Instruction::Lsl {
+            target,
+            lhs,
+            rhs: Data::Register(rhs),
+        } => sf(target) | 0x1ac0_2000 | n(rhs) << 16 | n(lhs) << 5 | n(target),
+        Instruction::Lsr {
+            target,
+            lhs,
+            rhs: Data::Register(rhs),
+        } => sf(target) | 0x1ac0_2400 | n(rhs) << 16 | n(lhs) << 5 | n(target),
+        Instruction::Asr {
+            target,
+            lhs,
+            rhs: Data::Register(rhs),
+        } => sf(target) | 0x1ac0_2800 | n(rhs) << 16 | n(lhs) << 5 | n(target),
+        // `ubfm`, which moves the bits that are kept in place
+        Instruction::Lsl {
+            target,
+            lhs,
+            rhs: Data::Immediate(amount),
+        } => {
+            let bits = target.bit_size().bits();
+            let amount = u32::try_from(amount).ok().filter(|&amount| amount < bits)?;
+            let (immr, imms) = ((bits - amount) % bits, bits - 1 - amount);
+            sf(target)
+                | bitfield_n(target)
+                | 0x5300_0000
+                | immr << 16
+                | imms << 10
+                | n(lhs) << 5
+                | n(target)
+        }
+        Instruction::Lsr {
+            target,
+            lhs,
+            rhs: Data::Immediate(amount),
+        } => {
+            let bits = target.bit_size().bits();
+            let amount = u32::try_from(amount).ok().filter(|&amount| amount < bits)?;
+            sf(target)
+                | bitfield_n(target)
+                | 0x5300_0000
+                | amount << 16
+                | (bits - 1) << 10
+                | n(lhs) << 5
+                | n(target)
+        }
+        // `sbfm`, like the `ubfm` of `lsr` but copying the sign bit
+        Instruction::Asr {
+            target,
+            lhs,
+            rhs: Data::Immediate(amount),
+        } => {
+            let bits = target.bit_size().bits();
+            let amount = u32::try_from(amount).ok().filter(|&amount| amount < bits)?;
+            sf(target)
+                | bitfield_n(target)
+                | 0x1300_0000
+                | amount << 16
+                | (bits - 1) << 10
+                | n(lhs) << 5
+                | n(target)
+        }
+        Instruction::And { target, lhs, rhs } => logical(0, target, lhs, rhs)?,
+        Instruction::Orr { target, lhs, rhs } => logical(1, target, lhs, rhs)?,
+        Instruction::Eor { target, lhs, rhs } => logical(2, target, lhs, rhs)?,
+        Instruction::Str { register, address } => {
+            let (unsigned, unscaled, scale) = word_access(register);
+            memory_access((unsigned, unscaled, scale), register, address)?
+        }
+        Instruction::Ldr { register, address } => {
+            let (unsigned, unscaled, scale) = word_access(register);
+            memory_access((unsigned | LOAD, unscaled | LOAD, scale), register, address)?
+        }
+        Instruction::Strb { register, address } => {
+            memory_access((0x3900_0000, 0x3800_0000, 1), register, address)?
+        }
+        Instruction::Ldrb { register, address } => {
+            memory_access((0x3940_0000, 0x3840_0000, 1), register, address)?
+        }
+        Instruction::Strh { register, address } => {
+            memory_access((0x7900_0000, 0x7800_0000, 2), register, address)?
+        }
+        Instruction::Ldrsh { register, address } => {
+            let opcodes = match register.bit_size() {
+                BitSize::Bit64 => (0x7980_0000, 0x7880_0000, 2),
+                BitSize::Bit32 => (0x79c0_0000, 0x78c0_0000, 2),
+            };
+            memory_access(opcodes, register, address)?
+        }
+        Instruction::Ldrh { register, address } => {
+            memory_access((0x7940_0000, 0x7840_0000, 2), register, address)?
+        }
+        Instruction::Ldrsb { register, address } => {
+            let opcodes = match register.bit_size() {
+                BitSize::Bit64 => (0x3980_0000, 0x3880_0000, 1),
+                BitSize::Bit32 => (0x39c0_0000, 0x38c0_0000, 1),
+            };
+            memory_access(opcodes, register, address)?
+        }
+        Instruction::Stp {
+            first,
+            second,
+            address,
+        } => pair(false, (first, second), address)?,
+        Instruction::Ldp {
+            first,
+            second,
+            address,
+        } => pair(true, (first, second), address)?,
+        Instruction::Ldar { register, base } => {
+            0x88df_fc00 | size(register) | n(base) << 5 | n(register)
+        }
+        Instruction::Stlr { register, base } => {
+            0x889f_fc00 | size(register) | n(base) << 5 | n(register)
+        }
+        Instruction::Ldaxr { register, base } => {
+            0x885f_fc00 | size(register) | n(base) << 5 | n(register)
+        }
+        Instruction::Stlxr {
+            status,
+            register,
+            base,
+        } => 0x8800_fc00 | size(register) | n(status) << 16 | n(base) << 5 | n(register),
+        Instruction::Ldaddal {
+            addend,
+            target,
+            base,
+        } => 0xb8e0_0000 | size(target) | n(addend) << 16 | n(base) << 5 | n(target),
+        Instruction::Fmov { target, source } => match (is_float(target), is_float(source)) {
+            (true, true) => 0x1e20_4000 | ftype(target) | n(source) << 5 | n(target),
+            (true, false) => sf(source) | 0x1e27_0000 | ftype(target) | n(source) << 5 | n(target),
+            (false, true) => sf(target) | 0x1e26_0000 | ftype(source) | n(source) << 5 | n(target),
+            (false, false) => return None,
+        },
+        Instruction::Fadd { target, lhs, rhs } => float_binary(0x1e20_2800, target, lhs, rhs),
+        Instruction::Fsub { target, lhs, rhs } => float_binary(0x1e20_3800, target, lhs, rhs),
+        Instruction::Fmul { target, lhs, rhs } => float_binary(0x1e20_0800, target, lhs, rhs),
+        Instruction::Fdiv { target, lhs, rhs } => float_binary(0x1e20_1800, target, lhs, rhs),
+        Instruction::Fneg { target, source } => {
+            0x1e21_4000 | ftype(target) | n(source) << 5 | n(target)
+        }
+        Instruction::Fcmp { lhs, rhs } => 0x1e20_2000 | ftype(lhs) | n(rhs) << 16 | n(lhs) << 5,
+        Instruction::Scvtf { target, source } => {
+            sf(source) | 0x1e22_0000 | ftype(target) | n(source) << 5 | n(target)
+        }
+        Instruction::Fcvtzs { target, source } => {
+            sf(target) | 0x1e38_0000 | ftype(source) | n(source) << 5 | n(target)
+        }
+        Instruction::Fcvt { target, source } => {
+            let double = (target.bit_size() == BitSize::Bit64) as u32;
+            0x1e22_4000 | ftype(source) | double << 15 | n(source) << 5 | n(target)
+        }
+        Instruction::Bti => 0xd503_245f,
+        Instruction::Paciasp => 0xd503_233f,
+        Instruction::Autiasp => 0xd503_23bf,
+        Instruction::Brk(immediate) => 0xd420_0000 | u32::from(immediate) << 5,
+        Instruction::Branch(ref branch) => match *branch {
+            Branch::Unconditional {
+                register: Some(register),
+                ..
+            } => 0xd61f_0000 | n(register) << 5,
+            Branch::LinkedRegister { register } => 0xd63f_0000 | n(register) << 5,
+            Branch::Unconditional { register: None, .. } => {
+                0x1400_0000 | branch_offset(address, target?, 26)?
+            }
+            Branch::Linked { .. } => 0x9400_0000 | branch_offset(address, target?, 26)?,
+            Branch::Conditional { condition, .. } => {
+                0x5400_0000 | branch_offset(address, target?, 19)? << 5 | condition_code(condition)
+            }
+            Branch::NonZero { register, .. } => {
+                sf(register) | 0x3500_0000 | branch_offset(address, target?, 19)? << 5 | n(register)
+            }
+        },
+        Instruction::Mov {
+            source: Data::StackOffset(_),
+            ..
+        }
+        | Instruction::MvN { .. }
+        | Instruction::Mul { .. }
+        | Instruction::Div { .. }
+        | Instruction::Lsl { .. }
+        | Instruction::Lsr { .. }
+        | Instruction::Asr { .. } => return None,
+    })
+}
+
+/// The L bit, which makes a store the load of the same size
+const LOAD: u32 = 1 << 22;
+
+/// The number of a register in its file; `sp` and the zero register are both 31
+const fn n(register: Register) -> u32 {
+    match register {
+        Register::GeneralPurpose { index, .. } | Register::Float { index, .. } => index as u32,
+        Register::ZeroRegister { .. } | Register::StackPointer => 31,
+    }
+}
+
+/// The sf bit, set for the 64-bit forms of the integer instructions
+const fn sf(register: Register) -> u32 {
+    match register.bit_size() {
+        BitSize::Bit32 => 0,
+        BitSize::Bit64 => 1 << 31,
+    }
+}
+
+/// The low bit of the size of the exclusive and atomic accesses, set when they're 64 bits
+const fn size(register: Register) -> u32 {
+    sf(register) >> 1
+}
+
+/// The N bit of the bitfield instructions, which must match sf
+const fn bitfield_n(register: Register) -> u32 {
+    match register.bit_size() {
+        BitSize::Bit32 => 0,
+        BitSize::Bit64 => 1 << 22,
+    }
+}
+
+/// The precision of a floating-point instruction: single or double
+const fn ftype(register: Register) -> u32 {
+    match register.bit_size() {
+        BitSize::Bit32 => 0,
+        BitSize::Bit64 => 1 << 22,
+    }
+}
+
+const fn is_sp(register: Register) -> bool {
+    matches!(register, Register::StackPointer)
+}
+
+const fn is_float(register: Register) -> bool {
+    matches!(register, Register::Float { .. })
+}
+
+const fn condition_code(condition: Condition) -> u32 {
+    match condition {
+        Condition::Equals => 0b0000,
+        Condition::NotEquals => 0b0001,
+        Condition::GreaterEqual => 0b1010,
+        Condition::LessThan => 0b1011,
+        Condition::GreaterThan => 0b1100,
+        Condition::LessEqual => 0b1101,
+        Condition::HigherSame => 0b0010,
+        Condition::Lower => 0b0011,
+        Condition::Higher => 0b1000,
+        Condition::LowerSame => 0b1001,
+    }
+}
+
+/// The value of an immediate in a register of the size of `register`: the assembler takes the
+/// lower bits of it for 32-bit registers, and sign extends it for 64-bit ones
+const fn register_value(register: Register, immediate: i32) -> u64 {
+    match register.bit_size() {
+        BitSize::Bit32 => immediate as u32 as u64,
+        BitSize::Bit64 => immediate as i64 as u64,
+    }
+}
+
+/// `add` and `sub`, or `adds` and `subs` when setting the flags. A negative immediate is the
+/// positive one of the opposite operation, and either register may be `sp`, which only the
+/// extended register form takes.
+fn add_sub(
+    (subtract, set_flags): (bool, bool),
+    target: Register,
+    lhs: Register,
+    rhs: Data,
+) -> Option<u32> {
+    let flags = (set_flags as u32) << 29;
+    match rhs {
+        Data::Immediate(value) => {
+            let magnitude = value.unsigned_abs();
+            let immediate = if magnitude < 1 << 12 {
+                magnitude << 10
+            } else if magnitude & 0xfff == 0 && magnitude < 1 << 24 {
+                1 << 22 | (magnitude >> 12) << 10
+            } else {
+                return None;
+            };
+            let subtract = (subtract != (value < 0)) as u32;
+            Some(
+                sf(target)
+                    | subtract << 30
+                    | flags
+                    | 0x1100_0000
+                    | immediate
+                    | n(lhs) << 5
+                    | n(target),
+            )
+        }
+        Data::Register(rhs) => {
+            let subtract = (subtract as u32) << 30;
+            if is_sp(target) || is_sp(lhs) {
+                // extended with `uxtx`, or `uxtw` for a 32-bit register
+                let option = match rhs.bit_size() {
+                    BitSize::Bit32 => 0b010,
+                    BitSize::Bit64 => 0b011,
+                };
+                Some(
+                    sf(target)
+                        | subtract
+                        | flags
+                        | 0x0b20_0000
+                        | n(rhs) << 16
+                        | option << 13
+                        | n(lhs) << 5
+                        | n(target),
+                )
+            } else {
+                Some(
+                    sf(target)
+                        | subtract
+                        | flags
+                        | 0x0b00_0000
+                        | n(rhs) << 16
+                        | n(lhs) << 5
+                        | n(target),
+                )
+            }
+        }
+        Data::StackOffset(_) => None,
+    }
+}
+
+/// `and`, `orr` (`opc` 1) and `eor` (`opc` 2), with a register or a bitmask immediate
+fn logical(opc: u32, target: Register, lhs: Register, rhs: Data) -> Option<u32> {
+    match rhs {
+        Data::Register(rhs) => {
+            Some(sf(target) | opc << 29 | 0x0a00_0000 | n(rhs) << 16 | n(lhs) << 5 | n(target))
+        }
+        Data::Immediate(value) => {
+            let value = register_value(target, value);
+            let (bit_n, immr, imms) = bitmask_immediate(value, target.bit_size())?;
+            Some(
+                sf(target)
+                    | opc << 29
+                    | 0x1200_0000
+                    | bit_n << 22
+                    | immr << 16
+                    | imms << 10
+                    | n(lhs) << 5
+                    | n(target),
+            )
+        }
+        Data::StackOffset(_) => None,
+    }
+}
+
+/// The N, immr and imms fields that make a value, if it's a bitmask immediate: a pattern of 2, 4,
+/// 8, 16, 32 or 64 bits repeated across the register, which is a rotated run of ones
+fn bitmask_immediate(value: u64, bit_size: BitSize) -> Option<(u32, u32, u32)> {
+    let value = match bit_size {
+        BitSize::Bit32 => value & 0xffff_ffff | value << 32,
+        BitSize::Bit64 => value,
+    };
+    if value == 0 || value == u64::MAX {
+        return None;
+    }
+    let mut size = 64;
+    while size > 2 {
+        let half = size / 2;
+        let mask = (1 << half) - 1;
+        if value & mask != (value >> half) & mask {
+            break;
+        }
+        size = half;
+    }
+    let mask = if size == 64 {
+        u64::MAX
+    } else {
+        (1 << size) - 1
+    };
+    let element = value & mask;
+    let ones = element.count_ones();
+    let run = if ones == 64 {
+        u64::MAX
+    } else {
+        (1 << ones) - 1
+    };
+    let rotate_right = |value: u64, amount: u32| {
+        if amount == 0 {
+            value
+        } else {
+            (value >> amount | value << (size - amount)) & mask
+        }
+    };
+    let immr = (0..size).find(|&amount| rotate_right(run, amount) == element)?;
+    let bit_n = (size == 64) as u32;
+    let imms = ((0b111_111 << (size.trailing_zeros() + 1)) & 0b111_111) | (ones - 1);
+    Some((bit_n, immr, imms))
+}
+
+/// `mov` with an immediate is a `movz`, a `movn` or an `orr` with the zero register, the first
+/// of them that can make the value
+fn mov_immediate(target: Register, value: i32) -> Option<u32> {
+    let bits = target.bit_size().bits();
+    let value = register_value(target, value);
+    let full = target.bit_size().full_bits();
+    for (opcode, value) in [(0x5280_0000, value), (0x1280_0000, !value & full)] {
+        for shift in (0..bits).step_by(16) {
+            if value & !(0xffff << shift) == 0 {
+                return move_wide(opcode, target, (value >> shift) as u16, shift as u8);
+            }
+        }
+    }
+    logical(
+        1,
+        target,
+        Register::ZeroRegister {
+            bit_size: target.bit_size(),
+        },
+        Data::Immediate(value as i32),
+    )
+}
+
+/// `movn`, `movz` and `movk`, whose shift is a multiple of 16 within the register
+fn move_wide(opcode: u32, target: Register, immediate: u16, shift: u8) -> Option<u32> {
+    let shift = u32::from(shift);
+    if shift % 16 != 0 || shift >= target.bit_size().bits() {
+        return None;
+    }
+    Some(sf(target) | opcode | (shift / 16) << 21 | u32::from(immediate) << 5 | n(target))
+}
+
+/// `madd` and `msub`: `target = addend ± lhs * rhs`
+fn multiply(
+    opcode: u32,
+    target: Register,
+    (lhs, rhs): (Register, Register),
+    addend: Register,
+) -> u32 {
+    sf(target) | opcode | n(rhs) << 16 | n(addend) << 10 | n(lhs) << 5 | n(target)
+}
+
+/// `csel` and `csinc`, which take the first register when the condition holds
+fn conditional_select(
+    opcode: u32,
+    target: Register,
+    (if_true, if_false): (Register, Register),
+    condition: Condition,
+) -> u32 {
+    sf(target)
+        | opcode
+        | n(if_false) << 16
+        | condition_code(condition) << 12
+        | n(if_true) << 5
+        | n(target)
+}
+
+fn float_binary(opcode: u32, target: Register, lhs: Register, rhs: Register) -> u32 {
+    opcode | ftype(target) | n(rhs) << 16 | n(lhs) << 5 | n(target)
+}
+
+/// The opcodes of `str` with an unsigned offset and with an unscaled one (`stur`), with the size
+/// of the access, for the register stored
+const fn word_access(register: Register) -> (u32, u32, usize) {
+    match (is_float(register), register.bit_size()) {
+        (false, BitSize::Bit32) => (0xb900_0000, 0xb800_0000, 4),
+        (false, BitSize::Bit64) => (0xf900_0000, 0xf800_0000, 8),
+        (true, BitSize::Bit32) => (0xbd00_0000, 0xbc00_0000, 4),
+        (true, BitSize::Bit64) => (0xfd00_0000, 0xfc00_0000, 8),
+    }
+}
+
+/// A load or store at an offset from a register: scaled by the size of the access if it's a
+/// multiple of it, or else unscaled (`ldur`, `stur`) if it's small enough
+fn memory_access(
+    (unsigned, unscaled, scale): (u32, u32, usize),
+    register: Register,
+    address: Memory,
+) -> Option<u32> {
+    let Offset::Determined(offset) = address.offset else {
+        return None;
+    };
+    let base = n(address.register) << 5 | n(register);
+    if offset % scale == 0 && offset / scale < 1 << 12 {
+        Some(unsigned | ((offset / scale) as u32) << 10 | base)
+    } else if offset < 1 << 8 {
+        Some(unscaled | (offset as u32) << 12 | base)
+    } else {
+        None
+    }
+}
+
+/// `stp` and `ldp`, whose offset is a 7-bit multiple of the size of the registers
+fn pair(load: bool, (first, second): (Register, Register), address: PairAddress) -> Option<u32> {
+    let (opcode, scale) = match (is_float(first), first.bit_size()) {
+        (false, BitSize::Bit32) => (0x2800_0000, 4),
+        (false, BitSize::Bit64) => (0xa800_0000, 8),
+        (true, BitSize::Bit32) => (0x2c00_0000, 4),
+        (true, BitSize::Bit64) => (0x6c00_0000, 8),
+    };
+    let (mode, base, offset) = match address {
+        PairAddress::PostIndex { base, offset } => (0b01, base, offset),
+        PairAddress::Offset { base, offset } => (0b10, base, offset),
+        PairAddress::PreIndex { base, offset } => (0b11, base, offset),
+    };
+    if offset % scale != 0 || !(-64..64).contains(&(offset / scale)) {
+        return None;
+    }
+    let offset = (offset / scale) as u32 & 0x7f;
+    Some(
+        opcode
+            | mode << 23
+            | (load as u32) << 22
+            | offset << 15
+            | n(second) << 10
+            | n(base) << 5
+            | n(first),
+    )
+}
+
+/// The offset of a branch in instructions, as a field of `bits` bits, if it reaches
+fn branch_offset(address: u64, target: u64, bits: u32) -> Option<u32> {
+    let offset = target.wrapping_sub(address) as i64;
+    let reach = 1 << (bits + 1);
+    if offset % 4 != 0 || !(-reach..reach).contains(&offset) {
+        return None;
+    }
+    Some((offset >> 2) as u32 & ((1 << bits) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn x(index: u8) -> Register {
+        Register::GeneralPurpose {
+            index,
+            bit_size: BitSize::Bit64,
+        }
+    }
+
+    const fn w(index: u8) -> Register {
+        Register::GeneralPurpose {
+            index,
+            bit_size: BitSize::Bit32,
+        }
+    }
+
+    #[test]
+    fn instructions_are_encoded_as_the_assembler_does() {
+        // checked against `llvm-mc -triple=aarch64-linux-gnu -show-encoding`
+        let encoded = [
+            (
+                Instruction::Stp {
+                    first: x(29),
+                    second: x(30),
+                    address: PairAddress::PreIndex {
+                        base: Register::StackPointer,
+                        offset: -16,
+                    },
+                },
+                0xa9bf_7bfd,
+            ),
+            (
+                Instruction::Mov {
+                    target: x(29),
+                    source: Data::Register(Register::StackPointer),
+                },
+                0x9100_03fd,
+            ),
+            (
+                Instruction::Sub {
+                    target: Register::StackPointer,
+                    lhs: Register::StackPointer,
+                    rhs: Data::Immediate(48),
+                },
+                0xd100_c3ff,
+            ),
+            (
+                Instruction::Mov {
+                    target: w(0),
+                    source: Data::Immediate(-1),
+                },
+                0x1280_0000,
+            ),
+            (
+                Instruction::And {
+                    target: w(2),
+                    lhs: w(2),
+                    rhs: Data::Immediate(0xff00),
+                },
+                0x1218_1c42,
+            ),
+            (
+                Instruction::Cmp {
+                    register: w(2),
+                    data: Data::Immediate(-4),
+                },
+                0x3100_105f,
+            ),
+            (
+                Instruction::Cset {
+                    target: w(0),
+                    condition: Condition::GreaterThan,
+                },
+                0x1a9f_d7e0,
+            ),
+            (
+                Instruction::Ldr {
+                    register: x(1),
+                    address: Memory {
+                        register: Register::StackPointer,
+                        offset: Offset::Determined(12),
+                    },
+                },
+                0xf840_c3e1,
+            ),
+            (
+                Instruction::Lsl {
+                    target: w(1),
+                    lhs: w(1),
+                    rhs: Data::Immediate(3),
+                },
+                0x531d_7021,
+            ),
+            (
+                Instruction::Fcvtzs {
+                    target: w(0),
+                    source: Register::Float {
+                        index: 0,
+                        bit_size: BitSize::Bit64,
+                    },
+                },
+                0x1e78_0000,
+            ),
+            (
+                Instruction::Sxtb {
+                    target: x(8),
+                    source: w(8),
+                },
+                0x9340_1d08,
+            ),
+            (
+                Instruction::Asr {
+                    target: x(7),
+                    lhs: x(7),
+                    rhs: Data::Immediate(3),
+                },
+                0x9343_fce7,
+            ),
+            (
+                Instruction::Asr {
+                    target: w(1),
+                    lhs: w(2),
+                    rhs: Data::Register(w(3)),
+                },
+                0x1ac3_2841,
+            ),
+            (
+                Instruction::Ldrh {
+                    register: w(0),
+                    address: Memory {
+                        register: Register::StackPointer,
+                        offset: Offset::Determined(6),
+                    },
+                },
+                0x7940_0fe0,
+            ),
+            (
+                Instruction::Ldrsb {
+                    register: w(8),
+                    address: Memory {
+                        register: x(8),
+                        offset: Offset::Determined(3),
+                    },
+                },
+                0x39c0_0d08,
+            ),
+            (
+                Instruction::Cset {
+                    target: w(8),
+                    condition: Condition::Lower,
+                },
+                0x1a9f_27e8,
+            ),
+            (
+                Instruction::Csel {
+                    target: x(0),
+                    if_true: x(16),
+                    if_false: x(17),
+                    condition: Condition::LessThan,
+                },
+                0x9a91_b200,
+            ),
+            (
+                Instruction::Csinc {
+                    target: x(0),
+                    if_true: x(1),
+                    if_false: Register::ZeroRegister {
+                        bit_size: BitSize::Bit64,
+                    },
+                    condition: Condition::Equals,
+                },
+                0x9a9f_0420,
+            ),
+            (Instruction::Retaa, 0xd65f_0bff),
+            (
+                Instruction::Ldar {
+                    register: w(0),
+                    base: x(1),
+                },
+                0x88df_fc20,
+            ),
+            (
+                Instruction::Stlr {
+                    register: x(6),
+                    base: x(7),
+                },
+                0xc89f_fce6,
+            ),
+            (
+                Instruction::Ldaxr {
+                    register: x(8),
+                    base: x(9),
+                },
+                0xc85f_fd28,
+            ),
+            (
+                Instruction::Stlxr {
+                    status: w(16),
+                    register: w(17),
+                    base: x(3),
+                },
+                0x8810_fc71,
+            ),
+            (
+                Instruction::Ldaddal {
+                    addend: x(1),
+                    target: x(2),
+                    base: x(3),
+                },
+                0xf8e1_0062,
+            ),
+        ];
+        for (instruction, expected) in encoded {
+            assert_eq!(
+                encode(&instruction, 0, None),
+                Some(expected),
+                "{}",
+                instruction
+            );
+        }
+        let too_wide = Instruction::And {
+            target: w(2),
+            lhs: w(2),
+            rhs: Data::Immediate(70000),
+        };
+        assert_eq!(encode(&too_wide, 0, None), None);
+        // back two instructions, to the `ldaxr` of the loop
+        let retry = Instruction::Branch(Branch::NonZero {
+            register: w(16),
+            label: Label::Atomic {
+                function: 0,
+                num: 0,
+            },
+        });
+        assert_eq!(encode(&retry, 8, Some(0)), Some(0x35ff_ffd0));
+    }
+}
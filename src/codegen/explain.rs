@@ -0,0 +1,128 @@
+//! `--explain-ir`: a listing of the source lines of a function, each followed by the IR statements
+//! generated for it and then the instructions they compiled to. Statements are tied to the source
+//! statement they were generated for, so the code around them (the frame and the branches) isn't
+//! listed, and neither are the few statements the optimizer makes on its own.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::assembly::Assembly;
+use crate::error::{SourceMetadata, Span};
+use crate::intermediate::{Binding, BlockBinding};
+use crate::intermediate::{BlockEnd, Branch, IR};
+use alloc::collections::BTreeMap;
+use hashbrown::HashMap;
+
+/// What was made from a source line
+#[derive(Default)]
+struct Line {
+    text: String,
+    ir: Vec<String>,
+    assembly: Vec<String>,
+}
+
+type Lines = BTreeMap<(Option<String>, usize), Line>;
+
+/// The line the statement that made a binding is on
+fn line_of<'a>(
+    lines: &'a mut Lines,
+    binding: Binding,
+    spans: &HashMap<Binding, Span>,
+    source: &SourceMetadata,
+) -> Option<&'a mut Line> {
+    let (file, snippet) = source.locate(*spans.get(&binding)?);
+    let snippet = snippet?;
+    Some(
+        lines
+            .entry((file, snippet.position().line))
+            .or_insert_with(|| Line {
+                text: snippet.line().trim().to_string(),
+                ..Line::default()
+            }),
+    )
+}
+
+/// List the lines of a function with the IR and the assembly made from them. `explained` is what
+/// [`super::codegen_function`] kept of each statement when explaining.
+pub fn explain_function(
+    name: &str,
+    ir: &IR,
+    spans: &HashMap<Binding, Span>,
+    explained: &[(Binding, Vec<Assembly>)],
+    source: &SourceMetadata,
+) -> String {
+    let mut lines = Lines::new();
+    for (index, block) in ir.code.iter().enumerate() {
+        for statement in &block.statements {
+            if let Some(line) = line_of(&mut lines, statement.binding(), spans, source) {
+                line.ir.push(format!("{}", statement));
+            }
+        }
+        let end_binding = match block.end {
+            BlockEnd::Return(binding) => Some(binding),
+            BlockEnd::Branch(Branch::Conditional { flag, .. }) => Some(flag),
+            BlockEnd::Branch(Branch::Unconditional { .. }) => None,
+        };
+        if let Some(line) =
+            end_binding.and_then(|binding| line_of(&mut lines, binding, spans, source))
+        {
+            line.ir
+                .push(format!("{}: {}", BlockBinding(index), block.end));
+        }
+    }
+    for (binding, assembly) in explained {
+        if let Some(line) = line_of(&mut lines, *binding, spans, source) {
+            line.assembly.extend(
+                assembly
+                    .iter()
+                    .map(|asm| asm.to_string().trim().to_string()),
+            );
+        }
+    }
+
+    let mut text = format!("{}:\n", name);
+    for ((file, number), line) in lines {
+        let file = file.as_deref().unwrap_or("<unknown source>");
+        text += &format!("{}:{}: {}\n", file, number + 1, line.text);
+        for statement in line.ir {
+            text += &format!("    {}\n", statement);
+        }
+        for instruction in line.assembly {
+            text += &format!("        {}\n", instruction);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_list_their_ir_and_then_their_assembly() -> anyhow::Result<()> {
+        let source = "int f(int a) {\n    return a * 3;\n}\n";
+        let meta = SourceMetadata::new(source).with_file("f.c".into());
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (mut module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let function = module.functions.remove(0);
+        let (ir, spans) = (function.ir.clone(), function.spans.clone());
+        let options = super::super::CodegenOptions {
+            explain: true,
+            ..Default::default()
+        };
+        let compiled = super::super::codegen_function(function, 0, &[], options, &meta);
+        let explained = explain_function("f", &ir, &spans, &compiled.explained, &meta);
+        let line = explained
+            .split_once("f.c:2: return a * 3;\n")
+            .expect("the return line is listed")
+            .1;
+        let listed: Vec<_> = line.lines().collect();
+        let last_statement = listed.iter().rposition(|line| line.starts_with("    %"));
+        let first_instruction = listed.iter().position(|line| line.starts_with("        "));
+        assert!(last_statement.is_some() && last_statement < first_instruction);
+        assert!(listed.iter().any(|line| line.trim().starts_with("mul")));
+        Ok(())
+    }
+}
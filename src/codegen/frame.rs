@@ -0,0 +1,425 @@
+//! Stack frame layout of a function, following the AAPCS64:
+//!
+//! ```text
+//!   caller's frame
+//!   ---------------  <- frame pointer of the caller
+//!   x29, x30         (frame record, x29 points here)
+//!   callee-saved registers (general purpose ones, then floating-point ones)
+//!   locals
+//!   ---------------  <- sp
+//! ```
+//!
+//! The frame record makes the function re-entrant (it can be called recursively, since the return
+//! address is saved) and lets debuggers unwind the stack. The stack pointer is kept 16-byte
+//! aligned at all times.
+//!
+//! When a local asks for a stricter alignment than that, `sp` is rounded down after making room
+//! for the frame, leaving a gap of unknown size between the frame record and the callee-saved
+//! registers. The epilogue then takes `sp` back from the frame pointer.
+
+use alloc::vec::Vec;
+
+use super::assembly::{self, BitSize, Instruction, Memory, Offset, PairAddress, Register};
+use super::output::AssemblyOutput;
+use super::target;
+use crate::allocators::{memory, registers};
+
+pub struct FrameLayout {
+    /// bytes reserved for the local variables
+    locals_size: usize,
+    /// alignment that the locals need from `sp`
+    locals_align: usize,
+    /// callee-saved registers that the function modifies, sorted
+    callee_saved: Vec<u8>,
+    /// callee-saved floating-point registers (`d8` to `d15`) that the function modifies, sorted
+    float_callee_saved: Vec<u8>,
+    branch_protection: super::BranchProtection,
+}
+
+const FRAME_POINTER: u8 = 29;
+const LINK_REGISTER: u8 = 30;
+
+fn register_size() -> usize {
+    target::current().data_layout.register_size
+}
+
+/// alignment of `sp` that the AAPCS64 guarantees
+fn stack_align() -> usize {
+    target::current().data_layout.stack_align
+}
+
+const fn x(index: u8) -> Register {
+    Register::GeneralPurpose {
+        index,
+        bit_size: BitSize::Bit64,
+    }
+}
+
+/// Only the lower 64 bits of the floating-point registers are preserved across calls
+const fn d(index: u8) -> Register {
+    Register::Float {
+        index,
+        bit_size: BitSize::Bit64,
+    }
+}
+
+impl FrameLayout {
+    pub fn new(
+        locals_size: usize,
+        locals_align: usize,
+        registers: &registers::RegisterMap,
+        branch_protection: super::BranchProtection,
+    ) -> Self {
+        let mut callee_saved: Vec<_> = registers
+            .values()
+            .filter_map(|register| match register {
+                assembly::RegisterID::GeneralPurpose { index }
+                    if registers::is_callee_saved(*index) =>
+                {
+                    Some(*index)
+                }
+                _ => None,
+            })
+            .collect();
+        callee_saved.sort_unstable();
+        callee_saved.dedup();
+        let mut float_callee_saved: Vec<_> = registers
+            .values()
+            .filter_map(|register| match register {
+                assembly::RegisterID::Float { index }
+                    if registers::is_float_callee_saved(*index) =>
+                {
+                    Some(*index)
+                }
+                _ => None,
+            })
+            .collect();
+        float_callee_saved.sort_unstable();
+        float_callee_saved.dedup();
+        Self {
+            locals_size: memory::align(locals_size, stack_align()),
+            locals_align,
+            callee_saved,
+            float_callee_saved,
+            branch_protection,
+        }
+    }
+
+    /// Size of the frame below the frame record (locals and saved registers)
+    pub fn size(&self) -> usize {
+        self.locals_size + self.save_area_size()
+    }
+
+    /// Bytes of stack the whole frame takes at most, frame record included
+    pub fn total_size(&self) -> usize {
+        2 * register_size() + self.size() + self.locals_align.saturating_sub(stack_align())
+    }
+
+    /// Bytes of the frame reserved for the local variables
+    pub fn locals_size(&self) -> usize {
+        self.locals_size
+    }
+
+    /// The callee-saved registers the prologue saves, general purpose ones first
+    pub fn saved_registers(&self) -> impl Iterator<Item = Register> + '_ {
+        let general_purpose = self.callee_saved.iter().map(|&index| x(index));
+        general_purpose.chain(self.float_callee_saved.iter().map(|&index| d(index)))
+    }
+
+    /// Whether `sp` has to be aligned further than it already is
+    fn realigns(&self) -> bool {
+        self.locals_align > stack_align()
+    }
+
+    /// Each register file is saved by pairs of its own
+    fn save_area_size(&self) -> usize {
+        let pairs = self.callee_saved.len().div_ceil(2) + self.float_callee_saved.len().div_ceil(2);
+        pairs * 2 * register_size()
+    }
+
+    /// addresses (relative to the final `sp`) where the callee-saved registers are kept, by pairs
+    fn saved_pairs(&self) -> impl Iterator<Item = (usize, Vec<Register>)> + '_ {
+        let general_purpose = self
+            .callee_saved
+            .chunks(2)
+            .map(|pair| pair.iter().map(|&index| x(index)).collect());
+        let floats = self
+            .float_callee_saved
+            .chunks(2)
+            .map(|pair| pair.iter().map(|&index| d(index)).collect());
+        general_purpose
+            .chain(floats)
+            .enumerate()
+            .map(move |(i, pair)| (self.locals_size + 2 * register_size() * i, pair))
+    }
+
+    /// The prologue makes the frame and saves the callee-saved registers. With `trap_on_entry`,
+    /// it stops in the debugger first, right after the landing pad.
+    pub fn prologue(&self, trap_on_entry: bool) -> AssemblyOutput {
+        let mut output = AssemblyOutput::new();
+        // signing the return address already makes a landing pad
+        if self.branch_protection.pac_ret {
+            output.push_back(Instruction::Paciasp);
+        } else if self.branch_protection.bti {
+            output.push_back(Instruction::Bti);
+        }
+        if trap_on_entry {
+            output.push_back(Instruction::Brk(0));
+        }
+        output.extend([
+            Instruction::Stp {
+                first: x(FRAME_POINTER),
+                second: x(LINK_REGISTER),
+                address: PairAddress::PreIndex {
+                    base: Register::StackPointer,
+                    offset: -(2 * register_size() as i32),
+                },
+            },
+            Instruction::Mov {
+                target: x(FRAME_POINTER),
+                source: assembly::Data::Register(Register::StackPointer),
+            },
+        ]);
+        if self.realigns() {
+            // `and` can't read `sp`, so the rounding goes through a scratch register
+            output.extend([
+                Instruction::Sub {
+                    target: x(super::SCRATCH_REGISTER),
+                    lhs: Register::StackPointer,
+                    rhs: assembly::Data::Immediate(self.size() as i32),
+                },
+                Instruction::And {
+                    target: Register::StackPointer,
+                    lhs: x(super::SCRATCH_REGISTER),
+                    rhs: assembly::Data::Immediate(-(self.locals_align as i32)),
+                },
+            ]);
+        } else if self.size() != 0 {
+            output.push_back(Instruction::Sub {
+                target: Register::StackPointer,
+                lhs: Register::StackPointer,
+                rhs: assembly::Data::Immediate(self.size() as i32),
+            });
+        }
+        for (offset, pair) in self.saved_pairs() {
+            output.push_back(match pair[..] {
+                [first, second] => Instruction::Stp {
+                    first,
+                    second,
+                    address: PairAddress::Offset {
+                        base: Register::StackPointer,
+                        offset: offset as i32,
+                    },
+                },
+                [single] => Instruction::Str {
+                    register: single,
+                    address: Memory {
+                        register: Register::StackPointer,
+                        offset: Offset::Determined(offset),
+                    },
+                },
+                _ => unreachable!("registers are saved by pairs"),
+            });
+        }
+        output
+    }
+
+    /// The epilogue restores everything the prologue saved and returns to the caller
+    pub fn epilogue(&self) -> AssemblyOutput {
+        let mut output = AssemblyOutput::new();
+        for (offset, pair) in self.saved_pairs() {
+            output.push_back(match pair[..] {
+                [first, second] => Instruction::Ldp {
+                    first,
+                    second,
+                    address: PairAddress::Offset {
+                        base: Register::StackPointer,
+                        offset: offset as i32,
+                    },
+                },
+                [single] => Instruction::Ldr {
+                    register: single,
+                    address: Memory {
+                        register: Register::StackPointer,
+                        offset: Offset::Determined(offset),
+                    },
+                },
+                _ => unreachable!("registers are saved by pairs"),
+            });
+        }
+        if self.realigns() {
+            // the gap left by the alignment is only known at runtime
+            output.push_back(Instruction::Mov {
+                target: Register::StackPointer,
+                source: assembly::Data::Register(x(FRAME_POINTER)),
+            });
+        } else if self.size() != 0 {
+            output.push_back(Instruction::Add {
+                target: Register::StackPointer,
+                lhs: Register::StackPointer,
+                rhs: assembly::Data::Immediate(self.size() as i32),
+            });
+        }
+        output.push_back(Instruction::Ldp {
+            first: x(FRAME_POINTER),
+            second: x(LINK_REGISTER),
+            address: PairAddress::PostIndex {
+                base: Register::StackPointer,
+                offset: 2 * register_size() as i32,
+            },
+        });
+        if self.branch_protection.pac_ret && target::cpu().features.pauth {
+            output.push_back(Instruction::Retaa);
+            return output;
+        }
+        if self.branch_protection.pac_ret {
+            output.push_back(Instruction::Autiasp);
+        }
+        output.push_back(Instruction::Ret);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::Binding;
+    use alloc::string::{String, ToString};
+
+    fn lines(output: AssemblyOutput) -> Vec<String> {
+        output
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn callee_saved_registers_are_kept_by_pairs_below_the_frame_record() {
+        // x0 is the caller's to save, and x20 is used twice
+        let registers: registers::RegisterMap = [0, 19, 20, 21, 20]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, index)| {
+                (
+                    Binding(binding),
+                    assembly::RegisterID::GeneralPurpose { index },
+                )
+            })
+            .collect();
+        let frame = FrameLayout::new(20, 16, &registers, Default::default());
+        // the locals take 32 bytes to keep the stack aligned, and the three registers 32 more
+        assert_eq!(frame.size(), 64);
+        assert_eq!(
+            lines(frame.prologue(false)),
+            [
+                "stp x29, x30, [sp, #-16]!",
+                "mov x29, sp",
+                "sub sp, sp, #64",
+                "stp x19, x20, [sp, #32]",
+                "str x21, [sp, #48]",
+            ]
+        );
+        assert_eq!(
+            lines(frame.epilogue()),
+            [
+                "ldp x19, x20, [sp, #32]",
+                "ldr x21, [sp, #48]",
+                "add sp, sp, #64",
+                "ldp x29, x30, [sp], #16",
+                "ret",
+            ]
+        );
+
+        // a leaf function with no locals only has its frame record
+        let frame = FrameLayout::new(0, 1, &registers::RegisterMap::new(), Default::default());
+        assert_eq!(frame.size(), 0);
+        assert_eq!(
+            lines(frame.prologue(false)),
+            ["stp x29, x30, [sp, #-16]!", "mov x29, sp"]
+        );
+    }
+
+    #[test]
+    fn locals_aligned_past_the_stack_realign_sp() {
+        let frame = FrameLayout::new(8, 64, &registers::RegisterMap::new(), Default::default());
+        assert_eq!(frame.size(), 16);
+        // the rounding down might skip up to 48 more bytes
+        assert_eq!(frame.total_size(), 16 + 16 + 48);
+        assert_eq!(
+            lines(frame.prologue(false)),
+            [
+                "stp x29, x30, [sp, #-16]!",
+                "mov x29, sp",
+                "sub x16, sp, #16",
+                "and sp, x16, #-64",
+            ]
+        );
+        // the frame pointer remembers where `sp` was before rounding it
+        assert_eq!(
+            lines(frame.epilogue()),
+            ["mov sp, x29", "ldp x29, x30, [sp], #16", "ret"]
+        );
+    }
+
+    #[test]
+    fn branch_protection_wraps_the_frame_record() {
+        let registers = registers::RegisterMap::new();
+        let bti = super::super::BranchProtection {
+            bti: true,
+            pac_ret: false,
+        };
+        let frame = FrameLayout::new(0, 1, &registers, bti);
+        assert_eq!(
+            lines(frame.prologue(false)),
+            [
+                "hint #34 // bti c",
+                "stp x29, x30, [sp, #-16]!",
+                "mov x29, sp"
+            ]
+        );
+        // returns aren't indirect branches, so they need no landing pad
+        assert_eq!(lines(frame.epilogue()), ["ldp x29, x30, [sp], #16", "ret"]);
+
+        // signing the return address is a landing pad already
+        let standard = "standard".parse().expect("a valid protection");
+        let frame = FrameLayout::new(0, 1, &registers, standard);
+        assert_eq!(
+            lines(frame.prologue(false)),
+            [
+                "hint #25 // paciasp",
+                "stp x29, x30, [sp, #-16]!",
+                "mov x29, sp"
+            ]
+        );
+        assert_eq!(
+            lines(frame.epilogue()),
+            ["ldp x29, x30, [sp], #16", "hint #29 // autiasp", "ret"]
+        );
+    }
+
+    #[test]
+    fn trapping_on_entry_comes_right_after_the_landing_pad() {
+        let registers = registers::RegisterMap::new();
+        let frame = FrameLayout::new(0, 1, &registers, Default::default());
+        assert_eq!(
+            lines(frame.prologue(true)),
+            ["brk #0", "stp x29, x30, [sp, #-16]!", "mov x29, sp"]
+        );
+        let bti = super::super::BranchProtection {
+            bti: true,
+            pac_ret: false,
+        };
+        let frame = FrameLayout::new(0, 1, &registers, bti);
+        assert_eq!(
+            lines(frame.prologue(true)),
+            [
+                "hint #34 // bti c",
+                "brk #0",
+                "stp x29, x30, [sp, #-16]!",
+                "mov x29, sp"
+            ]
+        );
+        // leaving the function is untouched
+        assert_eq!(lines(frame.epilogue()), ["ldp x29, x30, [sp], #16", "ret"]);
+    }
+}
@@ -0,0 +1,705 @@
+//! A minimal linker, which lays out a freestanding program as a static ELF executable without an
+//! assembler or a linker from a toolchain (`--emit=exe`). The program can't use anything it
+//! doesn't define itself, since no library is linked: it starts at its `_start`, or at a small
+//! one that calls `main` and exits with what it returns.
+//!
+//! The executable has its code (and the headers) in one read-only, executable segment, the
+//! read-only data in a segment of its own, and the global variables in a writable one, where those
+//! that start zeroed (`.bss`) come after the others and take no room in the file. Its sections and
+//! symbols are only there for tools like debuggers and disassemblers.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+use thiserror::Error;
+
+use super::assembly::{Assembly, BitSize, Branch, Directive, Instruction, Label, Register};
+use super::encode;
+use crate::error::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum LinkError {
+    #[error("undefined reference to `{0}`: the internal linker links no libraries, so a program linked with it can only use what it defines")]
+    Undefined(String),
+    #[error("`{0}` is defined more than once")]
+    Redefined(String),
+    #[error("there's no `main` or `_start` to start the program at")]
+    NoEntry,
+    #[error("the internal linker doesn't lay out `{0}` sections")]
+    UnsupportedSection(String),
+    #[error("internal compiler error: the internal linker can't encode `{0}`")]
+    Unencodable(String),
+}
+
+pub type LinkE = Error<LinkError>;
+
+/// Where the executable is loaded
+const BASE_ADDRESS: u64 = 0x40_0000;
+/// Segments are aligned to the largest page size of AArch64, so they load with any of them
+const SEGMENT_ALIGN: u64 = 0x1_0000;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+
+const EM_AARCH64: u16 = 183;
+const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474_e551;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_FUNC: u8 = 2;
+
+/// `nop`, which pads the code
+const NOP: u32 = 0xd503_201f;
+/// `svc #0`, the system call
+const SVC: u32 = 0xd400_0001;
+/// The number of the `exit_group` system call
+const EXIT_GROUP: u16 = 94;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectionKind {
+    Text,
+    ReadOnly,
+    /// Global variables with the bytes they start with
+    Data,
+    /// Global variables that start zeroed, which only take room in memory
+    Bss,
+    /// Notes the executable doesn't need, like the one that asks for a non-executable stack,
+    /// which the executable asks for with a program header instead
+    Discarded,
+}
+
+/// The bytes of a section, and the alignment it needs
+#[derive(Default)]
+struct Section {
+    bytes: Vec<u8>,
+    align: usize,
+}
+
+impl Section {
+    fn pad_to(&mut self, align: usize, filler: &[u8]) {
+        self.align = self.align.max(align);
+        while !self.bytes.len().is_multiple_of(align) {
+            let missing = align - self.bytes.len() % align;
+            self.bytes.extend(&filler[..filler.len().min(missing)]);
+        }
+    }
+}
+
+/// A label, by the section it's in and its offset there
+struct Defined {
+    section: SectionKind,
+    offset: usize,
+}
+
+/// Link the assembly of a whole program into the bytes of an executable
+pub fn link_executable(assembly: &[Assembly]) -> Result<Vec<u8>, LinkE> {
+    let defines = |name: &str| {
+        assembly
+            .iter()
+            .any(|item| matches!(item, Assembly::Label(label) if label == name))
+    };
+    let entry = if defines("_start") {
+        Vec::new()
+    } else if defines("main") {
+        start_calling_main()
+    } else {
+        return Err(LinkE::new(LinkError::NoEntry));
+    };
+
+    let (mut text, mut read_only) = (Section::default(), Section::default());
+    let (mut data, mut bss) = (Section::default(), Section::default());
+    text.align = 16;
+    let mut current = SectionKind::Text;
+    let mut labels = HashMap::new();
+    let mut instructions = Vec::new();
+    let (mut bindings, mut functions, mut aliases) = (HashMap::new(), HashSet::new(), Vec::new());
+    for item in entry.iter().chain(assembly) {
+        let section = match current {
+            SectionKind::Text => &mut text,
+            SectionKind::ReadOnly => &mut read_only,
+            SectionKind::Data => &mut data,
+            SectionKind::Bss => &mut bss,
+            SectionKind::Discarded => &mut Section::default(),
+        };
+        match item {
+            Assembly::Label(name) => {
+                let defined = Defined {
+                    section: current,
+                    offset: section.bytes.len(),
+                };
+                if labels.insert(name.clone(), defined).is_some() {
+                    return Err(LinkE::new(LinkError::Redefined(name.clone())));
+                }
+            }
+            Assembly::Instruction(instruction) => {
+                if current != SectionKind::Text {
+                    return Err(LinkE::new(LinkError::Unencodable(instruction.to_string())));
+                }
+                instructions.push((section.bytes.len(), instruction));
+                section.bytes.extend(NOP.to_le_bytes());
+            }
+            Assembly::Directive(directive) => match directive {
+                Directive::Text => current = SectionKind::Text,
+                Directive::Section { name, .. } => current = section_kind(name)?,
+                Directive::P2Align(power) => {
+                    let filler = match current {
+                        SectionKind::Text => NOP.to_le_bytes(),
+                        _ => [0; 4],
+                    };
+                    section.pad_to(1 << power, &filler);
+                }
+                Directive::Word(word) => section.bytes.extend(word.to_le_bytes()),
+                Directive::String(string) => {
+                    section.bytes.extend(string.as_bytes());
+                    section.bytes.push(0);
+                }
+                Directive::Byte(bytes) => section.bytes.extend(bytes),
+                Directive::Zero(len) => section.bytes.resize(section.bytes.len() + len, 0),
+                Directive::Global(name) => {
+                    bindings.insert(name.as_str(), STB_GLOBAL);
+                }
+                Directive::Weak(name) => {
+                    bindings.insert(name.as_str(), STB_WEAK);
+                }
+                Directive::Type(name, kind) if kind == "function" => {
+                    functions.insert(name.as_str());
+                }
+                Directive::Set(name, target) => aliases.push((name, target)),
+                Directive::Type(..)
+                | Directive::Size(..)
+                | Directive::Architecture(_)
+                | Directive::File(_)
+                | Directive::Ident(_) => (),
+            },
+            Assembly::Comment(_) => (),
+        }
+    }
+
+    // the headers come first in the code segment, and each of the other segments starts at an
+    // address that keeps its offset in the file within the page. The zeroed variables follow the
+    // others in memory, past the end of the file.
+    let (has_read_only, has_data) = (
+        !read_only.bytes.is_empty(),
+        !data.bytes.is_empty() || !bss.bytes.is_empty(),
+    );
+    let program_headers = 2 + usize::from(has_read_only) + usize::from(has_data);
+    let text_offset = align(
+        ELF_HEADER_SIZE + program_headers * PROGRAM_HEADER_SIZE,
+        text.align,
+    );
+    let text_end = text_offset + text.bytes.len();
+    let read_only_offset = align(text_end, read_only.align.max(1));
+    let text_address = BASE_ADDRESS + text_offset as u64;
+    let read_only_address = segment_address(BASE_ADDRESS + text_end as u64, read_only_offset);
+    let (data_offset, data_address) = if has_read_only {
+        let offset = align(read_only_offset + read_only.bytes.len(), data.align.max(1));
+        let read_only_end = read_only_address + read_only.bytes.len() as u64;
+        (offset, segment_address(read_only_end, offset))
+    } else {
+        let offset = align(text_end, data.align.max(1));
+        (
+            offset,
+            segment_address(BASE_ADDRESS + text_end as u64, offset),
+        )
+    };
+    let bss_address = align(
+        (data_address + data.bytes.len() as u64) as usize,
+        bss.align.max(1),
+    ) as u64;
+
+    let mut addresses: HashMap<&str, u64> = labels
+        .iter()
+        .map(|(name, defined)| {
+            let base = match defined.section {
+                SectionKind::Text => text_address,
+                SectionKind::ReadOnly => read_only_address,
+                SectionKind::Data => data_address,
+                SectionKind::Bss => bss_address,
+                SectionKind::Discarded => 0,
+            };
+            (name.as_str(), base + defined.offset as u64)
+        })
+        .collect();
+    for &(name, target) in &aliases {
+        let address = *addresses
+            .get(target.as_str())
+            .ok_or_else(|| LinkE::new(LinkError::Undefined(target.clone())))?;
+        addresses.insert(name, address);
+    }
+
+    for (offset, instruction) in instructions {
+        let target = match encode::label(instruction) {
+            Some(label) => Some(resolve(label, &addresses, &bindings)?),
+            None => None,
+        };
+        let address = text_address + offset as u64;
+        let encoded = encode::encode(instruction, address, target).ok_or_else(|| {
+            LinkE::new(LinkError::Unencodable(
+                instruction.to_string().trim().to_string(),
+            ))
+        })?;
+        text.bytes[offset..offset + 4].copy_from_slice(&encoded.to_le_bytes());
+    }
+
+    let mut symbols: Vec<_> = labels
+        .iter()
+        .filter(|(name, defined)| {
+            !name.starts_with('.') && defined.section != SectionKind::Discarded
+        })
+        .map(|(name, defined)| (name.as_str(), defined.section))
+        .chain(
+            aliases
+                .iter()
+                .filter_map(|&(name, target)| Some((name.as_str(), labels.get(target)?.section))),
+        )
+        .map(|(name, section)| Symbol {
+            name,
+            binding: bindings.get(name).copied().unwrap_or(0),
+            function: functions.contains(name) || name == "_start",
+            section,
+            address: addresses[name],
+        })
+        .collect();
+    // the local symbols go first, then the others
+    symbols.sort_by_key(|symbol| (symbol.binding != 0, symbol.address, symbol.name));
+
+    let entry = addresses["_start"];
+    Ok(write_executable(
+        (&text.bytes, text_offset, text_address),
+        (&read_only.bytes, read_only_offset, read_only_address),
+        (&data.bytes, data_offset, data_address),
+        (bss.bytes.len(), bss_address),
+        &symbols,
+        entry,
+    ))
+}
+
+/// The `_start` of a program that doesn't define one: call `main`, then exit with what it
+/// returned, which is already in the register of the first argument
+fn start_calling_main() -> Vec<Assembly> {
+    Vec::from([
+        Directive::Global("_start".into()).into(),
+        Assembly::Label("_start".into()),
+        Branch::Linked {
+            label: Label::Function("main".into()),
+        }
+        .into(),
+        Instruction::Movz {
+            target: Register::GeneralPurpose {
+                index: 8,
+                bit_size: BitSize::Bit64,
+            },
+            immediate: EXIT_GROUP,
+            shift: 0,
+        }
+        .into(),
+        Directive::Word(SVC).into(),
+    ])
+}
+
+fn section_kind(name: &str) -> Result<SectionKind, LinkE> {
+    let is = |prefix: &str| name == prefix || name.starts_with(&format!("{}.", prefix));
+    if is(".text") {
+        Ok(SectionKind::Text)
+    } else if is(".rodata") {
+        Ok(SectionKind::ReadOnly)
+    } else if is(".data") {
+        Ok(SectionKind::Data)
+    } else if is(".bss") {
+        Ok(SectionKind::Bss)
+    } else if name == ".note.GNU-stack" || name == ".note.gnu.property" {
+        Ok(SectionKind::Discarded)
+    } else {
+        Err(LinkE::new(LinkError::UnsupportedSection(name.to_string())))
+    }
+}
+
+/// The address of a label. A weak symbol that isn't defined anywhere is at address 0.
+fn resolve(
+    label: &Label,
+    addresses: &HashMap<&str, u64>,
+    bindings: &HashMap<&str, u8>,
+) -> Result<u64, LinkE> {
+    let name = label.to_string();
+    match addresses.get(name.as_str()) {
+        Some(&address) => Ok(address),
+        None if bindings.get(name.as_str()) == Some(&STB_WEAK) => Ok(0),
+        None => Err(LinkE::new(LinkError::Undefined(name))),
+    }
+}
+
+struct Symbol<'a> {
+    name: &'a str,
+    binding: u8,
+    function: bool,
+    section: SectionKind,
+    address: u64,
+}
+
+const fn align(value: usize, to: usize) -> usize {
+    value.div_ceil(to) * to
+}
+
+/// The address a segment at `offset` in the file is loaded at, from the first page boundary at or
+/// after `after`
+const fn segment_address(after: u64, offset: usize) -> u64 {
+    align(after as usize, SEGMENT_ALIGN as usize) as u64 + offset as u64 % SEGMENT_ALIGN
+}
+
+/// Little-endian fields of the headers
+trait Put {
+    fn put16(&mut self, value: u16);
+    fn put32(&mut self, value: u32);
+    fn put64(&mut self, value: u64);
+}
+
+impl Put for Vec<u8> {
+    fn put16(&mut self, value: u16) {
+        self.extend(value.to_le_bytes());
+    }
+    fn put32(&mut self, value: u32) {
+        self.extend(value.to_le_bytes());
+    }
+    fn put64(&mut self, value: u64) {
+        self.extend(value.to_le_bytes());
+    }
+}
+
+/// Write the ELF file: its header, the program headers, the code, the read-only data and the
+/// initialized variables, and then the symbols with the section headers that describe all of it
+fn write_executable(
+    (text, text_offset, text_address): (&[u8], usize, u64),
+    (read_only, read_only_offset, read_only_address): (&[u8], usize, u64),
+    (data, data_offset, data_address): (&[u8], usize, u64),
+    (bss_size, bss_address): (usize, u64),
+    symbols: &[Symbol],
+    entry: u64,
+) -> Vec<u8> {
+    let (has_read_only, has_data, has_bss) =
+        (!read_only.is_empty(), !data.is_empty(), bss_size > 0);
+    let mut section_names = Vec::from([0]);
+    let mut name = |name: &str| {
+        let offset = section_names.len() as u32;
+        section_names.extend(name.as_bytes());
+        section_names.push(0);
+        offset
+    };
+    let text_name = name(".text");
+    let read_only_name = has_read_only.then(|| name(".rodata"));
+    let data_name = has_data.then(|| name(".data"));
+    let bss_name = has_bss.then(|| name(".bss"));
+    let (symtab_name, strtab_name, shstrtab_name) =
+        (name(".symtab"), name(".strtab"), name(".shstrtab"));
+    // the sections are numbered in order, after the null one
+    let text_index = 1;
+    let mut index = text_index;
+    let mut next_index = |present: bool| {
+        index += u16::from(present);
+        index
+    };
+    let read_only_index = next_index(has_read_only);
+    let data_index = next_index(has_data);
+    let bss_index = next_index(has_bss);
+    let symtab_index = next_index(true);
+
+    let mut strings = Vec::from([0]);
+    let mut symbol_table = Vec::from([0; SYMBOL_SIZE]);
+    for symbol in symbols {
+        symbol_table.put32(strings.len() as u32);
+        strings.extend(symbol.name.as_bytes());
+        strings.push(0);
+        symbol_table.push(symbol.binding << 4 | if symbol.function { STT_FUNC } else { 0 });
+        symbol_table.push(0);
+        symbol_table.put16(match symbol.section {
+            SectionKind::ReadOnly => read_only_index,
+            SectionKind::Data => data_index,
+            SectionKind::Bss => bss_index,
+            _ => text_index,
+        });
+        symbol_table.put64(symbol.address);
+        symbol_table.put64(0);
+    }
+    let first_global = 1 + symbols
+        .iter()
+        .take_while(|symbol| symbol.binding == 0)
+        .count();
+
+    let end = if has_data {
+        data_offset + data.len()
+    } else if has_read_only {
+        read_only_offset + read_only.len()
+    } else {
+        text_offset + text.len()
+    };
+    let symtab_offset = align(end, 8);
+    let strtab_offset = symtab_offset + symbol_table.len();
+    let shstrtab_offset = strtab_offset + strings.len();
+    let section_headers_offset = align(shstrtab_offset + section_names.len(), 8);
+    let section_count = symtab_index + 3;
+
+    let mut file = Vec::new();
+    file.extend(b"\x7fELF");
+    // 64 bits, little endian, version 1 of ELF, for the System V ABI
+    file.extend([2, 1, 1, 0]);
+    file.resize(16, 0);
+    // an executable file
+    file.put16(2);
+    file.put16(EM_AARCH64);
+    file.put32(1);
+    file.put64(entry);
+    file.put64(ELF_HEADER_SIZE as u64);
+    file.put64(section_headers_offset as u64);
+    file.put32(0);
+    file.put16(ELF_HEADER_SIZE as u16);
+    file.put16(PROGRAM_HEADER_SIZE as u16);
+    file.put16(2 + u16::from(has_read_only) + u16::from(has_data || has_bss));
+    file.put16(SECTION_HEADER_SIZE as u16);
+    file.put16(section_count);
+    file.put16(section_count - 1);
+
+    // a segment may take more room in memory than in the file, which the loader fills with zeros
+    let segment = |file: &mut Vec<u8>,
+                   kind,
+                   flags,
+                   (offset, address, size): (u64, u64, u64),
+                   memory_size: u64| {
+        file.put32(kind);
+        file.put32(flags);
+        file.put64(offset);
+        file.put64(address);
+        file.put64(address);
+        file.put64(size);
+        file.put64(memory_size);
+        file.put64(if kind == PT_LOAD { SEGMENT_ALIGN } else { 16 });
+    };
+    let text_end = (text_offset + text.len()) as u64;
+    let code = (0, BASE_ADDRESS, text_end);
+    segment(&mut file, PT_LOAD, PF_R | PF_X, code, text_end);
+    if has_read_only {
+        let size = read_only.len() as u64;
+        let read_only = (read_only_offset as u64, read_only_address, size);
+        segment(&mut file, PT_LOAD, PF_R, read_only, size);
+    }
+    if has_data || has_bss {
+        let variables = (data_offset as u64, data_address, data.len() as u64);
+        let memory_size = bss_address + bss_size as u64 - data_address;
+        segment(&mut file, PT_LOAD, PF_R | PF_W, variables, memory_size);
+    }
+    segment(&mut file, PT_GNU_STACK, PF_R | PF_W, (0, 0, 0), 0);
+
+    file.resize(text_offset, 0);
+    file.extend(text);
+    if has_read_only {
+        file.resize(read_only_offset, 0);
+        file.extend(read_only);
+    }
+    if has_data {
+        file.resize(data_offset, 0);
+        file.extend(data);
+    }
+    file.resize(symtab_offset, 0);
+    file.extend(&symbol_table);
+    file.extend(&strings);
+    file.extend(&section_names);
+    file.resize(section_headers_offset, 0);
+
+    let section = |file: &mut Vec<u8>,
+                   name,
+                   kind,
+                   flags,
+                   (address, offset, size),
+                   (link, info),
+                   (align, entry_size)| {
+        file.put32(name);
+        file.put32(kind);
+        file.put64(flags);
+        file.put64(address);
+        file.put64(offset as u64);
+        file.put64(size as u64);
+        file.put32(link);
+        file.put32(info);
+        file.put64(align);
+        file.put64(entry_size);
+    };
+    file.resize(file.len() + SECTION_HEADER_SIZE, 0);
+    section(
+        &mut file,
+        text_name,
+        SHT_PROGBITS,
+        SHF_ALLOC | SHF_EXECINSTR,
+        (text_address, text_offset, text.len()),
+        (0, 0),
+        (16, 0),
+    );
+    if let Some(read_only_name) = read_only_name {
+        section(
+            &mut file,
+            read_only_name,
+            SHT_PROGBITS,
+            SHF_ALLOC,
+            (read_only_address, read_only_offset, read_only.len()),
+            (0, 0),
+            (1, 0),
+        );
+    }
+    if let Some(data_name) = data_name {
+        section(
+            &mut file,
+            data_name,
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_WRITE,
+            (data_address, data_offset, data.len()),
+            (0, 0),
+            (1, 0),
+        );
+    }
+    if let Some(bss_name) = bss_name {
+        // where it would be in the file, if it took any room there
+        let offset = data_offset + (bss_address - data_address) as usize;
+        section(
+            &mut file,
+            bss_name,
+            SHT_NOBITS,
+            SHF_ALLOC | SHF_WRITE,
+            (bss_address, offset, bss_size),
+            (0, 0),
+            (1, 0),
+        );
+    }
+    section(
+        &mut file,
+        symtab_name,
+        SHT_SYMTAB,
+        0,
+        (0, symtab_offset, symbol_table.len()),
+        (u32::from(symtab_index) + 1, first_global as u32),
+        (8, SYMBOL_SIZE as u64),
+    );
+    section(
+        &mut file,
+        strtab_name,
+        SHT_STRTAB,
+        0,
+        (0, strtab_offset, strings.len()),
+        (0, 0),
+        (1, 0),
+    );
+    section(
+        &mut file,
+        shstrtab_name,
+        SHT_STRTAB,
+        0,
+        (0, shstrtab_offset, section_names.len()),
+        (0, 0),
+        (1, 0),
+    );
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The assembly of a whole program: its functions and its global variables
+    fn program_assembly(source: &str) -> anyhow::Result<Vec<Assembly>> {
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let mut output: super::super::SectionedOutput = module
+            .functions
+            .into_iter()
+            .enumerate()
+            .map(|(index, function)| {
+                super::super::codegen_function(function, index, &[], Default::default(), &meta)
+                    .assembly
+            })
+            .collect();
+        output.merge(super::super::module_globals(
+            &module.globals,
+            Default::default(),
+        ));
+        Ok(output.into_iter().collect())
+    }
+
+    #[test]
+    fn programs_start_by_calling_main() -> anyhow::Result<()> {
+        let assembly = program_assembly("int main() {\n    return 7;\n}\n")?;
+        let executable = link_executable(&assembly).expect("`main` is all it needs");
+        assert_eq!(&executable[..4], b"\x7fELF");
+        assert_eq!(executable[18..20], EM_AARCH64.to_le_bytes());
+        let entry = u64::from_le_bytes(executable[24..32].try_into()?);
+        let entry_offset = (entry - BASE_ADDRESS) as usize;
+        // `bl main`, to the instruction right after the `_start` that ends in `svc #0`
+        let call = &executable[entry_offset..entry_offset + 4];
+        assert_eq!(u32::from_le_bytes(call.try_into()?), 0x9400_0003);
+        let call_to_puts = [
+            Assembly::Label("main".into()),
+            Branch::Linked {
+                label: Label::Function("puts".into()),
+            }
+            .into(),
+        ];
+        let error = link_executable(&call_to_puts).expect_err("`puts` isn't defined");
+        assert!(matches!(error.kind, LinkError::Undefined(name) if name == "puts"));
+        Ok(())
+    }
+
+    #[test]
+    fn global_variables_get_a_writable_segment() -> anyhow::Result<()> {
+        let source = "int table[4] = {1, 2, 3, 4};\n\
+                      static long counter;\n\
+                      int main(void) {\n\
+                      \x20   counter += table[2];\n\
+                      \x20   return counter;\n\
+                      }\n";
+        let executable =
+            link_executable(&program_assembly(source)?).expect("the program defines everything");
+        let field = |at: usize| u64::from_le_bytes(executable[at..at + 8].try_into().unwrap());
+        // the code, the variables and the stack
+        assert_eq!(executable[56..58], 3u16.to_le_bytes());
+        let variables = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+        assert_eq!(executable[variables..variables + 4], PT_LOAD.to_le_bytes());
+        assert_eq!(
+            executable[variables + 4..variables + 8],
+            (PF_R | PF_W).to_le_bytes()
+        );
+        let (offset, address) = (field(variables + 8), field(variables + 16));
+        assert_eq!(offset % SEGMENT_ALIGN, address % SEGMENT_ALIGN);
+        // `table` is in the file, while `counter` only takes room in memory, after it
+        let (file_size, memory_size) = (field(variables + 32), field(variables + 40));
+        assert_eq!((file_size, memory_size), (16, 24));
+        let offset = offset as usize;
+        assert_eq!(
+            executable[offset..offset + 16],
+            [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]
+        );
+
+        let thread_local = [
+            Directive::Section {
+                name: ".tbss".into(),
+                flags: "awT".into(),
+                kind: Some("nobits".into()),
+            }
+            .into(),
+            Assembly::Label("main".into()),
+        ];
+        let error = link_executable(&thread_local).expect_err("`.tbss` isn't laid out");
+        assert!(matches!(error.kind, LinkError::UnsupportedSection(name) if name == ".tbss"));
+        Ok(())
+    }
+}
@@ -1,14 +1,356 @@
+//! The backend: turns the IR of a function into ARM64 assembly.
+//!
+//! This is the only backend tree; anything that needs to know about the IR (binding usages,
+//! lifetimes, etc.) lives in [`crate::intermediate::analysis`] and is shared with the optimizer.
+//! The pipeline for a function is:
+//!  1. register allocation ([`crate::allocators::registers`]) based on the lifetime collisions,
+//!  2. stack allocation of the memory bindings ([`crate::allocators::memory`]),
+//!  3. frame layout ([`frame`]) and instruction selection per block, ending each one with the
+//!     moves of the values its successor's phi nodes take from it,
+//!  4. block layout: removing empty blocks, adding labels and the branches needed, and relaxing
+//!     the conditional branches that can't reach their targets ([`relax`]).
 pub mod assembly;
-pub mod has_binding;
+mod encode;
+pub mod explain;
+mod frame;
+pub mod link;
 mod output; // TODO: change output for a better builder (block based, receives IR branching maps for finishing)
+mod relax;
+pub mod target;
+#[cfg(feature = "std")]
+pub mod verify;
 use super::allocators::*;
 use super::intermediate::*;
-use output::AssemblyOutput;
+pub use output::{AssemblyOutput, SectionGroup, SectionedOutput};
 
-use std::collections::HashSet;
-use std::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
-pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
+use crate::error::{SourceMetadata, Span, WarningOptions};
+use alloc::collections::VecDeque;
+use core::str::FromStr;
+use hashbrown::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Scratch register used to break cycles when moving the arguments of a call or the values of phi
+/// nodes in place, and to compute addresses (`x16` and `x17` are intra-procedure-call registers,
+/// the allocator never hands them out).
+const SCRATCH_REGISTER: u8 = 16;
+
+/// The directives that start the assembly of a module: what it targets and where it came from.
+/// The options it was compiled with are kept as a comment.
+pub fn module_prologue(metadata: &ModuleMetadata) -> AssemblyOutput {
+    let mut output = AssemblyOutput::new();
+    if let Some(file) = &metadata.source_file {
+        output.push_back(assembly::Directive::File(file.clone()));
+    }
+    output.push_back(assembly::Directive::Architecture(metadata.target.clone()));
+    if !metadata.options.is_empty() {
+        output.push_back(assembly::Assembly::Comment(format!(
+            "options: {}",
+            metadata.options.join(" ")
+        )));
+    }
+    output
+}
+
+/// `GNU_PROPERTY_AARCH64_FEATURE_1_AND`: the features of the object, which the linked program
+/// only has if all of its objects do
+const GNU_PROPERTY_AARCH64_FEATURE_1_AND: u32 = 0xc000_0000;
+/// `NT_GNU_PROPERTY_TYPE_0`: the note holds an array of properties
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+/// The directives that end the assembly of a module, recording which compiler produced it, that
+/// the stack needn't be executable and which branch protections the code has
+pub fn module_epilogue(branch_protection: BranchProtection) -> AssemblyOutput {
+    let mut output: AssemblyOutput =
+        assembly::Directive::Ident(format!("tracc {}", env!("CARGO_PKG_VERSION"))).into();
+    output.push_back(assembly::Directive::Section {
+        name: ".note.GNU-stack".into(),
+        flags: String::new(),
+        kind: Some("progbits".into()),
+    });
+    let features = branch_protection.feature_bits();
+    if features != 0 {
+        output.push_back(assembly::Directive::Section {
+            name: ".note.gnu.property".into(),
+            flags: "a".into(),
+            kind: None,
+        });
+        output.extend([
+            assembly::Directive::P2Align(3),
+            // size of the name, of the description and the note's type
+            assembly::Directive::Word(4),
+            assembly::Directive::Word(16),
+            assembly::Directive::Word(NT_GNU_PROPERTY_TYPE_0),
+            assembly::Directive::String("GNU".into()),
+            // the property: its type, size and value, padded to 8 bytes
+            assembly::Directive::Word(GNU_PROPERTY_AARCH64_FEATURE_1_AND),
+            assembly::Directive::Word(4),
+            assembly::Directive::Word(features),
+            assembly::Directive::Word(0),
+        ]);
+    }
+    output
+}
+
+/// The protections against branching to arbitrary code that the generated code opts into, as
+/// given to `-mbranch-protection=`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchProtection {
+    /// functions start with a landing pad, the only place that is branched to indirectly
+    pub bti: bool,
+    /// return addresses are signed before being saved, and authenticated before returning
+    pub pac_ret: bool,
+}
+
+impl BranchProtection {
+    /// The bits of `GNU_PROPERTY_AARCH64_FEATURE_1_AND` that the code sets
+    const fn feature_bits(self) -> u32 {
+        self.bti as u32 | (self.pac_ret as u32) << 1
+    }
+}
+
+impl FromStr for BranchProtection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => return Ok(Self::default()),
+            "standard" => {
+                return Ok(Self {
+                    bti: true,
+                    pac_ret: true,
+                })
+            }
+            _ => (),
+        }
+        let mut protection = Self::default();
+        for part in s.split('+') {
+            match part {
+                "bti" => protection.bti = true,
+                "pac-ret" => protection.pac_ret = true,
+                // every function saves its return address in the frame record, so leaf functions
+                // are always signed
+                "leaf" if protection.pac_ret => (),
+                _ => {
+                    return Err(format!(
+                        "invalid argument `{}` to `-mbranch-protection=`",
+                        s
+                    ))
+                }
+            }
+        }
+        Ok(protection)
+    }
+}
+
+/// The directive that makes a symbol visible to the linker with the given linkage, if any
+fn linkage_directive(name: &str, linkage: crate::ast::Linkage) -> Option<assembly::Directive> {
+    match linkage {
+        crate::ast::Linkage::External => Some(assembly::Directive::Global(name.to_string())),
+        crate::ast::Linkage::Weak => Some(assembly::Directive::Weak(name.to_string())),
+        crate::ast::Linkage::Internal => None,
+    }
+}
+
+/// The symbols of a module that aren't functions of their own: aliases, which are defined as the
+/// address of their target, and the weak references to functions defined elsewhere, if at all
+pub fn module_symbols(aliases: &[generate::Alias], weak_references: &[&str]) -> AssemblyOutput {
+    let mut output = AssemblyOutput::new();
+    for name in weak_references {
+        output.push_back(assembly::Directive::Weak(name.to_string()));
+    }
+    for &generate::Alias {
+        name,
+        target,
+        linkage,
+    } in aliases
+    {
+        output.extend(linkage_directive(name, linkage));
+        output.extend([
+            assembly::Directive::Type(name.to_string(), "function".into()),
+            assembly::Directive::Set(name.to_string(), target.to_string()),
+        ]);
+    }
+    output
+}
+
+/// The global variables of a module, those starting zeroed in `.bss`, where only their size is
+/// reserved, and the rest in `.data` along with their bytes
+pub fn module_globals(
+    globals: &[generate::GlobalData],
+    sections: SectionPlacement,
+) -> SectionedOutput {
+    let mut output = SectionedOutput::default();
+    for (index, global) in globals.iter().enumerate() {
+        let zeroed = global.is_zeroed();
+        let size = global.bytes.len();
+        let mut data: AssemblyOutput = sections.data(global.name, zeroed).into();
+        data.extend(linkage_directive(global.name, global.linkage));
+        data.extend::<assembly::Assembly>([
+            assembly::Directive::P2Align(global.align.trailing_zeros() as u8).into(),
+            assembly::Directive::Type(global.name.to_string(), "object".into()).into(),
+            assembly::Directive::Size(global.name.to_string(), size).into(),
+            assembly::Assembly::Label(global.name.to_string()),
+        ]);
+        if zeroed {
+            data.push_back(assembly::Directive::Zero(size));
+            output.add(SectionGroup::Bss, index, data);
+        } else {
+            data.push_back(assembly::Directive::Byte(global.bytes.clone()));
+            output.add(SectionGroup::Data, index, data);
+        }
+    }
+    output
+}
+
+/// Whether each function gets sections of its own for its code and data, as asked with
+/// `-ffunction-sections` and `-fdata-sections`, so that the linker can drop the unused ones with
+/// `--gc-sections`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SectionPlacement {
+    pub function_sections: bool,
+    pub data_sections: bool,
+}
+
+impl SectionPlacement {
+    /// The section the code of `function` goes in
+    fn text(self, function: &str) -> assembly::Directive {
+        if self.function_sections {
+            assembly::Directive::Section {
+                name: format!(".text.{}", function),
+                flags: "ax".into(),
+                kind: Some("progbits".into()),
+            }
+        } else {
+            assembly::Directive::Text
+        }
+    }
+
+    /// The section the read-only data of `function` goes in
+    fn read_only(self, function: &str) -> assembly::Directive {
+        let name = if self.data_sections {
+            format!(".rodata.{}", function)
+        } else {
+            ".rodata".into()
+        };
+        assembly::Directive::Section {
+            name,
+            flags: "a".into(),
+            kind: Some("progbits".into()),
+        }
+    }
+
+    /// The section the global variable `name` goes in, which only takes up space in the file if
+    /// it doesn't start zeroed
+    fn data(self, name: &str, zeroed: bool) -> assembly::Directive {
+        let (prefix, kind) = if zeroed {
+            (".bss", "nobits")
+        } else {
+            (".data", "progbits")
+        };
+        let name = if self.data_sections {
+            format!("{}.{}", prefix, name)
+        } else {
+            prefix.into()
+        };
+        assembly::Directive::Section {
+            name,
+            flags: "aw".into(),
+            kind: Some(kind.into()),
+        }
+    }
+}
+
+/// How the code of the functions is generated
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodegenOptions {
+    pub branch_protection: BranchProtection,
+    pub sections: SectionPlacement,
+    /// the warnings to look for, which the caller may still filter by location
+    pub warnings: WarningOptions,
+    /// keep what each statement compiled to, in [`CompiledFunction::explained`]
+    pub explain: bool,
+    /// start the function with a `brk #0` for a debugger to stop at when it's called, which
+    /// `-fdebug-trap-main` and `-fdebug-trap=<function>` ask for
+    pub trap_on_entry: bool,
+    /// put comments above the function that sum up its frame, the registers it saves, its spills
+    /// and the optimizations that changed it, which `-fverbose-asm` asks for
+    pub verbose_asm: bool,
+}
+
+/// What code generation produced for a function
+pub struct CompiledFunction {
+    /// the code of the function and its read-only data, each in the section it goes in
+    pub assembly: SectionedOutput,
+    /// bytes of stack the function uses, once everything has been allocated
+    pub frame_size: usize,
+    /// what code generation found worth telling about the source
+    pub warnings: Vec<CodegenW>,
+    /// the instructions each statement compiled to, in order, along with the binding of the
+    /// statement. Only kept when explaining, and the code around them (the frame, branches) isn't
+    /// counted.
+    pub explained: Vec<(Binding, Vec<assembly::Assembly>)>,
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum CodegenWarning {
+    #[error("the stack frame of {function:?} is {size} bytes, larger than {limit} bytes")]
+    FrameLargerThan {
+        function: String,
+        size: usize,
+        limit: usize,
+    },
+    #[error("spill-heavy statement: its values take {registers} callee-saved registers, which have to be saved on the stack")]
+    SpillHeavy { registers: usize },
+}
+
+impl CodegenWarning {
+    /// The name of the `-W` flag that controls this warning
+    pub const fn flag(&self) -> &'static str {
+        match self {
+            Self::FrameLargerThan { .. } => "frame-larger-than",
+            Self::SpillHeavy { .. } => "spill-heavy",
+        }
+    }
+}
+
+pub type CodegenW = crate::error::Warning<CodegenWarning>;
+
+/// Generate the code of a function. The index must be unique per function, since it is used to
+/// name its blocks. The reserved registers hold global register variables, so they are never
+/// allocated.
+pub fn codegen_function(
+    function: generate::FunctionIR,
+    function_index: usize,
+    reserved_registers: &[u8],
+    options: CodegenOptions,
+    source_meta: &SourceMetadata,
+) -> CompiledFunction {
+    let CodegenOptions {
+        branch_protection,
+        sections,
+        warnings: warning_options,
+        explain,
+        trap_on_entry,
+        verbose_asm,
+    } = options;
+    let generate::FunctionIR {
+        name: function_name,
+        span,
+        linkage,
+        inline: _,
+        mut ir,
+        read_only,
+        merged_read_only,
+        spans,
+        variables: _,
+        remarks: _,
+        optimizations,
+    } = function;
+    let mut warnings = Vec::new();
+    split_phi_edges(&mut ir);
     let collisions = crate::intermediate::analysis::compute_lifetime_collisions(&ir);
     // TODO: integrate register spill output
     let registers::CodegenHints {
@@ -21,6 +363,7 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
         &collisions,
         analysis::order_by_deps(&ir, collisions.keys().cloned()),
         registers::make_allocator_hints(&ir),
+        reserved_registers,
     );
 
     let alloc_map = memory::make_alloc_map(&ir.code);
@@ -42,61 +385,103 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
     });
 
     debug_assert!(completely_spilled.is_empty(), "shouldn't have any spills");
+    let spills = completely_spilled.len();
 
-    let (memory, mem_size) = memory::figure_out_allocations(&ir, alloc_map, &collisions);
+    if warning_options.spill_heavy {
+        warnings.extend(spill_heavy_statements(&registers, &spans).into_iter().map(
+            |(span, registers)| {
+                CodegenW::new(CodegenWarning::SpillHeavy { registers })
+                    .with_source(span, source_meta)
+            },
+        ));
+    }
 
-    debug_assert!(save_upon_call.is_empty(), "TODO: implement save upon call");
+    let (memory, mem_size, mem_align) = memory::figure_out_allocations(&ir, alloc_map, &collisions);
+    let (call_saves, mem_size, mem_align) =
+        call_saves(&ir, &save_upon_call, &registers, (mem_size, mem_align));
 
-    debug_assert!(
-        need_move_to_return_reg.is_empty(),
-        "TODO: implement moves to return register (or generic move to register)"
-    );
+    let frame = frame::FrameLayout::new(mem_size, mem_align, &registers, branch_protection);
 
-    // align the stack to 16 bytes
-    let mem_size = 16 * ((mem_size as f64 / 16.0f64).ceil() as usize);
+    // all returns branch to the epilogue block, which restores the frame.
 
-    // if mem size is not zero, create an epilogue  block with a label and
-    // move all returns to the epilogue block.
+    // the structs returned in registers are loaded into them right before branching there
+    let returned_structs: HashMap<Binding, (Binding, usize)> = ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                index,
+                value: Value::ReturnedStruct { memory, size },
+            } => Some((*index, (*memory, *size))),
+            _ => None,
+        })
+        .collect();
 
-    // collect all the blocks and their ends
+    // the data merged with another function's is referred to by the label it has there
+    let read_only_labels: Vec<assembly::Label> = (0..read_only.len())
+        .map(|num| {
+            let (function, num) = merged_read_only
+                .get(&num)
+                .copied()
+                .unwrap_or((function_index, num));
+            assembly::Label::ReadOnly { function, num }
+        })
+        .collect();
+
+    // collect all the blocks and their ends, along with what their flags hold at the end. The
+    // values of the phi nodes are moved in place at the end of the blocks they come from.
+    let mut phi_moves = phi_moves(&ir, &registers);
+    let mut flags = Vec::with_capacity(ir.code.len());
+    let mut explained = Vec::new();
     let (mut blocks, mut ends): (Vec<_>, Vec<_>) = ir
         .code
         .into_iter()
-        .map(|BasicBlock { statements, end }| {
-            let block = compile_block(statements, &memory, &registers);
+        .enumerate()
+        .map(|(index, BasicBlock { statements, end })| {
+            let (mut block, block_flags) = compile_block(
+                statements,
+                function_index,
+                &read_only_labels,
+                &memory,
+                &registers,
+                &call_saves,
+                explain.then_some(&mut explained),
+            );
+            if let Some(moves) = phi_moves.remove(&BlockBinding(index)) {
+                block.extend(parallel_move(moves));
+            }
+            flags.push(block_flags);
             (block, end)
         })
         .unzip();
 
-    // change all blocks to branch to epilogue
-    let prologue = if mem_size != 0 {
-        ends.iter_mut().for_each(|end| {
-            use assembly::Label::Epilogue;
-
-            if let BlockEnd::Return(_) = end {
-                *end = BlockEnd::Branch(Branch::Unconditional {
-                    // block ID of epilogue is the new block.
-                    target: BlockBinding(blocks.len()),
-                });
+    // change all blocks to branch to epilogue, with the returned value in `w0`, or `v0` if it's a
+    // floating-point one
+    // block ID of epilogue is the new block.
+    let epilogue = BlockBinding(blocks.len());
+    blocks
+        .iter_mut()
+        .zip(ends.iter_mut())
+        .for_each(|(block, end)| {
+            if let BlockEnd::Return(returned) = end {
+                if let Some(&(returned_memory, size)) = returned_structs.get(returned) {
+                    let returned_memory = address_of(returned_memory, &memory, &registers);
+                    block.extend(
+                        struct_words(returned_memory, size).map(|(register, address)| {
+                            assembly::Instruction::Ldr { register, address }
+                        }),
+                    );
+                } else if need_move_to_return_reg.contains(returned) {
+                    let register = registers[returned];
+                    block.extend(move_register(return_register(register), register));
+                }
+                *end = BlockEnd::Branch(Branch::Unconditional { target: epilogue });
             }
         });
-
-        let epilogue = AssemblyOutput::from(assembly::Instruction::Add {
-            target: assembly::Register::StackPointer,
-            lhs: assembly::Register::StackPointer,
-            rhs: assembly::Data::Immediate(mem_size as i32),
-        })
-        .chain_one(assembly::Instruction::Ret);
-
-        blocks.push(epilogue);
-        AssemblyOutput::from(assembly::Instruction::Sub {
-            target: assembly::Register::StackPointer,
-            lhs: assembly::Register::StackPointer,
-            rhs: assembly::Data::Immediate(mem_size as i32),
-        })
-    } else {
-        AssemblyOutput::new()
-    };
+    blocks.push(frame.epilogue());
+    flags.push(None);
+    let prologue = frame.prologue(trap_on_entry);
 
     // TODO: for each end, reverse the condition if true_branch == current_block + 1
     // also reorder block names so that each block is nearest to the ones that branch to it.
@@ -104,49 +489,55 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
     // but before that, add the epilogue block and change the return ends to branch to the
     // epilogue.
 
-    // search backwards for empty blocks so we can delete them and make everything else branch to
-    // the next block of the empty one
+    // search backwards for empty blocks that just jump somewhere else, so we can delete them and
+    // make everything that branched to them branch to their target instead
     {
         let mut i = 0;
         while i != blocks.len() {
             let index = blocks.len() - i - 1;
-            if blocks[index].is_empty() {
-                // rewire all ends to one less
+            let jumps_to = if blocks[index].is_empty() {
+                match ends[index] {
+                    // an empty block looping onto itself is an infinite loop, so it has to stay
+                    BlockEnd::Branch(Branch::Unconditional { target }) if target.0 != index => {
+                        Some(target)
+                    }
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            if let Some(target) = jumps_to {
                 ends.iter_mut().for_each(|end| {
-                    for move_index in (index + 1..blocks.len()).rev() {
-                        // UNSAFE: safe. The block is about to be deleted.
-                        unsafe {
+                    // UNSAFE: safe. The block is about to be deleted, and the ones after it are
+                    // moved one place down.
+                    unsafe {
+                        refactor::end_rename_block(end, BlockBinding(index), target);
+                        for move_index in index + 1..blocks.len() {
                             refactor::end_rename_block(
                                 end,
-                                BlockBinding(move_index + 1),
                                 BlockBinding(move_index),
-                            )
-                        };
+                                BlockBinding(move_index - 1),
+                            );
+                        }
                     }
-                    // if a conditional branch ends with both blocks being the same, it means it
-                    // was just executing conditionally a block that ended up not having any source
-                    // code. TODO: this probably happens due to phi nodes and their targets already
-                    // being there. When phi nodes can't be allocated the same target then these
-                    // blocks won't be empty.
+                    // both targets of a conditional branch may have been empty blocks going to
+                    // the same place
                     if let BlockEnd::Branch(Branch::Conditional {
                         target_true,
                         target_false,
                         ..
-                    }) = end
+                    }) = *end
                     {
-                        debug_assert_ne!(
-                            target_true, target_false,
-                            "conditional branch ended up folded"
-                        );
-                        // if target_true == target_false {
-                        //     *end = BlockEnd::Branch(Branch::Unconditional {
-                        //         target: *target_true,
-                        //     })
-                        // }
+                        if target_true == target_false {
+                            *end = BlockEnd::Branch(Branch::Unconditional {
+                                target: target_true,
+                            });
+                        }
                     }
                 });
                 blocks.remove(index);
                 ends.remove(index);
+                flags.remove(index);
             } else {
                 i += 1;
             }
@@ -156,10 +547,15 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
     let blocks_len = blocks.len();
 
     let get_label = |index: usize| -> assembly::Label {
-        if index == blocks_len.saturating_sub(1) && mem_size != 0 {
-            assembly::Label::Epilogue
+        if index == blocks_len - 1 {
+            assembly::Label::Epilogue {
+                function: function_index,
+            }
         } else {
-            assembly::Label::Block { num: index }
+            assembly::Label::Block {
+                function: function_index,
+                num: index,
+            }
         }
     };
 
@@ -167,8 +563,8 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
     blocks
         .iter_mut()
         .enumerate()
-        .zip(ends)
-        .for_each(|((index, block), end)| {
+        .zip(ends.into_iter().zip(flags))
+        .for_each(|((index, block), (end, flags))| {
             match end {
                 BlockEnd::Return(_) => {
                     block.push_back(assembly::Instruction::Ret);
@@ -184,38 +580,41 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
                         ));
                     }
                 }
-                // TODO: flag condition hints to codegen
                 BlockEnd::Branch(Branch::Conditional {
                     flag,
                     target_true,
                     target_false,
                 }) => {
+                    // if the flags still hold the comparison that made the flag, branch on it
+                    // directly instead of comparing the flag with zero
+                    let holds = match flags {
+                        Some((binding, condition)) if binding == flag => condition,
+                        _ => {
+                            block.push_back(assembly::Instruction::Cmp {
+                                register: assembly::Register::from_id(
+                                    registers[&flag],
+                                    assembly::BitSize::Bit32,
+                                ),
+                                data: assembly::Data::Register(assembly::Register::ZeroRegister {
+                                    bit_size: assembly::BitSize::Bit32,
+                                }),
+                            });
+                            assembly::Condition::NotEquals
+                        }
+                    };
                     let (condition, condition_target, rest_target) = if target_true.0 == index + 1 {
-                        (assembly::Condition::Equals, target_false.0, target_true.0)
+                        (holds.opposite(), target_false.0, target_true.0)
                     } else {
                         needed_labels.insert(target_true.0);
-                        (
-                            assembly::Condition::NotEquals,
-                            target_true.0,
-                            target_false.0,
-                        )
+                        (holds, target_true.0, target_false.0)
                     };
                     needed_labels.insert(condition_target);
-                    block.extend(vec![
-                        assembly::Instruction::Cmp {
-                            register: assembly::Register::from_id(
-                                registers[&flag],
-                                assembly::BitSize::Bit32,
-                            ),
-                            data: assembly::Data::Register(assembly::Register::ZeroRegister {
-                                bit_size: assembly::BitSize::Bit32,
-                            }),
-                        },
-                        assembly::Instruction::Branch(assembly::Branch::Conditional {
+                    block.push_back(assembly::Instruction::Branch(
+                        assembly::Branch::Conditional {
                             condition,
                             label: get_label(condition_target),
-                        }),
-                    ]);
+                        },
+                    ));
 
                     if rest_target != index + 1 {
                         needed_labels.insert(rest_target);
@@ -232,71 +631,712 @@ pub fn codegen_function(function_name: String, mut ir: IR) -> AssemblyOutput {
         blocks[block].push_front(get_label(block));
     }
 
-    blocks
-        .into_iter()
-        .fold(prologue, |acc, next| acc.chain(next))
-        // declare function as global for linkage
-        .cons(assembly::Assembly::Label(function_name.clone()))
-        .cons(assembly::Directive::Type(
-            function_name.clone(),
-            "function".into(),
-        ))
-        .cons(assembly::Directive::Global(function_name))
+    let output = relax::relax_branches(
+        blocks
+            .into_iter()
+            .fold(prologue, |acc, next| acc.chain(next)),
+        function_index,
+        relax::CONDITIONAL_BRANCH_RANGE,
+    )
+    .cons(assembly::Assembly::Label(function_name.to_string()))
+    .cons(assembly::Directive::Type(
+        function_name.to_string(),
+        "function".into(),
+    ));
+    let mut output = match linkage_directive(function_name, linkage) {
+        Some(directive) => output.cons(directive),
+        None => output,
+    };
+    if verbose_asm {
+        for comment in verbose_header(function_name, &frame, spills, &optimizations)
+            .into_iter()
+            .rev()
+        {
+            output.push_front(assembly::Assembly::Comment(comment));
+        }
+    }
+    let mut assembly = SectionedOutput::default();
+    assembly.add(
+        SectionGroup::Text,
+        function_index,
+        output.cons(sections.text(function_name)),
+    );
+    if read_only.len() > merged_read_only.len() {
+        let mut data: AssemblyOutput = sections.read_only(function_name).into();
+        for (num, generate::ReadOnlyData { bytes, align }) in read_only
+            .into_iter()
+            .enumerate()
+            .filter(|(num, _)| !merged_read_only.contains_key(num))
+        {
+            data.extend::<assembly::Assembly>([
+                assembly::Directive::P2Align(align.trailing_zeros() as u8).into(),
+                assembly::Label::ReadOnly {
+                    function: function_index,
+                    num,
+                }
+                .into(),
+                assembly::Directive::Byte(bytes).into(),
+            ]);
+        }
+        assembly.add(SectionGroup::ReadOnly, function_index, data);
+    }
+    let frame_size = frame.total_size();
+    if let Some(limit) = warning_options.frame_larger_than {
+        if frame_size > limit {
+            warnings.push(
+                CodegenW::new(CodegenWarning::FrameLargerThan {
+                    function: function_name.to_string(),
+                    size: frame_size,
+                    limit,
+                })
+                .with_source(span, source_meta),
+            );
+        }
+    }
+    CompiledFunction {
+        assembly,
+        frame_size,
+        warnings,
+        explained,
+    }
+}
+
+/// The comments `-fverbose-asm` puts above a function: the size of its frame, the callee-saved
+/// registers it saves, how many values didn't get a register, and the optimizations that changed
+/// it, in the order they first did
+fn verbose_header(
+    function_name: &str,
+    frame: &frame::FrameLayout,
+    spills: usize,
+    optimizations: &[&str],
+) -> [String; 4] {
+    let or_none = |list: Vec<String>| {
+        if list.is_empty() {
+            "none".to_string()
+        } else {
+            list.join(", ")
+        }
+    };
+    let saved = frame.saved_registers().map(|register| register.to_string());
+    let optimizations = optimizations.iter().map(|pass| pass.to_string());
+    [
+        format!(
+            "{}: frame of {} bytes, {} of them for locals",
+            function_name,
+            frame.total_size(),
+            frame.locals_size()
+        ),
+        format!("saved registers: {}", or_none(saved.collect())),
+        format!("spilled values: {}", spills),
+        format!("optimizations: {}", or_none(optimizations.collect())),
+    ]
 }
 
+/// How many callee-saved registers the values of a statement have to take for it to be reported
+/// as spill-heavy
+const SPILL_HEAVY_REGISTERS: usize = 4;
+
+/// The statements whose values take many callee-saved registers, which the prologue has to save
+/// on the stack, along with how many they take. They are found by the spans the bindings were
+/// made for, so bindings made by passes aren't counted.
+fn spill_heavy_statements(
+    registers: &registers::RegisterMap,
+    spans: &HashMap<Binding, Span>,
+) -> Vec<(Span, usize)> {
+    let mut taken: HashMap<usize, (Span, HashSet<assembly::RegisterID>)> = HashMap::new();
+    for (binding, register) in registers {
+        let callee_saved = match register {
+            assembly::RegisterID::GeneralPurpose { index } => registers::is_callee_saved(*index),
+            assembly::RegisterID::Float { index } => registers::is_float_callee_saved(*index),
+            _ => false,
+        };
+        if let (true, Some(span)) = (callee_saved, spans.get(binding)) {
+            taken
+                .entry(span.offset)
+                .or_insert_with(|| (*span, HashSet::new()))
+                .1
+                .insert(*register);
+        }
+    }
+    let mut statements: Vec<_> = taken
+        .into_values()
+        .map(|(span, registers)| (span, registers.len()))
+        .filter(|(_, registers)| *registers >= SPILL_HEAVY_REGISTERS)
+        .collect();
+    statements.sort_by_key(|(span, _)| span.offset);
+    statements
+}
+
+/// The comparison the NZCV flags hold: the binding it was set into, and the condition that holds
+/// when that binding is true
+type Flags = Option<(Binding, assembly::Condition)>;
+
+/// For each call, the caller-saved registers holding values that live through it, along with the
+/// frame slot each is kept in while the callee runs
+type CallSaves = HashMap<Binding, Vec<(assembly::RegisterID, assembly::Memory)>>;
+
+/// Give the values that live through a call in a caller-saved register a frame slot each, after
+/// the locals of `(size, align)`, and find the calls they have to be saved around. Also returns
+/// the size and alignment of the locals with the slots.
+fn call_saves(
+    ir: &IR,
+    saved: &HashSet<Binding>,
+    registers: &registers::RegisterMap,
+    (size, align): (usize, usize),
+) -> (CallSaves, usize, usize) {
+    if saved.is_empty() {
+        return (CallSaves::new(), size, align);
+    }
+    // a whole register is saved, which is enough for the `d` registers too
+    let slot_size = target::current().data_layout.register_size;
+    let mut saved: Vec<_> = saved.iter().copied().collect();
+    saved.sort();
+    let start = memory::align(size, slot_size);
+    let slots: HashMap<_, _> = saved
+        .iter()
+        .enumerate()
+        .map(|(num, binding)| {
+            let slot = assembly::Memory {
+                register: assembly::Register::StackPointer,
+                offset: assembly::Offset::Determined(start + num * slot_size),
+            };
+            (*binding, slot)
+        })
+        .collect();
+    let lifetimes = analysis::lifetimes::compute_lifetimes(ir);
+    let mut saves = CallSaves::new();
+    for (block, BasicBlock { statements, .. }) in ir.code.iter().enumerate() {
+        for (statement_index, statement) in statements.iter().enumerate() {
+            let Statement::Assign {
+                index,
+                value:
+                    Value::Call { .. }
+                    | Value::CallPointer { .. }
+                    | Value::CallStruct { .. }
+                    | Value::CallFloat { .. },
+            } = statement
+            else {
+                continue;
+            };
+            let call = analysis::lifetimes::BlockAddress {
+                block: BlockBinding(block),
+                statement: statement_index,
+            };
+            let live: Vec<_> = saved
+                .iter()
+                .filter(|binding| lifetimes[*binding].is_live_at(call, ir))
+                .map(|binding| (registers[binding], slots[binding]))
+                .collect();
+            if !live.is_empty() {
+                saves.insert(*index, live);
+            }
+        }
+    }
+    (saves, start + saved.len() * slot_size, align.max(slot_size))
+}
+
+/// Compile the statements of a block in order. The constants loaded into registers are
+/// remembered until something else writes to them, so loading one again is skipped. Also returns
+/// what the flags hold at the end of the block. When explaining, the instructions of each
+/// statement are pushed to `explained`.
 fn compile_block(
     block: Vec<Statement>,
+    function_index: usize,
+    read_only_labels: &[assembly::Label],
     memory: &memory::MemoryMap,
     registers: &registers::RegisterMap,
-) -> AssemblyOutput {
-    block
+    call_saves: &CallSaves,
+    mut explained: Option<&mut Vec<(Binding, Vec<assembly::Assembly>)>>,
+) -> (AssemblyOutput, Flags) {
+    let mut constants = RegisterConstants::default();
+    let mut flags = None;
+    let output = block
         .into_iter()
         .fold(AssemblyOutput::new(), |output, statement| {
-            output.chain(match statement {
+            let binding = statement.binding();
+            let compiled = match statement {
                 Statement::Assign { index, value } => {
                     let register = registers[&index];
-                    compile_value(value, register, memory, registers)
+                    match value {
+                        Value::Cmp { condition, .. } => flags = Some((index, condition)),
+                        Value::FloatCmp {
+                            condition,
+                            lhs,
+                            rhs,
+                            ..
+                        } => flags = Some((index, float_condition(condition, lhs, rhs).0)),
+                        // the callee may leave anything in them
+                        Value::Call { .. }
+                        | Value::CallPointer { .. }
+                        | Value::CallStruct { .. }
+                        | Value::CallFloat { .. } => flags = None,
+                        _ => (),
+                    }
+                    match (constants.load(register, &value), value) {
+                        (Some(output), _) => output,
+                        (
+                            None,
+                            Value::AtomicFetchAdd {
+                                mem_binding,
+                                binding,
+                                byte_size,
+                            },
+                        ) => atomic_fetch_add(
+                            register,
+                            (mem_binding, binding, byte_size),
+                            registers,
+                            assembly::Label::Atomic {
+                                function: function_index,
+                                num: index.0,
+                            },
+                        ),
+                        (
+                            None,
+                            Value::Select {
+                                flag,
+                                if_true,
+                                if_false,
+                            },
+                        ) => select(register, (flag, if_true, if_false), &mut flags, registers),
+                        (None, value) => {
+                            compile_value(value, register, read_only_labels, memory, registers)
+                        }
+                    }
+                }
+                Statement::SetRegister { register, binding } => {
+                    let register = assembly::RegisterID::GeneralPurpose { index: register };
+                    constants.forget(register);
+                    move_register(register, registers[&binding])
                 }
                 Statement::Store {
                     mem_binding,
                     binding,
                     byte_size,
+                    ..
                 } => match byte_size {
-                    ByteSize::U64 => todo!("64-bit stores"),
-                    ByteSize::U8 => todo!("one byte stores"),
-                    ByteSize::U32 => assembly::Instruction::Str {
+                    ByteSize::U64 => assembly::Instruction::Str {
+                        register: assembly::Register::from_id(
+                            registers[&binding],
+                            assembly::BitSize::Bit64,
+                        ),
+                        address: address_of(mem_binding, memory, registers),
+                    }
+                    .into(),
+                    ByteSize::U8 | ByteSize::I8 => assembly::Instruction::Strb {
+                        register: assembly::Register::from_id(
+                            registers[&binding],
+                            assembly::BitSize::Bit32,
+                        ),
+                        address: address_of(mem_binding, memory, registers),
+                    }
+                    .into(),
+                    ByteSize::I16 | ByteSize::U16 => assembly::Instruction::Strh {
                         register: assembly::Register::from_id(
                             registers[&binding],
                             assembly::BitSize::Bit32,
                         ),
-                        address: memory[&mem_binding],
+                        address: address_of(mem_binding, memory, registers),
+                    }
+                    .into(),
+                    ByteSize::U32 | ByteSize::F32 => assembly::Instruction::Str {
+                        register: assembly::Register::from_id(
+                            registers[&binding],
+                            assembly::BitSize::Bit32,
+                        ),
+                        address: address_of(mem_binding, memory, registers),
+                    }
+                    .into(),
+                    ByteSize::F64 => assembly::Instruction::Str {
+                        register: assembly::Register::from_id(
+                            registers[&binding],
+                            assembly::BitSize::Bit64,
+                        ),
+                        address: address_of(mem_binding, memory, registers),
                     }
                     .into(),
                 },
-            })
-        })
+                Statement::AtomicStore {
+                    mem_binding,
+                    binding,
+                    byte_size,
+                } => assembly::Instruction::Stlr {
+                    register: assembly::Register::from_id(
+                        registers[&binding],
+                        atomic_bit_size(byte_size),
+                    ),
+                    base: assembly::Register::from_id(
+                        registers[&mem_binding],
+                        assembly::BitSize::Bit64,
+                    ),
+                }
+                .into(),
+            };
+            // the callee may clobber the caller-saved registers, so what lives through the call
+            // in them is kept in the frame meanwhile
+            let compiled = match call_saves.get(&binding) {
+                Some(saves) => {
+                    let register = |id| assembly::Register::from_id(id, assembly::BitSize::Bit64);
+                    let stores = saves
+                        .iter()
+                        .map(|&(id, address)| assembly::Instruction::Str {
+                            register: register(id),
+                            address,
+                        });
+                    let loads = saves
+                        .iter()
+                        .map(|&(id, address)| assembly::Instruction::Ldr {
+                            register: register(id),
+                            address,
+                        });
+                    AssemblyOutput::new()
+                        .chain(stores)
+                        .chain(compiled)
+                        .chain(loads)
+                }
+                None => compiled,
+            };
+            match explained.as_deref_mut() {
+                Some(explained) => {
+                    let compiled: Vec<_> = compiled.into_iter().collect();
+                    explained.push((binding, compiled.clone()));
+                    output.chain(compiled)
+                }
+                None => output.chain(compiled),
+            }
+        });
+    (output, flags)
+}
+
+/// The size of the registers an atomic access of `byte_size` uses, which is either 4 or 8 bytes
+fn atomic_bit_size(byte_size: ByteSize) -> assembly::BitSize {
+    match byte_size {
+        ByteSize::U64 => assembly::BitSize::Bit64,
+        _ => assembly::BitSize::Bit32,
+    }
+}
+
+/// Add to memory at once, leaving what it held in the target. With LSE that's one instruction.
+/// Without it, the sum is stored exclusively until nothing else has stored to the memory in
+/// between, starting again from `loop_label` when something has, and what the memory held is
+/// the sum less the addend.
+fn atomic_fetch_add(
+    target_register: assembly::RegisterID,
+    (mem_binding, binding, byte_size): (Binding, Binding, ByteSize),
+    registers: &registers::RegisterMap,
+    loop_label: assembly::Label,
+) -> AssemblyOutput {
+    let bit_size = atomic_bit_size(byte_size);
+    let base = assembly::Register::from_id(registers[&mem_binding], assembly::BitSize::Bit64);
+    let addend = assembly::Register::from_id(registers[&binding], bit_size);
+    let target = assembly::Register::from_id(target_register, bit_size);
+    if target::cpu().features.lse {
+        return assembly::Instruction::Ldaddal {
+            addend,
+            target,
+            base,
+        }
+        .into();
+    }
+    let scratch = |index| assembly::Register::GeneralPurpose { index, bit_size };
+    let (held, sum) = (scratch(SCRATCH_REGISTER), scratch(SCRATCH_REGISTER + 1));
+    let status = assembly::Register::GeneralPurpose {
+        index: SCRATCH_REGISTER,
+        bit_size: assembly::BitSize::Bit32,
+    };
+    [
+        assembly::Assembly::from(loop_label.clone()),
+        assembly::Instruction::Ldaxr {
+            register: held,
+            base,
+        }
+        .into(),
+        assembly::Instruction::Add {
+            target: sum,
+            lhs: held,
+            rhs: assembly::Data::Register(addend),
+        }
+        .into(),
+        assembly::Instruction::Stlxr {
+            status,
+            register: sum,
+            base,
+        }
+        .into(),
+        assembly::Branch::NonZero {
+            register: status,
+            label: loop_label,
+        }
+        .into(),
+        assembly::Instruction::Sub {
+            target,
+            lhs: sum,
+            rhs: assembly::Data::Register(addend),
+        }
+        .into(),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// The integer constants the registers are known to hold within a block
+#[derive(Default)]
+struct RegisterConstants(HashMap<assembly::RegisterID, i64>);
+
+impl RegisterConstants {
+    /// The code that puts a constant value in the register, which is nothing if it's already
+    /// there. Returns `None` for values that aren't constants, after forgetting what the register
+    /// held, and forgets everything upon calls, which may write to any of them.
+    fn load(&mut self, register: assembly::RegisterID, value: &Value) -> Option<AssemblyOutput> {
+        let constant = match value {
+            Value::Constant(constant) => *constant,
+            Value::Undefined if !matches!(register, assembly::RegisterID::Float { .. }) => 0,
+            Value::Call { .. }
+            | Value::CallPointer { .. }
+            | Value::CallStruct { .. }
+            | Value::CallFloat { .. } => {
+                self.0.clear();
+                return None;
+            }
+            _ => {
+                self.forget(register);
+                return None;
+            }
+        };
+        if self.0.insert(register, constant) == Some(constant) {
+            Some(AssemblyOutput::new())
+        } else {
+            Some(load_constant(register, constant))
+        }
+    }
+
+    fn forget(&mut self, register: assembly::RegisterID) {
+        self.0.remove(&register);
+    }
 }
 
-// NOTE: currently the size is always bit32 but there might be a moment in time
-// where it's not.
+/// Constants that fit in an immediate are used as one, the others are put in the scratch register
 fn could_be_constant_to_data(
     cbc: CouldBeConstant,
+    bit_size: assembly::BitSize,
+    registers: &registers::RegisterMap,
+) -> (AssemblyOutput, assembly::Data) {
+    match cbc {
+        CouldBeConstant::Binding(binding) => (
+            AssemblyOutput::new(),
+            assembly::Data::Register(assembly::Register::from_id(registers[&binding], bit_size)),
+        ),
+        CouldBeConstant::Constant(constant) => match i32::try_from(constant) {
+            Ok(constant) => (
+                AssemblyOutput::new(),
+                assembly::Data::immediate(constant, bit_size),
+            ),
+            Err(_) => could_be_constant_to_register(cbc, bit_size, registers),
+        },
+    }
+}
+
+/// For `add`, `sub` and `cmp`, whose immediates are 12 bits, which may be shifted left by 12.
+/// The assembler takes a negative one as the positive one of the opposite instruction.
+fn arithmetic_operand_to_data(
+    cbc: CouldBeConstant,
+    bit_size: assembly::BitSize,
+    registers: &registers::RegisterMap,
+) -> (AssemblyOutput, assembly::Data) {
+    match cbc {
+        CouldBeConstant::Constant(constant) => {
+            let magnitude = constant.unsigned_abs();
+            if magnitude < 1 << 12 || (magnitude & 0xfff == 0 && magnitude < 1 << 24) {
+                could_be_constant_to_data(cbc, bit_size, registers)
+            } else {
+                could_be_constant_to_register(cbc, bit_size, registers)
+            }
+        }
+        CouldBeConstant::Binding(_) => could_be_constant_to_data(cbc, bit_size, registers),
+    }
+}
+
+/// Pick one of two values on a flag, with the condition the flags already hold for it, or else
+/// comparing it with zero like a branch does. A 0 is the zero register and a 1 is one more than
+/// it with `csinc`. Other constants are put in the scratch registers first.
+fn select(
+    target_register: assembly::RegisterID,
+    (flag, if_true, if_false): (Binding, CouldBeConstant, CouldBeConstant),
+    flags: &mut Flags,
+    registers: &registers::RegisterMap,
+) -> AssemblyOutput {
+    let mut output = AssemblyOutput::new();
+    let condition = match *flags {
+        Some((binding, condition)) if binding == flag => condition,
+        _ => {
+            output.push_back(assembly::Instruction::Cmp {
+                register: assembly::Register::from_id(registers[&flag], assembly::BitSize::Bit32),
+                data: assembly::Data::Register(assembly::Register::ZeroRegister {
+                    bit_size: assembly::BitSize::Bit32,
+                }),
+            });
+            *flags = Some((flag, assembly::Condition::NotEquals));
+            assembly::Condition::NotEquals
+        }
+    };
+    let register = |id| assembly::Register::from_id(id, assembly::BitSize::Bit64);
+    let zero = assembly::Register::ZeroRegister {
+        bit_size: assembly::BitSize::Bit64,
+    };
+    let mut scratch =
+        (SCRATCH_REGISTER..).map(|index| assembly::RegisterID::GeneralPurpose { index });
+    // `None` is a 1, which is only kept as such if the other value is in a register
+    let mut operand = |cbc, keep_one: bool| match cbc {
+        CouldBeConstant::Binding(binding) => Some(register(registers[&binding])),
+        CouldBeConstant::Constant(0) => Some(zero),
+        CouldBeConstant::Constant(1) if keep_one => None,
+        CouldBeConstant::Constant(constant) => {
+            let scratch = scratch.next().expect("there are two scratch registers");
+            output.extend(load_constant(scratch, constant));
+            Some(register(scratch))
+        }
+    };
+    let if_true = operand(if_true, true);
+    let if_false = operand(if_false, if_true.is_some());
+    let target = register(target_register);
+    output.push_back(match (if_true, if_false) {
+        (Some(if_true), Some(if_false)) => assembly::Instruction::Csel {
+            target,
+            if_true,
+            if_false,
+            condition,
+        },
+        (Some(if_true), None) => assembly::Instruction::Csinc {
+            target,
+            if_true,
+            if_false: zero,
+            condition,
+        },
+        (None, Some(if_false)) => assembly::Instruction::Csinc {
+            target,
+            if_true: if_false,
+            if_false: zero,
+            condition: condition.opposite(),
+        },
+        (None, None) => unreachable!("a 1 is only kept when the other value is in a register"),
+    });
+    output
+}
+
+/// For instructions that only take registers: constants are moved to the scratch register first
+fn could_be_constant_to_register(
+    cbc: CouldBeConstant,
+    bit_size: assembly::BitSize,
     registers: &registers::RegisterMap,
-) -> assembly::Data {
+) -> (AssemblyOutput, assembly::Data) {
     match cbc {
-        CouldBeConstant::Binding(binding) => assembly::Data::Register(assembly::Register::from_id(
-            registers[&binding],
-            assembly::BitSize::Bit32,
-        )),
+        CouldBeConstant::Binding(_) => could_be_constant_to_data(cbc, bit_size, registers),
         CouldBeConstant::Constant(constant) => {
-            assembly::Data::immediate(constant as i32, assembly::BitSize::Bit32)
+            let scratch = assembly::RegisterID::GeneralPurpose {
+                index: SCRATCH_REGISTER,
+            };
+            (
+                load_constant(scratch, constant),
+                assembly::Data::Register(assembly::Register::from_id(scratch, bit_size)),
+            )
+        }
+    }
+}
+
+/// The amount of a shift. A shift by a register only uses the amount modulo the width of the
+/// registers, and constant amounts are reduced the same way (as the folder does), since an
+/// immediate one has to be less than the width. Out of range amounts are undefined in C anyway.
+fn shift_amount_to_data(
+    amount: CouldBeConstant,
+    bit_size: assembly::BitSize,
+    registers: &registers::RegisterMap,
+) -> (AssemblyOutput, assembly::Data) {
+    match amount {
+        CouldBeConstant::Constant(amount) => (
+            AssemblyOutput::new(),
+            assembly::Data::Immediate((amount & i64::from(bit_size.bits() - 1)) as i32),
+        ),
+        CouldBeConstant::Binding(_) => could_be_constant_to_data(amount, bit_size, registers),
+    }
+}
+
+/// Put a constant in a register. Constants are kept sign extended, so both halves of the register
+/// are right no matter the size of the operations that read it. One that a single `mov` can't
+/// build is made 16 bits at a time, starting from all zeros or all ones, whichever is closer.
+fn load_constant(target: assembly::RegisterID, constant: i64) -> AssemblyOutput {
+    // writing the lower half of a register clears the upper one, which is already right for
+    // non-negative values
+    let (bit_size, halves) = if (0..=u32::MAX.into()).contains(&constant) {
+        (assembly::BitSize::Bit32, 2)
+    } else {
+        (assembly::BitSize::Bit64, 4)
+    };
+    let target = assembly::Register::from_id(target, bit_size);
+    let half = |index: u8| (constant >> (16 * index)) as u16;
+    let zeros = (0..halves).filter(|&index| half(index) == 0).count();
+    let ones = (0..halves).filter(|&index| half(index) == u16::MAX).count();
+    if zeros + 1 >= halves as usize || ones + 1 >= halves as usize {
+        if let Ok(immediate) = i32::try_from(constant) {
+            return assembly::Instruction::Mov {
+                target,
+                source: assembly::Data::immediate(immediate, bit_size),
+            }
+            .into();
         }
+        // a 32-bit register only sees the lower bits of the immediate
+        if bit_size == assembly::BitSize::Bit32 {
+            return assembly::Instruction::Mov {
+                target,
+                source: assembly::Data::Immediate(constant as u32 as i32),
+            }
+            .into();
+        }
+    }
+    let background = if ones > zeros { u16::MAX } else { 0 };
+    let mut output = AssemblyOutput::new();
+    for index in (0..halves).filter(|&index| half(index) != background) {
+        let (immediate, shift) = (half(index), 16 * index);
+        output.push_back(if !output.is_empty() {
+            assembly::Instruction::Movk {
+                target,
+                immediate,
+                shift,
+            }
+        } else if background == 0 {
+            assembly::Instruction::Movz {
+                target,
+                immediate,
+                shift,
+            }
+        } else {
+            assembly::Instruction::Movn {
+                target,
+                immediate: !immediate,
+                shift,
+            }
+        });
     }
+    output
+}
+
+/// The address of a label, from the page it's in and its offset there
+fn label_address(target_register: assembly::RegisterID, label: assembly::Label) -> AssemblyOutput {
+    let target = assembly::Register::from_id(target_register, assembly::BitSize::Bit64);
+    let mut output = AssemblyOutput::from(assembly::Instruction::Adrp {
+        target,
+        label: label.clone(),
+    });
+    output.push_back(assembly::Instruction::AddPageOffset {
+        target,
+        source: target,
+        label,
+    });
+    output
 }
 
 fn compile_value(
     value: Value,
     target_register: assembly::RegisterID,
+    read_only_labels: &[assembly::Label],
     memory: &memory::MemoryMap,
     registers: &registers::RegisterMap,
 ) -> AssemblyOutput {
@@ -310,91 +1350,1513 @@ fn compile_value(
             condition,
             lhs,
             rhs,
-        } => AssemblyOutput::from(assembly::Instruction::Cmp {
-            register: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            data: could_be_constant_to_data(rhs, registers),
-        })
-        .chain_one(assembly::Instruction::Cset {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            condition,
-        }),
-        Value::Load {
+            bit_size,
+        } => {
+            let (mut output, data) = arithmetic_operand_to_data(rhs, bit_size, registers);
+            output.extend([
+                assembly::Instruction::Cmp {
+                    register: assembly::Register::from_id(registers[&lhs], bit_size),
+                    data,
+                },
+                assembly::Instruction::Cset {
+                    target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
+                    condition,
+                },
+            ]);
+            output
+        }
+        Value::AtomicLoad {
             mem_binding,
-            byte_size: _, // TODO: use different instruction/register size depending on byte size
-        } => assembly::Instruction::Ldr {
-            register: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            address: memory[&mem_binding],
+            byte_size,
+        } => assembly::Instruction::Ldar {
+            register: assembly::Register::from_id(target_register, atomic_bit_size(byte_size)),
+            base: assembly::Register::from_id(registers[&mem_binding], assembly::BitSize::Bit64),
         }
         .into(),
-        Value::Negate { binding } => assembly::Instruction::Neg {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            source: assembly::Register::from_id(registers[&binding], assembly::BitSize::Bit32),
+        // it needs the label of its loop, which `compile_block` gives it
+        Value::AtomicFetchAdd { .. } => unreachable!("fetch-adds are compiled with their block"),
+        // it needs what the flags hold, which `compile_block` keeps track of
+        Value::Select { .. } => unreachable!("selects are compiled with their block"),
+        Value::Load {
+            mem_binding,
+            byte_size,
+            ..
+        } => {
+            let register = |bit_size| assembly::Register::from_id(target_register, bit_size);
+            let address = address_of(mem_binding, memory, registers);
+            match byte_size {
+                ByteSize::U8 => assembly::Instruction::Ldrb {
+                    register: register(assembly::BitSize::Bit32),
+                    address,
+                },
+                ByteSize::I8 => assembly::Instruction::Ldrsb {
+                    register: register(assembly::BitSize::Bit32),
+                    address,
+                },
+                ByteSize::I16 => assembly::Instruction::Ldrsh {
+                    register: register(assembly::BitSize::Bit32),
+                    address,
+                },
+                ByteSize::U16 => assembly::Instruction::Ldrh {
+                    register: register(assembly::BitSize::Bit32),
+                    address,
+                },
+                ByteSize::U32 | ByteSize::F32 => assembly::Instruction::Ldr {
+                    register: register(assembly::BitSize::Bit32),
+                    address,
+                },
+                ByteSize::U64 | ByteSize::F64 => assembly::Instruction::Ldr {
+                    register: register(assembly::BitSize::Bit64),
+                    address,
+                },
+            }
+            .into()
+        }
+        Value::Negate { binding, bit_size } => assembly::Instruction::Neg {
+            target: assembly::Register::from_id(target_register, bit_size),
+            source: assembly::Register::from_id(registers[&binding], bit_size),
         }
         .into(),
-        // binding XOR FFFFFFFF does the trick.
-        Value::FlipBits { binding } => assembly::Instruction::Eor {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&binding], assembly::BitSize::Bit32),
-            rhs: assembly::Data::Immediate(std::i32::MAX),
-            bitmask: std::u32::MAX as u64,
+        // flipping every bit is a `mvn`
+        Value::FlipBits { binding, bit_size } => assembly::Instruction::MvN {
+            target: assembly::Register::from_id(target_register, bit_size),
+            source: assembly::Data::Register(assembly::Register::from_id(
+                registers[&binding],
+                bit_size,
+            )),
         }
         .into(),
-        Value::Add { lhs, rhs } => {
-            // currently both are only 32 bit
-            let lhs_register = registers[&lhs];
-            assembly::Instruction::Add {
-                target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-                lhs: assembly::Register::from_id(lhs_register, assembly::BitSize::Bit32),
-                rhs: could_be_constant_to_data(rhs, registers),
+        Value::SignExtend { binding, from } => {
+            let target = assembly::Register::from_id(target_register, assembly::BitSize::Bit64);
+            let source = assembly::Register::from_id(registers[&binding], assembly::BitSize::Bit32);
+            match from {
+                ByteSize::I8 => assembly::Instruction::Sxtb { target, source },
+                ByteSize::I16 => assembly::Instruction::Sxth { target, source },
+                ByteSize::U32 => assembly::Instruction::Sxtw { target, source },
+                ByteSize::U8 | ByteSize::U16 | ByteSize::U64 | ByteSize::F32 | ByteSize::F64 => {
+                    unreachable!("only `signed char`s, `short`s and `int`s are sign extended")
+                }
             }
             .into()
         }
-        Value::Subtract { lhs, rhs } => assembly::Instruction::Sub {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            rhs: could_be_constant_to_data(rhs, registers),
+        Value::Add { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = arithmetic_operand_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Add {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
         }
-        .into(),
-        Value::Multiply { lhs, rhs } => assembly::Instruction::Mul {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            rhs: could_be_constant_to_data(rhs, registers),
+        Value::Subtract { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = arithmetic_operand_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Sub {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Multiply { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = could_be_constant_to_register(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Mul {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
         }
-        .into(),
         Value::Divide {
             lhs,
             rhs,
             is_signed,
-        } => assembly::Instruction::Div {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            rhs: could_be_constant_to_data(rhs, registers),
-            signed: is_signed,
+            bit_size,
+        } => {
+            let (mut output, rhs) = could_be_constant_to_register(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Div {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+                signed: is_signed,
+            });
+            output
+        }
+        Value::Lsl { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = shift_amount_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Lsl {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Lsr { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = shift_amount_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Lsr {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Asr { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = shift_amount_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Asr {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::And { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = could_be_constant_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::And {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Or { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = could_be_constant_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Orr {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Xor { lhs, rhs, bit_size } => {
+            let (mut output, rhs) = could_be_constant_to_data(rhs, bit_size, registers);
+            output.push_back(assembly::Instruction::Eor {
+                target: assembly::Register::from_id(target_register, bit_size),
+                lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                rhs,
+            });
+            output
+        }
+        Value::Constant(ctant) => load_constant(target_register, ctant),
+        // frozen to zero, the same value the folder picks for it
+        Value::Undefined => match target_register {
+            assembly::RegisterID::Float { .. } => {
+                load_float_constant(target_register, 0, assembly::BitSize::Bit64)
+            }
+            _ => load_constant(target_register, 0),
+        },
+        Value::FloatConstant { bits, bit_size } => {
+            load_float_constant(target_register, bits, bit_size)
+        }
+        Value::FloatArithmetic {
+            op,
+            lhs,
+            rhs,
+            bit_size,
+        } => {
+            let target = assembly::Register::from_id(target_register, bit_size);
+            let lhs = assembly::Register::from_id(registers[&lhs], bit_size);
+            let rhs = assembly::Register::from_id(registers[&rhs], bit_size);
+            match op {
+                FloatOp::Add => assembly::Instruction::Fadd { target, lhs, rhs },
+                FloatOp::Subtract => assembly::Instruction::Fsub { target, lhs, rhs },
+                FloatOp::Multiply => assembly::Instruction::Fmul { target, lhs, rhs },
+                FloatOp::Divide => assembly::Instruction::Fdiv { target, lhs, rhs },
+            }
+            .into()
+        }
+        Value::FloatNegate { binding, bit_size } => assembly::Instruction::Fneg {
+            target: assembly::Register::from_id(target_register, bit_size),
+            source: assembly::Register::from_id(registers[&binding], bit_size),
         }
         .into(),
-        Value::Lsl { lhs, rhs } => todo!(),
-        Value::Lsr { lhs, rhs } => todo!(),
-        Value::And { lhs, rhs } => assembly::Instruction::And {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            rhs: could_be_constant_to_data(rhs, registers),
+        Value::FloatCmp {
+            condition,
+            lhs,
+            rhs,
+            bit_size,
+        } => {
+            let (condition, lhs, rhs) = float_condition(condition, lhs, rhs);
+            let mut output = AssemblyOutput::new();
+            output.extend([
+                assembly::Instruction::Fcmp {
+                    lhs: assembly::Register::from_id(registers[&lhs], bit_size),
+                    rhs: assembly::Register::from_id(registers[&rhs], bit_size),
+                },
+                assembly::Instruction::Cset {
+                    target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
+                    condition,
+                },
+            ]);
+            output
+        }
+        Value::IntToFloat { binding, from, to } => assembly::Instruction::Scvtf {
+            target: assembly::Register::from_id(target_register, to),
+            source: assembly::Register::from_id(registers[&binding], from),
         }
         .into(),
-        Value::Or { lhs, rhs } => todo!(),
-        Value::Xor { lhs, rhs } => assembly::Instruction::Eor {
-            target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-            lhs: assembly::Register::from_id(registers[&lhs], assembly::BitSize::Bit32),
-            rhs: could_be_constant_to_data(rhs, registers),
-            bitmask: std::u32::MAX as u64,
+        Value::FloatToInt { binding, from, to } => assembly::Instruction::Fcvtzs {
+            target: assembly::Register::from_id(target_register, to),
+            source: assembly::Register::from_id(registers[&binding], from),
         }
         .into(),
-        Value::Constant(ctant) => {
-            assembly::Instruction::Mov {
-                target: assembly::Register::from_id(target_register, assembly::BitSize::Bit32),
-                source: assembly::Data::immediate(ctant, assembly::BitSize::Bit32),
-            }
+        Value::FloatConvert { binding, from, to } => assembly::Instruction::Fcvt {
+            target: assembly::Register::from_id(target_register, to),
+            source: assembly::Register::from_id(registers[&binding], from),
         }
         .into(),
         Value::Binding(_) => todo!(),
+        Value::Call { function, args } => {
+            let sources = args.iter().map(|arg| registers[arg]).collect();
+            let mut output = move_to_argument_registers(sources);
+            output.push_back(assembly::Branch::Linked {
+                label: assembly::Label::Function(function),
+            });
+            output.chain(move_register(target_register, argument_register(0)))
+        }
+        Value::CallPointer { pointer, args } => {
+            // the pointer may be in one of the argument registers, so it's kept apart first
+            let callee = assembly::RegisterID::GeneralPurpose {
+                index: SCRATCH_REGISTER + 1,
+            };
+            let mut output = move_register(callee, registers[&pointer]);
+            let sources = args.iter().map(|arg| registers[arg]).collect();
+            output.extend(move_to_argument_registers(sources));
+            output.push_back(assembly::Branch::LinkedRegister {
+                register: assembly::Register::from_id(callee, assembly::BitSize::Bit64),
+            });
+            output.chain(move_register(target_register, argument_register(0)))
+        }
+        Value::CallStruct {
+            callee,
+            args,
+            result,
+            size,
+        } => {
+            let (mut output, branch) = call_branch(callee, registers);
+            let sources = args.iter().map(|arg| registers[arg]).collect();
+            output.extend(move_to_argument_registers(sources));
+            let result = address_of(result, memory, registers);
+            if size > generate::MAX_STRUCT_IN_REGISTERS {
+                output.push_back(assembly::Instruction::Add {
+                    target: assembly::Register::from_id(
+                        RESULT_ADDRESS_REGISTER,
+                        assembly::BitSize::Bit64,
+                    ),
+                    lhs: result.register,
+                    rhs: assembly::Data::Immediate(result.offset.determined_size() as i32),
+                });
+                output.push_back(branch);
+            } else {
+                output.push_back(branch);
+                output.extend(
+                    struct_words(result, size).map(|(register, address)| {
+                        assembly::Instruction::Str { register, address }
+                    }),
+                );
+            }
+            output.chain(move_register(target_register, argument_register(0)))
+        }
+        Value::CallFloat { callee, args, .. } => {
+            let (mut output, branch) = call_branch(callee, registers);
+            let sources = args.iter().map(|arg| registers[arg]).collect();
+            output.extend(move_to_argument_registers(sources));
+            output.push_back(branch);
+            output.chain(move_register(target_register, FLOAT_RESULT_REGISTER))
+        }
+        // the struct is loaded into `x0` and `x1` when returning
+        Value::ReturnedStruct { .. } => AssemblyOutput::new(),
+        Value::ResultAddress { .. } => move_register(target_register, RESULT_ADDRESS_REGISTER),
+        Value::FunctionAddress(function) => {
+            label_address(target_register, assembly::Label::Function(function))
+        }
+        Value::GlobalAddress(variable) => {
+            label_address(target_register, assembly::Label::Global(variable))
+        }
+        Value::Argument(index) => move_register(target_register, argument_register(index as u8)),
+        Value::FloatArgument(index) => move_register(
+            target_register,
+            assembly::RegisterID::Float { index: index as u8 },
+        ),
+        Value::GlobalRegister(register) => move_register(
+            target_register,
+            assembly::RegisterID::GeneralPurpose { index: register },
+        ),
+        Value::ReadOnlyAddress(num) => {
+            label_address(target_register, read_only_labels[num].clone())
+        }
+        Value::Address { mem_binding } => {
+            let assembly::Memory { register, offset } = memory[&mem_binding];
+            assembly::Instruction::Add {
+                target: assembly::Register::from_id(target_register, assembly::BitSize::Bit64),
+                lhs: register,
+                rhs: assembly::Data::Immediate(offset.determined_size() as i32),
+            }
+            .into()
+        }
+        Value::ElementAddress {
+            base,
+            index,
+            element_size,
+            bit_size,
+        } => {
+            let mut output = AssemblyOutput::new();
+            let base = match memory.get(&base) {
+                // the stack pointer can't be used as the addend, so the address goes to a scratch
+                // register first
+                Some(assembly::Memory { register, offset }) => {
+                    let scratch = assembly::Register::GeneralPurpose {
+                        index: SCRATCH_REGISTER + 1,
+                        bit_size: assembly::BitSize::Bit64,
+                    };
+                    output.push_back(assembly::Instruction::Add {
+                        target: scratch,
+                        lhs: *register,
+                        rhs: assembly::Data::Immediate(offset.determined_size() as i32),
+                    });
+                    scratch
+                }
+                None => assembly::Register::from_id(registers[&base], assembly::BitSize::Bit64),
+            };
+            let size = assembly::Register::GeneralPurpose {
+                index: SCRATCH_REGISTER,
+                bit_size,
+            };
+            let target = assembly::Register::from_id(target_register, assembly::BitSize::Bit64);
+            let index = assembly::Register::from_id(registers[&index], bit_size);
+            output.push_back(assembly::Instruction::Mov {
+                target: size,
+                source: assembly::Data::Immediate(element_size as i32),
+            });
+            // a 32-bit index is sign extended as part of the multiplication
+            output.push_back(match bit_size {
+                assembly::BitSize::Bit32 => assembly::Instruction::Smaddl {
+                    target,
+                    lhs: index,
+                    rhs: size,
+                    addend: base,
+                },
+                assembly::BitSize::Bit64 => assembly::Instruction::Madd {
+                    target,
+                    lhs: index,
+                    rhs: size,
+                    addend: base,
+                },
+            });
+            output
+        }
+    }
+}
+
+/// The condition and operands a floating-point comparison is made with. `lt` and `le` also hold
+/// when the comparison is unordered (either side is NaN), but `gt` and `ge` don't, so those are
+/// asked the other way around.
+fn float_condition(
+    condition: assembly::Condition,
+    lhs: Binding,
+    rhs: Binding,
+) -> (assembly::Condition, Binding, Binding) {
+    match condition {
+        assembly::Condition::LessThan => (assembly::Condition::GreaterThan, rhs, lhs),
+        assembly::Condition::LessEqual => (assembly::Condition::GreaterEqual, rhs, lhs),
+        condition => (condition, lhs, rhs),
+    }
+}
+
+/// Where a memory binding points to: either a stack slot or an address computed into a register
+fn address_of(
+    mem_binding: Binding,
+    memory: &memory::MemoryMap,
+    registers: &registers::RegisterMap,
+) -> assembly::Memory {
+    memory
+        .get(&mem_binding)
+        .copied()
+        .unwrap_or_else(|| assembly::Memory {
+            register: assembly::Register::from_id(
+                registers[&mem_binding],
+                assembly::BitSize::Bit64,
+            ),
+            offset: assembly::Offset::Determined(0),
+        })
+}
+
+/// Put the bits of a floating-point constant in a floating-point register. There's no immediate
+/// for most of them, so they're built in the scratch register first.
+fn load_float_constant(
+    target: assembly::RegisterID,
+    bits: u64,
+    bit_size: assembly::BitSize,
+) -> AssemblyOutput {
+    let target = assembly::Register::from_id(target, bit_size);
+    if bits == 0 {
+        return assembly::Instruction::Fmov {
+            target,
+            source: assembly::Register::ZeroRegister { bit_size },
+        }
+        .into();
+    }
+    let scratch = assembly::RegisterID::GeneralPurpose {
+        index: SCRATCH_REGISTER,
+    };
+    let mut output = load_constant(scratch, bits as i64);
+    output.push_back(assembly::Instruction::Fmov {
+        target,
+        source: assembly::Register::from_id(scratch, bit_size),
+    });
+    output
+}
+
+/// Split the edges going into blocks with phi nodes from blocks that branch somewhere else too,
+/// with a block in between. The values of the phi nodes are moved in place at the end of the
+/// blocks they come from, which then only go to the block of the phi nodes, after the moves.
+fn split_phi_edges(ir: &mut IR) {
+    let with_phis: Vec<_> = analysis::iterate_with_bindings(&ir.code)
+        .filter(|(_, block)| {
+            block.statements.iter().any(|statement| {
+                matches!(
+                    statement,
+                    Statement::Assign {
+                        value: Value::Phi { .. },
+                        ..
+                    }
+                )
+            })
+        })
+        .map(|(block, _)| block)
+        .collect();
+    let mut split = false;
+    for block in with_phis {
+        for statement in 0..ir[block].statements.len() {
+            let Statement::Assign {
+                value: Value::Phi { nodes },
+                ..
+            } = &ir[block].statements[statement]
+            else {
+                continue;
+            };
+            let mut predecessors: Vec<_> = nodes.iter().map(|node| node.block_from).collect();
+            predecessors.sort_unstable();
+            predecessors.dedup();
+            for predecessor in predecessors {
+                if !matches!(
+                    ir[predecessor].end,
+                    BlockEnd::Branch(Branch::Conditional { .. })
+                ) {
+                    continue;
+                }
+                let edge = BlockBinding(ir.code.len());
+                ir.code.push(BasicBlock {
+                    statements: Vec::new(),
+                    end: BlockEnd::Branch(Branch::Unconditional { target: block }),
+                });
+                // UNSAFE: safe. The new block is only branched to from here.
+                unsafe { refactor::end_rename_block(&mut ir[predecessor].end, block, edge) };
+                for statement in &mut ir[block].statements {
+                    if let Statement::Assign {
+                        value: Value::Phi { nodes },
+                        ..
+                    } = statement
+                    {
+                        nodes
+                            .iter_mut()
+                            .filter(|node| node.block_from == predecessor)
+                            .for_each(|node| node.block_from = edge);
+                    }
+                }
+                split = true;
+            }
+        }
+    }
+    if split {
+        (ir.forward_map, ir.backwards_map) = generate::generate_branching_graphs(&ir.code);
+    }
+}
+
+/// The moves at the end of each block that put the values of the phi nodes it goes to in their
+/// registers, as `(target, source)`
+fn phi_moves(
+    ir: &IR,
+    registers: &HashMap<Binding, assembly::RegisterID>,
+) -> HashMap<BlockBinding, Vec<(assembly::RegisterID, assembly::RegisterID)>> {
+    let mut moves: HashMap<_, Vec<_>> = HashMap::new();
+    for statement in ir.code.iter().flat_map(|block| &block.statements) {
+        let Statement::Assign {
+            index,
+            value: Value::Phi { nodes },
+        } = statement
+        else {
+            continue;
+        };
+        let Some(&target) = registers.get(index) else {
+            continue;
+        };
+        for node in nodes {
+            moves
+                .entry(node.block_from)
+                .or_default()
+                .push((target, registers[&node.value]));
+        }
+    }
+    moves
+}
+
+/// `mov` between registers, unless the value is already in place. The whole register is moved,
+/// since it may hold a pointer. Moves to or from a floating-point register keep the bits as they
+/// are.
+fn move_register(target: assembly::RegisterID, source: assembly::RegisterID) -> AssemblyOutput {
+    let is_float = |register| matches!(register, assembly::RegisterID::Float { .. });
+    if target == source {
+        AssemblyOutput::new()
+    } else if is_float(target) || is_float(source) {
+        assembly::Instruction::Fmov {
+            target: assembly::Register::from_id(target, assembly::BitSize::Bit64),
+            source: assembly::Register::from_id(source, assembly::BitSize::Bit64),
+        }
+        .into()
+    } else {
+        assembly::Instruction::Mov {
+            target: assembly::Register::from_id(target, assembly::BitSize::Bit64),
+            source: assembly::Data::Register(assembly::Register::from_id(
+                source,
+                assembly::BitSize::Bit64,
+            )),
+        }
+        .into()
+    }
+}
+
+const fn argument_register(index: u8) -> assembly::RegisterID {
+    assembly::RegisterID::GeneralPurpose { index }
+}
+
+/// Floating-point values are returned in `v0`
+const FLOAT_RESULT_REGISTER: assembly::RegisterID = assembly::RegisterID::Float { index: 0 };
+
+/// The register a value kept in `register` is returned in: the first argument register of its kind
+fn return_register(register: assembly::RegisterID) -> assembly::RegisterID {
+    match register {
+        assembly::RegisterID::Float { .. } => FLOAT_RESULT_REGISTER,
+        _ => argument_register(0),
+    }
+}
+
+/// The code that keeps the pointer a call goes through, if it goes through one, out of the way
+/// of the arguments, and the branch that makes the call
+fn call_branch(
+    callee: Callee,
+    registers: &registers::RegisterMap,
+) -> (AssemblyOutput, assembly::Branch) {
+    let callee_register = assembly::RegisterID::GeneralPurpose {
+        index: SCRATCH_REGISTER + 1,
+    };
+    match callee {
+        Callee::Function(function) => (
+            AssemblyOutput::new(),
+            assembly::Branch::Linked {
+                label: assembly::Label::Function(function),
+            },
+        ),
+        Callee::Pointer(pointer) => (
+            move_register(callee_register, registers[&pointer]),
+            assembly::Branch::LinkedRegister {
+                register: assembly::Register::from_id(callee_register, assembly::BitSize::Bit64),
+            },
+        ),
+    }
+}
+
+/// Structs of more than [`generate::MAX_STRUCT_IN_REGISTERS`] bytes are returned to the address
+/// the caller passes in `x8`
+const RESULT_ADDRESS_REGISTER: assembly::RegisterID =
+    assembly::RegisterID::GeneralPurpose { index: 8 };
+
+/// The 8-byte words of a struct returned in registers, each with the argument register it goes
+/// in: `x0` for the first one and `x1` for the second
+fn struct_words(
+    memory: assembly::Memory,
+    size: usize,
+) -> impl Iterator<Item = (assembly::Register, assembly::Memory)> {
+    (0..size.div_ceil(8)).map(move |word| {
+        (
+            assembly::Register::from_id(argument_register(word as u8), assembly::BitSize::Bit64),
+            assembly::Memory {
+                register: memory.register,
+                offset: assembly::Offset::Determined(memory.offset.determined_size() + 8 * word),
+            },
+        )
+    })
+}
+
+/// Move each source into its argument register, as if all the moves happened at once: sources
+/// that are also argument registers are read before being overwritten. Floating-point values go
+/// to `v0`, `v1`, ... in order, and the rest to `x0`, `x1`, ...
+fn move_to_argument_registers(sources: Vec<assembly::RegisterID>) -> AssemblyOutput {
+    let (mut integers, mut floats) = (0, 0);
+    let pending = sources
+        .into_iter()
+        .map(|source| {
+            let target = if let assembly::RegisterID::Float { .. } = source {
+                floats += 1;
+                assembly::RegisterID::Float { index: floats - 1 }
+            } else {
+                integers += 1;
+                argument_register(integers - 1)
+            };
+            (target, source)
+        })
+        .collect();
+    parallel_move(pending)
+}
+
+/// Do the moves, given as `(target, source)`, as if they all happened at once: the sources that
+/// are also targets are read before being overwritten
+fn parallel_move(moves: Vec<(assembly::RegisterID, assembly::RegisterID)>) -> AssemblyOutput {
+    let mut output = AssemblyOutput::new();
+    let mut pending: Vec<_> = moves
+        .into_iter()
+        .filter(|(target, source)| target != source)
+        .collect();
+    while !pending.is_empty() {
+        // a move can be done if nobody else still needs its target
+        let free = pending
+            .iter()
+            .position(|&(target, _)| pending.iter().all(|&(_, source)| source != target));
+        match free {
+            Some(position) => {
+                let (target, source) = pending.remove(position);
+                output.extend(move_register(target, source));
+            }
+            None => {
+                // all the pending moves form cycles: park one of the sources in the scratch
+                // register to break one
+                let cycled = pending[0].1;
+                let scratch = assembly::RegisterID::GeneralPurpose {
+                    index: SCRATCH_REGISTER,
+                };
+                output.extend(move_register(scratch, cycled));
+                pending
+                    .iter_mut()
+                    .filter(|(_, source)| *source == cycled)
+                    .for_each(|(_, source)| *source = scratch);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::passes::{OptLevel, PassManager};
+    use alloc::vec;
+
+    /// The assembly of every function of a program that compiles, one instruction per line
+    fn compile_to_assembly(source: &str) -> Vec<Vec<String>> {
+        compile_to_sections(source, SectionPlacement::default())
+    }
+
+    /// The assembly of every function, placing its code and data as asked
+    fn compile_to_sections(source: &str, sections: SectionPlacement) -> Vec<Vec<String>> {
+        let options = CodegenOptions {
+            sections,
+            ..CodegenOptions::default()
+        };
+        compile_functions(source, options)
+            .into_iter()
+            .map(lines)
+            .collect()
+    }
+
+    /// The assembly of every function of a program that compiles, optimized as with `-O1`
+    fn optimized_assembly(source: &str) -> Vec<Vec<String>> {
+        let meta = SourceMetadata::new(source);
+        let module = generate::tests::compiled(&meta);
+        module
+            .functions
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut function)| {
+                function.ir = PassManager::for_level(OptLevel::O1).run(function.ir, &mut |_, _| ());
+                let registers = &module.reserved_registers;
+                let options = CodegenOptions::default();
+                lines(codegen_function(function, index, registers, options, &meta))
+            })
+            .collect()
+    }
+
+    /// Generate the code of every function of a program that compiles
+    fn compile_functions(source: &str, options: CodegenOptions) -> Vec<CompiledFunction> {
+        let meta = SourceMetadata::new(source);
+        let module = generate::tests::compiled(&meta);
+        module
+            .functions
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut function)| {
+                function.ir = fold::constant_fold(function.ir);
+                codegen_function(function, index, &module.reserved_registers, options, &meta)
+            })
+            .collect()
+    }
+
+    /// The assembly of a function, one instruction per line
+    fn lines(compiled: CompiledFunction) -> Vec<String> {
+        compiled
+            .assembly
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn long_arithmetic_uses_the_x_registers_and_ints_widen_with_their_sign() {
+        let assembly = compile_to_assembly(
+            "int f(long a, long b, long *out) { *out = a * b + a - (b & 3); return 0; }\n\
+             int g(int i, long *out) { *out = i; return 0; }\n\
+             int h(long *out) { *out = 4294967301; return 0; }\n",
+        );
+        let [f, g, h] = assembly.as_slice() else {
+            panic!("there are three functions");
+        };
+        for operation in ["mul x", "add x", "and x", "sub x"] {
+            assert!(
+                f.iter().any(|line| line.starts_with(operation)),
+                "{operation} in {f:#?}"
+            );
+        }
+        assert!(f.iter().all(|line| !line.starts_with("mul w")), "{f:#?}");
+        assert!(g.iter().any(|line| line.starts_with("sxtw x")), "{g:#?}");
+        // a constant too wide for a single `mov` is built 16 bits at a time
+        assert!(
+            h.iter()
+                .any(|line| line.starts_with("movz x") && line.ends_with("#0x5")),
+            "{h:#?}"
+        );
+        assert!(
+            h.iter()
+                .any(|line| line.starts_with("movk x") && line.ends_with("#0x1, lsl #32")),
+            "{h:#?}"
+        );
+    }
+
+    #[test]
+    fn shorts_are_stored_and_loaded_in_halfwords() {
+        let assembly = compile_to_assembly(
+            "int f(short *p, int i) { p[0] = i; return p[1] + 1; }\n\
+             long g(short s) { return s; }\n",
+        );
+        // only the low half of the `int` is stored, and the sign of the `short` is kept
+        for (function, instruction) in assembly.iter().zip(["strh w1, [x", "sxth x0, w0"]) {
+            assert!(
+                function.iter().any(|line| line.starts_with(instruction)),
+                "{function:#?}"
+            );
+        }
+        // a short element is two bytes from the next
+        assert!(assembly[0].contains(&"mov w16, #2".to_string()));
+        assert!(assembly[0].iter().any(|line| line.starts_with("ldrsh w0")));
+    }
+
+    #[test]
+    fn floating_point_values_use_the_s_and_d_registers() {
+        let assembly = compile_to_assembly(
+            "int f(double a, double b, double *out) { *out = a * b - a; return a < b; }\n\
+             int g(float a, int i, float *out) { *out = a + i; return a; }\n",
+        );
+        let [f, g] = assembly.as_slice() else {
+            panic!("there are two functions");
+        };
+        // floating arguments come in their own registers, so the pointer is still the first one
+        for operation in ["fmul d", "fsub d", "str d2, [x0]", "fcmp d1, d0"] {
+            assert!(
+                f.iter().any(|line| line.starts_with(operation)),
+                "{operation} in {f:#?}"
+            );
+        }
+        // the `int` is converted before adding, and the result truncated when returning it
+        for operation in ["scvtf s", "fadd s", "fcvtzs w0, s"] {
+            assert!(
+                g.iter().any(|line| line.starts_with(operation)),
+                "{operation} in {g:#?}"
+            );
+        }
+    }
+
+    #[test]
+    fn floating_point_values_are_returned_in_v0() {
+        let assembly = compile_to_assembly(
+            "double half(double x) { return x / 2; }\n\
+             float third(int i) { return i / 3.0f; }\n\
+             long wide(long a) { return a; }\n\
+             int main(void) { return half(5.0) + third(1) * wide(3); }\n",
+        );
+        let [half, third, _, main] = assembly.as_slice() else {
+            panic!("there are four functions");
+        };
+        assert!(
+            half.iter().any(|line| line.starts_with("fdiv d0, d0")),
+            "{half:#?}"
+        );
+        assert!(
+            third.iter().any(|line| line.starts_with("fdiv s0")),
+            "{third:#?}"
+        );
+        // the caller keeps each result apart from `v0` before the next call overwrites it
+        let after = |call: &str| {
+            let position = main
+                .iter()
+                .position(|line| line.ends_with(call))
+                .unwrap_or_else(|| panic!("{call} is called in {main:#?}"));
+            main[position + 1].clone()
+        };
+        assert!(after("half").ends_with(", d0"), "{main:#?}");
+        assert!(after("third").ends_with(", d0"), "{main:#?}");
+        assert!(
+            main.iter().any(|line| line.starts_with("fcvtzs w0, d")),
+            "{main:#?}"
+        );
+    }
+
+    #[test]
+    fn branch_protection_is_parsed_like_gcc_does() {
+        let parse = |argument: &str| argument.parse::<BranchProtection>();
+        let both = BranchProtection {
+            bti: true,
+            pac_ret: true,
+        };
+        assert_eq!(parse("none"), Ok(BranchProtection::default()));
+        assert_eq!(parse("standard"), Ok(both));
+        assert_eq!(parse("pac-ret+bti"), Ok(both));
+        assert_eq!(parse("bti+pac-ret+leaf"), Ok(both));
+        assert_eq!(
+            parse("bti"),
+            Ok(BranchProtection {
+                bti: true,
+                pac_ret: false
+            })
+        );
+        // `leaf` only qualifies `pac-ret`
+        assert!(parse("leaf").is_err());
+        assert!(parse("bti+leaf").is_err());
+        assert!(parse("standard+bti").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn modules_start_with_where_they_came_from() {
+        let meta = SourceMetadata::new("int main(void) { return 0; }").with_file("m.c".into());
+        let metadata = generate::tests::compiled(&meta).metadata;
+        assert_eq!(metadata.source_file.as_deref(), Some("m.c"));
+        let prologue: Vec<_> = module_prologue(&metadata)
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect();
+        assert_eq!(
+            prologue,
+            [
+                ".file \"m.c\"",
+                ".arch armv8-a",
+                // the options that change the code are noted even when left as they were
+                "// options: --std=c99 --target=aarch64-linux-gnu",
+            ]
+        );
+        let ident = module_epilogue(BranchProtection::default())
+            .into_iter()
+            .next()
+            .map(|line| line.to_string().trim().to_string());
+        assert_eq!(
+            ident.as_deref(),
+            Some(concat!(".ident \"tracc ", env!("CARGO_PKG_VERSION"), "\""))
+        );
+    }
+
+    #[test]
+    fn modules_end_with_the_notes_for_the_linker() {
+        let lines = |output: AssemblyOutput| -> Vec<String> {
+            output
+                .into_iter()
+                .map(|line| line.to_string().trim().to_string())
+                .collect()
+        };
+        let plain = lines(module_epilogue(BranchProtection::default()));
+        assert_eq!(
+            plain.last().unwrap(),
+            ".section .note.GNU-stack,\"\",%progbits"
+        );
+        assert!(plain
+            .iter()
+            .all(|line| !line.contains(".note.gnu.property")));
+
+        let protected = lines(module_epilogue(BranchProtection {
+            bti: true,
+            pac_ret: true,
+        }));
+        let note = protected
+            .iter()
+            .position(|line| line == ".section .note.gnu.property,\"a\"")
+            .expect("the features are noted");
+        // the feature bits are the second to last word of the property
+        assert_eq!(
+            protected[note + 6..],
+            [".word 3221225472", ".word 4", ".word 3", ".word 0"]
+        );
+    }
+
+    #[test]
+    fn shift_amounts_are_taken_modulo_the_width() {
+        let assembly = compile_to_assembly(
+            "int f(int a, long l, long *out) { *out = l << 65; return (a << 33) + (a << -1); }\n",
+        );
+        // an immediate amount must be less than the width, like the ones in registers are
+        let shifts: Vec<_> = assembly[0]
+            .iter()
+            .filter(|line| line.starts_with("lsl"))
+            .map(|line| &line[line.rfind(' ').unwrap() + 1..])
+            .collect();
+        assert_eq!(shifts, ["#1", "#1", "#31"], "{assembly:#?}");
+    }
+
+    #[test]
+    fn functions_and_their_data_can_get_sections_of_their_own() {
+        // big enough for its initializer to be copied from read-only data
+        let source = "int f(void) { int a[16] = {1}; return a[0]; }\n";
+        let sections_of = |sections| -> Vec<String> {
+            compile_to_sections(source, sections)
+                .remove(0)
+                .into_iter()
+                .filter(|line| line.starts_with(".text") || line.starts_with(".section"))
+                .collect()
+        };
+        assert_eq!(
+            sections_of(SectionPlacement::default()),
+            [".text", ".section .rodata,\"a\",%progbits"]
+        );
+        assert_eq!(
+            sections_of(SectionPlacement {
+                function_sections: true,
+                data_sections: false,
+            }),
+            [
+                ".section .text.f,\"ax\",%progbits",
+                ".section .rodata,\"a\",%progbits",
+            ]
+        );
+        assert_eq!(
+            sections_of(SectionPlacement {
+                function_sections: true,
+                data_sections: true,
+            }),
+            [
+                ".section .text.f,\"ax\",%progbits",
+                ".section .rodata.f,\"a\",%progbits",
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_are_set_to_their_target() {
+        let aliases = [
+            generate::Alias {
+                name: "api",
+                target: "impl",
+                linkage: crate::ast::Linkage::External,
+            },
+            generate::Alias {
+                name: "local",
+                target: "impl",
+                linkage: crate::ast::Linkage::Internal,
+            },
+        ];
+        let lines: Vec<String> = module_symbols(&aliases, &["hook"])
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ".weak hook",
+                ".global api",
+                ".type api, %function",
+                ".set api, impl",
+                ".type local, %function",
+                ".set local, impl",
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_are_only_loaded_again_once_their_register_changed() {
+        let x = |index| assembly::RegisterID::GeneralPurpose { index };
+        // the bindings are given the registers directly, so that some of them share one
+        let registers: registers::RegisterMap = [0, 0, 1, 0, 0, 0, 0, 1, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, index)| (Binding(binding), x(index)))
+            .collect();
+        let assign = |index, value| Statement::Assign {
+            index: Binding(index),
+            value,
+        };
+        let block = vec![
+            assign(0, Value::Constant(5)),
+            // already there
+            assign(1, Value::Constant(5)),
+            assign(2, Value::Constant(7)),
+            // a call may write to any register
+            assign(
+                3,
+                Value::Call {
+                    function: "f".into(),
+                    args: vec![],
+                },
+            ),
+            assign(4, Value::Constant(5)),
+            // overwritten by something else
+            assign(
+                5,
+                Value::Add {
+                    lhs: Binding(4),
+                    rhs: CouldBeConstant::Constant(1),
+                    bit_size: assembly::BitSize::Bit32,
+                },
+            ),
+            assign(6, Value::Constant(5)),
+            assign(7, Value::Constant(7)),
+            // a different constant in the same register
+            assign(8, Value::Constant(8)),
+        ];
+        let (output, _) = compile_block(
+            block,
+            0,
+            &[],
+            &memory::MemoryMap::new(),
+            &registers,
+            &CallSaves::new(),
+            None,
+        );
+        let lines: Vec<String> = output
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "mov w0, #5",
+                "mov w1, #7",
+                "bl  f",
+                "mov w0, #5",
+                "add w0, w0, #1",
+                "mov w0, #5",
+                "mov w1, #7",
+                "mov w1, #8",
+            ]
+        );
+    }
+
+    #[test]
+    fn conditions_branch_on_the_flags_of_their_comparison() {
+        let assembly = compile_to_assembly(
+            "int f(int a, int b) { if (a < b) return 1; return 2; }\n\
+             int g(int a) { volatile int c = a < 3; if (c) return 1; return 2; }\n",
+        );
+        let [f, g] = assembly.as_slice() else {
+            panic!("there are two functions");
+        };
+        // falling through to the `then` block, the branch skips it when the comparison fails
+        assert!(f.iter().all(|line| !line.ends_with("wzr")), "{f:#?}");
+        assert!(f.iter().any(|line| line.starts_with("bge")), "{f:#?}");
+        // the flag comes from memory, so the flags hold nothing about it
+        assert!(
+            g.iter()
+                .any(|line| line.starts_with("cmp w") && line.ends_with(", wzr")),
+            "{g:#?}"
+        );
+        assert!(g.iter().any(|line| line.starts_with("beq")), "{g:#?}");
+    }
+
+    #[test]
+    fn values_living_through_a_call_past_the_callee_saved_registers_are_saved_around_it() {
+        let assembly = compile_to_assembly(
+            "int g(int x);\n\
+             int f(int a) {\n\
+             \x20   int b = a + 1; int c = a + 2; int d = a + 3; int e = a + 4;\n\
+             \x20   int h = a + 5; int i = a + 6; int j = a + 7; int k = a + 8;\n\
+             \x20   int l = a + 9; int m = a + 10; int n = a + 11;\n\
+             \x20   int r = g(a);\n\
+             \x20   return r + b + c + d + e + h + i + j + k + l + m + n;\n\
+             }\n",
+        );
+        let f = &assembly[0];
+        let call = f
+            .iter()
+            .position(|line| line == "bl  g")
+            .expect("g is called");
+        // x19 to x28 only fit ten of the eleven values
+        let stored: Vec<_> = f[..call]
+            .iter()
+            .rev()
+            .take_while(|line| line.starts_with("str x") || line.starts_with("mov"))
+            .filter_map(|line| line.strip_prefix("str "))
+            .collect();
+        let loaded: Vec<_> = f[call + 1..]
+            .iter()
+            .take_while(|line| line.starts_with("ldr x"))
+            .filter_map(|line| line.strip_prefix("ldr "))
+            .collect();
+        assert_eq!(stored.len(), 1, "{f:#?}");
+        assert_eq!(stored, loaded, "{f:#?}");
+    }
+
+    #[test]
+    fn statements_taking_many_callee_saved_registers_are_spill_heavy() {
+        let x = |index| assembly::RegisterID::GeneralPurpose { index };
+        let d = |index| assembly::RegisterID::Float { index };
+        let span = |offset| Span { offset, len: 1 };
+        let registers: registers::RegisterMap = [
+            // four callee-saved registers for the statement at 10, counting `x19` once
+            (0, x(19)),
+            (1, x(20)),
+            (2, x(19)),
+            (3, d(8)),
+            (4, x(21)),
+            // caller-saved ones are free to use
+            (5, x(0)),
+            (6, x(1)),
+            (7, x(2)),
+            (8, x(22)),
+            // a binding a pass made has no statement
+            (9, x(23)),
+        ]
+        .into_iter()
+        .map(|(binding, register)| (Binding(binding), register))
+        .collect();
+        let spans: HashMap<Binding, Span> = (0..9)
+            .map(|binding| (Binding(binding), span(if binding < 5 { 10 } else { 20 })))
+            .collect();
+        assert_eq!(
+            spill_heavy_statements(&registers, &spans)
+                .into_iter()
+                .map(|(span, registers)| (span.offset, registers))
+                .collect::<Vec<_>>(),
+            [(10, 4)]
+        );
+    }
+
+    #[test]
+    fn frame_sizes_count_the_locals_and_the_frame_record() {
+        let source = "int leaf(int x) { return x + 1; }\n\
+                      int array(void) { int a[64]; a[0] = 1; return a[0]; }\n\
+                      long odd(void) { long a = 1, *p = &a; char c[3]; return *p + c[0]; }\n";
+        let sizes: Vec<_> = compile_functions(source, CodegenOptions::default())
+            .iter()
+            .map(|compiled| compiled.frame_size)
+            .collect();
+        // the locals are rounded up to keep `sp` aligned to 16 bytes
+        assert_eq!(sizes, [16, 16 + 256, 16 + 16]);
+    }
+
+    #[test]
+    fn frames_larger_than_the_limit_are_warned_about() {
+        let source = "int big(void) { int a[64]; a[0] = 1; return a[0]; }\n\
+                      int small(void) { int a[2]; a[0] = 1; return a[0]; }\n";
+        let mut options = CodegenOptions::default();
+        assert!(options.warnings.apply_flag("frame-larger-than=128"));
+        let warnings: Vec<Vec<String>> = compile_functions(source, options)
+            .into_iter()
+            .map(|compiled| {
+                compiled
+                    .warnings
+                    .iter()
+                    .map(|warning| warning.kind().to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].len(), 1, "{warnings:?}");
+        assert!(warnings[0][0].contains("big"), "{warnings:?}");
+        assert!(warnings[1].is_empty(), "{warnings:?}");
+        // without a limit there's nothing to warn about
+        let compiled = compile_functions(source, CodegenOptions::default());
+        assert!(compiled.iter().all(|compiled| compiled.warnings.is_empty()));
+    }
+
+    #[test]
+    fn small_structs_go_in_registers_and_big_ones_through_memory() {
+        let assembly = compile_to_assembly(
+            "struct pair { int a; int b; };\n\
+             struct big { long a; long b; long c; };\n\
+             struct pair swap(struct pair p) {\n\
+             \x20   struct pair q = {p.b, p.a};\n\
+             \x20   return q;\n\
+             }\n\
+             struct big grow(struct big b) {\n\
+             \x20   b.c = b.a + b.b;\n\
+             \x20   return b;\n\
+             }\n\
+             int main() {\n\
+             \x20   struct pair p = {1, 2};\n\
+             \x20   struct big b = {3, 4, 5};\n\
+             \x20   b = grow(b);\n\
+             \x20   return swap(p).a + b.c;\n\
+             }\n",
+        );
+        let [swap, grow, main] = assembly.as_slice() else {
+            panic!("there are three functions");
+        };
+        // the pair comes in x0, and goes back in it
+        assert!(
+            swap.iter().any(|line| line.starts_with("str x0, [sp")),
+            "{swap:#?}"
+        );
+        let ret = swap
+            .iter()
+            .position(|line| line == "ret")
+            .expect("swap returns");
+        let result = swap[..ret].iter().rposition(|line| line.contains("x0"));
+        assert!(
+            result.is_some_and(|result| swap[result].starts_with("ldr x0, [sp")),
+            "{swap:#?}"
+        );
+        // the big struct is copied by the caller and passed by its address, and the result is
+        // written where x8 points
+        let reads_x0 = |line: &String| line.starts_with("ldr x") && line.ends_with(", [x0]");
+        assert!(grow.iter().any(reads_x0), "{grow:#?}");
+        assert!(grow.iter().any(|line| line.contains("x8")), "{grow:#?}");
+        let call = main
+            .iter()
+            .position(|line| line == "bl  grow")
+            .expect("grow is called");
+        assert!(
+            main[..call]
+                .iter()
+                .any(|line| line.starts_with("add x8, sp")),
+            "{main:#?}"
+        );
+    }
+
+    #[test]
+    fn zero_initialized_globals_only_reserve_their_size_in_bss() {
+        let globals = [
+            generate::GlobalData {
+                name: "table",
+                linkage: crate::ast::Linkage::External,
+                bytes: vec![1, 0, 2, 0],
+                align: 2,
+            },
+            generate::GlobalData {
+                name: "counter",
+                linkage: crate::ast::Linkage::Internal,
+                bytes: vec![0; 8],
+                align: 8,
+            },
+        ];
+        let lines = |sections| -> Vec<String> {
+            module_globals(&globals, sections)
+                .into_iter()
+                .map(|line| line.to_string().trim().to_string())
+                .collect()
+        };
+        assert_eq!(
+            lines(SectionPlacement::default()),
+            [
+                ".section .data,\"aw\",%progbits",
+                ".global table",
+                ".p2align 1",
+                ".type table, %object",
+                ".size table, 4",
+                "table:",
+                ".byte 1, 0, 2, 0",
+                ".section .bss,\"aw\",%nobits",
+                ".p2align 3",
+                ".type counter, %object",
+                ".size counter, 8",
+                "counter:",
+                ".zero 8",
+            ]
+        );
+        let separate = lines(SectionPlacement {
+            function_sections: false,
+            data_sections: true,
+        });
+        assert_eq!(separate[0], ".section .data.table,\"aw\",%progbits");
+        assert_eq!(separate[7], ".section .bss.counter,\"aw\",%nobits");
+
+        // the functions reach a global through its address
+        let assembly = compile_to_assembly("int counter;\nint main(void) { return counter; }\n");
+        let [main] = assembly.as_slice() else {
+            panic!("there is one function");
+        };
+        assert!(
+            main.iter()
+                .any(|line| line.starts_with("adrp ") && line.ends_with(", counter")),
+            "{main:#?}"
+        );
+    }
+
+    #[test]
+    fn global_variables_take_the_alignment_they_ask_for() {
+        let source = "_Alignas(16) int g;\n\
+                      static _Alignas(8) _Alignas(4) int h = 1;\n\
+                      char c;\n\
+                      _Alignas(double) char c = 2;\n\
+                      int main(void) { return g + h + c; }\n";
+        let meta = SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (module, _) = generate::compile_program(program, &meta, Default::default())
+            .expect("the source is fine");
+        let lines: Vec<String> = module_globals(&module.globals, SectionPlacement::default())
+            .into_iter()
+            .map(|line| line.to_string().trim().to_string())
+            .collect();
+        let align_before = |label: &str| {
+            let position = lines
+                .iter()
+                .position(|line| *line == format!("{label}:"))
+                .unwrap_or_else(|| panic!("{label} is defined in {lines:#?}"));
+            lines[..position]
+                .iter()
+                .rev()
+                .find(|line| line.starts_with(".p2align"))
+                .cloned()
+        };
+        assert_eq!(align_before("g").as_deref(), Some(".p2align 4"));
+        assert_eq!(align_before("h").as_deref(), Some(".p2align 3"));
+        assert_eq!(align_before("c").as_deref(), Some(".p2align 3"));
+
+        let error = |source: &str| {
+            let meta = SourceMetadata::new(source);
+            let error = match crate::grammar::Parser::new(&meta).parse() {
+                Ok(program) => generate::compile_program(program, &meta, Default::default())
+                    .err()
+                    .map(|error| error.to_string()),
+                Err(error) => Some(error.to_string()),
+            };
+            error
+        };
+        let reduced = error("_Alignas(1) int x;\nint main(void) { return x; }\n");
+        assert!(reduced.is_some_and(|error| error.contains("cannot reduce")));
+        let function = error("_Alignas(16) int f(void);\n");
+        assert!(function.is_some_and(|error| error.contains("only applies to variables")));
+    }
+
+    #[test]
+    fn verbose_assembly_sums_up_each_function() {
+        let source = "static inline int twice(int x) { return x + x; }\n\
+                      int f(int a) { return twice(a) * 3; }\n";
+        let meta = SourceMetadata::new(source);
+        let mut module = generate::tests::compiled(&meta);
+        inline::inline_functions(&mut module);
+        let mut function = module.functions.remove(0);
+        function.ir = fold::constant_fold(function.ir);
+        let options = CodegenOptions {
+            verbose_asm: true,
+            ..Default::default()
+        };
+        let compiled = codegen_function(function, 0, &[], options, &meta);
+        let comments: Vec<_> = compiled
+            .assembly
+            .into_iter()
+            .skip_while(|line| matches!(line, assembly::Assembly::Directive(_)))
+            .take_while(|line| matches!(line, assembly::Assembly::Comment(_)))
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(comments.len(), 4);
+        assert!(comments[0].starts_with("// f: frame of "));
+        assert_eq!(comments[1], "// saved registers: none");
+        assert_eq!(comments[3], "// optimizations: inlining");
+    }
+
+    #[test]
+    fn promoted_variables_are_moved_in_place_where_paths_meet() {
+        // the call keeps the swap behind the branch, instead of it becoming selects
+        let source = "int g(void);\n\
+                      int f(int x, int y, int c) {\n\
+                      \x20   if (c) { int t = x; x = y; y = t; g(); }\n\
+                      \x20   return x - 2 * y;\n\
+                      }\n";
+        let assembly = optimized_assembly(source).remove(0);
+        // the variables never go to the stack
+        assert!(
+            !assembly
+                .iter()
+                .any(|line| line.contains("ldr") || line.contains("str")),
+            "{assembly:#?}"
+        );
+        // swapping them on one side moves both phi nodes' values at once, through the scratch
+        // register
+        assert!(
+            assembly.iter().any(|line| line.contains("mov x16, x")),
+            "{assembly:#?}"
+        );
+    }
+
+    #[test]
+    fn values_picked_on_a_condition_are_selected_without_branching() {
+        let source = "int f(int a, int b) {\n\
+                      \x20   int r;\n\
+                      \x20   if (a > b) r = a + 1; else r = b;\n\
+                      \x20   return r;\n\
+                      }\n\
+                      int g(int a, int b) {\n\
+                      \x20   int r = b;\n\
+                      \x20   if (a) r = 1;\n\
+                      \x20   return r;\n\
+                      }\n";
+        // a 1 is one more than the zero register
+        for (assembly, select) in optimized_assembly(source).iter().zip(["csel", "csinc"]) {
+            // no block is branched to
+            assert!(
+                !assembly.iter().any(|line| line.contains(".LBB")),
+                "{assembly:#?}"
+            );
+            assert!(
+                assembly.iter().any(|line| line.contains(select)),
+                "{assembly:#?}"
+            );
+        }
+    }
+
+    #[test]
+    fn atomic_builtins_use_the_instructions_of_the_cpu() -> Result<(), String> {
+        use crate::check_str;
+        use target::Cpu;
+        let source = "int main() {\n\
+                      \x20   int count = 1;\n\
+                      \x20   long total = 5;\n\
+                      \x20   __atomic_store_n(&count, 40, 5);\n\
+                      \x20   int before = __atomic_fetch_add(&count, 2, 5);\n\
+                      \x20   __atomic_fetch_add(&total, -3, 0);\n\
+                      \x20   return before + __atomic_load_n(&count, 2) + __atomic_load_n(&total, 2);\n\
+                      }\n";
+        assert_eq!(generate::tests::returned(source), 84);
+        let assembly = |cpu| {
+            target::with_selected(target::current(), cpu, || compile_to_assembly(source)).remove(0)
+        };
+        let exclusive = assembly(Cpu::default());
+        for instruction in ["stlr", "ldaxr", "stlxr", "cbnz", "ldar"] {
+            assert!(
+                exclusive.iter().any(|line| line.contains(instruction)),
+                "no {instruction} in {exclusive:#?}"
+            );
+        }
+        let lse = assembly(Cpu::by_architecture("armv8.1-a")?);
+        let fetch_adds = lse.iter().filter(|line| line.contains("ldaddal"));
+        assert_eq!(fetch_adds.count(), 2);
+        assert!(!lse.iter().any(|line| line.contains("ldaxr")));
+
+        // the memory orders are numbered as in GCC
+        let ordered = "#if __ATOMIC_RELAXED == 0 && __ATOMIC_ACQ_REL == 4 && __ATOMIC_SEQ_CST == 5\n\
+                       int f(int *i) {\n\
+                       \x20   __atomic_store_n(i, 1, __ATOMIC_RELEASE);\n\
+                       \x20   return __atomic_load_n(i, __ATOMIC_ACQUIRE) + __atomic_load_n(i, __ATOMIC_CONSUME);\n\
+                       }\n\
+                       #endif\n\
+                       int main(void) { int i = 0; return f(&i); }\n";
+        assert!(check_str(ordered).is_empty(), "{}", check_str(ordered));
+        assert!(!check_str("int f(double *d) { return __atomic_load_n(d, 5); }").is_empty());
+        assert!(!check_str("int f(int *i) { __atomic_store_n(i, 1); return 0; }").is_empty());
+        assert!(!check_str("int f(int *i) { return __atomic_store_n(i, 1, 5); }").is_empty());
+        Ok(())
     }
 }
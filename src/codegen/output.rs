@@ -1,6 +1,6 @@
 use super::assembly;
-use std::collections::VecDeque;
-use std::fmt;
+use alloc::collections::{BTreeMap, VecDeque};
+use core::fmt;
 
 pub struct AssemblyOutput(VecDeque<assembly::Assembly>);
 impl Default for AssemblyOutput {
@@ -67,7 +67,7 @@ impl AssemblyOutput {
         self
     }
 
-    pub fn iter_mut<'out>(&'out mut self) -> impl Iterator<Item = &'out mut assembly::Assembly> {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut assembly::Assembly> {
         self.0.iter_mut()
     }
 }
@@ -109,6 +109,130 @@ impl fmt::Debug for AssemblyOutput {
             }
         }
 
-        f.debug_list().entries(self.0.iter().map(DoDisplay)).finish()
+        f.debug_list()
+            .entries(self.0.iter().map(DoDisplay))
+            .finish()
+    }
+}
+
+/// The kinds of sections the assembly of a module is grouped in, in the order they're written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectionGroup {
+    Text,
+    ReadOnly,
+    Data,
+    Bss,
+}
+
+/// The assembly of a module grouped by the kind of section it goes in, each piece starting with
+/// the directive that switches to its section. The groups are written in order, and the pieces of
+/// a group in the order of the functions they belong to, whatever order they were added in. A
+/// piece that's in the same section as the one before it doesn't switch to it again.
+#[derive(Default)]
+pub struct SectionedOutput(BTreeMap<(SectionGroup, usize), AssemblyOutput>);
+
+impl SectionedOutput {
+    /// Add the assembly of a function to a group, after what it already has there
+    pub fn add(&mut self, group: SectionGroup, function_index: usize, output: AssemblyOutput) {
+        self.0
+            .entry((group, function_index))
+            .or_default()
+            .extend(output);
+    }
+
+    /// Add all the pieces of another output to this one
+    pub fn merge(&mut self, other: SectionedOutput) {
+        for ((group, function_index), output) in other.0 {
+            self.add(group, function_index, output);
+        }
+    }
+
+    /// The assembly of all the groups, one after the other
+    pub fn into_output(self) -> AssemblyOutput {
+        let mut section = None;
+        let mut output = AssemblyOutput::new();
+        for item in self.0.into_values().flatten() {
+            if let assembly::Assembly::Directive(
+                directive @ (assembly::Directive::Text | assembly::Directive::Section { .. }),
+            ) = &item
+            {
+                if section.as_ref() == Some(directive) {
+                    continue;
+                }
+                section = Some(directive.clone());
+            }
+            output.push_back(item);
+        }
+        output
+    }
+}
+
+impl IntoIterator for SectionedOutput {
+    type Item = assembly::Assembly;
+    type IntoIter = <AssemblyOutput as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_output().into_iter()
+    }
+}
+
+impl FromIterator<SectionedOutput> for SectionedOutput {
+    fn from_iter<T: IntoIterator<Item = SectionedOutput>>(iter: T) -> Self {
+        let mut output = Self::default();
+        for other in iter {
+            output.merge(other);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
+
+    fn piece(section: assembly::Directive, label: &str) -> AssemblyOutput {
+        AssemblyOutput::from(assembly::Assembly::Label(label.into())).cons(section)
+    }
+
+    fn read_only() -> assembly::Directive {
+        assembly::Directive::Section {
+            name: ".rodata".into(),
+            flags: "a".into(),
+            kind: Some("progbits".into()),
+        }
+    }
+
+    #[test]
+    fn sections_are_grouped_in_the_order_of_the_functions() {
+        // the second function is compiled first
+        let second: SectionedOutput = {
+            let mut output = SectionedOutput::default();
+            output.add(SectionGroup::ReadOnly, 1, piece(read_only(), ".Lrodata1_0"));
+            output.add(SectionGroup::Text, 1, piece(assembly::Directive::Text, "g"));
+            output
+        };
+        let first: SectionedOutput = {
+            let mut output = SectionedOutput::default();
+            output.add(SectionGroup::Text, 0, piece(assembly::Directive::Text, "f"));
+            output.add(SectionGroup::ReadOnly, 0, piece(read_only(), ".Lrodata0_0"));
+            output
+        };
+        let rendered: Vec<String> = [second, first]
+            .into_iter()
+            .collect::<SectionedOutput>()
+            .into_iter()
+            .map(|item| item.to_string())
+            .collect();
+        let expected = [
+            "\t.text",
+            "f:",
+            "g:",
+            "\t.section .rodata,\"a\",%progbits",
+            ".Lrodata0_0:",
+            ".Lrodata1_0:",
+        ];
+        assert_eq!(rendered, expected);
     }
 }
@@ -0,0 +1,172 @@
+//! Long-branch relaxation. A conditional branch only reaches 1MB either way, which a very large
+//! function can exceed, so the ones whose target ends up too far away are turned into a branch on
+//! the opposite condition over an unconditional branch, which reaches 128MB.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::assembly::{Assembly, Branch, Instruction, Label};
+use super::AssemblyOutput;
+use hashbrown::HashMap;
+
+/// Bytes in every instruction
+const INSTRUCTION_SIZE: usize = 4;
+
+/// How far a conditional branch reaches, in bytes: its offset is a signed 19-bit count of
+/// instructions
+pub const CONDITIONAL_BRANCH_RANGE: usize = INSTRUCTION_SIZE << 18;
+
+/// Rewrite the conditional branches of the code of a function whose targets are `range` bytes or
+/// further away. Each rewrite grows the code, which may take other branches out of range, so it
+/// goes on until all of them reach.
+pub fn relax_branches(
+    output: AssemblyOutput,
+    function_index: usize,
+    range: usize,
+) -> AssemblyOutput {
+    let mut code: Vec<Assembly> = output.into_iter().collect();
+    let mut relaxed = 0;
+    loop {
+        let mut offsets = Vec::with_capacity(code.len());
+        let mut labels = HashMap::new();
+        let mut offset = 0;
+        for asm in &code {
+            offsets.push(offset);
+            match asm {
+                Assembly::Label(name) => {
+                    labels.insert(name.clone(), offset);
+                }
+                Assembly::Instruction(_) => offset += INSTRUCTION_SIZE,
+                Assembly::Directive(_) | Assembly::Comment(_) => (),
+            }
+        }
+        let far: Vec<usize> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(index, asm)| match asm {
+                Assembly::Instruction(Instruction::Branch(Branch::Conditional {
+                    label, ..
+                })) => {
+                    let target = *labels.get(&label.to_string())?;
+                    (target.abs_diff(offsets[index]) >= range).then_some(index)
+                }
+                _ => None,
+            })
+            .collect();
+        if far.is_empty() {
+            return code.into_iter().collect();
+        }
+        // from the end, so that the indices before each rewrite stay the same
+        for index in far.into_iter().rev() {
+            let (condition, label) = match &code[index] {
+                Assembly::Instruction(Instruction::Branch(Branch::Conditional {
+                    condition,
+                    label,
+                })) => (*condition, label.clone()),
+                _ => unreachable!("only conditional branches are relaxed"),
+            };
+            let skip = Label::Relaxed {
+                function: function_index,
+                num: relaxed,
+            };
+            relaxed += 1;
+            code.splice(
+                index..=index,
+                [
+                    Branch::Conditional {
+                        condition: condition.opposite(),
+                        label: skip.clone(),
+                    }
+                    .into(),
+                    Branch::Unconditional {
+                        register: None,
+                        label,
+                    }
+                    .into(),
+                    skip.into(),
+                ],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::assembly::Condition;
+
+    fn branch_to(num: usize) -> Assembly {
+        Branch::Conditional {
+            condition: Condition::Equals,
+            label: Label::Block { function: 0, num },
+        }
+        .into()
+    }
+
+    fn rendered(output: AssemblyOutput) -> Vec<String> {
+        output.into_iter().map(|asm| asm.to_string()).collect()
+    }
+
+    #[test]
+    fn near_branches_stay() {
+        let code = || -> AssemblyOutput {
+            [
+                branch_to(1),
+                Instruction::Ret.into(),
+                Label::Block {
+                    function: 0,
+                    num: 1,
+                }
+                .into(),
+                Instruction::Ret.into(),
+            ]
+            .into_iter()
+            .collect()
+        };
+        assert_eq!(rendered(relax_branches(code(), 0, 16)), rendered(code()));
+    }
+
+    #[test]
+    fn far_branches_jump_over_unconditional_ones() {
+        let code: AssemblyOutput = core::iter::once(branch_to(1))
+            .chain(core::iter::repeat_n(Instruction::Ret.into(), 4))
+            .chain([Label::Block {
+                function: 0,
+                num: 1,
+            }
+            .into()])
+            .collect();
+        let relaxed = rendered(relax_branches(code, 0, 16));
+        assert_eq!(
+            relaxed[..3],
+            ["\tbne .Lrelax0_0", "\tb   .LBB0_1", ".Lrelax0_0:"]
+        );
+        assert_eq!(relaxed.len(), 8);
+    }
+
+    #[test]
+    fn relaxing_can_take_other_branches_out_of_range() {
+        // the second branch reaches back to its target with one instruction to spare, until the
+        // first one grows by one in between
+        let label = |num| Label::Block { function: 0, num }.into();
+        let code: AssemblyOutput = [
+            label(2),
+            Instruction::Ret.into(),
+            branch_to(1),
+            Instruction::Ret.into(),
+            branch_to(2),
+            Instruction::Ret.into(),
+            label(1),
+        ]
+        .into_iter()
+        .collect();
+        let relaxed = rendered(relax_branches(code, 0, 16));
+        assert_eq!(
+            relaxed
+                .iter()
+                .filter(|line| line.starts_with("\tbne"))
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,555 @@
+//! What the compiler assumes about the machine the generated code runs on: its architecture and
+//! the sizes and alignments of the types. Everything that depends on those (the offsets of struct
+//! members, the strides of arrays, the slots of stack frames and the width of the registers a
+//! value is computed in) reads them from the [`current`] target instead of spelling the numbers
+//! out. Which instructions can be used besides the baseline ones depends on the [`cpu`] selected
+//! with `-mcpu`, `-march` and `-mattr`.
+//!
+//! The selection is the only state of the compiler that outlives a call into it: everything else is
+//! passed along or made for the function being compiled. Libraries compiling for a machine of their
+//! own should go through [`with_selected`], which doesn't leave it behind for the next compilation.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use super::assembly::BitSize;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The machine code is generated for
+#[derive(Debug)]
+pub struct Target {
+    /// what `--target=` calls it, the first name being the one it's known by
+    pub names: &'static [&'static str],
+    pub data_layout: TargetDataLayout,
+}
+
+/// Size and alignment of a type, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalarLayout {
+    pub size: usize,
+    pub align: usize,
+}
+
+impl ScalarLayout {
+    /// A type aligned to its own size
+    const fn natural(size: usize) -> Self {
+        Self { size, align: size }
+    }
+}
+
+/// How the types are laid out in memory and in registers
+#[derive(Debug)]
+pub struct TargetDataLayout {
+    pub bool: ScalarLayout,
+    pub char: ScalarLayout,
+    pub short: ScalarLayout,
+    pub int: ScalarLayout,
+    pub long: ScalarLayout,
+    pub float: ScalarLayout,
+    pub double: ScalarLayout,
+    pub pointer: ScalarLayout,
+    /// bytes in a general purpose register
+    pub register_size: usize,
+    /// alignment of the stack pointer, which is kept at all times
+    pub stack_align: usize,
+}
+
+impl TargetDataLayout {
+    /// The width of the registers that hold a value of `size` bytes
+    pub const fn register_width(&self, size: usize) -> BitSize {
+        if size <= self.int.size {
+            BitSize::Bit32
+        } else {
+            BitSize::Bit64
+        }
+    }
+}
+
+/// The targets code can be generated for. The first one is the default.
+pub const TARGETS: [Target; 2] = [
+    // ARM64 under the AAPCS64, with the LP64 data model
+    Target {
+        names: &["aarch64-linux-gnu", "aarch64-linux", "aarch64"],
+        data_layout: TargetDataLayout {
+            bool: ScalarLayout::natural(1),
+            char: ScalarLayout::natural(1),
+            short: ScalarLayout::natural(2),
+            int: ScalarLayout::natural(4),
+            long: ScalarLayout::natural(8),
+            float: ScalarLayout::natural(4),
+            double: ScalarLayout::natural(8),
+            pointer: ScalarLayout::natural(8),
+            register_size: 8,
+            stack_align: 16,
+        },
+    },
+    // the same, with the ILP32 data model: `long`s and pointers are 32 bits, and live in the
+    // lower half of the registers like `int`s do
+    Target {
+        names: &["aarch64-linux-gnu_ilp32", "aarch64-linux-ilp32"],
+        data_layout: TargetDataLayout {
+            bool: ScalarLayout::natural(1),
+            char: ScalarLayout::natural(1),
+            short: ScalarLayout::natural(2),
+            int: ScalarLayout::natural(4),
+            long: ScalarLayout::natural(4),
+            float: ScalarLayout::natural(4),
+            double: ScalarLayout::natural(8),
+            pointer: ScalarLayout::natural(4),
+            register_size: 8,
+            stack_align: 16,
+        },
+    },
+];
+
+/// What the rest of the compiler takes for granted about a target
+const fn check(target: &Target) {
+    let layout = &target.data_layout;
+    assert!(layout.char.size == 1, "sizes are counted in `char`s");
+    assert!(
+        layout.bool.size <= layout.int.size && layout.short.size <= layout.int.size,
+        "values narrower than an `int` are promoted to one"
+    );
+    assert!(
+        layout.int.size == 4 && layout.float.size == 4,
+        "`int`s and `float`s are computed in the 32-bit view of a register"
+    );
+    assert!(
+        (layout.long.size == layout.int.size || layout.long.size == layout.register_size)
+            && (layout.pointer.size == layout.int.size
+                || layout.pointer.size == layout.register_size),
+        "`long`s and pointers are computed in either view of a register"
+    );
+    assert!(
+        layout.double.size == layout.register_size,
+        "`double`s fill a whole register"
+    );
+    assert!(
+        layout.stack_align.is_power_of_two() && layout.stack_align == 2 * layout.register_size,
+        "registers are saved on the stack by pairs"
+    );
+}
+
+// checked when the compiler is built
+const _: () = {
+    let mut index = 0;
+    while index < TARGETS.len() {
+        check(&TARGETS[index]);
+        index += 1;
+    }
+};
+
+/// The optional extensions of the architecture that instruction selection can use, when the CPU
+/// has them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    /// the Large System Extensions: atomic read-modify-write instructions (`ldadd`, `cas`, `swp`)
+    /// instead of loops of exclusive loads and stores
+    pub lse: bool,
+    /// the Common Short Sequence Compression instructions (`abs`, `cnt`, `ctz`, `smax`...)
+    pub cssc: bool,
+    /// pointer authentication beyond the hints, like `retaa` to authenticate the return address
+    /// and return at once
+    pub pauth: bool,
+}
+
+impl Features {
+    /// The features by the names `-march`, `-mattr` and the `.arch` directive give them
+    const NAMES: [&'static str; 3] = ["lse", "cssc", "pauth"];
+
+    const fn all_of(lse: bool, cssc: bool, pauth: bool) -> Self {
+        Self { lse, cssc, pauth }
+    }
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "lse" => Some(&mut self.lse),
+            "cssc" => Some(&mut self.cssc),
+            "pauth" => Some(&mut self.pauth),
+            _ => None,
+        }
+    }
+    fn has(mut self, name: &str) -> bool {
+        self.flag(name).is_some_and(|flag| *flag)
+    }
+    #[cfg(not(feature = "std"))]
+    const fn bits(self) -> usize {
+        self.lse as usize | (self.cssc as usize) << 1 | (self.pauth as usize) << 2
+    }
+    #[cfg(not(feature = "std"))]
+    const fn from_bits(bits: usize) -> Self {
+        Self::all_of(bits & 1 != 0, bits & 2 != 0, bits & 4 != 0)
+    }
+}
+
+/// A version of the architecture, with the extensions it makes mandatory
+#[derive(Debug, PartialEq, Eq)]
+pub struct Architecture {
+    pub name: &'static str,
+    pub features: Features,
+}
+
+/// The versions of the architecture that `-march` takes. The first one is the baseline every
+/// target supports.
+pub const ARCHITECTURES: [Architecture; 15] = {
+    const fn version(name: &'static str, lse: bool, cssc: bool, pauth: bool) -> Architecture {
+        Architecture {
+            name,
+            features: Features::all_of(lse, cssc, pauth),
+        }
+    }
+    [
+        version("armv8-a", false, false, false),
+        version("armv8.1-a", true, false, false),
+        version("armv8.2-a", true, false, false),
+        version("armv8.3-a", true, false, true),
+        version("armv8.4-a", true, false, true),
+        version("armv8.5-a", true, false, true),
+        version("armv8.6-a", true, false, true),
+        version("armv8.7-a", true, false, true),
+        version("armv8.8-a", true, false, true),
+        version("armv8.9-a", true, true, true),
+        version("armv9-a", true, false, true),
+        version("armv9.1-a", true, false, true),
+        version("armv9.2-a", true, false, true),
+        version("armv9.3-a", true, false, true),
+        version("armv9.4-a", true, true, true),
+    ]
+};
+
+/// The CPUs that `-mcpu` takes, by the version of the architecture they implement
+const CPUS: &[(&str, &str)] = &[
+    ("generic", "armv8-a"),
+    ("cortex-a53", "armv8-a"),
+    ("cortex-a72", "armv8-a"),
+    ("cortex-a55", "armv8.2-a"),
+    ("cortex-a76", "armv8.2-a"),
+    ("neoverse-n1", "armv8.2-a"),
+    ("apple-m1", "armv8.5-a"),
+    ("neoverse-v1", "armv8.4-a"),
+    ("neoverse-n2", "armv9-a"),
+    ("neoverse-v2", "armv9-a"),
+    ("cortex-x4", "armv9.2-a"),
+];
+
+/// What the generated code may use of the CPU it runs on: a version of the architecture, and the
+/// extensions it has on top of it or lacks of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpu {
+    pub architecture: &'static Architecture,
+    pub features: Features,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::of(&ARCHITECTURES[0])
+    }
+}
+
+impl Cpu {
+    /// Any CPU that implements the architecture, with just the extensions it makes mandatory
+    const fn of(architecture: &'static Architecture) -> Self {
+        Self {
+            architecture,
+            features: architecture.features,
+        }
+    }
+
+    /// The CPU named by `-mcpu`
+    pub fn by_name(name: &str) -> Result<Self, String> {
+        let (_, architecture) = CPUS
+            .iter()
+            .find(|(cpu, _)| *cpu == name)
+            .ok_or_else(|| format!("unknown CPU `{}`", name))?;
+        Self::by_architecture(architecture)
+    }
+
+    /// The CPU that `-march` describes: a version of the architecture, followed by the extensions
+    /// added (`+lse`) or taken out (`+nolse`)
+    pub fn by_architecture(description: &str) -> Result<Self, String> {
+        let mut parts = description.split('+');
+        let name = parts.next().unwrap_or_default();
+        let architecture = ARCHITECTURES
+            .iter()
+            .find(|architecture| architecture.name == name)
+            .ok_or_else(|| format!("unknown architecture `{}`", name))?;
+        let mut cpu = Self::of(architecture);
+        for part in parts {
+            let (name, enabled) = match part.strip_prefix("no") {
+                Some(name) => (name, false),
+                None => (part, true),
+            };
+            cpu.set(name, enabled)?;
+        }
+        Ok(cpu)
+    }
+
+    /// Add and take out the extensions listed by `-mattr`, separated by commas, like `+lse,-cssc`
+    pub fn apply_attributes(&mut self, attributes: &str) -> Result<(), String> {
+        for attribute in attributes.split(',') {
+            match attribute.split_at_checked(1) {
+                Some(("+", name)) => self.set(name, true)?,
+                Some(("-", name)) => self.set(name, false)?,
+                _ => return Err(format!("`{}` doesn't add or take out a feature", attribute)),
+            }
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, feature: &str, enabled: bool) -> Result<(), String> {
+        let flag = self
+            .features
+            .flag(feature)
+            .ok_or_else(|| format!("unknown feature `{}`", feature))?;
+        *flag = enabled;
+        Ok(())
+    }
+
+    /// The operand of the `.arch` directive, which tells the assembler to accept what the CPU has,
+    /// like `armv8.2-a+nolse+pauth`
+    pub fn directive(&self) -> String {
+        let mut directive = self.architecture.name.to_string();
+        for name in Features::NAMES {
+            match (
+                self.architecture.features.has(name),
+                self.features.has(name),
+            ) {
+                (false, true) => directive.push_str(&format!("+{}", name)),
+                (true, false) => directive.push_str(&format!("+no{}", name)),
+                _ => (),
+            }
+        }
+        directive
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    /// The target of the compilation running on this thread
+    static CURRENT: Cell<&'static Target> = const { Cell::new(&TARGETS[0]) };
+    /// The CPU of the compilation running on this thread
+    static CPU: Cell<Cpu> = Cell::new(Cpu::default());
+}
+
+/// The index in [`TARGETS`] of the target being compiled for. Without `std` there are no threads
+/// to keep apart, so it's the same for all of the compilations.
+#[cfg(not(feature = "std"))]
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// The index in [`ARCHITECTURES`] of the version of the CPU being compiled for, above the bits of
+/// its features
+#[cfg(not(feature = "std"))]
+static CPU: AtomicUsize = AtomicUsize::new(0);
+
+/// The target being compiled for, which is the default one unless another was [`select`]ed
+#[cfg(feature = "std")]
+pub fn current() -> &'static Target {
+    CURRENT.with(Cell::get)
+}
+
+/// The target being compiled for, which is the default one unless another was [`select`]ed
+#[cfg(not(feature = "std"))]
+pub fn current() -> &'static Target {
+    &TARGETS[CURRENT.load(Ordering::Relaxed)]
+}
+
+/// Compile for another target from now on, in this thread
+#[cfg(feature = "std")]
+pub fn select(target: &'static Target) {
+    CURRENT.with(|current| current.set(target));
+}
+
+/// Compile for another one of the [`TARGETS`] from now on
+#[cfg(not(feature = "std"))]
+pub fn select(target: &'static Target) {
+    let index = TARGETS
+        .iter()
+        .position(|known| known.name() == target.name())
+        .expect("the target is one of `TARGETS`");
+    CURRENT.store(index, Ordering::Relaxed);
+}
+
+/// The CPU being compiled for, which is the baseline of the architecture unless another was
+/// [`select_cpu`]ed
+#[cfg(feature = "std")]
+pub fn cpu() -> Cpu {
+    CPU.with(Cell::get)
+}
+
+/// The CPU being compiled for, which is the baseline of the architecture unless another was
+/// [`select_cpu`]ed
+#[cfg(not(feature = "std"))]
+pub fn cpu() -> Cpu {
+    let selected = CPU.load(Ordering::Relaxed);
+    Cpu {
+        architecture: &ARCHITECTURES[selected >> 8],
+        features: Features::from_bits(selected & 0xff),
+    }
+}
+
+/// Compile for another CPU from now on, in this thread
+#[cfg(feature = "std")]
+pub fn select_cpu(cpu: Cpu) {
+    CPU.with(|current| current.set(cpu));
+}
+
+/// Compile for another CPU from now on
+#[cfg(not(feature = "std"))]
+pub fn select_cpu(cpu: Cpu) {
+    let index = ARCHITECTURES
+        .iter()
+        .position(|known| known == cpu.architecture)
+        .expect("the architecture is one of `ARCHITECTURES`");
+    CPU.store(index << 8 | cpu.features.bits(), Ordering::Relaxed);
+}
+
+/// Compile for `target` and `cpu` while `f` runs, and go back to what was selected before
+/// afterwards, even if `f` panics. The selection can be nested, and with `std` each thread keeps its
+/// own, so hosts can compile for different machines from many threads at the same time. Without
+/// `std` there is a single selection: compilations for different machines have to take turns.
+pub fn with_selected<T>(target: &'static Target, cpu: Cpu, f: impl FnOnce() -> T) -> T {
+    let _previous = Selection {
+        target: current(),
+        cpu: self::cpu(),
+    };
+    select(target);
+    select_cpu(cpu);
+    f()
+}
+
+/// What was selected before [`with_selected`], selected again when it's dropped
+struct Selection {
+    target: &'static Target,
+    cpu: Cpu,
+}
+
+impl Drop for Selection {
+    fn drop(&mut self) {
+        select(self.target);
+        select_cpu(self.cpu);
+    }
+}
+
+/// The target with the given name, if there's one
+pub fn by_name(name: &str) -> Option<&'static Target> {
+    TARGETS.iter().find(|target| target.names.contains(&name))
+}
+
+impl Target {
+    /// The name the target is known by
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Type;
+    use crate::intermediate::ByteSize;
+
+    #[test]
+    fn types_are_as_wide_as_the_selected_target_makes_them() {
+        let pointer = Type::Pointer(Box::new(Type::Int));
+        assert_eq!((Type::Long.size(), pointer.size()), (8, 8));
+        let ilp32 = by_name("aarch64-linux-ilp32").expect("the target exists");
+        with_selected(ilp32, Cpu::default(), || {
+            assert_eq!((Type::Long.size(), pointer.size()), (4, 4));
+            assert_eq!(ByteSize::from(&pointer), ByteSize::U32);
+            // the selections nest, each one restoring the one it replaced
+            with_selected(&TARGETS[0], Cpu::default(), || {
+                assert_eq!(pointer.size(), 8)
+            });
+            assert_eq!(pointer.size(), 4);
+        });
+        assert_eq!(current().name(), TARGETS[0].name());
+    }
+
+    #[test]
+    fn cpus_have_the_features_of_their_architecture() -> Result<(), String> {
+        let named = Cpu::by_name("neoverse-n1")?;
+        assert_eq!(named.architecture.name, "armv8.2-a");
+        assert!(named.features.lse && !named.features.pauth);
+        assert_eq!(named.directive(), "armv8.2-a");
+
+        let mut custom = Cpu::by_architecture("armv8.3-a+nolse+cssc")?;
+        assert_eq!(
+            custom.features,
+            Features {
+                lse: false,
+                cssc: true,
+                pauth: true
+            }
+        );
+        custom.apply_attributes("+lse,-pauth")?;
+        assert_eq!(custom.directive(), "armv8.3-a+cssc+nopauth");
+
+        assert!(Cpu::by_name("pentium").is_err());
+        assert!(Cpu::by_architecture("armv8-a+sve").is_err());
+        assert!(custom.apply_attributes("lse").is_err());
+
+        select_cpu(Cpu::by_architecture("armv8.1-a")?);
+        assert!(cpu().features.lse);
+        select_cpu(Cpu::default());
+        assert_eq!(cpu(), Cpu::of(&ARCHITECTURES[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn compilations_on_many_threads_keep_their_own_machine() -> Result<(), String> {
+        use crate::codegen::{self, CompiledFunction};
+        use crate::error::{Diagnostics, SourceMetadata};
+        use crate::intermediate::generate::{tests::compiled, Module};
+        use alloc::string::ToString;
+        use alloc::vec::Vec;
+        fn is_send_and_sync<T: Send + Sync>() {}
+        is_send_and_sync::<Module<'static>>();
+        is_send_and_sync::<Diagnostics>();
+        is_send_and_sync::<CompiledFunction>();
+
+        let source = "int add(int *total, int n) { return __atomic_fetch_add(total, n, 5); }\n\
+                      int main() { int total = 1; return add(&total, 2); }\n";
+        let compile = |machine: (&'static Target, Cpu)| {
+            with_selected(machine.0, machine.1, || {
+                let meta = SourceMetadata::new(source);
+                let mut lines = Vec::new();
+                for (index, function) in compiled(&meta).functions.into_iter().enumerate() {
+                    let compiled =
+                        codegen::codegen_function(function, index, &[], Default::default(), &meta);
+                    lines.extend(compiled.assembly.into_iter().map(|line| line.to_string()));
+                }
+                lines
+            })
+        };
+        let ilp32 = by_name("aarch64-linux-ilp32").expect("the target exists");
+        let machines = [
+            (current(), Cpu::default()),
+            (ilp32, Cpu::by_architecture("armv8.1-a")?),
+        ];
+        let expected = machines.map(compile);
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|index| {
+                    let machine = machines[index % 2];
+                    scope.spawn(move || (index % 2, (0..4).map(|_| compile(machine)).collect()))
+                })
+                .collect();
+            for thread in threads {
+                let (machine, compiled): (_, Vec<_>) =
+                    thread.join().expect("the compilation doesn't panic");
+                for lines in compiled {
+                    assert_eq!(lines, expected[machine]);
+                }
+            }
+        });
+        assert!(expected[0].iter().any(|line| line.contains("ldaxr")));
+        assert!(expected[1].iter().any(|line| line.contains("ldaddal")));
+        // the selection of the calling thread is left as it was
+        assert_eq!(cpu(), Cpu::default());
+        assert_eq!(current().name(), machines[0].0.name());
+        Ok(())
+    }
+}
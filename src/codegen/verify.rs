@@ -0,0 +1,149 @@
+//! Checking the generated assembly with a real assembler, to catch instructions that are encoded
+//! or rendered wrong before anything tries to link them. The assembler only parses and encodes
+//! the code, its object is thrown away.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::error::{Error, SourceMetadata, Span};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+pub enum AssemblerError {
+    #[error("internal compiler error: the assembler rejected the generated code: {0}")]
+    Rejected(String),
+}
+
+pub type AssemblerE = Error<AssemblerError>;
+
+/// The assemblers tried, in order, with the arguments that make them read the code from stdin and
+/// write no object
+const ASSEMBLERS: &[(&str, &[&str])] = &[
+    ("aarch64-linux-gnu-as", &["-o", "/dev/null", "-"]),
+    ("llvm-mc", &["-triple=aarch64-linux-gnu", "-filetype=null"]),
+    (
+        "clang",
+        &[
+            "--target=aarch64-linux-gnu",
+            "-c",
+            "-x",
+            "assembler",
+            "-o",
+            "/dev/null",
+            "-",
+        ],
+    ),
+];
+
+/// Run the assembly in `source` through the first assembler available. Every error it reports
+/// points at the line of the assembly it is about. Returns `None` if there is no assembler for the
+/// target.
+pub fn verify_assembly(source: &SourceMetadata) -> Option<Vec<AssemblerE>> {
+    let native = cfg!(target_arch = "aarch64").then_some(("as", &["-o", "/dev/null", "-"][..]));
+    let stderr = native
+        .into_iter()
+        .chain(ASSEMBLERS.iter().copied())
+        .find_map(|(program, args)| run_assembler(program, args, source.input()))?;
+
+    let mut errors: Vec<AssemblerE> = stderr
+        .lines()
+        .filter_map(parse_error)
+        .map(|(line, message)| {
+            let error = AssemblerE::new(AssemblerError::Rejected(message));
+            match line_span(source.input(), line) {
+                Some(span) => error.with_source(span, source),
+                None => error,
+            }
+        })
+        .collect();
+    if errors.is_empty() && !stderr.is_empty() {
+        errors.push(AssemblerE::new(AssemblerError::Rejected(
+            stderr.trim().to_string(),
+        )));
+    }
+    Some(errors)
+}
+
+/// What the assembler wrote to stderr if it failed, an empty string if it succeeded, or `None` if
+/// it couldn't be run
+fn run_assembler(program: &str, args: &[&str], code: &str) -> Option<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(code.as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if output.status.success() {
+        Some(String::new())
+    } else {
+        Some(String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+}
+
+/// The line (starting at 1) and message of an error, in either the GNU format
+/// (`{standard input}:12: Error: ...`) or the LLVM one (`<stdin>:12:3: error: ...`)
+fn parse_error(line: &str) -> Option<(usize, String)> {
+    let rest = line
+        .strip_prefix("{standard input}:")
+        .or_else(|| line.strip_prefix("<stdin>:"))?;
+    let (number, rest) = rest.split_once(':')?;
+    let number = number.parse().ok()?;
+    // LLVM also gives the column
+    let rest = match rest.split_once(':') {
+        Some((column, rest)) if column.parse::<usize>().is_ok() => rest,
+        _ => rest,
+    };
+    let rest = rest.trim_start();
+    let message = rest
+        .strip_prefix("Error:")
+        .or_else(|| rest.strip_prefix("error:"))?;
+    Some((number, message.trim().to_string()))
+}
+
+/// The span of a line of the assembly
+fn line_span(code: &str, number: usize) -> Option<Span> {
+    let offset = code
+        .split_terminator('\n')
+        .take(number.checked_sub(1)?)
+        .map(|line| line.len() + 1)
+        .sum();
+    let line = code.get(offset..)?.lines().next()?;
+    Some(Span {
+        offset,
+        len: line.len().max(1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembler_errors_point_at_their_line() {
+        let gnu = "{standard input}:3: Error: unknown mnemonic `movv' -- `movv w0,#1'";
+        assert_eq!(
+            parse_error(gnu),
+            Some((3, "unknown mnemonic `movv' -- `movv w0,#1'".to_string()))
+        );
+        let llvm = "<stdin>:2:5: error: invalid operand for instruction";
+        assert_eq!(
+            parse_error(llvm),
+            Some((2, "invalid operand for instruction".to_string()))
+        );
+        // warnings and the lines that quote the code are left out
+        assert_eq!(parse_error("<stdin>:2:5: warning: unused label"), None);
+        assert_eq!(parse_error("    movv w0, #1"), None);
+
+        let code = "f:\n    movv w0, #1\n\n    ret\n";
+        assert_eq!(line_span(code, 2), Some(Span { offset: 3, len: 15 }));
+        // an empty line still has something to point at
+        assert_eq!(line_span(code, 3), Some(Span { offset: 19, len: 1 }));
+        assert_eq!(line_span(code, 0), None);
+        assert_eq!(line_span(code, 9), None);
+    }
+}
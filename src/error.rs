@@ -1,10 +1,16 @@
-use std::error;
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use core::error;
+use core::fmt;
+use hashbrown::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct Error<T> {
     pub kind: T,
-    file: Option<std::path::PathBuf>,
+    file: Option<String>,
     snippet: Option<Snippet>,
     contexts: Vec<&'static str>,
 }
@@ -19,14 +25,15 @@ impl Span {
     pub const fn new(offset: usize) -> Self {
         Self { offset, len: 1 }
     }
-    pub const fn as_range(&self) -> std::ops::Range<usize> {
+    pub const fn as_range(&self) -> core::ops::Range<usize> {
         self.offset..self.offset + self.len
     }
     pub fn snippet_from_source(&self, source: &SourceMetadata) -> Option<Snippet> {
         let mut offset = 0;
+        let mut last = None;
         for (i, line) in source.input().split_terminator('\n').enumerate() {
             let next_offset = offset + line.len() + 1;
-            if next_offset >= self.offset {
+            if next_offset > self.offset {
                 // offset is somewhere in the current line
                 return Some(Snippet {
                     position: Position {
@@ -36,16 +43,28 @@ impl Span {
                     line: line.to_string(),
                 });
             }
+            last = Some((i, line, offset));
             offset = next_offset;
         }
-        None
+        // the end of the input is shown past the end of its last line
+        let (i, line, start) = last.filter(|_| self.offset == offset)?;
+        Some(Snippet {
+            position: Position {
+                line: i,
+                col: self.offset - start,
+            },
+            line: line.to_string(),
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct SourceMetadata<'a> {
-    file: Option<std::path::PathBuf>,
+    /// the path of the file, as it's shown in diagnostics
+    file: Option<String>,
     source: &'a str,
+    /// Where each part of the source came from, if files were included into it
+    includes: Option<&'a SourceMap>,
 }
 
 impl<'a> SourceMetadata<'a> {
@@ -53,13 +72,87 @@ impl<'a> SourceMetadata<'a> {
         self.source
     }
     pub const fn new(source: &'a str) -> Self {
-        Self { file: None, source }
+        Self {
+            file: None,
+            source,
+            includes: None,
+        }
+    }
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
     }
     #[must_use]
-    pub fn with_file(mut self, file: std::path::PathBuf) -> Self {
+    pub fn with_file(mut self, file: String) -> Self {
         self.file = Some(file);
         self
     }
+    /// The source was put together from several files, and the map says where each part of it
+    /// came from
+    #[must_use]
+    pub const fn with_includes(mut self, includes: &'a SourceMap) -> Self {
+        self.includes = Some(includes);
+        self
+    }
+    /// The file a span points into, and where in the file
+    pub fn locate(&self, span: Span) -> (Option<String>, Option<Snippet>) {
+        match self.includes.and_then(|includes| includes.locate(span)) {
+            Some((file, source, span)) => (
+                Some(file.to_string()),
+                span.snippet_from_source(&SourceMetadata::new(source)),
+            ),
+            None => (self.file.clone(), span.snippet_from_source(self)),
+        }
+    }
+}
+
+/// Where each part of a source put together from several files came from. The parts are
+/// contiguous: each one goes on until the next one starts.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// The paths of the files, along with their contents
+    files: Vec<(String, String)>,
+    /// Where each part starts in the whole source, and the file and offset in it it comes from
+    parts: Vec<(usize, usize, usize)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a file to the map, returning its index
+    pub fn add_file(&mut self, file: String, contents: String) -> usize {
+        self.files.push((file, contents));
+        self.files.len() - 1
+    }
+    pub fn file_contents(&self, index: usize) -> &str {
+        &self.files[index].1
+    }
+    pub fn file_path(&self, index: usize) -> &str {
+        &self.files[index].0
+    }
+    /// Start a new part at `offset` of the whole source, which comes from `file_offset` of a
+    /// file. The parts have to be added in order.
+    pub fn start_part(&mut self, offset: usize, file: usize, file_offset: usize) {
+        debug_assert!(self.parts.last().is_none_or(|(start, ..)| *start <= offset));
+        self.parts.push((offset, file, file_offset));
+    }
+    /// The file a span of the whole source points into, its contents and the span within it
+    pub fn locate(&self, span: Span) -> Option<(&str, &str, Span)> {
+        let part = self
+            .parts
+            .partition_point(|(start, ..)| *start <= span.offset)
+            .checked_sub(1)?;
+        let (start, file, file_offset) = self.parts[part];
+        let (path, contents) = &self.files[file];
+        Some((
+            path,
+            contents,
+            Span {
+                offset: file_offset + span.offset - start,
+                len: span.len,
+            },
+        ))
+    }
 }
 
 impl<T> Error<T> {
@@ -93,8 +186,7 @@ impl<T> Error<T> {
     }
     #[must_use]
     pub fn with_source(mut self, span: Span, source: &SourceMetadata) -> Self {
-        self.file = source.file.clone();
-        self.snippet = span.snippet_from_source(source);
+        (self.file, self.snippet) = source.locate(span);
         self
     }
     #[must_use]
@@ -104,12 +196,497 @@ impl<T> Error<T> {
     }
 }
 
+/// A diagnostic that doesn't stop compilation. It carries the same location info as an [`Error`]
+/// and is displayed the same way, just marked as a warning. Notes can point at other related
+/// places in the source.
+#[derive(Debug, Clone)]
+pub struct Warning<T> {
+    inner: Error<T>,
+    span: Option<Span>,
+    notes: Vec<Error<&'static str>>,
+}
+
+impl<T> Warning<T> {
+    pub const fn new(kind: T) -> Self {
+        Self {
+            inner: Error::new(kind),
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+    pub const fn kind(&self) -> &T {
+        &self.inner.kind
+    }
+    /// Where the warning points to, if it has been given a source location
+    pub const fn span(&self) -> Option<Span> {
+        self.span
+    }
+    #[must_use]
+    pub fn with_source(mut self, span: Span, source: &SourceMetadata) -> Self {
+        self.inner = self.inner.with_source(span, source);
+        self.span = Some(span);
+        self
+    }
+    #[must_use]
+    pub fn with_note(mut self, note: &'static str, span: Span, source: &SourceMetadata) -> Self {
+        self.notes.push(Error::new(note).with_source(span, source));
+        self
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Warning<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {}", self.inner)?;
+        for note in &self.notes {
+            write!(f, "\nnote: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// An error or a warning, already rendered, with what's needed to sort, filter and serialize it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The message of the diagnostic, without the location info
+    pub message: String,
+    pub file: Option<String>,
+    pub position: Option<Position>,
+    /// The `-W` flag that controls a warning
+    pub flag: Option<&'static str>,
+    rendered: String,
+}
+
+impl Diagnostic {
+    pub fn error<T: fmt::Display>(error: &Error<T>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: error.kind.to_string(),
+            file: error.file.clone(),
+            position: error.snippet.as_ref().map(|snippet| snippet.position),
+            flag: None,
+            rendered: error.to_string(),
+        }
+    }
+    pub fn warning<T: fmt::Display>(warning: &Warning<T>, flag: &'static str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            flag: Some(flag),
+            rendered: warning.to_string(),
+            ..Self::error(&warning.inner)
+        }
+    }
+    /// Diagnostics are sorted by file and then by position, and the ones without location info
+    /// go after the rest
+    fn sort_key(&self) -> impl Ord + '_ {
+        (
+            self.file.is_none(),
+            self.file.as_deref(),
+            self.position.is_none(),
+            self.position.map(|position| (position.line, position.col)),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.rendered)
+    }
+}
+
+/// How many errors are shown by default, like `-ferror-limit` in clang. The first error often
+/// causes the following ones, so it's the only one sure to be worth reading.
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+/// All the errors and warnings of a compilation, sorted by where they point to. The same
+/// diagnostic reported twice for the same place (like from each expansion of a macro) is only
+/// kept once, and only the first errors up to the limit are shown.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+    error_limit: Option<usize>,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            error_limit: Some(DEFAULT_ERROR_LIMIT),
+        }
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Show at most `limit` errors, or all of them with `None`
+    #[must_use]
+    pub fn with_error_limit(mut self, limit: Option<usize>) -> Self {
+        self.error_limit = limit;
+        self
+    }
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if self.entries.contains(&diagnostic) {
+            return;
+        }
+        // after the ones in the same place, to keep the order they were reported in
+        let index = self
+            .entries
+            .partition_point(|entry| entry.sort_key() <= diagnostic.sort_key());
+        self.entries.insert(index, diagnostic);
+    }
+    pub fn push_error<T: fmt::Display>(&mut self, error: &Error<T>) {
+        self.push(Diagnostic::error(error));
+    }
+    pub fn push_warning<T: fmt::Display>(&mut self, warning: &Warning<T>, flag: &'static str) {
+        self.push(Diagnostic::warning(warning, flag));
+    }
+    pub fn error_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.severity == Severity::Error)
+            .count()
+    }
+    pub fn has_errors(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.severity == Severity::Error)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// How many errors are left out because they are over the limit
+    pub fn suppressed_errors(&self) -> usize {
+        self.error_limit
+            .map_or(0, |limit| self.error_count().saturating_sub(limit))
+    }
+    /// The diagnostics in order, leaving out the errors over the limit
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        let mut errors = 0;
+        let limit = self.error_limit.unwrap_or(usize::MAX);
+        self.entries.iter().filter(move |entry| {
+            if entry.severity == Severity::Error {
+                errors += 1;
+                errors <= limit
+            } else {
+                true
+            }
+        })
+    }
+    /// The shown diagnostics as a JSON array, for tools to read
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (index, diagnostic) in self.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!(
+                "{{\"severity\":\"{}\",\"message\":{}",
+                diagnostic.severity,
+                json_string(&diagnostic.message)
+            ));
+            if let Some(file) = &diagnostic.file {
+                json.push_str(",\"file\":");
+                json.push_str(&json_string(file));
+            }
+            if let Some(position) = diagnostic.position {
+                json.push_str(&format!(
+                    ",\"line\":{},\"column\":{}",
+                    position.line + 1,
+                    position.col + 1
+                ));
+            }
+            if let Some(flag) = diagnostic.flag {
+                json.push_str(&format!(",\"flag\":\"-W{}\"", flag));
+            }
+            json.push('}');
+        }
+        json.push(']');
+        json
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = Box<dyn Iterator<Item = &'a Diagnostic> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl Extend<Diagnostic> for Diagnostics {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        for diagnostic in iter {
+            self.push(diagnostic);
+        }
+    }
+}
+
+impl<T: fmt::Display> From<Error<T>> for Diagnostics {
+    fn from(error: Error<T>) -> Self {
+        let mut diagnostics = Self::new();
+        diagnostics.push_error(&error);
+        diagnostics
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, diagnostic) in self.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        match self.suppressed_errors() {
+            0 => Ok(()),
+            suppressed => write!(
+                f,
+                "\nnote: {} more error(s) not shown, `-fmax-errors=0` shows all of them",
+                suppressed
+            ),
+        }
+    }
+}
+
+impl error::Error for Diagnostics {}
+
+/// Which of the opt-in warnings are enabled. They are toggled from the command line with
+/// `-W<name>` and `-Wno-<name>`.
+#[derive(Debug, Clone, Copy)]
+pub struct WarningOptions {
+    /// `-Wconversion`: an implicit conversion may change the value converted
+    pub conversion: bool,
+    /// `-Wshadow`: a declaration hides another one from an outer scope
+    pub shadow: bool,
+    /// `-Wconstant-condition`: a (non-literal) condition always takes the same branch
+    pub constant_condition: bool,
+    /// `-Winfinite-recursion`: a function calls itself on every path, so it can never return
+    pub infinite_recursion: bool,
+    /// `-Wunused-function`: a `static` function is never called
+    pub unused_function: bool,
+    /// `-Wunused-parameter`: a parameter is never read
+    pub unused_parameter: bool,
+    /// `-Wimplicit-function-declaration`: a function is called before being declared (C89 only,
+    /// it's an error since C99)
+    pub implicit_function_declaration: bool,
+    /// `-Wuninitialized`: a variable is read before anything has been written to it
+    pub uninitialized: bool,
+    /// `-Wstrict-prototypes`: a function is declared with `()`, leaving its parameters unspecified
+    pub strict_prototypes: bool,
+    /// `-Wshift-count-overflow`: a value is shifted by a constant count of at least its width
+    pub shift_count_overflow: bool,
+    /// `-Wshift-count-negative`: a value is shifted by a negative constant count
+    pub shift_count_negative: bool,
+    /// `-Wparentheses`: an operand's grouping relies on a precedence that's often misread, like
+    /// `&&` within `||`
+    pub parentheses: bool,
+    /// `-Wmain`: `main` is declared with parameters other than `(void)` or `(int, char **)`
+    pub main: bool,
+    /// `-Wframe-larger-than=<bytes>`: the stack frame of a function is larger than the limit
+    pub frame_larger_than: Option<usize>,
+    /// `-Wspill-heavy`: the values of a statement take so many callee-saved registers that
+    /// saving them makes the frame grow
+    pub spill_heavy: bool,
+}
+
+impl Default for WarningOptions {
+    fn default() -> Self {
+        Self {
+            conversion: true,
+            shadow: false,
+            constant_condition: true,
+            infinite_recursion: true,
+            unused_function: false,
+            unused_parameter: false,
+            implicit_function_declaration: true,
+            uninitialized: true,
+            strict_prototypes: false,
+            shift_count_overflow: true,
+            shift_count_negative: true,
+            parentheses: true,
+            main: true,
+            frame_larger_than: None,
+            spill_heavy: false,
+        }
+    }
+}
+
+impl WarningOptions {
+    /// Apply a `-W` flag, without the `-W` itself. Returns `false` if the warning is unknown.
+    pub fn apply_flag(&mut self, flag: &str) -> bool {
+        if let Some(limit) = flag.strip_prefix("frame-larger-than=") {
+            return limit
+                .parse()
+                .map(|limit| self.frame_larger_than = Some(limit))
+                .is_ok();
+        }
+        if flag == "no-frame-larger-than" {
+            self.frame_larger_than = None;
+            return true;
+        }
+        let (name, enable) = match flag.strip_prefix("no-") {
+            Some(name) => (name, false),
+            None => (flag, true),
+        };
+        match self.flag_mut(name) {
+            Some(option) => {
+                *option = enable;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the warning with the given name (without the `-W`) is enabled. Warnings that can't
+    /// be turned off from the command line are always enabled.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        if name == "frame-larger-than" {
+            return self.frame_larger_than.is_some();
+        }
+        let mut options = *self;
+        match options.flag_mut(name) {
+            Some(option) => *option,
+            None => true,
+        }
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "conversion" => &mut self.conversion,
+            "shadow" => &mut self.shadow,
+            "constant-condition" => &mut self.constant_condition,
+            "infinite-recursion" => &mut self.infinite_recursion,
+            "unused-function" => &mut self.unused_function,
+            "unused-parameter" => &mut self.unused_parameter,
+            "implicit-function-declaration" => &mut self.implicit_function_declaration,
+            "uninitialized" => &mut self.uninitialized,
+            "strict-prototypes" => &mut self.strict_prototypes,
+            "shift-count-overflow" => &mut self.shift_count_overflow,
+            "shift-count-negative" => &mut self.shift_count_negative,
+            "parentheses" => &mut self.parentheses,
+            "main" => &mut self.main,
+            "spill-heavy" => &mut self.spill_heavy,
+            _ => return None,
+        })
+    }
+}
+
+/// A `#pragma GCC diagnostic` directive, which changes which warnings are reported from that
+/// point of the file onwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticPragma {
+    /// `push`: save the current state of the warnings
+    Push,
+    /// `pop`: go back to the state saved by the matching `push`
+    Pop,
+    /// `ignored "-W<flag>"` disables a warning, `warning "-W<flag>"` (or `error`) enables it
+    Set { flag: String, enable: bool },
+}
+
+/// Decides whether a warning is reported, given the command line options and the diagnostic
+/// pragmas that come before the warning's location.
+#[derive(Debug, Clone)]
+pub struct DiagnosticFilter {
+    options: WarningOptions,
+    /// The pragmas, sorted by their offset in the source
+    pragmas: Vec<(usize, DiagnosticPragma)>,
+}
+
+impl DiagnosticFilter {
+    pub const fn new(options: WarningOptions, pragmas: Vec<(usize, DiagnosticPragma)>) -> Self {
+        Self { options, pragmas }
+    }
+
+    /// Whether a warning with the given name, pointing at `offset`, should be reported
+    pub fn is_enabled(&self, flag: &str, offset: usize) -> bool {
+        let mut overrides: HashMap<&str, bool> = HashMap::new();
+        let mut saved = Vec::new();
+        for (_, pragma) in self.pragmas.iter().take_while(|(at, _)| *at < offset) {
+            match pragma {
+                DiagnosticPragma::Push => saved.push(overrides.clone()),
+                DiagnosticPragma::Pop => {
+                    // like GCC, an unmatched pop is ignored
+                    if let Some(previous) = saved.pop() {
+                        overrides = previous;
+                    }
+                }
+                DiagnosticPragma::Set { flag, enable } => {
+                    overrides.insert(flag, *enable);
+                }
+            }
+        }
+        overrides
+            .get(flag)
+            .copied()
+            .unwrap_or_else(|| self.options.is_enabled(flag))
+    }
+
+    /// The options to check the program with. Warnings enabled by some pragma have to be looked
+    /// for everywhere, and then get filtered by location with [`Self::is_enabled`].
+    pub fn check_options(&self) -> WarningOptions {
+        let mut options = self.options;
+        for (_, pragma) in &self.pragmas {
+            if let DiagnosticPragma::Set { flag, enable: true } = pragma {
+                if let Some(option) = options.flag_mut(flag) {
+                    *option = true;
+                }
+            }
+        }
+        options
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Snippet {
     position: Position,
     line: String,
 }
 
+impl Snippet {
+    pub const fn position(&self) -> Position {
+        self.position
+    }
+    /// The whole line the snippet is on
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+}
+
 #[derive(Debug)]
 pub enum WantedSpec<T> {
     Specific(T),
@@ -149,11 +726,7 @@ impl<T: fmt::Display> fmt::Display for Error<T> {
         } else {
             return write!(f, "{}(no location info){}", self.kind, whiles);
         };
-        let file = self
-            .file
-            .as_ref()
-            .and_then(|x| x.to_str())
-            .unwrap_or("<unknown source>");
+        let file = self.file.as_deref().unwrap_or("<unknown source>");
 
         write!(
             f,
@@ -174,3 +747,100 @@ impl<T: fmt::Display> fmt::Display for Error<T> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_are_sorted_deduplicated_and_capped() {
+        let source = "int a;\nint b;\nint c;\n";
+        let meta = SourceMetadata::new(source).with_file("test.c".into());
+        let error = |offset| Error::new("oops").with_source(Span::new(offset), &meta);
+        let mut diagnostics = Diagnostics::new().with_error_limit(Some(2));
+        diagnostics.push_error(&error(14));
+        diagnostics.push_warning(
+            &Warning::new("hmm").with_source(Span::new(7), &meta),
+            "test",
+        );
+        diagnostics.push_error(&error(0));
+        diagnostics.push_error(&error(14));
+        diagnostics.push_error(&error(7));
+        let lines: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let position = diagnostic.position.unwrap();
+                (diagnostic.severity, position.line)
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (Severity::Error, 0),
+                (Severity::Warning, 1),
+                (Severity::Error, 1)
+            ]
+        );
+        assert_eq!(diagnostics.suppressed_errors(), 1);
+        assert!(diagnostics.to_json().starts_with(
+            r#"[{"severity":"error","message":"oops","file":"test.c","line":1,"column":1},"#
+        ));
+    }
+
+    #[test]
+    fn every_warning_has_a_flag_that_turns_it_off_and_on() {
+        use crate::ast::{BinaryOp, LogicOp, Type};
+        use crate::codegen::CodegenWarning;
+        use crate::intermediate::generate::VarWarning;
+        let or = BinaryOp::Logic(LogicOp::Or);
+        let warnings = [
+            VarWarning::Narrowing {
+                from: Type::Long,
+                to: Type::Int,
+            },
+            VarWarning::Shadow("a".into()),
+            VarWarning::ConstantCondition(true),
+            VarWarning::InfiniteRecursion("f".into()),
+            VarWarning::UnusedFunction("f".into()),
+            VarWarning::UnusedParameter("a".into()),
+            VarWarning::Uninitialized("a".into()),
+            VarWarning::ImplicitDeclaration {
+                function: "f".into(),
+                arguments: 0,
+            },
+            VarWarning::StrictPrototypes("f".into()),
+            VarWarning::ShiftCountOverflow {
+                count: 32,
+                ty: Type::Int,
+            },
+            VarWarning::ShiftCountNegative(-1),
+            VarWarning::MainSignature,
+            VarWarning::Parentheses {
+                inner: or,
+                outer: or,
+                fix: "(a)".into(),
+            },
+        ]
+        .map(|warning| warning.flag());
+        let codegen_warnings = [
+            CodegenWarning::FrameLargerThan {
+                function: "f".into(),
+                size: 2,
+                limit: 1,
+            },
+            CodegenWarning::SpillHeavy { registers: 1 },
+        ]
+        .map(|warning| warning.flag());
+        for flag in warnings.into_iter().chain(codegen_warnings) {
+            let mut options = WarningOptions::default();
+            assert!(options.apply_flag(&format!("no-{flag}")), "-Wno-{flag}");
+            assert!(!options.is_enabled(flag), "-Wno-{flag}");
+            let on = match flag {
+                "frame-larger-than" => format!("{flag}=1"),
+                _ => flag.to_string(),
+            };
+            assert!(options.apply_flag(&on), "-W{on}");
+            assert!(options.is_enabled(flag), "-W{on}");
+        }
+    }
+}
@@ -1,5 +1,10 @@
+use alloc::vec::Vec;
+
 use super::{lexer::TokenKind, Parse, ParseRes, Parser};
-use crate::{ast::Block, error::Span};
+use crate::{
+    ast::{Block, Statement},
+    error::Span,
+};
 
 impl<'source> Parse<'source> for (Block<'source>, Span) {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
@@ -8,11 +13,7 @@ impl<'source> Parse<'source> for (Block<'source>, Span) {
             let start = parser.current_position();
             parser.accept_current();
 
-            let mut statements = Vec::new();
-
-            while parser.peek_token()? != Some(TokenKind::CloseBrace) {
-                statements.push(parser.parse()?);
-            }
+            let statements = statements(parser)?;
 
             parser.expect_token(TokenKind::CloseBrace)?;
             let end = parser.current_position();
@@ -34,11 +35,7 @@ impl<'source> Parse<'source> for Block<'source> {
             parser.expect_token(TokenKind::OpenBrace)?;
             parser.accept_current();
 
-            let mut statements = Vec::new();
-
-            while parser.peek_token()? != Some(TokenKind::CloseBrace) {
-                statements.push(parser.parse()?);
-            }
+            let statements = statements(parser)?;
 
             parser.expect_token(TokenKind::CloseBrace)?;
             parser.accept_current();
@@ -47,3 +44,20 @@ impl<'source> Parse<'source> for Block<'source> {
         })
     }
 }
+
+/// The statements of a block, up to its closing brace. The enumerators of the enums a statement
+/// defines are declared right before it.
+fn statements<'source>(parser: &mut Parser<'source>) -> ParseRes<Vec<(Statement<'source>, Span)>> {
+    let mut statements = Vec::new();
+    while parser.peek_token()? != Some(TokenKind::CloseBrace) {
+        let statement = parser.parse()?;
+        statements.extend(
+            parser
+                .take_enumerators()
+                .into_iter()
+                .map(|(enumerators, span)| (Statement::DeclareEnum(enumerators), span)),
+        );
+        statements.push(statement);
+    }
+    Ok(statements)
+}
@@ -1,24 +1,47 @@
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
 use super::lexer::Source;
-use super::{lexer::TokenKind, Parse, ParseErrorKind, ParseRes, Parser, WantedSpec};
+use super::{lexer, lexer::TokenKind, Parse, ParseErrorKind, ParseRes, Parser, WantedSpec};
 use crate::ast::Associativity;
 use crate::ast::BinaryOp;
 use crate::ast::Expr;
+use crate::ast::FloatType;
+use crate::ast::Type;
 use crate::ast::UnaryOp;
 use crate::error::Span;
 
 impl<'source> Parse<'source> for (Expr<'source>, Span) {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
-        parse_primary(parser)
-            .and_then(|lhs| {
-                parse_binary_expression(parser, lhs, 0)
-                    .map_err(|e| e.add_context("parsing binary expression"))
-            })
-            .map_err(|x| x.add_context("parsing expression"))
+        expression(parser).map(|(expr, _)| expr)
     }
 }
 
+/// An expression along with its depth: 1 if it has no operands, or else one more than its
+/// deepest operand. The later stages recurse that deep into it, except along the left operands
+/// of a [chain](BinaryOp::chains), so a flat one like `a + b + c` isn't any deeper than `a + b`.
+type Nested<'source> = ((Expr<'source>, Span), usize);
+
+/// The arguments of a call
+type Arguments<'source> = Vec<(Expr<'source>, Span)>;
+
+fn expression<'source>(parser: &mut Parser<'source>) -> ParseRes<Nested<'source>> {
+    parse_primary(parser)
+        .and_then(|lhs| {
+            parse_binary_expression(parser, lhs, 0)
+                .map_err(|e| e.add_context("parsing binary expression"))
+        })
+        .map_err(|x| x.add_context("parsing expression"))
+}
+
+/// Parse an expression that's an operand of the one being parsed
+fn operand<'source>(parser: &mut Parser<'source>) -> ParseRes<Nested<'source>> {
+    parser.nested(expression)
+}
+
 // parse prefix, simple or parenthesis
-fn parse_primary<'source>(parser: &mut Parser<'source>) -> ParseRes<(Expr<'source>, Span)> {
+fn parse_primary<'source>(parser: &mut Parser<'source>) -> ParseRes<Nested<'source>> {
     parser.with_context("parsing primary expression", |parser| {
         // collect all unary operators
         let ops: Vec<_> = {
@@ -33,46 +56,111 @@ fn parse_primary<'source>(parser: &mut Parser<'source>) -> ParseRes<(Expr<'sourc
             }
             Ok(vec)
         }?;
-        let mut expr = match parser.expect_a_token(Some(WantedSpec::Description("expression")))? {
-            TokenKind::OpenParen => {
-                let start = parser.current_position();
-                parser.accept_current();
-                let (e, _) = parser.parse()?;
-                parser
-                    .expect_token(TokenKind::CloseParen)
-                    .map_err(|x| x.add_context("as the end of the expression"))?;
-                let end = parser.current_position();
-                parser.accept_current();
-                Ok((
-                    e,
-                    Span {
-                        offset: start,
-                        len: end - start,
-                    },
-                ))
-            }
-            TokenKind::Number => {
-                let num = parser.current_token_source().parse().unwrap();
-                let span = parser.current_token_span();
-                parser.accept_current();
-                Ok((Expr::Constant(num), span))
-            }
-            TokenKind::Identifier => {
-                let source = parser.current_token_source();
-                let span = parser.current_token_span();
-                parser.accept_current();
-                Ok((
-                    Expr::Variable {
-                        name: Source { span, source },
-                    },
-                    span,
-                ))
-            }
-            tok => parser.reject_current_token(ParseErrorKind::Expected {
-                found: tok,
-                wanted: WantedSpec::Description("open paren, identifier or number"),
-            }),
-        }?;
+        let (mut expr, mut depth) =
+            match parser.expect_a_token(Some(WantedSpec::Description("expression")))? {
+                TokenKind::OpenParen => {
+                    let start = parser.current_position();
+                    parser.accept_current();
+                    match parser.peek_token()? {
+                        Some(TokenKind::Identifier)
+                            if Type::is_type_keyword(parser.current_token_source()) =>
+                        {
+                            cast(parser, start)
+                        }
+                        _ => {
+                            let ((e, e_span), depth) = operand(parser)?;
+                            parser
+                                .expect_token(TokenKind::CloseParen)
+                                .map_err(|x| x.add_context("as the end of the expression"))?;
+                            let end = parser.current_position() + 1;
+                            parser.accept_current();
+                            Ok((
+                                (
+                                    Expr::Parenthesized {
+                                        expr: (Box::new(e), e_span),
+                                    },
+                                    Span {
+                                        offset: start,
+                                        len: end - start,
+                                    },
+                                ),
+                                depth + 1,
+                            ))
+                        }
+                    }
+                }
+                TokenKind::Number => {
+                    let source = parser.current_token_source();
+                    let Some(constant) = integer_constant(source) else {
+                        let digits = source.trim_end_matches(['u', 'U', 'l', 'L']);
+                        return parser.reject_current_token(ParseErrorKind::ConstantTooLarge(
+                            digits.to_string(),
+                        ));
+                    };
+                    let span = parser.current_token_span();
+                    parser.accept_current();
+                    Ok(((constant, span), 1))
+                }
+                TokenKind::StringLiteral => {
+                    let bytes = unquote(parser.current_token_source());
+                    let span = parser.current_token_span();
+                    parser.accept_current();
+                    Ok(((Expr::StringLiteral(bytes), span), 1))
+                }
+                // a character constant is an `int`
+                TokenKind::CharLiteral => {
+                    let bytes = unquote(parser.current_token_source());
+                    let span = parser.current_token_span();
+                    parser.accept_current();
+                    Ok(((Expr::Constant(bytes[0].into()), span), 1))
+                }
+                TokenKind::FloatNumber => {
+                    let expr = float_constant(parser.current_token_source());
+                    let span = parser.current_token_span();
+                    parser.accept_current();
+                    Ok(((expr, span), 1))
+                }
+                TokenKind::Identifier => {
+                    let source = parser.current_token_source();
+                    let span = parser.current_token_span();
+                    parser.accept_current();
+                    if matches!(source, "_Alignof" | "alignof") {
+                        align_of(parser, span.offset).map(|expr| (expr, 1))
+                    } else if let Some(TokenKind::OpenParen) = parser.peek_token()? {
+                        call(parser, Source { span, source })
+                    } else {
+                        Ok((
+                            (
+                                Expr::Variable {
+                                    name: Source { span, source },
+                                },
+                                span,
+                            ),
+                            1,
+                        ))
+                    }
+                }
+                tok => parser.reject_current_token(ParseErrorKind::Expected {
+                    found: tok,
+                    wanted: WantedSpec::Description(
+                        "open paren, identifier, number, character or string",
+                    ),
+                }),
+            }?;
+        // postfix operators bind tighter than the prefix ones
+        loop {
+            (expr, depth) = match parser.peek_token()? {
+                Some(TokenKind::OpenBracket) => index(parser, (expr, depth))?,
+                Some(TokenKind::Dot) => (member(parser, expr, false)?, depth + 1),
+                Some(TokenKind::Arrow) => (member(parser, expr, true)?, depth + 1),
+                Some(TokenKind::OpenParen) => call_pointer(parser, (expr, depth))?,
+                _ => break,
+            };
+            parser.check_depth(depth, expr.1)?;
+        }
+        let prefixed = ops.first().map_or(expr.1, |&(_, span)| span);
+        parser.check_depth(depth + ops.len(), prefixed)?;
+        depth += ops.len();
         for (operator, Span { offset, len }) in ops.into_iter().rev() {
             expr = (
                 Expr::Unary {
@@ -85,18 +173,380 @@ fn parse_primary<'source>(parser: &mut Parser<'source>) -> ParseRes<(Expr<'sourc
                 },
             );
         }
-        Ok(expr)
+        Ok((expr, depth))
+    })
+}
+
+// the open paren has already been accepted. A cast binds as tight as unary operators do.
+fn cast<'source>(parser: &mut Parser<'source>, start: usize) -> ParseRes<Nested<'source>> {
+    parser.with_context("parsing cast", |parser| {
+        let (target, _) = super::types::type_or_void(parser)?;
+        let (target, _) = super::types::declarator(parser, target, false, false)?;
+        parser.expect_token(TokenKind::CloseParen)?;
+        parser.accept_current();
+        let ((expr, expr_span), depth) = parser.nested(parse_primary)?;
+        Ok((
+            (
+                Expr::Cast {
+                    target,
+                    expr: (Box::new(expr), expr_span),
+                },
+                Span {
+                    offset: start,
+                    len: expr_span.offset + expr_span.len - start,
+                },
+            ),
+            depth + 1,
+        ))
+    })
+}
+
+/// The bytes of a string or character literal, which the lexer already checked
+fn unquote(quoted: &str) -> Vec<u8> {
+    lexer::decode_escapes(&quoted[1..quoted.len() - 1])
+        .expect("the lexer only lets valid escape sequences through")
+}
+
+/// The value of an integer constant without its suffix, or `None` if it doesn't fit in a `long`.
+/// The lexer only lets the digits of its base through, so that's the only way to fail.
+pub(super) fn integer_value(digits: &str) -> Option<u64> {
+    let (digits, radix) = integer_radix(digits);
+    u64::from_str_radix(digits, radix).ok()
+}
+
+fn integer_radix(digits: &str) -> (&str, u32) {
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if let Some(binary) = digits
+        .strip_prefix("0b")
+        .or_else(|| digits.strip_prefix("0B"))
+    {
+        (binary, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits, 10)
+    }
+}
+
+/// An integer constant, in the first type its value fits in among the ones its suffix allows.
+/// Decimal constants are only unsigned with a `u` suffix, while the others can also be unsigned
+/// when they are too big for the signed type.
+fn integer_constant(source: &str) -> Option<Expr<'static>> {
+    let digits = source.trim_end_matches(['u', 'U', 'l', 'L']);
+    let suffix = &source[digits.len()..];
+    let unsigned = suffix.contains(['u', 'U']);
+    let long = suffix.contains(['l', 'L']);
+    let decimal = integer_radix(digits).1 == 10;
+    let value = integer_value(digits)?;
+    let int = i32::try_from(value).ok().filter(|_| !unsigned && !long);
+    let unsigned_int = u32::try_from(value)
+        .ok()
+        .filter(|_| (unsigned || !decimal) && !long);
+    let long_value = i64::try_from(value).ok().filter(|_| !unsigned);
+    let unsigned_long = Some(value).filter(|_| unsigned || !decimal);
+    int.map(Expr::Constant)
+        .or_else(|| unsigned_int.map(Expr::UnsignedConstant))
+        .or_else(|| long_value.map(Expr::LongConstant))
+        .or_else(|| unsigned_long.map(Expr::UnsignedLongConstant))
+}
+
+/// The value of a floating constant, rounded to nearest (ties to even) in the type its suffix gives
+/// it
+fn float_constant(source: &str) -> Expr<'static> {
+    let (digits, ty) = match source.as_bytes()[source.len() - 1] {
+        b'f' | b'F' => (&source[..source.len() - 1], FloatType::Float),
+        b'l' | b'L' => (&source[..source.len() - 1], FloatType::LongDouble),
+        _ => (source, FloatType::Double),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => {
+            let (mantissa, exponent) = hex
+                .split_once(['p', 'P'])
+                .expect("the lexer requires an exponent");
+            match ty {
+                // too big for a `float` becomes infinity here
+                FloatType::Float => {
+                    f64::from(
+                        hex_float(mantissa, exponent, f32::MANTISSA_DIGITS, f32::MIN_EXP - 1)
+                            as f32,
+                    )
+                }
+                FloatType::Double | FloatType::LongDouble => {
+                    hex_float(mantissa, exponent, f64::MANTISSA_DIGITS, f64::MIN_EXP - 1)
+                }
+            }
+        }
+        None => match ty {
+            FloatType::Float => digits.parse::<f32>().map(f64::from),
+            FloatType::Double | FloatType::LongDouble => digits.parse(),
+        }
+        .expect("the lexer only lets valid constants through"),
+    };
+    Expr::FloatConstant {
+        bits: value.to_bits(),
+        ty,
+    }
+}
+
+/// The value of the hexadecimal constant `0x<mantissa>p<exponent>`, rounded to nearest (ties to
+/// even) with `precision` significant bits, or fewer for subnormals below `2^min_exponent`. The
+/// rounded value is exact as an `f64`, unless it's too big for one.
+fn hex_float(mantissa: &str, exponent: &str, precision: u32, min_exponent: i32) -> f64 {
+    let (negative, magnitude) = match exponent.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, exponent.trim_start_matches('+')),
+    };
+    // anything this big over- or underflows all the same
+    let magnitude = magnitude
+        .bytes()
+        .fold(0i64, |acc, digit| {
+            acc.saturating_mul(10).saturating_add((digit - b'0').into())
+        })
+        .min(1 << 20);
+    let mut exponent = if negative { -magnitude } else { magnitude };
+    // digits are gathered while they fit, and of the rest only whether they are all zero matters
+    let mut significand = 0u64;
+    let mut sticky = false;
+    let mut after_point = false;
+    for ch in mantissa.chars() {
+        if ch == '.' {
+            after_point = true;
+            continue;
+        }
+        let digit = ch
+            .to_digit(16)
+            .expect("the lexer only lets hex digits through");
+        if significand >> 60 == 0 {
+            significand = significand << 4 | u64::from(digit);
+            if after_point {
+                exponent -= 4;
+            }
+        } else {
+            sticky |= digit != 0;
+            if !after_point {
+                exponent += 4;
+            }
+        }
+    }
+    if significand == 0 {
+        return 0.0;
+    }
+    // the lowest bit kept is `precision` bits below the highest one, but never below the lowest
+    // bit of the smallest subnormal
+    let highest = exponent + i64::from(63 - significand.leading_zeros());
+    let lowest = (highest - i64::from(precision) + 1)
+        .max(i64::from(min_exponent) - i64::from(precision) + 1);
+    let dropped = lowest - exponent;
+    let kept = if dropped <= 0 {
+        return scale(significand, exponent);
+    } else if dropped > 64 {
+        // less than half of the lowest bit
+        0
+    } else {
+        let significand = u128::from(significand);
+        let kept = significand >> dropped;
+        let rest = significand & ((1 << dropped) - 1);
+        let half = 1 << (dropped - 1);
+        if rest > half || (rest == half && (sticky || kept & 1 == 1)) {
+            kept + 1
+        } else {
+            kept
+        }
+    };
+    scale(kept as u64, lowest)
+}
+
+/// `significand * 2^exponent`, which is exact as long as it's representable
+fn scale(significand: u64, exponent: i64) -> f64 {
+    // the significand has been rounded to at most 53 bits
+    let mut value = significand as f64;
+    let mut exponent = exponent;
+    // each step is a power of two that is a normal value, and keeps the value normal until the
+    // last one
+    while exponent != 0 {
+        let step = exponent.clamp(-1000, 1000);
+        value *= f64::from_bits(((1023 + step) as u64) << 52);
+        exponent -= step;
+    }
+    value
+}
+
+// the keyword has already been accepted
+fn align_of<'source>(
+    parser: &mut Parser<'source>,
+    start: usize,
+) -> ParseRes<(Expr<'source>, Span)> {
+    parser.with_context("parsing `_Alignof`", |parser| {
+        parser.expect_token(TokenKind::OpenParen)?;
+        parser.accept_current();
+        let (ty, _): (Type, Span) = parser.parse()?;
+        parser.expect_token(TokenKind::CloseParen)?;
+        let end = parser.current_position() + 1;
+        parser.accept_current();
+        Ok((
+            Expr::AlignOf(ty),
+            Span {
+                offset: start,
+                len: end - start,
+            },
+        ))
+    })
+}
+
+// the open paren is the current token
+fn call<'source>(
+    parser: &mut Parser<'source>,
+    function: Source<'source>,
+) -> ParseRes<Nested<'source>> {
+    let (args, end, depth) = arguments(parser)?;
+    Ok((
+        (
+            Expr::Call { function, args },
+            Span {
+                offset: function.span.offset,
+                len: end - function.span.offset,
+            },
+        ),
+        depth + 1,
+    ))
+}
+
+// the open paren is the current token
+fn call_pointer<'source>(
+    parser: &mut Parser<'source>,
+    ((pointer, pointer_span), pointer_depth): Nested<'source>,
+) -> ParseRes<Nested<'source>> {
+    let (args, end, depth) = arguments(parser)?;
+    Ok((
+        (
+            Expr::CallPointer {
+                pointer: (Box::new(pointer), pointer_span),
+                args,
+            },
+            Span {
+                offset: pointer_span.offset,
+                len: end - pointer_span.offset,
+            },
+        ),
+        depth.max(pointer_depth) + 1,
+    ))
+}
+
+/// The arguments of a call, whose open paren is the current token, where they end and the depth
+/// of the deepest one
+fn arguments<'source>(
+    parser: &mut Parser<'source>,
+) -> ParseRes<(Arguments<'source>, usize, usize)> {
+    parser.with_context("parsing function call", |parser| {
+        parser.accept_current();
+        let mut args = Vec::new();
+        let mut depth = 0;
+        if parser.peek_token()? != Some(TokenKind::CloseParen) {
+            loop {
+                let (arg, arg_depth) = operand(parser)?;
+                args.push(arg);
+                depth = depth.max(arg_depth);
+                match parser.expect_a_token(Some(WantedSpec::Description("`,` or `)`")))? {
+                    TokenKind::Comma => parser.accept_current(),
+                    TokenKind::CloseParen => break,
+                    found => {
+                        return parser.reject_current_token(ParseErrorKind::Expected {
+                            wanted: WantedSpec::Description("`,` or `)`"),
+                            found,
+                        })
+                    }
+                }
+            }
+        }
+        let end = parser.current_position() + 1;
+        parser.accept_current();
+        Ok((args, end, depth))
     })
 }
 
+// the open bracket is the current token
+fn index<'source>(
+    parser: &mut Parser<'source>,
+    ((array, array_span), array_depth): Nested<'source>,
+) -> ParseRes<Nested<'source>> {
+    let (((index, index_span), index_depth), end) =
+        parser.with_context("parsing array subscript", |parser| {
+            parser.accept_current();
+            let index = operand(parser)?;
+            parser.expect_token(TokenKind::CloseBracket)?;
+            let end = parser.current_position() + 1;
+            parser.accept_current();
+            Ok((index, end))
+        })?;
+    Ok((
+        (
+            Expr::Index {
+                array: (Box::new(array), array_span),
+                index: (Box::new(index), index_span),
+            },
+            Span {
+                offset: array_span.offset,
+                len: end - array_span.offset,
+            },
+        ),
+        array_depth.max(index_depth) + 1,
+    ))
+}
+
+// the dot (or arrow, if `through_pointer`) is the current token
+fn member<'source>(
+    parser: &mut Parser<'source>,
+    (base, base_span): (Expr<'source>, Span),
+    through_pointer: bool,
+) -> ParseRes<(Expr<'source>, Span)> {
+    let member = parser.with_context("parsing member access", |parser| {
+        parser.accept_current();
+        parser.expect_token(TokenKind::Identifier)?;
+        let member = Source {
+            span: parser.current_token_span(),
+            source: parser.current_token_source(),
+        };
+        parser.accept_current();
+        Ok(member)
+    })?;
+    let base = (Box::new(base), base_span);
+    Ok((
+        if through_pointer {
+            Expr::Arrow {
+                pointer: base,
+                member,
+            }
+        } else {
+            Expr::Member { base, member }
+        },
+        Span {
+            offset: base_span.offset,
+            len: member.span.offset + member.span.len - base_span.offset,
+        },
+    ))
+}
+
 // Use a mid step to detect ternary operator
 #[derive(Clone, Copy, Debug)]
-enum DetectTernary {
+pub(super) enum DetectTernary {
     NormalOp(BinaryOp),
     Ternary,
 }
 
 impl DetectTernary {
+    const fn chains(self) -> bool {
+        match self {
+            Self::NormalOp(op) => op.chains(),
+            Self::Ternary => false,
+        }
+    }
     const fn associativity(self) -> Associativity {
         match self {
             Self::NormalOp(op) => op.associativity(),
@@ -119,18 +569,22 @@ impl DetectTernary {
         }
     }
 
-    // consume more tokens if needed since we accepted the operator
-    fn builder<'code>(self, parser: &mut Parser<'code>) -> ParseRes<BinExprBuilder<'code>> {
+    // consume more tokens if needed since we accepted the operator. Also gives the depth of the
+    // operands parsed along with it.
+    fn builder<'code>(
+        self,
+        parser: &mut Parser<'code>,
+    ) -> ParseRes<(BinExprBuilder<'code>, usize)> {
         parser.accept_current();
         match self {
-            Self::NormalOp(op) => Ok(BinExprBuilder::NormalOp(op)),
+            Self::NormalOp(op) => Ok((BinExprBuilder::NormalOp(op), 0)),
             Self::Ternary => {
-                let middle = parser.parse()?;
+                let (middle, depth) = operand(parser)?;
                 parser.expect_token(TokenKind::Colon).map_err(|err| {
                     err.add_context("`?` from ternaary operator needs a `:` for the `else` part")
                 })?;
                 parser.accept_current();
-                Ok(BinExprBuilder::Ternary { middle })
+                Ok((BinExprBuilder::Ternary { middle }, depth))
             }
         }
     }
@@ -196,42 +650,135 @@ impl<'code> BinExprBuilder<'code> {
 //     }
 // }
 
+/// The binary operator the current token is, if any. It's looked at again by every operator to
+/// its left that is still deciding where its operand ends, so the answer is kept until the token
+/// is accepted.
+fn peek_binary_operator(parser: &mut Parser) -> ParseRes<Option<DetectTernary>> {
+    if let Some(operator) = parser.current_operator {
+        return Ok(operator);
+    }
+    let operator = parser
+        .peek_token()?
+        .and_then(TokenKind::as_operator)
+        .and_then(DetectTernary::from_operator);
+    parser.current_operator = Some(operator);
+    Ok(operator)
+}
+
 fn parse_binary_expression<'source>(
     parser: &mut Parser<'source>,
-    mut lhs: (Expr<'source>, Span),
+    (mut lhs, mut depth): Nested<'source>,
     min_precedence: u8,
-) -> ParseRes<(Expr<'source>, Span)> {
-    while let Some(op) = parser
-        .peek_token()?
-        .and_then(TokenKind::as_operator)
-        .and_then(DetectTernary::from_operator)
-        .filter(|x| x.precedence() >= min_precedence)
+) -> ParseRes<Nested<'source>> {
+    while let Some(op) = peek_binary_operator(parser)?.filter(|x| x.precedence() >= min_precedence)
     {
         let this_precedence = op.precedence();
-        let builder = op.builder(parser)?;
+        let (builder, middle_depth) = op.builder(parser)?;
         let mut rhs = parse_primary(parser)?;
-        #[allow(clippy::blocks_in_if_conditions)]
-        // XXX: I don't know what clippy is trying to tell me; that closure is fine.
-        while parser
-            .peek_token()?
-            .and_then(TokenKind::as_operator)
-            .and_then(DetectTernary::from_operator)
-            .filter(move |op2| {
-                let other_precedence = op2.precedence();
-                match op2.associativity() {
-                    Associativity::RightToLeft => other_precedence >= this_precedence,
-                    Associativity::LeftToRight => other_precedence > this_precedence,
-                }
-            })
-            .is_some()
-        {
-            rhs = parse_binary_expression(parser, rhs, min_precedence + 1)?;
+        // an operator that binds tighter takes the operand first, and so does one as tight that
+        // groups to the right
+        while let Some(next) = peek_binary_operator(parser)?.filter(|next| {
+            next.precedence() > this_precedence
+                || next.precedence() == this_precedence
+                    && matches!(next.associativity(), Associativity::RightToLeft)
+        }) {
+            let tighter = next.precedence() > this_precedence;
+            let precedence = this_precedence + u8::from(tighter);
+            rhs = parser.nested(|parser| parse_binary_expression(parser, rhs, precedence))?;
         }
+        let (rhs, rhs_depth) = rhs;
         let span = Span {
             offset: lhs.1.offset,
             len: rhs.1.offset + rhs.1.len - lhs.1.offset,
         };
+        let operands_depth = middle_depth.max(rhs_depth) + 1;
+        depth = match &lhs.0 {
+            Expr::Binary { operator, .. } if op.chains() && operator.chains() => {
+                depth.max(operands_depth)
+            }
+            _ => (depth + 1).max(operands_depth),
+        };
+        parser.check_depth(depth, span)?;
         lhs = (builder.build(lhs, rhs), span);
     }
-    Ok(lhs)
+    Ok((lhs, depth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{float_constant, integer_value};
+    use crate::ast::{Expr, FloatType};
+    use crate::intermediate::generate::tests::returned;
+
+    #[test]
+    fn integer_values_are_read_in_their_base() {
+        assert_eq!(integer_value("0x1F"), Some(31));
+        assert_eq!(integer_value("0XfF"), Some(255));
+        assert_eq!(integer_value("0b101"), Some(5));
+        assert_eq!(integer_value("017"), Some(15));
+        assert_eq!(integer_value("0"), Some(0));
+        assert_eq!(integer_value("42"), Some(42));
+        assert_eq!(integer_value("0x7fffffffffffffff"), Some(i64::MAX as u64));
+        assert_eq!(integer_value("0xffffffffffffffff"), Some(u64::MAX));
+        // too big for an `unsigned long`
+        assert_eq!(integer_value("0x10000000000000000"), None);
+    }
+
+    #[test]
+    fn float_values_are_rounded_to_their_type() {
+        let value = |source| match float_constant(source) {
+            Expr::FloatConstant { bits, ty } => (f64::from_bits(bits), ty),
+            _ => unreachable!("a floating constant"),
+        };
+        assert_eq!(value("0x1.8p3"), (12.0, FloatType::Double));
+        assert_eq!(value("1e-3"), (0.001, FloatType::Double));
+        assert_eq!(value("0x1p-2f"), (0.25, FloatType::Float));
+        // the nearest `float` to a tenth isn't the nearest `double`
+        assert_eq!(value("0.1f"), (f64::from(0.1f32), FloatType::Float));
+        assert_eq!(value("1.5L"), (1.5, FloatType::LongDouble));
+    }
+
+    #[test]
+    fn operators_group_by_precedence_and_associativity() {
+        let cases = [
+            ("10 - 2 * 3 + 4", 8),
+            ("100 / 5 * 2 - 3 * 2 + 1", 35),
+            ("1 - 2 - 3", -4),
+            ("64 >> 1 + 1 >> 2", 4),
+            ("0 ? 1 : 0 ? 2 : 3", 3),
+        ];
+        for (expr, expected) in cases {
+            let source = alloc::format!("int main() {{ return {expr}; }}");
+            assert_eq!(returned(&source), expected, "{expr}");
+        }
+    }
+
+    #[test]
+    fn integer_constants_take_the_type_of_their_suffix_and_value() {
+        let cases = [
+            ("return 3u + 4U;", 7),
+            // `u` makes them unsigned, so -1 becomes the biggest `unsigned int` next to them
+            ("return -1u > 0;", 1),
+            ("return -1 < 0u;", 0),
+            ("return 4294967295u == -1;", 1),
+            ("return (0u - 1) >> 31;", 1),
+            // a decimal constant too big for an `int` is a `long`, but a hexadecimal one is an
+            // `unsigned int` if it fits in one
+            ("return 4294967295 > 0;", 1),
+            ("return 0xFFFFFFFF == -1;", 1),
+            ("return (1UL << 40) >> 40;", 1),
+            ("return 10lu / 3 + 7LLU % 4 + 5uLL;", 11),
+            ("return -1 < 0ul;", 0),
+            ("return -1l < 0u;", 1),
+            ("return 0xFFFFFFFFFFFFFFFF > 0;", 1),
+        ];
+        for (body, expected) in cases {
+            let source = alloc::format!("int main() {{ {body} }}");
+            assert_eq!(returned(&source), expected, "{body}");
+        }
+        for malformed in ["3uu", "3lul", "3lL", "18446744073709551616u"] {
+            let source = alloc::format!("int main() {{ return {malformed}; }}");
+            assert!(!crate::check_str(&source).is_empty(), "{malformed}");
+        }
+    }
 }
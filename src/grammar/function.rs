@@ -1,32 +1,291 @@
-use super::{lexer::TokenKind, Parse, ParseRes, Parser};
-use crate::ast::Function;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use super::{
+    lexer::{Source, TokenKind},
+    Parse, ParseErrorKind, ParseRes, Parser, WantedSpec,
+};
+use crate::ast::{Attribute, Expr, Function, Identifier, Linkage, Parameter, Type};
+use crate::error::Span;
 
 impl<'source> Parse<'source> for Function<'source> {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
         parser.with_context("parsing function", |parser| {
-            parser.keyword("int")?;
-            let (name, _) = parser.parse()?;
-            parser.expect_token(TokenKind::OpenParen)?;
-            parser.accept_current();
-            parser.expect_token(TokenKind::CloseParen)?;
-            parser.accept_current();
+            let specifiers = storage_specifiers(parser)?;
+            let (return_type, _) = super::types::type_or_void(parser)?;
+            let (Identifier(name), span) = parser.parse()?;
+            returning(
+                parser,
+                return_type,
+                Source { span, source: name },
+                specifiers,
+            )
+        })
+    }
+}
 
-            let body = parser.parse()?;
+/// What the specifiers before the type of a declaration at file scope say
+pub(super) struct StorageSpecifiers<'source> {
+    pub linkage: Linkage,
+    /// where `inline` is, if it's there
+    pub inline: Option<Span>,
+    /// whether `extern` is there, which makes a variable without an initializer only declared
+    pub is_extern: bool,
+    pub attributes: Vec<(Attribute<'source>, Span)>,
+    /// the `_Alignas(...)` specifiers, which only variables may have
+    pub align: Vec<(Expr<'source>, Span)>,
+}
 
-            Ok(Self { name, body })
+/// Parse the specifiers before the type of a declaration at file scope: `static`, `extern`,
+/// `inline` and `_Alignas(...)`, in any order, and the attributes before or after them
+pub(super) fn storage_specifiers<'source>(
+    parser: &mut Parser<'source>,
+) -> ParseRes<StorageSpecifiers<'source>> {
+    let mut specifiers = StorageSpecifiers {
+        linkage: Linkage::External,
+        inline: None,
+        is_extern: false,
+        attributes: attribute_specifiers(parser)?,
+        align: Vec::new(),
+    };
+    while let Some(TokenKind::Identifier) = parser.peek_token()? {
+        match parser.current_token_source() {
+            "static" => specifiers.linkage = Linkage::Internal,
+            "extern" => specifiers.is_extern = true,
+            "inline" | "__inline" | "__inline__" => {
+                specifiers.inline = Some(parser.current_token_span())
+            }
+            keyword if Type::is_alignment_keyword(keyword) => {
+                let align = super::statement::alignment_specifiers(parser)?;
+                specifiers.align.extend(align);
+                continue;
+            }
+            _ => break,
+        }
+        parser.accept_current();
+    }
+    specifiers.attributes.extend(attribute_specifiers(parser)?);
+    Ok(specifiers)
+}
 
-            // parser.expect_token(TokenKind::OpenBrace)?;
-            // parser.accept_current();
+/// Parse the rest of a function whose return type and name have already been parsed: its
+/// parameters and its body, if it isn't only declared. The linkage, whether it's `inline` and
+/// some of its attributes come from the specifiers before the return type.
+pub(super) fn returning<'source>(
+    parser: &mut Parser<'source>,
+    return_type: Option<Type>,
+    Source { span, source: name }: Source<'source>,
+    StorageSpecifiers {
+        linkage,
+        inline,
+        mut attributes,
+        align,
+        ..
+    }: StorageSpecifiers<'source>,
+) -> ParseRes<Function<'source>> {
+    if let Some(&(_, span)) = align.first() {
+        return parser.emit_error_at(span, ParseErrorKind::VariableOnly("_Alignas".into()));
+    }
+    parser.expect_token(TokenKind::OpenParen)?;
+    parser.accept_current();
+    let (params, params_unspecified, variadic) = parameters(parser, true)?;
+    let params = params
+        .into_iter()
+        .map(|(ty, name)| Parameter {
+            ty,
+            name: name.expect("the parameters of a function are named"),
+        })
+        .collect();
+    attributes.extend(attribute_specifiers(parser)?);
 
-            // let body = parser.iterate().collect();
+    let body = if let Some(TokenKind::Semicolon) = parser.peek_token()? {
+        parser.accept_current();
+        None
+    } else {
+        Some(parser.parse()?)
+    };
 
-            // // parser.keyword("return")?;
-            // // let return_expr = parser.parse()?;
-            // // parser.expect_token(TokenKind::Semicolon)?;
-            // parser.accept_current();
-            // parser.expect_token(TokenKind::CloseBrace)?;
-            // parser.accept_current();
-            // Ok(Self { name, body })
-        })
+    Ok(Function {
+        name: Source { span, source: name },
+        linkage,
+        inline: inline.is_some(),
+        return_type,
+        params,
+        params_unspecified,
+        variadic,
+        body,
+        attributes,
+    })
+}
+
+/// The parameters of a declarator, each with its name if it has one, and whether they were left
+/// unspecified and whether they end in `...`
+type Parameters<'source> = (Vec<(Type, Option<Source<'source>>)>, bool, bool);
+
+// the open paren has already been accepted. Consumes the closing paren. Also tells whether the
+// parameters were left unspecified, with an empty list, and whether they end in `...`. Only the
+// parameters of a function pointer may leave out their names, when they aren't `named`.
+pub(super) fn parameters<'source>(
+    parser: &mut Parser<'source>,
+    named: bool,
+) -> ParseRes<Parameters<'source>> {
+    parser.with_context("parsing parameters", |parser| {
+        let mut params = Vec::new();
+        if parser.expect_a_token(Some(WantedSpec::Description("parameters")))?
+            == TokenKind::CloseParen
+        {
+            parser.accept_current();
+            return Ok((params, true, false));
+        }
+        loop {
+            let (ty, _) = super::types::type_or_void(parser)?;
+            // `(void)` declares there are no parameters
+            if ty.is_none()
+                && params.is_empty()
+                && parser.peek_token()? == Some(TokenKind::CloseParen)
+            {
+                parser.accept_current();
+                return Ok((params, false, false));
+            }
+            // a parameter declared as an array is a pointer to its first element
+            let (ty, name) = match super::types::declarator(parser, ty, true, named)? {
+                (Type::Array { element, .. }, name) => (Type::Pointer(element), name),
+                param => param,
+            };
+            params.push((ty, name));
+            match parser.expect_a_token(Some(WantedSpec::Description("`,` or `)`")))? {
+                TokenKind::Comma => {
+                    parser.accept_current();
+                    if let Some(TokenKind::Ellipsis) = parser.peek_token()? {
+                        parser.accept_current();
+                        parser.expect_token(TokenKind::CloseParen)?;
+                        parser.accept_current();
+                        return Ok((params, false, true));
+                    }
+                }
+                TokenKind::CloseParen => {
+                    parser.accept_current();
+                    return Ok((params, false, false));
+                }
+                found => {
+                    return parser.reject_current_token(ParseErrorKind::Expected {
+                        wanted: WantedSpec::Description("`,` or `)`"),
+                        found,
+                    })
+                }
+            }
+        }
+    })
+}
+
+/// Parse any number of `__attribute__((...))`, each with a list of attributes
+fn attribute_specifiers<'source>(
+    parser: &mut Parser<'source>,
+) -> ParseRes<Vec<(Attribute<'source>, Span)>> {
+    let mut attributes = Vec::new();
+    while let Some(TokenKind::Identifier) = parser.peek_token()? {
+        if !matches!(
+            parser.current_token_source(),
+            "__attribute__" | "__attribute"
+        ) {
+            break;
+        }
+        parser.accept_current();
+        for _ in 0..2 {
+            parser.expect_token(TokenKind::OpenParen)?;
+            parser.accept_current();
+        }
+        loop {
+            parser.expect_token(TokenKind::Identifier)?;
+            let (name, span) = (parser.current_token_source(), parser.current_token_span());
+            parser.accept_current();
+            // every attribute can also be spelled between double underscores
+            let name = name
+                .strip_prefix("__")
+                .and_then(|name| name.strip_suffix("__"))
+                .unwrap_or(name);
+            let attribute = match name {
+                "weak" => Attribute::Weak,
+                "alias" => {
+                    parser.expect_token(TokenKind::OpenParen)?;
+                    parser.accept_current();
+                    parser.expect_token(TokenKind::StringLiteral)?;
+                    let (quoted, quoted_span) =
+                        (parser.current_token_source(), parser.current_token_span());
+                    parser.accept_current();
+                    parser.expect_token(TokenKind::CloseParen)?;
+                    parser.accept_current();
+                    Attribute::Alias(Source {
+                        span: Span {
+                            offset: quoted_span.offset + 1,
+                            len: quoted_span.len - 2,
+                        },
+                        source: &quoted[1..quoted.len() - 1],
+                    })
+                }
+                _ => {
+                    return parser
+                        .emit_error_at(span, ParseErrorKind::UnknownAttribute(name.to_string()))
+                }
+            };
+            attributes.push((attribute, span));
+            match parser.expect_a_token(Some(WantedSpec::Description("`,` or `)`")))? {
+                TokenKind::Comma => parser.accept_current(),
+                TokenKind::CloseParen => {
+                    parser.accept_current();
+                    break;
+                }
+                found => {
+                    return parser.reject_current_token(ParseErrorKind::Expected {
+                        wanted: WantedSpec::Description("`,` or `)`"),
+                        found,
+                    })
+                }
+            }
+        }
+        parser.expect_token(TokenKind::CloseParen)?;
+        parser.accept_current();
+    }
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SourceMetadata;
+    use alloc::boxed::Box;
+
+    #[test]
+    fn parameters_may_be_qualified() {
+        let source = "int printf(const char *fmt, ...);\n\
+                      int copy(char *restrict to, const char *const restrict from);\n\
+                      int poll(const volatile int *flag, volatile const int count);\n";
+        let meta = SourceMetadata::new(source);
+        let program: crate::ast::Program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the qualifiers are skipped");
+        let types: Vec<Vec<Type>> = program
+            .functions
+            .iter()
+            .map(|function| {
+                function
+                    .params
+                    .iter()
+                    .map(|param| param.ty.clone())
+                    .collect()
+            })
+            .collect();
+        let char_pointer = Type::Pointer(Box::new(Type::Char));
+        assert_eq!(types[0], core::slice::from_ref(&char_pointer));
+        assert!(program.functions[0].variadic);
+        assert_eq!(types[1], [char_pointer.clone(), char_pointer]);
+        // only `volatile` changes the type
+        assert_eq!(
+            types[2],
+            [
+                Type::Pointer(Box::new(Type::Int.volatile())),
+                Type::Int.volatile()
+            ]
+        );
     }
 }
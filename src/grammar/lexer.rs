@@ -1,6 +1,8 @@
+use alloc::vec::Vec;
+
 use crate::error::{self, SourceMetadata, Span, WantedSpec};
-use std::error::Error;
-use std::fmt;
+use core::error::Error;
+use core::fmt;
 
 impl Error for LexErrorKind {}
 
@@ -8,12 +10,21 @@ impl fmt::Display for TokenKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Colon => write!(f, "colon ':'"),
+            Self::Comma => write!(f, "comma ','"),
+            Self::Dot => write!(f, "dot '.'"),
+            Self::Ellipsis => write!(f, "ellipsis '...'"),
+            Self::Arrow => write!(f, "arrow '->'"),
             Self::CloseBrace => write!(f, "closing brace '}}'"),
             Self::OpenBrace => write!(f, "opening brace '{{'"),
             Self::Identifier => write!(f, "identifier"),
             Self::Number => write!(f, "number"),
+            Self::FloatNumber => write!(f, "floating constant"),
+            Self::StringLiteral => write!(f, "string literal"),
+            Self::CharLiteral => write!(f, "character constant"),
             Self::OpenParen => write!(f, "opening parentheses '('"),
             Self::CloseParen => write!(f, "closing parentheses ')'"),
+            Self::OpenBracket => write!(f, "opening bracket '['"),
+            Self::CloseBracket => write!(f, "closing bracket ']'"),
             Self::Semicolon => write!(f, "semicolon ';'"),
             Self::Whitespace => write!(f, "whitespace"),
             Self::Operator { kind, has_equal } => write!(
@@ -55,6 +66,15 @@ impl fmt::Display for LexErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::UnexpectedChar(ch) => write!(f, "unexpected {:?}", ch),
+            Self::UnterminatedString => write!(f, "missing terminating '\"' character"),
+            Self::UnterminatedChar => write!(f, "missing terminating ' character"),
+            Self::UnknownEscape(ch) => write!(f, "unknown escape sequence '\\{}'", ch),
+            Self::MalformedEscape(problem) => write!(f, "malformed escape sequence: {}", problem),
+            Self::MalformedChar(problem) => {
+                write!(f, "malformed character constant: {}", problem)
+            }
+            Self::MalformedFloat(problem) => write!(f, "malformed floating constant: {}", problem),
+            Self::MalformedNumber(problem) => write!(f, "malformed integer constant: {}", problem),
             Self::Expected { wanted, found } => {
                 write!(f, "unexpected {:?}\nexpectetd {}", found, wanted)
             }
@@ -83,7 +103,7 @@ impl<'a> Iterator for LexerIter<'a> {
     }
 }
 
-impl<'a> std::iter::FusedIterator for LexerIter<'a> {}
+impl<'a> core::iter::FusedIterator for LexerIter<'a> {}
 
 impl<'a> IntoIterator for Lexer<'a> {
     type IntoIter = LexerIter<'a>;
@@ -112,9 +132,26 @@ impl<'a> Token<'a> {
     pub const fn close_paren(source: Source<'a>) -> Self {
         Self::new(TokenKind::CloseParen, source)
     }
+    pub const fn open_bracket(source: Source<'a>) -> Self {
+        Self::new(TokenKind::OpenBracket, source)
+    }
+    pub const fn close_bracket(source: Source<'a>) -> Self {
+        Self::new(TokenKind::CloseBracket, source)
+    }
     pub const fn number(source: Source<'a>) -> Self {
         Self::new(TokenKind::Number, source)
     }
+    pub const fn float_number(source: Source<'a>) -> Self {
+        Self::new(TokenKind::FloatNumber, source)
+    }
+    /// the source includes the quotes
+    pub const fn string_literal(source: Source<'a>) -> Self {
+        Self::new(TokenKind::StringLiteral, source)
+    }
+    /// the source includes the quotes
+    pub const fn char_literal(source: Source<'a>) -> Self {
+        Self::new(TokenKind::CharLiteral, source)
+    }
     pub const fn operator(kind: Operator, has_equal: bool, source: Source<'a>) -> Self {
         Self::new(TokenKind::Operator { kind, has_equal }, source)
     }
@@ -127,6 +164,18 @@ impl<'a> Token<'a> {
     pub const fn colon(source: Source<'a>) -> Self {
         Self::new(TokenKind::Colon, source)
     }
+    pub const fn comma(source: Source<'a>) -> Self {
+        Self::new(TokenKind::Comma, source)
+    }
+    pub const fn dot(source: Source<'a>) -> Self {
+        Self::new(TokenKind::Dot, source)
+    }
+    pub const fn ellipsis(source: Source<'a>) -> Self {
+        Self::new(TokenKind::Ellipsis, source)
+    }
+    pub const fn arrow(source: Source<'a>) -> Self {
+        Self::new(TokenKind::Arrow, source)
+    }
     pub const fn close_brace(source: Source<'a>) -> Self {
         Self::new(TokenKind::CloseBrace, source)
     }
@@ -141,12 +190,27 @@ pub enum TokenKind {
     CloseBrace,
     OpenParen,
     CloseParen,
+    OpenBracket,
+    CloseBracket,
     Number,
+    /// a constant with a fraction or an exponent, possibly hexadecimal, like `1.5e-3` or `0x1.8p3`
+    FloatNumber,
+    StringLiteral,
+    /// a single (maybe escaped) character between single quotes, like `'a'` or `'\n'`
+    CharLiteral,
     Identifier,
     Semicolon,
     Whitespace,
     Colon,
-    Operator { kind: Operator, has_equal: bool },
+    Comma,
+    Dot,
+    /// `...`, ending the parameters of a variadic function
+    Ellipsis,
+    Arrow,
+    Operator {
+        kind: Operator,
+        has_equal: bool,
+    },
 }
 
 impl TokenKind {
@@ -195,7 +259,7 @@ impl From<Source<'_>> for Span {
 }
 
 pub struct Lexer<'a> {
-    input: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: core::iter::Peekable<core::str::CharIndices<'a>>,
     metadata: &'a SourceMetadata<'a>,
 }
 
@@ -206,9 +270,86 @@ pub enum LexErrorKind {
         found: char,
     },
     UnexpectedChar(char),
+    UnterminatedString,
+    UnterminatedChar,
+    UnknownEscape(char),
+    MalformedEscape(&'static str),
+    MalformedChar(&'static str),
+    MalformedFloat(&'static str),
+    MalformedNumber(&'static str),
     // TODO
 }
 
+/// The bytes of the contents of a string or character literal, with its escape sequences decoded.
+/// An invalid escape sequence gives where it starts in the contents, and what's wrong with it.
+pub fn decode_escapes(contents: &str) -> Result<Vec<u8>, (usize, LexErrorKind)> {
+    let mut bytes = Vec::with_capacity(contents.len());
+    let mut chars = contents.char_indices().peekable();
+    while let Some((offset, ch)) = chars.next() {
+        if ch != '\\' {
+            bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some((_, escaped)) => escaped,
+            None => return Err((offset, LexErrorKind::MalformedEscape("nothing is escaped"))),
+        };
+        bytes.push(match escaped {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' | '\'' | '"' | '?' => escaped as u8,
+            // up to three octal digits
+            '0'..='7' => {
+                let mut value = escaped.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.next_if(|(_, c)| c.is_digit(8)) {
+                        Some((_, digit)) => value = value * 8 + digit.to_digit(8).unwrap(),
+                        None => break,
+                    }
+                }
+                u8::try_from(value).map_err(|_| {
+                    (
+                        offset,
+                        LexErrorKind::MalformedEscape("the octal value doesn't fit in a char"),
+                    )
+                })?
+            }
+            // as many hexadecimal digits as there are
+            'x' => {
+                let mut value = None;
+                while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                    let digit = digit.to_digit(16).unwrap();
+                    value = Some(
+                        value
+                            .unwrap_or(0u32)
+                            .saturating_mul(16)
+                            .saturating_add(digit),
+                    );
+                }
+                let value = value.ok_or((
+                    offset,
+                    LexErrorKind::MalformedEscape("`\\x` is used with no hexadecimal digits"),
+                ))?;
+                u8::try_from(value).map_err(|_| {
+                    (
+                        offset,
+                        LexErrorKind::MalformedEscape(
+                            "the hexadecimal value doesn't fit in a char",
+                        ),
+                    )
+                })?
+            }
+            other => return Err((offset, LexErrorKind::UnknownEscape(other))),
+        });
+    }
+    Ok(bytes)
+}
+
 impl<'source> Lexer<'source> {
     pub fn new(input: &'source SourceMetadata<'source>) -> Self {
         Self {
@@ -219,6 +360,9 @@ impl<'source> Lexer<'source> {
 
     pub fn next_token(&mut self) -> Result<Option<Token<'source>>, LexError> {
         self.skip_whitespace();
+        if self.skip_pragma() {
+            return self.next_token();
+        }
         if let Some(pos) = self.eat_char('(') {
             self.advance();
             return Ok(Some(Token::open_paren(self.source_from_len(pos, 1))));
@@ -227,6 +371,14 @@ impl<'source> Lexer<'source> {
             self.advance();
             return Ok(Some(Token::close_paren(self.source_from_len(pos, 1))));
         }
+        if let Some(pos) = self.eat_char('[') {
+            self.advance();
+            return Ok(Some(Token::open_bracket(self.source_from_len(pos, 1))));
+        }
+        if let Some(pos) = self.eat_char(']') {
+            self.advance();
+            return Ok(Some(Token::close_bracket(self.source_from_len(pos, 1))));
+        }
         if let Some(pos) = self.eat_char('{') {
             self.advance();
             return Ok(Some(Token::open_brace(self.source_from_len(pos, 1))));
@@ -243,9 +395,41 @@ impl<'source> Lexer<'source> {
             self.advance();
             return Ok(Some(Token::colon(self.source_from_len(pos, 1))));
         }
+        if let Some(pos) = self.eat_char(',') {
+            self.advance();
+            return Ok(Some(Token::comma(self.source_from_len(pos, 1))));
+        }
+        // a constant may start with its decimal point, so it goes before `.`
+        if let Some(token) = self.number().map_err(|e| e.add_context("number"))? {
+            return Ok(Some(token));
+        }
+        let offset = self.current_offset();
+        if self.metadata.input()[offset..].starts_with("...") {
+            self.advance();
+            self.advance();
+            self.advance();
+            return Ok(Some(Token::ellipsis(self.source_from_len(offset, 3))));
+        }
+        if let Some(pos) = self.eat_char('.') {
+            self.advance();
+            return Ok(Some(Token::dot(self.source_from_len(pos, 1))));
+        }
+        if let Some(src) = self.quoted('"')? {
+            return Ok(Some(Token::string_literal(src)));
+        }
+        if let Some(src) = self.char_literal()? {
+            return Ok(Some(Token::char_literal(src)));
+        }
         if let Some(src) = self.identifier() {
             return Ok(Some(Token::identifier(src)));
         }
+        // `-` is never followed by `>` otherwise
+        let offset = self.current_offset();
+        if self.metadata.input()[offset..].starts_with("->") {
+            self.advance();
+            self.advance();
+            return Ok(Some(Token::arrow(self.source_from_len(offset, 2))));
+        }
         if let Some((start, kind)) = self.operator() {
             let has_equal = self.skip_if(|x| x == '=').is_some();
             return Ok(Some(Token::operator(
@@ -254,9 +438,6 @@ impl<'source> Lexer<'source> {
                 self.source_until_current(start),
             )));
         }
-        if let Some(src) = self.number().map_err(|e| e.add_context("number"))? {
-            return Ok(Some(Token::number(src)));
-        }
         match self.input.peek().copied() {
             None => Ok(None),
             Some((pos, ch)) => Err(self.error(pos, LexErrorKind::UnexpectedChar(ch))),
@@ -353,12 +534,132 @@ impl<'source> Lexer<'source> {
         Some(self.source_until_current(start))
     }
 
-    fn number(&mut self) -> Result<Option<Source<'source>>, LexError> {
-        let start = match self.skip_if(|c| c.is_ascii_digit()) {
+    /// A string or character literal between `quote`s, which can't span several lines. Its escape
+    /// sequences must be valid.
+    fn quoted(&mut self, quote: char) -> Result<Option<Source<'source>>, LexError> {
+        let start = match self.skip_if(|c| c == quote) {
             Some((pos, _)) => pos,
             None => return Ok(None),
         };
-        self.skip_while(|c| c.is_ascii_digit());
+        loop {
+            self.skip_while(|c| c != quote && c != '\n' && c != '\\');
+            // the character after a backslash never ends the literal
+            if self.skip_if(|c| c == '\\').is_none() {
+                break;
+            }
+            self.skip_if(|c| c != '\n');
+        }
+        if self.skip_if(|c| c == quote).is_none() {
+            return Err(self.error(
+                start,
+                if quote == '"' {
+                    LexErrorKind::UnterminatedString
+                } else {
+                    LexErrorKind::UnterminatedChar
+                },
+            ));
+        }
+        let source = self.source_until_current(start);
+        if let Err((offset, kind)) = decode_escapes(&source.source[1..source.source.len() - 1]) {
+            return Err(self.error(start + 1 + offset, kind));
+        }
+        Ok(Some(source))
+    }
+
+    /// A character constant, which has a single byte
+    fn char_literal(&mut self) -> Result<Option<Source<'source>>, LexError> {
+        let source = match self.quoted('\'')? {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+        let contents = &source.source[1..source.source.len() - 1];
+        match decode_escapes(contents).map(|bytes| bytes.len()) {
+            Ok(1) => Ok(Some(source)),
+            Ok(0) => Err(self.error(
+                source.span.offset,
+                LexErrorKind::MalformedChar("it's empty"),
+            )),
+            _ => Err(self.error(
+                source.span.offset,
+                LexErrorKind::MalformedChar("it has more than one character"),
+            )),
+        }
+    }
+
+    /// An integer or floating constant. Integers are decimal, hexadecimal (`0x`), binary (`0b`) or
+    /// octal (starting with `0`). Floating ones have a fraction or an exponent, which is binary
+    /// (`p`) and required in hexadecimal ones.
+    fn number(&mut self) -> Result<Option<Token<'source>>, LexError> {
+        let start = self.current_offset();
+        let rest = &self.metadata.input()[start..];
+        let is_digit = |rest: &str| rest.starts_with(|c: char| c.is_ascii_digit());
+        if !(is_digit(rest) || rest.starts_with('.') && is_digit(&rest[1..])) {
+            return Ok(None);
+        }
+        let is_float = if rest.starts_with("0x") || rest.starts_with("0X") {
+            self.advance();
+            self.advance();
+            let mut digits = self.skip_while(|c| c.is_ascii_hexdigit()).source.len();
+            let has_fraction = self.skip_if(|c| c == '.').is_some();
+            if has_fraction {
+                digits += self.skip_while(|c| c.is_ascii_hexdigit()).source.len();
+            }
+            if digits == 0 {
+                return Err(self.error(
+                    start,
+                    LexErrorKind::MalformedNumber("no hexadecimal digits"),
+                ));
+            }
+            let has_exponent = self.exponent(|c| c == 'p' || c == 'P')?;
+            if has_fraction && !has_exponent {
+                return Err(self.error(
+                    start,
+                    LexErrorKind::MalformedFloat("hexadecimal constants need a `p` exponent"),
+                ));
+            }
+            has_exponent
+        } else if rest.starts_with("0b") || rest.starts_with("0B") {
+            self.advance();
+            self.advance();
+            if self.skip_while(|c| c == '0' || c == '1').source.is_empty() {
+                return Err(self.error(start, LexErrorKind::MalformedNumber("no binary digits")));
+            }
+            false
+        } else {
+            let integer = self.skip_while(|c| c.is_ascii_digit());
+            let has_fraction = self.skip_if(|c| c == '.').is_some();
+            if has_fraction {
+                self.skip_while(|c| c.is_ascii_digit());
+            }
+            let is_float = self.exponent(|c| c == 'e' || c == 'E')? || has_fraction;
+            if let Some(offset) = integer
+                .source
+                .strip_prefix('0')
+                .and_then(|octal| octal.find(|c| c > '7'))
+                .filter(|_| !is_float)
+            {
+                return Err(self.error(
+                    integer.span.offset + 1 + offset,
+                    LexErrorKind::MalformedNumber("octal constants only have digits 0 to 7"),
+                ));
+            }
+            is_float
+        };
+        if is_float {
+            // `f` makes it a `float`, and `l` a `long double`
+            self.skip_if(|c| matches!(c, 'f' | 'F' | 'l' | 'L'));
+        } else {
+            // a `u` suffix makes it unsigned, and an `l` or `ll`, in either case, makes it a `long`.
+            // They can come in either order.
+            let is_unsigned = |c: char| c == 'u' || c == 'U';
+            let unsigned = self.skip_if(is_unsigned).is_some();
+            if let Some((_, suffix)) = self.skip_if(|c| c == 'l' || c == 'L') {
+                self.skip_if(|c| c == suffix);
+                if !unsigned {
+                    self.skip_if(is_unsigned);
+                }
+            }
+        }
         if let Some((pos, ch)) = self
             .input
             .peek()
@@ -373,7 +674,32 @@ impl<'source> Lexer<'source> {
                 },
             ));
         }
-        Ok(Some(self.source_until_current(start)))
+        let source = self.source_until_current(start);
+        Ok(Some(if is_float {
+            Token::float_number(source)
+        } else {
+            Token::number(source)
+        }))
+    }
+
+    /// The exponent of a floating constant, if it starts with a letter that `marker` accepts: an
+    /// optional sign and at least one digit
+    fn exponent<F>(&mut self, marker: F) -> Result<bool, LexError>
+    where
+        F: Fn(char) -> bool,
+    {
+        if self.skip_if(marker).is_none() {
+            return Ok(false);
+        }
+        self.skip_if(|c| c == '+' || c == '-');
+        let start = self.current_offset();
+        if self.skip_while(|c| c.is_ascii_digit()).source.is_empty() {
+            return Err(self.error(
+                start,
+                LexErrorKind::MalformedFloat("the exponent has no digits"),
+            ));
+        }
+        Ok(true)
     }
 
     fn skip_while<F>(&mut self, filter: F) -> Source<'source>
@@ -419,6 +745,21 @@ impl<'source> Lexer<'source> {
         }
     }
 
+    /// Skip a `#pragma` line. The diagnostic pragmas are read separately, and any other pragma is
+    /// ignored.
+    fn skip_pragma(&mut self) -> bool {
+        let offset = self.current_offset();
+        let rest = &self.metadata.input()[offset..];
+        let line = rest.split('\n').next().unwrap_or(rest);
+        if !line.starts_with('#') || super::pragma::pragma_body(line).is_none() {
+            return false;
+        }
+        while self.input.peek().is_some_and(|(_, ch)| *ch != '\n') {
+            self.advance();
+        }
+        true
+    }
+
     fn advance(&mut self) {
         self.input.next();
     }
@@ -457,7 +798,7 @@ impl<'source> Lexer<'source> {
         Span::new(self.current_offset())
     }
 
-    pub const fn get_metadata(&self) -> &SourceMetadata {
+    pub const fn get_metadata(&self) -> &SourceMetadata<'_> {
         self.metadata
     }
 
@@ -472,3 +813,105 @@ impl<'source> Lexer<'source> {
 fn is_delimeter(ch: char) -> bool {
     ch.is_whitespace() || ch.is_ascii_punctuation()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+
+    /// The kinds and sources of the tokens of `input`, or the first error
+    fn lex(input: &str) -> Result<Vec<(TokenKind, String)>, LexErrorKind> {
+        let meta = SourceMetadata::new(input);
+        Lexer::new(&meta)
+            .into_iter()
+            .map(|token| {
+                token
+                    .map(|token| (token.kind, token.source.source.to_string()))
+                    .map_err(|error| error.kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn integer_constants_can_be_written_in_any_base() {
+        for number in ["0x1F", "0XffL", "0b101", "0B1", "017", "0", "10"] {
+            assert_eq!(
+                lex(number).ok(),
+                Some(vec![(TokenKind::Number, number.to_string())]),
+                "{number}"
+            );
+        }
+        // octal digits stop at 7, but floating constants are always decimal
+        assert!(matches!(lex("018"), Err(LexErrorKind::MalformedNumber(_))));
+        assert_eq!(
+            lex("018.5").ok(),
+            Some(vec![(TokenKind::FloatNumber, "018.5".to_string())])
+        );
+        for malformed in ["0x", "0b", "0b2"] {
+            assert!(
+                matches!(lex(malformed), Err(LexErrorKind::MalformedNumber(_))),
+                "{malformed}"
+            );
+        }
+        assert!(matches!(lex("0x1.8"), Err(LexErrorKind::MalformedFloat(_))));
+        assert_eq!(
+            lex("0x1.8p1").ok(),
+            Some(vec![(TokenKind::FloatNumber, "0x1.8p1".to_string())])
+        );
+    }
+
+    #[test]
+    fn floating_constants_have_a_fraction_or_an_exponent() {
+        for number in [
+            "0x1.8p3", "1e-3", "1.5", ".5", "2.", "1E+10", "0X1P-2", "1.5f", "3e2L",
+        ] {
+            assert_eq!(
+                lex(number).ok(),
+                Some(vec![(TokenKind::FloatNumber, number.to_string())]),
+                "{number}"
+            );
+        }
+        // the sign of an exponent is part of the constant, but not one after it
+        assert_eq!(
+            lex("1e-3-1").ok(),
+            Some(vec![
+                (TokenKind::FloatNumber, "1e-3".to_string()),
+                (
+                    TokenKind::Operator {
+                        kind: Operator::Minus,
+                        has_equal: false
+                    },
+                    "-".to_string()
+                ),
+                (TokenKind::Number, "1".to_string()),
+            ])
+        );
+        for malformed in ["1e", "1e-", "0x1p", "0x.8"] {
+            assert!(
+                matches!(lex(malformed), Err(LexErrorKind::MalformedFloat(_))),
+                "{malformed}"
+            );
+        }
+    }
+
+    #[test]
+    fn escape_sequences_are_decoded() {
+        assert_eq!(
+            decode_escapes(r#"a\n\t\0\\\'\"\x41\101\1234"#).unwrap(),
+            b"a\n\t\0\\'\"AAS4"
+        );
+    }
+
+    #[test]
+    fn invalid_escape_sequences_point_at_their_backslash() {
+        assert!(matches!(
+            decode_escapes(r"ab\q"),
+            Err((2, LexErrorKind::UnknownEscape('q')))
+        ));
+        assert!(matches!(
+            decode_escapes(r"\x100"),
+            Err((0, LexErrorKind::MalformedEscape(_)))
+        ));
+    }
+}
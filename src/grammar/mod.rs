@@ -1,19 +1,49 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::ast::{AggregateKind, Enumerator, StructType, Type};
 use crate::error::*;
+use alloc::sync::Arc;
+use hashbrown::{HashMap, HashSet};
 
 mod block;
 mod expr;
 mod function;
 mod identifier;
 pub mod lexer;
+pub mod pragma;
+pub mod preprocess;
 mod program;
 mod statement;
+mod types;
 
 use lexer::*;
 // TODO(#5): add measureme to the parser
 
+/// How deep expressions and statements may be nested by default, which the parser and the later
+/// stages can recurse through without running out of the main thread's stack. C asks for 127
+/// levels of nested blocks and 63 of nested parentheses to be supported.
+pub const DEFAULT_NESTING_LIMIT: usize = 128;
+
 pub struct Parser<'source> {
     lexer: Lexer<'source>,
     current_tok: Option<Token<'source>>,
+    /// What binary operator the current token is, once the expression parser has looked, so that
+    /// each token is classified once however many times the operators around it look ahead
+    current_operator: Option<Option<expr::DetectTernary>>,
+    /// The structs and unions defined so far, by tag. Their layout is needed as soon as they are
+    /// used.
+    structs: HashMap<&'source str, Arc<StructType>>,
+    /// The tags of the enums defined so far, which share their namespace with the structs
+    enums: HashSet<&'source str>,
+    /// The enumerators of the enums defined since they were last taken, with where the enum was
+    /// defined. They belong to the scope the definition is in.
+    enumerators: Vec<(Vec<Enumerator<'source>>, Span)>,
+    /// How many statements and expressions what's being parsed is nested in
+    depth: usize,
+    /// The most statements and expressions may be nested in each other, counting every operand of
+    /// an expression as nested in it
+    nesting_limit: usize,
 }
 
 impl<'source> Parser<'source> {
@@ -21,8 +51,19 @@ impl<'source> Parser<'source> {
         Self {
             lexer: Lexer::new(source),
             current_tok: None,
+            current_operator: None,
+            structs: HashMap::new(),
+            enums: HashSet::new(),
+            enumerators: Vec::new(),
+            depth: 0,
+            nesting_limit: DEFAULT_NESTING_LIMIT,
         }
     }
+    /// Allow statements and expressions to be nested at most `limit` deep
+    pub fn with_nesting_limit(mut self, limit: usize) -> Self {
+        self.nesting_limit = limit;
+        self
+    }
     pub fn current_position(&self) -> usize {
         self.current_token_span().offset
     }
@@ -51,6 +92,7 @@ impl<'source> Parser<'source> {
     }
     pub fn accept_current(&mut self) {
         self.current_tok = None;
+        self.current_operator = None;
     }
     pub fn emit_error_at<T>(&self, span: Span, kind: ParseErrorKind) -> ParseRes<T> {
         Err(ParseError::new(kind).with_source(span, self.lexer.get_metadata()))
@@ -93,12 +135,39 @@ impl<'source> Parser<'source> {
             Ok(())
         }
     }
+    /// Take the enumerators of the enums defined since the last call, so that they can be declared
+    /// in the scope being parsed
+    pub fn take_enumerators(&mut self) -> Vec<(Vec<Enumerator<'source>>, Span)> {
+        core::mem::take(&mut self.enumerators)
+    }
     pub fn parse<T>(&mut self) -> ParseRes<T>
     where
         T: Parse<'source>,
     {
         T::parse(self)
     }
+    /// Parse a statement or an expression nested in the one being parsed, unless it's nested too
+    /// deep
+    pub fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseRes<T>) -> ParseRes<T> {
+        if self.depth >= self.nesting_limit {
+            let span = match &self.current_tok {
+                Some(token) => token.source.span,
+                None => self.lexer.current_span(),
+            };
+            return self.emit_error_at(span, ParseErrorKind::TooDeeplyNested);
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+    /// Check that an expression `depth` levels deep, at `span`, isn't nested too deep where it is
+    pub fn check_depth(&self, depth: usize, span: Span) -> ParseRes<()> {
+        if self.depth + depth > self.nesting_limit {
+            return self.emit_error_at(span, ParseErrorKind::TooDeeplyNested);
+        }
+        Ok(())
+    }
     pub fn with_context<F, T>(&mut self, context: &'static str, mut cont: F) -> ParseRes<T>
     where
         F: FnMut(&mut Self) -> ParseRes<T>,
@@ -137,11 +206,29 @@ pub enum ParseErrorKind {
         wanted: Option<WantedSpec<TokenKind>>,
     },
     UnpairedBrace,
+    UnknownTag(AggregateKind, String),
+    UnknownEnum(String),
+    TagRedefined(String),
+    WrongTagKind(AggregateKind, String),
+    DuplicateMember(String),
+    ConstantTooLarge(String),
+    FlexibleArrayNotLast(String),
+    FlexibleArrayAlone(String),
+    FlexibleArrayInUnion(String),
+    NestedFlexibleArray(String),
+    UnknownAttribute(String),
+    /// `inline`, or an attribute, given to a variable
+    FunctionOnly(String),
+    /// `_Alignas` given to a function
+    VariableOnly(String),
+    VoidDeclaration,
+    SignedNonInteger(Type),
+    TooDeeplyNested,
 }
 
 impl ParseErrorKind {
     pub const fn is_critical(&self) -> bool {
-        matches!(self, Self::LexError(_))
+        matches!(self, Self::LexError(_) | Self::TooDeeplyNested)
     }
 }
 
@@ -149,7 +236,7 @@ pub trait Parse<'source>: Sized {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self>;
 }
 
-use std::error;
+use core::error;
 impl error::Error for ParseErrorKind {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         if let Self::LexError(err) = self {
@@ -160,11 +247,47 @@ impl error::Error for ParseErrorKind {
     }
 }
 
-use std::fmt;
+use core::fmt;
 impl fmt::Display for ParseErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::UnpairedBrace => write!(f, "unpaired brace: '}}'"),
+            Self::UnknownTag(kind, tag) => write!(f, "unknown {} {:?}", kind.keyword(), tag),
+            Self::UnknownEnum(tag) => write!(f, "unknown enum {:?}", tag),
+            Self::TagRedefined(tag) => write!(f, "tag {:?} was already defined", tag),
+            Self::WrongTagKind(kind, tag) => {
+                write!(f, "{:?} was not defined as a {}", tag, kind.keyword())
+            }
+            Self::DuplicateMember(name) => write!(f, "duplicate member {:?}", name),
+            Self::FlexibleArrayNotLast(name) => {
+                write!(f, "flexible array member {:?} is not the last member", name)
+            }
+            Self::FlexibleArrayAlone(name) => write!(
+                f,
+                "flexible array member {:?} in a struct with no other members",
+                name
+            ),
+            Self::FlexibleArrayInUnion(name) => {
+                write!(f, "flexible array member {:?} in a union", name)
+            }
+            Self::NestedFlexibleArray(ty) => write!(
+                f,
+                "{} ends in a flexible array member, so it can't be nested in another type",
+                ty
+            ),
+            Self::UnknownAttribute(name) => write!(f, "unknown attribute `{}`", name),
+            Self::FunctionOnly(name) => write!(f, "`{}` only applies to functions", name),
+            Self::VariableOnly(name) => write!(f, "`{}` only applies to variables", name),
+            Self::VoidDeclaration => {
+                write!(f, "only functions can be `void`, by returning nothing")
+            }
+            Self::SignedNonInteger(ty) => {
+                write!(f, "only integer types are `signed` or `unsigned`, not `{}`", ty)
+            }
+            Self::TooDeeplyNested => write!(f, "expression or statement too deeply nested"),
+            Self::ConstantTooLarge(number) => {
+                write!(f, "integer constant {} is too large for any type", number)
+            }
             Self::LexError(err) => write!(f, "error while lexing source: {}", err),
             Self::UnexpectedEOF { wanted } => {
                 write!(f, "unexpected end of input")?;
@@ -180,3 +303,72 @@ impl fmt::Display for ParseErrorKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check_str;
+    use alloc::format;
+
+    #[test]
+    fn nesting_too_deep_is_an_error_instead_of_a_stack_overflow() {
+        let too_deep = |diagnostics: Diagnostics| {
+            let error = diagnostics.iter().next().cloned();
+            error.is_some_and(|error| error.message.contains("too deeply nested"))
+        };
+        // a flat chain of operators isn't nested any deeper than its operands, however long it is
+        let sum = format!(
+            "int main() {{ int a = 1; return {}a; }}",
+            "a + ".repeat(10000)
+        );
+        assert!(check_str(&sum).is_empty());
+        let mixed = format!(
+            "int main() {{ int a = 1; return {}a; }}",
+            "a * 2 - a < a << 1 == ".repeat(2000)
+        );
+        assert!(check_str(&mixed).is_empty());
+        let folded = format!(
+            "int global = {}1;\nint main() {{ return 0; }}",
+            "1 + ".repeat(10000)
+        );
+        assert!(check_str(&folded).is_empty());
+        // it's not parsed recursively, but makes a tree as deep as it is long
+        let negated = format!("int main() {{ return {}1; }}", "- ".repeat(10000));
+        assert!(too_deep(check_str(&negated)));
+        let shallow = format!("int main() {{ int a = 1; return {}a; }}", "a + ".repeat(20));
+        assert!(check_str(&shallow).is_empty());
+
+        let parse = |source: &str, limit| {
+            let meta = SourceMetadata::new(source);
+            let parsed = Parser::new(&meta)
+                .with_nesting_limit(limit)
+                .parse::<crate::ast::Program>();
+            parsed.err().map(Diagnostics::from).is_some_and(too_deep)
+        };
+        let parens = |n: usize| {
+            let (open, close) = ("(".repeat(n), ")".repeat(n));
+            format!("int main() {{ return {open}1{close}; }}")
+        };
+        assert!(parse(&parens(10000), 16));
+        assert!(parse(&parens(16), 16));
+        assert!(!parse(&parens(8), 16));
+        let blocks = |n: usize| {
+            let (open, close) = ("{".repeat(n), "}".repeat(n));
+            format!("int main() {{ {open}return 1;{close} }}")
+        };
+        assert!(parse(&blocks(10000), 16));
+        assert!(!parse(&blocks(8), 16));
+        let calls = format!(
+            "int f(int x);\nint main() {{ return {}1{}; }}",
+            "f(".repeat(20),
+            ")".repeat(20)
+        );
+        assert!(parse(&calls, 16));
+        let list = format!(
+            "int main() {{ int a[1] = {}1{}; }}",
+            "{".repeat(20),
+            "}".repeat(20)
+        );
+        assert!(parse(&list, 16));
+    }
+}
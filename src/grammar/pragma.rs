@@ -0,0 +1,82 @@
+//! `#pragma` directives. The preprocessor leaves them in, so the lexer skips them as if they were
+//! whitespace, and the diagnostic ones are collected separately from the whole source.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::error::DiagnosticPragma;
+
+/// The text after `#pragma`, if the line is a pragma directive
+pub fn pragma_body(line: &str) -> Option<&str> {
+    line.trim_start()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("pragma")
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Parse a `#pragma GCC diagnostic` (or `#pragma clang diagnostic`) line. Any other line,
+/// including pragmas not about diagnostics, gives `None`.
+pub fn parse_diagnostic_pragma(line: &str) -> Option<DiagnosticPragma> {
+    let mut words = pragma_body(line)?.split_whitespace();
+    if !matches!(words.next()?, "GCC" | "clang") || words.next()? != "diagnostic" {
+        return None;
+    }
+    match words.next()? {
+        "push" => Some(DiagnosticPragma::Push),
+        "pop" => Some(DiagnosticPragma::Pop),
+        kind @ ("ignored" | "warning" | "error") => {
+            let flag = words
+                .next()?
+                .strip_prefix('"')?
+                .strip_suffix('"')?
+                .strip_prefix("-W")?;
+            // `-Wframe-larger-than=<bytes>` is named without its argument in pragmas
+            let flag = flag.strip_suffix('=').unwrap_or(flag);
+            Some(DiagnosticPragma::Set {
+                flag: flag.to_string(),
+                enable: kind != "ignored",
+            })
+        }
+        _ => None,
+    }
+}
+
+/// All the diagnostic pragmas in the source, along with the offset of the line they are on
+pub fn diagnostic_pragmas(source: &str) -> Vec<(usize, DiagnosticPragma)> {
+    let mut pragmas = Vec::new();
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        if let Some(pragma) = parse_diagnostic_pragma(line) {
+            pragmas.push((offset, pragma));
+        }
+        offset += line.len();
+    }
+    pragmas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{DiagnosticFilter, WarningOptions};
+
+    #[test]
+    fn push_pop_regions() {
+        let source = "int a;\n\
+                      #pragma GCC diagnostic push\n\
+                      #pragma GCC diagnostic ignored \"-Wconstant-condition\"\n\
+                      #pragma GCC diagnostic warning \"-Wshadow\"\n\
+                      int b;\n\
+                      #pragma GCC diagnostic pop\n\
+                      int c;\n";
+        let filter = DiagnosticFilter::new(WarningOptions::default(), diagnostic_pragmas(source));
+        let [a, b, c] = ["int a", "int b", "int c"].map(|decl| source.find(decl).unwrap());
+        assert!(filter.is_enabled("constant-condition", a));
+        assert!(!filter.is_enabled("constant-condition", b));
+        assert!(filter.is_enabled("constant-condition", c));
+        assert!(!filter.is_enabled("shadow", a));
+        assert!(filter.is_enabled("shadow", b));
+        assert!(!filter.is_enabled("shadow", c));
+        assert!(filter.check_options().shadow);
+    }
+}
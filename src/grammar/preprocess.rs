@@ -0,0 +1,668 @@
+//! The preprocessor, which runs before the lexer. It replaces each `#include` line with the
+//! contents of the file it names, leaves out the lines that conditional directives (`#if`,
+//! `#ifdef`, `#ifndef`, `#elif`, `#else` and `#endif`) skip, and keeps a [`SourceMap`] of where
+//! each part of the result came from so diagnostics point at the right file. Other directives are
+//! left for the lexer.
+//!
+//! Macros are only defined (with `#define` and `#undef`, or `-D` and `-U`) for the conditions to
+//! test: their uses in the code are not replaced.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::{Error, SourceMap, SourceMetadata, Span};
+use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// How deep `#include`s can nest, like in GCC. Deeper than that is most likely a file including
+/// itself.
+pub const MAX_INCLUDE_DEPTH: usize = 200;
+
+#[derive(Error, Debug)]
+pub enum PreprocessError {
+    #[error("#include expects \"FILENAME\" or <FILENAME>")]
+    MalformedInclude,
+    #[error("extra text after the file name of #include")]
+    ExtraText,
+    #[error("{0}: file not found")]
+    NotFound(String),
+    #[error("{0}: couldn't read the file")]
+    Unreadable(String),
+    #[error("the byte at offset {offset} of {file} isn't valid UTF-8 (sources in Latin-1 need `-finput-charset=latin1`)")]
+    InvalidEncoding { file: String, offset: usize },
+    #[error("#include nested more than {MAX_INCLUDE_DEPTH} levels deep")]
+    TooDeep,
+    #[error("#{0} with no expression")]
+    MissingCondition(&'static str),
+    #[error("the condition of #{0} isn't an integer constant expression")]
+    InvalidCondition(&'static str),
+    #[error("macro names must be identifiers")]
+    MacroName,
+    #[error("#{0} without #if")]
+    Unmatched(&'static str),
+    #[error("#{0} after #else")]
+    AfterElse(&'static str),
+    #[error("unterminated #{0}")]
+    Unterminated(&'static str),
+}
+
+pub type PreprocessE = Error<PreprocessError>;
+
+/// The encoding source files are written in (`-finput-charset=<charset>`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, where each byte is the character of the same code point
+    Latin1,
+}
+
+impl core::str::FromStr for Charset {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Self::Utf8,
+            "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Self::Latin1,
+            _ => return Err(format!("unknown input charset `{}`", s)),
+        })
+    }
+}
+
+/// The text of the source file `file`, read as `bytes`. A UTF-8 byte order mark at the start is
+/// left out, and a byte that isn't valid UTF-8 is reported where it is, instead of the whole file
+/// being unreadable.
+pub fn decode_source(
+    file: &str,
+    mut bytes: Vec<u8>,
+    charset: Charset,
+) -> Result<String, PreprocessE> {
+    match charset {
+        Charset::Utf8 => {
+            if bytes.starts_with(b"\xEF\xBB\xBF") {
+                bytes.drain(..3);
+            }
+            String::from_utf8(bytes).map_err(|error| {
+                let offset = error.utf8_error().valid_up_to();
+                // the bytes before are the same in the text with the invalid ones replaced
+                let text = String::from_utf8_lossy(error.as_bytes());
+                let meta = SourceMetadata::new(&text).with_file(file.to_string());
+                Error::new(PreprocessError::InvalidEncoding {
+                    file: file.to_string(),
+                    offset,
+                })
+                .with_source(Span::new(offset), &meta)
+            })
+        }
+        Charset::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+    }
+}
+
+/// Where the files that `#include`s name come from
+pub trait FileLoader {
+    /// The path and the contents of the file named by an `#include` in the file at `including`,
+    /// which was named between angle brackets if `angled`
+    fn load(
+        &self,
+        name: &str,
+        angled: bool,
+        including: &str,
+    ) -> Result<(String, String), PreprocessError>;
+    /// What a file is known by for `#pragma once`, which has to be the same however it was named
+    fn identity(&self, path: &str) -> String {
+        path.to_string()
+    }
+}
+
+/// Files kept in memory, by the name they are included with, wherever they are included from
+impl FileLoader for HashMap<String, String> {
+    fn load(
+        &self,
+        name: &str,
+        _angled: bool,
+        _including: &str,
+    ) -> Result<(String, String), PreprocessError> {
+        self.get(name)
+            .map(|contents| (name.to_string(), contents.clone()))
+            .ok_or_else(|| PreprocessError::NotFound(name.to_string()))
+    }
+}
+
+/// The directories searched for included files. A file named between quotes is looked for next to
+/// the file that includes it first, and then in the same places as one named between angle
+/// brackets: the `user` directories and then the `system` ones, in order.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct IncludePaths {
+    pub user: Vec<PathBuf>,
+    pub system: Vec<PathBuf>,
+    /// the encoding of the files found
+    pub charset: Charset,
+}
+
+#[cfg(feature = "std")]
+impl IncludePaths {
+    /// Where the file named by an `#include` is, if it exists
+    pub fn find(&self, name: &str, angled: bool, including_dir: Option<&Path>) -> Option<PathBuf> {
+        including_dir
+            .filter(|_| !angled)
+            .into_iter()
+            .chain(self.user.iter().map(PathBuf::as_path))
+            .chain(self.system.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(feature = "std")]
+impl FileLoader for IncludePaths {
+    fn load(
+        &self,
+        name: &str,
+        angled: bool,
+        including: &str,
+    ) -> Result<(String, String), PreprocessError> {
+        let found = self
+            .find(name, angled, Path::new(including).parent())
+            .ok_or_else(|| PreprocessError::NotFound(name.to_string()))?;
+        let bytes =
+            std::fs::read(&found).map_err(|_| PreprocessError::Unreadable(name.to_string()))?;
+        let path = found.display().to_string();
+        let contents = decode_source(&path, bytes, self.charset).map_err(|error| error.kind)?;
+        Ok((path, contents))
+    }
+    fn identity(&self, path: &str) -> String {
+        Path::new(path)
+            .canonicalize()
+            .map_or_else(|_| path.to_string(), |path| path.display().to_string())
+    }
+}
+
+/// The macros that are defined, with what they are replaced by
+pub type Macros = HashMap<String, String>;
+
+/// The macros that are defined before the source starts, some of them by the target
+pub fn predefined_macros() -> Macros {
+    let data_layout = &crate::codegen::target::current().data_layout;
+    let model: &[&str] = if data_layout.pointer.size == 8 {
+        &["_LP64", "__LP64__"]
+    } else {
+        &["_ILP32", "__ILP32__"]
+    };
+    ["__STDC__", "__aarch64__", "__tracc__"]
+        .iter()
+        .chain(model)
+        .map(|name| (name.to_string(), "1".to_string()))
+        // the memory orders of the `__atomic` builtins
+        .chain(
+            crate::intermediate::generate::MEMORY_ORDERS
+                .iter()
+                .enumerate()
+                .map(|(order, name)| (name.to_string(), order.to_string())),
+        )
+        .collect()
+}
+
+/// A source with its `#include`s resolved
+#[derive(Debug)]
+pub struct Preprocessed {
+    pub source: String,
+    pub map: SourceMap,
+}
+
+impl Preprocessed {
+    /// The metadata to compile the source with, which finds the file each span points into
+    pub fn metadata(&self) -> SourceMetadata<'_> {
+        SourceMetadata::new(&self.source)
+            .with_file(self.map.file_path(0).to_string())
+            .with_includes(&self.map)
+    }
+}
+
+/// Resolve the directives of the contents of `file`, and of the files it includes, starting with
+/// `macros` defined
+pub fn preprocess(
+    file: String,
+    contents: String,
+    loader: &dyn FileLoader,
+    macros: Macros,
+) -> Result<Preprocessed, PreprocessE> {
+    let mut preprocessor = Preprocessor {
+        loader,
+        output: Preprocessed {
+            source: String::with_capacity(contents.len()),
+            map: SourceMap::new(),
+        },
+        included_once: HashSet::new(),
+        macros,
+    };
+    let main = preprocessor.output.map.add_file(file, contents);
+    preprocessor.include(main, 0)?;
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor<'a> {
+    loader: &'a dyn FileLoader,
+    output: Preprocessed,
+    /// the files with `#pragma once`, which are only included the first time
+    included_once: HashSet<String>,
+    macros: Macros,
+}
+
+/// An `#if` (or `#ifdef` or `#ifndef`) whose `#endif` hasn't been reached yet
+struct Conditional {
+    /// the directive that opened it, and where it is
+    directive: &'static str,
+    at: usize,
+    /// whether the lines of the current branch are kept
+    taking: bool,
+    /// whether one of its branches has been taken, so the ones after it are skipped
+    taken: bool,
+    /// whether `#else` has been reached
+    in_else: bool,
+}
+
+impl Preprocessor<'_> {
+    /// Append the contents of a file of the map to the output, with its directives resolved
+    fn include(&mut self, file: usize, depth: usize) -> Result<(), PreprocessE> {
+        let contents = self.output.map.file_contents(file).to_string();
+        let path = self.output.map.file_path(file).to_string();
+        let error = |at, kind| {
+            PreprocessE::new(kind).with_source(
+                Span::new(at),
+                &SourceMetadata::new(&contents).with_file(path.clone()),
+            )
+        };
+        self.output
+            .map
+            .start_part(self.output.source.len(), file, 0);
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut offset = 0;
+        for line in contents.split_inclusive('\n') {
+            let at = offset + line.len() - line.trim_start().len();
+            let taking = conditionals.iter().all(|conditional| conditional.taking);
+            let (name, rest) = directive(line).unwrap_or_default();
+            match name {
+                "if" | "ifdef" | "ifndef" => {
+                    let directive = match name {
+                        "if" => "if",
+                        "ifdef" => "ifdef",
+                        _ => "ifndef",
+                    };
+                    // the conditions of skipped lines aren't even looked at
+                    let holds = taking
+                        && self
+                            .condition(directive, rest)
+                            .map_err(|kind| error(at, kind))?;
+                    conditionals.push(Conditional {
+                        directive,
+                        at,
+                        taking: holds,
+                        taken: holds || !taking,
+                        in_else: false,
+                    });
+                }
+                "elif" | "else" => {
+                    let directive = if name == "elif" { "elif" } else { "else" };
+                    let (conditional, outer) = conditionals
+                        .split_last_mut()
+                        .ok_or_else(|| error(at, PreprocessError::Unmatched(directive)))?;
+                    if conditional.in_else {
+                        return Err(error(at, PreprocessError::AfterElse(directive)));
+                    }
+                    let outer_taking = outer.iter().all(|conditional| conditional.taking);
+                    conditional.taking = !conditional.taken
+                        && outer_taking
+                        && (directive == "else"
+                            || self
+                                .condition(directive, rest)
+                                .map_err(|kind| error(at, kind))?);
+                    conditional.taken |= conditional.taking;
+                    conditional.in_else = directive == "else";
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error(at, PreprocessError::Unmatched("endif")))?;
+                }
+                "define" if taking => {
+                    let (name, replacement) = macro_name(rest).map_err(|kind| error(at, kind))?;
+                    self.macros
+                        .insert(name.to_string(), strip_comments(replacement).to_string());
+                }
+                "undef" if taking => {
+                    let (name, _) = macro_name(rest).map_err(|kind| error(at, kind))?;
+                    self.macros.remove(name);
+                }
+                "include" if taking => {
+                    let (name, angled) = include_directive(line)
+                        .expect("the line is an #include directive")
+                        .map_err(|kind| error(at, kind))?;
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(error(at, PreprocessError::TooDeep));
+                    }
+                    let (found, included) = self
+                        .loader
+                        .load(name, angled, &path)
+                        .map_err(|kind| error(at, kind))?;
+                    if !self.included_once.contains(&self.loader.identity(&found)) {
+                        let index = self.output.map.add_file(found, included);
+                        self.include(index, depth + 1)?;
+                        // what comes after the directive still starts a line of its own
+                        if !self.output.source.is_empty() && !self.output.source.ends_with('\n') {
+                            self.output.source.push('\n');
+                        }
+                    }
+                }
+                _ if taking => {
+                    if super::pragma::pragma_body(line).map(str::trim) == Some("once") {
+                        self.included_once.insert(self.loader.identity(&path));
+                    }
+                    self.output.source.push_str(line);
+                    offset += line.len();
+                    continue;
+                }
+                // a skipped line
+                _ => {}
+            }
+            // the line is left out, so the part after it starts again
+            offset += line.len();
+            self.output
+                .map
+                .start_part(self.output.source.len(), file, offset);
+        }
+        // each file has to close the conditionals it opens
+        match conditionals.first() {
+            Some(conditional) => Err(error(
+                conditional.at,
+                PreprocessError::Unterminated(conditional.directive),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the condition of a conditional directive holds
+    fn condition(&self, directive: &'static str, condition: &str) -> Result<bool, PreprocessError> {
+        let condition = strip_comments(condition);
+        if directive != "if" && directive != "elif" {
+            let (name, rest) = macro_name(condition)?;
+            if !rest.is_empty() {
+                return Err(PreprocessError::MacroName);
+            }
+            return Ok(self.macros.contains_key(name) == (directive == "ifdef"));
+        }
+        let expanded = self.expand(condition, &mut Vec::new());
+        if expanded.trim().is_empty() {
+            return Err(PreprocessError::MissingCondition(directive));
+        }
+        // the condition is folded like any integer constant expression, with no enumerators
+        let meta = SourceMetadata::new(&expanded);
+        let mut parser = super::Parser::new(&meta);
+        let invalid = || PreprocessError::InvalidCondition(directive);
+        let (expr, _) = parser
+            .parse::<(crate::ast::Expr, Span)>()
+            .map_err(|_| invalid())?;
+        if !matches!(parser.peek_token(), Ok(None)) {
+            return Err(invalid());
+        }
+        let variables = crate::intermediate::generate::VariableTracker::new();
+        crate::intermediate::generate::reduce_expr(&expr, &variables)
+            .map(|value| value != 0)
+            .ok_or_else(invalid)
+    }
+
+    /// Replace the macros of a condition by what they are defined as, `defined(NAME)` by whether
+    /// `NAME` is a macro and any other identifier by 0. `expanding` are the macros being replaced
+    /// already, which aren't replaced again inside of themselves.
+    fn expand(&self, condition: &str, expanding: &mut Vec<String>) -> String {
+        let mut expanded = String::with_capacity(condition.len());
+        let mut rest = condition;
+        while let Some(c) = rest.chars().next() {
+            let word_len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            // numbers are copied whole, suffixes included
+            if c.is_ascii_digit() || word_len == 0 {
+                let len = word_len.max(c.len_utf8());
+                expanded.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+            let (word, after) = rest.split_at(word_len);
+            rest = after;
+            if word == "defined" {
+                let parenthesized = after.trim_start().strip_prefix('(');
+                let name_start = parenthesized.unwrap_or(after).trim_start();
+                let name_len = name_start
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(name_start.len());
+                let (name, after) = name_start.split_at(name_len);
+                rest = match parenthesized {
+                    Some(_) => after.trim_start().strip_prefix(')').unwrap_or(""),
+                    None => after,
+                };
+                // a malformed `defined` leaves an invalid condition
+                expanded.push_str(match (name.is_empty(), self.macros.contains_key(name)) {
+                    (true, _) => "defined",
+                    (false, true) => " 1 ",
+                    (false, false) => " 0 ",
+                });
+            } else if let (Some(replacement), false) = (
+                self.macros.get(word),
+                expanding.iter().any(|name| name == word),
+            ) {
+                expanding.push(word.to_string());
+                let replaced = self.expand(replacement, expanding);
+                expanding.pop();
+                expanded += &format!(" ({}) ", replaced);
+            } else {
+                expanded.push_str(" 0 ");
+            }
+        }
+        expanded
+    }
+}
+
+/// The name of the directive a line is, and the text after it. Lines that aren't directives give
+/// `None`.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let len = rest
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(rest.len());
+    Some(rest.split_at(len))
+}
+
+/// The macro name a directive starts with, and the text after it
+fn macro_name(text: &str) -> Result<(&str, &str), PreprocessError> {
+    let text = text.trim_start();
+    let len = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    match text.split_at(len) {
+        (name, rest) if name.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+            Ok((name, rest))
+        }
+        _ => Err(PreprocessError::MacroName),
+    }
+}
+
+/// The text of a directive without the comment at its end, if any
+fn strip_comments(text: &str) -> &str {
+    let end = [text.find("//"), text.find("/*")]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(text.len());
+    text[..end].trim()
+}
+
+/// The file named by an `#include` line, and whether it was named between angle brackets. Any
+/// other line gives `None`.
+fn include_directive(line: &str) -> Option<Result<(&str, bool), PreprocessError>> {
+    let rest = line
+        .trim_start()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("include")?;
+    // the name may come right after `include`, but `#include_next` is another directive
+    if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let rest = rest.trim();
+    let (close, angled) = match rest.chars().next() {
+        Some('"') => ('"', false),
+        Some('<') => ('>', true),
+        _ => return Some(Err(PreprocessError::MalformedInclude)),
+    };
+    Some(match rest[1..].split_once(close) {
+        Some(("", _)) | None => Err(PreprocessError::MalformedInclude),
+        Some((name, after)) => {
+            let after = after.trim_start();
+            if after.is_empty() || after.starts_with("//") || after.starts_with("/*") {
+                Ok((name, angled))
+            } else {
+                Err(PreprocessError::ExtraText)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_lines() {
+        assert_eq!(
+            include_directive("#include \"a.h\"\n").unwrap().unwrap(),
+            ("a.h", false)
+        );
+        assert_eq!(
+            include_directive("  #  include<sys/b.h> // why\n")
+                .unwrap()
+                .unwrap(),
+            ("sys/b.h", true)
+        );
+        assert!(include_directive("#include_next <a.h>").is_none());
+        assert!(include_directive("int a; // #include \"a.h\"").is_none());
+        assert!(matches!(
+            include_directive("#include a.h"),
+            Some(Err(PreprocessError::MalformedInclude))
+        ));
+        assert!(matches!(
+            include_directive("#include \"a.h\" int"),
+            Some(Err(PreprocessError::ExtraText))
+        ));
+    }
+
+    #[test]
+    fn sources_are_decoded_without_byte_order_marks() {
+        let decode = |bytes: &[u8], charset| decode_source("a.c", bytes.to_vec(), charset);
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFint a;\n", Charset::Utf8).unwrap(),
+            "int a;\n"
+        );
+        assert_eq!(
+            decode(b"char *s = \"\xE9\";", Charset::Latin1).unwrap(),
+            "char *s = \"\u{e9}\";"
+        );
+        let error = decode(b"int a;\nchar *\xC3\xA9 = \"\xE9\";", Charset::Utf8).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            PreprocessError::InvalidEncoding { offset: 19, .. }
+        ));
+        assert!(error.to_string().contains("a.c:2:13"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn diagnostics_point_into_included_files() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("tracc-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sys"))?;
+        std::fs::write(dir.join("a.h"), "#pragma once\nint a(void);\n")?;
+        std::fs::write(dir.join("sys/b.h"), "#include \"a.h\"\nint b(void);")?;
+        let main = "#include \"a.h\"\n#include <b.h>\nint c;\n";
+        let paths = IncludePaths {
+            user: vec![dir.clone()],
+            system: vec![dir.join("sys")],
+            charset: Charset::Utf8,
+        };
+        let preprocessed = preprocess(
+            dir.join("main.c").display().to_string(),
+            main.to_string(),
+            &paths,
+            Macros::new(),
+        )?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(
+            preprocessed.source,
+            "#pragma once\nint a(void);\nint b(void);\nint c;\n"
+        );
+        let meta = preprocessed.metadata();
+        let at = |decl| {
+            let error = Error::new("")
+                .with_source(Span::new(preprocessed.source.find(decl).unwrap()), &meta);
+            error.to_string()
+        };
+        assert!(at("int b").contains("sys/b.h:2:1"));
+        assert!(at("int c").contains("main.c:3:1"));
+        Ok(())
+    }
+
+    #[test]
+    fn conditionals_keep_the_lines_of_the_branch_taken() -> anyhow::Result<()> {
+        let main = "\
+#include \"two.h\"
+#if TWO * 2 == 4 && !defined(THREE)
+int a;
+#  ifdef THREE
+int b;
+#  elif defined SIX
+int c;
+#  else
+int d;
+#  endif
+#elif 1
+int e;
+#endif
+#undef TWO
+#ifndef TWO
+int f;
+#endif
+";
+        let files = HashMap::from([("two.h".to_string(), "#define TWO 2\n".to_string())]);
+        let macros = Macros::from([("SIX".to_string(), String::new())]);
+        let preprocessed = preprocess("main.c".into(), main.to_string(), &files, macros)?;
+        assert_eq!(preprocessed.source, "int a;\nint c;\nint f;\n");
+        let meta = preprocessed.metadata();
+        let error = Error::new("")
+            .with_source(Span::new(preprocessed.source.find("int f").unwrap()), &meta);
+        assert!(error.to_string().contains("main.c:16:1"));
+
+        let unterminated = preprocess(
+            "main.c".into(),
+            "int a;\n#if X\n".to_string(),
+            &files,
+            Macros::new(),
+        );
+        assert!(matches!(
+            unterminated.unwrap_err().kind,
+            PreprocessError::Unterminated("if")
+        ));
+        let invalid = preprocess(
+            "main.c".into(),
+            "#if 1 +\n#endif\n".to_string(),
+            &files,
+            Macros::new(),
+        );
+        assert!(matches!(
+            invalid.unwrap_err().kind,
+            PreprocessError::InvalidCondition("if")
+        ));
+        Ok(())
+    }
+}
@@ -1,13 +1,167 @@
-use super::{Parse, ParseRes, Parser};
-use crate::ast::Program;
+use alloc::vec::Vec;
+
+use super::{
+    function::StorageSpecifiers,
+    lexer::{Source, TokenKind},
+    Parse, ParseErrorKind, ParseRes, Parser, WantedSpec,
+};
+use crate::ast::{Attribute, Function, GlobalRegister, GlobalVariable, Identifier, Program, Type};
+use crate::error::Span;
 
 impl<'source> Parse<'source> for Program<'source> {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
-        let mut vec = Vec::new();
-        // TODO: redefinitions.
-        while parser.peek_token()?.is_some() {
-            vec.push(parser.parse()?);
+        let mut functions = Vec::new();
+        let mut globals = Vec::new();
+        let mut global_registers = Vec::new();
+        let mut enums = Vec::new();
+        while let Some(token) = parser.peek_token()? {
+            if token == TokenKind::Identifier && parser.current_token_source() == "register" {
+                parser.accept_current();
+                global_registers.push(global_register(parser)?);
+            } else {
+                external_declaration(parser, &mut functions, &mut globals)?;
+            }
+            enums.extend(
+                parser
+                    .take_enumerators()
+                    .into_iter()
+                    .map(|(enumerators, _)| enumerators),
+            );
         }
-        Ok(Program(vec))
+        Ok(Program {
+            functions,
+            globals,
+            global_registers,
+            enums,
+        })
+    }
+}
+
+/// Parse a declaration at file scope: a function if the first name it declares is followed by its
+/// parameters, or else variables. A struct (or union) definition on its own only matters to the
+/// parser, which lays it out, while the enumerators of an enum are declared at file scope.
+fn external_declaration<'source>(
+    parser: &mut Parser<'source>,
+    functions: &mut Vec<Function<'source>>,
+    globals: &mut Vec<GlobalVariable<'source>>,
+) -> ParseRes<()> {
+    let specifiers = super::function::storage_specifiers(parser)?;
+    let (ty, ty_span) =
+        parser.with_context("parsing declaration", super::types::specifiers_or_void)?;
+    if let (Some(_), Some(TokenKind::Semicolon)) = (&ty, parser.peek_token()?) {
+        parser.accept_current();
+        return Ok(());
     }
+    let mut span = ty_span;
+    let pointer = match &ty {
+        Some(ty) => Some(super::types::pointers(parser, ty.clone(), &mut span)?),
+        None => None,
+    };
+    // the declarator of the first variable is parsed here, up to its name, to tell it apart
+    let first = if parser.peek_token()? == Some(TokenKind::Identifier) {
+        let (Identifier(name), name_span) = parser.parse()?;
+        let name = Source {
+            span: name_span,
+            source: name,
+        };
+        if parser.peek_token()? == Some(TokenKind::OpenParen) {
+            functions.push(
+                super::function::returning(parser, pointer, name, specifiers)
+                    .map_err(|e| e.add_context("parsing function"))?,
+            );
+            return Ok(());
+        }
+        let Some(ty) = pointer else {
+            return parser.emit_error_at(name_span, ParseErrorKind::VoidDeclaration);
+        };
+        (super::types::array_suffix(parser, ty, false)?, name)
+    } else {
+        let (ty, name) = super::types::declarator(parser, pointer, false, true)?;
+        (ty, name.expect("the declaration is named"))
+    };
+    global_variables(parser, (ty, ty_span), first, specifiers, globals)
+        .map_err(|e| e.add_context("parsing global variable"))
+}
+
+/// Parse the rest of a declaration of variables at file scope, whose first declarator has been
+/// parsed up to its initializer. The base type of the declaration is what the `*`s of the others
+/// make pointers to.
+fn global_variables<'source>(
+    parser: &mut Parser<'source>,
+    (ty, ty_span): (Option<Type>, Span),
+    (first_type, first_name): (Type, Source<'source>),
+    StorageSpecifiers {
+        linkage,
+        inline,
+        is_extern,
+        attributes,
+        align,
+    }: StorageSpecifiers<'source>,
+    globals: &mut Vec<GlobalVariable<'source>>,
+) -> ParseRes<()> {
+    if let Some(span) = inline {
+        return parser.emit_error_at(span, ParseErrorKind::FunctionOnly("inline".into()));
+    }
+    if let Some(&(attribute, span)) = attributes.first() {
+        let name = match attribute {
+            Attribute::Weak => "weak",
+            Attribute::Alias(_) => "alias",
+        };
+        return parser.emit_error_at(span, ParseErrorKind::FunctionOnly(name.into()));
+    }
+    let mut declarators = Vec::from([super::statement::initialized(
+        parser, first_type, first_name,
+    )?]);
+    if super::statement::another_declarator(parser)? {
+        declarators.extend(super::statement::declarators(parser, ty, ty_span)?);
+    }
+    parser.accept_current();
+    globals.extend(declarators.into_iter().map(|declarator| GlobalVariable {
+        declarator,
+        linkage,
+        is_extern,
+        align: align.clone(),
+    }));
+    Ok(())
+}
+
+/// Parse what follows `register` at file scope: the variable and the register it lives in, as in
+/// `register int x asm("x28");`
+fn global_register<'source>(parser: &mut Parser<'source>) -> ParseRes<GlobalRegister<'source>> {
+    parser.with_context("parsing global register variable", |parser| {
+        let (ty, _): (Type, Span) = parser.parse()?;
+        let (Identifier(name), span) = parser.parse()?;
+        const WANTED: WantedSpec<TokenKind> =
+            WantedSpec::Description("`asm` and the register of the variable");
+        let found = parser.expect_a_token(Some(WANTED))?;
+        if found != TokenKind::Identifier
+            || !matches!(parser.current_token_source(), "asm" | "__asm" | "__asm__")
+        {
+            return parser.reject_current_token(super::ParseErrorKind::Expected {
+                wanted: WANTED,
+                found,
+            });
+        }
+        parser.accept_current();
+        parser.expect_token(TokenKind::OpenParen)?;
+        parser.accept_current();
+        parser.expect_token(TokenKind::StringLiteral)?;
+        let (quoted, quoted_span) = (parser.current_token_source(), parser.current_token_span());
+        parser.accept_current();
+        parser.expect_token(TokenKind::CloseParen)?;
+        parser.accept_current();
+        parser.expect_token(TokenKind::Semicolon)?;
+        parser.accept_current();
+        Ok(GlobalRegister {
+            ty,
+            name: Source { span, source: name },
+            register: Source {
+                span: Span {
+                    offset: quoted_span.offset + 1,
+                    len: quoted_span.len - 2,
+                },
+                source: &quoted[1..quoted.len() - 1],
+            },
+        })
+    })
 }
@@ -1,85 +1,193 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
 use super::{
-    lexer::{Operator, TokenKind},
-    Parse, ParseRes, Parser,
+    lexer::{Operator, Source, TokenKind},
+    Parse, ParseErrorKind, ParseRes, Parser, WantedSpec,
 };
 use crate::{
-    ast::{Block, Expr, Identifier, Statement},
+    ast::{Block, Declarator, Expr, Identifier, Initializer, Statement, Type},
     error::Span,
 };
 
 impl<'source> Parse<'source> for (Statement<'source>, Span) {
     fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
-        parser.with_context("parsing statement", |parser| {
-            Ok(match parser.peek_token()? {
-                // TODO: add better description of what is expected
-                Some(TokenKind::Identifier) => {
-                    let source = parser.current_token_source();
-                    let start = parser.current_position();
-                    match source {
-                        "if" => {
-                            let offset = parser.current_position();
-                            parser.accept_current();
-                            let (condition, true_branch, false_branch, len) = if_statement(parser)?;
-                            (
-                                Statement::IfStatement {
-                                    condition,
-                                    true_branch: (Box::new(true_branch.0), true_branch.1),
-                                    false_branch: false_branch.map(|(a, b)| (Box::new(a), b)),
-                                },
-                                Span { offset, len },
-                            )
-                        }
-                        "return" => {
-                            parser.accept_current();
-                            let return_expr = parser.parse()?;
-                            parser.expect_token(TokenKind::Semicolon)?;
-                            let end = parser.current_position() + 1;
-                            parser.accept_current();
-                            (
-                                Statement::Return(return_expr),
-                                Span {
-                                    offset: start,
-                                    len: end - start,
-                                },
-                            )
-                        }
-                        "int" => {
-                            parser.accept_current();
-                            let (Identifier(name), span) = parser.parse()?;
-                            let init = if let Some(TokenKind::Operator {
-                                kind: Operator::Equals,
-                                ..
-                            }) = parser.peek_token()?
-                            {
-                                parser.accept_current();
-                                parser.parse().map(Some)?
-                            } else {
-                                None
-                            };
-                            parser.expect_token(TokenKind::Semicolon)?;
-                            let end = parser.current_position() + 1;
-                            parser.accept_current();
-                            (
-                                Statement::DeclareVar {
-                                    name: super::lexer::Source { span, source: name },
-                                    init,
-                                },
-                                Span {
-                                    offset: start,
-                                    len: end - start,
-                                },
-                            )
-                        }
-                        _ => single_expr(parser)?,
-                    }
+        parser.nested(|parser| parser.with_context("parsing statement", statement))
+    }
+}
+
+fn statement<'source>(parser: &mut Parser<'source>) -> ParseRes<(Statement<'source>, Span)> {
+    Ok(match parser.peek_token()? {
+        // TODO: add better description of what is expected
+        Some(TokenKind::Identifier) => {
+            let source = parser.current_token_source();
+            let start = parser.current_position();
+            match source {
+                "if" => {
+                    let offset = parser.current_position();
+                    parser.accept_current();
+                    let (condition, true_branch, false_branch, len) = if_statement(parser)?;
+                    (
+                        Statement::IfStatement {
+                            condition,
+                            true_branch: (Box::new(true_branch.0), true_branch.1),
+                            false_branch: false_branch.map(|(a, b)| (Box::new(a), b)),
+                        },
+                        Span { offset, len },
+                    )
+                }
+                "return" => {
+                    parser.accept_current();
+                    let return_expr = match parser.peek_token()? {
+                        Some(TokenKind::Semicolon) => None,
+                        _ => Some(parser.parse()?),
+                    };
+                    parser.expect_token(TokenKind::Semicolon)?;
+                    let end = parser.current_position() + 1;
+                    parser.accept_current();
+                    (
+                        Statement::Return(return_expr),
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
+                }
+                "switch" => {
+                    parser.accept_current();
+                    let (scrutinee, body) = switch_statement(parser)?;
+                    let end = body.1.offset + body.1.len;
+                    (
+                        Statement::Switch {
+                            scrutinee,
+                            body: (Box::new(body.0), body.1),
+                        },
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
+                }
+                "case" => {
+                    parser.accept_current();
+                    let value = parser.with_context("parsing case label", |parser| {
+                        let value = parser.parse()?;
+                        parser.expect_token(TokenKind::Colon)?;
+                        Ok(value)
+                    })?;
+                    let end = parser.current_position() + 1;
+                    parser.accept_current();
+                    (
+                        Statement::Case(value),
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
                 }
-                Some(TokenKind::OpenBrace) => {
-                    parser.parse().map(|(Block { statements }, block_span)| {
-                        (Statement::Block(statements), block_span)
-                    })?
+                "default" => {
+                    parser.accept_current();
+                    parser.expect_token(TokenKind::Colon)?;
+                    let end = parser.current_position() + 1;
+                    parser.accept_current();
+                    (
+                        Statement::Default,
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
+                }
+                "break" => {
+                    parser.accept_current();
+                    parser.expect_token(TokenKind::Semicolon)?;
+                    let end = parser.current_position() + 1;
+                    parser.accept_current();
+                    (
+                        Statement::LoopBreak,
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
+                }
+                _ if Type::is_alignment_keyword(source)
+                    || Type::is_type_keyword(source)
+                    || source == "void" =>
+                {
+                    let align = alignment_specifiers(parser)?;
+                    let (ty, ty_span) = super::types::specifiers_or_void(parser)?;
+                    // a struct, union or enum definition on its own declares nothing else
+                    if let (Some(_), Some(TokenKind::Semicolon)) = (&ty, parser.peek_token()?) {
+                        let end = parser.current_position() + 1;
+                        parser.accept_current();
+                        return Ok((
+                            Statement::Block(Vec::new()),
+                            Span {
+                                offset: start,
+                                len: end - start,
+                            },
+                        ));
+                    }
+                    let declarators = declarators(parser, ty, ty_span)?;
+                    let end = parser.current_position() + 1;
+                    parser.accept_current();
+                    (
+                        Statement::DeclareVars { declarators, align },
+                        Span {
+                            offset: start,
+                            len: end - start,
+                        },
+                    )
                 }
                 _ => single_expr(parser)?,
-            })
+            }
+        }
+        Some(TokenKind::OpenBrace) => parser
+            .parse()
+            .map(|(Block { statements }, block_span)| (Statement::Block(statements), block_span))?,
+        _ => single_expr(parser)?,
+    })
+}
+
+impl<'source> Parse<'source> for (Initializer<'source>, Span) {
+    fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
+        if parser.peek_token()? != Some(TokenKind::OpenBrace) {
+            let (expr, span) = parser.parse()?;
+            return Ok((Initializer::Expr(expr), span));
+        }
+        parser.with_context("parsing initializer list", |parser| {
+            let start = parser.current_position();
+            parser.accept_current();
+            let mut inits = Vec::new();
+            // a trailing comma is allowed, but the list can't be empty
+            loop {
+                inits.push(parser.nested(Parser::parse)?);
+                match parser.expect_a_token(Some(WantedSpec::Description("`,` or `}`")))? {
+                    TokenKind::Comma => {
+                        parser.accept_current();
+                        if parser.peek_token()? == Some(TokenKind::CloseBrace) {
+                            break;
+                        }
+                    }
+                    TokenKind::CloseBrace => break,
+                    found => {
+                        return parser.reject_current_token(ParseErrorKind::Expected {
+                            wanted: WantedSpec::Description("`,` or `}`"),
+                            found,
+                        })
+                    }
+                }
+            }
+            let end = parser.current_position() + 1;
+            parser.accept_current();
+            Ok((
+                Initializer::List(inits),
+                Span {
+                    offset: start,
+                    len: end - start,
+                },
+            ))
         })
     }
 }
@@ -92,14 +200,101 @@ fn single_expr<'source>(parser: &mut Parser<'source>) -> ParseRes<(Statement<'so
     Ok((Statement::SingleExpr(expr), expr_span))
 }
 
-fn if_statement<'code>(
-    parser: &mut Parser<'code>,
-) -> ParseRes<(
+/// Parse the declarators of a declaration of type `ty` up to its `;`, each with its initializer if
+/// it has one. The `*`s of a declarator make pointers to the type.
+pub(super) fn declarators<'source>(
+    parser: &mut Parser<'source>,
+    ty: Option<Type>,
+    ty_span: Span,
+) -> ParseRes<Vec<Declarator<'source>>> {
+    let mut declarators = Vec::new();
+    loop {
+        let mut span = ty_span;
+        let pointer = match &ty {
+            Some(ty) => Some(super::types::pointers(parser, ty.clone(), &mut span)?),
+            None => None,
+        };
+        let (ty, name) = super::types::declarator(parser, pointer, false, true)?;
+        let name = name.expect("the declaration is named");
+        declarators.push(initialized(parser, ty, name)?);
+        if !another_declarator(parser)? {
+            return Ok(declarators);
+        }
+    }
+}
+
+/// Parse the `= init` that may follow the declarator of a variable
+pub(super) fn initialized<'source>(
+    parser: &mut Parser<'source>,
+    ty: Type,
+    name: Source<'source>,
+) -> ParseRes<Declarator<'source>> {
+    let init = if let Some(TokenKind::Operator {
+        kind: Operator::Equals,
+        ..
+    }) = parser.peek_token()?
+    {
+        parser.accept_current();
+        parser.parse().map(Some)?
+    } else {
+        None
+    };
+    Ok(Declarator { ty, name, init })
+}
+
+/// Whether a `,` follows, and another declarator after it, or the `;` that ends the declaration,
+/// which is left for the caller to accept
+pub(super) fn another_declarator(parser: &mut Parser) -> ParseRes<bool> {
+    match parser.expect_a_token(Some(WantedSpec::Description("`,` or `;`")))? {
+        TokenKind::Comma => {
+            parser.accept_current();
+            Ok(true)
+        }
+        TokenKind::Semicolon => Ok(false),
+        found => parser.reject_current_token(ParseErrorKind::Expected {
+            wanted: WantedSpec::Description("`,` or `;`"),
+            found,
+        }),
+    }
+}
+
+/// Parse the `_Alignas(...)` specifiers before a declaration's type, if any
+pub(super) fn alignment_specifiers<'source>(
+    parser: &mut Parser<'source>,
+) -> ParseRes<Vec<(Expr<'source>, Span)>> {
+    let mut align = Vec::new();
+    while parser.peek_token()? == Some(TokenKind::Identifier)
+        && Type::is_alignment_keyword(parser.current_token_source())
+    {
+        parser.accept_current();
+        parser.with_context("parsing alignment specifier", |parser| {
+            parser.expect_token(TokenKind::OpenParen)?;
+            parser.accept_current();
+            align.push(match parser.peek_token()? {
+                Some(TokenKind::Identifier)
+                    if Type::is_type_keyword(parser.current_token_source()) =>
+                {
+                    let (ty, span) = parser.parse()?;
+                    (Expr::AlignOf(ty), span)
+                }
+                _ => parser.parse()?,
+            });
+            parser.expect_token(TokenKind::CloseParen)?;
+            parser.accept_current();
+            Ok(())
+        })?;
+    }
+    Ok(align)
+}
+
+type IfStatementParts<'code> = (
     (Expr<'code>, Span),
     (Statement<'code>, Span),
     Option<(Statement<'code>, Span)>,
     usize,
-)> {
+);
+
+fn if_statement<'code>(parser: &mut Parser<'code>) -> ParseRes<IfStatementParts<'code>> {
     let (condition, condition_span) =
         parser.with_context("parsing if statement's condition", |parser| {
             parser.expect_token(TokenKind::OpenParen)?;
@@ -149,3 +344,21 @@ fn if_statement<'code>(
         total_span_len,
     ))
 }
+
+type SwitchStatementParts<'code> = ((Expr<'code>, Span), (Statement<'code>, Span));
+
+fn switch_statement<'code>(parser: &mut Parser<'code>) -> ParseRes<SwitchStatementParts<'code>> {
+    let scrutinee = parser.with_context(
+        "parsing switch statement's controlling expression",
+        |parser| {
+            parser.expect_token(TokenKind::OpenParen)?;
+            parser.accept_current();
+            let scrutinee: (_, Span) = parser.parse()?;
+            parser.expect_token(TokenKind::CloseParen)?;
+            parser.accept_current();
+            Ok(scrutinee)
+        },
+    )?;
+    let body = parser.parse()?;
+    Ok((scrutinee, body))
+}
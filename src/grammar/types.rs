@@ -0,0 +1,498 @@
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{
+    expr,
+    lexer::{Operator, Source, TokenKind},
+    Parse, ParseErrorKind, ParseRes, Parser, WantedSpec,
+};
+use crate::{
+    ast::{AggregateKind, Enumerator, FunctionType, Identifier, StructType, Type},
+    error::Span,
+};
+use alloc::sync::Arc;
+
+impl<'source> Parse<'source> for (Type, Span) {
+    fn parse(parser: &mut Parser<'source>) -> ParseRes<Self> {
+        let (ty, mut span) = specifiers(parser)?;
+        let ty = pointers(parser, ty, &mut span)?;
+        Ok((ty, span))
+    }
+}
+
+/// Parse a type up to the `*`s that make pointers to it, which in a declaration belong to each of
+/// its declarators
+pub fn specifiers(parser: &mut Parser) -> ParseRes<(Type, Span)> {
+    parser.with_context("parsing type", |parser| {
+        parser.expect_token(TokenKind::Identifier)?;
+        let mut span = parser.current_token_span();
+        // the qualifiers go before or after what they qualify
+        let mut volatile = qualifiers(parser, &mut span)?;
+        parser.expect_token(TokenKind::Identifier)?;
+        let ty = if let Some(kind) = AggregateKind::from_keyword(parser.current_token_source()) {
+            parser.accept_current();
+            struct_type(parser, kind)?
+        } else if parser.current_token_source() == "enum" {
+            parser.accept_current();
+            enum_type(parser)?
+        } else {
+            // `signed` or `unsigned` go first, and are an `int` on their own
+            let signedness = Type::is_signedness_keyword(parser.current_token_source())
+                .then(|| parser.current_token_source() == "unsigned");
+            if signedness.is_some() {
+                parser.accept_current();
+            }
+            let keyword = match parser.peek_token()? {
+                Some(TokenKind::Identifier) => Type::from_keyword(parser.current_token_source()),
+                _ => None,
+            };
+            let ty = match keyword {
+                Some(ty) => {
+                    let end = parser.current_token_span();
+                    span.len = end.offset + end.len - span.offset;
+                    parser.accept_current();
+                    ty
+                }
+                None if signedness.is_some() => Type::Int,
+                None => {
+                    return parser.reject_current_token(ParseErrorKind::Expected {
+                        wanted: WantedSpec::Description("type"),
+                        found: TokenKind::Identifier,
+                    })
+                }
+            };
+            // `long double` is kept as precise as a `double`
+            let ty = if ty == Type::Long && optional_keywords(parser, &["double"], &mut span)? {
+                Type::Double
+            } else {
+                // `long long int` and `short int` mean the same as `long` and `short`
+                let rest: &[&str] = match ty {
+                    Type::Long => &["long", "int"],
+                    Type::Short => &["int"],
+                    _ => &[],
+                };
+                optional_keywords(parser, rest, &mut span)?;
+                ty
+            };
+            match signedness {
+                Some(unsigned) => ty.with_signedness(unsigned).map_or_else(
+                    || parser.emit_error_at(span, ParseErrorKind::SignedNonInteger(ty)),
+                    Ok,
+                )?,
+                None => ty,
+            }
+        };
+        volatile |= qualifiers(parser, &mut span)?;
+        Ok((if volatile { ty.volatile() } else { ty }, span))
+    })
+}
+
+/// Parse the `*`s after a type, each of which makes a pointer to what came before it. A pointer
+/// is volatile itself if `volatile` follows its `*`, among its other qualifiers.
+pub fn pointers(parser: &mut Parser, mut ty: Type, span: &mut Span) -> ParseRes<Type> {
+    while let Some(TokenKind::Operator {
+        kind: Operator::Star,
+        has_equal: false,
+    }) = parser.peek_token()?
+    {
+        ty = Type::Pointer(Box::new(ty));
+        span.len = parser.current_position() + 1 - span.offset;
+        parser.accept_current();
+        if qualifiers(parser, span)? {
+            ty = ty.volatile();
+        }
+    }
+    Ok(ty)
+}
+
+/// Skip the qualifiers there are, in any order, extending the span over them. Tells whether
+/// `volatile` is one of them.
+fn qualifiers(parser: &mut Parser, span: &mut Span) -> ParseRes<bool> {
+    let mut volatile = false;
+    while parser.peek_token()? == Some(TokenKind::Identifier)
+        && Type::is_qualifier_keyword(parser.current_token_source())
+    {
+        volatile |= parser.current_token_source() == "volatile";
+        let end = parser.current_token_span();
+        span.len = end.offset + end.len - span.offset;
+        parser.accept_current();
+    }
+    Ok(volatile)
+}
+
+/// Skip each of the keywords, in order, if they're there, extending the span over them. Tells
+/// whether any of them was.
+fn optional_keywords(parser: &mut Parser, keywords: &[&str], span: &mut Span) -> ParseRes<bool> {
+    let mut found = false;
+    for &keyword in keywords {
+        if parser.peek_token()? == Some(TokenKind::Identifier)
+            && parser.current_token_source() == keyword
+        {
+            let end = parser.current_token_span();
+            span.len = end.offset + end.len - span.offset;
+            parser.accept_current();
+            found = true;
+        }
+    }
+    Ok(found)
+}
+
+/// Parse what follows `struct` (or `union`): the tag, if any, and the members if this is where the
+/// type is defined. A type without a tag can't be named again, so it's always defined.
+fn struct_type<'source>(parser: &mut Parser<'source>, kind: AggregateKind) -> ParseRes<Type> {
+    let tag = match parser.peek_token()? {
+        Some(TokenKind::Identifier) => {
+            let tag = (parser.current_token_source(), parser.current_token_span());
+            parser.accept_current();
+            Some(tag)
+        }
+        _ => None,
+    };
+    let found = parser.expect_a_token(Some(WantedSpec::Description("tag or `{`")))?;
+    if found != TokenKind::OpenBrace {
+        let Some((tag, tag_span)) = tag else {
+            return parser.reject_current_token(ParseErrorKind::Expected {
+                wanted: WantedSpec::Description("tag or `{`"),
+                found,
+            });
+        };
+        return match parser.structs.get(tag) {
+            Some(struct_type) if struct_type.kind == kind => {
+                Ok(Type::Struct(Arc::clone(struct_type)))
+            }
+            // structs and unions share their tags
+            Some(_) => parser.emit_error_at(
+                tag_span,
+                ParseErrorKind::WrongTagKind(kind, tag.to_string()),
+            ),
+            None => {
+                parser.emit_error_at(tag_span, ParseErrorKind::UnknownTag(kind, tag.to_string()))
+            }
+        };
+    }
+    if let Some((tag, tag_span)) = tag {
+        if parser.structs.contains_key(tag) || parser.enums.contains(tag) {
+            return parser.emit_error_at(tag_span, ParseErrorKind::TagRedefined(tag.to_string()));
+        }
+    }
+    parser.with_context("parsing members", |parser| {
+        parser.accept_current();
+        let mut members: Vec<(String, Type)> = Vec::new();
+        // the names of the members so far, along with those of the anonymous members' members
+        let mut names: Vec<String> = Vec::new();
+        let mut flexible = None;
+        while parser.peek_token()? != Some(TokenKind::CloseBrace) {
+            let (ty, ty_span): (Type, Span) = parser.parse()?;
+            // only the last member may be a flexible array
+            if let Some((flexible, flexible_span)) = flexible {
+                return parser.emit_error_at(
+                    flexible_span,
+                    ParseErrorKind::FlexibleArrayNotLast(String::from(flexible)),
+                );
+            }
+            if let Type::Struct(struct_type) = &ty {
+                if struct_type.has_flexible_array() {
+                    return parser.emit_error_at(
+                        ty_span,
+                        ParseErrorKind::NestedFlexibleArray(ty.to_string()),
+                    );
+                }
+            }
+            // a struct or union without a tag nor a name is an anonymous member, whose members
+            // are accessed as members of this one
+            let anonymous = match &ty {
+                Type::Struct(struct_type)
+                    if struct_type.tag.is_empty()
+                        && parser.peek_token()? == Some(TokenKind::Semicolon) =>
+                {
+                    Some(Arc::clone(struct_type))
+                }
+                _ => None,
+            };
+            let (ty, name, span, introduced) = match anonymous {
+                Some(struct_type) => {
+                    let introduced = struct_type
+                        .member_names()
+                        .into_iter()
+                        .map(String::from)
+                        .collect();
+                    (ty, "", ty_span, introduced)
+                }
+                None => {
+                    let (ty, name) = declarator(parser, Some(ty), true, true)?;
+                    let Source { source: name, span } = name.expect("the member is named");
+                    (ty, name, span, vec![name.to_string()])
+                }
+            };
+            if let Some(duplicate) = introduced.iter().find(|name| names.contains(name)) {
+                return parser
+                    .emit_error_at(span, ParseErrorKind::DuplicateMember(duplicate.clone()));
+            }
+            names.extend(introduced);
+            if ty.is_flexible_array() {
+                if kind == AggregateKind::Union {
+                    return parser
+                        .emit_error_at(span, ParseErrorKind::FlexibleArrayInUnion(name.into()));
+                }
+                if members.is_empty() {
+                    return parser
+                        .emit_error_at(span, ParseErrorKind::FlexibleArrayAlone(name.into()));
+                }
+                flexible = Some((name, span));
+            }
+            members.push((name.to_string(), ty));
+            parser.expect_token(TokenKind::Semicolon)?;
+            parser.accept_current();
+        }
+        parser.accept_current();
+        let struct_type = Arc::new(StructType::new(
+            kind,
+            tag.map_or_else(String::new, |(tag, _)| tag.to_string()),
+            members,
+        ));
+        if let Some((tag, _)) = tag {
+            parser.structs.insert(tag, Arc::clone(&struct_type));
+        }
+        Ok(Type::Struct(struct_type))
+    })
+}
+
+/// Parse what follows `enum`: the tag, if any, and the enumerators if this is where the enum is
+/// defined. Enums are `int`s, and their enumerators are left for the enclosing scope to declare.
+fn enum_type<'source>(parser: &mut Parser<'source>) -> ParseRes<Type> {
+    let tag = match parser.peek_token()? {
+        Some(TokenKind::Identifier) => {
+            let tag = (parser.current_token_source(), parser.current_token_span());
+            parser.accept_current();
+            Some(tag)
+        }
+        _ => None,
+    };
+    let found = parser.expect_a_token(Some(WantedSpec::Description("enum tag or `{`")))?;
+    if found != TokenKind::OpenBrace {
+        return match tag {
+            Some((tag, _)) if parser.enums.contains(tag) => Ok(Type::Int),
+            Some((tag, tag_span)) => {
+                parser.emit_error_at(tag_span, ParseErrorKind::UnknownEnum(tag.to_string()))
+            }
+            None => parser.reject_current_token(ParseErrorKind::Expected {
+                wanted: WantedSpec::Description("enum tag or `{`"),
+                found,
+            }),
+        };
+    }
+    if let Some((tag, tag_span)) = tag {
+        if parser.structs.contains_key(tag) || !parser.enums.insert(tag) {
+            return parser.emit_error_at(tag_span, ParseErrorKind::TagRedefined(tag.to_string()));
+        }
+    }
+    let start = parser.current_position();
+    parser.accept_current();
+    let enumerators = parser.with_context("parsing enumerators", |parser| {
+        let mut enumerators = Vec::new();
+        loop {
+            let (Identifier(name), span) = parser.parse()?;
+            let value = if let Some(TokenKind::Operator {
+                kind: Operator::Equals,
+                has_equal: false,
+            }) = parser.peek_token()?
+            {
+                parser.accept_current();
+                Some(parser.parse()?)
+            } else {
+                None
+            };
+            enumerators.push(Enumerator {
+                name: super::lexer::Source { span, source: name },
+                value,
+            });
+            // a trailing comma is allowed
+            match parser.expect_a_token(Some(WantedSpec::Description("`,` or `}`")))? {
+                TokenKind::Comma => {
+                    parser.accept_current();
+                    if parser.peek_token()? == Some(TokenKind::CloseBrace) {
+                        return Ok(enumerators);
+                    }
+                }
+                TokenKind::CloseBrace => return Ok(enumerators),
+                found => {
+                    return parser.reject_current_token(ParseErrorKind::Expected {
+                        wanted: WantedSpec::Description("`,` or `}`"),
+                        found,
+                    })
+                }
+            }
+        }
+    })?;
+    let end = parser.current_position() + 1;
+    parser.accept_current();
+    parser.enumerators.push((
+        enumerators,
+        Span {
+            offset: start,
+            len: end - start,
+        },
+    ));
+    Ok(Type::Int)
+}
+
+/// Parse the `[len]` that follows the name of an array declaration, if any. A struct member may
+/// be `flexible`, leaving out the length.
+pub fn array_suffix(parser: &mut Parser, ty: Type, flexible: bool) -> ParseRes<Type> {
+    if parser.peek_token()? != Some(TokenKind::OpenBracket) {
+        return Ok(ty);
+    }
+    if let Type::Struct(struct_type) = &ty {
+        if struct_type.has_flexible_array() {
+            return parser
+                .reject_current_token(ParseErrorKind::NestedFlexibleArray(ty.to_string()));
+        }
+    }
+    parser.with_context("parsing array length", |parser| {
+        parser.accept_current();
+        if flexible && parser.peek_token()? == Some(TokenKind::CloseBracket) {
+            parser.accept_current();
+            return Ok(Type::Array {
+                element: Box::new(ty.clone()),
+                len: 0,
+            });
+        }
+        parser.expect_token(TokenKind::Number)?;
+        let digits = parser
+            .current_token_source()
+            .trim_end_matches(['u', 'U', 'l', 'L']);
+        let len = match expr::integer_value(digits) {
+            Some(len) if len > 0 => len as usize,
+            _ => {
+                return parser.reject_current_token(ParseErrorKind::Expected {
+                    wanted: WantedSpec::Description("positive array length"),
+                    found: TokenKind::Number,
+                })
+            }
+        };
+        parser.accept_current();
+        parser.expect_token(TokenKind::CloseBracket)?;
+        parser.accept_current();
+        Ok(Type::Array {
+            element: Box::new(ty.clone()),
+            len,
+        })
+    })
+}
+
+/// Parse a type that may be `void`, which is `None`
+pub fn type_or_void(parser: &mut Parser) -> ParseRes<(Option<Type>, Span)> {
+    if let Some(span) = void(parser)? {
+        return Ok((None, span));
+    }
+    let (ty, span) = parser.parse()?;
+    Ok((Some(ty), span))
+}
+
+/// Parse the type a declaration starts with up to the `*`s of its first declarator, as in
+/// [`specifiers`], which may be `void` too
+pub fn specifiers_or_void(parser: &mut Parser) -> ParseRes<(Option<Type>, Span)> {
+    if let Some(span) = void(parser)? {
+        return Ok((None, span));
+    }
+    let (ty, span) = specifiers(parser)?;
+    Ok((Some(ty), span))
+}
+
+/// Skip `void` if it's next, giving where it is
+fn void(parser: &mut Parser) -> ParseRes<Option<Span>> {
+    if parser.peek_token()? == Some(TokenKind::Identifier)
+        && parser.current_token_source() == "void"
+    {
+        let span = parser.current_token_span();
+        parser.accept_current();
+        return Ok(Some(span));
+    }
+    Ok(None)
+}
+
+/// Parse what follows the type of a declaration: the name it declares and the `[len]` of an array
+/// if there's one (which a struct member may leave out, if `flexible`), or `(*name)(parameters)`
+/// for a pointer to a function returning the type. Only such a pointer may be declared with
+/// `void`, which is `None`. The name may be left out if it isn't `named`, as in a cast.
+pub fn declarator<'source>(
+    parser: &mut Parser<'source>,
+    ty: Option<Type>,
+    flexible: bool,
+    named: bool,
+) -> ParseRes<(Type, Option<Source<'source>>)> {
+    if parser.peek_token()? != Some(TokenKind::OpenParen) {
+        let Some(ty) = ty else {
+            return parser.reject_current_token(ParseErrorKind::VoidDeclaration);
+        };
+        let name = name(parser, named)?;
+        return Ok((array_suffix(parser, ty, flexible)?, name));
+    }
+    parser.with_context("parsing function pointer", |parser| {
+        parser.accept_current();
+        parser.expect_token(TokenKind::Operator {
+            kind: Operator::Star,
+            has_equal: false,
+        })?;
+        parser.accept_current();
+        let name = name(parser, named)?;
+        parser.expect_token(TokenKind::CloseParen)?;
+        parser.accept_current();
+        parser.expect_token(TokenKind::OpenParen)?;
+        parser.accept_current();
+        let (params, unspecified, variadic) = super::function::parameters(parser, false)?;
+        let function_type = FunctionType {
+            return_type: ty.clone(),
+            params: (!unspecified).then(|| {
+                params
+                    .into_iter()
+                    .map(|(ty, _)| ty.unqualified().clone())
+                    .collect()
+            }),
+            variadic,
+        };
+        Ok((Type::FunctionPointer(Box::new(function_type)), name))
+    })
+}
+
+/// The name being declared, which may be left out if it isn't `named`
+fn name<'source>(parser: &mut Parser<'source>, named: bool) -> ParseRes<Option<Source<'source>>> {
+    if !named && parser.peek_token()? != Some(TokenKind::Identifier) {
+        return Ok(None);
+    }
+    let (Identifier(name), span) = parser.parse()?;
+    Ok(Some(Source { span, source: name }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SourceMetadata;
+
+    #[test]
+    fn flexible_array_members_take_no_room_of_their_own() {
+        let parse = |source| {
+            let meta = SourceMetadata::new(source);
+            let parsed = Parser::new(&meta).parse::<(Type, Span)>();
+            parsed
+                .map(|(ty, _)| ty)
+                .map_err(|error| error.kind.to_string())
+        };
+        let Ok(Type::Struct(packet)) = parse("struct packet { char kind; long data[]; }") else {
+            panic!("the struct is fine");
+        };
+        // the array starts where its elements must, and the struct is padded to them
+        let data = packet.member("data").expect("the array is a member");
+        assert_eq!((data.offset, packet.size, packet.align), (8, 8, 8));
+        assert_eq!(data.ty.to_string(), "long[]");
+
+        let error = |source| parse(source).expect_err("the struct is wrong");
+        assert!(error("struct s { int n; int a[]; int m; }").contains("not the last member"));
+        assert!(error("struct s { int a[]; }").contains("no other members"));
+        assert!(error("union u { int n; int a[]; }").contains("in a union"));
+        let nested = "struct outer { struct inner { int n; int a[]; } inner; int m; }";
+        assert!(error(nested).contains("can't be nested"));
+    }
+}
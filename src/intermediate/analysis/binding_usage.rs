@@ -1,7 +1,10 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 use crate::intermediate::{BasicBlock, BlockEnd, Branch, IR};
-use crate::intermediate::{Binding, CouldBeConstant, Statement, Value};
-use std::collections::HashMap;
-use std::iter::FromIterator;
+use crate::intermediate::{Binding, Callee, CouldBeConstant, Statement, Value};
+use core::iter::FromIterator;
+use hashbrown::HashMap;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Usage {
@@ -9,6 +12,8 @@ pub enum Usage {
     Binding(Binding),
     /// the binding is used in a store
     Store(Binding),
+    /// the binding is written to the register of a global register variable
+    SetRegister(u8),
     /// the binding is used in a return statement
     Return,
     /// the binding is used as a switch for branch
@@ -27,10 +32,19 @@ pub fn get_usage_map(ir: &IR) -> UsageMap {
                     mem_binding,
                     binding,
                     ..
+                }
+                | Statement::AtomicStore {
+                    mem_binding,
+                    binding,
+                    ..
                 } => usage_map
                     .entry(*binding)
                     .or_default()
                     .push(Usage::Store(*mem_binding)),
+                Statement::SetRegister { register, binding } => usage_map
+                    .entry(*binding)
+                    .or_default()
+                    .push(Usage::SetRegister(*register)),
                 Statement::Assign { index, value } => {
                     for dep in value.binding_deps() {
                         usage_map
@@ -86,69 +100,183 @@ impl BindingUsage for CouldBeConstant {
 impl BindingUsage for Value {
     fn contains_binding(&self, search_target: Binding) -> bool {
         match self {
-            Value::Allocate { size: _ } => false,
+            Value::Allocate { .. } => false,
             Value::Phi { nodes } => nodes
                 .iter()
                 .any(|node| node.value.contains_binding(search_target)),
-            Value::Add { lhs, rhs }
-            | Value::Subtract { lhs, rhs }
-            | Value::Multiply { lhs, rhs }
-            | Value::Divide {
-                lhs,
-                rhs,
-                is_signed: _,
-            }
-            | Value::Lsl { lhs, rhs }
-            | Value::Lsr { lhs, rhs }
-            | Value::And { lhs, rhs }
-            | Value::Or { lhs, rhs }
-            | Value::Xor { lhs, rhs }
-            | Value::Cmp {
-                condition: _,
-                lhs,
-                rhs,
-            } => lhs.contains_binding(search_target) | rhs.contains_binding(search_target),
+            Value::Add { lhs, rhs, .. }
+            | Value::Subtract { lhs, rhs, .. }
+            | Value::Multiply { lhs, rhs, .. }
+            | Value::Divide { lhs, rhs, .. }
+            | Value::Lsl { lhs, rhs, .. }
+            | Value::Lsr { lhs, rhs, .. }
+            | Value::Asr { lhs, rhs, .. }
+            | Value::And { lhs, rhs, .. }
+            | Value::Or { lhs, rhs, .. }
+            | Value::Xor { lhs, rhs, .. }
+            | Value::Cmp { lhs, rhs, .. } => {
+                lhs.contains_binding(search_target) | rhs.contains_binding(search_target)
+            }
+            Value::FloatArithmetic { lhs, rhs, .. } | Value::FloatCmp { lhs, rhs, .. } => {
+                lhs.contains_binding(search_target) | rhs.contains_binding(search_target)
+            }
+            Value::Select {
+                flag,
+                if_true,
+                if_false,
+            } => {
+                flag.contains_binding(search_target)
+                    | if_true.contains_binding(search_target)
+                    | if_false.contains_binding(search_target)
+            }
             Value::Load {
                 mem_binding,
                 byte_size: _,
-            } => mem_binding.contains_binding(search_target),
-            Value::FlipBits { binding } | Value::Negate { binding } => {
-                binding.contains_binding(search_target)
+                volatile: _,
             }
-            Value::Constant(_) => false,
+            | Value::AtomicLoad { mem_binding, .. }
+            | Value::Address { mem_binding } => mem_binding.contains_binding(search_target),
+            Value::AtomicFetchAdd {
+                mem_binding,
+                binding,
+                ..
+            } => {
+                mem_binding.contains_binding(search_target)
+                    | binding.contains_binding(search_target)
+            }
+            Value::FlipBits { binding, .. }
+            | Value::Negate { binding, .. }
+            | Value::SignExtend { binding, .. }
+            | Value::FloatNegate { binding, .. }
+            | Value::IntToFloat { binding, .. }
+            | Value::FloatToInt { binding, .. }
+            | Value::FloatConvert { binding, .. } => binding.contains_binding(search_target),
+            Value::Constant(_)
+            | Value::FloatConstant { .. }
+            | Value::Undefined
+            | Value::Argument(_)
+            | Value::FloatArgument(_)
+            | Value::GlobalRegister(_)
+            | Value::ReadOnlyAddress(_)
+            | Value::FunctionAddress(_)
+            | Value::GlobalAddress(_)
+            | Value::ResultAddress { .. } => false,
             Value::Binding(b) => b.contains_binding(search_target),
+            Value::Call { function: _, args } => args.contains(&search_target),
+            Value::CallPointer { pointer, args } => {
+                *pointer == search_target || args.contains(&search_target)
+            }
+            Value::CallStruct {
+                callee,
+                args,
+                result,
+                ..
+            } => {
+                *callee == Callee::Pointer(search_target)
+                    || args.contains(&search_target)
+                    || *result == search_target
+            }
+            Value::CallFloat { callee, args, .. } => {
+                *callee == Callee::Pointer(search_target) || args.contains(&search_target)
+            }
+            Value::ReturnedStruct { memory, .. } => *memory == search_target,
+            Value::ElementAddress { base, index, .. } => {
+                base.contains_binding(search_target) | index.contains_binding(search_target)
+            }
         }
     }
 
     fn binding_deps(&self) -> Vec<Binding> {
         match self {
-            Value::Constant(_) | Value::Allocate { size: _ } => vec![],
+            Value::Constant(_)
+            | Value::FloatConstant { .. }
+            | Value::Undefined
+            | Value::Allocate { .. }
+            | Value::Argument(_)
+            | Value::FloatArgument(_)
+            | Value::GlobalRegister(_)
+            | Value::ReadOnlyAddress(_)
+            | Value::FunctionAddress(_)
+            | Value::GlobalAddress(_)
+            | Value::ResultAddress { .. } => vec![],
             Value::Phi { nodes } => nodes.iter().map(|node| node.value).collect(),
-            Value::Add { lhs, rhs }
-            | Value::Subtract { lhs, rhs }
-            | Value::Multiply { lhs, rhs }
-            | Value::Divide {
-                lhs,
-                rhs,
-                is_signed: _,
-            }
-            | Value::Lsl { lhs, rhs }
-            | Value::Lsr { lhs, rhs }
-            | Value::And { lhs, rhs }
-            | Value::Or { lhs, rhs }
-            | Value::Xor { lhs, rhs }
-            | Value::Cmp {
-                condition: _,
-                lhs,
-                rhs,
-            } => Some(*lhs).into_iter().chain(rhs.as_binding()).collect(),
+            Value::Add { lhs, rhs, .. }
+            | Value::Subtract { lhs, rhs, .. }
+            | Value::Multiply { lhs, rhs, .. }
+            | Value::Divide { lhs, rhs, .. }
+            | Value::Lsl { lhs, rhs, .. }
+            | Value::Lsr { lhs, rhs, .. }
+            | Value::Asr { lhs, rhs, .. }
+            | Value::And { lhs, rhs, .. }
+            | Value::Or { lhs, rhs, .. }
+            | Value::Xor { lhs, rhs, .. }
+            | Value::Cmp { lhs, rhs, .. } => {
+                Some(*lhs).into_iter().chain(rhs.as_binding()).collect()
+            }
+            Value::FloatArithmetic { lhs, rhs, .. } | Value::FloatCmp { lhs, rhs, .. } => {
+                vec![*lhs, *rhs]
+            }
+            Value::Select {
+                flag,
+                if_true,
+                if_false,
+            } => Some(*flag)
+                .into_iter()
+                .chain(if_true.as_binding())
+                .chain(if_false.as_binding())
+                .collect(),
             Value::Load {
                 mem_binding,
                 byte_size: _,
-            } => vec![*mem_binding],
-            Value::Binding(binding) | Value::Negate { binding } | Value::FlipBits { binding } => {
+                volatile: _,
+            }
+            | Value::AtomicLoad { mem_binding, .. }
+            | Value::Address { mem_binding } => vec![*mem_binding],
+            Value::AtomicFetchAdd {
+                mem_binding,
+                binding,
+                ..
+            } => vec![*mem_binding, *binding],
+            Value::Binding(binding)
+            | Value::Negate { binding, .. }
+            | Value::FlipBits { binding, .. }
+            | Value::SignExtend { binding, .. }
+            | Value::FloatNegate { binding, .. }
+            | Value::IntToFloat { binding, .. }
+            | Value::FloatToInt { binding, .. }
+            | Value::FloatConvert { binding, .. } => {
                 vec![*binding]
             }
+            Value::Call { function: _, args } => args.clone(),
+            Value::CallPointer { pointer, args } => Some(*pointer)
+                .into_iter()
+                .chain(args.iter().copied())
+                .collect(),
+            Value::CallStruct {
+                callee,
+                args,
+                result,
+                ..
+            } => {
+                let pointer = match callee {
+                    Callee::Function(_) => None,
+                    Callee::Pointer(pointer) => Some(*pointer),
+                };
+                pointer
+                    .into_iter()
+                    .chain(args.iter().copied())
+                    .chain([*result])
+                    .collect()
+            }
+            Value::CallFloat { callee, args, .. } => {
+                let pointer = match callee {
+                    Callee::Function(_) => None,
+                    Callee::Pointer(pointer) => Some(*pointer),
+                };
+                pointer.into_iter().chain(args.iter().copied()).collect()
+            }
+            Value::ReturnedStruct { memory, .. } => vec![*memory],
+            Value::ElementAddress { base, index, .. } => vec![*base, *index],
         }
     }
 }
@@ -160,7 +288,17 @@ impl BindingUsage for Statement {
                 byte_size: _,
                 mem_binding,
                 binding,
+                volatile: _,
+            }
+            | Self::AtomicStore {
+                mem_binding,
+                binding,
+                byte_size: _,
             } => vec![*mem_binding, *binding],
+            Self::SetRegister {
+                register: _,
+                binding,
+            } => vec![*binding],
             Self::Assign { index: _, value } => value.binding_deps(),
         }
     }
@@ -170,7 +308,17 @@ impl BindingUsage for Statement {
                 mem_binding,
                 binding,
                 byte_size: _,
+                volatile: _,
+            }
+            | Self::AtomicStore {
+                mem_binding,
+                binding,
+                byte_size: _,
             } => mem_binding.contains_binding(target) || binding.contains_binding(target),
+            Self::SetRegister {
+                register: _,
+                binding,
+            } => binding.contains_binding(target),
             Self::Assign { index: _, value } => value.contains_binding(target),
         }
     }
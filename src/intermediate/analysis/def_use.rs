@@ -0,0 +1,288 @@
+//! Def-use chains: for each binding, the block it is defined in and the blocks it is used in.
+//!
+//! Unlike [`super::get_usage_map`], the chains can be kept up to date while the IR is being
+//! modified, as long as the modifications go through [`DefUseChains`]' methods. That way passes
+//! don't have to recompute the binding dependencies over the whole function after each change.
+//!
+//! Only the blocks are recorded, not where the statements are in them: a statement is found by
+//! walking its block, so inserting or removing one doesn't move anything else in the chains.
+
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::lifetimes::BlockAddress;
+use super::BindingUsage;
+use crate::intermediate::refactor::redefine::{Rename, RenameMap};
+use crate::intermediate::{Binding, BlockBinding, IRCode, Statement};
+
+#[derive(Debug, Default)]
+pub struct DefUseChains {
+    /// the block each binding is defined in
+    defs: HashMap<Binding, BlockBinding>,
+    /// the blocks each binding is used in, with how many times each of them uses it. The end of
+    /// a block counts as one of its uses.
+    uses: HashMap<Binding, HashMap<BlockBinding, usize>>,
+}
+
+fn defined_binding(statement: &Statement) -> Option<Binding> {
+    if let Statement::Assign { index, .. } = statement {
+        Some(*index)
+    } else {
+        None
+    }
+}
+
+impl DefUseChains {
+    pub fn new(code: &IRCode) -> Self {
+        let mut chains = Self::default();
+        for (block_binding, block) in super::iterate_with_bindings(code) {
+            chains.defs.extend(
+                block
+                    .statements
+                    .iter()
+                    .filter_map(defined_binding)
+                    .map(|defined| (defined, block_binding)),
+            );
+            chains.add_uses(block_binding, block.binding_deps());
+        }
+        chains
+    }
+
+    /// The block the binding is defined in, if it is defined at all
+    pub fn definition(&self, binding: Binding) -> Option<BlockBinding> {
+        self.defs.get(&binding).copied()
+    }
+
+    /// Where the binding is defined, walking its block to find the statement
+    pub fn find_definition(&self, code: &IRCode, binding: Binding) -> Option<BlockAddress> {
+        let block = self.definition(binding)?;
+        let statement = code[block.0]
+            .statements
+            .iter()
+            .position(|statement| defined_binding(statement) == Some(binding))?;
+        Some(BlockAddress { block, statement })
+    }
+
+    /// The blocks the binding is used in, in no particular order
+    pub fn users(&self, binding: Binding) -> impl Iterator<Item = BlockBinding> + '_ {
+        self.uses
+            .get(&binding)
+            .into_iter()
+            .flat_map(|blocks| blocks.keys().copied())
+    }
+
+    pub fn is_unused(&self, binding: Binding) -> bool {
+        self.uses.get(&binding).is_none_or(HashMap::is_empty)
+    }
+
+    /// Iterate over all the defined bindings and the blocks they are defined in
+    pub fn definitions(&self) -> impl Iterator<Item = (Binding, BlockBinding)> + '_ {
+        self.defs.iter().map(|(binding, block)| (*binding, *block))
+    }
+
+    /// Rename all the uses of `target` as `rename_as`, both in the IR and the chains.
+    pub fn rename(&mut self, code: &mut IRCode, target: Binding, rename_as: Binding) {
+        let mut map = RenameMap::new();
+        map.insert(target, rename_as);
+        self.rename_all(code, &map)
+    }
+
+    /// Apply a substitution map, only visiting the blocks that use the renamed bindings.
+    pub fn rename_all(&mut self, code: &mut IRCode, substitutions: &RenameMap) {
+        let mut blocks: Vec<_> = substitutions
+            .keys()
+            .flat_map(|binding| self.users(*binding))
+            .collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+        for block in blocks {
+            self.remove_uses(block, code[block.0].binding_deps());
+            code[block.0].rename_all(substitutions);
+            self.add_uses(block, code[block.0].binding_deps());
+        }
+    }
+
+    /// Insert a statement at the given address, before the one that was there.
+    pub fn insert_statement(
+        &mut self,
+        code: &mut IRCode,
+        address: BlockAddress,
+        statement: Statement,
+    ) {
+        self.register(address.block, &statement);
+        code[address.block.0]
+            .statements
+            .insert(address.statement, statement);
+    }
+
+    /// Remove the statement at the given address.
+    pub fn remove_statement(&mut self, code: &mut IRCode, address: BlockAddress) -> Statement {
+        let statement = code[address.block.0].statements.remove(address.statement);
+        if let Some(defined) = defined_binding(&statement) {
+            self.defs.remove(&defined);
+        }
+        self.remove_uses(address.block, statement.binding_deps());
+        statement
+    }
+
+    /// Remove the statement that defines the binding, if there is one.
+    pub fn remove_definition(&mut self, code: &mut IRCode, binding: Binding) -> Option<Statement> {
+        let address = self.find_definition(code, binding)?;
+        Some(self.remove_statement(code, address))
+    }
+
+    /// Remove the statements that define any of the bindings, if `removable` agrees, walking each
+    /// of their blocks once. Gives the statements removed.
+    pub fn remove_definitions(
+        &mut self,
+        code: &mut IRCode,
+        bindings: &HashSet<Binding>,
+        mut removable: impl FnMut(&Statement) -> bool,
+    ) -> Vec<Statement> {
+        let mut blocks: Vec<_> = bindings
+            .iter()
+            .filter_map(|binding| self.definition(*binding))
+            .collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+        let mut removed = Vec::new();
+        for block in blocks {
+            let statements = core::mem::take(&mut code[block.0].statements);
+            for statement in statements {
+                match defined_binding(&statement) {
+                    Some(defined) if bindings.contains(&defined) && removable(&statement) => {
+                        self.defs.remove(&defined);
+                        self.remove_uses(block, statement.binding_deps());
+                        removed.push(statement);
+                    }
+                    _ => code[block.0].statements.push(statement),
+                }
+            }
+        }
+        removed
+    }
+
+    fn register(&mut self, block: BlockBinding, statement: &Statement) {
+        if let Some(defined) = defined_binding(statement) {
+            self.defs.insert(defined, block);
+        }
+        self.add_uses(block, statement.binding_deps());
+    }
+
+    fn add_uses(&mut self, block: BlockBinding, deps: impl IntoIterator<Item = Binding>) {
+        for dep in deps {
+            *self.uses.entry(dep).or_default().entry(block).or_default() += 1;
+        }
+    }
+
+    fn remove_uses(&mut self, block: BlockBinding, deps: impl IntoIterator<Item = Binding>) {
+        for dep in deps {
+            let Some(blocks) = self.uses.get_mut(&dep) else {
+                continue;
+            };
+            if let Some(count) = blocks.get_mut(&block) {
+                *count -= 1;
+                if *count == 0 {
+                    blocks.remove(&block);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::codegen::assembly::BitSize;
+    use crate::intermediate::{BasicBlock, BlockEnd, Branch, Value};
+
+    fn address(block: usize, statement: usize) -> BlockAddress {
+        BlockAddress {
+            block: BlockBinding(block),
+            statement,
+        }
+    }
+
+    fn users(chains: &DefUseChains, binding: usize) -> Vec<usize> {
+        let mut users: Vec<_> = chains
+            .users(Binding(binding))
+            .map(|block| block.0)
+            .collect();
+        users.sort_unstable();
+        users
+    }
+
+    #[test]
+    fn chains_stay_in_sync() {
+        let mut code = vec![
+            BasicBlock {
+                statements: vec![
+                    Statement::Assign {
+                        index: Binding(0),
+                        value: Value::Constant(1),
+                    },
+                    Statement::Assign {
+                        index: Binding(1),
+                        value: Value::Add {
+                            lhs: Binding(0),
+                            rhs: Binding(0).into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    },
+                ],
+                end: BlockEnd::Branch(Branch::Unconditional {
+                    target: BlockBinding(1),
+                }),
+            },
+            BasicBlock {
+                statements: vec![Statement::Assign {
+                    index: Binding(2),
+                    value: Value::Add {
+                        lhs: Binding(1),
+                        rhs: Binding(0).into(),
+                        bit_size: BitSize::Bit32,
+                    },
+                }],
+                end: BlockEnd::Return(Binding(2)),
+            },
+        ];
+        let mut chains = DefUseChains::new(&code);
+        assert_eq!(users(&chains, 0), [0, 1]);
+        assert_eq!(users(&chains, 2), [1]);
+        assert_eq!(
+            chains.find_definition(&code, Binding(2)),
+            Some(address(1, 0))
+        );
+
+        chains.insert_statement(
+            &mut code,
+            address(0, 0),
+            Statement::Assign {
+                index: Binding(3),
+                value: Value::Constant(2),
+            },
+        );
+        assert_eq!(chains.definition(Binding(3)), Some(BlockBinding(0)));
+        assert_eq!(
+            chains.find_definition(&code, Binding(1)),
+            Some(address(0, 2))
+        );
+
+        chains.rename(&mut code, Binding(0), Binding(3));
+        assert!(chains.is_unused(Binding(0)));
+        assert_eq!(users(&chains, 3), [0, 1]);
+
+        // the uses of the removed statement go with it, however many times it used them
+        let removed = chains.remove_definition(&mut code, Binding(1));
+        assert!(removed.is_some());
+        assert_eq!(users(&chains, 1), [1]);
+        assert_eq!(users(&chains, 3), [1]);
+        assert_eq!(chains.definition(Binding(1)), None);
+        assert_eq!(
+            chains.find_definition(&code, Binding(2)),
+            Some(address(1, 0))
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Dominance: a block dominates another if it runs before it on every path from the entry.
+//!
+//! The tree is computed with the algorithm of Cooper, Harvey and Kennedy ("A Simple, Fast
+//! Dominance Algorithm"), which walks the blocks in reverse postorder until the immediate
+//! dominators stop changing. Blocks the entry doesn't reach aren't in the tree: they're dominated
+//! by nothing and dominate nothing.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::intermediate::{BlockBinding, IR};
+
+/// The blocks in the dominance frontier of each block: the ones it doesn't strictly dominate, but
+/// that have a predecessor it dominates. They're where the values defined in the block meet the
+/// ones coming from elsewhere, so that's where SSA construction puts the phi nodes.
+pub type DominanceFrontiers = HashMap<BlockBinding, HashSet<BlockBinding>>;
+
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    /// the immediate dominator of each reached block. The entry is its own.
+    idom: HashMap<BlockBinding, BlockBinding>,
+    children: HashMap<BlockBinding, Vec<BlockBinding>>,
+    /// the reached blocks, each one after all the blocks that branch to it (back edges aside)
+    reverse_postorder: Vec<BlockBinding>,
+    /// when each block is entered and left in a preorder walk of the tree, so that a block
+    /// dominates the ones whose interval is inside its own
+    intervals: HashMap<BlockBinding, (usize, usize)>,
+}
+
+impl DominatorTree {
+    /// Compute the tree from the branching graphs of the IR, which have to be up to date
+    pub fn new(ir: &IR) -> Self {
+        let reverse_postorder = reverse_postorder(ir);
+        let order: HashMap<_, _> = reverse_postorder
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (*block, index))
+            .collect();
+
+        let mut idom = HashMap::new();
+        if let Some(&entry) = reverse_postorder.first() {
+            idom.insert(entry, entry);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in reverse_postorder.iter().skip(1) {
+                let mut new_idom = None;
+                for &predecessor in ir.backwards_map.get(&block).into_iter().flatten() {
+                    if !idom.contains_key(&predecessor) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => intersect(&idom, &order, predecessor, other),
+                    });
+                }
+                // the first block in reverse postorder that reaches it was processed already
+                let new_idom = new_idom.expect("a reached block has a reached predecessor");
+                if idom.insert(block, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children: HashMap<_, Vec<_>> = HashMap::new();
+        for &block in reverse_postorder.iter().skip(1) {
+            children.entry(idom[&block]).or_default().push(block);
+        }
+
+        let mut tree = Self {
+            idom,
+            children,
+            reverse_postorder,
+            intervals: HashMap::new(),
+        };
+        tree.intervals = tree.preorder_intervals();
+        tree
+    }
+
+    fn preorder_intervals(&self) -> HashMap<BlockBinding, (usize, usize)> {
+        let mut intervals = HashMap::new();
+        let Some(&entry) = self.reverse_postorder.first() else {
+            return intervals;
+        };
+        let mut counter = 0;
+        // each block is pushed twice: once to enter it and once to leave it
+        let mut stack = vec![(entry, false)];
+        while let Some((block, leaving)) = stack.pop() {
+            counter += 1;
+            if leaving {
+                intervals.entry(block).and_modify(|(_, end)| *end = counter);
+            } else {
+                intervals.insert(block, (counter, counter));
+                stack.push((block, true));
+                stack.extend(self.children(block).iter().map(|child| (*child, false)));
+            }
+        }
+        intervals
+    }
+
+    /// Whether the entry reaches the block
+    pub fn is_reached(&self, block: BlockBinding) -> bool {
+        self.idom.contains_key(&block)
+    }
+
+    /// The closest block that strictly dominates this one, which is `None` for the entry and the
+    /// blocks it doesn't reach
+    pub fn immediate_dominator(&self, block: BlockBinding) -> Option<BlockBinding> {
+        self.idom
+            .get(&block)
+            .copied()
+            .filter(|dominator| *dominator != block)
+    }
+
+    /// The blocks this one is the immediate dominator of
+    pub fn children(&self, block: BlockBinding) -> &[BlockBinding] {
+        self.children.get(&block).map_or(&[], Vec::as_slice)
+    }
+
+    /// The reached blocks in reverse postorder, the entry first. Walking them in this order sees
+    /// every block after its dominators.
+    pub fn reverse_postorder(&self) -> &[BlockBinding] {
+        &self.reverse_postorder
+    }
+
+    /// Whether `dominator` runs before `block` on every path from the entry to it. Every reached
+    /// block dominates itself.
+    pub fn dominates(&self, dominator: BlockBinding, block: BlockBinding) -> bool {
+        match (self.intervals.get(&dominator), self.intervals.get(&block)) {
+            (Some(&(start, end)), Some(&(inner_start, inner_end))) => {
+                start <= inner_start && inner_end <= end
+            }
+            _ => false,
+        }
+    }
+
+    pub fn strictly_dominates(&self, dominator: BlockBinding, block: BlockBinding) -> bool {
+        dominator != block && self.dominates(dominator, block)
+    }
+
+    /// The blocks that dominate this one, from its immediate dominator up to the entry
+    pub fn dominators(&self, block: BlockBinding) -> impl Iterator<Item = BlockBinding> + '_ {
+        core::iter::successors(self.immediate_dominator(block), |block| {
+            self.immediate_dominator(*block)
+        })
+    }
+
+    /// The dominance frontier of every reached block, which may be empty
+    pub fn frontiers(&self, ir: &IR) -> DominanceFrontiers {
+        let mut frontiers: DominanceFrontiers = self
+            .reverse_postorder
+            .iter()
+            .map(|block| (*block, HashSet::new()))
+            .collect();
+        for &block in &self.reverse_postorder {
+            let idom = self.immediate_dominator(block);
+            for &predecessor in ir.backwards_map.get(&block).into_iter().flatten() {
+                if !self.is_reached(predecessor) {
+                    continue;
+                }
+                // every block from the predecessor up to the immediate dominator (excluded)
+                // reaches this one without dominating it
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner.filter(|runner| Some(*runner) != idom) {
+                    frontiers.entry(current).or_default().insert(block);
+                    runner = self.immediate_dominator(current);
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/// The iterated dominance frontier of the blocks: their frontiers, the frontiers of those, and so
+/// on. A variable assigned in the blocks needs a phi node in each of these.
+pub fn iterated_frontier(
+    frontiers: &DominanceFrontiers,
+    blocks: impl IntoIterator<Item = BlockBinding>,
+) -> HashSet<BlockBinding> {
+    let mut result = HashSet::new();
+    let mut queue: Vec<_> = blocks.into_iter().collect();
+    while let Some(block) = queue.pop() {
+        for &frontier in frontiers.get(&block).into_iter().flatten() {
+            if result.insert(frontier) {
+                queue.push(frontier);
+            }
+        }
+    }
+    result
+}
+
+/// Walk up the tree from both blocks until they meet, at their closest common dominator
+fn intersect(
+    idom: &HashMap<BlockBinding, BlockBinding>,
+    order: &HashMap<BlockBinding, usize>,
+    mut lhs: BlockBinding,
+    mut rhs: BlockBinding,
+) -> BlockBinding {
+    while lhs != rhs {
+        while order[&lhs] > order[&rhs] {
+            lhs = idom[&lhs];
+        }
+        while order[&rhs] > order[&lhs] {
+            rhs = idom[&rhs];
+        }
+    }
+    lhs
+}
+
+fn reverse_postorder(ir: &IR) -> Vec<BlockBinding> {
+    let mut postorder = Vec::new();
+    if ir.code.is_empty() {
+        return postorder;
+    }
+    let mut visited = HashSet::new();
+    let entry = BlockBinding(0);
+    visited.insert(entry);
+    // the block and how many of its children were already pushed
+    let mut stack = vec![(entry, 0)];
+    while let Some((block, next_child)) = stack.last_mut() {
+        let children = ir.forward_map.get(block).map_or(&[][..], Vec::as_slice);
+        if let Some(&child) = children.get(*next_child) {
+            *next_child += 1;
+            if visited.insert(child) {
+                stack.push((child, 0));
+            }
+        } else {
+            postorder.push(*block);
+            stack.pop();
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{BasicBlock, Binding, Branch};
+
+    fn jump(target: usize) -> BasicBlock {
+        BasicBlock {
+            statements: vec![],
+            end: Branch::Unconditional {
+                target: BlockBinding(target),
+            }
+            .into(),
+        }
+    }
+
+    fn branch(target_true: usize, target_false: usize) -> BasicBlock {
+        BasicBlock {
+            statements: vec![],
+            end: Branch::Conditional {
+                flag: Binding(0),
+                target_true: BlockBinding(target_true),
+                target_false: BlockBinding(target_false),
+            }
+            .into(),
+        }
+    }
+
+    fn ret() -> BasicBlock {
+        BasicBlock {
+            statements: vec![],
+            end: Binding(0).into(),
+        }
+    }
+
+    fn blocks(blocks: &[usize]) -> HashSet<BlockBinding> {
+        blocks.iter().copied().map(BlockBinding).collect()
+    }
+
+    #[test]
+    fn diamond() {
+        let ir = IR::from(vec![branch(1, 2), jump(3), jump(3), ret()]);
+        let tree = DominatorTree::new(&ir);
+        for block in 1..=3 {
+            assert_eq!(
+                tree.immediate_dominator(BlockBinding(block)),
+                Some(BlockBinding(0))
+            );
+        }
+        assert_eq!(tree.immediate_dominator(BlockBinding(0)), None);
+        assert!(tree.dominates(BlockBinding(0), BlockBinding(3)));
+        assert!(tree.dominates(BlockBinding(3), BlockBinding(3)));
+        assert!(!tree.strictly_dominates(BlockBinding(3), BlockBinding(3)));
+        assert!(!tree.dominates(BlockBinding(1), BlockBinding(3)));
+
+        let frontiers = tree.frontiers(&ir);
+        assert_eq!(frontiers[&BlockBinding(0)], blocks(&[]));
+        assert_eq!(frontiers[&BlockBinding(1)], blocks(&[3]));
+        assert_eq!(frontiers[&BlockBinding(2)], blocks(&[3]));
+        assert_eq!(frontiers[&BlockBinding(3)], blocks(&[]));
+    }
+
+    #[test]
+    fn loops_and_unreached_blocks() {
+        // 0 -> 1 -> 2 -> {1, 3}, and 4 -> 3, which nothing reaches
+        let ir = IR::from(vec![jump(1), jump(2), branch(1, 3), ret(), jump(3)]);
+        let tree = DominatorTree::new(&ir);
+        assert_eq!(
+            tree.reverse_postorder(),
+            &[0, 1, 2, 3].map(BlockBinding)[..]
+        );
+        assert_eq!(
+            tree.dominators(BlockBinding(3)).collect::<Vec<_>>(),
+            [2, 1, 0].map(BlockBinding)
+        );
+        assert!(!tree.is_reached(BlockBinding(4)));
+        assert!(!tree.dominates(BlockBinding(4), BlockBinding(3)));
+        assert!(!tree.dominates(BlockBinding(0), BlockBinding(4)));
+
+        // the loop header is where the values of the latch meet the ones from the entry
+        let frontiers = tree.frontiers(&ir);
+        assert_eq!(frontiers[&BlockBinding(1)], blocks(&[1]));
+        assert_eq!(frontiers[&BlockBinding(2)], blocks(&[1]));
+        assert_eq!(
+            iterated_frontier(&frontiers, [BlockBinding(2)]),
+            blocks(&[1])
+        );
+    }
+
+    #[test]
+    fn nested_joins() {
+        // 0 -> {1, 4}, 1 -> {2, 3}, 2 -> 5, 3 -> 5, 5 -> 6, 4 -> 6
+        let ir = IR::from(vec![
+            branch(1, 4),
+            branch(2, 3),
+            jump(5),
+            jump(5),
+            jump(6),
+            jump(6),
+            ret(),
+        ]);
+        let tree = DominatorTree::new(&ir);
+        assert_eq!(
+            tree.immediate_dominator(BlockBinding(5)),
+            Some(BlockBinding(1))
+        );
+        assert_eq!(
+            tree.immediate_dominator(BlockBinding(6)),
+            Some(BlockBinding(0))
+        );
+        let frontiers = tree.frontiers(&ir);
+        assert_eq!(frontiers[&BlockBinding(2)], blocks(&[5]));
+        assert_eq!(frontiers[&BlockBinding(5)], blocks(&[6]));
+        assert_eq!(frontiers[&BlockBinding(1)], blocks(&[6]));
+        assert_eq!(
+            iterated_frontier(&frontiers, [BlockBinding(2)]),
+            blocks(&[5, 6])
+        );
+    }
+}
@@ -1,5 +1,10 @@
-use crate::intermediate::{analysis, Binding, BlockBinding, BlockEnd, Branch, Statement, IR};
-use std::collections::{HashMap, HashSet};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::intermediate::{
+    analysis, Binding, BlockBinding, BlockEnd, Branch, Statement, Value, IR,
+};
+use hashbrown::{HashMap, HashSet};
 
 pub type LifetimeMap = HashMap<Binding, Lifetime>;
 
@@ -191,38 +196,104 @@ impl Lifetime {
         self.ends.contains_key(&self.start.block)
     }
     pub fn intersects(&self, other: &Self, ir: &IR) -> bool {
-        let self_passes_through: HashSet<_> = self.pass_through_list(ir).collect();
-        let other_passes_through: HashSet<_> = other.pass_through_list(ir).collect();
-
-        // they won't intersect if:
-        //  - the blocks they pass through never coincide.
-        self_passes_through
-            .intersection(&other_passes_through)
-            //  - if there is any block where they coincide:
-            .any(|block| {
-                //      - if one dies and the other one is defined in the same block:
-                let dies_or_defined =
-                    self.ends
-                        .get(block)
-                        .copied()
-                        .and_then(|end| other.start_from_block(*block).map(|start| (end, start)))
-                        .or_else(|| {
-                            other.ends.get(block).copied().and_then(|end| {
-                                self.start_from_block(*block).map(|start| (end, start))
-                            })
-                        });
-
-                if let Some((dies, defined)) = dies_or_defined {
-                    //          - if the one that dies has a higher statement index than the statement index
-                    //              of the one that is defined, then they intersect.
-                    dies > defined
-
-                    //          - otherwise, they won't intersect (since one is defined after the other is
-                    //          already dead)
+        let self_ranges = self.live_ranges(ir);
+        let other_ranges = other.live_ranges(ir);
+
+        // they intersect if there's a block where both are alive at the same time. A binding that
+        // dies on the same statement the other one is defined doesn't collide with it.
+        self_ranges.iter().any(|(block, &(start, end))| {
+            other_ranges
+                .get(block)
+                .is_some_and(|&(other_start, other_end)| end > other_start && other_end > start)
+        })
+    }
+
+    /// The blocks where the binding is alive, along with the statements between which it is
+    /// alive there, as `(defined, last used]`. A binding alive when entering the block is defined
+    /// at `-1`, and one still alive when leaving it is last used after the block end. Phi nodes
+    /// use their values at the end of the block they come from.
+    fn live_ranges(&self, ir: &IR) -> HashMap<BlockBinding, (isize, isize)> {
+        use super::BindingUsage;
+        let binding = self.attached_binding;
+        let mut uses: HashMap<BlockBinding, isize> = HashMap::new();
+        let mut add_use = |block: BlockBinding, statement: usize| {
+            let last_use = uses.entry(block).or_insert(-1);
+            *last_use = (*last_use).max(statement as isize);
+        };
+        for (block_binding, block) in analysis::iterate_with_bindings(&ir.code) {
+            for (statement_index, statement) in block.statements.iter().enumerate() {
+                match statement {
+                    Statement::Assign {
+                        value: Value::Phi { nodes },
+                        ..
+                    } => {
+                        for node in nodes.iter().filter(|node| node.value == binding) {
+                            add_use(node.block_from, ir[node.block_from].statements.len());
+                        }
+                    }
+                    _ if statement.contains_binding(binding) => {
+                        add_use(block_binding, statement_index)
+                    }
+                    _ => (),
+                }
+            }
+            match block.end {
+                BlockEnd::Branch(Branch::Conditional { flag, .. }) if flag == binding => {
+                    add_use(block_binding, block.statements.len())
+                }
+                BlockEnd::Return(ret) if ret == binding => {
+                    add_use(block_binding, block.statements.len())
+                }
+                _ => (),
+            }
+        }
+
+        // it's alive in the blocks reached from its definition that can still reach a use
+        let live_blocks: HashSet<BlockBinding> = super::predecessors(ir, self.start.block)
+            .filter(|&block| super::predecessors(ir, block).any(|next| uses.contains_key(&next)))
+            .collect();
+
+        let mut ranges: HashMap<_, _> = live_blocks
+            .iter()
+            .map(|&block| {
+                let start = self
+                    .start_from_block(block)
+                    .map_or(-1, |start| start as isize);
+                let lives_on = ir
+                    .forward_map
+                    .get(&block)
+                    .into_iter()
+                    .flatten()
+                    .any(|next| *next != self.start.block && live_blocks.contains(next));
+                let end = if lives_on {
+                    ir[block].statements.len() as isize + 1
+                } else {
+                    uses.get(&block).copied().unwrap_or(start)
+                };
+                (block, (start, end))
+            })
+            .collect();
+        // an unused binding is still written when it's defined
+        ranges
+            .entry(self.start.block)
+            .or_insert((self.start.statement as isize, self.start.statement as isize));
+        ranges
+    }
+
+    /// Checks whether the binding is alive right at the given statement: it was defined before
+    /// it and is still used after it.
+    pub fn is_live_at(&self, address: BlockAddress, ir: &IR) -> bool {
+        let defined_before = if self.start.block == address.block {
+            self.start.statement < address.statement
+        } else {
+            super::predecessors(ir, self.start.block).any(|block| block == address.block)
+        };
+        defined_before
+            && self.ends.iter().any(|(&block, &statement)| {
+                if block == address.block {
+                    statement > address.statement
                 } else {
-                    // if no one dies here with the other being defined in the same block, then
-                    // this not a collision
-                    false
+                    super::predecessors(ir, address.block).any(|child| child == block)
                 }
             })
     }
@@ -243,11 +314,11 @@ pub struct Lifetime {
     pub start: BlockAddress,
     pub ends: HashMap<BlockBinding, usize>,
 }
-impl std::fmt::Debug for Lifetime {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for Lifetime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         struct AddressMapDebugImpl<'a>(&'a HashMap<BlockBinding, usize>);
-        impl<'a> std::fmt::Debug for AddressMapDebugImpl<'a> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl<'a> core::fmt::Debug for AddressMapDebugImpl<'a> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
                 f.debug_list()
                     .entries(
                         self.0
@@ -263,8 +334,8 @@ impl std::fmt::Debug for Lifetime {
             .finish()
     }
 }
-impl std::fmt::Debug for BlockAddress {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for BlockAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}[{}]", self.block, self.statement)
     }
 }
@@ -280,6 +351,7 @@ pub struct BlockAddress {
 mod tests {
     use super::*;
     use crate::intermediate::*;
+    use std::dbg;
     // TODO: more tests on intersections:
     //  - different blocks, collides
     //  - different blocks, different branches
@@ -292,7 +364,7 @@ mod tests {
             statements: vec![
                 Statement::Assign {
                     index: Binding(0),
-                    value: Value::Allocate { size: 4 },
+                    value: Value::Allocate { size: 4, align: 4 },
                 },
                 Statement::Assign {
                     index: Binding(1),
@@ -302,15 +374,17 @@ mod tests {
                     binding: Binding(1),
                     mem_binding: Binding(0),
                     byte_size: ByteSize::U32,
+                    volatile: false,
                 },
                 Statement::Assign {
                     index: Binding(2),
-                    value: Value::Allocate { size: 4 },
+                    value: Value::Allocate { size: 4, align: 4 },
                 },
                 Statement::Store {
                     binding: Binding(1),
                     mem_binding: Binding(2),
                     byte_size: ByteSize::U32,
+                    volatile: false,
                 },
             ],
             end: BlockEnd::Return(Binding(1)),
@@ -408,15 +482,20 @@ mod tests {
     fn compile_source_into_ir(source: &str) -> anyhow::Result<crate::intermediate::IR> {
         let meta = crate::error::SourceMetadata::new(source).with_file("<test program>".into());
         let program = crate::grammar::Parser::new(&meta).parse()?;
-        let (_function_name, ir) = crate::intermediate::generate::compile_program(program, &meta)?;
-        Ok(ir)
+        let (mut module, _warnings) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        Ok(module.functions.remove(0).ir)
     }
 
     #[test]
     fn correct_branch_pass_through() -> anyhow::Result<()> {
-        const SOURCE_CODE: &str = include_str!(
-            "../../../write_a_c_compiler/stage_4/valid/skip_on_failure_multi_short_circuit.c"
-        );
+        // write_a_c_compiler/stage_4/valid/skip_on_failure_multi_short_circuit.c
+        const SOURCE_CODE: &str = r#"
+int main() {
+    int a = 0;
+    a || (a = 3) || (a = 4);
+    return a;
+}"#;
 
         let ir = compile_source_into_ir(SOURCE_CODE)?;
         let lifetimes = analysis::compute_lifetimes(&ir);
@@ -428,4 +507,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn alive_across_branches() -> anyhow::Result<()> {
+        const SOURCE_CODE: &str = r#"
+int main() {
+    int a = 1;
+    int b = 2;
+    if (a) { a = 3; } else { b = 4; }
+    return a + b;
+}"#;
+
+        let ir = compile_source_into_ir(SOURCE_CODE)?;
+        let lifetimes = analysis::compute_lifetimes(&ir);
+        assert!(
+            lifetimes[&Binding(0)].intersects(&lifetimes[&Binding(2)], &ir),
+            "{:?}\n%0 and %2 should collide",
+            ir
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,268 @@
+//! Natural loops: a back edge is a branch to a block that dominates the one branching, its
+//! header. The loop of a header is the header along with every block that reaches one of its back
+//! edges without going through it, so the loops of all the back edges to a header are one loop.
+//!
+//! The header is the only way into the loop, which is what passes moving code out of loops or
+//! unrolling them rely on. A cycle that can be entered from more than one block has no header
+//! dominating the rest of it, so it isn't a loop here. Two loops are either disjoint or one is
+//! nested in the other.
+
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::DominatorTree;
+use crate::intermediate::{BlockBinding, IR};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: BlockBinding,
+    /// the blocks that branch back to the header, in order
+    pub latches: Vec<BlockBinding>,
+    /// the blocks of the loop, the header and those of the loops nested in it included
+    pub body: HashSet<BlockBinding>,
+    /// the branches that leave the loop, from a block in it to one outside of it, in order
+    pub exits: Vec<(BlockBinding, BlockBinding)>,
+    /// the header of the loop this one is directly nested in
+    pub parent: Option<BlockBinding>,
+    /// how many loops this one is nested in, 0 for the outermost ones
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn contains(&self, block: BlockBinding) -> bool {
+        self.body.contains(&block)
+    }
+}
+
+/// The loops of a function, with the innermost loop of each block
+#[derive(Debug, Clone, Default)]
+pub struct LoopForest {
+    /// ordered so that each loop comes after the ones it's nested in
+    loops: Vec<Loop>,
+    /// the index of the innermost loop each block in a loop is in
+    innermost: HashMap<BlockBinding, usize>,
+}
+
+impl LoopForest {
+    /// Find the loops from the branching graphs of the IR, which have to be up to date, and its
+    /// dominator tree. Only the blocks the entry reaches are in a loop.
+    pub fn new(ir: &IR, dominators: &DominatorTree) -> Self {
+        let successors =
+            |block: BlockBinding| ir.forward_map.get(&block).into_iter().flatten().copied();
+
+        // the dominators come first in reverse postorder, so the headers of the loops a loop is
+        // nested in are found before its own
+        let mut forest = Self::default();
+        for &header in dominators.reverse_postorder() {
+            let mut latches: Vec<_> = ir
+                .backwards_map
+                .get(&header)
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|&latch| dominators.dominates(header, latch))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            latches.sort_unstable();
+            latches.dedup();
+
+            // walk back from the latches until the header, which dominates all of them
+            let mut body = HashSet::new();
+            body.insert(header);
+            let mut pending = latches.clone();
+            while let Some(block) = pending.pop() {
+                if body.insert(block) {
+                    pending.extend(
+                        ir.backwards_map
+                            .get(&block)
+                            .into_iter()
+                            .flatten()
+                            .copied()
+                            .filter(|&predecessor| dominators.is_reached(predecessor)),
+                    );
+                }
+            }
+
+            let mut exits: Vec<_> = body
+                .iter()
+                .flat_map(|&block| successors(block).map(move |successor| (block, successor)))
+                .filter(|(_, successor)| !body.contains(successor))
+                .collect();
+            exits.sort_unstable();
+            exits.dedup();
+
+            let parent = forest.innermost.get(&header).copied();
+            let index = forest.loops.len();
+            forest.loops.push(Loop {
+                header,
+                latches,
+                parent: parent.map(|parent| forest.loops[parent].header),
+                depth: parent.map_or(0, |parent| forest.loops[parent].depth + 1),
+                body,
+                exits,
+            });
+            // this loop is nested in every other loop its blocks were found in so far
+            for &block in &forest.loops[index].body {
+                forest.innermost.insert(block, index);
+            }
+        }
+        forest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loops.is_empty()
+    }
+
+    /// The loops, each one after the ones it's nested in. Going through them in reverse sees the
+    /// innermost loops first.
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// The loop with this header
+    pub fn get(&self, header: BlockBinding) -> Option<&Loop> {
+        self.loops.iter().find(|found| found.header == header)
+    }
+
+    /// The innermost loop the block is in, if it's in any
+    pub fn innermost(&self, block: BlockBinding) -> Option<&Loop> {
+        self.innermost.get(&block).map(|&index| &self.loops[index])
+    }
+
+    /// How many loops the block is in
+    pub fn depth(&self, block: BlockBinding) -> usize {
+        self.innermost(block).map_or(0, |found| found.depth + 1)
+    }
+
+    /// The loops directly nested in the one with this header
+    pub fn children(&self, header: BlockBinding) -> impl Iterator<Item = &Loop> + '_ {
+        self.loops
+            .iter()
+            .filter(move |found| found.parent == Some(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(blocks: &[usize]) -> HashSet<BlockBinding> {
+        blocks.iter().copied().map(BlockBinding).collect()
+    }
+
+    fn forest(ir: &IR) -> LoopForest {
+        LoopForest::new(ir, &DominatorTree::new(ir))
+    }
+
+    #[test]
+    fn nested_loops() -> anyhow::Result<()> {
+        // an outer loop on BB1 that runs an inner loop on BB2, which can leave both at once
+        let ir: IR = "
+            BB0:
+              %0 = arg 0
+              br  BB1
+            BB1:
+              br-cond %0, BB2, BB5
+            BB2:
+              br-cond %0, BB3, BB4
+            BB3:
+              br-cond %0, BB2, BB5
+            BB4:
+              br  BB1
+            BB5:
+              ret %0
+        "
+        .parse()?;
+        let forest = forest(&ir);
+        let headers: Vec<_> = forest.loops().iter().map(|found| found.header).collect();
+        assert_eq!(headers, [BlockBinding(1), BlockBinding(2)]);
+
+        let outer = forest.get(BlockBinding(1)).expect("BB1 is a header");
+        assert_eq!(outer.latches, [BlockBinding(4)]);
+        assert_eq!(outer.body, blocks(&[1, 2, 3, 4]));
+        assert_eq!(
+            outer.exits,
+            [
+                (BlockBinding(1), BlockBinding(5)),
+                (BlockBinding(3), BlockBinding(5))
+            ]
+        );
+        assert_eq!((outer.parent, outer.depth), (None, 0));
+
+        let inner = forest.get(BlockBinding(2)).expect("BB2 is a header");
+        assert_eq!(inner.latches, [BlockBinding(3)]);
+        assert_eq!(inner.body, blocks(&[2, 3]));
+        assert_eq!(
+            inner.exits,
+            [
+                (BlockBinding(2), BlockBinding(4)),
+                (BlockBinding(3), BlockBinding(5))
+            ]
+        );
+        assert_eq!((inner.parent, inner.depth), (Some(BlockBinding(1)), 1));
+
+        let children: Vec<_> = forest.children(BlockBinding(1)).collect();
+        assert_eq!(children, [inner]);
+        assert_eq!(forest.innermost(BlockBinding(3)), Some(inner));
+        assert_eq!(forest.innermost(BlockBinding(4)), Some(outer));
+        assert_eq!(forest.innermost(BlockBinding(5)), None);
+        assert_eq!(
+            [0, 1, 2, 3, 4, 5].map(|block| forest.depth(BlockBinding(block))),
+            [0, 1, 2, 2, 1, 0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn back_edges_to_a_header_make_one_loop() -> anyhow::Result<()> {
+        // BB1 loops back to itself, and BB2 back to BB1 too
+        let ir: IR = "
+            BB0:
+              %0 = arg 0
+              br  BB1
+            BB1:
+              br-cond %0, BB1, BB2
+            BB2:
+              br-cond %0, BB1, BB3
+            BB3:
+              ret %0
+        "
+        .parse()?;
+        let forest = forest(&ir);
+        let [found] = forest.loops() else {
+            panic!("there's one loop: {:?}", forest);
+        };
+        assert_eq!(found.header, BlockBinding(1));
+        assert_eq!(found.latches, [BlockBinding(1), BlockBinding(2)]);
+        assert_eq!(found.body, blocks(&[1, 2]));
+        assert_eq!(found.exits, [(BlockBinding(2), BlockBinding(3))]);
+        Ok(())
+    }
+
+    #[test]
+    fn cycles_entered_from_two_blocks_are_not_loops() -> anyhow::Result<()> {
+        // BB1 and BB2 branch to each other, and the entry to both. BB4 loops, but nothing reaches
+        // it.
+        let ir: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              br-cond %0, BB2, BB3
+            BB2:
+              br  BB1
+            BB3:
+              ret %0
+            BB4:
+              br  BB4
+        "
+        .parse()?;
+        let forest = forest(&ir);
+        assert!(forest.is_empty(), "{:?}", forest);
+        assert_eq!(forest.depth(BlockBinding(4)), 0);
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
-use std::collections::BTreeMap;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use alloc::vec;
+use alloc::vec::Vec;
 
-use super::{BasicBlock, Binding, BlockBinding, BranchingMap, Statement, Value, IR};
+use alloc::collections::BTreeMap;
+use hashbrown::HashMap;
+use hashbrown::HashSet;
+
+use super::{BasicBlock, Binding, BlockBinding, BranchingMap, ByteSize, Statement, Value, IR};
 mod binding_usage;
+pub mod def_use;
+pub mod dominators;
 pub mod lifetimes;
+pub mod loops;
+pub mod uninitialized;
 
 // TODO: output some information on phi nodes per block edge between parent/child.
 
@@ -14,6 +21,12 @@ pub use lifetimes::{
 
 pub use binding_usage::{get_usage_map, BindingUsage, UsageMap};
 
+pub use def_use::DefUseChains;
+
+pub use dominators::{iterated_frontier, DominanceFrontiers, DominatorTree};
+
+pub use loops::{Loop, LoopForest};
+
 pub fn order_by_deps(ir: &IR, bindings: impl Iterator<Item = Binding>) -> Vec<Binding> {
     let mut all_bindings: BTreeMap<_, HashSet<_>> = bindings
         .map(|binding| {
@@ -78,6 +91,148 @@ pub fn is_indirect_child_of(
     }
 }
 
+/// The names of the functions called from the given IR, possibly repeated.
+pub fn called_functions(ir: &IR) -> impl Iterator<Item = &str> + '_ {
+    ir.code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign { value, .. } => value.called_function(),
+            _ => None,
+        })
+}
+
+/// The names of the functions whose address is taken in the given IR, possibly repeated.
+pub fn addressed_functions(ir: &IR) -> impl Iterator<Item = &str> + '_ {
+    ir.code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                value: Value::FunctionAddress(function),
+                ..
+            } => Some(function.as_str()),
+            _ => None,
+        })
+}
+
+/// The functions that are called on every path through the IR, before it can return. The
+/// `noreturn` functions end the paths that call them.
+pub fn unconditionally_called_functions<'ir>(
+    ir: &'ir IR,
+    noreturn: &'ir [&str],
+) -> impl Iterator<Item = &'ir str> + 'ir {
+    let mut callers: HashMap<&str, HashSet<BlockBinding>> = HashMap::new();
+    for (block_binding, block) in iterate_with_bindings(&ir.code) {
+        for statement in &block.statements {
+            if let Some(function) = match statement {
+                Statement::Assign { value, .. } => value.called_function(),
+                _ => None,
+            } {
+                callers.entry(function).or_default().insert(block_binding);
+            }
+        }
+    }
+    callers
+        .into_iter()
+        .filter(move |(_, blocks)| are_unavoidable(ir, blocks, noreturn))
+        .map(|(function, _)| function)
+}
+
+/// Checks whether every path from the entry block to a return goes through one of the given
+/// blocks. A call to one of the `noreturn` functions, like `exit`, ends a path as a return does.
+pub fn are_unavoidable(ir: &IR, blocks: &HashSet<BlockBinding>, noreturn: &[&str]) -> bool {
+    // look for a return that can be reached without passing through the blocks
+    let mut visited = HashSet::new();
+    let mut queue = vec![BlockBinding(0)];
+    while let Some(next) = queue.pop() {
+        if blocks.contains(&next) || !visited.insert(next) {
+            continue;
+        }
+        if matches!(ir[next].end, super::BlockEnd::Return(_))
+            || calls_noreturn(&ir[next], noreturn)
+        {
+            return false;
+        }
+        queue.extend(ir.forward_map.get(&next).into_iter().flatten().copied());
+    }
+    true
+}
+
+/// Whether the block calls one of the `noreturn` functions
+fn calls_noreturn(block: &BasicBlock, noreturn: &[&str]) -> bool {
+    block.statements.iter().any(|statement| {
+        matches!(
+            statement,
+            Statement::Assign { value, .. } if value
+                .called_function()
+                .is_some_and(|function| noreturn.contains(&function))
+        )
+    })
+}
+
+/// The bindings that hold floating-point values, which live in registers of their own: the ones
+/// computed as such, and the phi nodes and copies of those.
+pub fn float_bindings(ir: &IR) -> HashSet<Binding> {
+    let assignments: Vec<(Binding, &Value)> = ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign { index, value } => Some((*index, value)),
+            _ => None,
+        })
+        .collect();
+    let mut floats: HashSet<Binding> = assignments
+        .iter()
+        .filter(|(_, value)| {
+            matches!(
+                value,
+                Value::FloatConstant { .. }
+                    | Value::FloatArithmetic { .. }
+                    | Value::FloatNegate { .. }
+                    | Value::IntToFloat { .. }
+                    | Value::FloatConvert { .. }
+                    | Value::FloatArgument(_)
+                    | Value::CallFloat { .. }
+                    | Value::Load {
+                        byte_size: ByteSize::F32 | ByteSize::F64,
+                        ..
+                    }
+            )
+        })
+        .map(|(binding, _)| *binding)
+        .collect();
+    // a phi node may come before the values it merges, so go until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (binding, value) in &assignments {
+            let is_float = match value {
+                Value::Phi { nodes } => nodes.iter().any(|node| floats.contains(&node.value)),
+                Value::Binding(other) => floats.contains(other),
+                _ => false,
+            };
+            if is_float {
+                changed |= floats.insert(*binding);
+            }
+        }
+    }
+    floats
+}
+
+/// One more than the highest binding the code defines, which is the first one free to define
+pub fn binding_bound(code: &[BasicBlock]) -> usize {
+    code.iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign { index, .. } => Some(index.0 + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
 pub fn find_assignment_value(code: &[BasicBlock], binding: Binding) -> Option<&Value> {
     code.iter()
         .flat_map(|block| block.statements.iter())
@@ -145,14 +300,14 @@ impl<'code> Iterator for BottomTopTraversal<'code> {
             .get(&next)
             .into_iter()
             .flatten()
-            .filter(|x| !visited_ref.contains(x))
+            .filter(|x| !visited_ref.contains(*x))
             .copied();
         self.queue.extend(parents);
         Some(next)
     }
 }
 
-pub fn predecessors(ir: &IR, block: BlockBinding) -> TopBottomTraversal {
+pub fn predecessors(ir: &IR, block: BlockBinding) -> TopBottomTraversal<'_> {
     TopBottomTraversal::new(ir, vec![block])
 }
 
@@ -163,7 +318,7 @@ pub fn predecessors_filtering_branches<'ir>(
 ) -> impl Iterator<Item = BlockBinding> + 'ir {
     let mut queue = vec![block_from];
     let mut visited = HashSet::new();
-    std::iter::from_fn(move || {
+    core::iter::from_fn(move || {
         let next = queue.pop().filter(|block| visited.insert(*block))?;
         if continue_branch(next) {
             queue.extend(ir.forward_map.get(&next).into_iter().flatten().copied());
@@ -201,7 +356,7 @@ impl<'ir> TopBottomTraversal<'ir> {
         mut mapper: impl FnMut(BlockBinding) -> I + 'ir,
     ) -> impl Iterator<Item = T> + 'ir {
         let mut current = None;
-        std::iter::from_fn(move || {
+        core::iter::from_fn(move || {
             if current.is_none() {
                 let next_block = self.next()?;
                 current = Some(mapper(next_block).into_iter());
@@ -222,7 +377,7 @@ impl<'ir> TopBottomTraversal<'ir> {
         mut self,
         mut exclude_fn: impl FnMut(BlockBinding) -> bool + 'ir,
     ) -> impl Iterator<Item = BlockBinding> + 'ir {
-        std::iter::from_fn(move || {
+        core::iter::from_fn(move || {
             let next = self.next()?;
             if exclude_fn(next) {
                 self.exclude(next);
@@ -266,4 +421,4 @@ impl<'code> Iterator for TopBottomTraversal<'code> {
 
 // parent-child order has a queue and it won't return/compute anything once everything has been
 // iterated
-impl std::iter::FusedIterator for TopBottomTraversal<'_> {}
+impl core::iter::FusedIterator for TopBottomTraversal<'_> {}
@@ -0,0 +1,173 @@
+//! Find the reads of local memory that happen before anything has been written to it.
+
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::lifetimes::BlockAddress;
+use crate::intermediate::{Binding, BlockBinding, Statement, Value, IR};
+
+/// The loads, along with the memory they read, that no store can have written to on any path
+/// from the start of the function. Only allocations whose address is never taken are considered,
+/// since anything else could be written through a pointer, or by a call returning a struct.
+pub fn uninitialized_loads(ir: &IR) -> Vec<(BlockAddress, Binding)> {
+    let statements = || ir.code.iter().flat_map(|block| &block.statements);
+    let escaped: HashSet<Binding> = statements()
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                value:
+                    Value::Address { mem_binding: base }
+                    | Value::ElementAddress { base, .. }
+                    | Value::CallStruct { result: base, .. },
+                ..
+            } => Some(*base),
+            _ => None,
+        })
+        .collect();
+    let tracked: HashSet<Binding> = statements()
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                index,
+                value: Value::Allocate { .. },
+            } if !escaped.contains(index) => Some(*index),
+            _ => None,
+        })
+        .collect();
+    // the traversal can give a block more than once, when two of the blocks before it are visited
+    // before it is
+    let mut seen = HashSet::new();
+    let reachable: Vec<BlockBinding> = super::predecessors(ir, BlockBinding(0))
+        .filter(|block| seen.insert(*block))
+        .collect();
+
+    // the memories that may have been written when each block starts, found by propagating the
+    // stores forward until nothing changes
+    let mut written_at_start: HashMap<BlockBinding, HashSet<Binding>> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &reachable {
+            let mut written = written_at_start.get(&block).cloned().unwrap_or_default();
+            written.extend(stored_memories(ir, block));
+            for child in ir.forward_map.get(&block).into_iter().flatten() {
+                let child_written = written_at_start.entry(*child).or_default();
+                let before = child_written.len();
+                child_written.extend(&written);
+                changed |= child_written.len() != before;
+            }
+        }
+    }
+
+    let mut loads = Vec::new();
+    for block in reachable {
+        let mut written = written_at_start.remove(&block).unwrap_or_default();
+        for (statement_index, statement) in ir[block].statements.iter().enumerate() {
+            match statement {
+                Statement::Store { mem_binding, .. }
+                | Statement::AtomicStore { mem_binding, .. } => {
+                    written.insert(*mem_binding);
+                }
+                Statement::Assign {
+                    value: Value::Load { mem_binding, .. },
+                    ..
+                } if tracked.contains(mem_binding) && !written.contains(mem_binding) => {
+                    loads.push((
+                        BlockAddress {
+                            block,
+                            statement: statement_index,
+                        },
+                        *mem_binding,
+                    ))
+                }
+                _ => (),
+            }
+        }
+    }
+    loads
+}
+
+fn stored_memories(ir: &IR, block: BlockBinding) -> impl Iterator<Item = Binding> + '_ {
+    ir[block]
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Store { mem_binding, .. } | Statement::AtomicStore { mem_binding, .. } => {
+                Some(*mem_binding)
+            }
+            Statement::Assign { .. } | Statement::SetRegister { .. } => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::intermediate::generate::VarWarning;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn only_definitely_uninitialized() -> anyhow::Result<()> {
+        const SOURCE_CODE: &str = r#"
+int main() {
+    int never;
+    int sometimes;
+    int always;
+    if (always = 1) { sometimes = 2; }
+    return never + sometimes + always;
+}"#;
+        let meta =
+            crate::error::SourceMetadata::new(SOURCE_CODE).with_file("<test program>".into());
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (_, warnings) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let uninitialized: Vec<_> = warnings
+            .iter()
+            .filter_map(|warning| match warning.kind() {
+                VarWarning::Uninitialized(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(uninitialized, ["never"]);
+        Ok(())
+    }
+
+    #[test]
+    fn blocks_reached_from_two_branches_are_seen_once() -> anyhow::Result<()> {
+        use crate::intermediate::interpret::{self, Inputs};
+        use crate::intermediate::passes::{OptLevel, PassManager};
+
+        const SOURCE_CODE: &str = r#"
+int f(int c, int d) {
+    int x = c ? d : 3;
+    int y = c && d;
+    int z = c || d;
+    return x + y + z;
+}"#;
+        let meta =
+            crate::error::SourceMetadata::new(SOURCE_CODE).with_file("<test program>".into());
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (module, warnings) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let uninitialized = warnings
+            .iter()
+            .filter(|warning| matches!(warning.kind(), VarWarning::Uninitialized(_)))
+            .count();
+        assert_eq!(uninitialized, 0);
+
+        let function = module.functions.into_iter().next().expect("f is compiled");
+        let ir = PassManager::for_level(OptLevel::O1).run(function.ir, &mut |_, _| ());
+        for (c, d) in [(0, 0), (0, 5), (2, 0), (2, 5)] {
+            let mut integers = [0; 8];
+            integers[..2].copy_from_slice(&[c, d]);
+            let inputs = Inputs {
+                integers,
+                floats: [0; 8],
+            };
+            let expected = if c != 0 { d } else { 3 }
+                + i64::from(c != 0 && d != 0)
+                + i64::from(c != 0 || d != 0);
+            let returned =
+                interpret::run(&ir, &function.read_only, &inputs).map(|run| run.returned);
+            assert_eq!(returned, Ok(expected), "f({}, {})\n{}", c, d, ir);
+        }
+        Ok(())
+    }
+}
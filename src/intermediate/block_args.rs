@@ -0,0 +1,352 @@
+//! Another form of the IR, where the values that flow into a block are its parameters and every
+//! branch passes the arguments for them (like Cranelift or MLIR do), instead of phi nodes naming
+//! the blocks each value comes from. Branches are then the only place where blocks are named, so
+//! renaming or removing blocks can't leave phi nodes pointing at the wrong ones.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{BasicBlock, Binding, BlockBinding, BlockEnd, Branch, PhiDescriptor, Statement, Value};
+use super::{IRCode, IR};
+
+/// A branch to a block, with the values of its parameters, in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockCall {
+    pub target: BlockBinding,
+    pub args: Vec<Binding>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArgBlockEnd {
+    Jump(BlockCall),
+    Branch {
+        flag: Binding,
+        if_true: BlockCall,
+        if_false: BlockCall,
+    },
+    Return(Binding),
+}
+
+/// A basic block without phi nodes: the bindings they defined are its parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArgBlock {
+    pub params: Vec<Binding>,
+    pub statements: Vec<Statement>,
+    pub end: ArgBlockEnd,
+}
+
+#[derive(Clone)]
+pub struct ArgIR {
+    pub code: Vec<ArgBlock>,
+}
+
+impl ArgBlockEnd {
+    pub fn calls(&self) -> impl Iterator<Item = &BlockCall> {
+        let (first, second) = match self {
+            Self::Jump(call) => (Some(call), None),
+            Self::Branch {
+                if_true, if_false, ..
+            } => (Some(if_true), Some(if_false)),
+            Self::Return(_) => (None, None),
+        };
+        first.into_iter().chain(second)
+    }
+
+    pub fn calls_mut(&mut self) -> impl Iterator<Item = &mut BlockCall> {
+        let (first, second) = match self {
+            Self::Jump(call) => (Some(call), None),
+            Self::Branch {
+                if_true, if_false, ..
+            } => (Some(if_true), Some(if_false)),
+            Self::Return(_) => (None, None),
+        };
+        first.into_iter().chain(second)
+    }
+}
+
+impl ArgIR {
+    /// Rename a block. Only the branches to it need to change.
+    ///
+    /// # Safety
+    /// The new block name must not collide with other blocks.
+    pub unsafe fn rename_block(&mut self, target: BlockBinding, replace_with: BlockBinding) {
+        self.code
+            .iter_mut()
+            .flat_map(|block| block.end.calls_mut())
+            .filter(|call| call.target == target)
+            .for_each(|call| call.target = replace_with);
+    }
+
+    /// Remove a block, along with the arguments it passed to the blocks it branched to
+    ///
+    /// # Safety
+    /// No other block may branch to it.
+    pub unsafe fn remove_block(&mut self, target: BlockBinding) -> ArgBlock {
+        let BlockBinding(index) = target;
+        for i in index..self.code.len() {
+            self.rename_block(BlockBinding(i + 1), BlockBinding(i));
+        }
+        self.code.remove(index)
+    }
+}
+
+/// The branch to `target`, with the values that the phi nodes of `phis` take when coming from
+/// `from`
+fn block_call(target: BlockBinding, from: BlockBinding, phis: &[Vec<PhiDescriptor>]) -> BlockCall {
+    let args = phis
+        .iter()
+        .map(|nodes| {
+            nodes
+                .iter()
+                .find(|node| node.block_from == from)
+                .map(|node| node.value)
+                .expect("Malformed phi node: no value for a predecessor")
+        })
+        .collect();
+    BlockCall { target, args }
+}
+
+impl From<IR> for ArgIR {
+    fn from(ir: IR) -> Self {
+        // the phi nodes of each block become its parameters, in the order they are defined
+        let (params, phis): (Vec<Vec<Binding>>, Vec<Vec<Vec<PhiDescriptor>>>) = ir
+            .code
+            .iter()
+            .map(|block| {
+                block
+                    .statements
+                    .iter()
+                    .filter_map(|statement| match statement {
+                        Statement::Assign {
+                            index,
+                            value: Value::Phi { nodes },
+                        } => Some((*index, nodes.clone())),
+                        _ => None,
+                    })
+                    .unzip()
+            })
+            .unzip();
+        let code = ir
+            .code
+            .into_iter()
+            .zip(params)
+            .enumerate()
+            .map(|(index, (BasicBlock { statements, end }, params))| {
+                let from = BlockBinding(index);
+                let call = |target: BlockBinding| block_call(target, from, &phis[target.0]);
+                ArgBlock {
+                    params,
+                    statements: statements
+                        .into_iter()
+                        .filter(|statement| {
+                            !matches!(
+                                statement,
+                                Statement::Assign {
+                                    value: Value::Phi { .. },
+                                    ..
+                                }
+                            )
+                        })
+                        .collect(),
+                    end: match end {
+                        BlockEnd::Branch(Branch::Unconditional { target }) => {
+                            ArgBlockEnd::Jump(call(target))
+                        }
+                        BlockEnd::Branch(Branch::Conditional {
+                            flag,
+                            target_true,
+                            target_false,
+                        }) => ArgBlockEnd::Branch {
+                            flag,
+                            if_true: call(target_true),
+                            if_false: call(target_false),
+                        },
+                        BlockEnd::Return(binding) => ArgBlockEnd::Return(binding),
+                    },
+                }
+            })
+            .collect();
+        Self { code }
+    }
+}
+
+impl From<ArgIR> for IR {
+    /// The parameters become phi nodes at the start of their blocks. A block that nothing
+    /// branches to has undefined parameters.
+    fn from(ir: ArgIR) -> Self {
+        let mut phis: Vec<Vec<Vec<PhiDescriptor>>> = ir
+            .code
+            .iter()
+            .map(|block| vec![Vec::new(); block.params.len()])
+            .collect();
+        for (index, block) in ir.code.iter().enumerate() {
+            let block_from = BlockBinding(index);
+            for call in block.end.calls() {
+                let nodes = &mut phis[call.target.0];
+                debug_assert_eq!(
+                    call.args.len(),
+                    nodes.len(),
+                    "a branch must pass an argument for each parameter"
+                );
+                for (nodes, &value) in nodes.iter_mut().zip(&call.args) {
+                    // both sides of a conditional branch may go to the same block
+                    match nodes.iter().find(|node| node.block_from == block_from) {
+                        Some(node) => debug_assert_eq!(
+                            node.value, value,
+                            "both branches to a block must pass it the same arguments"
+                        ),
+                        None => nodes.push(PhiDescriptor { value, block_from }),
+                    }
+                }
+            }
+        }
+        let code: IRCode = ir
+            .code
+            .into_iter()
+            .zip(phis)
+            .map(|(block, phis)| BasicBlock {
+                statements: block
+                    .params
+                    .into_iter()
+                    .zip(phis)
+                    .map(|(index, nodes)| Statement::Assign {
+                        index,
+                        value: if nodes.is_empty() {
+                            Value::Undefined
+                        } else {
+                            Value::Phi { nodes }
+                        },
+                    })
+                    .chain(block.statements)
+                    .collect(),
+                end: match block.end {
+                    ArgBlockEnd::Jump(call) => Branch::Unconditional {
+                        target: call.target,
+                    }
+                    .into(),
+                    ArgBlockEnd::Branch {
+                        flag,
+                        if_true,
+                        if_false,
+                    } => Branch::Conditional {
+                        flag,
+                        target_true: if_true.target,
+                        target_false: if_false.target,
+                    }
+                    .into(),
+                    ArgBlockEnd::Return(binding) => binding.into(),
+                },
+            })
+            .collect();
+        code.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::assembly::{BitSize, Condition};
+
+    fn assign(index: usize, value: Value) -> Statement {
+        Statement::Assign {
+            index: Binding(index),
+            value,
+        }
+    }
+
+    /// `%2 = %0 == 0 ? %0 : %1`, with the middle block only reached from the first one
+    fn diamond() -> IR {
+        vec![
+            BasicBlock {
+                statements: vec![
+                    assign(0, Value::Argument(0)),
+                    assign(
+                        3,
+                        Value::Cmp {
+                            condition: Condition::Equals,
+                            lhs: Binding(0),
+                            rhs: 0.into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    ),
+                ],
+                end: Branch::Conditional {
+                    flag: Binding(3),
+                    target_true: BlockBinding(2),
+                    target_false: BlockBinding(1),
+                }
+                .into(),
+            },
+            BasicBlock {
+                statements: vec![assign(1, Value::Argument(1))],
+                end: Branch::Unconditional {
+                    target: BlockBinding(2),
+                }
+                .into(),
+            },
+            BasicBlock {
+                statements: vec![assign(
+                    2,
+                    Value::Phi {
+                        nodes: vec![
+                            PhiDescriptor {
+                                value: Binding(0),
+                                block_from: BlockBinding(0),
+                            },
+                            PhiDescriptor {
+                                value: Binding(1),
+                                block_from: BlockBinding(1),
+                            },
+                        ],
+                    },
+                )],
+                end: Binding(2).into(),
+            },
+        ]
+        .into()
+    }
+
+    #[test]
+    fn phi_nodes_become_arguments_and_back() {
+        let args = ArgIR::from(diamond());
+        assert_eq!(args.code[2].params, vec![Binding(2)]);
+        assert!(args.code[2].statements.is_empty());
+        assert_eq!(
+            args.code[1].end,
+            ArgBlockEnd::Jump(BlockCall {
+                target: BlockBinding(2),
+                args: vec![Binding(1)],
+            })
+        );
+        let ir = IR::from(args);
+        let original = diamond();
+        for (block, original) in ir.code.iter().zip(&original.code) {
+            assert_eq!(block.statements, original.statements);
+            assert_eq!(block.end, original.end);
+        }
+    }
+
+    #[test]
+    fn removing_a_predecessor_drops_its_arguments() {
+        let mut args = ArgIR::from(diamond());
+        args.code[0].end = ArgBlockEnd::Jump(BlockCall {
+            target: BlockBinding(2),
+            args: vec![Binding(0)],
+        });
+        // UNSAFE: safe, nothing branches to the middle block anymore
+        unsafe { args.remove_block(BlockBinding(1)) };
+        let ir = IR::from(args);
+        assert_eq!(
+            ir.code[1].statements,
+            vec![assign(
+                2,
+                Value::Phi {
+                    nodes: vec![PhiDescriptor {
+                        value: Binding(0),
+                        block_from: BlockBinding(0),
+                    }],
+                },
+            )]
+        );
+        assert_eq!(ir.backwards_map[&BlockBinding(1)], vec![BlockBinding(0)]);
+    }
+}
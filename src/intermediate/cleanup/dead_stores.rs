@@ -0,0 +1,197 @@
+//! Dead store elimination for stack slots. Nothing but the function's own loads can read a stack
+//! slot whose address goes nowhere else, so a store to one is dead when no load reads what it
+//! wrote before another store overwrites it, or the function returns. The slots are accessed from
+//! their start, so what may still be read is kept as how many bytes from the start, which a store
+//! as wide overwrites.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::intermediate::analysis::BindingUsage;
+use crate::intermediate::{Binding, BlockBinding, BlockEnd, Branch, Statement, Value, IR};
+
+/// How many bytes from the start of each slot may be read before being overwritten. The slots
+/// that aren't there have none.
+type Needed = HashMap<Binding, usize>;
+
+/// Remove the stores to stack slots that are never read, giving how many there were
+pub fn remove_dead_stores(ir: &mut IR) -> usize {
+    let slots = slots(ir);
+    if slots.is_empty() {
+        return 0;
+    }
+    // what each block needs when it starts, found going backwards until nothing changes
+    let mut needed_in: Vec<Needed> = vec![Needed::new(); ir.code.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in (0..ir.code.len()).rev() {
+            let (needed, _) = needed_at_start(ir, &slots, &needed_in, BlockBinding(block));
+            if needed != needed_in[block] {
+                needed_in[block] = needed;
+                changed = true;
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for block in 0..ir.code.len() {
+        let (_, dead) = needed_at_start(ir, &slots, &needed_in, BlockBinding(block));
+        removed += dead.len();
+        let mut index = 0;
+        ir.code[block].statements.retain(|_| {
+            index += 1;
+            !dead.contains(&(index - 1))
+        });
+    }
+    removed
+}
+
+/// What the block needs when it starts, given what the blocks it branches to do, along with the
+/// indices of its dead stores
+fn needed_at_start(
+    ir: &IR,
+    slots: &HashSet<Binding>,
+    needed_in: &[Needed],
+    block: BlockBinding,
+) -> (Needed, Vec<usize>) {
+    let mut needed = Needed::new();
+    for successor in ir.forward_map.get(&block).into_iter().flatten() {
+        for (&slot, &bytes) in &needed_in[successor.0] {
+            let entry = needed.entry(slot).or_default();
+            *entry = (*entry).max(bytes);
+        }
+    }
+    let mut dead = Vec::new();
+    for (index, statement) in ir[block].statements.iter().enumerate().rev() {
+        match statement {
+            Statement::Assign {
+                value:
+                    Value::Load {
+                        mem_binding,
+                        byte_size,
+                        ..
+                    },
+                ..
+            } if slots.contains(mem_binding) => {
+                let entry = needed.entry(*mem_binding).or_default();
+                *entry = (*entry).max(byte_size.bytes());
+            }
+            Statement::Store {
+                mem_binding,
+                byte_size,
+                ..
+            } if slots.contains(mem_binding) => match needed.get(mem_binding) {
+                None => dead.push(index),
+                // a narrower store leaves some of what's needed as it was
+                Some(&bytes) if byte_size.bytes() >= bytes => {
+                    needed.remove(mem_binding);
+                }
+                Some(_) => (),
+            },
+            _ => (),
+        }
+    }
+    (needed, dead)
+}
+
+/// The allocations only used by the loads and stores that aren't volatile, to access them
+fn slots(ir: &IR) -> HashSet<Binding> {
+    let mut escaped = HashSet::new();
+    let mut allocations = HashSet::new();
+    for block in &ir.code {
+        for statement in &block.statements {
+            match statement {
+                Statement::Assign {
+                    index,
+                    value: Value::Allocate { .. },
+                } => {
+                    allocations.insert(*index);
+                }
+                Statement::Assign {
+                    value:
+                        Value::Load {
+                            volatile: false, ..
+                        },
+                    ..
+                } => (),
+                Statement::Store {
+                    binding,
+                    volatile: false,
+                    ..
+                } => {
+                    // storing the address somewhere lets it be used from there
+                    escaped.insert(*binding);
+                }
+                statement => escaped.extend(statement.binding_deps()),
+            }
+        }
+        escaped.extend(match block.end {
+            BlockEnd::Return(binding)
+            | BlockEnd::Branch(Branch::Conditional { flag: binding, .. }) => Some(binding),
+            BlockEnd::Branch(Branch::Unconditional { .. }) => None,
+        });
+    }
+    allocations.retain(|allocation| !escaped.contains(allocation));
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{interpret, verify};
+
+    fn compile(source: &str) -> IR {
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (mut module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())
+                .expect("the source is fine");
+        module.functions.remove(0).ir
+    }
+
+    fn returns(ir: &IR) -> Vec<i64> {
+        interpret::sample_inputs()
+            .map(|inputs| interpret::run(ir, &[], &inputs).map(|outcome| outcome.returned))
+            .map(|returned| returned.expect("the function returns"))
+            .collect()
+    }
+
+    #[test]
+    fn stores_overwritten_before_being_read_are_removed() {
+        let mut ir = compile(
+            "int f(int a) {\n\
+             \x20   char c = 1;\n\
+             \x20   short s = 2;\n\
+             \x20   c = a;\n\
+             \x20   if (a) s = c;\n\
+             \x20   c = 3;\n\
+             \x20   return s + c;\n\
+             }\n",
+        );
+        let before = returns(&ir);
+        // `c = 1` is overwritten before anything reads it. `s = 2` is read when `a` is zero, and
+        // `c = a` when it isn't.
+        assert_eq!(remove_dead_stores(&mut ir), 1);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert_eq!(returns(&ir), before);
+        assert_eq!(remove_dead_stores(&mut ir), 0);
+    }
+
+    #[test]
+    fn stores_to_memory_that_escapes_stay() {
+        let mut ir = compile(
+            "int g(char *p);\n\
+             int f(int a) {\n\
+             \x20   char c = 1;\n\
+             \x20   g(&c);\n\
+             \x20   c = 2;\n\
+             \x20   return a;\n\
+             }\n",
+        );
+        assert_eq!(remove_dead_stores(&mut ir), 0);
+    }
+}
@@ -0,0 +1,257 @@
+//! If-conversion: a conditional branch whose sides only compute the values of the phi nodes where
+//! they meet again becomes a jump there, the phi nodes taking `select`s of what each side gave
+//! them. The statements of the sides are moved before the branch, so they run whichever side is
+//! taken, which is why they have to be cheap and do nothing but give a value. A side may also go
+//! straight to where they meet. The blocks of the sides are left for
+//! [`super::prune_unreached_blocks`].
+use alloc::vec::Vec;
+
+use crate::intermediate::{analysis, generate};
+use crate::intermediate::{
+    Binding, BlockBinding, BlockEnd, Branch, CouldBeConstant, PhiDescriptor, Statement, Value, IR,
+};
+
+/// How many statements a side may have for them to run on both paths
+const MAX_SIDE_STATEMENTS: usize = 2;
+
+/// Turn the branches whose sides only compute values into selects, giving how many there were
+pub fn convert_to_selects(ir: &mut IR) -> usize {
+    let mut converted = 0;
+    for header in (0..ir.code.len()).map(BlockBinding) {
+        if convert(ir, header) {
+            (ir.forward_map, ir.backwards_map) = generate::generate_branching_graphs(&ir.code);
+            converted += 1;
+        }
+    }
+    converted
+}
+
+/// Values cheap enough to compute when they may not be needed, and that can't fault
+const fn is_speculable(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Constant(_)
+            | Value::Binding(_)
+            | Value::Cmp { .. }
+            | Value::Select { .. }
+            | Value::Negate { .. }
+            | Value::FlipBits { .. }
+            | Value::SignExtend { .. }
+            | Value::Add { .. }
+            | Value::Subtract { .. }
+            | Value::Lsl { .. }
+            | Value::Lsr { .. }
+            | Value::Asr { .. }
+            | Value::And { .. }
+            | Value::Or { .. }
+            | Value::Xor { .. }
+            | Value::Argument(_)
+            | Value::Address { .. }
+            | Value::ElementAddress { .. }
+    )
+}
+
+/// Where the side of the branch of `header` that goes to `block` jumps, if all the block does is
+/// compute a few values before that
+fn side_target(ir: &IR, header: BlockBinding, block: BlockBinding) -> Option<BlockBinding> {
+    if ir.backwards_map.get(&block).map(Vec::as_slice) != Some(&[header]) {
+        return None;
+    }
+    let BlockEnd::Branch(Branch::Unconditional { target }) = ir[block].end else {
+        return None;
+    };
+    let statements = &ir[block].statements;
+    let cheap = statements.len() <= MAX_SIDE_STATEMENTS
+        && statements.iter().all(|statement| {
+            matches!(statement, Statement::Assign { value, .. } if is_speculable(value))
+        });
+    (cheap && target != block && target != header).then_some(target)
+}
+
+/// Turn the branch that ends `header` into a jump to where its sides meet, telling whether it did
+fn convert(ir: &mut IR, header: BlockBinding) -> bool {
+    let BlockEnd::Branch(Branch::Conditional {
+        flag,
+        target_true,
+        target_false,
+    }) = ir[header].end
+    else {
+        return false;
+    };
+    if target_true == target_false {
+        return false;
+    }
+    // the block each side takes the phi nodes' value from, and where they meet
+    let (from_true, from_false, join) = match (
+        side_target(ir, header, target_true),
+        side_target(ir, header, target_false),
+    ) {
+        (Some(join_true), Some(join_false)) if join_true == join_false => {
+            (target_true, target_false, join_true)
+        }
+        (Some(join), _) if join == target_false => (target_true, header, join),
+        (_, Some(join)) if join == target_true => (header, target_false, join),
+        _ => return false,
+    };
+    // there's no select for the floating-point registers
+    let floats = analysis::float_bindings(ir);
+    let mut phis = Vec::new();
+    for statement in &ir[join].statements {
+        if let Statement::Assign {
+            index,
+            value: Value::Phi { nodes },
+        } = statement
+        {
+            if floats.contains(index) {
+                return false;
+            }
+            let from = |block| nodes.iter().find(|node| node.block_from == block);
+            let (Some(if_true), Some(if_false)) = (from(from_true), from(from_false)) else {
+                return false;
+            };
+            phis.push((*index, if_true.value, if_false.value));
+        }
+    }
+
+    // the sides run before the branch would, and then the selects
+    let mut next = analysis::binding_bound(&ir.code);
+    for side in [from_true, from_false] {
+        if side != header {
+            let statements = core::mem::take(&mut ir[side].statements);
+            ir[header].statements.extend(statements);
+        }
+    }
+    for (phi, if_true, if_false) in phis {
+        let selected = if if_true == if_false {
+            if_true
+        } else {
+            let selected = Binding(next);
+            next += 1;
+            ir[header].statements.push(Statement::Assign {
+                index: selected,
+                value: Value::Select {
+                    flag,
+                    if_true: CouldBeConstant::Binding(if_true),
+                    if_false: CouldBeConstant::Binding(if_false),
+                },
+            });
+            selected
+        };
+        for statement in &mut ir[join].statements {
+            match statement {
+                Statement::Assign {
+                    index,
+                    value: Value::Phi { nodes },
+                } if *index == phi => {
+                    // the side going straight to the join was the header already
+                    match nodes.iter_mut().find(|node| node.block_from == header) {
+                        Some(node) => node.value = selected,
+                        None => nodes.push(PhiDescriptor {
+                            value: selected,
+                            block_from: header,
+                        }),
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    ir[header].end = BlockEnd::Branch(Branch::Unconditional { target: join });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{cleanup, verify};
+
+    #[test]
+    fn sides_that_only_compute_a_value_become_a_select() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = arg 1
+              br-cond %0, BB1, BB2
+            BB1:
+              %2 = add %1, 1
+              br  BB3
+            BB2:
+              %3 = 5
+              br  BB3
+            BB3:
+              %4 = phi [ %2, BB1 ], [ %3, BB2 ]
+              ret %4
+        "
+        .parse()?;
+        assert_eq!(convert_to_selects(&mut ir), 1);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        cleanup::prune_unreached_blocks(&mut ir);
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = arg 1
+              %2 = add %1, 1
+              %3 = 5
+              %5 = select %0, %2, %3
+              br  BB1
+            BB1:
+              %4 = phi [ %5, BB0 ]
+              ret %4
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        Ok(())
+    }
+
+    #[test]
+    fn a_side_may_go_straight_to_where_they_meet() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = arg 1
+              br-cond %0, BB2, BB1
+            BB1:
+              %2 = sub %1, 1
+              br  BB2
+            BB2:
+              %3 = phi [ %1, BB0 ], [ %2, BB1 ]
+              ret %3
+        "
+        .parse()?;
+        assert_eq!(convert_to_selects(&mut ir), 1);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        cleanup::prune_unreached_blocks(&mut ir);
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = arg 1
+              %2 = sub %1, 1
+              %4 = select %0, %1, %2
+              br  BB1
+            BB1:
+              %3 = phi [ %4, BB0 ]
+              ret %3
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        Ok(())
+    }
+
+    #[test]
+    fn sides_that_may_fault_stay_behind_the_branch() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              %1 = load %0, u32
+              br  BB2
+            BB2:
+              %2 = phi [ %0, BB0 ], [ %1, BB1 ]
+              ret %2
+        "
+        .parse()?;
+        assert_eq!(convert_to_selects(&mut ir), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Block merging: IR generation starts a new block for each statement that branches, so a lot of
+//! blocks just jump to the next one, which nothing else jumps to. Those are merged into one, the
+//! phi nodes of the merged block being replaced everywhere by what they took from the block before
+//! it.
+use hashbrown::HashMap;
+
+use crate::intermediate::refactor::{
+    self,
+    redefine::{Rename, RenameMap},
+};
+use crate::intermediate::{BasicBlock, BlockBinding, Statement, Value, IR};
+
+/// The places where a block only jumps to a child that has no other parent
+fn find_unique_jumps(ir: &IR) -> impl Iterator<Item = (BlockBinding, BlockBinding)> + '_ {
+    ir.forward_map.iter().filter_map(|(parent, children)| {
+        let [unique_child] = children.as_slice() else {
+            return None;
+        };
+        let unique_child_parents = &ir.backwards_map[unique_child];
+        if unique_child_parents.len() == 1 {
+            debug_assert_eq!(
+                unique_child_parents[0], *parent,
+                "Mismatch in backwards map: one block has a child who doesn't recognize it as a parent"
+            );
+            Some((*parent, *unique_child))
+        } else {
+            None
+        }
+    })
+}
+
+/// Merge the blocks that are the only child of a parent that only jumps to them into it, telling
+/// whether there were any. The branching graphs are kept up to date.
+pub fn merge_unique_jumps(ir: &mut IR) -> bool {
+    let mut jumps: HashMap<_, _> = find_unique_jumps(ir).collect();
+
+    let did_merge = !jumps.is_empty();
+
+    // a child that's the parent of another merge gets its own child merged into it first
+    fn find_noncolliding_merge(
+        jumps: &mut HashMap<BlockBinding, BlockBinding>,
+    ) -> Option<(BlockBinding, BlockBinding)> {
+        for (parent, child) in jumps.iter().map(|(a, b)| (*a, *b)) {
+            if !jumps.contains_key(&child) {
+                jumps.remove(&parent);
+                return Some((parent, child));
+            }
+        }
+        None
+    }
+
+    while let Some((parent, child)) = find_noncolliding_merge(&mut jumps) {
+        // the phi nodes of the child can only take what comes from the parent, which is used
+        // instead of them in the whole function
+        let aliases = take_phis_from(&mut ir[child], parent);
+        ir.code.rename_all(&aliases);
+        // the parent takes the place of the child in the branching graphs: it goes where the
+        // child went, and comes from where it came from
+        ir.forward_map.remove(&parent);
+        ir.backwards_map.remove(&child);
+        // UNSAFE: safe. The child is removed right after, so the parent is the only one named so.
+        unsafe { refactor::rename_block(ir, child, parent) };
+        // UNSAFE: safe. Nothing refers to the child anymore.
+        let child_block = unsafe { refactor::remove_block(ir, child) };
+        // the blocks after the child moved one place down, the parent and the pending jumps too
+        let shift = |block: BlockBinding| {
+            if block > child {
+                BlockBinding(block.0 - 1)
+            } else {
+                block
+            }
+        };
+        merge_blocks(&mut ir[shift(parent)], child_block);
+        jumps = jumps
+            .into_iter()
+            .map(|(parent, child)| (shift(parent), shift(child)))
+            .collect();
+    }
+
+    did_merge
+}
+
+/// Remove the phi nodes of the block, giving what each one took from `predecessor`
+fn take_phis_from(block: &mut BasicBlock, predecessor: BlockBinding) -> RenameMap {
+    let mut aliases = RenameMap::new();
+    block.statements.retain(|statement| {
+        let Statement::Assign {
+            index,
+            value: Value::Phi { nodes },
+        } = statement
+        else {
+            return true;
+        };
+        let value = nodes
+            .iter()
+            .find(|descriptor| descriptor.block_from == predecessor)
+            .expect("Merging with no phi node data")
+            .value;
+        aliases.insert(*index, value);
+        false
+    });
+    aliases
+}
+
+/// Append the child to the parent. The child has to be removed first, so it's taken by value.
+fn merge_blocks(parent: &mut BasicBlock, child: BasicBlock) {
+    parent.end = child.end;
+    parent.statements.extend(child.statements);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{generate, verify, BranchingMap};
+    use alloc::vec::Vec;
+
+    fn sorted(map: &BranchingMap) -> Vec<(BlockBinding, Vec<BlockBinding>)> {
+        let mut entries: Vec<_> = map
+            .iter()
+            .map(|(block, others)| {
+                let mut others = others.clone();
+                others.sort_unstable();
+                (*block, others)
+            })
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    #[test]
+    fn chains_of_jumps_become_one_block_with_the_graphs_kept() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB3
+            BB1:
+              %1 = add.64 %0, 1
+              br  BB2
+            BB2:
+              %2 = phi [ %1, BB1 ]
+              %3 = add.64 %2, 2
+              br  BB4
+            BB3:
+              br  BB4
+            BB4:
+              %4 = phi [ %3, BB2 ], [ %0, BB3 ]
+              ret %4
+        "
+        .parse()?;
+        assert!(merge_unique_jumps(&mut ir));
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              %1 = add.64 %0, 1
+              %3 = add.64 %1, 2
+              br  BB3
+            BB2:
+              br  BB3
+            BB3:
+              %4 = phi [ %3, BB1 ], [ %0, BB2 ]
+              ret %4
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        let (forward_map, backwards_map) = generate::generate_branching_graphs(&ir.code);
+        assert_eq!(sorted(&ir.forward_map), sorted(&forward_map));
+        assert_eq!(sorted(&ir.backwards_map), sorted(&backwards_map));
+        assert!(!merge_unique_jumps(&mut ir));
+
+        // a block that returns, which goes nowhere
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br  BB1
+            BB1:
+              ret %0
+        "
+        .parse()?;
+        assert!(merge_unique_jumps(&mut ir));
+        assert_eq!(ir.code.len(), 1);
+        let (forward_map, backwards_map) = generate::generate_branching_graphs(&ir.code);
+        assert_eq!(sorted(&ir.forward_map), sorted(&forward_map));
+        assert_eq!(sorted(&ir.backwards_map), sorted(&backwards_map));
+        Ok(())
+    }
+
+    #[test]
+    fn phi_nodes_merged_away_are_replaced_in_every_block() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br  BB1
+            BB1:
+              %1 = phi [ %0, BB0 ]
+              br-cond %1, BB2, BB3
+            BB2:
+              %2 = add.64 %1, 1
+              br  BB3
+            BB3:
+              %3 = phi [ %1, BB1 ], [ %2, BB2 ]
+              ret %3
+        "
+        .parse()?;
+        assert!(merge_unique_jumps(&mut ir));
+        assert_eq!(verify::verify(&ir), Ok(()));
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              %2 = add.64 %0, 1
+              br  BB2
+            BB2:
+              %3 = phi [ %0, BB0 ], [ %2, BB1 ]
+              ret %3
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        Ok(())
+    }
+}
@@ -1,153 +1,227 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+use hashbrown::HashSet;
+
+pub mod dead_stores;
+pub mod if_conversion;
+pub mod merge;
+pub mod sccp;
+pub mod threading;
 
 use super::{
-    refactor::{self, redefine::Rename},
+    analysis::{BindingUsage, DefUseChains},
+    block_args::ArgIR,
+    refactor::{
+        self,
+        redefine::{Rename, RenameMap},
+    },
     BasicBlock, Binding, BlockBinding, BlockEnd, Branch, IRCode, Statement, Value, IR,
 };
 
 pub fn run_safe_cleanup(ir: &mut IR) {
+    remove_single_phi_nodes(&mut ir.code);
     remove_aliases(&mut ir.code);
     remove_unused_bindings(ir);
 }
 
+/// Remove the bindings that nothing uses, unless computing them has side effects. What only they
+/// used is unused once they are gone, so it's removed too.
 pub fn remove_unused_bindings(ir: &mut IR) {
-    // #1. Catch all the definitions
-
-    use super::analysis::lifetimes::BlockAddress;
-    let defs_map: HashMap<_, _> = super::analysis::lifetimes::get_defs(ir).collect();
-
-    let defs: HashSet<_> = defs_map.keys().cloned().collect();
-
-    // #2 Catch all the binding dependencies
-
-    use super::analysis::BindingUsage;
-    let deps: HashSet<Binding> = ir
-        .code
-        .iter()
-        .flat_map(|block: &BasicBlock| block.binding_deps())
+    let mut chains = DefUseChains::new(&ir.code);
+    let mut unused: HashSet<Binding> = chains
+        .definitions()
+        .map(|(binding, _)| binding)
+        .filter(|binding| chains.is_unused(*binding))
         .collect();
-
-    // #3. Anything defined but not depended on is unused
-    let unused = defs
-        .difference(&deps)
-        .cloned()
-        .map(|binding| defs_map[&binding]);
-
-    // #4. Organize the addresses into their corresponding blocks
-    let mut blocks: HashMap<BlockBinding, Vec<usize>> = HashMap::new();
-    for BlockAddress { block, statement } in unused {
-        blocks.entry(block).or_default().push(statement);
-    }
-
-    // #5. Do the liberations
-    for (block, mut indices) in blocks {
-        // sort the indices so that deletion can be done while keeping all indices correct
-        indices.sort_unstable_by(|a, b| a.cmp(b).reverse());
-
-        for index in indices.into_iter() {
-            ir[block].statements.remove(index);
-        }
+    while !unused.is_empty() {
+        let removed = chains.remove_definitions(&mut ir.code, &unused, |statement| {
+            !matches!(statement, Statement::Assign { value, .. } if value.has_side_effects())
+        });
+        unused = removed
+            .iter()
+            .flat_map(|statement| statement.binding_deps())
+            .filter(|binding| chains.is_unused(*binding))
+            .collect();
     }
 }
 
-fn order_by_dependency<K: Copy + Eq + std::hash::Hash>(mut map: HashMap<K, K>) -> Vec<(K, K)> {
-    let mut v = Vec::new();
-    while let Some(k) = map.keys().find(|k| !map.contains_key(&map[k])).copied() {
-        let value = map.remove(&k).unwrap();
-        v.push((k, value))
+/// A phi node that takes a value from a single block, the only one that reaches its own, is a
+/// copy of that value
+pub fn remove_single_phi_nodes(code: &mut IRCode) {
+    for statement in code.iter_mut().flat_map(|block| &mut block.statements) {
+        if let Statement::Assign { value, .. } = statement {
+            if let Value::Phi { nodes } = value {
+                if let [single] = nodes.as_slice() {
+                    *value = Value::Binding(single.value);
+                }
+            }
+        }
     }
-    v
 }
 
 /// More efficient routine when it is known that the expected aliases are in the same block.
 pub fn remove_aliases_in_same_block(block: &mut BasicBlock) {
-    let aliases = block
-        .statements
-        .iter()
-        .filter_map(|statement| {
+    let mut aliases = RenameMap::new();
+    block.statements.retain(|statement| {
+        if let Statement::Assign {
+            index: target,
+            value: Value::Binding(bind),
+        } = statement
+        {
+            aliases.insert(*target, *bind);
+            false
+        } else {
+            true
+        }
+    });
+    block.rename_all(&aliases);
+}
+
+pub fn remove_aliases(code: &mut IRCode) {
+    // #1. Catch (and remove) all the aliases
+    let mut aliases = RenameMap::new();
+
+    for block in code.iter_mut() {
+        block.statements.retain(|statement| {
             if let Statement::Assign {
                 index: target,
-                value: Value::Binding(bind),
+                value: Value::Binding(other),
             } = statement
             {
-                Some((*target, *bind))
+                aliases.insert(*target, *other);
+                false
             } else {
-                None
+                true
             }
-        })
-        .collect::<HashMap<_, _>>();
-
-    let mut to_remove = HashSet::new();
-
-    for (from, to) in order_by_dependency(aliases) {
-        to_remove.insert(
-            block
-                .statements
-                .iter()
-                .enumerate()
-                .find_map(|(index, stmt)| {
-                    if let Statement::Assign { index: target, .. } = stmt {
-                        if target == &from {
-                            Some(index)
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
+        });
+    }
+
+    // #2. Rebind aliases
+    code.rename_all(&aliases);
+}
+
+/// Replace the copies of bindings by what they copy, following them through chains of copies
+/// and through the phi nodes that merge a single value, even when those chains go across blocks.
+/// A phi node merges a single value when all that comes into it is that value or the phi node
+/// itself, once the copies found are replaced. Tells whether there were any.
+pub fn propagate_copies(code: &mut IRCode) -> bool {
+    let mut chains = DefUseChains::new(code);
+    let mut copies = RenameMap::new();
+    let resolve = |copies: &RenameMap, mut binding: Binding| {
+        while let Some(copied) = copies.get(&binding) {
+            binding = *copied;
+        }
+        binding
+    };
+    // a phi node may only merge a single value once others are found to, so the blocks that use
+    // what is found to be a copy are looked at again
+    let mut pending: Vec<BlockBinding> = (0..code.len()).rev().map(BlockBinding).collect();
+    let mut queued: HashSet<BlockBinding> = pending.iter().copied().collect();
+    while let Some(block) = pending.pop() {
+        queued.remove(&block);
+        for statement in &code[block.0].statements {
+            let Statement::Assign { index, value } = statement else {
+                continue;
+            };
+            if copies.contains_key(index) {
+                continue;
+            }
+            let copied = match value {
+                Value::Binding(other) => Some(resolve(&copies, *other)),
+                Value::Phi { nodes } => {
+                    let mut merged = nodes
+                        .iter()
+                        .map(|node| resolve(&copies, node.value))
+                        .filter(|merged| merged != index);
+                    merged
+                        .next()
+                        .filter(|first| merged.all(|other| other == *first))
+                }
+                _ => None,
+            };
+            // what copies itself through a cycle of copies is left alone
+            if let Some(copied) = copied.filter(|copied| copied != index) {
+                copies.insert(*index, copied);
+                for user in chains.users(*index) {
+                    if queued.insert(user) {
+                        pending.push(user);
                     }
-                })
-                .unwrap(),
-        );
-        block.rename(from, to);
+                }
+            }
+        }
     }
-    let mut to_remove = to_remove.into_iter().collect::<Vec<_>>();
-    to_remove.sort_unstable();
-    for index in to_remove.into_iter().rev() {
-        block.statements.remove(index);
+    if copies.is_empty() {
+        return false;
     }
+    let copied: HashSet<Binding> = copies.keys().copied().collect();
+    chains.remove_definitions(code, &copied, |_| true);
+    chains.rename_all(code, &copies);
+    true
 }
 
-pub fn remove_aliases(code: &mut IRCode) {
-    // #1. Catch all the aliases
-    let mut aliases = HashMap::new();
-
-    for (block_index, block) in code.iter().enumerate() {
-        for (statement_index, statement) in block.statements.iter().enumerate() {
+/// Make the conditional branch that ends the block only go where it goes when its flag holds
+/// `flag`. The phi nodes of the other target stop taking values from the block.
+pub fn decide_branch(ir: &mut IR, block: BlockBinding, flag: i64) {
+    let BlockEnd::Branch(Branch::Conditional {
+        target_true,
+        target_false,
+        ..
+    }) = ir[block].end
+    else {
+        return;
+    };
+    let (taken, dropped) = if flag == 0 {
+        (target_false, target_true)
+    } else {
+        (target_true, target_false)
+    };
+    ir[block].end = BlockEnd::Branch(Branch::Unconditional { target: taken });
+    // the block doesn't reach the other target anymore, so its phi nodes can't take a value from
+    // it
+    if dropped != taken {
+        for statement in &mut ir[dropped].statements {
             if let Statement::Assign {
-                index: target,
-                value: Value::Binding(other),
+                value: Value::Phi { nodes },
+                ..
             } = statement
             {
-                aliases.insert(*target, (*other, block_index, statement_index));
+                nodes.retain(|node| node.block_from != block);
             }
         }
     }
+}
 
-    let mut aliases: Vec<_> = aliases.into_iter().collect();
-
-    aliases.sort_unstable_by(
-        |(_, (_, block1, statement1)), (_, (_, block2, statement2))| {
-            if *block1 != *block2 {
-                std::cmp::Ordering::Equal
-            } else {
-                statement1.cmp(statement2).reverse()
-            }
-        },
-    );
-
-    // #2. Rebind aliases
-    for (from, (to, block_index, statement_index)) in aliases {
-        code.rename(from, to); // rebind
-        debug_assert_eq!(
-            code[block_index].statements.remove(statement_index),
-            Statement::Assign {
-                index: from,
-                value: Value::Binding(to)
-            },
-            "Health check: remove alias correctly"
-        );
+/// Make the conditional branches that can only go one way unconditional: the ones on a constant
+/// flag, and the ones whose targets are the same block. Tells whether there were any.
+pub fn simplify_branches(ir: &mut IR) -> bool {
+    let mut simplified = false;
+    for block in (0..ir.code.len()).map(BlockBinding) {
+        let BlockEnd::Branch(Branch::Conditional {
+            flag,
+            target_true,
+            target_false,
+        }) = ir[block].end
+        else {
+            continue;
+        };
+        if target_true == target_false {
+            // the phi nodes there take a single value from the block either way
+            ir[block].end = BlockEnd::Branch(Branch::Unconditional {
+                target: target_true,
+            });
+            simplified = true;
+        } else if let Some(Value::Constant(c)) =
+            super::analysis::find_assignment_value(&ir.code, flag)
+        {
+            decide_branch(ir, block, *c);
+            simplified = true;
+        }
     }
+    if simplified {
+        (ir.forward_map, ir.backwards_map) = super::generate::generate_branching_graphs(&ir.code);
+    }
+    simplified
 }
 
 /// prune not reached blocks
@@ -163,8 +237,7 @@ pub fn prune_unreached_blocks(ir: &mut IR) {
             .collect();
 
         // now propagate to their children
-        while !queue.is_empty() {
-            let next = queue.pop().unwrap();
+        while let Some(next) = queue.pop() {
             if unreached.contains(&next) {
                 continue;
             }
@@ -201,10 +274,181 @@ pub fn prune_unreached_blocks(ir: &mut IR) {
         unreached
     };
 
-    // for all unused blocks:
+    if unused_blocks.is_empty() {
+        return;
+    }
+    // the blocks are removed with block arguments instead of phi nodes, so that what they passed
+    // to the blocks they branched to goes away with them
+    let mut args = ArgIR::from(core::mem::replace(ir, IRCode::new().into()));
     for unused_binding in unused_blocks {
-        // remove the block
         // UNSAFE: safe. the block is no longer used.
-        unsafe { refactor::remove_block(ir, unused_binding) };
+        unsafe { args.remove_block(unused_binding) };
+    }
+    *ir = args.into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn assign(index: usize, value: Value) -> Statement {
+        Statement::Assign {
+            index: Binding(index),
+            value,
+        }
+    }
+
+    #[test]
+    fn what_only_unused_bindings_used_goes_with_them() {
+        let mut ir = IR::from(vec![BasicBlock {
+            statements: vec![
+                assign(0, Value::Constant(1)),
+                assign(1, Value::Binding(Binding(0))),
+                assign(2, Value::Binding(Binding(1))),
+                assign(3, Value::Constant(2)),
+            ],
+            end: BlockEnd::Return(Binding(3)),
+        }]);
+        remove_unused_bindings(&mut ir);
+        assert_eq!(ir.code[0].statements, [assign(3, Value::Constant(2))]);
+    }
+
+    #[test]
+    fn copies_are_followed_through_phi_nodes_and_blocks() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = %0
+              %2 = cmp.64 gt, %1, 10
+              br-cond %2, BB1, BB2
+            BB1:
+              %3 = %1
+              br  BB3
+            BB2:
+              br  BB3
+            BB3:
+              ; the same value from both sides, once the copies are followed
+              %4 = phi [ %3, BB1 ], [ %0, BB2 ]
+              %5 = cmp.64 eq, %4, 0
+              br-cond %5, BB4, BB5
+            BB4:
+              br  BB5
+            BB5:
+              ; the phi node before, or this one again
+              %6 = phi [ %4, BB3 ], [ %6, BB4 ]
+              %7 = add.64 %6, 1
+              ret %7
+        "
+        .parse()?;
+        assert!(propagate_copies(&mut ir.code));
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              %2 = cmp.64 gt, %0, 10
+              br-cond %2, BB1, BB2
+            BB1:
+              br  BB3
+            BB2:
+              br  BB3
+            BB3:
+              %5 = cmp.64 eq, %0, 0
+              br-cond %5, BB4, BB5
+            BB4:
+              br  BB5
+            BB5:
+              %7 = add.64 %0, 1
+              ret %7
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        assert!(!propagate_copies(&mut ir.code));
+        Ok(())
+    }
+
+    #[test]
+    fn branches_that_can_only_go_one_way_become_unconditional() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = 0
+              br-cond %0, BB1, BB1
+            BB1:
+              br-cond %1, BB2, BB3
+            BB2:
+              br  BB3
+            BB3:
+              %2 = phi [ %0, BB1 ], [ %1, BB2 ]
+              ret %2
+        "
+        .parse()?;
+        assert!(simplify_branches(&mut ir));
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = 0
+              br  BB1
+            BB1:
+              br  BB3
+            BB2:
+              br  BB3
+            BB3:
+              %2 = phi [ %0, BB1 ], [ %1, BB2 ]
+              ret %2
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        assert_eq!(ir.backwards_map.get(&BlockBinding(2)), None);
+        assert!(!simplify_branches(&mut ir));
+        Ok(())
+    }
+
+    #[test]
+    fn phi_nodes_merging_different_values_stay() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              %1 = arg 1
+              br-cond %0, BB1, BB2
+            BB1:
+              br  BB2
+            BB2:
+              %2 = phi [ %0, BB0 ], [ %1, BB1 ]
+              ret %2
+        "
+        .parse()?;
+        let before = ir.code.clone();
+        assert!(!propagate_copies(&mut ir.code));
+        assert_eq!(ir.code, before);
+        Ok(())
+    }
+
+    #[test]
+    fn volatile_accesses_outlive_cleanup() {
+        use crate::error::SourceMetadata;
+        use crate::intermediate::generate::tests::compiled;
+        let source = "int main() {\n\
+                      \x20   volatile int ready = 0;\n\
+                      \x20   int volatile *status = &ready;\n\
+                      \x20   ready;\n\
+                      \x20   *status;\n\
+                      \x20   return 0;\n\
+                      }\n";
+        let meta = SourceMetadata::new(source);
+        let mut ir = compiled(&meta).functions.remove(0).ir;
+        run_safe_cleanup(&mut ir);
+        let loads: Vec<_> = ir
+            .code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter_map(|statement| match statement {
+                Statement::Assign {
+                    value: Value::Load { volatile, .. },
+                    ..
+                } => Some(*volatile),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(loads, [true, false, true]);
     }
 }
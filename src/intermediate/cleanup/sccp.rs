@@ -0,0 +1,317 @@
+//! Sparse conditional constant propagation, as described by Wegman and Zadeck. Constants are
+//! propagated through the bindings and the branches at once: a block is only looked at when a
+//! branch that can be taken leads to it, and a phi node only merges the values that come from the
+//! blocks that can branch to it. That finds constants folding block by block can't, like a phi node
+//! whose other values come from branches that are never taken. The conditional branches on a known
+//! flag become unconditional, which leaves the blocks only they led to for
+//! [`super::prune_unreached_blocks`].
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::intermediate::analysis::BindingUsage;
+use crate::intermediate::{fold, generate};
+use crate::intermediate::{Binding, BlockBinding, BlockEnd, Branch, Statement, Value, IR};
+
+/// What's known about the value of a binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    /// nothing that defines it was found to run yet
+    Unknown,
+    Constant(i64),
+    /// it may hold different values
+    Varying,
+}
+
+impl Lattice {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Unknown, known) | (known, Self::Unknown) => known,
+            (Self::Constant(a), Self::Constant(b)) if a == b => self,
+            _ => Self::Varying,
+        }
+    }
+}
+
+/// Replace the bindings found to always hold a constant by it, and take the branches on the
+/// flags that do. Tells whether it changed anything.
+pub fn propagate_constants(ir: &mut IR) -> bool {
+    let mut propagation = Propagation::new(ir);
+    propagation.run();
+    let Propagation {
+        values, reached, ..
+    } = propagation;
+    let constant = |binding: &Binding| match values.get(binding) {
+        Some(Lattice::Constant(c)) => Some(*c),
+        _ => None,
+    };
+
+    let mut changed = false;
+    let mut reached: Vec<_> = reached.into_iter().collect();
+    reached.sort_unstable();
+    for block in reached {
+        for statement in &mut ir[block].statements {
+            if let Statement::Assign { index, value } = statement {
+                if matches!(value, Value::Constant(_) | Value::Undefined) {
+                    continue;
+                }
+                if let Some(c) = constant(index) {
+                    *value = Value::Constant(c);
+                    changed = true;
+                }
+            }
+        }
+        if let BlockEnd::Branch(Branch::Conditional { flag, .. }) = ir[block].end {
+            if let Some(c) = constant(&flag) {
+                super::decide_branch(ir, block, c);
+                changed = true;
+            }
+        }
+    }
+    if changed {
+        (ir.forward_map, ir.backwards_map) = generate::generate_branching_graphs(&ir.code);
+    }
+    changed
+}
+
+/// Where a binding is used: a statement of a block, or its end
+type Use = (BlockBinding, Option<usize>);
+
+struct Propagation<'ir> {
+    ir: &'ir IR,
+    values: HashMap<Binding, Lattice>,
+    uses: HashMap<Binding, Vec<Use>>,
+    /// the blocks a branch that can be taken leads to
+    reached: HashSet<BlockBinding>,
+    /// the branches that can be taken, from one block to another
+    edges: HashSet<(BlockBinding, BlockBinding)>,
+    /// the blocks just reached, whose statements haven't been looked at yet
+    pending_blocks: Vec<BlockBinding>,
+    /// the bindings whose value changed since their uses were looked at
+    pending_bindings: Vec<Binding>,
+}
+
+impl<'ir> Propagation<'ir> {
+    fn new(ir: &'ir IR) -> Self {
+        let mut uses: HashMap<Binding, Vec<Use>> = HashMap::new();
+        for (block_index, block) in ir.code.iter().enumerate() {
+            let block_binding = BlockBinding(block_index);
+            for (index, statement) in block.statements.iter().enumerate() {
+                for dep in statement.binding_deps() {
+                    uses.entry(dep)
+                        .or_default()
+                        .push((block_binding, Some(index)));
+                }
+            }
+            if let BlockEnd::Branch(Branch::Conditional { flag, .. }) = block.end {
+                uses.entry(flag).or_default().push((block_binding, None));
+            }
+        }
+        Self {
+            ir,
+            values: HashMap::new(),
+            uses,
+            reached: HashSet::new(),
+            edges: HashSet::new(),
+            pending_blocks: Vec::new(),
+            pending_bindings: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) {
+        if self.ir.code.is_empty() {
+            return;
+        }
+        self.reached.insert(BlockBinding(0));
+        self.pending_blocks.push(BlockBinding(0));
+        loop {
+            if let Some(block) = self.pending_blocks.pop() {
+                for index in 0..self.ir[block].statements.len() {
+                    self.visit(block, Some(index));
+                }
+                self.visit(block, None);
+            } else if let Some(binding) = self.pending_bindings.pop() {
+                let uses = self.uses.get(&binding).cloned().unwrap_or_default();
+                for (block, statement) in uses {
+                    if self.reached.contains(&block) {
+                        self.visit(block, statement);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn value(&self, binding: Binding) -> Lattice {
+        self.values
+            .get(&binding)
+            .copied()
+            .unwrap_or(Lattice::Unknown)
+    }
+
+    fn visit(&mut self, block: BlockBinding, statement: Option<usize>) {
+        let Some(index) = statement else {
+            match self.ir[block].end {
+                BlockEnd::Return(_) => (),
+                BlockEnd::Branch(Branch::Unconditional { target }) => self.take(block, target),
+                BlockEnd::Branch(Branch::Conditional {
+                    flag,
+                    target_true,
+                    target_false,
+                }) => match self.value(flag) {
+                    Lattice::Unknown => (),
+                    Lattice::Constant(0) => self.take(block, target_false),
+                    Lattice::Constant(_) => self.take(block, target_true),
+                    Lattice::Varying => {
+                        self.take(block, target_true);
+                        self.take(block, target_false);
+                    }
+                },
+            }
+            return;
+        };
+        if let Statement::Assign { index, value } = &self.ir[block].statements[index] {
+            let found = self.evaluate(block, value);
+            let current = self.value(*index);
+            let lowered = current.meet(found);
+            if lowered != current {
+                self.values.insert(*index, lowered);
+                self.pending_bindings.push(*index);
+            }
+        }
+    }
+
+    /// The branch from `from` to `to` can be taken
+    fn take(&mut self, from: BlockBinding, to: BlockBinding) {
+        if !self.edges.insert((from, to)) {
+            return;
+        }
+        if self.reached.insert(to) {
+            self.pending_blocks.push(to);
+        } else {
+            // the block was looked at already, only its phi nodes see the new branch
+            for (index, statement) in self.ir[to].statements.iter().enumerate() {
+                if let Statement::Assign {
+                    value: Value::Phi { .. },
+                    ..
+                } = statement
+                {
+                    self.visit(to, Some(index));
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, block: BlockBinding, value: &Value) -> Lattice {
+        match value {
+            Value::Phi { nodes } => nodes
+                .iter()
+                .filter(|node| self.edges.contains(&(node.block_from, block)))
+                .map(|node| self.value(node.value))
+                .fold(Lattice::Unknown, Lattice::meet),
+            Value::Constant(c) => Lattice::Constant(*c),
+            // undefined values are frozen to zero, like when folding
+            Value::Undefined => Lattice::Constant(0),
+            value if value.has_side_effects() => Lattice::Varying,
+            value => {
+                let operands: Vec<_> = value
+                    .binding_deps()
+                    .into_iter()
+                    .map(|dep| self.value(dep))
+                    .collect();
+                if operands.contains(&Lattice::Varying) {
+                    Lattice::Varying
+                } else if operands.contains(&Lattice::Unknown) {
+                    Lattice::Unknown
+                } else {
+                    let constant = |binding| match self.value(binding) {
+                        Lattice::Constant(c) => Some(c),
+                        _ => None,
+                    };
+                    fold::evaluate(value, constant).map_or(Lattice::Varying, Lattice::Constant)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{interpret, mem2reg, verify};
+
+    fn promoted(source: &str) -> IR {
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (mut module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())
+                .expect("the source is fine");
+        let mut ir = module.functions.remove(0).ir;
+        mem2reg::promote_variables(&mut ir);
+        super::super::run_safe_cleanup(&mut ir);
+        ir
+    }
+
+    fn returns(ir: &IR) -> Vec<i64> {
+        interpret::sample_inputs()
+            .map(|inputs| interpret::run(ir, &[], &inputs).map(|outcome| outcome.returned))
+            .map(|returned| returned.expect("the function returns"))
+            .collect()
+    }
+
+    fn conditional_branches(ir: &IR) -> usize {
+        ir.code
+            .iter()
+            .filter(|block| matches!(block.end, BlockEnd::Branch(Branch::Conditional { .. })))
+            .count()
+    }
+
+    #[test]
+    fn constants_flow_through_phi_nodes_and_the_branches_taken() {
+        let mut ir = promoted(
+            "int f(int a) {\n\
+             \x20   int x = 3;\n\
+             \x20   int z;\n\
+             \x20   if (a) x = 3;\n\
+             \x20   if (x == 3) z = 4; else z = a;\n\
+             \x20   return z + x;\n\
+             }\n",
+        );
+        let before = returns(&ir);
+        assert!(propagate_constants(&mut ir));
+        super::super::prune_unreached_blocks(&mut ir);
+        super::super::run_safe_cleanup(&mut ir);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert_eq!(returns(&ir), before);
+        // only the branch on `a` is left, and what's returned is known
+        assert_eq!(conditional_branches(&ir), 1);
+        let returned = ir.code.iter().find_map(|block| match block.end {
+            BlockEnd::Return(binding) => Some(binding),
+            _ => None,
+        });
+        let returned = returned.and_then(|binding| {
+            crate::intermediate::analysis::find_assignment_value(&ir.code, binding).cloned()
+        });
+        assert_eq!(returned, Some(Value::Constant(7)));
+        // nothing else is left to find
+        assert!(!propagate_constants(&mut ir));
+    }
+
+    #[test]
+    fn values_that_depend_on_the_path_taken_vary() {
+        let mut ir = promoted(
+            "int f(int a) {\n\
+             \x20   int x;\n\
+             \x20   if (a) x = 1; else x = 2;\n\
+             \x20   if (x) return x;\n\
+             \x20   return 0;\n\
+             }\n",
+        );
+        let branches = conditional_branches(&ir);
+        assert!(!propagate_constants(&mut ir));
+        assert_eq!(conditional_branches(&ir), branches);
+    }
+}
@@ -0,0 +1,246 @@
+//! Jump threading: the branches to a block that would only pass them on go straight to where it
+//! goes. That's a block with nothing in it but a jump, or one that only branches on a phi node
+//! that takes a constant from the block branching to it. The blocks nothing branches to anymore
+//! are left for [`super::prune_unreached_blocks`].
+use alloc::vec::Vec;
+
+use crate::intermediate::analysis::{self, BindingUsage};
+use crate::intermediate::refactor;
+use crate::intermediate::{
+    BasicBlock, Binding, BlockBinding, BlockEnd, Branch, PhiDescriptor, Statement, Value, IR,
+};
+
+/// Make the branches skip the blocks that only pass them on, giving how many do
+pub fn thread_jumps(ir: &mut IR) -> usize {
+    let mut threaded = 0;
+    // the entry isn't branched to
+    for block in (1..ir.code.len()).map(BlockBinding) {
+        threaded += skip_jump(ir, block) + skip_decided_branch(ir, block);
+    }
+    if threaded > 0 {
+        (ir.forward_map, ir.backwards_map) =
+            crate::intermediate::generate::generate_branching_graphs(&ir.code);
+    }
+    threaded
+}
+
+/// The blocks whose end branches to `block`. The branching graphs aren't kept up to date while
+/// the branches change, so they're found in the code.
+fn predecessors(ir: &IR, block: BlockBinding) -> Vec<BlockBinding> {
+    analysis::iterate_with_bindings(&ir.code)
+        .filter(|(_, predecessor)| successors(predecessor).contains(&block))
+        .map(|(predecessor, _)| predecessor)
+        .collect()
+}
+
+fn successors(block: &BasicBlock) -> Vec<BlockBinding> {
+    match block.end {
+        BlockEnd::Return(_) => Vec::new(),
+        BlockEnd::Branch(Branch::Unconditional { target }) => alloc::vec![target],
+        BlockEnd::Branch(Branch::Conditional {
+            target_true,
+            target_false,
+            ..
+        }) => alloc::vec![target_true, target_false],
+    }
+}
+
+fn phi_nodes(block: &mut BasicBlock) -> impl Iterator<Item = &mut Vec<PhiDescriptor>> {
+    block
+        .statements
+        .iter_mut()
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                value: Value::Phi { nodes },
+                ..
+            } => Some(nodes),
+            _ => None,
+        })
+}
+
+/// An empty block that jumps somewhere else
+fn jumps_to(block: &BasicBlock) -> Option<BlockBinding> {
+    match block.end {
+        BlockEnd::Branch(Branch::Unconditional { target }) if block.statements.is_empty() => {
+            Some(target)
+        }
+        _ => None,
+    }
+}
+
+/// Make the branch of `predecessor` to `from` go to `to`, where the phi nodes take from it what
+/// they took from `from`. It's not done if they'd take two values from it.
+fn retarget(ir: &mut IR, predecessor: BlockBinding, from: BlockBinding, to: BlockBinding) -> bool {
+    let has_phis = phi_nodes(&mut ir[to]).next().is_some();
+    if has_phis && successors(&ir[predecessor]).contains(&to) {
+        return false;
+    }
+    // UNSAFE: safe. `to` is a block of the function.
+    unsafe { refactor::end_rename_block(&mut ir[predecessor].end, from, to) };
+    for nodes in phi_nodes(&mut ir[to]) {
+        if let Some(&PhiDescriptor { value, .. }) =
+            nodes.iter().find(|node| node.block_from == from)
+        {
+            nodes.push(PhiDescriptor {
+                value,
+                block_from: predecessor,
+            });
+        }
+    }
+    true
+}
+
+/// Branch to where the block jumps instead, if it's empty
+fn skip_jump(ir: &mut IR, block: BlockBinding) -> usize {
+    // jumps to jumps are skipped from the last one, so that a loop of them is left alone
+    let Some(target) =
+        jumps_to(&ir[block]).filter(|target| *target != block && jumps_to(&ir[*target]).is_none())
+    else {
+        return 0;
+    };
+    predecessors(ir, block)
+        .into_iter()
+        .filter(|&predecessor| retarget(ir, predecessor, block, target))
+        .count()
+}
+
+/// Whether the binding is used anywhere but the end of `block`
+fn used_elsewhere(ir: &IR, binding: Binding, block: BlockBinding) -> bool {
+    analysis::iterate_with_bindings(&ir.code).any(|(other, code)| {
+        code.statements
+            .iter()
+            .any(|statement| statement.contains_binding(binding))
+            || (other != block && code.contains_binding(binding))
+    })
+}
+
+/// Branch to where the block would branch on the constants its flag takes from some of the
+/// blocks branching to it, if there's nothing else in it
+fn skip_decided_branch(ir: &mut IR, block: BlockBinding) -> usize {
+    let BlockEnd::Branch(Branch::Conditional {
+        flag,
+        target_true,
+        target_false,
+    }) = ir[block].end
+    else {
+        return 0;
+    };
+    let [Statement::Assign {
+        index,
+        value: Value::Phi { nodes },
+    }] = ir[block].statements.as_slice()
+    else {
+        return 0;
+    };
+    if *index != flag || used_elsewhere(ir, flag, block) {
+        return 0;
+    }
+    let decided: Vec<_> = nodes
+        .iter()
+        .filter_map(
+            |node| match analysis::find_assignment_value(&ir.code, node.value) {
+                Some(Value::Constant(c)) => Some((
+                    node.block_from,
+                    if *c != 0 { target_true } else { target_false },
+                )),
+                _ => None,
+            },
+        )
+        .filter(|&(predecessor, target)| predecessor != block && target != block)
+        .collect();
+    let mut threaded = 0;
+    for (predecessor, target) in decided {
+        if retarget(ir, predecessor, block, target) {
+            // the flag doesn't come from there anymore
+            for nodes in phi_nodes(&mut ir[block]) {
+                nodes.retain(|node| node.block_from != predecessor);
+            }
+            threaded += 1;
+        }
+    }
+    threaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{cleanup, verify};
+
+    #[test]
+    fn branches_skip_the_blocks_that_pass_them_on() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              br  BB3
+            BB2:
+              %1 = 5
+              br  BB3
+            BB3:
+              %2 = phi [ %0, BB1 ], [ %1, BB2 ]
+              ret %2
+        "
+        .parse()?;
+        assert_eq!(thread_jumps(&mut ir), 1);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        cleanup::prune_unreached_blocks(&mut ir);
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB2, BB1
+            BB1:
+              %1 = 5
+              br  BB2
+            BB2:
+              %2 = phi [ %0, BB0 ], [ %1, BB1 ]
+              ret %2
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        Ok(())
+    }
+
+    #[test]
+    fn branches_on_a_flag_known_from_where_they_come_are_taken_early() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            BB0:
+              %0 = arg 0
+              br-cond %0, BB1, BB2
+            BB1:
+              %1 = 1
+              br  BB3
+            BB2:
+              %2 = arg 1
+              br  BB3
+            BB3:
+              %3 = phi [ %1, BB1 ], [ %2, BB2 ]
+              br-cond %3, BB4, BB5
+            BB4:
+              ret %0
+            BB5:
+              %4 = 0
+              ret %4
+        "
+        .parse()?;
+        assert_eq!(thread_jumps(&mut ir), 1);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert_eq!(
+            ir[BlockBinding(1)].end,
+            BlockEnd::Branch(Branch::Unconditional {
+                target: BlockBinding(4)
+            })
+        );
+        let [Statement::Assign {
+            value: Value::Phi { nodes },
+            ..
+        }] = ir[BlockBinding(3)].statements.as_slice()
+        else {
+            panic!("the flag is still a phi node: {}", ir);
+        };
+        assert_eq!(nodes.len(), 1);
+        // nothing else is known
+        assert_eq!(thread_jumps(&mut ir), 0);
+        Ok(())
+    }
+}
@@ -20,6 +20,12 @@ impl From<Binding> for CouldBeConstant {
 
 impl From<i32> for CouldBeConstant {
     fn from(value: i32) -> Self {
+        Self::Constant(value.into())
+    }
+}
+
+impl From<i64> for CouldBeConstant {
+    fn from(value: i64) -> Self {
         Self::Constant(value)
     }
 }
@@ -32,6 +38,12 @@ impl From<Binding> for Value {
 
 impl From<i32> for Value {
     fn from(c: i32) -> Self {
+        Self::Constant(c.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(c: i64) -> Self {
         Self::Constant(c)
     }
 }
@@ -1,8 +1,12 @@
 // Constant fold IR
+use alloc::vec::Vec;
+
 use super::*;
-use std::collections::HashSet;
+use hashbrown::HashSet;
+
+pub(super) fn fold_ir_blocks(ir: &mut IR) {
+    freeze_undefined(&mut ir.code);
 
-fn fold_ir_blocks(ir: &mut IR) {
     // fold each block independently
     (0..ir.code.len())
         .map(BlockBinding)
@@ -13,115 +17,102 @@ fn fold_ir_blocks(ir: &mut IR) {
 
 }
 
-pub fn constant_fold(mut ir: IR) -> IR {
-    cleanup::run_safe_cleanup(&mut ir);
-    while try_merge(&mut ir) {
-        cleanup::prune_unreached_blocks(&mut ir);
+/// Undefined values are frozen, so one choice has to hold for all the uses of each of them. Zero
+/// is picked for all of them: it's what codegen would materialize anyway, and it makes the
+/// operations on them fold like on any other constant.
+fn freeze_undefined(code: &mut IRCode) {
+    for statement in code.iter_mut().flat_map(|block| &mut block.statements) {
+        if let Statement::Assign {
+            value: value @ Value::Undefined,
+            ..
+        } = statement
+        {
+            *value = Value::Constant(0);
+        }
     }
-    cleanup::run_safe_cleanup(&mut ir);
-    ir
 }
 
-fn repr_into_i32(repr: u32) -> i32 {
-    // safely converts to u32 representation so no bits are missed and then interprets the
-    // resulting 32-bits as two's complement.
-    unsafe { std::mem::transmute(repr) }
+pub fn constant_fold(ir: IR) -> IR {
+    constant_fold_observed(ir, &mut |_, _| ())
 }
 
-// find places where a block jumps to another (child) block and this child only has that parent
-fn find_unique_jumps(ir: &IR) -> impl Iterator<Item = (BlockBinding, BlockBinding)> + '_ {
-    ir.forward_map.iter().filter_map(|(parent, children)| {
-        if children.len() == 1 {
-            let unique_child = children[0];
-            let unique_child_parents = &ir.backwards_map[&unique_child];
-            if unique_child_parents.len() == 1 {
-                debug_assert_eq!(unique_child_parents[0], *parent, "Mismatch in backwards map: one block has a child who doesn't recognize it as a parent");
-                Some((*parent, unique_child))
-            } else { None}
-        } else { None }
-    })
+/// Fold the IR like `constant_fold` does, showing `after_pass` the name of each pass run and the
+/// IR it leaves
+pub fn constant_fold_observed(ir: IR, after_pass: &mut dyn FnMut(&'static str, &IR)) -> IR {
+    passes::PassManager::for_level(passes::OptLevel::O1).run(ir, after_pass)
 }
 
-fn try_merge(ir: &mut IR) -> bool {
-    // make an initial folding step for all the blocks
-    fold_ir_blocks(ir);
-
-    // cleanup the code after the fold
-    cleanup::run_safe_cleanup(ir);
-
-    cleanup::prune_unreached_blocks(ir);
-
-    // if I find a direct mapping somewhere, I inline
-    let mut jumps: HashMap<_, _> = find_unique_jumps(ir).collect();
-
-    let did_merge = !jumps.is_empty();
-
-    fn find_noncolliding_merge(
-        jumps: &mut HashMap<BlockBinding, BlockBinding>,
-    ) -> Option<(BlockBinding, BlockBinding)> {
-        for (parent, child) in jumps.iter().map(|(a, b)| (*a, *b)) {
-            if !jumps.contains_key(&child) {
-                jumps.remove(&parent);
-                return Some((parent, child));
-            }
-        }
-        None
+/// The value an operation of `bit_size` bits leaves: 32-bit values are kept sign extended, as
+/// `Value::Constant` wants them
+fn wrap(value: i64, bit_size: BitSize) -> i64 {
+    match bit_size {
+        // only the lower 32 bits are kept, and then interpreted as two's complement
+        BitSize::Bit32 => value as i32 as i64,
+        BitSize::Bit64 => value,
     }
+}
 
-    while let Some((parent, child)) = find_noncolliding_merge(&mut jumps) {
-        // rename the child block to the block it's inlined before removing it
-        // before renaming, we set the predecessor
-        block_set_predecessor(&mut ir[child], parent);
-        unsafe { refactor::rename_block(ir, child, parent) };
-        let child_block = unsafe { refactor::remove_block(ir, child) };
-        merge_blocks(&mut ir[parent], child_block, parent);
+/// The value of `bit_size` bits, read as unsigned
+fn unsigned(value: i64, bit_size: BitSize) -> u64 {
+    match bit_size {
+        BitSize::Bit32 => value as u32 as u64,
+        BitSize::Bit64 => value as u64,
     }
-
-    did_merge
 }
 
-fn block_set_predecessor(block: &mut BasicBlock, predecessor: BlockBinding) {
-    block.statements = std::mem::take(&mut block.statements)
-        .into_iter()
-        .map(|statement| {
-            if let Statement::Assign {
-                index,
-                value: Value::Phi { nodes },
-            } = statement
-            {
-                let bind = nodes
-                    .into_iter()
-                    .find_map(|descriptor| {
-                        if descriptor.block_from == predecessor {
-                            Some(descriptor.value)
-                        } else {
-                            None
-                        }
-                    })
-                    .expect("Inlining with no phi node data");
-                Statement::Assign {
-                    index,
-                    value: Value::Binding(bind),
-                }
-            } else {
-                statement
-            }
-        })
-        .collect();
+/// Shifts only look at the lower bits of the amount, as many as needed to count the bits, like
+/// AArch64 does with an amount in a register. Negative amounts or ones past the width are undefined
+/// in C, and IR generation warns about the constant ones.
+fn shift_amount(amount: i64, bit_size: BitSize) -> u32 {
+    amount as u32 & (bit_size.bits() - 1)
 }
 
-fn merge_blocks(
-    predecessor: &mut BasicBlock,
-    mut next: BasicBlock, // NOTE: the `next` block has to have a proof that it can be removed, that's why I take it by value.
-    predecessor_binding: BlockBinding,
-) {
-    predecessor.end = next.end;
-    predecessor.statements.extend(next.statements);
-    cleanup::remove_aliases_in_same_block(predecessor);
-    // TODO: set predecessor to the other basic block in the predecessor if there's a loop between
+/// The constant an integer operation computes when the bindings it takes hold the constants
+/// `known` gives them, if they're all known. Divisions by zero aren't computed, like when folding.
+pub(super) fn evaluate(value: &Value, known: impl Fn(Binding) -> Option<i64>) -> Option<i64> {
+    if !matches!(
+        value,
+        Value::Constant(_)
+            | Value::Binding(_)
+            | Value::Cmp { .. }
+            | Value::Select { .. }
+            | Value::Negate { .. }
+            | Value::FlipBits { .. }
+            | Value::SignExtend { .. }
+            | Value::Add { .. }
+            | Value::Subtract { .. }
+            | Value::Multiply { .. }
+            | Value::Divide { .. }
+            | Value::Lsl { .. }
+            | Value::Lsr { .. }
+            | Value::Asr { .. }
+            | Value::And { .. }
+            | Value::Or { .. }
+            | Value::Xor { .. }
+    ) {
+        return None;
+    }
+    use super::analysis::BindingUsage;
+    // the operands are propagated one at a time, until none of them changes the value anymore
+    let mut value = value.clone();
+    loop {
+        if let Value::Constant(c) = value {
+            return Some(c);
+        }
+        let mut modified = false;
+        for binding in value.binding_deps() {
+            let constant = known(binding)?;
+            let result = value_propagate_constant(binding, constant, value);
+            value = result.value;
+            modified |= result.modified;
+        }
+        if !modified {
+            return None;
+        }
+    }
 }
 
-fn find_potential_folds(code: &[Statement]) -> impl Iterator<Item = (usize, Binding, i32)> {
+fn find_potential_folds(code: &[Statement]) -> impl Iterator<Item = (usize, Binding, i64)> {
     let mut found_constants = HashMap::new();
 
     let mut folds = Vec::new();
@@ -150,7 +141,7 @@ fn find_potential_folds(code: &[Statement]) -> impl Iterator<Item = (usize, Bind
 fn fold_block(ir: &mut IR, block: BlockBinding) {
     // fold as much of the statements as possible
     // let mut start_index = 0;
-    let mut failed_folds: HashMap<usize, HashSet<(Binding, i32)>> = HashMap::new();
+    let mut failed_folds: HashMap<usize, HashSet<(Binding, i64)>> = HashMap::new();
     loop {
         // collect into a vec to avoid reference issues
         let mut potential_folds: Vec<_> = find_potential_folds(&ir[block].statements)
@@ -170,11 +161,11 @@ fn fold_block(ir: &mut IR, block: BlockBinding) {
 
         for (index, binding, value) in potential_folds {
             // make a replacement with a dummy
-            let old_statement = std::mem::replace(
+            let old_statement = core::mem::replace(
                 &mut ir[block].statements[index],
                 Statement::Assign {
-                    index: Binding(std::usize::MAX),
-                    value: Value::Allocate { size: 0 },
+                    index: Binding(usize::MAX),
+                    value: Value::Allocate { size: 0, align: 1 },
                 },
             );
 
@@ -196,7 +187,7 @@ fn fold_block(ir: &mut IR, block: BlockBinding) {
             }
 
             // put the new value
-            std::mem::replace(&mut ir[block].statements[index], new_statement);
+            core::mem::replace(&mut ir[block].statements[index], new_statement);
         }
 
         // now we're going to forget all of the inserted 'forget's
@@ -207,16 +198,9 @@ fn fold_block(ir: &mut IR, block: BlockBinding) {
 
     // if we got a conditional branch and the flag is known,
     // we can switch it to an unconditional branch
-    if let BlockEnd::Branch(Branch::Conditional {
-        flag,
-        target_true,
-        target_false,
-    }) = ir[block].end
-    {
+    if let BlockEnd::Branch(Branch::Conditional { flag, .. }) = ir[block].end {
         if let Value::Constant(c) = analysis::find_assignment_value(&ir.code, flag).unwrap() {
-            ir[block].end = BlockEnd::Branch(Branch::Unconditional {
-                target: if *c == 0 { target_false } else { target_true },
-            })
+            cleanup::decide_branch(ir, block, *c);
         }
     }
 }
@@ -251,7 +235,7 @@ impl<T> PropagationResult<T> {
 
 fn statement_propagate_constant(
     known_binding: Binding,
-    known_value: i32,
+    known_value: i64,
     statement: Statement,
 ) -> PropagationResult<Statement> {
     match statement {
@@ -260,13 +244,15 @@ fn statement_propagate_constant(
                 .map(|value| Statement::Assign { index, value })
         }
         // stores can't be folded further.
-        Statement::Store { .. } => PropagationResult::unchanged(statement),
+        Statement::Store { .. } | Statement::AtomicStore { .. } | Statement::SetRegister { .. } => {
+            PropagationResult::unchanged(statement)
+        }
     }
 }
 
 fn could_be_constant_propagate(
     known_binding: Binding,
-    known_value: i32,
+    known_value: i64,
     c: CouldBeConstant,
 ) -> PropagationResult<CouldBeConstant> {
     match c {
@@ -293,7 +279,7 @@ impl<T> FromIterator<PropagationResult<T>> for PropagationResult<Vec<T>> {
 #[allow(unused)]
 fn value_propagate_constant(
     known_binding: Binding,
-    binding_value: i32,
+    binding_value: i64,
     value: Value,
 ) -> PropagationResult<Value> {
     match value {
@@ -306,52 +292,24 @@ fn value_propagate_constant(
             condition,
             lhs,
             rhs,
+            bit_size,
         } => {
-            fn eval_condition(condition: Condition, lhs: i32, rhs: i32) -> i32 {
+            fn eval_condition(condition: Condition, lhs: i64, rhs: i64, bit_size: BitSize) -> i64 {
+                let (lhs, rhs) = (wrap(lhs, bit_size), wrap(rhs, bit_size));
+                let (unsigned_lhs, unsigned_rhs) = (unsigned(lhs, bit_size), unsigned(rhs, bit_size));
                 match condition {
-                    Condition::Equals => {
-                        if lhs == rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
-                    Condition::LessEqual => {
-                        if lhs <= rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
-                    Condition::GreaterEqual => {
-                        if lhs >= rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
-                    Condition::LessThan => {
-                        if lhs < rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
-                    Condition::GreaterThan => {
-                        if lhs > rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
-                    Condition::NotEquals => {
-                        if lhs != rhs {
-                            1
-                        } else {
-                            0
-                        }
-                    }
+                    Condition::Equals => lhs == rhs,
+                    Condition::LessEqual => lhs <= rhs,
+                    Condition::GreaterEqual => lhs >= rhs,
+                    Condition::LessThan => lhs < rhs,
+                    Condition::GreaterThan => lhs > rhs,
+                    Condition::NotEquals => lhs != rhs,
+                    Condition::Higher => unsigned_lhs > unsigned_rhs,
+                    Condition::HigherSame => unsigned_lhs >= unsigned_rhs,
+                    Condition::Lower => unsigned_lhs < unsigned_rhs,
+                    Condition::LowerSame => unsigned_lhs <= unsigned_rhs,
                 }
+                .into()
             }
             match rhs {
                 CouldBeConstant::Constant(ctant) if lhs == known_binding => {
@@ -359,6 +317,7 @@ fn value_propagate_constant(
                         condition,
                         binding_value,
                         ctant,
+                        bit_size,
                     )))
                 }
                 CouldBeConstant::Binding(other) => {
@@ -367,20 +326,23 @@ fn value_propagate_constant(
                             condition,
                             binding_value,
                             binding_value,
+                            bit_size,
                         )))
                     } else if other == known_binding {
                         // we know half the thing.
                         PropagationResult::modified(Value::Cmp {
                             condition,
                             lhs,
-                            rhs: CouldBeConstant::Constant(binding_value),
+                            rhs: wrap(binding_value, bit_size).into(),
+                            bit_size,
                         })
                     } else if lhs == known_binding && condition.is_commutative() {
                         // if the condition is commutative, we can flip the thing.
                         PropagationResult::modified(Value::Cmp {
                             condition,
                             lhs: other,
-                            rhs: CouldBeConstant::Constant(binding_value),
+                            rhs: wrap(binding_value, bit_size).into(),
+                            bit_size,
                         })
                     } else {
                         PropagationResult::unchanged(value)
@@ -389,43 +351,102 @@ fn value_propagate_constant(
                 CouldBeConstant::Constant(_) => PropagationResult::unchanged(value),
             }
         }
+        Value::Select {
+            flag,
+            if_true,
+            if_false,
+        } => {
+            let known = |operand| match operand {
+                CouldBeConstant::Binding(binding) if binding == known_binding => {
+                    CouldBeConstant::Constant(binding_value)
+                }
+                operand => operand,
+            };
+            if flag == known_binding {
+                // the value is the one it picks
+                let picked = if binding_value != 0 { if_true } else { if_false };
+                match picked {
+                    CouldBeConstant::Binding(binding) => {
+                        PropagationResult::modified(Value::Binding(binding))
+                    }
+                    CouldBeConstant::Constant(ctant) => {
+                        PropagationResult::modified(Value::Constant(ctant))
+                    }
+                }
+            } else if known(if_true) != if_true || known(if_false) != if_false {
+                PropagationResult::modified(match (known(if_true), known(if_false)) {
+                    (CouldBeConstant::Constant(a), CouldBeConstant::Constant(b)) if a == b => {
+                        Value::Constant(a)
+                    }
+                    (if_true, if_false) => Value::Select {
+                        flag,
+                        if_true,
+                        if_false,
+                    },
+                })
+            } else {
+                PropagationResult::unchanged(value)
+            }
+        }
         Value::Load {
             mem_binding,
             byte_size,
+            ..
         } => todo!(),
-        Value::Negate { binding } => {
+        Value::Negate { binding, bit_size } => {
             if binding == known_binding {
-                PropagationResult::modified(Value::Constant((!binding_value).wrapping_add(1)))
+                PropagationResult::modified(Value::Constant(wrap(
+                    binding_value.wrapping_neg(),
+                    bit_size,
+                )))
             } else {
                 PropagationResult::unchanged(value)
             }
         }
-        Value::FlipBits { binding } => {
+        Value::FlipBits { binding, bit_size } => {
             if binding == known_binding {
-                PropagationResult::modified(Value::Constant(!binding_value))
+                PropagationResult::modified(Value::Constant(wrap(!binding_value, bit_size)))
             } else {
                 PropagationResult::unchanged(value)
             }
         }
-        Value::Add { lhs, rhs } => match (lhs, rhs) {
-            (a, CouldBeConstant::Constant(c)) if a == known_binding => {
-                PropagationResult::modified(Value::Constant(binding_value.wrapping_add(c)))
+        Value::SignExtend { binding, from } => {
+            if binding == known_binding {
+                PropagationResult::modified(Value::Constant(match from {
+                    ByteSize::U8 | ByteSize::I8 => (binding_value as i8).into(),
+                    ByteSize::I16 | ByteSize::U16 => (binding_value as i16).into(),
+                    ByteSize::U32 => wrap(binding_value, BitSize::Bit32),
+                    ByteSize::U64 => binding_value,
+                    ByteSize::F32 | ByteSize::F64 => {
+                        unreachable!("floating-point values are never sign extended")
+                    }
+                }))
+            } else {
+                PropagationResult::unchanged(value)
             }
+        }
+        Value::Add { lhs, rhs, bit_size } => match (lhs, rhs) {
+            (a, CouldBeConstant::Constant(c)) if a == known_binding => PropagationResult::modified(
+                Value::Constant(wrap(binding_value.wrapping_add(c), bit_size)),
+            ),
             (a, CouldBeConstant::Binding(b)) => {
                 if a == b && a == known_binding {
-                    PropagationResult::modified(Value::Constant(
+                    PropagationResult::modified(Value::Constant(wrap(
                         binding_value.wrapping_add(binding_value),
-                    ))
+                        bit_size,
+                    )))
                 } else if a == known_binding {
                     // flip the operation to have the constant on rhs
                     PropagationResult::modified(Value::Add {
                         lhs: b,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else if b == known_binding {
                     PropagationResult::modified(Value::Add {
                         lhs: a,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else {
                     PropagationResult::unchanged(value)
@@ -434,10 +455,10 @@ fn value_propagate_constant(
 
             (lhs, rhs) => PropagationResult::unchanged(value),
         },
-        Value::Subtract { lhs, rhs } => match rhs {
-            CouldBeConstant::Constant(c) if lhs == known_binding => {
-                PropagationResult::modified(Value::Constant(binding_value.wrapping_sub(c)))
-            }
+        Value::Subtract { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(c) if lhs == known_binding => PropagationResult::modified(
+                Value::Constant(wrap(binding_value.wrapping_sub(c), bit_size)),
+            ),
             CouldBeConstant::Binding(other) => {
                 if lhs == known_binding && other == known_binding {
                     PropagationResult::modified(Value::Constant(0))
@@ -450,7 +471,8 @@ fn value_propagate_constant(
                     // here I can change the rhs to be a constant
                     PropagationResult::modified(Value::Subtract {
                         lhs,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else {
                     PropagationResult::unchanged(value)
@@ -458,25 +480,28 @@ fn value_propagate_constant(
             }
             CouldBeConstant::Constant(_) => PropagationResult::unchanged(value),
         },
-        Value::Multiply { lhs, rhs } => match (lhs, rhs) {
-            (a, CouldBeConstant::Constant(c)) if a == known_binding => {
-                PropagationResult::modified(Value::Constant(binding_value.wrapping_mul(c)))
-            }
+        Value::Multiply { lhs, rhs, bit_size } => match (lhs, rhs) {
+            (a, CouldBeConstant::Constant(c)) if a == known_binding => PropagationResult::modified(
+                Value::Constant(wrap(binding_value.wrapping_mul(c), bit_size)),
+            ),
             (a, CouldBeConstant::Binding(b)) => {
                 if a == b && a == known_binding {
-                    PropagationResult::modified(Value::Constant(
+                    PropagationResult::modified(Value::Constant(wrap(
                         binding_value.wrapping_mul(binding_value),
-                    ))
+                        bit_size,
+                    )))
                 } else if a == known_binding {
                     // flip the operation to have the constant on rhs
                     PropagationResult::modified(Value::Multiply {
                         lhs: b,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else if b == known_binding {
                     PropagationResult::modified(Value::Multiply {
                         lhs: a,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else {
                     PropagationResult::unchanged(value)
@@ -491,49 +516,136 @@ fn value_propagate_constant(
             lhs,
             rhs,
             is_signed,
+            bit_size,
         } => match rhs {
             CouldBeConstant::Binding(other) => {
                 // NOTE: Since division does *not* support any kind of *commutativity, I cannot
                 // reorder it
-                if lhs == known_binding && other == known_binding && binding_value != 0 {
+                if lhs == known_binding
+                    && other == known_binding
+                    && wrap(binding_value, bit_size) != 0
+                {
                     PropagationResult::modified(Value::Constant(1))
                 } else if other == known_binding {
                     // I can set the other to be a constant
                     PropagationResult::modified(Value::Divide {
                         lhs,
-                        rhs: CouldBeConstant::Constant(binding_value),
+                        rhs: wrap(binding_value, bit_size).into(),
                         is_signed,
+                        bit_size,
                     })
                 } else {
                     PropagationResult::unchanged(value)
                 }
             }
-            CouldBeConstant::Constant(ctant) if lhs == known_binding && ctant != 0 => {
-                PropagationResult::modified(Value::Constant(binding_value / ctant))
+            CouldBeConstant::Constant(ctant)
+                if lhs == known_binding && wrap(ctant, bit_size) != 0 =>
+            {
+                let (lhs, rhs) = (wrap(binding_value, bit_size), wrap(ctant, bit_size));
+                PropagationResult::modified(Value::Constant(match (is_signed, bit_size) {
+                    (true, _) => wrap(lhs.wrapping_div(rhs), bit_size),
+                    (false, BitSize::Bit32) => wrap((lhs as u32 / rhs as u32).into(), bit_size),
+                    (false, BitSize::Bit64) => (lhs as u64 / rhs as u64) as i64,
+                }))
             }
             // otherwise i'll leave it as is, because I can't fold it in a safe way.
             _ => PropagationResult::unchanged(value),
         },
-        Value::Lsl { lhs, rhs } => todo!(),
-        Value::Lsr { lhs, rhs } => todo!(),
-        Value::And { lhs, rhs } => match rhs {
+        Value::Lsl { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(ctant) if lhs == known_binding => {
+                PropagationResult::modified(Value::Constant(wrap(
+                    binding_value << shift_amount(ctant, bit_size),
+                    bit_size,
+                )))
+            }
+            // shifts aren't commutative, so only the amount can be made a constant
+            CouldBeConstant::Binding(other) if other == known_binding => {
+                PropagationResult::modified(Value::Lsl {
+                    lhs,
+                    rhs: wrap(binding_value, bit_size).into(),
+                    bit_size,
+                })
+            }
+            _ => PropagationResult::unchanged(value),
+        },
+        Value::Lsr { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(ctant) if lhs == known_binding => {
+                let amount = shift_amount(ctant, bit_size);
+                PropagationResult::modified(Value::Constant(match bit_size {
+                    BitSize::Bit32 => wrap(((binding_value as u32) >> amount).into(), bit_size),
+                    BitSize::Bit64 => ((binding_value as u64) >> amount) as i64,
+                }))
+            }
+            CouldBeConstant::Binding(other) if other == known_binding => {
+                PropagationResult::modified(Value::Lsr {
+                    lhs,
+                    rhs: wrap(binding_value, bit_size).into(),
+                    bit_size,
+                })
+            }
+            _ => PropagationResult::unchanged(value),
+        },
+        Value::Asr { lhs, rhs, bit_size } => match rhs {
             CouldBeConstant::Constant(ctant) if lhs == known_binding => {
-                PropagationResult::modified(Value::Constant(binding_value & ctant))
+                PropagationResult::modified(Value::Constant(
+                    wrap(binding_value, bit_size) >> shift_amount(ctant, bit_size),
+                ))
+            }
+            CouldBeConstant::Binding(other) if other == known_binding => {
+                PropagationResult::modified(Value::Asr {
+                    lhs,
+                    rhs: wrap(binding_value, bit_size).into(),
+                    bit_size,
+                })
+            }
+            _ => PropagationResult::unchanged(value),
+        },
+        Value::And { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(ctant) if lhs == known_binding => {
+                PropagationResult::modified(Value::Constant(wrap(binding_value & ctant, bit_size)))
             }
             CouldBeConstant::Binding(other) => {
                 if lhs == known_binding && other == known_binding {
-                    PropagationResult::modified(Value::Constant(binding_value))
+                    PropagationResult::modified(Value::Constant(wrap(binding_value, bit_size)))
                 } else if lhs == known_binding {
                     // reorder the AND
                     PropagationResult::modified(Value::And {
                         lhs: other,
-                        rhs: binding_value.into(),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else if other == known_binding {
                     // we could evaluate half of it.
                     PropagationResult::modified(Value::And {
                         lhs,
-                        rhs: binding_value.into(),
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
+                    })
+                } else {
+                    PropagationResult::unchanged(value)
+                }
+            }
+            CouldBeConstant::Constant(_) => PropagationResult::unchanged(value),
+        },
+        Value::Or { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(ctant) if lhs == known_binding => {
+                PropagationResult::modified(Value::Constant(wrap(binding_value | ctant, bit_size)))
+            }
+            CouldBeConstant::Binding(other) => {
+                if lhs == known_binding && other == known_binding {
+                    PropagationResult::modified(Value::Constant(wrap(binding_value, bit_size)))
+                } else if lhs == known_binding {
+                    // reorder the OR
+                    PropagationResult::modified(Value::Or {
+                        lhs: other,
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
+                    })
+                } else if other == known_binding {
+                    PropagationResult::modified(Value::Or {
+                        lhs,
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
                     })
                 } else {
                     PropagationResult::unchanged(value)
@@ -541,10 +653,82 @@ fn value_propagate_constant(
             }
             CouldBeConstant::Constant(_) => PropagationResult::unchanged(value),
         },
-        Value::Or { lhs, rhs } => todo!(),
-        Value::Xor { lhs, rhs } => todo!(),
+        Value::Xor { lhs, rhs, bit_size } => match rhs {
+            CouldBeConstant::Constant(ctant) if lhs == known_binding => {
+                PropagationResult::modified(Value::Constant(wrap(binding_value ^ ctant, bit_size)))
+            }
+            CouldBeConstant::Binding(other) => {
+                if lhs == known_binding && other == known_binding {
+                    PropagationResult::modified(Value::Constant(0))
+                } else if lhs == known_binding {
+                    // reorder the XOR
+                    PropagationResult::modified(Value::Xor {
+                        lhs: other,
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
+                    })
+                } else if other == known_binding {
+                    PropagationResult::modified(Value::Xor {
+                        lhs,
+                        rhs: wrap(binding_value, bit_size).into(),
+                        bit_size,
+                    })
+                } else {
+                    PropagationResult::unchanged(value)
+                }
+            }
+            CouldBeConstant::Constant(_) => PropagationResult::unchanged(value),
+        },
+        Value::IntToFloat { binding, from, to } => {
+            if binding == known_binding {
+                // rounds to nearest, like `scvtf` does
+                let integer = wrap(binding_value, from);
+                PropagationResult::modified(Value::FloatConstant {
+                    bits: match to {
+                        BitSize::Bit32 => (integer as f32).to_bits().into(),
+                        BitSize::Bit64 => (integer as f64).to_bits(),
+                    },
+                    bit_size: to,
+                })
+            } else {
+                PropagationResult::unchanged(value)
+            }
+        }
+        // only integer constants are known, floating-point values aren't folded yet
+        Value::FloatArithmetic { .. }
+        | Value::FloatNegate { .. }
+        | Value::FloatCmp { .. }
+        | Value::FloatToInt { .. }
+        | Value::FloatConvert { .. } => PropagationResult::unchanged(value),
         // already a constant, cannot fold further
-        Value::Constant(_) => PropagationResult::unchanged(value),
-        Value::Binding(_) => todo!(),
+        Value::Constant(_) | Value::FloatConstant { .. } | Value::Undefined => {
+            PropagationResult::unchanged(value)
+        }
+        // the callee receives the arguments in registers, whether they are constant or not
+        Value::Call { .. }
+        | Value::CallPointer { .. }
+        | Value::CallStruct { .. }
+        | Value::CallFloat { .. }
+        | Value::ReturnedStruct { .. }
+        | Value::ResultAddress { .. }
+        | Value::Argument(_)
+        | Value::FloatArgument(_)
+        | Value::GlobalRegister(_)
+        | Value::ReadOnlyAddress(_)
+        | Value::FunctionAddress(_)
+        | Value::GlobalAddress(_)
+        | Value::Address { .. }
+        | Value::ElementAddress { .. } => PropagationResult::unchanged(value),
+        // the memory is read and written at once, which takes its operands in registers
+        Value::AtomicLoad { .. } | Value::AtomicFetchAdd { .. } => {
+            PropagationResult::unchanged(value)
+        }
+        Value::Binding(other) => {
+            if other == known_binding {
+                PropagationResult::modified(Value::Constant(binding_value))
+            } else {
+                PropagationResult::unchanged(value)
+            }
+        }
     }
 }
@@ -1,10 +1,17 @@
-use std::fmt;
+use alloc::format;
+use alloc::string::{String, ToString};
 
+use core::fmt;
+use hashbrown::HashMap;
+
+use crate::codegen::assembly::BitSize;
 use crate::write_instruction;
 
+use super::block_args::{ArgBlock, ArgBlockEnd, ArgIR, BlockCall};
+use super::generate::FunctionIR;
 use super::{
-    BasicBlock, Binding, BlockBinding, BlockEnd, Branch, ByteSize, CouldBeConstant, PhiDescriptor,
-    Statement, Value, IR,
+    BasicBlock, Binding, BlockBinding, BlockEnd, Branch, ByteSize, Callee, CouldBeConstant,
+    FloatOp, PhiDescriptor, Statement, Value, IR,
 };
 
 // format impls
@@ -41,18 +48,68 @@ impl fmt::Display for CouldBeConstant {
     }
 }
 
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callee::Function(function) => write!(f, "@{}", function),
+            Callee::Pointer(pointer) => write!(f, "*{}", pointer),
+        }
+    }
+}
+
 impl fmt::Display for PhiDescriptor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "[ {}, {} ]", self.value, self.block_from)
     }
 }
 
+/// The name of an operation on integers, marked with `.64` when it works on 64 bits
+fn sized(name: &str, bit_size: BitSize) -> String {
+    match bit_size {
+        BitSize::Bit32 => name.to_string(),
+        BitSize::Bit64 => format!("{}.64", name),
+    }
+}
+
+/// The name of a memory access, marked with `.volatile` when it's volatile
+fn volatile_name(name: &str, volatile: bool) -> String {
+    if volatile {
+        format!("{}.volatile", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// The name of an operation on floating-point values, marked with the precision it works on
+fn float_sized(name: &str, bit_size: BitSize) -> String {
+    format!("{}.{}", name, float_type(bit_size))
+}
+
+const fn float_type(bit_size: BitSize) -> &'static str {
+    match bit_size {
+        BitSize::Bit32 => "f32",
+        BitSize::Bit64 => "f64",
+    }
+}
+
+const fn int_type(bit_size: BitSize) -> &'static str {
+    match bit_size {
+        BitSize::Bit32 => "i32",
+        BitSize::Bit64 => "i64",
+    }
+}
+
 impl fmt::Display for ByteSize {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(match self {
             Self::U8 => "u8",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::U16 => "u16",
             Self::U32 => "u32",
             Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
         })
     }
 }
@@ -73,29 +130,189 @@ impl fmt::Display for Value {
                 condition,
                 lhs,
                 rhs,
-            } => write_instruction!(f, "cmp", condition, lhs, rhs),
+                bit_size,
+            } => write_instruction!(f, sized("cmp", *bit_size), condition, lhs, rhs),
+            Value::Select {
+                flag,
+                if_true,
+                if_false,
+            } => write_instruction!(f, "select", flag, if_true, if_false),
             Value::Load {
                 mem_binding,
                 byte_size,
-            } => write_instruction!(f, "load", mem_binding, byte_size),
-            Value::Negate { binding } => write_instruction!(f, "neg", binding),
-            Value::FlipBits { binding } => write_instruction!(f, "flip_bits", binding),
-            Value::Add { lhs, rhs } => write_instruction!(f, "add", lhs, rhs),
-            Value::Subtract { lhs, rhs } => write_instruction!(f, "sub", lhs, rhs),
+                volatile,
+            } => write_instruction!(f, volatile_name("load", *volatile), mem_binding, byte_size),
+            Value::AtomicLoad {
+                mem_binding,
+                byte_size,
+            } => write_instruction!(f, "atomic_load", mem_binding, byte_size),
+            Value::AtomicFetchAdd {
+                mem_binding,
+                binding,
+                byte_size,
+            } => write_instruction!(
+                f,
+                "atomic_fetch_add",
+                mem_binding,
+                format!("{} {}", byte_size, binding)
+            ),
+            Value::Address { mem_binding } => write_instruction!(f, "address", mem_binding),
+            Value::Negate { binding, bit_size } => {
+                write_instruction!(f, sized("neg", *bit_size), binding)
+            }
+            Value::FlipBits { binding, bit_size } => {
+                write_instruction!(f, sized("flip_bits", *bit_size), binding)
+            }
+            Value::SignExtend { binding, from } => write_instruction!(f, "sext", binding, from),
+            Value::FloatConstant { bits, bit_size } => match bit_size {
+                BitSize::Bit32 => write!(f, "{:?}f", f32::from_bits(*bits as u32)),
+                BitSize::Bit64 => write!(f, "{:?}", f64::from_bits(*bits)),
+            },
+            Value::FloatArithmetic {
+                op,
+                lhs,
+                rhs,
+                bit_size,
+            } => {
+                let name = match op {
+                    FloatOp::Add => "fadd",
+                    FloatOp::Subtract => "fsub",
+                    FloatOp::Multiply => "fmul",
+                    FloatOp::Divide => "fdiv",
+                };
+                write_instruction!(f, float_sized(name, *bit_size), lhs, rhs)
+            }
+            Value::FloatNegate { binding, bit_size } => {
+                write_instruction!(f, float_sized("fneg", *bit_size), binding)
+            }
+            Value::FloatCmp {
+                condition,
+                lhs,
+                rhs,
+                bit_size,
+            } => write_instruction!(f, float_sized("fcmp", *bit_size), condition, lhs, rhs),
+            Value::IntToFloat { binding, from, to } => {
+                write_instruction!(f, "itof", binding, int_type(*from), float_type(*to))
+            }
+            Value::FloatToInt { binding, from, to } => {
+                write_instruction!(f, "ftoi", binding, float_type(*from), int_type(*to))
+            }
+            Value::FloatConvert { binding, from, to } => {
+                write_instruction!(f, "fconv", binding, float_type(*from), float_type(*to))
+            }
+            Value::Add { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("add", *bit_size), lhs, rhs)
+            }
+            Value::Subtract { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("sub", *bit_size), lhs, rhs)
+            }
             Value::Divide {
                 lhs,
                 rhs,
                 is_signed,
-            } => write_instruction!(f, if *is_signed { "idiv" } else { "udiv" }, lhs, rhs),
-            Value::Lsl { lhs, rhs } => write_instruction!(f, "lsl", lhs, rhs),
-            Value::Lsr { lhs, rhs } => write_instruction!(f, "lsr", lhs, rhs),
-            Value::And { lhs, rhs } => write_instruction!(f, "and", lhs, rhs),
-            Value::Or { lhs, rhs } => write_instruction!(f, "or", lhs, rhs),
-            Value::Xor { lhs, rhs } => write_instruction!(f, "xor", lhs, rhs),
-            Value::Multiply { lhs, rhs } => write_instruction!(f, "mul", lhs, rhs),
-            Value::Allocate { size } => write_instruction!(f, "alloca", size),
+                bit_size,
+            } => write_instruction!(
+                f,
+                sized(if *is_signed { "idiv" } else { "udiv" }, *bit_size),
+                lhs,
+                rhs
+            ),
+            Value::Lsl { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("lsl", *bit_size), lhs, rhs)
+            }
+            Value::Lsr { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("lsr", *bit_size), lhs, rhs)
+            }
+            Value::Asr { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("asr", *bit_size), lhs, rhs)
+            }
+            Value::And { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("and", *bit_size), lhs, rhs)
+            }
+            Value::Or { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("or", *bit_size), lhs, rhs)
+            }
+            Value::Xor { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("xor", *bit_size), lhs, rhs)
+            }
+            Value::Multiply { lhs, rhs, bit_size } => {
+                write_instruction!(f, sized("mul", *bit_size), lhs, rhs)
+            }
+            Value::Allocate { size, align } => write_instruction!(f, "alloca", size, align),
             Value::Constant(constant) => constant.fmt(f),
+            Value::Undefined => f.write_str("undef"),
             Value::Binding(binding) => binding.fmt(f),
+            Value::Call { function, args } => {
+                write!(f, "call @{}(", function)?;
+                if let Some((first, rest)) = args.split_first() {
+                    first.fmt(f)?;
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+                f.write_str(")")
+            }
+            Value::CallPointer { pointer, args } => {
+                write!(f, "call *{}(", pointer)?;
+                if let Some((first, rest)) = args.split_first() {
+                    first.fmt(f)?;
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+                f.write_str(")")
+            }
+            Value::CallStruct {
+                callee,
+                args,
+                result,
+                size,
+            } => {
+                write!(f, "call {}(", callee)?;
+                if let Some((first, rest)) = args.split_first() {
+                    first.fmt(f)?;
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+                write!(f, ") into {}, {}", result, size)
+            }
+            Value::CallFloat {
+                callee,
+                args,
+                bit_size,
+            } => {
+                write!(f, "{} {}(", float_sized("call", *bit_size), callee)?;
+                if let Some((first, rest)) = args.split_first() {
+                    first.fmt(f)?;
+                    for arg in rest {
+                        write!(f, ", {}", arg)?;
+                    }
+                }
+                f.write_str(")")
+            }
+            Value::ReturnedStruct { memory, size } => {
+                write_instruction!(f, "retstruct", memory, size)
+            }
+            Value::ResultAddress { size } => write_instruction!(f, "resultaddr", size),
+            Value::FunctionAddress(function) => write!(f, "fnaddr @{}", function),
+            Value::GlobalAddress(variable) => write!(f, "global @{}", variable),
+            Value::Argument(index) => write_instruction!(f, "arg", index),
+            Value::FloatArgument(index) => write_instruction!(f, "farg", index),
+            Value::GlobalRegister(register) => write!(f, "register x{}", register),
+            Value::ReadOnlyAddress(index) => write_instruction!(f, "rodata", index),
+            Value::ElementAddress {
+                base,
+                index,
+                element_size,
+                bit_size,
+            } => write_instruction!(
+                f,
+                sized("element_address", *bit_size),
+                base,
+                index,
+                element_size
+            ),
         }
     }
 }
@@ -108,12 +325,26 @@ impl fmt::Display for Statement {
                 mem_binding,
                 binding,
                 byte_size,
+                volatile,
+            } => write_instruction!(
+                f,
+                volatile_name("store", *volatile),
+                mem_binding,
+                format!("{} {}", byte_size, binding)
+            ),
+            Statement::AtomicStore {
+                mem_binding,
+                binding,
+                byte_size,
             } => write_instruction!(
                 f,
-                "store",
+                "atomic_store",
                 mem_binding,
                 format!("{} {}", byte_size, binding)
             ),
+            Statement::SetRegister { register, binding } => {
+                write!(f, "set_register x{}, {}", register, binding)
+            }
         }
     }
 }
@@ -156,9 +387,10 @@ impl fmt::Display for Branch {
     }
 }
 
-impl fmt::Debug for IR {
+/// The blocks in order, each with its label and then a statement per line, as `--emit=ir` writes
+/// them. Passes are compared by this text, so it only changes when the code does.
+impl fmt::Display for IR {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("\n")?;
         for (block_index, block) in self.code.iter().enumerate() {
             let bb = BlockBinding(block_index);
             write!(f, "{}:", bb)?;
@@ -167,3 +399,249 @@ impl fmt::Debug for IR {
         Ok(())
     }
 }
+
+impl fmt::Debug for IR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\n")?;
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// The IR of the function under its name, like calls refer to it
+impl fmt::Display for FunctionIR<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "@{}:", self.name)?;
+        self.ir.named(&self.variables).fmt(f)
+    }
+}
+
+/// The IR of a function, with each binding shown as `%name.index`: the name is the variable whose
+/// memory it is, or was loaded from, and otherwise the kind of value it is. The index is kept so
+/// that a binding is named the same before and after a pass.
+pub struct Named<'ir> {
+    ir: &'ir IR,
+    names: HashMap<Binding, String>,
+}
+
+impl IR {
+    /// Show the bindings by where they came from. `variables` names the bindings that hold the
+    /// memory of a variable, or the argument a parameter was passed in.
+    pub fn named(&self, variables: &HashMap<Binding, String>) -> Named<'_> {
+        let mut names = HashMap::new();
+        for statement in self.code.iter().flat_map(|block| &block.statements) {
+            let (index, value) = match statement {
+                Statement::Assign { index, value } => (*index, value),
+                _ => continue,
+            };
+            let from_variable = match value {
+                Value::Load { mem_binding, .. }
+                | Value::AtomicLoad { mem_binding, .. }
+                | Value::Address { mem_binding } => variables.get(mem_binding),
+                Value::Binding(binding) => names.get(binding),
+                // a value that is the same variable whichever way it's reached
+                Value::Phi { nodes } => names.get(&nodes[0].value).filter(|&name| {
+                    nodes
+                        .iter()
+                        .all(|node| names.get(&node.value) == Some(name))
+                }),
+                _ => None,
+            };
+            let name = variables
+                .get(&index)
+                .or(from_variable)
+                .cloned()
+                .unwrap_or_else(|| value.kind().to_string());
+            names.insert(index, name);
+        }
+        Named { ir: self, names }
+    }
+}
+
+impl Value {
+    /// What kind of value it is, to name the bindings that aren't a variable
+    const fn kind(&self) -> &'static str {
+        match self {
+            Value::Phi { .. } => "phi",
+            Value::Cmp { .. } => "cmp",
+            Value::Select { .. } => "select",
+            Value::Load { .. } => "load",
+            Value::AtomicLoad { .. } => "atomic_load",
+            Value::AtomicFetchAdd { .. } => "fetch_add",
+            Value::Address { .. } => "addr",
+            Value::Negate { .. } => "neg",
+            Value::FlipBits { .. } => "not",
+            Value::SignExtend { .. } => "sext",
+            Value::FloatConstant { .. } => "fconst",
+            Value::FloatArithmetic { op, .. } => match op {
+                FloatOp::Add => "fadd",
+                FloatOp::Subtract => "fsub",
+                FloatOp::Multiply => "fmul",
+                FloatOp::Divide => "fdiv",
+            },
+            Value::FloatNegate { .. } => "fneg",
+            Value::FloatCmp { .. } => "fcmp",
+            Value::IntToFloat { .. } => "itof",
+            Value::FloatToInt { .. } => "ftoi",
+            Value::FloatConvert { .. } => "fconv",
+            Value::Add { .. } => "add",
+            Value::Subtract { .. } => "sub",
+            Value::Divide { .. } => "div",
+            Value::Lsl { .. } => "lsl",
+            Value::Lsr { .. } => "lsr",
+            Value::Asr { .. } => "asr",
+            Value::And { .. } => "and",
+            Value::Or { .. } => "or",
+            Value::Xor { .. } => "xor",
+            Value::Multiply { .. } => "mul",
+            Value::Allocate { .. } => "alloca",
+            Value::Constant(_) => "const",
+            Value::Undefined => "undef",
+            Value::Binding(_) => "copy",
+            Value::Call { .. }
+            | Value::CallPointer { .. }
+            | Value::CallStruct { .. }
+            | Value::CallFloat { .. } => "call",
+            Value::ReturnedStruct { .. } => "retstruct",
+            Value::ResultAddress { .. } => "resultaddr",
+            Value::FunctionAddress(_) => "fnaddr",
+            Value::GlobalAddress(_) => "global",
+            Value::Argument(_) | Value::FloatArgument(_) => "arg",
+            Value::GlobalRegister(_) => "reg",
+            Value::ReadOnlyAddress(_) => "rodata",
+            Value::ElementAddress { .. } => "elem",
+        }
+    }
+}
+
+impl fmt::Display for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the IR is written as usual, and then the name of each binding is put after its `%`
+        let text = self.ir.to_string();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find('%') {
+            f.write_str(&rest[..=start])?;
+            rest = &rest[start + 1..];
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let name = rest[..digits]
+                .parse()
+                .ok()
+                .and_then(|index| self.names.get(&Binding(index)));
+            if let Some(name) = name {
+                write!(f, "{}.", name)?;
+            }
+        }
+        f.write_str(rest)
+    }
+}
+
+impl fmt::Debug for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\n")?;
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// The bindings in parentheses, separated by commas
+fn write_list(f: &mut fmt::Formatter<'_>, bindings: &[Binding]) -> fmt::Result {
+    f.write_str("(")?;
+    if let Some((first, rest)) = bindings.split_first() {
+        write!(f, "{}", first)?;
+        for binding in rest {
+            write!(f, ", {}", binding)?;
+        }
+    }
+    f.write_str(")")
+}
+
+impl fmt::Display for BlockCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.target.fmt(f)?;
+        write_list(f, &self.args)
+    }
+}
+
+impl fmt::Display for ArgBlockEnd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgBlockEnd::Jump(call) => write_instruction!(f, "br", call),
+            ArgBlockEnd::Branch {
+                flag,
+                if_true,
+                if_false,
+            } => write_instruction!(f, "br-cond", flag, if_true, if_false),
+            ArgBlockEnd::Return(index) => write_instruction!(f, "ret", index),
+        }
+    }
+}
+
+impl fmt::Display for ArgBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_list(f, &self.params)?;
+        f.write_str(":\n")?;
+        for stmt in &self.statements {
+            writeln!(f, "  {}", stmt)?;
+        }
+        writeln!(f, "  {}", self.end)
+    }
+}
+
+impl fmt::Debug for ArgIR {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\n")?;
+        for (block_index, block) in self.code.iter().enumerate() {
+            write!(f, "{}{}", BlockBinding(block_index), block)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_are_named_by_their_variable_or_their_kind() -> anyhow::Result<()> {
+        let source = "int f(int a) {\n    int x = a * 3;\n    return x;\n}\n";
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let function = &module.functions[0];
+        let named = format!("{:?}", function.ir.named(&function.variables));
+        assert!(named.contains("%a.1 = arg 0"), "{}", named);
+        assert!(named.contains("= mul %a."), "{}", named);
+        assert!(named.contains("ret %x."), "{}", named);
+        // outside of the named IR, bindings are only numbered
+        assert!(!format!("{:?}", function.ir).contains("%a."));
+        Ok(())
+    }
+
+    #[test]
+    fn functions_are_written_block_by_block() -> anyhow::Result<()> {
+        let source = "int f(int a) {\n    if (a)\n        return 2;\n    return a;\n}\n";
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        let text = module.functions[0].to_string();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("@f:"));
+        assert_eq!(lines.next(), Some("BB0:"));
+        let blocks = text.lines().filter(|line| line.starts_with("BB")).count();
+        assert_eq!(blocks, module.functions[0].ir.code.len());
+        // each statement is indented under the label of its block
+        assert!(text
+            .lines()
+            .skip(1)
+            .all(|line| line.starts_with("BB") || line.starts_with("  ")));
+        // with its bindings named, as `--emit=ir` writes it
+        assert!(text.contains("%a."), "{}", text);
+        assert_eq!(
+            format!("{:?}", module.functions[0].ir),
+            format!("\n{}", module.functions[0].ir)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+//! Structs as whole values, which are copied from where they are to where they go: assigned,
+//! initialized from other structs, and passed to and returned from functions as the AAPCS64 says.
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{
+    expr, initializer, BindingCounter, BlockBuilder, ByteSize, IRGenState, SourceMetadata, Value,
+    VarE, VarError, VariableTracker, MAX_STRUCT_IN_REGISTERS,
+};
+use crate::ast;
+use crate::codegen::assembly::BitSize;
+use crate::error::Span;
+use crate::intermediate::Binding;
+
+/// Structs of up to this many bytes are copied with loads and stores, larger ones with `memcpy`
+const INLINE_COPY_MAX: usize = 64;
+
+/// Check that values of the type are passed in a way the generator knows. A struct made of up to
+/// four floating-point values of the same type (a homogeneous floating-point aggregate) goes in
+/// floating-point registers, which isn't supported.
+pub fn check_passable(ty: &ast::Type) -> Result<(), VarE> {
+    let ast::Type::Struct(_) = ty else {
+        return Ok(());
+    };
+    let mut scalars = Vec::new();
+    push_scalars(ty, &mut scalars);
+    let homogeneous = scalars.first().is_some_and(|first| {
+        first.is_floating()
+            && scalars.iter().all(|scalar| scalar == first)
+            && ty.size() <= 4 * first.size()
+    });
+    if homogeneous {
+        return Err(VarE::new(VarError::FloatAggregate(ty.clone())));
+    }
+    Ok(())
+}
+
+/// Push the scalars a type is made of, in order, until there are more than four of them
+fn push_scalars(ty: &ast::Type, scalars: &mut Vec<ast::Type>) {
+    match ty.unqualified() {
+        ast::Type::Struct(struct_type) => {
+            for member in &struct_type.members {
+                push_scalars(&member.ty, scalars);
+            }
+        }
+        ast::Type::Array { element, len } => {
+            for _ in 0..*len {
+                if scalars.len() > 4 {
+                    return;
+                }
+                push_scalars(element, scalars);
+            }
+        }
+        scalar => scalars.push(scalar.clone()),
+    }
+}
+
+/// Copy a value of type `ty` from the memory at `from` to the one at `to`, either of which may be
+/// an allocation or a pointer. No access is wider than the type's alignment.
+pub fn copy_memory(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    (from, to): (Binding, Binding),
+    ty: &ast::Type,
+) {
+    let size = ty.size();
+    if size > INLINE_COPY_MAX {
+        let destination = address(builder, bindings, to);
+        let source = address(builder, bindings, from);
+        let len = bindings.next_binding();
+        builder.assign(len, Value::Constant(size as i64));
+        let call = bindings.next_binding();
+        builder.assign(
+            call,
+            Value::Call {
+                function: "memcpy".into(),
+                args: vec![destination, source, len],
+            },
+        );
+        return;
+    }
+    let mut offset = 0;
+    while offset < size {
+        let (width, byte_size) = initializer::widest_access(offset, size, ty.align());
+        let source = initializer::offset_address(builder, bindings, from, offset);
+        let value = bindings.next_binding();
+        builder.load(value, source, byte_size);
+        let destination = initializer::offset_address(builder, bindings, to, offset);
+        builder.store(value, destination, byte_size);
+        offset += width;
+    }
+}
+
+/// The address of an allocation, or of what a pointer points to, as a value
+fn address(builder: &mut BlockBuilder, bindings: &mut BindingCounter, memory: Binding) -> Binding {
+    let zero = bindings.next_binding();
+    builder.assign(zero, Value::Constant(0));
+    let address = bindings.next_binding();
+    builder.assign(
+        address,
+        Value::ElementAddress {
+            base: memory,
+            index: zero,
+            element_size: 1,
+            bit_size: BitSize::Bit32,
+        },
+    );
+    address
+}
+
+/// Allocate memory for a struct moved in registers, padded to whole words
+pub fn register_memory(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    ty: &ast::Type,
+) -> Binding {
+    let memory = bindings.next_binding();
+    builder.allocate(memory, ty.size().next_multiple_of(8), ty.align().max(8));
+    memory
+}
+
+/// Compute where the struct an expression evaluates to is, which must be of type `expected`: the
+/// memory of a variable or of what a pointer points to, or the memory a call returns it in
+pub fn struct_place<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (expr, span): (ast::Expr<'code>, Span),
+    expected: &ast::Type,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Binding), VarE> {
+    let found = expr::expr_type(&expr, variables);
+    if found != *expected.unqualified() {
+        return Err(VarE::new(VarError::MismatchedStruct {
+            expected: Box::new(expected.clone()),
+            found: Box::new(found),
+        })
+        .with_source(span, source_info));
+    }
+    match expr.without_parens() {
+        ast::Expr::Variable { .. }
+        | ast::Expr::Member { .. }
+        | ast::Expr::Arrow { .. }
+        | ast::Expr::Index { .. }
+        | ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            ..
+        }
+        | ast::Expr::Call { .. }
+        | ast::Expr::CallPointer { .. } => (),
+        _ => return Err(VarE::new(VarError::StructValue(found)).with_source(span, source_info)),
+    }
+    let (builder, place, _) =
+        expr::expr_as_target(state, builder, expr, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(span, source_info))?;
+    Ok((builder, place))
+}
+
+/// Compute a struct argument into the bindings it's passed in: its words if it's small enough for
+/// registers, or else the address of a copy of it, which the callee may change
+pub fn pass_struct<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (arg, arg_span): (ast::Expr<'code>, Span),
+    ty: &ast::Type,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Vec<Binding>), VarE> {
+    check_passable(ty).map_err(|e| e.with_source(arg_span, source_info))?;
+    let (mut builder, place) = struct_place(
+        state,
+        builder,
+        (arg, arg_span),
+        ty,
+        bindings,
+        variables,
+        source_info,
+    )?;
+    let size = ty.size();
+    if size > MAX_STRUCT_IN_REGISTERS {
+        let copy = bindings.next_binding();
+        builder.allocate(copy, size, ty.align());
+        copy_memory(&mut builder, bindings, (place, copy), ty);
+        let address = bindings.next_binding();
+        builder.assign(address, Value::Address { mem_binding: copy });
+        return Ok((builder, vec![address]));
+    }
+    // the words are loaded from a copy padded to whole aligned words, unless the struct already is
+    let words = if ty.align() >= 8 {
+        place
+    } else {
+        let copy = register_memory(&mut builder, bindings, ty);
+        copy_memory(&mut builder, bindings, (place, copy), ty);
+        copy
+    };
+    let words = (0..size.div_ceil(8))
+        .map(|word| {
+            let address = initializer::offset_address(&mut builder, bindings, words, 8 * word);
+            let value = bindings.next_binding();
+            builder.load(value, address, ByteSize::U64);
+            value
+        })
+        .collect();
+    Ok((builder, words))
+}
+
+/// Store the words of a struct parameter passed in registers, from the `first` integer argument
+/// on, into the memory of the parameter. Gives the bindings of the arguments.
+pub fn receive_in_registers(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    (memory, ty): (Binding, &ast::Type),
+    first: usize,
+) -> Vec<Binding> {
+    builder.allocate(memory, ty.size().next_multiple_of(8), ty.align().max(8));
+    (0..ty.size().div_ceil(8))
+        .map(|word| {
+            let argument = bindings.next_binding();
+            builder.assign(argument, Value::Argument(first + word));
+            let address = initializer::offset_address(builder, bindings, memory, 8 * word);
+            builder.store(argument, address, ByteSize::U64);
+            argument
+        })
+        .collect()
+}
+
+/// Compile the value of `return` in a function returning the struct `ty`. A struct returned
+/// through memory is copied to the address kept in `result_address`, and the others to padded
+/// memory they are loaded in registers from. Gives the binding to return.
+pub fn return_struct<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    expr: (ast::Expr<'code>, Span),
+    (ty, result_address): (&ast::Type, Option<Binding>),
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Binding), VarE> {
+    let (mut builder, place) =
+        struct_place(state, builder, expr, ty, bindings, variables, source_info)?;
+    let returned = bindings.next_binding();
+    match result_address {
+        Some(result_address) => {
+            let destination = bindings.next_binding();
+            builder.load(destination, result_address, ByteSize::U64);
+            copy_memory(&mut builder, bindings, (place, destination), ty);
+            builder.assign(returned, 0);
+        }
+        None => {
+            let memory = register_memory(&mut builder, bindings, ty);
+            copy_memory(&mut builder, bindings, (place, memory), ty);
+            builder.assign(
+                returned,
+                Value::ReturnedStruct {
+                    memory,
+                    size: ty.size(),
+                },
+            );
+        }
+    }
+    Ok((builder, returned))
+}
+
+/// Compile the assignment of a struct to another one of the same type, which copies it. The
+/// struct assigned is computed first, like the value of any other assignment.
+pub fn assign_struct<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (lhs, lhs_span): (ast::Expr<'code>, Span),
+    rhs: (ast::Expr<'code>, Span),
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<BlockBuilder, VarE> {
+    if let ast::Expr::Call { .. } | ast::Expr::CallPointer { .. } = lhs.without_parens() {
+        return Err(VarE::new(VarError::NotAssignable).with_source(lhs_span, source_info));
+    }
+    let ty = expr::expr_type(&lhs, variables);
+    let (builder, from) = struct_place(state, builder, rhs, &ty, bindings, variables, source_info)?;
+    let (mut builder, to, _) =
+        expr::expr_as_target(state, builder, lhs, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
+    copy_memory(&mut builder, bindings, (from, to), &ty);
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check_str;
+
+    #[test]
+    fn structs_are_passed_and_returned_by_value() {
+        let source = "struct pair { int a; int b; };\n\
+                      struct big { long a; long b; long c; };\n\
+                      struct pair swap(struct pair p) {\n\
+                      \x20   struct pair q = {p.b, p.a};\n\
+                      \x20   return q;\n\
+                      }\n\
+                      struct big grow(struct big b, struct pair p) {\n\
+                      \x20   b.c = swap(p).a;\n\
+                      \x20   return b;\n\
+                      }\n\
+                      int main() {\n\
+                      \x20   struct pair p = {1, 2};\n\
+                      \x20   struct big b = {3, 4, 5};\n\
+                      \x20   b = grow(b, p);\n\
+                      \x20   return b.c;\n\
+                      }\n";
+        assert!(check_str(source).is_empty());
+
+        let mismatched = "struct a { int x; };\nstruct b { int x; };\n\
+                          int f(struct a s) { return s.x; }\n\
+                          int main() { struct b s = {1}; return f(s); }\n";
+        let error = check_str(mismatched).iter().next().cloned();
+        assert!(error.is_some_and(|error| error.message.contains("expected `struct a`")));
+        let floats = "struct v { float x; float y; };\nint f(struct v v) { return 0; }\n";
+        assert!(!check_str(floats).is_empty());
+    }
+}
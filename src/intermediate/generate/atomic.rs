@@ -0,0 +1,158 @@
+//! The `__atomic` builtins, which access memory other threads may be accessing at the same time.
+//! Every access is sequentially consistent, which is at least as strong as any memory order they
+//! can be given, so the order is evaluated and then ignored. It may be named by one of the
+//! [`MEMORY_ORDERS`] even though macros aren't replaced in the code.
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::{
+    expr, BindingCounter, BlockBuilder, ByteSize, IRGenState, SourceMetadata, Statement, Value,
+    VarE, VarError, VariableTracker,
+};
+use crate::ast;
+use crate::error::Span;
+use crate::grammar::lexer::Source;
+
+/// The names of the memory orders, as GCC predefines them, each one standing for its index
+pub const MEMORY_ORDERS: [&str; 6] = [
+    "__ATOMIC_RELAXED",
+    "__ATOMIC_CONSUME",
+    "__ATOMIC_ACQUIRE",
+    "__ATOMIC_RELEASE",
+    "__ATOMIC_ACQ_REL",
+    "__ATOMIC_SEQ_CST",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `__atomic_load_n(pointer, order)`
+    Load,
+    /// `__atomic_store_n(pointer, value, order)`, which returns `void`
+    Store,
+    /// `__atomic_fetch_add(pointer, value, order)`, whose value is what the memory held before
+    FetchAdd,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "__atomic_load_n" => Self::Load,
+            "__atomic_store_n" => Self::Store,
+            "__atomic_fetch_add" => Self::FetchAdd,
+            _ => return None,
+        })
+    }
+    const fn params(self) -> usize {
+        match self {
+            Self::Load => 2,
+            Self::Store | Self::FetchAdd => 3,
+        }
+    }
+    pub const fn returns_void(self) -> bool {
+        matches!(self, Self::Store)
+    }
+}
+
+/// The type of the memory the first argument of a builtin points to, which is the type of what
+/// the builtin gives
+pub fn accessed_type(args: &[(ast::Expr, Span)], variables: &VariableTracker) -> ast::Type {
+    args.first()
+        .and_then(|(pointer, _)| expr::pointee(&expr::expr_type(pointer, variables)))
+        .map_or(ast::Type::Int, |ty| ty.unqualified().clone())
+}
+
+fn fits_a_register(ty: &ast::Type) -> bool {
+    !ty.is_floating()
+        && !matches!(ty, ast::Type::Array { .. } | ast::Type::Struct(_))
+        && matches!(ty.size(), 4 | 8)
+}
+
+/// Compile a call to a builtin. The memory is accessed all at once, so it has to be an integer or
+/// a pointer that fits in a register: 4 or 8 bytes.
+pub fn compile_builtin<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (builtin, function): (Builtin, Source<'code>),
+    args: Vec<(ast::Expr<'code>, Span)>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    if args.len() != builtin.params() {
+        return Err(VarE::new(VarError::ArgumentCount {
+            function: function.source.into(),
+            expected: builtin.params() as u32,
+            found: args.len() as u32,
+        })
+        .with_source(function.span, source_info));
+    }
+    let operand = expr::expr_type(&args[0].0, variables);
+    let Some(ty) = expr::pointee(&operand)
+        .map(|ty| ty.unqualified().clone())
+        .filter(fits_a_register)
+    else {
+        return Err(VarE::new(VarError::AtomicOperand {
+            function: function.source.into(),
+            operand: Box::new(operand),
+        })
+        .with_source(args[0].1, source_info));
+    };
+    let byte_size = ByteSize::from(&ty);
+
+    // the arguments are evaluated left to right, the memory order last
+    let mut builder = builder;
+    let mut operands = Vec::with_capacity(args.len());
+    for (index, (arg, arg_span)) in args.into_iter().enumerate() {
+        let arg = match arg {
+            ast::Expr::Variable { name } if index == builtin.params() - 1 => {
+                memory_order(name.source, variables).map_or(arg, ast::Expr::Constant)
+            }
+            arg => arg,
+        };
+        let arg_type = expr::expr_type(&arg, variables);
+        let (mut end, value) =
+            expr::compile_expr(state, builder, arg, bindings, variables, source_info)
+                .map_err(|e| e.with_backup_source(arg_span, source_info))?;
+        let binding = bindings.next_binding();
+        end.assign(binding, value);
+        // the value stored or added is converted to the type of the memory
+        let binding = if index == 1 && builtin != Builtin::Load {
+            expr::convert(&mut end, bindings, binding, &arg_type, &ty)
+        } else {
+            binding
+        };
+        operands.push(binding);
+        builder = end;
+    }
+    let mem_binding = operands[0];
+    let value = match builtin {
+        Builtin::Load => Value::AtomicLoad {
+            mem_binding,
+            byte_size,
+        },
+        Builtin::Store => {
+            builder.push(Statement::AtomicStore {
+                mem_binding,
+                binding: operands[1],
+                byte_size,
+            });
+            Value::Undefined
+        }
+        Builtin::FetchAdd => Value::AtomicFetchAdd {
+            mem_binding,
+            binding: operands[1],
+            byte_size,
+        },
+    };
+    Ok((builder, value))
+}
+
+/// The value of a memory order named as GCC predefines it, unless the name is declared as
+/// something else
+fn memory_order(name: &str, variables: &VariableTracker) -> Option<i32> {
+    if variables.lookup(name).is_some() {
+        return None;
+    }
+    let order = MEMORY_ORDERS.iter().position(|order| *order == name)?;
+    Some(order as i32)
+}
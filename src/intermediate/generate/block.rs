@@ -10,20 +10,28 @@ pub fn compile_block<'code>(
     block_depth: usize,
     source_info: &SourceMetadata,
 ) -> Result<BlockBuilder, VarE> {
-    // clean the variables for now
-    variables.variables_at_depth(block_depth).clear();
+    // make sure the depth exists, it may already hold the function's parameters
+    variables.variables_at_depth(block_depth);
 
     for (st, st_span) in statements {
-        builder = statement::compile_statement(
-            state,
-            builder,
-            bindings,
-            st,
-            variables,
-            block_depth,
-            source_info,
-        )
-        .map_err(|e| e.with_backup_source(st_span, source_info))?;
+        builder = bindings
+            .compile_from(st_span, |bindings| {
+                statement::compile_statement(
+                    state,
+                    builder,
+                    bindings,
+                    st,
+                    variables,
+                    block_depth,
+                    source_info,
+                )
+            })
+            .map_err(|e| e.with_backup_source(st_span, source_info))?;
+        if let Some(budget) = bindings.over_budget() {
+            return Err(VarE::new(VarError::OverBudget(budget)).with_source(st_span, source_info));
+        }
     }
+    // the variables go out of scope
+    variables.variables_at_depth(block_depth).clear();
     Ok(builder)
 }
@@ -1,14 +1,222 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
 use super::{
-    statement, Binding, BindingCounter, BlockBuilder, Branch, ByteSize, Condition, IRGenState,
-    PhiDescriptor, Source, SourceMetadata, Value, VarE, VarError, VariableTracker,
+    aggregate, atomic, statement, Binding, BindingCounter, BlockBuilder, Branch, ByteSize,
+    Condition, Declaration, GlobalVariable, IRGenState, PhiDescriptor, ReadOnlyData,
+    RegisterVariable, Source, SourceMetadata, Symbol, Value, VarE, VarError, VarW, VarWarning,
+    Variable, VariableTracker,
 };
 use crate::ast;
+use crate::codegen::assembly::BitSize;
+use crate::codegen::target;
+use crate::error::Span;
+use crate::intermediate::{Callee, FloatOp};
+
+/// Compile an expression, reusing the binding of the same pure expression if the statement has
+/// already computed it in this block
+pub fn compile_expr<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    expr: ast::Expr<'code>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    // the left operands of a chain nest as deep as it's long, so they're taken apart here and
+    // the chain is compiled from its innermost operation out. One that was already computed is
+    // reused as a whole.
+    let mut chain = Vec::new();
+    let mut expr = expr;
+    let mut key = statement_key(&expr, variables);
+    while key
+        .as_ref()
+        .is_none_or(|key| !builder.reused.contains_key(key))
+    {
+        match expr {
+            ast::Expr::Binary {
+                operator,
+                lhs: (lhs, lhs_span),
+                rhs,
+            } if operator.chains()
+                && matches!(&*lhs, ast::Expr::Binary { operator, .. } if operator.chains()) =>
+            {
+                check_parentheses(
+                    state,
+                    operator,
+                    [(&lhs, lhs_span), (&rhs.0, rhs.1)],
+                    source_info,
+                );
+                chain.push(ChainLink {
+                    operator,
+                    rhs: (*rhs.0, rhs.1),
+                    lhs_span,
+                    key,
+                });
+                expr = *lhs;
+                key = statement_key(&expr, variables);
+            }
+            innermost => {
+                expr = innermost;
+                break;
+            }
+        }
+    }
+    let Some(innermost) = chain.last() else {
+        return compile_reusing(state, builder, expr, key, bindings, variables, source_info);
+    };
+    let mut lhs_type = expr_type(&expr, variables);
+    let innermost_span = innermost.lhs_span;
+    let (mut builder, mut value) =
+        compile_reusing(state, builder, expr, key, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(innermost_span, source_info))?;
+    // each operation is the left operand of the one before it
+    let spans: Vec<_> = chain.iter().map(|link| link.lhs_span).collect();
+    for (index, link) in chain.into_iter().enumerate().rev() {
+        let ChainLink {
+            operator, rhs, key, ..
+        } = link;
+        let lhs = bindings.next_binding();
+        builder.assign(lhs, value);
+        let ty = operation_type(operator, &lhs_type, &expr_type(&rhs.0, variables));
+        let result = compile_operation(
+            state,
+            builder,
+            (operator, lhs, &lhs_type),
+            rhs,
+            bindings,
+            variables,
+            source_info,
+        );
+        (builder, value) = match index.checked_sub(1) {
+            Some(outer) => result.map_err(|e| e.with_backup_source(spans[outer], source_info)),
+            None => result,
+        }?;
+        if let Some(key) = key {
+            let binding = bindings.next_binding();
+            builder.assign(binding, value);
+            builder.reused.insert(key, binding);
+            value = Value::Binding(binding);
+        }
+        lhs_type = ty;
+    }
+    Ok((builder, value))
+}
+
+/// An operation of a chain, whose left operand is compiled before it
+struct ChainLink<'code> {
+    operator: ast::BinaryOp,
+    rhs: (ast::Expr<'code>, Span),
+    lhs_span: Span,
+    /// the [`reuse_key`] of the operation, if its value can be reused
+    key: Option<String>,
+}
+
+/// The key the value of an expression is reused by within a statement, if it's worth reusing
+fn statement_key(expr: &ast::Expr, variables: &VariableTracker) -> Option<String> {
+    match expr {
+        ast::Expr::Binary { operator, .. } if operator.chains() => reuse_key(expr, variables),
+        _ => None,
+    }
+}
+
+/// Compile an expression, or reuse the binding already computed for its `key`
+fn compile_reusing<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    expr: ast::Expr<'code>,
+    key: Option<String>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    let Some(key) = key else {
+        return compile_expr_once(state, builder, expr, bindings, variables, source_info);
+    };
+    if let Some(&binding) = builder.reused.get(&key) {
+        // the repetition is still compiled for the names it refers to and its diagnostics, but
+        // its code is thrown away
+        let scratch = BlockBuilder::new(builder.block_id);
+        compile_expr_once(state, scratch, expr, bindings, variables, source_info)?;
+        return Ok((builder, Value::Binding(binding)));
+    }
+    let (mut builder, value) =
+        compile_expr_once(state, builder, expr, bindings, variables, source_info)?;
+    let binding = bindings.next_binding();
+    builder.assign(binding, value);
+    builder.reused.insert(key, binding);
+    Ok((builder, Value::Binding(binding)))
+}
+
+/// How many operators and operands an expression may have to get a [`reuse_key`]. The key of
+/// each operation of a long chain would take as long to make as the chain.
+const REUSE_KEY_NODES: usize = 64;
+
+/// A key that's the same for two expressions exactly when they're syntactically the same and
+/// compute the same value without side effects, as long as no memory is written in between. The
+/// variables are told apart by their memory, so that a shadowed name is another key.
+pub fn reuse_key(expr: &ast::Expr, variables: &VariableTracker) -> Option<String> {
+    let mut nodes = REUSE_KEY_NODES;
+    reuse_key_within(expr, variables, &mut nodes)
+}
+
+fn reuse_key_within(
+    expr: &ast::Expr,
+    variables: &VariableTracker,
+    nodes: &mut usize,
+) -> Option<String> {
+    *nodes = nodes.checked_sub(1)?;
+    let mut reuse_key = |expr| reuse_key_within(expr, variables, nodes);
+    Some(match expr {
+        ast::Expr::Parenthesized { expr: (expr, _) } => reuse_key(expr)?,
+        ast::Expr::Variable { name } => match variables.lookup(name.source)? {
+            Symbol::Variable(variable)
+                if !variable.ty.is_volatile()
+                    && !matches!(
+                        variable.ty.unqualified(),
+                        ast::Type::Array { .. } | ast::Type::Struct(_)
+                    ) =>
+            {
+                format!("v{}", variable.memory.0)
+            }
+            Symbol::Register(variable) => format!("r{}", variable.register),
+            Symbol::Constant { value, .. } => format!("{value}"),
+            _ => return None,
+        },
+        ast::Expr::Constant(constant) => format!("{constant}"),
+        ast::Expr::LongConstant(constant) => format!("{constant}l"),
+        ast::Expr::UnsignedConstant(constant) => format!("{constant}u"),
+        ast::Expr::UnsignedLongConstant(constant) => format!("{constant}ul"),
+        ast::Expr::FloatConstant { bits, ty } => format!("{bits}{ty:?}"),
+        ast::Expr::Unary {
+            operator:
+                operator @ (ast::UnaryOp::Negate | ast::UnaryOp::BitNot | ast::UnaryOp::LogicNot),
+            expr: (expr, _),
+        } => format!("{operator:?}({})", reuse_key(expr)?),
+        ast::Expr::Binary {
+            operator:
+                operator @ (ast::BinaryOp::Arithmetic(_)
+                | ast::BinaryOp::Bit(_)
+                | ast::BinaryOp::Relational(_)),
+            lhs: (lhs, _),
+            rhs: (rhs, _),
+        } => format!("{operator:?}({}, {})", reuse_key(lhs)?, reuse_key(rhs)?),
+        ast::Expr::Cast {
+            target,
+            expr: (expr, _),
+        } => format!("({target:?}) {}", reuse_key(expr)?),
+        _ => return None,
+    })
+}
 
 // TODO: consider refactoring logic expressions to use `merge_branches` or even a new utility that
 // spits out a phi node (from ternary expression).
 // XXX: consider moving from `Value` to `Binding` due to codegen not having to make any
 // optimization decisions
-pub fn compile_expr<'code>(
+fn compile_expr_once<'code>(
     state: &mut IRGenState,
     builder: BlockBuilder,
     expr: ast::Expr<'code>,
@@ -17,41 +225,240 @@ pub fn compile_expr<'code>(
     source_info: &SourceMetadata<'code>,
 ) -> Result<(BlockBuilder, Value), VarE> {
     match expr {
-        // TODO: use a different strategy if the type is a structure
-        ast::Expr::Variable {
-            name: Source { source: name, .. },
+        ast::Expr::Parenthesized {
+            expr: (expr, expr_span),
+        } => compile_expr(state, builder, *expr, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(expr_span, source_info)),
+        ast::Expr::Variable { name: source } => {
+            let name = source.source;
+            let symbol = variables.lookup(name);
+            state.symbols.refer(source, symbol);
+            let mut builder = builder;
+            let (memory, ty) = match symbol {
+                Some(Symbol::Variable(variable)) => (variable.memory, &variable.ty),
+                Some(Symbol::Global(global)) => {
+                    (global_memory(&mut builder, bindings, name), &global.ty)
+                }
+                Some(Symbol::Register(variable)) => {
+                    return Ok((builder, Value::GlobalRegister(variable.register)))
+                }
+                Some(Symbol::Constant { value, .. }) => {
+                    return Ok((builder, Value::Constant((*value).into())))
+                }
+                Some(Symbol::Function(_)) => {
+                    return Ok((builder, Value::FunctionAddress(name.to_string())))
+                }
+                None => return Err(VarE::new(VarError::UnknownVariable(name.to_string()))),
+            };
+            match ty.unqualified() {
+                // an array used as a value is a pointer to its first element, which a global
+                // variable's memory already is
+                ast::Type::Array { .. } if matches!(symbol, Some(Symbol::Global(_))) => {
+                    Ok((builder, Value::Binding(memory)))
+                }
+                ast::Type::Array { .. } => Ok((
+                    builder,
+                    Value::Address {
+                        mem_binding: memory,
+                    },
+                )),
+                ast::Type::Struct(_) => Err(VarE::new(VarError::StructValue(ty.clone()))),
+                _ => Ok((
+                    builder,
+                    Value::Load {
+                        mem_binding: memory,
+                        byte_size: ty.into(),
+                        volatile: ty.is_volatile(),
+                    },
+                )),
+            }
+        }
+        // the function a pointer points to is a pointer to it again as a value
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            expr: (pointer, pointer_span),
+        } if matches!(
+            expr_type(&pointer, variables),
+            ast::Type::FunctionPointer(_)
+        ) =>
+        {
+            compile_expr(state, builder, *pointer, bindings, variables, source_info)
+                .map_err(|e| e.with_backup_source(pointer_span, source_info))
+        }
+        ast::Expr::Index { .. }
+        | ast::Expr::Member { .. }
+        | ast::Expr::Arrow { .. }
+        | ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            ..
         } => {
-            let (variable_mem, variable_size) = *variables
-                .get(name)
-                .ok_or_else(|| VarE::new(VarError::UnknownVariable(name.to_string())))?;
-            Ok((
+            let (builder, address, ty) =
+                expr_as_target(state, builder, expr, bindings, variables, source_info)?;
+            match ty.unqualified() {
+                // the address of an array is already the pointer it decays into
+                ast::Type::Array { .. } => Ok((builder, Value::Binding(address))),
+                ast::Type::Struct(_) => Err(VarE::new(VarError::StructValue(ty))),
+                _ => Ok((
+                    builder,
+                    Value::Load {
+                        mem_binding: address,
+                        byte_size: (&ty).into(),
+                        volatile: ty.is_volatile(),
+                    },
+                )),
+            }
+        }
+        ast::Expr::Call { function, args } => {
+            if returns_void(state, &ast::Expr::Variable { name: function }, variables) {
+                return Err(VarE::new(VarError::VoidValue(function.source.to_string()))
+                    .with_source(function.span, source_info));
+            }
+            let callee = ast::Expr::Variable { name: function };
+            if let ty @ ast::Type::Struct(_) = return_type(&callee, variables) {
+                return Err(VarE::new(VarError::StructValue(ty)));
+            }
+            compile_call(
+                state,
+                builder,
+                function,
+                args,
+                bindings,
+                variables,
+                source_info,
+            )
+        }
+        ast::Expr::CallPointer {
+            pointer: (pointer, pointer_span),
+            args,
+        } => {
+            if returns_void(state, &pointer, variables) {
+                let callee = &source_info.input()[pointer_span.as_range()];
+                return Err(VarE::new(VarError::VoidValue(callee.to_string()))
+                    .with_source(pointer_span, source_info));
+            }
+            if let ty @ ast::Type::Struct(_) = return_type(&pointer, variables) {
+                return Err(VarE::new(VarError::StructValue(ty)));
+            }
+            compile_pointer_call(
+                state,
                 builder,
-                Value::Load {
-                    mem_binding: variable_mem,
-                    byte_size: variable_size,
+                (*pointer, pointer_span),
+                args,
+                bindings,
+                variables,
+                source_info,
+            )
+        }
+        ast::Expr::Cast {
+            target,
+            expr: (expr, expr_span),
+        } => {
+            // the value of a cast is never volatile, whatever type it's cast to
+            let target = target.unqualified().clone();
+            let from = expr_type(&expr, variables);
+            let (mut end, expr_value) =
+                compile_expr(state, builder, *expr, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(expr_span, source_info))?;
+            // conversions to or from a floating type take an instruction of their own, after which
+            // integers narrower than an `int` are narrowed as usual
+            let (from, expr_value) = if from.is_floating() || target.is_floating() {
+                let floating = if from.is_floating() { &from } else { &target };
+                if is_address(&from) || is_address(&target) {
+                    return Err(VarE::new(VarError::FloatPointerCast(floating.clone())));
+                }
+                let to = match target {
+                    ast::Type::Bool
+                    | ast::Type::UnsignedInt
+                    | ast::Type::Long
+                    | ast::Type::UnsignedLong
+                    | ast::Type::Float
+                    | ast::Type::Double => target.clone(),
+                    _ => ast::Type::Int,
+                };
+                let expr_target = bindings.next_binding();
+                end.assign(expr_target, expr_value);
+                let converted = convert(&mut end, bindings, expr_target, &from, &to);
+                (to, Value::Binding(converted))
+            } else {
+                (from, expr_value)
+            };
+            match target {
+                // integers only fill the lower half of a register
+                ast::Type::Pointer(_) | ast::Type::FunctionPointer(_) if !is_address(&from) => {
+                    Err(VarE::new(VarError::IntegerToPointer(from)))
+                }
+                ast::Type::Pointer(_) | ast::Type::FunctionPointer(_) => Ok((end, expr_value)),
+                // values are always computed as (at least) `int`, and narrowing a `long` leaves
+                // its upper half alone, so there's nothing to do
+                ast::Type::Int | ast::Type::UnsignedInt => Ok((end, expr_value)),
+                ast::Type::Float | ast::Type::Double => Ok((end, expr_value)),
+                ast::Type::Bool | ast::Type::Long | ast::Type::UnsignedLong => {
+                    let expr_target = bindings.next_binding();
+                    end.assign(expr_target, expr_value);
+                    let converted = convert(&mut end, bindings, expr_target, &from, &target);
+                    Ok((end, Value::Binding(converted)))
+                }
+                ast::Type::Volatile(_) => unreachable!("the qualifiers were removed"),
+                ast::Type::Array { .. } => unreachable!("casts to arrays can't be parsed"),
+                ast::Type::Struct(_) => Err(VarE::new(VarError::StructValue(target))),
+                ast::Type::Char
+                | ast::Type::SignedChar
+                | ast::Type::Short
+                | ast::Type::UnsignedShort => {
+                    let expr_target = bindings.next_binding();
+                    end.assign(expr_target, expr_value);
+                    Ok((
+                        end,
+                        narrowed(expr_target, &target).expect("the type is narrower than `int`"),
+                    ))
+                }
+            }
+        }
+        ast::Expr::Constant(constant) => Ok((builder, constant.into())),
+        ast::Expr::LongConstant(constant) => Ok((builder, constant.into())),
+        // the 32 bits of an `unsigned int` are kept like those of an `int`
+        ast::Expr::UnsignedConstant(constant) => Ok((builder, (constant as i32).into())),
+        ast::Expr::UnsignedLongConstant(constant) => Ok((builder, (constant as i64).into())),
+        ast::Expr::FloatConstant { bits, ty } => Ok((
+            builder,
+            match ty.ty() {
+                // the constant is rounded to a `float` already, so this conversion is exact
+                ast::Type::Float => Value::FloatConstant {
+                    bits: (f64::from_bits(bits) as f32).to_bits().into(),
+                    bit_size: BitSize::Bit32,
                 },
-            ))
+                _ => Value::FloatConstant {
+                    bits,
+                    bit_size: BitSize::Bit64,
+                },
+            },
+        )),
+        ast::Expr::AlignOf(ty) => Ok((builder, Value::Constant(ty.align() as i64))),
+        ast::Expr::StringLiteral(contents) => {
+            // the array lives in read-only memory, and decays into a pointer to its first `char`
+            let mut bytes = contents;
+            bytes.push(0);
+            let index = state.add_read_only(ReadOnlyData { bytes, align: 1 });
+            Ok((builder, Value::ReadOnlyAddress(index)))
         }
-        ast::Expr::Constant(constant) => Ok((builder, Value::Constant(constant))),
         ast::Expr::Ternary {
             condition: (condition_expr, condition_span),
             value_true: (true_expr, true_span),
             value_false: (false_expr, false_span),
         } => {
-            let (compute_condition, cond_flag) = {
-                let (mut compute, cond_value) = compile_expr(
-                    state,
-                    builder, // continue the builder we had previously
-                    *condition_expr,
-                    bindings,
-                    variables,
-                    source_info,
-                )
-                .map_err(|e| e.with_backup_source(condition_span, source_info))?;
-                let value_binding = bindings.next_binding();
-                compute.assign(value_binding, cond_value);
-                (compute, value_binding)
-            };
+            let (compute_condition, cond_flag) = compile_condition(
+                state,
+                builder, // continue the builder we had previously
+                *condition_expr,
+                bindings,
+                variables,
+                source_info,
+            )
+            .map_err(|e| e.with_backup_source(condition_span, source_info))?;
+            // both values are converted to their common type before they're merged
+            let true_type = expr_type(&true_expr, variables);
+            let false_type = expr_type(&false_expr, variables);
+            let ty = common_type(&true_type, &false_type);
 
             let (compute_if_true, true_binding, true_head) = {
                 let builder = state.new_block();
@@ -61,6 +468,7 @@ pub fn compile_expr<'code>(
                         .map_err(|e| e.with_backup_source(true_span, source_info))?;
                 let value_binding = bindings.next_binding();
                 compute.assign(value_binding, expr_value);
+                let value_binding = convert(&mut compute, bindings, value_binding, &true_type, &ty);
                 (compute, value_binding, head)
             };
 
@@ -78,6 +486,8 @@ pub fn compile_expr<'code>(
                 .map_err(|e| e.with_backup_source(false_span, source_info))?;
                 let value_binding = bindings.next_binding();
                 compute.assign(value_binding, expr_value);
+                let value_binding =
+                    convert(&mut compute, bindings, value_binding, &false_type, &ty);
                 (compute, value_binding, head)
             };
 
@@ -108,222 +518,1945 @@ pub fn compile_expr<'code>(
                 },
             ))
         }
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::AddressOf,
+            expr: (operand, operand_span),
+        } => {
+            // the address of a function is the same pointer to it that its name is
+            if is_function_designator(&operand, variables) {
+                return compile_expr(state, builder, *operand, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(operand_span, source_info));
+            }
+            // a local variable's address is that of its memory, anything else already computes
+            // one
+            let is_variable = matches!(
+                operand.without_parens(),
+                ast::Expr::Variable { name } if variables.get(name.source).is_some()
+            );
+            let (builder, address, _) =
+                expr_as_target(state, builder, *operand, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(operand_span, source_info))?;
+            Ok((
+                builder,
+                if is_variable {
+                    Value::Address {
+                        mem_binding: address,
+                    }
+                } else {
+                    Value::Binding(address)
+                },
+            ))
+        }
         ast::Expr::Unary {
             operator,
             expr: (expr, expr_span),
         } => {
+            let ty = expr_type(&expr, variables);
+            let bit_size = bit_size(&ty);
             let expr_target = bindings.next_binding();
             let (mut end, expr_value) =
                 compile_expr(state, builder, *expr, bindings, variables, source_info)
                     .map_err(|e| e.with_backup_source(expr_span, source_info))?;
             end.assign(expr_target, expr_value);
-            Ok((
-                end,
-                match operator {
-                    ast::UnaryOp::Negate => Value::Negate {
-                        binding: expr_target,
-                    },
-                    ast::UnaryOp::BitNot => Value::FlipBits {
-                        binding: expr_target,
-                    },
-                    ast::UnaryOp::LogicNot => Value::Cmp {
-                        condition: Condition::Equals,
-                        lhs: expr_target,
-                        rhs: 0.into(),
-                    },
+            let value = match operator {
+                ast::UnaryOp::Negate if ty.is_floating() => Value::FloatNegate {
+                    binding: expr_target,
+                    bit_size,
                 },
-            ))
+                ast::UnaryOp::BitNot if ty.is_floating() => {
+                    return Err(VarE::new(VarError::FloatOperand(ty))
+                        .with_backup_source(expr_span, source_info))
+                }
+                ast::UnaryOp::LogicNot if ty.is_floating() => Value::FloatCmp {
+                    condition: Condition::Equals,
+                    lhs: expr_target,
+                    rhs: float_zero(&mut end, bindings, bit_size),
+                    bit_size,
+                },
+                ast::UnaryOp::Negate => Value::Negate {
+                    binding: expr_target,
+                    bit_size,
+                },
+                ast::UnaryOp::BitNot => Value::FlipBits {
+                    binding: expr_target,
+                    bit_size,
+                },
+                ast::UnaryOp::LogicNot => Value::Cmp {
+                    condition: Condition::Equals,
+                    lhs: expr_target,
+                    rhs: 0.into(),
+                    bit_size,
+                },
+                ast::UnaryOp::AddressOf | ast::UnaryOp::Deref => {
+                    unreachable!("handled as memory accesses")
+                }
+            };
+            Ok((end, value))
         }
         ast::Expr::Binary {
             operator,
             lhs: (lhs_expr, lhs_span),
             rhs: (rhs_expr, rhs_span),
-        } => match operator {
-            ast::BinaryOp::Arithmetic(arithmop) => {
-                // compute first lhs, then rhs
-                let (mut builder, lhs_result) =
-                    compile_expr(state, builder, *lhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
-                let lhs = bindings.next_binding();
-                builder.assign(lhs, lhs_result);
-
-                let (mut builder, rhs_result) =
-                    compile_expr(state, builder, *rhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
-                let rhs = bindings.next_binding();
-                builder.assign(rhs, rhs_result);
-                let result = compile_arithmetic(&mut builder, bindings, arithmop, lhs, rhs);
-                Ok((builder, result))
-            }
-            ast::BinaryOp::Bit(bitop) => {
-                // compute first lhs, then rhs
-                let (mut builder, lhs_result) =
-                    compile_expr(state, builder, *lhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
-                let lhs = bindings.next_binding();
-                builder.assign(lhs, lhs_result);
-
-                let (mut builder, rhs_result) =
-                    compile_expr(state, builder, *rhs_expr, bindings, variables, source_info)
+        } => {
+            check_parentheses(
+                state,
+                operator,
+                [(&lhs_expr, lhs_span), (&rhs_expr, rhs_span)],
+                source_info,
+            );
+            match operator {
+                operator @ (ast::BinaryOp::Arithmetic(_)
+                | ast::BinaryOp::Bit(_)
+                | ast::BinaryOp::Relational(_)) => {
+                    let lhs_type = expr_type(&lhs_expr, variables);
+                    // compute first lhs, then rhs
+                    let (mut builder, lhs_result) =
+                        compile_expr(state, builder, *lhs_expr, bindings, variables, source_info)
+                            .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
+                    let lhs = bindings.next_binding();
+                    builder.assign(lhs, lhs_result);
+                    compile_operation(
+                        state,
+                        builder,
+                        (operator, lhs, &lhs_type),
+                        (*rhs_expr, rhs_span),
+                        bindings,
+                        variables,
+                        source_info,
+                    )
+                }
+                ast::BinaryOp::Logic(logicop) => {
+                    // lhs is going to be computed straight ahead
+                    let (mut lhs_builder, lhs) = compile_condition(
+                        state,
+                        builder,
+                        *lhs_expr,
+                        bindings,
+                        variables,
+                        source_info,
+                    )
+                    .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
+
+                    // compile another block in which rhs is computed
+                    let (rhs_builder, compute_rhs, rhs) = {
+                        let block = state.new_block();
+                        let start = block.block(); // make sure that lhs jumps to the *start* of rhs's computation
+                        let (rhs_block, rhs) = compile_condition(
+                            state,
+                            block,
+                            *rhs_expr,
+                            bindings,
+                            variables,
+                            source_info,
+                        )
                         .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
 
-                let rhs = bindings.next_binding();
-                builder.assign(rhs, rhs_result);
+                        (rhs_block, start, rhs)
+                    };
+                    // create the new block that will start with a phi node to merge the two branches
+                    let mut end_builder = state.new_block();
+                    // finish rhs by telling it to jump directly to the end
+                    let rhs_block = rhs_builder.finish_block(
+                        state,
+                        Branch::Unconditional {
+                            target: end_builder.block(),
+                        },
+                    );
+                    // finish lhs by adding the comparison to zero and the conditional branch
+                    let bail_condition = match logicop {
+                        ast::LogicOp::And => Condition::Equals,
+                        ast::LogicOp::Or => Condition::NotEquals,
+                    };
+                    let flag = bindings.next_binding();
+                    lhs_builder.assign(
+                        flag,
+                        Value::Cmp {
+                            condition: bail_condition,
+                            lhs,
+                            rhs: 0.into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    );
+                    let lhs_block = lhs_builder.finish_block(
+                        state,
+                        Branch::Conditional {
+                            flag,
+                            target_true: end_builder.block(),
+                            target_false: compute_rhs,
+                        },
+                    );
 
-                let result = compile_bitop(bitop, lhs, rhs);
-                Ok((builder, result))
+                    let end = bindings.next_binding();
+                    end_builder.assign(
+                        end,
+                        Value::Phi {
+                            nodes: vec![
+                                PhiDescriptor {
+                                    value: lhs,
+                                    block_from: lhs_block,
+                                },
+                                PhiDescriptor {
+                                    value: rhs,
+                                    block_from: rhs_block,
+                                },
+                            ],
+                        },
+                    );
+
+                    // the operand the value comes from may be any non-zero value
+                    Ok((
+                        end_builder,
+                        Value::Cmp {
+                            condition: Condition::NotEquals,
+                            lhs: end,
+                            rhs: 0.into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    ))
+                }
+                ast::BinaryOp::Assignment { op } => {
+                    match op {
+                        None => check_narrowing(
+                            state,
+                            &expr_type(&lhs_expr, variables),
+                            &rhs_expr,
+                            rhs_span,
+                            variables,
+                            source_info,
+                        ),
+                        Some(ast::AssignmentEnabledOp::Bit(
+                            ast::BitOp::LeftShift | ast::BitOp::RightShift,
+                        )) => check_shift_count(
+                            state,
+                            &expr_type(&lhs_expr, variables),
+                            &rhs_expr,
+                            rhs_span,
+                            variables,
+                            source_info,
+                        ),
+                        Some(_) => (),
+                    }
+                    let rhs_type = expr_type(&rhs_expr, variables);
+                    // compute rhs
+                    let (mut builder, rhs_value) =
+                        compile_expr(state, builder, *rhs_expr, bindings, variables, source_info)
+                            .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
+
+                    let rhs = bindings.next_binding();
+                    builder.assign(rhs, rhs_value);
+
+                    // global register variables are written to their register instead of memory
+                    let register = match &*lhs_expr {
+                        ast::Expr::Variable { name } => {
+                            let register = variables.get_register(name.source);
+                            if register.is_some() {
+                                state.symbols.refer(*name, variables.lookup(name.source));
+                            }
+                            register
+                        }
+                        _ => None,
+                    };
+                    let (mut builder, lhs_target, lhs_object) = match register {
+                        Some(variable) => (
+                            builder,
+                            AssignTarget::Register(variable.register),
+                            variable.ty.clone(),
+                        ),
+                        None => {
+                            let (builder, lhs_mem, lhs_type) = expr_as_target(
+                                state,
+                                builder,
+                                *lhs_expr,
+                                bindings,
+                                variables,
+                                source_info,
+                            )
+                            .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
+                            (builder, AssignTarget::Memory(lhs_mem), lhs_type)
+                        }
+                    };
+                    // the object is read and written with its qualifiers, the values have none
+                    let lhs_type = lhs_object.unqualified().clone();
+                    match lhs_type {
+                        ast::Type::Array { .. } => {
+                            return Err(VarE::new(VarError::NotAssignable)
+                                .with_backup_source(lhs_span, source_info))
+                        }
+                        ast::Type::Struct(_) => {
+                            return Err(VarE::new(VarError::StructValue(lhs_type))
+                                .with_backup_source(lhs_span, source_info))
+                        }
+                        _ => (),
+                    }
+                    let result_binding = if let Some(assignment_enabled) = op {
+                        // 1. read the memory
+                        let lhs = bindings.next_binding();
+                        lhs_target.read(&mut builder, lhs, &lhs_object);
+                        // 2. Compute the value. It's at least as wide as lhs, and storing it narrows it
+                        // back for free, unless it has to be normalized into a `_Bool` first.
+                        let value_type = match assignment_enabled {
+                            ast::AssignmentEnabledOp::Bit(
+                                ast::BitOp::LeftShift | ast::BitOp::RightShift,
+                            ) => promoted(&lhs_type),
+                            _ => common_type(&lhs_type, &rhs_type),
+                        };
+                        let value = match assignment_enabled {
+                            ast::AssignmentEnabledOp::Arithmetic(arithmop) => {
+                                compile_arithmetic_operands(
+                                    &mut builder,
+                                    bindings,
+                                    arithmop,
+                                    (lhs, &lhs_type),
+                                    (rhs, &rhs_type),
+                                )?
+                            }
+                            ast::AssignmentEnabledOp::Bit(bitop) => compile_bitop_operands(
+                                &mut builder,
+                                bindings,
+                                bitop,
+                                (lhs, &lhs_type),
+                                (rhs, &rhs_type),
+                            )?,
+                        };
+                        let result = bindings.next_binding();
+                        builder.assign(result, value);
+                        convert(&mut builder, bindings, result, &value_type, &lhs_type)
+                    } else {
+                        convert(&mut builder, bindings, rhs, &rhs_type, &lhs_type)
+                    };
+                    lhs_target.write(&mut builder, result_binding, &lhs_object);
+                    // the value of the assignment is the one stored, which storing narrowed
+                    let value = narrowed(result_binding, &lhs_type)
+                        .unwrap_or(Value::Binding(result_binding));
+                    Ok((builder, value))
+                }
             }
-            ast::BinaryOp::Relational(relational) => {
-                // compute first lhs, then rhs
-                let (mut builder, lhs_result) =
-                    compile_expr(state, builder, *lhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
+        }
+    }
+}
 
-                let lhs = bindings.next_binding();
-                builder.assign(lhs, lhs_result);
+/// Compile the right operand of an arithmetic, bitwise or relational operation and the operation
+/// itself, once its left operand is in `lhs`
+fn compile_operation<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (operator, lhs, lhs_type): (ast::BinaryOp, Binding, &ast::Type),
+    (rhs_expr, rhs_span): (ast::Expr<'code>, Span),
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    let rhs_type = expr_type(&rhs_expr, variables);
+    let mut builder = builder;
+    let lhs = match operator {
+        ast::BinaryOp::Bit(ast::BitOp::LeftShift | ast::BitOp::RightShift) => {
+            check_shift_count(state, lhs_type, &rhs_expr, rhs_span, variables, source_info);
+            lhs
+        }
+        ast::BinaryOp::Relational(_) => {
+            let ty = common_type(lhs_type, &rhs_type);
+            convert(&mut builder, bindings, lhs, lhs_type, &ty)
+        }
+        _ => lhs,
+    };
+    let (mut builder, rhs_result) =
+        compile_expr(state, builder, rhs_expr, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
+    let rhs = bindings.next_binding();
+    builder.assign(rhs, rhs_result);
+    let result = match operator {
+        ast::BinaryOp::Arithmetic(arithmop) => compile_arithmetic_operands(
+            &mut builder,
+            bindings,
+            arithmop,
+            (lhs, lhs_type),
+            (rhs, &rhs_type),
+        )?,
+        ast::BinaryOp::Bit(bitop) => compile_bitop_operands(
+            &mut builder,
+            bindings,
+            bitop,
+            (lhs, lhs_type),
+            (rhs, &rhs_type),
+        )?,
+        ast::BinaryOp::Relational(relational) => {
+            let ty = common_type(lhs_type, &rhs_type);
+            let rhs = convert(&mut builder, bindings, rhs, &rhs_type, &ty);
+            relational_as_value(relational, lhs, rhs, &ty)
+        }
+        ast::BinaryOp::Logic(_) | ast::BinaryOp::Assignment { .. } => {
+            unreachable!("only the operators of a chain are compiled as operations")
+        }
+    };
+    Ok((builder, result))
+}
 
-                let (mut builder, rhs_result) =
-                    compile_expr(state, builder, *rhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
-                let rhs = bindings.next_binding();
-                builder.assign(rhs, rhs_result);
-                let result = relational_as_value(relational, lhs, rhs);
-                Ok((builder, result))
+/// Compile a call to a function, whose value is `void` if the function returns it. Only a call
+/// whose value is discarded may do that. A call returning a struct writes it to memory, and its
+/// value is that memory.
+pub fn compile_call<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    Source {
+        source: function,
+        span: function_span,
+    }: Source<'code>,
+    args: Vec<(ast::Expr<'code>, Span)>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    // a function pointer variable is called through
+    if let Some(Symbol::Variable(_) | Symbol::Global(_) | Symbol::Register(_)) =
+        variables.lookup(function)
+    {
+        let name = Source {
+            source: function,
+            span: function_span,
+        };
+        return compile_pointer_call(
+            state,
+            builder,
+            (ast::Expr::Variable { name }, function_span),
+            args,
+            bindings,
+            variables,
+            source_info,
+        );
+    }
+    if let Some(builtin) = atomic::Builtin::from_name(function) {
+        let function = Source {
+            source: function,
+            span: function_span,
+        };
+        return atomic::compile_builtin(
+            state,
+            builder,
+            (builtin, function),
+            args,
+            bindings,
+            variables,
+            source_info,
+        );
+    }
+    state.symbols.refer_function(Source {
+        source: function,
+        span: function_span,
+    });
+    if args.len() > super::MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyArguments(function.to_string())));
+    }
+    if let Some(&Declaration {
+        params: Some(expected),
+        variadic,
+        ..
+    }) = state.declared_functions.get(function)
+    {
+        if variadic && args.len() < expected {
+            return Err(VarE::new(VarError::VariadicArgumentCount {
+                function: function.into(),
+                expected: expected as u32,
+                found: args.len() as u32,
+            })
+            .with_source(function_span, source_info));
+        }
+        if !variadic && expected != args.len() {
+            return Err(VarE::new(VarError::ArgumentCount {
+                function: function.into(),
+                expected: expected as u32,
+                found: args.len() as u32,
+            })
+            .with_source(function_span, source_info));
+        }
+    }
+    if !state.declared_functions.contains_key(function) {
+        let function = function.to_string();
+        let arguments = args.len();
+        if state.standard >= ast::Standard::C99 {
+            return Err(VarE::new(VarError::ImplicitDeclaration {
+                function,
+                arguments,
+            })
+            .with_source(function_span, source_info));
+        }
+        if state.warning_options.implicit_function_declaration {
+            state.warn(
+                VarW::new(VarWarning::ImplicitDeclaration {
+                    function: function.clone(),
+                    arguments,
+                })
+                .with_source(function_span, source_info),
+            );
+        }
+        // an implicit declaration doesn't specify the parameters, and returns an `int`
+        state.declared_functions.insert(
+            function,
+            Declaration {
+                params: None,
+                variadic: false,
+                returns_void: false,
+            },
+        );
+    }
+    // the arguments that no parameter describes, past the named ones of a variadic function or
+    // all of those to a function without a prototype, go through the default argument
+    // promotions. On AArch64 they're passed in the same registers as any other arguments.
+    let described = match state.declared_functions[function] {
+        Declaration {
+            params: Some(params),
+            variadic: true,
+            ..
+        } => params,
+        Declaration { params: None, .. } => 0,
+        Declaration { .. } => args.len(),
+    };
+    let (params, return_type) = match variables.lookup(function) {
+        Some(Symbol::Function(function_type)) => (
+            function_type.params.clone().unwrap_or_default(),
+            function_type.return_type.clone(),
+        ),
+        _ => (Vec::new(), None),
+    };
+    // arguments are evaluated left to right
+    let mut builder = builder;
+    let mut arg_bindings = Vec::with_capacity(args.len());
+    for (index, (arg, arg_span)) in args.into_iter().enumerate() {
+        let arg_type = expr_type(&arg, variables);
+        if let ty @ ast::Type::Struct(_) = params.get(index).unwrap_or(&arg_type) {
+            let (end, words) = aggregate::pass_struct(
+                state,
+                builder,
+                (arg, arg_span),
+                ty,
+                bindings,
+                variables,
+                source_info,
+            )?;
+            arg_bindings.extend(words);
+            builder = end;
+            continue;
+        }
+        let (mut end, arg_value) =
+            compile_expr(state, builder, arg, bindings, variables, source_info)
+                .map_err(|e| e.with_backup_source(arg_span, source_info))?;
+        let arg_binding = bindings.next_binding();
+        end.assign(arg_binding, arg_value);
+        // narrower integers are already computed as `int`s
+        let arg_binding = if index >= described && arg_type == ast::Type::Float {
+            convert(
+                &mut end,
+                bindings,
+                arg_binding,
+                &ast::Type::Float,
+                &ast::Type::Double,
+            )
+        } else {
+            arg_binding
+        };
+        arg_bindings.push(arg_binding);
+        builder = end;
+    }
+    if arg_bindings.len() > super::MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyArguments(function.to_string())));
+    }
+    let callee = Callee::Function(function.to_string());
+    Ok(call_value(
+        builder,
+        callee,
+        arg_bindings,
+        return_type,
+        bindings,
+    ))
+}
+
+/// Compile a call through a function pointer, whose value is `void` if the function returns it.
+/// The pointer is computed before the arguments, which are converted to the types of the
+/// parameters the pointer's type gives. A call returning a struct is like [`compile_call`]'s.
+pub fn compile_pointer_call<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (pointer, pointer_span): (ast::Expr<'code>, Span),
+    args: Vec<(ast::Expr<'code>, Span)>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Value), VarE> {
+    let callee = &source_info.input()[pointer_span.as_range()];
+    let function_type = match expr_type(&pointer, variables) {
+        ast::Type::FunctionPointer(function_type) => *function_type,
+        other => {
+            return Err(
+                VarE::new(VarError::NotAFunction(other)).with_source(pointer_span, source_info)
+            )
+        }
+    };
+    if args.len() > super::MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyArguments(callee.to_string()))
+            .with_source(pointer_span, source_info));
+    }
+    if let Some(return_type) = &function_type.return_type {
+        aggregate::check_passable(return_type)
+            .map_err(|e| e.with_source(pointer_span, source_info))?;
+    }
+    let params = function_type.params.unwrap_or_default();
+    if function_type.variadic && args.len() < params.len() {
+        return Err(VarE::new(VarError::VariadicArgumentCount {
+            function: callee.into(),
+            expected: params.len() as u32,
+            found: args.len() as u32,
+        })
+        .with_source(pointer_span, source_info));
+    }
+    if !function_type.variadic && !params.is_empty() && params.len() != args.len() {
+        return Err(VarE::new(VarError::ArgumentCount {
+            function: callee.into(),
+            expected: params.len() as u32,
+            found: args.len() as u32,
+        })
+        .with_source(pointer_span, source_info));
+    }
+    let (mut builder, pointer_value) =
+        compile_expr(state, builder, pointer, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(pointer_span, source_info))?;
+    let pointer = bindings.next_binding();
+    builder.assign(pointer, pointer_value);
+    let mut arg_bindings = Vec::with_capacity(args.len());
+    for (index, (arg, arg_span)) in args.into_iter().enumerate() {
+        let arg_type = expr_type(&arg, variables);
+        if let ty @ ast::Type::Struct(_) = params.get(index).unwrap_or(&arg_type) {
+            let (end, words) = aggregate::pass_struct(
+                state,
+                builder,
+                (arg, arg_span),
+                ty,
+                bindings,
+                variables,
+                source_info,
+            )?;
+            arg_bindings.extend(words);
+            builder = end;
+            continue;
+        }
+        let (mut end, arg_value) =
+            compile_expr(state, builder, arg, bindings, variables, source_info)
+                .map_err(|e| e.with_backup_source(arg_span, source_info))?;
+        let arg_binding = bindings.next_binding();
+        end.assign(arg_binding, arg_value);
+        // the arguments no parameter describes go through the default argument promotions
+        let param_type = match params.get(index) {
+            Some(param_type) => param_type,
+            None if arg_type == ast::Type::Float => &ast::Type::Double,
+            None => &arg_type,
+        };
+        arg_bindings.push(convert(
+            &mut end,
+            bindings,
+            arg_binding,
+            &arg_type,
+            param_type,
+        ));
+        builder = end;
+    }
+    if arg_bindings.len() > super::MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyArguments(callee.to_string()))
+            .with_source(pointer_span, source_info));
+    }
+    let callee = Callee::Pointer(pointer);
+    Ok(call_value(
+        builder,
+        callee,
+        arg_bindings,
+        function_type.return_type,
+        bindings,
+    ))
+}
+
+/// The value of a call with its arguments computed. A struct that's returned is written to a
+/// temporary, padded to whole words in case it comes back in registers, a floating-point value
+/// comes back in `v0`, and an integer narrower than an `int` is narrowed again, as the bits of
+/// `w0` beyond it are left unspecified.
+fn call_value(
+    mut builder: BlockBuilder,
+    callee: Callee,
+    args: Vec<Binding>,
+    return_type: Option<ast::Type>,
+    bindings: &mut BindingCounter,
+) -> (BlockBuilder, Value) {
+    let value = match (callee, return_type) {
+        (callee, Some(ty @ ast::Type::Struct(_))) => Value::CallStruct {
+            callee,
+            args,
+            result: aggregate::register_memory(&mut builder, bindings, &ty),
+            size: ty.size(),
+        },
+        (callee, Some(ty)) if ty.is_floating() => Value::CallFloat {
+            callee,
+            args,
+            bit_size: bit_size(&ty),
+        },
+        (Callee::Function(function), return_type) => narrowed_call(
+            &mut builder,
+            bindings,
+            Value::Call { function, args },
+            return_type,
+        ),
+        (Callee::Pointer(pointer), return_type) => narrowed_call(
+            &mut builder,
+            bindings,
+            Value::CallPointer { pointer, args },
+            return_type,
+        ),
+    };
+    (builder, value)
+}
+
+/// The value of a call that returns in `x0`, narrowed to what it returns
+fn narrowed_call(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    call: Value,
+    return_type: Option<ast::Type>,
+) -> Value {
+    match return_type.as_ref().map(ast::Type::unqualified) {
+        Some(
+            ty @ (ast::Type::Char
+            | ast::Type::SignedChar
+            | ast::Type::Short
+            | ast::Type::UnsignedShort),
+        ) => {
+            let result = bindings.next_binding();
+            builder.assign(result, call);
+            narrowed(result, ty).expect("the type is narrower than `int`")
+        }
+        _ => call,
+    }
+}
+
+/// The type a call to the callee returns, as far as its value goes: what its type says it returns,
+/// or else an `int`
+fn return_type(callee: &ast::Expr, variables: &VariableTracker) -> ast::Type {
+    match expr_type(callee, variables) {
+        ast::Type::FunctionPointer(function_type) => match function_type.return_type {
+            Some(ty) => ty.unqualified().clone(),
+            None => ast::Type::Int,
+        },
+        _ => ast::Type::Int,
+    }
+}
+
+/// Whether what a call calls returns `void`: a function pointer says so in its type, and the
+/// functions that are only declared implicitly or built in do in their declaration
+fn returns_void(state: &IRGenState, callee: &ast::Expr, variables: &VariableTracker) -> bool {
+    match (expr_type(callee, variables), callee) {
+        (ast::Type::FunctionPointer(function_type), _) => function_type.return_type.is_none(),
+        (_, ast::Expr::Variable { name }) => match atomic::Builtin::from_name(name.source) {
+            Some(builtin) => builtin.returns_void(),
+            None => state
+                .declared_functions
+                .get(name.source)
+                .is_some_and(|declaration| declaration.returns_void),
+        },
+        _ => false,
+    }
+}
+
+/// The memory of a global variable, which is at its symbol's address
+fn global_memory(builder: &mut BlockBuilder, bindings: &mut BindingCounter, name: &str) -> Binding {
+    let memory = bindings.next_binding();
+    builder.assign(memory, Value::GlobalAddress(name.to_string()));
+    memory
+}
+
+/// Whether the expression names a function, or is the function a pointer points to. As a value,
+/// it's a pointer to the function, and so is its address.
+fn is_function_designator(expr: &ast::Expr, variables: &VariableTracker) -> bool {
+    match expr.without_parens() {
+        ast::Expr::Variable { name } => {
+            matches!(variables.lookup(name.source), Some(Symbol::Function(_)))
+        }
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            expr: (pointer, _),
+        } => matches!(expr_type(pointer, variables), ast::Type::FunctionPointer(_)),
+        _ => false,
+    }
+}
+
+/// Where an assignment writes its value
+enum AssignTarget {
+    Memory(Binding),
+    /// the register of a global register variable
+    Register(u8),
+}
+
+impl AssignTarget {
+    fn read(&self, builder: &mut BlockBuilder, target: Binding, ty: &ast::Type) {
+        match *self {
+            Self::Memory(memory) => builder.load_object(target, memory, ty),
+            Self::Register(register) => builder.assign(target, Value::GlobalRegister(register)),
+        }
+    }
+
+    fn write(&self, builder: &mut BlockBuilder, value: Binding, ty: &ast::Type) {
+        match *self {
+            Self::Memory(memory) => builder.store_object(value, memory, ty),
+            Self::Register(register) => builder.push(super::Statement::SetRegister {
+                register,
+                binding: value,
+            }),
+        }
+    }
+}
+
+/// The type of the value an expression evaluates to. Because of integer promotion, any operation
+/// on integers yields (at least) an `int`.
+pub fn expr_type(expr: &ast::Expr, variables: &VariableTracker) -> ast::Type {
+    qualified_type(expr, variables).unqualified().clone()
+}
+
+/// The type of an expression with its qualifiers, which only the objects it designates have:
+/// variables, and what pointers point to, including their elements and members
+pub fn qualified_type(expr: &ast::Expr, variables: &VariableTracker) -> ast::Type {
+    match expr {
+        ast::Expr::Variable {
+            name: Source { source: name, .. },
+        } => match variables.lookup(name) {
+            Some(
+                Symbol::Variable(Variable { ty, .. })
+                | Symbol::Global(GlobalVariable { ty, .. })
+                | Symbol::Register(RegisterVariable { ty, .. }),
+            ) => ty.clone(),
+            Some(Symbol::Function(function_type)) => {
+                ast::Type::FunctionPointer(Box::new(function_type.clone()))
             }
-            ast::BinaryOp::Logic(logicop) => {
-                // lhs is going to be computed straight ahead
-                let (mut lhs_builder, lhs_result) =
-                    compile_expr(state, builder, *lhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
-
-                let lhs = bindings.next_binding();
-                lhs_builder.assign(lhs, lhs_result);
-
-                // compile another block in which rhs is computed
-                let (rhs_builder, compute_rhs, rhs) = {
-                    let block = state.new_block();
-                    let start = block.block(); // make sure that lhs jumps to the *start* of rhs's computation
-                    let (mut rhs_block, rhs_value) =
-                        compile_expr(state, block, *rhs_expr, bindings, variables, source_info)
-                            .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
-                    let rhs = bindings.next_binding();
-                    rhs_block.assign(rhs, rhs_value);
+            Some(Symbol::Constant { .. }) | None => ast::Type::Int,
+        },
+        ast::Expr::Cast { target, .. } => target.clone(),
+        ast::Expr::Parenthesized { expr: (expr, _) } => qualified_type(expr, variables),
+        ast::Expr::Index {
+            array: (array, _), ..
+        } => pointee(&qualified_type(array, variables)).unwrap_or(ast::Type::Int),
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            expr: (pointer, _),
+        } => match expr_type(pointer, variables) {
+            function @ ast::Type::FunctionPointer(_) => function,
+            ty => pointee(&ty).unwrap_or(ast::Type::Int),
+        },
+        ast::Expr::Member {
+            base: (base, _),
+            member,
+        } => member_type(&qualified_type(base, variables), member.source),
+        ast::Expr::Arrow {
+            pointer: (pointer, _),
+            member,
+        } => pointee(&expr_type(pointer, variables)).map_or(ast::Type::Int, |pointee| {
+            member_type(&pointee, member.source)
+        }),
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::AddressOf,
+            expr: (operand, _),
+        } if is_function_designator(operand, variables) => expr_type(operand, variables),
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::AddressOf,
+            expr: (operand, _),
+        } => ast::Type::Pointer(Box::new(qualified_type(operand, variables))),
+        ast::Expr::Binary { operator, .. } if operator.chains() => {
+            let (operations, innermost) = unchain(expr);
+            operations
+                .into_iter()
+                .rev()
+                .fold(expr_type(innermost, variables), |lhs, (operator, rhs)| {
+                    operation_type(operator, &lhs, &expr_type(rhs, variables))
+                })
+        }
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Negate | ast::UnaryOp::BitNot,
+            expr: (operand, _),
+        } => promoted(&expr_type(operand, variables)),
+        ast::Expr::Ternary {
+            value_true: (value_true, _),
+            value_false: (value_false, _),
+            ..
+        } => common_type(
+            &expr_type(value_true, variables),
+            &expr_type(value_false, variables),
+        ),
+        ast::Expr::LongConstant(_) => ast::Type::Long,
+        ast::Expr::UnsignedConstant(_) => ast::Type::UnsignedInt,
+        ast::Expr::UnsignedLongConstant(_) => ast::Type::UnsignedLong,
+        ast::Expr::Binary {
+            operator: ast::BinaryOp::Logic(_),
+            ..
+        }
+        | ast::Expr::Unary {
+            operator: ast::UnaryOp::LogicNot,
+            ..
+        } => ast::Type::Bool,
+        ast::Expr::FloatConstant { ty, .. } => ty.ty(),
+        ast::Expr::StringLiteral(contents) => ast::Type::Array {
+            element: Box::new(ast::Type::Char),
+            len: contents.len() + 1,
+        },
+        ast::Expr::Binary {
+            operator: ast::BinaryOp::Assignment { .. },
+            lhs: (lhs, _),
+            ..
+        } => expr_type(lhs, variables),
+        // the atomic builtins give the value of the memory they access
+        ast::Expr::Call { function, args }
+            if atomic::Builtin::from_name(function.source).is_some() =>
+        {
+            atomic::accessed_type(args, variables)
+        }
+        ast::Expr::Call { function, .. } => {
+            return_type(&ast::Expr::Variable { name: *function }, variables)
+        }
+        ast::Expr::CallPointer {
+            pointer: (pointer, _),
+            ..
+        } => return_type(pointer, variables),
+        ast::Expr::Constant(_)
+        | ast::Expr::AlignOf(_)
+        | ast::Expr::Unary { .. }
+        | ast::Expr::Binary { .. } => ast::Type::Int,
+    }
+}
 
-                    (rhs_block, start, rhs)
-                };
-                // create the new block that will start with a phi node to merge the two branches
-                let mut end_builder = state.new_block();
-                // finish rhs by telling it to jump directly to the end
-                let rhs_block = rhs_builder.finish_block(
-                    state,
-                    Branch::Unconditional {
-                        target: end_builder.block(),
-                    },
-                );
-                // finish lhs by adding the comparison to zero and the conditional branch
-                let bail_condition = match logicop {
-                    ast::LogicOp::And => Condition::Equals,
-                    ast::LogicOp::Or => Condition::NotEquals,
-                };
-                let flag = bindings.next_binding();
-                lhs_builder.assign(
-                    flag,
-                    Value::Cmp {
-                        condition: bail_condition,
-                        lhs,
-                        rhs: 0.into(),
-                    },
-                );
-                let lhs_block = lhs_builder.finish_block(
-                    state,
-                    Branch::Conditional {
-                        flag,
-                        target_true: end_builder.block(),
-                        target_false: compute_rhs,
-                    },
-                );
+/// The type of the result of `operator` on operands of these types
+fn operation_type(operator: ast::BinaryOp, lhs: &ast::Type, rhs: &ast::Type) -> ast::Type {
+    match operator {
+        ast::BinaryOp::Arithmetic(arithmop) => match (pointee(lhs), pointee(rhs), arithmop) {
+            (Some(pointee), None, _) | (None, Some(pointee), ast::ArithmeticOp::Add) => {
+                ast::Type::Pointer(Box::new(pointee))
+            }
+            (Some(_), Some(_), _) => ast::Type::Int,
+            _ => common_type(lhs, rhs),
+        },
+        // a shift has the type of what's shifted
+        ast::BinaryOp::Bit(ast::BitOp::LeftShift | ast::BitOp::RightShift) => promoted(lhs),
+        ast::BinaryOp::Bit(_) => common_type(lhs, rhs),
+        ast::BinaryOp::Relational(_) | ast::BinaryOp::Logic(_) => ast::Type::Bool,
+        ast::BinaryOp::Assignment { .. } => lhs.clone(),
+    }
+}
 
-                let end = bindings.next_binding();
-                end_builder.assign(
-                    end,
-                    Value::Phi {
-                        nodes: vec![
-                            PhiDescriptor {
-                                value: lhs,
-                                block_from: lhs_block,
-                            },
-                            PhiDescriptor {
-                                value: rhs,
-                                block_from: rhs_block,
-                            },
-                        ],
-                    },
-                );
+/// Take apart a [chain](ast::BinaryOp::chains) into its operations from the outermost in, each
+/// with its right operand, and the left operand of the innermost one
+fn unchain<'expr, 'code>(
+    mut expr: &'expr ast::Expr<'code>,
+) -> (
+    Vec<(ast::BinaryOp, &'expr ast::Expr<'code>)>,
+    &'expr ast::Expr<'code>,
+) {
+    let mut operations = Vec::new();
+    while let ast::Expr::Binary {
+        operator,
+        lhs: (lhs, _),
+        rhs: (rhs, _),
+    } = expr
+    {
+        if !operator.chains() {
+            break;
+        }
+        operations.push((*operator, &**rhs));
+        expr = lhs;
+    }
+    (operations, expr)
+}
 
-                Ok((
-                    end_builder,
-                    Value::And {
-                        lhs: end,
-                        rhs: (1).into(),
-                    },
-                ))
+/// The type of a struct or union's member, or `int` if there's no such member
+fn member_type(ty: &ast::Type, member: &str) -> ast::Type {
+    match ty {
+        ast::Type::Struct(struct_type) => struct_type
+            .member(member)
+            .map_or(ast::Type::Int, |member| member.ty),
+        ast::Type::Volatile(ty) => member_type(ty, member).volatile(),
+        _ => ast::Type::Int,
+    }
+}
+
+/// The value of the expression, if it's a literal
+pub fn literal_value(expr: &ast::Expr) -> Option<i64> {
+    match expr.without_parens() {
+        ast::Expr::Constant(constant) => Some((*constant).into()),
+        ast::Expr::LongConstant(constant) => Some(*constant),
+        ast::Expr::UnsignedConstant(constant) => Some((*constant).into()),
+        ast::Expr::UnsignedLongConstant(constant) => i64::try_from(*constant).ok(),
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Negate,
+            expr: (expr, _),
+        } => literal_value(expr).map(|value| -value),
+        _ => None,
+    }
+}
+
+/// The value of the expression, if it's a floating literal
+pub fn float_literal_value(expr: &ast::Expr) -> Option<f64> {
+    match expr.without_parens() {
+        ast::Expr::FloatConstant { bits, .. } => Some(f64::from_bits(*bits)),
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Negate,
+            expr: (expr, _),
+        } => float_literal_value(expr).map(|value| -value),
+        _ => None,
+    }
+}
+
+/// The value of an integer constant expression, made of literals, enumerators and `_Alignof`, if
+/// `expr` is one. It's computed without the limits of any type, so `None` is also given when it
+/// overflows.
+pub fn reduce_expr(expr: &ast::Expr, variables: &VariableTracker) -> Option<i64> {
+    match expr.without_parens() {
+        ast::Expr::Constant(constant) => Some((*constant).into()),
+        ast::Expr::LongConstant(constant) => Some(*constant),
+        ast::Expr::UnsignedConstant(constant) => Some((*constant).into()),
+        ast::Expr::UnsignedLongConstant(constant) => i64::try_from(*constant).ok(),
+        ast::Expr::AlignOf(ty) => Some(ty.align() as i64),
+        ast::Expr::Variable { name } => variables.get_constant(name.source).map(Into::into),
+        ast::Expr::Unary {
+            operator,
+            expr: (operand, _),
+        } => {
+            let value = reduce_expr(operand, variables)?;
+            match operator {
+                ast::UnaryOp::Negate => value.checked_neg(),
+                ast::UnaryOp::BitNot => Some(!value),
+                ast::UnaryOp::LogicNot => Some((value == 0).into()),
+                ast::UnaryOp::AddressOf | ast::UnaryOp::Deref => None,
             }
-            ast::BinaryOp::Assignment { op } => {
-                // compute rhs
-                let (mut builder, rhs_value) =
-                    compile_expr(state, builder, *rhs_expr, bindings, variables, source_info)
-                        .map_err(|e| e.with_backup_source(rhs_span, source_info))?;
+        }
+        ast::Expr::Binary { operator, .. } if operator.chains() => {
+            let (operations, innermost) = unchain(expr.without_parens());
+            let lhs = (
+                reduce_expr(innermost, variables)?,
+                expr_type(innermost, variables),
+            );
+            operations
+                .into_iter()
+                .rev()
+                .try_fold(lhs, |(lhs, lhs_type), (operator, rhs)| {
+                    let rhs_type = expr_type(rhs, variables);
+                    let value = reduce_operation(
+                        operator,
+                        (lhs, &lhs_type),
+                        (reduce_expr(rhs, variables)?, &rhs_type),
+                    )?;
+                    Some((value, operation_type(operator, &lhs_type, &rhs_type)))
+                })
+                .map(|(value, _)| value)
+        }
+        ast::Expr::Binary {
+            operator,
+            lhs: (lhs, _),
+            rhs: (rhs, _),
+        } => reduce_operation(
+            *operator,
+            (reduce_expr(lhs, variables)?, &expr_type(lhs, variables)),
+            (reduce_expr(rhs, variables)?, &expr_type(rhs, variables)),
+        ),
+        ast::Expr::Ternary {
+            condition: (condition, _),
+            value_true: (value_true, _),
+            value_false: (value_false, _),
+        } => {
+            let condition = reduce_expr(condition, variables)?;
+            let value_true = reduce_expr(value_true, variables)?;
+            let value_false = reduce_expr(value_false, variables)?;
+            Some(if condition != 0 {
+                value_true
+            } else {
+                value_false
+            })
+        }
+        ast::Expr::Cast {
+            target,
+            expr: (expr, _),
+        } => {
+            let value = reduce_expr(expr, variables)?;
+            match target {
+                ast::Type::Bool => Some((value != 0).into()),
+                ast::Type::UnsignedLong if target.size() == 8 => Some(value).filter(|v| *v >= 0),
+                integer if integer.is_integer() => Some(wrap_integer(value, target)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
 
-                let rhs = bindings.next_binding();
-                builder.assign(rhs, rhs_value);
+/// The value of `operator` on constant operands of these types, if it's defined and representable
+fn reduce_operation(
+    operator: ast::BinaryOp,
+    (lhs, lhs_type): (i64, &ast::Type),
+    (rhs, rhs_type): (i64, &ast::Type),
+) -> Option<i64> {
+    // unsigned operands wrap around into their type, and a 64-bit one beyond the values of
+    // a `long` isn't kept
+    let ty = match operator {
+        ast::BinaryOp::Relational(_) => common_type(lhs_type, rhs_type),
+        _ => operation_type(operator, lhs_type, rhs_type),
+    };
+    let (lhs, rhs) = if ty.is_unsigned() {
+        (wrap_integer(lhs, &ty), wrap_integer(rhs, &ty))
+    } else {
+        (lhs, rhs)
+    };
+    if ty.is_unsigned() && (lhs < 0 || rhs < 0) {
+        return None;
+    }
+    let value = match operator {
+        ast::BinaryOp::Arithmetic(op) => match op {
+            ast::ArithmeticOp::Add => lhs.checked_add(rhs),
+            ast::ArithmeticOp::Subtract => lhs.checked_sub(rhs),
+            ast::ArithmeticOp::Multiply => lhs.checked_mul(rhs),
+            ast::ArithmeticOp::Divide => lhs.checked_div(rhs),
+            ast::ArithmeticOp::Modulo => lhs.checked_rem(rhs),
+        },
+        ast::BinaryOp::Bit(op) => match op {
+            ast::BitOp::And => Some(lhs & rhs),
+            ast::BitOp::Or => Some(lhs | rhs),
+            ast::BitOp::Xor => Some(lhs ^ rhs),
+            // shifting an `int` by its width or more is undefined
+            ast::BitOp::LeftShift => (0..32)
+                .contains(&rhs)
+                .then(|| lhs << rhs)
+                .filter(|shifted| shifted >> rhs == lhs),
+            ast::BitOp::RightShift => (0..32).contains(&rhs).then(|| lhs >> rhs),
+        },
+        ast::BinaryOp::Logic(op) => Some(match op {
+            ast::LogicOp::And => (lhs != 0 && rhs != 0).into(),
+            ast::LogicOp::Or => (lhs != 0 || rhs != 0).into(),
+        }),
+        ast::BinaryOp::Relational(relation) => Some(
+            match relation {
+                ast::Relational::Less => lhs < rhs,
+                ast::Relational::LessEqual => lhs <= rhs,
+                ast::Relational::Greater => lhs > rhs,
+                ast::Relational::GreaterEqual => lhs >= rhs,
+                ast::Relational::Equals => lhs == rhs,
+                ast::Relational::NotEquals => lhs != rhs,
+            }
+            .into(),
+        ),
+        ast::BinaryOp::Assignment { .. } => None,
+    }?;
+    match operator {
+        ast::BinaryOp::Arithmetic(_) | ast::BinaryOp::Bit(_) if ty.is_unsigned() => {
+            Some(wrap_integer(value, &ty)).filter(|value| *value >= 0)
+        }
+        _ => Some(value),
+    }
+}
 
-                let (lhs_mem, lhs_size) = expr_as_ptr(*lhs_expr, variables)
-                    .map_err(|e| e.with_backup_source(lhs_span, source_info))?;
-                let result_binding = if let Some(assignment_enabled) = op {
-                    // 1. read the memory
-                    let lhs = bindings.next_binding();
-                    builder.load(lhs, lhs_mem, lhs_size);
-                    // 2. Compute the value
-                    let value = match assignment_enabled {
-                        ast::AssignmentEnabledOp::Arithmetic(arithmop) => {
-                            compile_arithmetic(&mut builder, bindings, arithmop, lhs, rhs)
+/// Warn when storing `expr` into a `target` may change its value. Explicit casts and constant
+/// expressions that fit in the target are trusted. Converting a floating value to an integer
+/// drops its fraction, so it's always checked.
+pub fn check_narrowing(
+    state: &mut IRGenState,
+    target: &ast::Type,
+    expr: &ast::Expr,
+    expr_span: Span,
+    variables: &VariableTracker,
+    source_info: &SourceMetadata,
+) {
+    if let ast::Expr::Cast { .. } = expr.without_parens() {
+        return;
+    }
+    let from = expr_type(expr, variables);
+    if let ast::Type::Array { .. } = from {
+        // not a value, that's an error on its own
+        return;
+    }
+    if *target == ast::Type::Bool {
+        // any value becomes a `_Bool` by comparing it to zero, which is the point of storing it
+        return;
+    }
+    let widens = (!from.is_floating() || target.is_floating()) && from.size() <= target.size();
+    if widens
+        || reduce_expr(expr, variables).is_some_and(|value| target.can_represent(value))
+        || float_literal_value(expr).is_some_and(|value| target.can_represent_float(value))
+    {
+        return;
+    }
+    state.warn(
+        VarW::new(VarWarning::Narrowing {
+            from,
+            to: target.clone(),
+        })
+        .with_source(expr_span, source_info),
+    );
+}
+
+/// Warn about operands of `outer` whose grouping is often misread, unless they are written in
+/// parentheses: `&&` within `||`, `+` or `-` within a shift or a bitwise operator, comparisons
+/// within a bitwise operator, and `&` or `^` within `|`, or `&` within `^`.
+fn check_parentheses(
+    state: &mut IRGenState,
+    outer: ast::BinaryOp,
+    operands: [(&ast::Expr, Span); 2],
+    source_info: &SourceMetadata,
+) {
+    use ast::{ArithmeticOp as A, BinaryOp as B, BitOp};
+    for (operand, span) in operands {
+        let ast::Expr::Binary {
+            operator: inner, ..
+        } = operand
+        else {
+            continue;
+        };
+        let misread = matches!(
+            (outer, *inner),
+            (B::Logic(ast::LogicOp::Or), B::Logic(ast::LogicOp::And))
+                | (B::Bit(_), B::Arithmetic(A::Add | A::Subtract))
+                | (
+                    B::Bit(BitOp::And | BitOp::Or | BitOp::Xor),
+                    B::Relational(_)
+                )
+                | (B::Bit(BitOp::Or), B::Bit(BitOp::And | BitOp::Xor))
+                | (B::Bit(BitOp::Xor), B::Bit(BitOp::And))
+        );
+        if misread {
+            let fix = format!("({})", &source_info.input()[span.as_range()]);
+            state.warn(
+                VarW::new(VarWarning::Parentheses {
+                    inner: *inner,
+                    outer,
+                    fix,
+                })
+                .with_source(span, source_info),
+            );
+        }
+    }
+}
+
+/// Warn when a shift is by a constant count that is negative, or not less than the width of the
+/// (promoted) value shifted, since the shift is undefined then. The generated code takes the count
+/// modulo the width, as AArch64 does with a count in a register.
+fn check_shift_count(
+    state: &mut IRGenState,
+    shifted: &ast::Type,
+    count: &ast::Expr,
+    count_span: Span,
+    variables: &VariableTracker,
+    source_info: &SourceMetadata,
+) {
+    let Some(count) = reduce_expr(count, variables) else {
+        return;
+    };
+    let ty = promoted(shifted);
+    let warning = if count < 0 {
+        VarWarning::ShiftCountNegative(count)
+    } else if count >= 8 * ty.size() as i64 {
+        VarWarning::ShiftCountOverflow { count, ty }
+    } else {
+        return;
+    };
+    state.warn(VarW::new(warning).with_source(count_span, source_info));
+}
+
+/// Compute where the value of an assignable expression lives, along with its type. A call
+/// returning a struct isn't assignable, but its struct lives in memory too.
+pub fn expr_as_target<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    expr: ast::Expr<'code>,
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Binding, ast::Type), VarE> {
+    match expr {
+        ast::Expr::Parenthesized {
+            expr: (expr, expr_span),
+        } => expr_as_target(state, builder, *expr, bindings, variables, source_info)
+            .map_err(|e| e.with_backup_source(expr_span, source_info)),
+        ast::Expr::Variable { name: source } => {
+            let name = source.source;
+            let symbol = variables.lookup(name);
+            state.symbols.refer(source, symbol);
+            match symbol {
+                Some(Symbol::Variable(variable)) => {
+                    Ok((builder, variable.memory, variable.ty.clone()))
+                }
+                Some(Symbol::Global(global)) => {
+                    let mut builder = builder;
+                    let memory = global_memory(&mut builder, bindings, name);
+                    Ok((builder, memory, global.ty.clone()))
+                }
+                // a register has no address
+                Some(Symbol::Register(_)) => {
+                    Err(VarE::new(VarError::RegisterAddress(name.to_string())))
+                }
+                Some(Symbol::Constant { .. } | Symbol::Function(_)) => {
+                    Err(VarE::new(VarError::NotAssignable))
+                }
+                None => Err(VarE::new(VarError::UnknownVariable(name.to_string()))),
+            }
+        }
+        ast::Expr::Index {
+            array: (array, array_span),
+            index: (index, index_span),
+        } => {
+            // the array is located first, then the index is computed. A pointer's value is where
+            // its elements are.
+            let (builder, base, element) = match expr_type(&array, variables) {
+                ast::Type::Pointer(pointee) => {
+                    let (mut builder, pointer) =
+                        compile_expr(state, builder, *array, bindings, variables, source_info)
+                            .map_err(|e| e.with_backup_source(array_span, source_info))?;
+                    let base = bindings.next_binding();
+                    builder.assign(base, pointer);
+                    (builder, base, *pointee)
+                }
+                _ => {
+                    let (builder, base, array_type) =
+                        expr_as_target(state, builder, *array, bindings, variables, source_info)
+                            .map_err(|e| e.with_backup_source(array_span, source_info))?;
+                    match array_type {
+                        ast::Type::Array { element, .. } => (builder, base, *element),
+                        other => {
+                            return Err(VarE::new(VarError::NotAnArray(other))
+                                .with_backup_source(array_span, source_info))
                         }
-                        ast::AssignmentEnabledOp::Bit(bitop) => compile_bitop(bitop, lhs, rhs),
-                    };
-                    let result = bindings.next_binding();
-                    builder.assign(result, value);
-                    result
-                } else {
-                    rhs
-                };
-                builder.store(result_binding, lhs_mem, lhs_size);
-                Ok((builder, Value::Binding(result_binding)))
+                    }
+                }
+            };
+            let index_type = expr_type(&index, variables);
+            if index_type.is_floating() {
+                return Err(VarE::new(VarError::FloatOperand(index_type))
+                    .with_source(index_span, source_info));
             }
+            let bit_size = bit_size(&index_type);
+            let (mut builder, index_value) =
+                compile_expr(state, builder, *index, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(index_span, source_info))?;
+            let index = bindings.next_binding();
+            builder.assign(index, index_value);
+            let address = bindings.next_binding();
+            builder.assign(
+                address,
+                Value::ElementAddress {
+                    base,
+                    index,
+                    element_size: element.size(),
+                    bit_size,
+                },
+            );
+            Ok((builder, address, element))
+        }
+        ast::Expr::Unary {
+            operator: ast::UnaryOp::Deref,
+            expr: (pointer, pointer_span),
+        } => {
+            let pointer_type = expr_type(&pointer, variables);
+            let pointee = pointee(&pointer_type).ok_or_else(|| {
+                VarE::new(VarError::NotAPointer(pointer_type))
+                    .with_source(pointer_span, source_info)
+            })?;
+            let (mut builder, pointer_value) =
+                compile_expr(state, builder, *pointer, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(pointer_span, source_info))?;
+            let address = bindings.next_binding();
+            builder.assign(address, pointer_value);
+            Ok((builder, address, pointee))
+        }
+        ast::Expr::Member {
+            base: (base, base_span),
+            member,
+        } => {
+            let (mut builder, base, base_type) =
+                expr_as_target(state, builder, *base, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(base_span, source_info))?;
+            let (address, ty) = member_address(
+                &mut builder,
+                bindings,
+                (base, base_type, base_span),
+                member,
+                source_info,
+            )?;
+            Ok((builder, address, ty))
+        }
+        ast::Expr::Arrow {
+            pointer: (pointer, pointer_span),
+            member,
+        } => {
+            // the pointer's value is the base itself, it doesn't go through a dereference first
+            let pointer_type = expr_type(&pointer, variables);
+            let pointee = pointee(&pointer_type).ok_or_else(|| {
+                VarE::new(VarError::NotAPointer(pointer_type))
+                    .with_source(pointer_span, source_info)
+            })?;
+            let (mut builder, pointer_value) =
+                compile_expr(state, builder, *pointer, bindings, variables, source_info)
+                    .map_err(|e| e.with_backup_source(pointer_span, source_info))?;
+            let base = bindings.next_binding();
+            builder.assign(base, pointer_value);
+            let (address, ty) = member_address(
+                &mut builder,
+                bindings,
+                (base, pointee, pointer_span),
+                member,
+                source_info,
+            )?;
+            Ok((builder, address, ty))
+        }
+        ast::Expr::Call { function, args } => {
+            let ty @ ast::Type::Struct(_) =
+                return_type(&ast::Expr::Variable { name: function }, variables)
+            else {
+                return Err(VarE::new(VarError::NotAssignable));
+            };
+            let (mut builder, value) = compile_call(
+                state,
+                builder,
+                function,
+                args,
+                bindings,
+                variables,
+                source_info,
+            )?;
+            let result = called_struct(&mut builder, bindings, value);
+            Ok((builder, result, ty))
+        }
+        ast::Expr::CallPointer {
+            pointer: (pointer, pointer_span),
+            args,
+        } => {
+            let ty @ ast::Type::Struct(_) = return_type(&pointer, variables) else {
+                return Err(VarE::new(VarError::NotAssignable));
+            };
+            let (mut builder, value) = compile_pointer_call(
+                state,
+                builder,
+                (*pointer, pointer_span),
+                args,
+                bindings,
+                variables,
+                source_info,
+            )?;
+            let result = called_struct(&mut builder, bindings, value);
+            Ok((builder, result, ty))
+        }
+        _ => Err(VarE::new(VarError::NotAssignable)),
+    }
+}
+
+/// Make the call returning a struct, giving the memory the struct is written to
+fn called_struct(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    call: Value,
+) -> Binding {
+    let Value::CallStruct { result, .. } = call else {
+        unreachable!("a call returning a struct")
+    };
+    let call_binding = bindings.next_binding();
+    builder.assign(call_binding, call);
+    result
+}
+
+/// Compute the address of a member of the struct or union at `base`, along with the member's type
+fn member_address(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    (base, base_type, base_span): (Binding, ast::Type, Span),
+    member: Source,
+    source_info: &SourceMetadata,
+) -> Result<(Binding, ast::Type), VarE> {
+    let struct_type = match base_type.unqualified() {
+        ast::Type::Struct(struct_type) => struct_type,
+        _ => {
+            return Err(
+                VarE::new(VarError::NotAStruct(base_type)).with_source(base_span, source_info)
+            )
+        }
+    };
+    let member = struct_type.member(member.source).ok_or_else(|| {
+        VarE::new(VarError::UnknownMember {
+            struct_type: struct_type.clone(),
+            member: member.source.into(),
+        })
+        .with_source(member.span, source_info)
+    })?;
+    // the member is the byte at its offset from the start of the aggregate (always 0 in a union)
+    let offset = bindings.next_binding();
+    builder.assign(offset, Value::Constant(member.offset as i64));
+    let address = bindings.next_binding();
+    builder.assign(
+        address,
+        Value::ElementAddress {
+            base,
+            index: offset,
+            element_size: 1,
+            bit_size: BitSize::Bit32,
         },
+    );
+    // the members of a volatile struct are volatile too
+    let ty = match base_type {
+        ast::Type::Volatile(_) => member.ty.volatile(),
+        _ => member.ty,
+    };
+    Ok((address, ty))
+}
+
+/// What a value of the given type points to, if it's a pointer (or an array, which decays into
+/// one)
+pub fn pointee(ty: &ast::Type) -> Option<ast::Type> {
+    match ty {
+        ast::Type::Pointer(pointee) => Some(*pointee.clone()),
+        ast::Type::Array { element, .. } => Some(*element.clone()),
+        ast::Type::Volatile(ty) => pointee(ty),
+        ast::Type::Bool
+        | ast::Type::Char
+        | ast::Type::SignedChar
+        | ast::Type::Short
+        | ast::Type::UnsignedShort
+        | ast::Type::Int
+        | ast::Type::UnsignedInt
+        | ast::Type::Long
+        | ast::Type::UnsignedLong
+        | ast::Type::Float
+        | ast::Type::Double
+        | ast::Type::Struct(_)
+        | ast::Type::FunctionPointer(_) => None,
+    }
+}
+
+/// Whether values of the type are addresses: pointers, arrays (which decay into one) and
+/// function pointers
+fn is_address(ty: &ast::Type) -> bool {
+    pointee(ty).is_some() || matches!(ty, ast::Type::FunctionPointer(_))
+}
+
+/// A constant as a value of an integer type, wrapping around when it doesn't fit. A `long` may be
+/// as wide as an `int` depending on the target.
+pub fn wrap_integer(value: i64, ty: &ast::Type) -> i64 {
+    match (ty.size(), ty.is_unsigned()) {
+        (1, true) => (value as u8).into(),
+        (1, false) => (value as i8).into(),
+        (2, true) => (value as u16).into(),
+        (2, false) => (value as i16).into(),
+        (4, true) => (value as u32).into(),
+        (4, false) => (value as i32).into(),
+        _ => value,
+    }
+}
+
+/// The width of the registers that hold a value of the given type
+pub fn bit_size(ty: &ast::Type) -> BitSize {
+    let layout = &target::current().data_layout;
+    match ty {
+        // arrays decay into pointers
+        ast::Type::Array { .. } => layout.register_width(layout.pointer.size),
+        // never a value on its own
+        ast::Type::Struct(_) => BitSize::Bit32,
+        scalar => layout.register_width(scalar.size()),
+    }
+}
+
+/// The value an integer in `binding` is left with once it's made one of a type narrower than an
+/// `int`: the bits beyond the type are dropped, and the rest are sign extended if it's signed.
+/// `None` if the type isn't that narrow, which leaves the value alone.
+fn narrowed(binding: Binding, ty: &ast::Type) -> Option<Value> {
+    match ty {
+        ast::Type::Char | ast::Type::UnsignedShort => {
+            let mask = match ty {
+                ast::Type::Char => i32::from(u8::MAX),
+                _ => i32::from(u16::MAX),
+            };
+            Some(Value::And {
+                lhs: binding,
+                rhs: mask.into(),
+                bit_size: BitSize::Bit32,
+            })
+        }
+        ast::Type::SignedChar | ast::Type::Short => Some(Value::SignExtend {
+            binding,
+            from: ByteSize::from(ty),
+        }),
+        _ => None,
     }
 }
 
-fn expr_as_ptr<'code>(
+/// Narrow the integer in `binding` to the type, like [`narrowed`], into a new binding if that
+/// changes it
+pub fn narrow(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    binding: Binding,
+    ty: &ast::Type,
+) -> Binding {
+    match narrowed(binding, ty) {
+        Some(value) => {
+            let narrowed = bindings.next_binding();
+            builder.assign(narrowed, value);
+            narrowed
+        }
+        None => binding,
+    }
+}
+
+/// The type a value is operated on as: integers narrower than an `int` become one, and arrays
+/// decay into pointers
+pub fn promoted(ty: &ast::Type) -> ast::Type {
+    match ty {
+        // an `int` holds every value of these, even the unsigned ones
+        ast::Type::Bool
+        | ast::Type::Char
+        | ast::Type::SignedChar
+        | ast::Type::Short
+        | ast::Type::UnsignedShort => ast::Type::Int,
+        ast::Type::Array { element, .. } => ast::Type::Pointer(element.clone()),
+        ty => ty.clone(),
+    }
+}
+
+/// The type both operands of a binary operator are converted to before it's applied: a pointer
+/// if either of them is one, otherwise the widest floating type among them. Integers are promoted
+/// first, and then the one of higher rank wins, unless they differ in signedness: the unsigned
+/// one wins if it ranks at least as high, the signed one if it's wider, and otherwise both become
+/// the unsigned version of the signed one.
+fn common_type(lhs: &ast::Type, rhs: &ast::Type) -> ast::Type {
+    match (promoted(lhs), promoted(rhs)) {
+        (pointer @ (ast::Type::Pointer(_) | ast::Type::FunctionPointer(_)), _)
+        | (_, pointer @ (ast::Type::Pointer(_) | ast::Type::FunctionPointer(_))) => pointer,
+        (ast::Type::Double, _) | (_, ast::Type::Double) => ast::Type::Double,
+        (ast::Type::Float, _) | (_, ast::Type::Float) => ast::Type::Float,
+        (lhs, rhs) if lhs.is_integer() && rhs.is_integer() => {
+            let (unsigned, signed) = match (lhs.is_unsigned(), rhs.is_unsigned()) {
+                (true, false) => (lhs, rhs),
+                (false, true) => (rhs, lhs),
+                _ if is_long(&rhs) => return rhs,
+                _ => return lhs,
+            };
+            if is_long(&unsigned) || !is_long(&signed) {
+                unsigned
+            } else if signed.size() > unsigned.size() {
+                signed
+            } else {
+                signed
+                    .with_signedness(true)
+                    .expect("`long` has an unsigned version")
+            }
+        }
+        _ => ast::Type::Int,
+    }
+}
+
+/// Whether a promoted integer type has the rank of a `long`, rather than the one of an `int`
+fn is_long(ty: &ast::Type) -> bool {
+    matches!(ty, ast::Type::Long | ast::Type::UnsignedLong)
+}
+
+/// Convert the value in `binding` from one type to another, giving where the result is. Only
+/// widening to 64 bits, normalizing into a `_Bool` and conversions to or from a floating type take
+/// an instruction, as narrowing an integer just leaves the upper half behind.
+///
+/// There are only signed conversions between integers and floating values, so a 32-bit unsigned
+/// value goes through a 64-bit one, which holds all of its values. 64-bit unsigned values are
+/// converted as if they were signed.
+pub fn convert(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    binding: Binding,
+    from: &ast::Type,
+    to: &ast::Type,
+) -> Binding {
+    let floating_value = match (from.is_floating(), to.is_floating()) {
+        _ if *to == ast::Type::Bool => return into_bool(builder, bindings, binding, from),
+        (false, false) => None,
+        (false, true) if from.is_unsigned() && bit_size(from) == BitSize::Bit32 => {
+            let extended = convert(builder, bindings, binding, from, &ast::Type::UnsignedLong);
+            Some(Value::IntToFloat {
+                binding: extended,
+                from: BitSize::Bit64,
+                to: bit_size(to),
+            })
+        }
+        (false, true) => Some(Value::IntToFloat {
+            binding,
+            from: bit_size(from),
+            to: bit_size(to),
+        }),
+        // the lower half of the 64-bit result is the 32-bit unsigned one
+        (true, false) if to.is_unsigned() && bit_size(to) == BitSize::Bit32 => {
+            Some(Value::FloatToInt {
+                binding,
+                from: bit_size(from),
+                to: BitSize::Bit64,
+            })
+        }
+        (true, false) => Some(Value::FloatToInt {
+            binding,
+            from: bit_size(from),
+            to: bit_size(to),
+        }),
+        (true, true) if from == to => return binding,
+        (true, true) => Some(Value::FloatConvert {
+            binding,
+            from: bit_size(from),
+            to: bit_size(to),
+        }),
+    };
+    if let Some(value) = floating_value {
+        let converted = bindings.next_binding();
+        builder.assign(converted, value);
+        converted
+    } else if bit_size(from) == BitSize::Bit32 && bit_size(to) == BitSize::Bit64 {
+        let extended = bindings.next_binding();
+        let value = if from.is_unsigned() {
+            Value::And {
+                lhs: binding,
+                rhs: i64::from(u32::MAX).into(),
+                bit_size: BitSize::Bit64,
+            }
+        } else {
+            Value::SignExtend {
+                binding,
+                from: ByteSize::U32,
+            }
+        };
+        builder.assign(extended, value);
+        extended
+    } else {
+        binding
+    }
+}
+
+/// Normalize the value in `binding` into a `_Bool`: 1 if it's nonzero and 0 otherwise
+pub fn into_bool(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    binding: Binding,
+    from: &ast::Type,
+) -> Binding {
+    if *from == ast::Type::Bool {
+        return binding;
+    }
+    let normalized = bindings.next_binding();
+    let comparison = if from.is_floating() {
+        Value::FloatCmp {
+            condition: Condition::NotEquals,
+            lhs: binding,
+            rhs: float_zero(builder, bindings, bit_size(from)),
+            bit_size: bit_size(from),
+        }
+    } else {
+        Value::Cmp {
+            condition: Condition::NotEquals,
+            lhs: binding,
+            rhs: 0.into(),
+            bit_size: bit_size(from),
+        }
+    };
+    builder.assign(normalized, comparison);
+    normalized
+}
+
+/// Assign a floating-point zero of the given width to a new binding. Floating-point instructions
+/// have no immediate operands, so values are compared to it instead.
+fn float_zero(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    bit_size: BitSize,
+) -> Binding {
+    let zero = bindings.next_binding();
+    builder.assign(zero, Value::FloatConstant { bits: 0, bit_size });
+    zero
+}
+
+/// Compile an expression that decides a branch into a flag that is nonzero when it holds.
+/// Branches only test the lower half of the flag in a general purpose register, so 64-bit and
+/// floating-point values are compared to zero first.
+pub fn compile_condition<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
     expr: ast::Expr<'code>,
+    bindings: &mut BindingCounter,
     variables: &VariableTracker<'code>,
-) -> Result<(Binding, ByteSize), VarE> {
-    match expr {
-        ast::Expr::Variable {
-            name: Source { source: name, .. },
-        } => variables
-            .get(name)
-            .ok_or_else(|| VarE::new(VarError::UnknownVariable(name.to_string())))
-            .copied(),
+    source_info: &SourceMetadata<'code>,
+) -> Result<(BlockBuilder, Binding), VarE> {
+    let ty = expr_type(&expr, variables);
+    let (mut builder, value) =
+        compile_expr(state, builder, expr, bindings, variables, source_info)?;
+    let binding = bindings.next_binding();
+    builder.assign(binding, value);
+    if bit_size(&ty) == BitSize::Bit32 && !ty.is_floating() {
+        return Ok((builder, binding));
+    }
+    let flag = into_bool(&mut builder, bindings, binding, &ty);
+    Ok((builder, flag))
+}
 
-        _ => unreachable!("pointers are not yet supported!"),
+/// Apply an arithmetic operator to two computed operands of the given types, doing pointer
+/// arithmetic if either of them is a pointer
+fn compile_arithmetic_operands(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    arithmop: ast::ArithmeticOp,
+    (lhs, lhs_type): (Binding, &ast::Type),
+    (rhs, rhs_type): (Binding, &ast::Type),
+) -> Result<Value, VarE> {
+    let lhs_pointee = pointee(lhs_type);
+    let rhs_pointee = pointee(rhs_type);
+    if lhs_pointee.is_some() || rhs_pointee.is_some() {
+        if lhs_type.is_floating() || rhs_type.is_floating() {
+            return Err(VarE::new(VarError::InvalidPointerArithmetic));
+        }
+        return compile_pointer_arithmetic(
+            builder,
+            bindings,
+            arithmop,
+            (lhs, lhs_pointee, bit_size(lhs_type)),
+            (rhs, rhs_pointee, bit_size(rhs_type)),
+        );
     }
+    let ty = common_type(lhs_type, rhs_type);
+    let lhs = convert(builder, bindings, lhs, lhs_type, &ty);
+    let rhs = convert(builder, bindings, rhs, rhs_type, &ty);
+    if ty.is_floating() {
+        let op = match arithmop {
+            ast::ArithmeticOp::Add => FloatOp::Add,
+            ast::ArithmeticOp::Subtract => FloatOp::Subtract,
+            ast::ArithmeticOp::Multiply => FloatOp::Multiply,
+            ast::ArithmeticOp::Divide => FloatOp::Divide,
+            ast::ArithmeticOp::Modulo => return Err(VarE::new(VarError::FloatOperand(ty))),
+        };
+        return Ok(Value::FloatArithmetic {
+            op,
+            lhs,
+            rhs,
+            bit_size: bit_size(&ty),
+        });
+    }
+    Ok(compile_arithmetic(
+        builder,
+        bindings,
+        arithmop,
+        lhs,
+        rhs,
+        !ty.is_unsigned(),
+        bit_size(&ty),
+    ))
 }
 
-fn relational_as_value(relational: ast::Relational, lhs: Binding, rhs: Binding) -> Value {
-    Value::Cmp {
-        condition: relational.to_condition(),
+/// Apply a bitwise operator to two computed operands of the given types. Shifts keep the type of
+/// their lhs, while the rest convert both operands to a common one.
+fn compile_bitop_operands(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    bitop: ast::BitOp,
+    (lhs, lhs_type): (Binding, &ast::Type),
+    (rhs, rhs_type): (Binding, &ast::Type),
+) -> Result<Value, VarE> {
+    if let Some(floating) = [lhs_type, rhs_type].into_iter().find(|ty| ty.is_floating()) {
+        return Err(VarE::new(VarError::FloatOperand(floating.clone())));
+    }
+    let ty = match bitop {
+        ast::BitOp::LeftShift | ast::BitOp::RightShift => promoted(lhs_type),
+        _ => common_type(lhs_type, rhs_type),
+    };
+    let lhs = convert(builder, bindings, lhs, lhs_type, &ty);
+    let rhs = convert(builder, bindings, rhs, rhs_type, &ty);
+    Ok(compile_bitop(
+        bitop,
         lhs,
-        rhs: rhs.into(),
+        rhs,
+        !ty.is_unsigned(),
+        bit_size(&ty),
+    ))
+}
+
+/// Adding an integer to a pointer moves it that many elements, and the difference of two pointers
+/// is the number of elements between them.
+fn compile_pointer_arithmetic(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    arithmop: ast::ArithmeticOp,
+    (lhs, lhs_pointee, lhs_size): (Binding, Option<ast::Type>, BitSize),
+    (rhs, rhs_pointee, rhs_size): (Binding, Option<ast::Type>, BitSize),
+) -> Result<Value, VarE> {
+    match (arithmop, lhs_pointee, rhs_pointee) {
+        (ast::ArithmeticOp::Add, Some(pointee), None) => Ok(Value::ElementAddress {
+            base: lhs,
+            index: rhs,
+            element_size: pointee.size(),
+            bit_size: rhs_size,
+        }),
+        (ast::ArithmeticOp::Add, None, Some(pointee)) => Ok(Value::ElementAddress {
+            base: rhs,
+            index: lhs,
+            element_size: pointee.size(),
+            bit_size: lhs_size,
+        }),
+        (ast::ArithmeticOp::Subtract, Some(pointee), None) => {
+            let index = bindings.next_binding();
+            builder.assign(
+                index,
+                Value::Negate {
+                    binding: rhs,
+                    bit_size: rhs_size,
+                },
+            );
+            Ok(Value::ElementAddress {
+                base: lhs,
+                index,
+                element_size: pointee.size(),
+                bit_size: rhs_size,
+            })
+        }
+        (ast::ArithmeticOp::Subtract, Some(lhs_pointee), Some(rhs_pointee))
+            if lhs_pointee == rhs_pointee =>
+        {
+            // the difference is an `int`, so only the lower halves of the pointers matter
+            let bytes = Value::Subtract {
+                lhs,
+                rhs: rhs.into(),
+                bit_size: BitSize::Bit32,
+            };
+            if lhs_pointee.size() == 1 {
+                return Ok(bytes);
+            }
+            let difference = bindings.next_binding();
+            builder.assign(difference, bytes);
+            Ok(Value::Divide {
+                lhs: difference,
+                rhs: (lhs_pointee.size() as i32).into(),
+                is_signed: true,
+                bit_size: BitSize::Bit32,
+            })
+        }
+        _ => Err(VarE::new(VarError::InvalidPointerArithmetic)),
+    }
+}
+
+fn relational_as_value(
+    relational: ast::Relational,
+    lhs: Binding,
+    rhs: Binding,
+    ty: &ast::Type,
+) -> Value {
+    if ty.is_floating() {
+        Value::FloatCmp {
+            condition: relational.to_condition(),
+            lhs,
+            rhs,
+            bit_size: bit_size(ty),
+        }
+    } else if ty.is_unsigned() {
+        Value::Cmp {
+            condition: relational.to_condition().unsigned(),
+            lhs,
+            rhs: rhs.into(),
+            bit_size: bit_size(ty),
+        }
+    } else {
+        Value::Cmp {
+            condition: relational.to_condition(),
+            lhs,
+            rhs: rhs.into(),
+            bit_size: bit_size(ty),
+        }
     }
 }
 
@@ -335,24 +2468,30 @@ fn compile_arithmetic(
     arithmop: ast::ArithmeticOp,
     lhs: Binding,
     rhs: Binding,
+    is_signed: bool,
+    bit_size: BitSize,
 ) -> Value {
     match arithmop {
         ast::ArithmeticOp::Add => Value::Add {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::ArithmeticOp::Subtract => Value::Subtract {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::ArithmeticOp::Multiply => Value::Multiply {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::ArithmeticOp::Divide => Value::Divide {
             lhs,
             rhs: rhs.into(),
-            is_signed: true, // assume signed division for now (i32)
+            is_signed,
+            bit_size,
         },
         ast::ArithmeticOp::Modulo => {
             // modulo is a bit more complex.
@@ -365,7 +2504,8 @@ fn compile_arithmetic(
                 Value::Divide {
                     lhs,
                     rhs: rhs.into(),
-                    is_signed: false, // TODO: make signedness on div/modulo depend on signedness of operands
+                    is_signed,
+                    bit_size,
                 },
             );
             builder.assign(
@@ -373,11 +2513,13 @@ fn compile_arithmetic(
                 Value::Multiply {
                     lhs: q,
                     rhs: rhs.into(),
+                    bit_size,
                 },
             );
             Value::Subtract {
                 lhs,
                 rhs: qxd.into(),
+                bit_size,
             }
         }
     }
@@ -385,27 +2527,198 @@ fn compile_arithmetic(
 
 // bit operations can't go out of the block, and
 // require both elements to be computed first
-fn compile_bitop(bitop: ast::BitOp, lhs: Binding, rhs: Binding) -> Value {
+fn compile_bitop(
+    bitop: ast::BitOp,
+    lhs: Binding,
+    rhs: Binding,
+    is_signed: bool,
+    bit_size: BitSize,
+) -> Value {
     match bitop {
         ast::BitOp::And => Value::And {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::BitOp::Or => Value::Or {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::BitOp::Xor => Value::Xor {
             lhs,
             rhs: rhs.into(),
+            bit_size,
+        },
+        // a signed value keeps its sign
+        ast::BitOp::RightShift if is_signed => Value::Asr {
+            lhs,
+            rhs: rhs.into(),
+            bit_size,
         },
         ast::BitOp::RightShift => Value::Lsr {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
         ast::BitOp::LeftShift => Value::Lsl {
             lhs,
             rhs: rhs.into(),
+            bit_size,
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{compiled, returned};
+    use super::*;
+    use crate::intermediate::Statement;
+
+    #[test]
+    fn assignments_are_expressions_of_the_stored_value() {
+        let cases = [
+            ("int x, y, z; x = y = z = 7; return x + y + z;", 21),
+            ("int x = 1, y = 2; x += y = 5; return x * 10 + y;", 65),
+            ("int x; return (x = 5) + 1;", 6),
+            // the value is narrowed as it's stored, and then widened into the next target
+            ("char c; int x; c = x = 300; return c * 1000 + x;", 44300),
+            ("short s; long l; l = s = 70000; return l;", 4464),
+        ];
+        for (body, expected) in cases {
+            let source = alloc::format!("int main() {{ {body} }}");
+            assert_eq!(returned(&source), expected, "{body}");
+        }
+        // the target of the outer assignment is computed once
+        let source = "int next(void);\nint main() { int a[2], y; a[next()] = y = 3; return y; }";
+        let meta = SourceMetadata::new(source);
+        let calls = compiled(&meta).functions[0]
+            .ir
+            .code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter(|statement| {
+                matches!(
+                    statement,
+                    Statement::Assign {
+                        value: Value::Call { .. },
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn array_elements_are_read_and_written_through_subscripts() {
+        let source = "int main() {\n\
+                      \x20   int a[10];\n\
+                      \x20   long l[3];\n\
+                      \x20   int i = 2;\n\
+                      \x20   a[i + 1] = 4;\n\
+                      \x20   a[i] = a[3] * 10;\n\
+                      \x20   l[0] = 5000000000;\n\
+                      \x20   l[1] = l[0] / 1000000000;\n\
+                      \x20   return a[2] + a[3] + l[1];\n\
+                      }\n";
+        assert_eq!(returned(source), 49);
+        let whole = "int main() { int a[2], b[2]; a = b; return 0; }";
+        assert!(!crate::check_str(whole).is_empty());
+    }
+
+    #[test]
+    fn members_are_reached_through_pointers_with_arrows() {
+        let source = "struct point { int x; long y; };\n\
+                      struct line { struct point from; struct point *to; };\n\
+                      int main() {\n\
+                      \x20   struct point b = {3, 4};\n\
+                      \x20   struct line l = {{1, 2}, &b};\n\
+                      \x20   struct line *p = &l;\n\
+                      \x20   p->to->y = p->from.x + 10;\n\
+                      \x20   (&l)->from.y = 5;\n\
+                      \x20   return b.y * 10 + l.from.y;\n\
+                      }\n";
+        assert_eq!(returned(source), 115);
+        let error = |source| {
+            let diagnostics = crate::check_str(source);
+            let error = diagnostics.iter().next().expect("the source has an error");
+            error.message.clone()
+        };
+        assert!(error("int main() { int i = 0; return i->x; }").contains("requires a pointer"));
+        assert!(error("int main() { int *p = 0; return p->x; }").contains("requires a struct"));
+        let unknown = "struct s { int x; };\nint f(struct s *p) { return p->y; }";
+        assert!(error(unknown).contains("no member named"));
+    }
+
+    #[test]
+    fn enumerators_are_integer_constants() {
+        let source = "enum color { RED, GREEN = 5, BLUE, LAST = BLUE * 2 };\n\
+                      int main() {\n\
+                      \x20   enum { LOCAL = RED - 1 } local = LOCAL;\n\
+                      \x20   enum color c = LAST;\n\
+                      \x20   switch (c) {\n\
+                      \x20   case GREEN: return 1;\n\
+                      \x20   case LAST: return BLUE * 100 + LAST * 10 + local;\n\
+                      \x20   default: return 3;\n\
+                      \x20   }\n\
+                      }\n";
+        assert_eq!(returned(source), 600 + 120 - 1);
+        let error = |source| {
+            let diagnostics = crate::check_str(source);
+            let error = diagnostics.iter().next().expect("the source has an error");
+            error.message.clone()
+        };
+        let variable = "int x;\nenum { A = x };";
+        assert!(error(variable).contains("not an integer constant"));
+        let wide = "enum { BIG = 2147483647, NEXT };";
+        assert!(error(wide).contains("doesn't fit in an `int`"));
+    }
+
+    #[test]
+    fn bools_only_hold_zero_or_one() {
+        let cases = [
+            ("_Bool b = 5; return b;", 1),
+            ("_Bool b = 256; return b;", 1),
+            ("_Bool b = 0; return b;", 0),
+            ("_Bool b = 0.5; return b;", 1),
+            ("long l = 4294967296; _Bool b = l; return b;", 1),
+            ("int x = 7, *p = &x; _Bool b = p; return b;", 1),
+            // compound assignments normalize what they store too
+            ("_Bool b = 1; b += 1; return b;", 1),
+            ("_Bool b = 1; b <<= 4; return b * 10;", 10),
+            ("_Bool b = 1; b -= 1; return b;", 0),
+            // and comparisons are already 0 or 1
+            ("_Bool b = 3 < 4; return b + (2 == 2);", 2),
+        ];
+        for (body, expected) in cases {
+            let source = alloc::format!("int main() {{ {body} }}");
+            assert_eq!(returned(&source), expected, "{body}");
+        }
+    }
+
+    #[test]
+    fn arithmetic_follows_the_usual_conversions() {
+        let cases = [
+            // signed values keep their sign when shifted and divided
+            ("int i = -8; return i >> 1;", -4),
+            ("int i = -7; return i % 2;", -1),
+            ("signed char c = (signed char)200; return c * 2;", -112),
+            // an `int` becomes unsigned next to an `unsigned int`, but not next to a wider type
+            ("unsigned u = 1; int i = -1; return i < u;", 0),
+            ("unsigned u = 1; long l = -1; return l < u;", 1),
+            ("unsigned u = (unsigned)-1; return u >> 31;", 1),
+            ("return (unsigned)-1 / 2 > 0;", 1),
+            // narrower types are promoted to `int`, and unsigned ones widen without their sign
+            ("unsigned short s = 65535; return s + 1;", 65536),
+            ("long l = (unsigned)-1; return l > 0;", 1),
+            ("unsigned u = (unsigned)-1; double d = u; return d > 0;", 1),
+        ];
+        for (body, expected) in cases {
+            let source = alloc::format!("int main() {{ {body} }}");
+            assert_eq!(returned(&source), expected, "{body}");
+        }
+        let unsigned_double = "int main() { unsigned double d = 1; return 0; }";
+        assert!(!crate::check_str(unsigned_double).is_empty());
+    }
+}
@@ -0,0 +1,611 @@
+//! Initializers of variables: a single expression, or a braced list with the values of the
+//! elements or members of an aggregate, in order. Those of local variables are compiled to code,
+//! while those of global variables have to be constant, as they are the bytes the program starts
+//! with.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{
+    aggregate, expr, symbols::SymbolRecorder, BindingCounter, BlockBuilder, ByteSize, IRGenState,
+    ReadOnlyData, SourceMetadata, Value, VarE, VarError, VariableTracker,
+};
+use crate::ast;
+use crate::codegen::assembly::BitSize;
+use crate::error::Span;
+use crate::intermediate::Binding;
+use core::iter::Peekable;
+
+/// Variables of at least this many bytes, initialized only with constants, are copied from a
+/// template in read-only memory instead of being written one value at a time
+const TEMPLATE_MIN_SIZE: usize = 64;
+
+type Initializers<'code> = Peekable<alloc::vec::IntoIter<(ast::Initializer<'code>, Span)>>;
+
+/// A scalar inside a variable, along with the expression it starts with
+struct ScalarInit<'code> {
+    /// bytes from the start of the variable
+    offset: usize,
+    ty: ast::Type,
+    expr: ast::Expr<'code>,
+    span: Span,
+}
+
+/// Write the initial value of the variable of type `ty` that lives in `memory`. Whatever a braced
+/// list leaves out is zero.
+pub fn compile_initializer<'code>(
+    state: &mut IRGenState,
+    builder: BlockBuilder,
+    (memory, ty): (Binding, &ast::Type),
+    (init, init_span): (ast::Initializer<'code>, Span),
+    bindings: &mut BindingCounter,
+    variables: &VariableTracker<'code>,
+    source_meta: &SourceMetadata,
+) -> Result<BlockBuilder, VarE> {
+    let mut scalars = Vec::new();
+    match init {
+        ast::Initializer::Expr(expr) => match ty.unqualified() {
+            ast::Type::Array { .. } => {
+                return Err(
+                    VarE::new(VarError::ArrayInitializer).with_source(init_span, source_meta)
+                )
+            }
+            struct_type @ ast::Type::Struct(_) => {
+                let (mut builder, from) = aggregate::struct_place(
+                    state,
+                    builder,
+                    (expr, init_span),
+                    struct_type,
+                    bindings,
+                    variables,
+                    source_meta,
+                )?;
+                aggregate::copy_memory(&mut builder, bindings, (from, memory), struct_type);
+                return Ok(builder);
+            }
+            _ => scalars.push(ScalarInit {
+                offset: 0,
+                ty: ty.clone(),
+                expr,
+                span: init_span,
+            }),
+        },
+        list => {
+            let mut inits = vec![(list, init_span)].into_iter().peekable();
+            element(ty, 0, &mut inits, &mut scalars, source_meta)?;
+        }
+    }
+    // the initializers copied from a template aren't compiled, so their names are recorded here
+    for scalar in &scalars {
+        state.symbols.refer_all(&scalar.expr, variables);
+        expr::check_narrowing(
+            state,
+            &scalar.ty,
+            &scalar.expr,
+            scalar.span,
+            variables,
+            source_meta,
+        );
+    }
+
+    if ty.size() >= TEMPLATE_MIN_SIZE {
+        if let Some(bytes) = template(ty.size(), &scalars, variables) {
+            return Ok(copy_template(
+                state,
+                builder,
+                memory,
+                ReadOnlyData {
+                    bytes,
+                    align: ty.align(),
+                },
+                bindings,
+            ));
+        }
+    }
+
+    let mut builder = builder;
+    let mut covered: Vec<(usize, usize)> = scalars
+        .iter()
+        .map(|scalar| (scalar.offset, scalar.offset + scalar.ty.size()))
+        .collect();
+    covered.sort_unstable();
+    let mut start = 0;
+    for (offset, end) in covered.into_iter().chain([(ty.size(), ty.size())]) {
+        if start < offset {
+            store_zeros(&mut builder, bindings, memory, start..offset);
+        }
+        start = start.max(end);
+    }
+    for ScalarInit {
+        offset,
+        ty,
+        expr,
+        span,
+    } in scalars
+    {
+        let expr_type = expr::expr_type(&expr, variables);
+        let (mut end, value) =
+            expr::compile_expr(state, builder, expr, bindings, variables, source_meta)
+                .map_err(|e| e.with_backup_source(span, source_meta))?;
+        let value_binding = bindings.next_binding();
+        end.assign(value_binding, value);
+        let value_binding = expr::convert(
+            &mut end,
+            bindings,
+            value_binding,
+            &expr_type,
+            ty.unqualified(),
+        );
+        let address = offset_address(&mut end, bindings, memory, offset);
+        end.store_object(value_binding, address, &ty);
+        builder = end;
+    }
+    Ok(builder)
+}
+
+/// The bytes a global variable of type `ty` starts with, which its initializer has to give as
+/// constants
+pub fn constant_bytes<'code>(
+    ty: &ast::Type,
+    (init, init_span): (ast::Initializer<'code>, Span),
+    variables: &VariableTracker<'code>,
+    symbols: &mut SymbolRecorder,
+    source_meta: &SourceMetadata,
+) -> Result<Vec<u8>, VarE> {
+    let mut scalars = Vec::new();
+    match init {
+        ast::Initializer::Expr(expr) => match ty.unqualified() {
+            ast::Type::Array { .. } => {
+                return Err(
+                    VarE::new(VarError::ArrayInitializer).with_source(init_span, source_meta)
+                )
+            }
+            // a struct can only be copied from another one, which isn't a constant
+            ast::Type::Struct(_) => {
+                return Err(
+                    VarE::new(VarError::NonConstantInitializer).with_source(init_span, source_meta)
+                )
+            }
+            _ => scalars.push(ScalarInit {
+                offset: 0,
+                ty: ty.clone(),
+                expr,
+                span: init_span,
+            }),
+        },
+        list => {
+            let mut inits = vec![(list, init_span)].into_iter().peekable();
+            element(ty, 0, &mut inits, &mut scalars, source_meta)?;
+        }
+    }
+    let mut bytes = vec![0; ty.size()];
+    for scalar in &scalars {
+        symbols.refer_all(&scalar.expr, variables);
+        let value = scalar_bits(scalar, variables).ok_or_else(|| {
+            VarE::new(VarError::NonConstantInitializer).with_source(scalar.span, source_meta)
+        })?;
+        write_scalar(&mut bytes, scalar, value);
+    }
+    Ok(bytes)
+}
+
+/// Match the next initializers of a braced list with the element or member of type `ty`, at
+/// `offset` bytes into the variable. An aggregate takes a braced list of its own, or as many
+/// initializers of the enclosing list as it needs when its braces are left out.
+fn element<'code>(
+    ty: &ast::Type,
+    offset: usize,
+    inits: &mut Initializers<'code>,
+    scalars: &mut Vec<ScalarInit<'code>>,
+    source_meta: &SourceMetadata,
+) -> Result<(), VarE> {
+    let is_aggregate = matches!(
+        ty.unqualified(),
+        ast::Type::Array { .. } | ast::Type::Struct(_)
+    );
+    match inits.next_if(|_| !is_aggregate) {
+        Some((ast::Initializer::Expr(expr), span)) => {
+            scalars.push(ScalarInit {
+                offset,
+                ty: ty.clone(),
+                expr,
+                span,
+            });
+            return Ok(());
+        }
+        // a scalar in braces, `{ value }`
+        Some((ast::Initializer::List(list), span)) => {
+            let mut list = list.into_iter();
+            return match list.next() {
+                Some((ast::Initializer::Expr(expr), span)) => {
+                    excess(list.next(), source_meta)?;
+                    scalars.push(ScalarInit {
+                        offset,
+                        ty: ty.clone(),
+                        expr,
+                        span,
+                    });
+                    Ok(())
+                }
+                _ => Err(VarE::new(VarError::ScalarBraces).with_source(span, source_meta)),
+            };
+        }
+        None if !is_aggregate => return Ok(()),
+        None => (),
+    }
+    match inits.next_if(|(init, _)| matches!(init, ast::Initializer::List(_))) {
+        Some((ast::Initializer::List(list), _)) => {
+            let mut list = list.into_iter().peekable();
+            members(ty, offset, &mut list, scalars, source_meta)?;
+            excess(list.next(), source_meta)
+        }
+        _ => members(ty, offset, inits, scalars, source_meta),
+    }
+}
+
+/// Match initializers with the elements of an array or the members of a struct in order, until
+/// either runs out. Only the first member of a union is initialized.
+fn members<'code>(
+    ty: &ast::Type,
+    offset: usize,
+    inits: &mut Initializers<'code>,
+    scalars: &mut Vec<ScalarInit<'code>>,
+    source_meta: &SourceMetadata,
+) -> Result<(), VarE> {
+    match ty.unqualified() {
+        ast::Type::Array {
+            element: inner,
+            len,
+        } => {
+            for index in 0..*len {
+                if inits.peek().is_none() {
+                    break;
+                }
+                element(
+                    inner,
+                    offset + index * inner.size(),
+                    inits,
+                    scalars,
+                    source_meta,
+                )?;
+            }
+        }
+        ast::Type::Struct(struct_type) => {
+            let members = match struct_type.kind {
+                ast::AggregateKind::Struct => &struct_type.members[..],
+                ast::AggregateKind::Union => &struct_type.members[..1],
+            };
+            for member in members {
+                // a flexible array member has no room in a variable
+                if inits.peek().is_none() || member.ty.size() == 0 {
+                    break;
+                }
+                // the members of a volatile struct are volatile too
+                let member_type = match ty {
+                    ast::Type::Volatile(_) => member.ty.clone().volatile(),
+                    _ => member.ty.clone(),
+                };
+                element(
+                    &member_type,
+                    offset + member.offset,
+                    inits,
+                    scalars,
+                    source_meta,
+                )?;
+            }
+        }
+        _ => element(ty, offset, inits, scalars, source_meta)?,
+    }
+    Ok(())
+}
+
+/// A braced list can't have more initializers than what it initializes
+fn excess(
+    next: Option<(ast::Initializer, Span)>,
+    source_meta: &SourceMetadata,
+) -> Result<(), VarE> {
+    match next {
+        Some((_, span)) => {
+            Err(VarE::new(VarError::ExcessInitializers).with_source(span, source_meta))
+        }
+        None => Ok(()),
+    }
+}
+
+/// The bytes of the variable, if all its initializers are constant
+fn template(size: usize, scalars: &[ScalarInit], variables: &VariableTracker) -> Option<Vec<u8>> {
+    let mut bytes = vec![0; size];
+    for scalar in scalars {
+        write_scalar(&mut bytes, scalar, scalar_bits(scalar, variables)?);
+    }
+    Some(bytes)
+}
+
+/// The bits of the value a scalar starts with, if its initializer is constant
+fn scalar_bits(scalar: &ScalarInit, variables: &VariableTracker) -> Option<u64> {
+    Some(match scalar.ty.unqualified() {
+        ast::Type::Float => {
+            let value = expr::float_literal_value(&scalar.expr)
+                .or_else(|| Some(expr::reduce_expr(&scalar.expr, variables)? as f64))?;
+            (value as f32).to_bits().into()
+        }
+        ast::Type::Double => expr::float_literal_value(&scalar.expr)
+            .or_else(|| Some(expr::reduce_expr(&scalar.expr, variables)? as f64))?
+            .to_bits(),
+        ast::Type::Bool => (expr::reduce_expr(&scalar.expr, variables)? != 0).into(),
+        _ => expr::reduce_expr(&scalar.expr, variables)? as u64,
+    })
+}
+
+fn write_scalar(bytes: &mut [u8], scalar: &ScalarInit, value: u64) {
+    let size = scalar.ty.size();
+    bytes[scalar.offset..scalar.offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+}
+
+/// Copy a template into the variable with `memcpy`
+fn copy_template(
+    state: &mut IRGenState,
+    mut builder: BlockBuilder,
+    memory: Binding,
+    template: ReadOnlyData,
+    bindings: &mut BindingCounter,
+) -> BlockBuilder {
+    let size = template.bytes.len();
+    let destination = bindings.next_binding();
+    builder.assign(
+        destination,
+        Value::Address {
+            mem_binding: memory,
+        },
+    );
+    let source = bindings.next_binding();
+    builder.assign(
+        source,
+        Value::ReadOnlyAddress(state.add_read_only(template)),
+    );
+    let len = bindings.next_binding();
+    builder.assign(len, Value::Constant(size as i64));
+    let call = bindings.next_binding();
+    builder.assign(
+        call,
+        Value::Call {
+            function: "memcpy".into(),
+            args: vec![destination, source, len],
+        },
+    );
+    builder
+}
+
+/// Write zeros over the given bytes of the variable, with the widest stores that stay aligned
+fn store_zeros(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    memory: Binding,
+    range: core::ops::Range<usize>,
+) {
+    let zero = bindings.next_binding();
+    builder.assign(zero, Value::Constant(0));
+    let mut offset = range.start;
+    while offset < range.end && bindings.over_budget().is_none() {
+        let (width, byte_size) = widest_access(offset, range.end, 8);
+        let address = offset_address(builder, bindings, memory, offset);
+        builder.store(zero, address, byte_size);
+        offset += width;
+    }
+}
+
+/// The widest access at `offset`, of at most `max_width` bytes, that stays aligned and doesn't go
+/// past `end`, with its width
+pub fn widest_access(offset: usize, end: usize, max_width: usize) -> (usize, ByteSize) {
+    [
+        (8, ByteSize::U64),
+        (4, ByteSize::U32),
+        (2, ByteSize::I16),
+        (1, ByteSize::U8),
+    ]
+    .into_iter()
+    .find(|&(width, _)| width <= max_width && offset.is_multiple_of(width) && offset + width <= end)
+    .expect("a single byte always fits")
+}
+
+/// The address `offset` bytes into the variable
+pub fn offset_address(
+    builder: &mut BlockBuilder,
+    bindings: &mut BindingCounter,
+    memory: Binding,
+    offset: usize,
+) -> Binding {
+    if offset == 0 {
+        return memory;
+    }
+    let index = bindings.next_binding();
+    builder.assign(index, Value::Constant(offset as i64));
+    let address = bindings.next_binding();
+    builder.assign(
+        address,
+        Value::ElementAddress {
+            base: memory,
+            index,
+            element_size: 1,
+            bit_size: BitSize::Bit32,
+        },
+    );
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VarError;
+    use crate::intermediate::{Statement, Value};
+    use alloc::format;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::collections::HashMap;
+
+    /// The bytes the initializer of the only variable of `main` writes, or `None` for the ones it
+    /// leaves alone
+    fn initialized_bytes(declarations: &str, declaration: &str) -> Vec<Option<u8>> {
+        let source = format!("{declarations}int main() {{ {declaration} return 0; }}");
+        let meta = crate::error::SourceMetadata::new(&source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (module, _) = super::super::compile_program(program, &meta, Default::default())
+            .expect("the source is fine");
+
+        let mut bytes = Vec::new();
+        let mut constants = HashMap::new();
+        let mut offsets = HashMap::new();
+        for statement in &module.functions[0].ir.code[0].statements {
+            match statement {
+                Statement::Assign {
+                    index,
+                    value: Value::Allocate { size, .. },
+                } => {
+                    bytes = vec![None; *size];
+                    offsets.insert(*index, 0);
+                }
+                Statement::Assign {
+                    index,
+                    value: Value::Constant(constant),
+                } => {
+                    constants.insert(*index, *constant);
+                }
+                Statement::Assign {
+                    index,
+                    value:
+                        Value::ElementAddress {
+                            base,
+                            index: offset,
+                            element_size: 1,
+                            ..
+                        },
+                } => {
+                    offsets.insert(*index, offsets[base] + constants[offset] as usize);
+                }
+                Statement::Store {
+                    mem_binding,
+                    binding,
+                    byte_size,
+                    ..
+                } => {
+                    let width = match byte_size {
+                        super::ByteSize::U8 | super::ByteSize::I8 => 1,
+                        super::ByteSize::I16 | super::ByteSize::U16 => 2,
+                        super::ByteSize::U32 | super::ByteSize::F32 => 4,
+                        super::ByteSize::U64 | super::ByteSize::F64 => 8,
+                    };
+                    let start = offsets[mem_binding];
+                    let value = constants[binding].to_le_bytes();
+                    for (byte, value) in bytes[start..start + width].iter_mut().zip(value) {
+                        *byte = Some(value);
+                    }
+                }
+                _ => (),
+            }
+        }
+        bytes
+    }
+
+    fn words(words: &[u32]) -> Vec<Option<u8>> {
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .map(Some)
+            .collect()
+    }
+
+    #[test]
+    fn brace_initializers_zero_what_they_leave_out() {
+        let point = "struct p { int x; int y; };\n";
+        assert_eq!(
+            initialized_bytes("", "int a[5] = {1, 2};"),
+            words(&[1, 2, 0, 0, 0])
+        );
+        assert_eq!(
+            initialized_bytes(point, "struct p p = {7};"),
+            words(&[7, 0])
+        );
+        // inner braces can be left out, taking as many values as the aggregate needs
+        assert_eq!(
+            initialized_bytes(point, "struct p ps[3] = {{1, 2}, {3}};"),
+            words(&[1, 2, 3, 0, 0, 0])
+        );
+        assert_eq!(
+            initialized_bytes(point, "struct p ps[3] = {1, 2, 3};"),
+            words(&[1, 2, 3, 0, 0, 0])
+        );
+        // padding is zeroed too
+        let mixed = "struct m { char c; int i; char d[3]; };\n";
+        let mut expected = words(&[5, 6, 0]);
+        expected[8] = Some(7);
+        assert_eq!(
+            initialized_bytes(mixed, "struct m m = {5, 6, {7}};"),
+            expected
+        );
+        // a scalar may have braces of its own
+        assert_eq!(initialized_bytes("", "int i = {3};"), words(&[3]));
+    }
+
+    #[test]
+    fn large_constant_initializers_are_copied_from_a_template() {
+        let source = "int main() { int a[16] = {1, 2}; return a[1]; }";
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (module, _) = super::super::compile_program(program, &meta, Default::default())
+            .expect("the source is fine");
+        let function = &module.functions[0];
+        let [template] = function.read_only.as_slice() else {
+            panic!("there's a single template");
+        };
+        let mut expected = vec![0; 64];
+        (expected[0], expected[4]) = (1, 2);
+        assert_eq!(template.bytes, expected);
+        assert_eq!(template.align, 4);
+        assert!(function.ir.code[0]
+            .statements
+            .iter()
+            .any(|statement| matches!(
+                statement,
+                Statement::Assign {
+                    value: Value::Call { function, .. },
+                    ..
+                } if function == "memcpy"
+            )));
+    }
+
+    #[test]
+    fn initializers_must_fit_what_they_initialize() {
+        let error = |declaration: &str| {
+            let source = format!("struct p {{ int x; int y; }};\nint main() {{ {declaration} }}");
+            let meta = crate::error::SourceMetadata::new(&source);
+            let program = crate::grammar::Parser::new(&meta)
+                .parse()
+                .expect("the source parses");
+            super::super::compile_program(program, &meta, Default::default())
+                .err()
+                .map(|error| error.kind)
+        };
+        assert!(error("int a[2] = {1, 2}; return 0;").is_none());
+        for declaration in [
+            "int a[2] = {1, 2, 3}; return 0;",
+            "struct p p = {1, 2, 3}; return 0;",
+            "struct p ps[1] = {{1}, {2}}; return 0;",
+            "int i = {1, 2}; return 0;",
+        ] {
+            assert!(
+                matches!(error(declaration), Some(VarError::ExcessInitializers)),
+                "{declaration}"
+            );
+        }
+        assert!(matches!(
+            error("int a[2] = 1; return 0;"),
+            Some(VarError::ArrayInitializer)
+        ));
+        assert!(matches!(
+            error("int i = {{1}}; return 0;"),
+            Some(VarError::ScalarBraces)
+        ));
+    }
+}
@@ -1,18 +1,32 @@
-use std::collections::HashMap;
-use std::fmt;
-use std::mem::MaybeUninit;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::mem::MaybeUninit;
+use hashbrown::{HashMap, HashSet};
 
 use super::{
-    BasicBlock, Binding, BlockBinding, BranchingMap, ByteSize, Condition, IRCode, Statement, Value,
-    IR,
+    BasicBlock, Binding, BlockBinding, BranchingMap, ByteSize, Condition, IRCode, ModuleMetadata,
+    Statement, Value, IR,
 };
-use crate::error::SourceMetadata;
+use crate::codegen::assembly::BitSize;
+use crate::error::{SourceMetadata, WarningOptions};
 use crate::grammar::lexer::Source;
-use crate::intermediate::{BlockEnd, Branch, PhiDescriptor};
+use crate::intermediate::{analysis::BindingUsage, BlockEnd, Branch, PhiDescriptor};
 use crate::{ast, error};
+use symbols::{SymbolIndex, SymbolKind, SymbolRecorder};
+mod aggregate;
+mod atomic;
 mod block;
 mod expr;
+mod initializer;
 mod statement;
+pub mod symbols;
+pub(crate) use atomic::MEMORY_ORDERS;
+pub(crate) use expr::reduce_expr;
 use thiserror::Error;
 
 pub fn generate_branching_graphs(ir: &IRCode) -> (BranchingMap, BranchingMap) {
@@ -42,18 +56,875 @@ impl From<Vec<BasicBlock>> for IR {
     }
 }
 
+/// Arguments are passed in `x0`-`x7`; passing them through the stack is not supported yet.
+pub const MAX_REGISTER_ARGUMENTS: usize = 8;
+
+/// Structs of up to this many bytes are passed and returned in general purpose registers, a word
+/// in each. Larger ones are passed as a pointer to a copy, and returned to the address the caller
+/// passes in `x8`.
+pub const MAX_STRUCT_IN_REGISTERS: usize = 16;
+
+/// The strictest alignment that `_Alignas` can ask for, the same as GCC's
+pub const MAX_ALIGN: i64 = 1 << 28;
+
+/// A function lowered to IR, along with what the backend needs to know to emit it
+#[derive(Clone)]
+pub struct FunctionIR<'code> {
+    pub name: &'code str,
+    /// where the name of the definition is
+    pub span: error::Span,
+    pub linkage: ast::Linkage,
+    /// whether any declaration of it says `inline`, for its calls to be replaced by its body
+    pub inline: bool,
+    pub ir: IR,
+    /// the read-only data that the code refers to by index, each of it different
+    pub read_only: Vec<ReadOnlyData>,
+    /// the read-only data that is the same as an earlier function's, and is emitted only there:
+    /// the index of the data, to the index of that function in the module and of the data in it
+    pub merged_read_only: HashMap<usize, (usize, usize)>,
+    /// the source statement each binding was made for, which passes may leave out of date for
+    /// the bindings they make
+    pub spans: HashMap<Binding, error::Span>,
+    /// the variable each binding holds the memory of, or for a parameter also the argument it was
+    /// passed in, to name the bindings of the IR with [`IR::named`]
+    pub variables: HashMap<Binding, String>,
+    /// the optimizations that changed the function, in the order they first did, which
+    /// `-fverbose-asm` lists
+    pub optimizations: Vec<&'static str>,
+    /// what the passes did to the function and why, or why they couldn't, for `-fopt-remarks`
+    pub remarks: Vec<super::remarks::Remark>,
+}
+
+impl FunctionIR<'_> {
+    /// Keep in mind that an optimization changed the function, unless one already did
+    pub fn note_optimization(&mut self, optimization: &'static str) {
+        if !self.optimizations.contains(&optimization) {
+            self.optimizations.push(optimization);
+        }
+    }
+}
+
+/// Bytes the code only reads, like the templates that large initializers are copied from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOnlyData {
+    pub bytes: Vec<u8>,
+    pub align: usize,
+}
+
+/// A variable defined at file scope, with the bytes it starts with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalData<'code> {
+    pub name: &'code str,
+    pub linkage: ast::Linkage,
+    pub bytes: Vec<u8>,
+    pub align: usize,
+}
+
+impl GlobalData<'_> {
+    /// Whether it starts zeroed, so that only its size has to be reserved for it
+    pub fn is_zeroed(&self) -> bool {
+        self.bytes.iter().all(|&byte| byte == 0)
+    }
+}
+
+/// The functions of a translation unit, along with what it was compiled from
+pub struct Module<'code> {
+    pub metadata: ModuleMetadata,
+    pub functions: Vec<FunctionIR<'code>>,
+    /// the variables defined at file scope, in the order they were first defined
+    pub globals: Vec<GlobalData<'code>>,
+    /// the registers that hold global register variables, which no function may allocate
+    pub reserved_registers: Vec<u8>,
+    /// the functions defined as other names for functions of the module
+    pub aliases: Vec<Alias<'code>>,
+    /// the functions declared `weak` but not defined, which may be left undefined when linking
+    pub weak_references: Vec<&'code str>,
+    /// where each name is declared and used
+    pub symbols: SymbolIndex,
+}
+
+impl Module<'_> {
+    /// Have each function refer to the read-only data of the earlier functions that is the same as
+    /// its own, instead of emitting it again (`-fmerge-all-constants`). The data of a function is
+    /// already different from the rest of it.
+    pub fn merge_constants(&mut self) {
+        let mut emitted: HashMap<Vec<u8>, (usize, usize)> = HashMap::new();
+        for index in 0..self.functions.len() {
+            for num in 0..self.functions[index].read_only.len() {
+                let ReadOnlyData { bytes, align } = self.functions[index].read_only[num].clone();
+                match emitted.get(&bytes) {
+                    Some(&(owner, owner_num)) => {
+                        let owned = &mut self.functions[owner].read_only[owner_num];
+                        owned.align = owned.align.max(align);
+                        self.functions[index]
+                            .merged_read_only
+                            .insert(num, (owner, owner_num));
+                    }
+                    None => {
+                        emitted.insert(bytes, (index, num));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A function declared with `__attribute__((alias("target")))`, another symbol for `target`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alias<'code> {
+    pub name: &'code str,
+    pub target: &'code str,
+    pub linkage: ast::Linkage,
+}
+
+/// Options that change how the source is translated
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenerateOptions {
+    pub standard: ast::Standard,
+    pub warnings: WarningOptions,
+    /// The most bindings the IR of a function may have, if there's a limit. Past it the
+    /// generation stops with an error, instead of going on with code that makes too much IR (like
+    /// zeroing a huge array one store at a time).
+    pub binding_budget: Option<usize>,
+    /// Treat the names of [`BUILTIN_FUNCTIONS`] like any other, as `-fno-builtin` asks: they have
+    /// to be declared before they're called, and may return. Freestanding code that defines them
+    /// its own way needs it.
+    pub no_builtin: bool,
+}
+
+/// Whether `main` takes one of the parameter lists the standard defines: none, or the argument
+/// count and vector that the startup code passes in `x0` and `x1`. Those reach the parameters like
+/// any other function's arguments.
+fn is_standard_main(function: &ast::Function) -> bool {
+    let argv = ast::Type::Pointer(Box::new(ast::Type::Pointer(Box::new(ast::Type::Char))));
+    if function.return_type != Some(ast::Type::Int) {
+        return false;
+    }
+    match function.params.as_slice() {
+        [] => true,
+        [argc, argv_param] => argc.ty == ast::Type::Int && argv_param.ty == argv,
+        _ => false,
+    }
+}
+
+/// Compile all the functions of a program, in order, and the global variables they use.
+pub fn compile_program<'code>(
+    program: ast::Program<'code>,
+    source_meta: &SourceMetadata<'code>,
+    options: GenerateOptions,
+) -> Result<(Module<'code>, Vec<VarW>), VarE> {
+    let warning_options = options.warnings;
+    // global register variables and the enumerators of file scope enums
+    let mut file_scope = VariableTracker::new();
+    let mut symbols = SymbolRecorder::default();
+    for ast::GlobalRegister { ty, name, register } in program.global_registers {
+        let index =
+            crate::allocators::registers::global_register(register.source).ok_or_else(|| {
+                VarE::new(VarError::InvalidGlobalRegister(register.source.to_string()))
+                    .with_source(register.span, source_meta)
+            })?;
+        if !matches!(ty, ast::Type::Int | ast::Type::Pointer(_)) {
+            return Err(
+                VarE::new(VarError::GlobalRegisterType(ty)).with_source(name.span, source_meta)
+            );
+        }
+        let globals = file_scope.scope_mut(None);
+        if globals.contains_key(name.source) {
+            return Err(VarE::new(VarError::Redeclared(name.source.to_string()))
+                .with_source(name.span, source_meta));
+        }
+        if globals
+            .values()
+            .any(|symbol| matches!(symbol, Symbol::Register(other) if other.register == index))
+        {
+            return Err(
+                VarE::new(VarError::RegisterTaken(register.source.to_string()))
+                    .with_source(register.span, source_meta),
+            );
+        }
+        globals.insert(
+            name.source,
+            Symbol::Register(RegisterVariable {
+                register: index,
+                ty,
+                declared_at: name.span,
+            }),
+        );
+        symbols.define(name.source, SymbolKind::GlobalRegister, name.span);
+    }
+    for enumerators in program.enums {
+        declare_enumerators(
+            enumerators,
+            &mut file_scope,
+            None,
+            &mut symbols,
+            source_meta,
+        )?;
+    }
+    let mut globals = core::mem::take(file_scope.scope_mut(None));
+    // the variables at file scope are declared in between the functions, each one visible to the
+    // functions after it
+    let mut global_variables = program.globals.into_iter().peekable();
+    let mut global_definitions = GlobalDefinitions::default();
+    let mut definitions: HashMap<&str, (ast::Linkage, error::Span)> = HashMap::new();
+    // a function declared `static` once has internal linkage everywhere, one declared `weak` once
+    // is weak everywhere, and one declared `inline` once is inline everywhere
+    let mut internal = HashSet::new();
+    let mut weak = HashMap::new();
+    let mut inline = HashSet::new();
+    // an alias defines the function too, as another name for its target
+    let mut alias_targets = Vec::new();
+    for function in &program.functions {
+        let Source { source: name, span } = function.name;
+        if function.linkage == ast::Linkage::Internal {
+            internal.insert(name);
+        }
+        if function.inline {
+            inline.insert(name);
+        }
+        let mut is_definition = function.body.is_some();
+        for &(attribute, attribute_span) in &function.attributes {
+            match attribute {
+                ast::Attribute::Weak => {
+                    weak.entry(name).or_insert(attribute_span);
+                }
+                ast::Attribute::Alias(target) => {
+                    if is_definition {
+                        return Err(VarE::new(VarError::FunctionRedefined(name.to_string()))
+                            .with_source(span, source_meta));
+                    }
+                    is_definition = true;
+                    alias_targets.push((function.name, target));
+                }
+            }
+        }
+        if is_definition && definitions.insert(name, (function.linkage, span)).is_some() {
+            return Err(VarE::new(VarError::FunctionRedefined(name.to_string()))
+                .with_source(span, source_meta));
+        }
+    }
+    if definitions.is_empty() {
+        return Err(VarE::new(VarError::NoFunctions));
+    }
+    for (name, (linkage, _)) in definitions.iter_mut() {
+        if internal.contains(name) {
+            *linkage = ast::Linkage::Internal;
+        } else if weak.contains_key(name) {
+            *linkage = ast::Linkage::Weak;
+        }
+    }
+    if let Some((name, span)) = weak
+        .iter()
+        .filter(|(name, _)| internal.contains(*name))
+        .min_by_key(|(_, span)| span.offset)
+    {
+        return Err(
+            VarE::new(VarError::WeakInternal(name.to_string())).with_source(*span, source_meta)
+        );
+    }
+    let mut aliases = Vec::with_capacity(alias_targets.len());
+    for (name, target) in alias_targets {
+        if !definitions.contains_key(target.source) {
+            return Err(
+                VarE::new(VarError::AliasUndefined(target.source.to_string()))
+                    .with_source(target.span, source_meta),
+            );
+        }
+        aliases.push(Alias {
+            name: name.source,
+            target: target.source,
+            linkage: definitions[name.source].0,
+        });
+        symbols.refer_function(target);
+    }
+    // a function is declared where it's defined, and its other declarations refer to that
+    let mut first_declarations = HashSet::new();
+    for function in &program.functions {
+        let Source { source: name, span } = function.name;
+        let defined_at = definitions.get(name).map_or(span, |(_, span)| *span);
+        if first_declarations.insert(name) {
+            symbols.define(name, SymbolKind::Function, defined_at);
+        }
+        if span != defined_at {
+            symbols.refer_function(function.name);
+        }
+    }
+    let mut weak_references: Vec<&str> = weak
+        .into_iter()
+        .filter(|(name, _)| !definitions.contains_key(name))
+        .map(|(name, _)| name)
+        .collect();
+    weak_references.sort_unstable();
+
+    let mut warnings = Vec::new();
+    let mut functions = Vec::with_capacity(definitions.len());
+    // functions can only be called once they have been declared, apart from the builtin ones
+    let builtins = if options.no_builtin {
+        &[]
+    } else {
+        BUILTIN_FUNCTIONS
+    };
+    let mut declared: DeclaredFunctions = builtins
+        .iter()
+        .map(|&(name, declaration)| (name.to_string(), declaration))
+        .collect();
+    for mut function in program.functions {
+        let Source { source: name, span } = function.name;
+        while let Some(variable) =
+            global_variables.next_if(|variable| variable.declarator.name.span.offset < span.offset)
+        {
+            global_definitions.declare(variable, &mut globals, &mut symbols, source_meta)?;
+        }
+        let returns_void = function.return_type.is_none();
+        if function.params_unspecified && warning_options.strict_prototypes {
+            warnings.push(
+                VarW::new(VarWarning::StrictPrototypes(name.to_string()))
+                    .with_source(span, source_meta),
+            );
+        }
+        for ty in function
+            .return_type
+            .iter()
+            .chain(function.params.iter().map(|param| &param.ty))
+        {
+            aggregate::check_passable(ty).map_err(|e| e.with_source(span, source_meta))?;
+        }
+        let params = (!function.params_unspecified).then_some(function.params.len());
+        match declared.get_mut(name) {
+            Some(previous) => {
+                if previous.returns_void != returns_void {
+                    return Err(VarE::new(VarError::ConflictingReturnType(name.to_string()))
+                        .with_source(span, source_meta));
+                }
+                match (previous.params, params) {
+                    (Some(previous_params), Some(params))
+                        if previous_params != params || previous.variadic != function.variadic =>
+                    {
+                        return Err(
+                            VarE::new(VarError::ConflictingDeclaration(name.to_string()))
+                                .with_source(span, source_meta),
+                        );
+                    }
+                    (None, Some(_)) => {
+                        previous.params = params;
+                        previous.variadic = function.variadic;
+                    }
+                    _ => (),
+                }
+            }
+            None => {
+                declared.insert(
+                    name.to_string(),
+                    Declaration {
+                        params,
+                        variadic: function.variadic,
+                        returns_void,
+                    },
+                );
+            }
+        }
+        // from its declaration on, the name of a function is a pointer to it
+        match globals.get(name) {
+            Some(Symbol::Function(previous)) if previous.return_type != function.return_type => {
+                return Err(VarE::new(VarError::ConflictingReturnType(name.to_string()))
+                    .with_source(span, source_meta));
+            }
+            Some(Symbol::Function(_)) | None => {
+                globals.insert(name, Symbol::Function(ast::FunctionType::of(&function)));
+            }
+            Some(_) => {
+                return Err(VarE::new(VarError::Redeclared(name.to_string()))
+                    .with_source(span, source_meta))
+            }
+        }
+        if warning_options.main && name == "main" && !is_standard_main(&function) {
+            warnings.push(VarW::new(VarWarning::MainSignature).with_source(span, source_meta));
+        }
+        if function.body.is_none() {
+            continue;
+        }
+        function.linkage = definitions[function.name.source].0;
+        function.inline = inline.contains(name);
+        let (function, function_warnings) = compile_function(
+            function,
+            source_meta,
+            options,
+            &mut declared,
+            &mut symbols,
+            &globals,
+        )?;
+        warnings.extend(function_warnings);
+        functions.push(function);
+    }
+
+    for variable in global_variables {
+        global_definitions.declare(variable, &mut globals, &mut symbols, source_meta)?;
+    }
+
+    if warning_options.infinite_recursion {
+        let noreturn = if options.no_builtin {
+            &[]
+        } else {
+            NORETURN_FUNCTIONS
+        };
+        warnings.extend(find_infinite_recursion(
+            &functions,
+            (&definitions, noreturn),
+            source_meta,
+        ));
+    }
+
+    if warning_options.unused_function {
+        // a function is used if it can be reached from outside the file: from one with external
+        // linkage, or through an alias, by calling it or taking its address, which it may be
+        // called through. Functions only calling each other are still unused. Functions defined
+        // `static inline` are meant to be left unused by some of the files that define them, like
+        // those of a header, so what they use counts as used.
+        let references: HashMap<&str, Vec<&str>> = functions
+            .iter()
+            .map(|function| {
+                let referenced = super::analysis::called_functions(&function.ir)
+                    .chain(super::analysis::addressed_functions(&function.ir));
+                (function.name, referenced.collect())
+            })
+            .collect();
+        let mut pending: Vec<&str> = definitions
+            .iter()
+            .filter(|(name, (linkage, _))| {
+                *linkage != ast::Linkage::Internal || inline.contains(*name)
+            })
+            .map(|(name, _)| *name)
+            .chain(aliases.iter().map(|alias| alias.target))
+            .collect();
+        let mut reached = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if reached.insert(name) {
+                pending.extend(references.get(name).into_iter().flatten());
+            }
+        }
+        let mut unused: Vec<_> = definitions
+            .into_iter()
+            .filter(|(name, (linkage, _))| {
+                *linkage == ast::Linkage::Internal
+                    && !reached.contains(name)
+                    && !inline.contains(name)
+            })
+            .collect();
+        unused.sort_unstable_by_key(|(_, (_, span))| span.offset);
+        warnings.extend(unused.into_iter().map(|(name, (_, span))| {
+            VarW::new(VarWarning::UnusedFunction(name.to_string())).with_source(span, source_meta)
+        }));
+    }
+
+    let metadata = ModuleMetadata {
+        source_file: source_meta.file().map(ToString::to_string),
+        target: crate::codegen::target::cpu().directive(),
+        options: [
+            format!("--std={}", options.standard),
+            format!("--target={}", crate::codegen::target::current().name()),
+        ]
+        .into_iter()
+        .chain((crate::codegen::target::cpu() != Default::default()).then(|| {
+            format!("-march={}", crate::codegen::target::cpu().directive())
+        }))
+        .chain(options.no_builtin.then(|| "-fno-builtin".to_string()))
+        .collect(),
+    };
+    let mut reserved_registers: Vec<u8> = globals
+        .into_values()
+        .filter_map(|symbol| match symbol {
+            Symbol::Register(variable) => Some(variable.register),
+            _ => None,
+        })
+        .collect();
+    reserved_registers.sort_unstable();
+    Ok((
+        Module {
+            metadata,
+            functions,
+            globals: global_definitions.finish(),
+            reserved_registers,
+            aliases,
+            weak_references,
+            symbols: symbols.finish(source_meta),
+        },
+        warnings,
+    ))
+}
+
+/// The global variables defined so far, and the ones declared `static`
+#[derive(Default)]
+struct GlobalDefinitions<'code> {
+    defined: Vec<GlobalData<'code>>,
+    /// where each variable is in `defined`, and whether it was defined with an initializer rather
+    /// than tentatively, like `int x;`, which makes it zero unless another definition initializes
+    /// it
+    indices: HashMap<&'code str, (usize, bool)>,
+    internal: HashSet<&'code str>,
+}
+
+impl<'code> GlobalDefinitions<'code> {
+    /// Declare a variable at file scope, which may be declared many times with the same type. A
+    /// declaration with an initializer defines it, and so do the ones that aren't `extern`.
+    fn declare(
+        &mut self,
+        ast::GlobalVariable {
+            declarator: ast::Declarator { ty, name, init },
+            linkage,
+            is_extern,
+            align,
+        }: ast::GlobalVariable<'code>,
+        globals: &mut VariableMemories<'code>,
+        symbols: &mut SymbolRecorder,
+        source_meta: &SourceMetadata,
+    ) -> Result<(), VarE> {
+        let Source { source, span } = name;
+        match globals.get(source) {
+            Some(Symbol::Global(previous)) if previous.ty != ty => {
+                return Err(
+                    VarE::new(VarError::ConflictingGlobalType(source.to_string()))
+                        .with_source(span, source_meta),
+                );
+            }
+            Some(previous @ Symbol::Global(_)) => symbols.refer(name, Some(previous)),
+            Some(_) => {
+                return Err(VarE::new(VarError::Redeclared(source.to_string()))
+                    .with_source(span, source_meta));
+            }
+            None => {
+                globals.insert(
+                    source,
+                    Symbol::Global(GlobalVariable {
+                        ty: ty.clone(),
+                        declared_at: span,
+                    }),
+                );
+                symbols.define(source, SymbolKind::GlobalVariable, span);
+            }
+        }
+        if linkage == ast::Linkage::Internal {
+            self.internal.insert(source);
+        }
+        // `_Alignas` sees the variables declared so far, like the initializer
+        let variables = VariableTracker::with_globals(core::mem::take(globals));
+        let align = statement::declared_align(&ty, source, &align, &variables, source_meta);
+        *globals = variables.globals;
+        let align = align?;
+        let initialized = init.is_some();
+        let bytes = match init {
+            Some(init) => {
+                if let Some((_, true)) = self.indices.get(source) {
+                    return Err(VarE::new(VarError::VariableRedefined(source.to_string()))
+                        .with_source(span, source_meta));
+                }
+                // the initializer sees the variables declared so far, this one included
+                let variables = VariableTracker::with_globals(core::mem::take(globals));
+                let bytes =
+                    initializer::constant_bytes(&ty, init, &variables, symbols, source_meta);
+                *globals = variables.globals;
+                bytes?
+            }
+            None if is_extern => return Ok(()),
+            None => vec![0; ty.size()],
+        };
+        match self.indices.get_mut(source) {
+            Some((index, defined)) => {
+                let global = &mut self.defined[*index];
+                global.align = global.align.max(align);
+                if initialized {
+                    global.bytes = bytes;
+                    *defined = true;
+                }
+            }
+            None => {
+                self.indices
+                    .insert(source, (self.defined.len(), initialized));
+                self.defined.push(GlobalData {
+                    name: source,
+                    linkage: ast::Linkage::External,
+                    bytes,
+                    align,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The variables defined, which have internal linkage if any of their declarations is
+    /// `static`
+    fn finish(mut self) -> Vec<GlobalData<'code>> {
+        for global in &mut self.defined {
+            if self.internal.contains(global.name) {
+                global.linkage = ast::Linkage::Internal;
+            }
+        }
+        self.defined
+    }
+}
+
+/// Declare the enumerators of an enum in the scope at `depth` (or file scope), in order, since each
+/// one may use the ones before it
+pub fn declare_enumerators<'code>(
+    enumerators: Vec<ast::Enumerator<'code>>,
+    variables: &mut VariableTracker<'code>,
+    depth: Option<usize>,
+    symbols: &mut SymbolRecorder,
+    source_meta: &SourceMetadata,
+) -> Result<(), VarE> {
+    let mut next = 0;
+    for ast::Enumerator { name, value } in enumerators {
+        let value = match value {
+            Some((expr, span)) => {
+                symbols.refer_all(&expr, variables);
+                expr::reduce_expr(&expr, variables).ok_or_else(|| {
+                    VarE::new(VarError::NonConstantEnumerator(name.source.to_string()))
+                        .with_source(span, source_meta)
+                })?
+            }
+            None => next,
+        };
+        if !ast::Type::Int.can_represent(value) {
+            return Err(
+                VarE::new(VarError::EnumeratorRange(name.source.to_string()))
+                    .with_source(name.span, source_meta),
+            );
+        }
+        let scope = variables.scope_mut(depth);
+        if scope.contains_key(name.source) {
+            return Err(VarE::new(VarError::Redeclared(name.source.to_string()))
+                .with_source(name.span, source_meta));
+        }
+        scope.insert(
+            name.source,
+            Symbol::Constant {
+                value: value as i32,
+                declared_at: name.span,
+            },
+        );
+        symbols.define(name.source, SymbolKind::Enumerator, name.span);
+        next = value + 1;
+    }
+    Ok(())
+}
+
+/// Find the functions that can never return because, whatever path they take, they end up
+/// calling themselves again (directly or through other functions that must call them). A path
+/// that calls one of the `noreturn` functions doesn't return.
+fn find_infinite_recursion(
+    functions: &[FunctionIR],
+    (definitions, noreturn): (&HashMap<&str, (ast::Linkage, error::Span)>, &[&str]),
+    source_meta: &SourceMetadata,
+) -> Vec<VarW> {
+    // a function `f` must call `g` if every path through `f` calls `g`
+    let must_call: HashMap<&str, HashSet<&str>> = functions
+        .iter()
+        .map(|function| {
+            (
+                function.name,
+                super::analysis::unconditionally_called_functions(&function.ir, noreturn)
+                    .collect(),
+            )
+        })
+        .collect();
+
+    functions
+        .iter()
+        .filter(|function| {
+            // a cycle in the must-call graph through this function
+            let mut visited = HashSet::new();
+            let mut queue: Vec<&str> = must_call[function.name].iter().copied().collect();
+            while let Some(next) = queue.pop() {
+                if next == function.name {
+                    return true;
+                }
+                if visited.insert(next) {
+                    queue.extend(must_call.get(next).into_iter().flatten().copied());
+                }
+            }
+            false
+        })
+        .map(|function| {
+            VarW::new(VarWarning::InfiniteRecursion(function.name.to_string()))
+                .with_source(definitions[function.name].1, source_meta)
+        })
+        .collect()
+}
+
+/// What the declarations of a function so far say about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Declaration {
+    /// the number of parameters, or `None` if it was declared without specifying them
+    pub params: Option<usize>,
+    /// whether calls may pass more arguments than there are parameters
+    pub variadic: bool,
+    /// whether it returns `void`, so that calling it has no value
+    pub returns_void: bool,
+}
+
+/// The functions that can be called
+pub type DeclaredFunctions = HashMap<String, Declaration>;
+
+/// Library functions that can be called without declaring them. Declaring them again has to agree
+/// with what they are declared as here.
+pub const BUILTIN_FUNCTIONS: &[(&str, Declaration)] = &[
+    // `void exit(int status)` ends the process from anywhere, as returning `status` from `main`
+    // does. The startup code calls it with what `main` returns.
+    (
+        "exit",
+        Declaration {
+            params: Some(1),
+            variadic: false,
+            returns_void: true,
+        },
+    ),
+];
+
+/// Functions that never return to their caller
+pub const NORETURN_FUNCTIONS: &[&str] = &["exit"];
+
+/// Compile the definition of a function. Calls to functions not in `declared_functions` are
+/// implicit declarations, which (before C99) declare the function from then on.
 pub fn compile_function<'code>(
     f: ast::Function<'code>,
     source_meta: &SourceMetadata<'code>,
-) -> Result<(&'code str, IR), VarE> {
+    options: GenerateOptions,
+    declared_functions: &mut DeclaredFunctions,
+    symbols: &mut SymbolRecorder,
+    globals: &VariableMemories<'code>,
+) -> Result<(FunctionIR<'code>, Vec<VarW>), VarE> {
     let ast::Function {
-        name: ast::Identifier(name),
-        body: ast::Block { statements },
+        name: Source {
+            source: name,
+            span: name_span,
+        },
+        linkage,
+        inline,
+        return_type,
+        params,
+        variadic,
+        body,
+        ..
     } = f;
-    let mut state = IRGenState::default();
-    let mut binding_counter = BindingCounter::default();
-    let mut env = VariableTracker::default();
-    let entry = state.new_block();
+    let ast::Block { statements } = body.expect("prototypes have nothing to compile");
+    if variadic {
+        return Err(VarE::new(VarError::VariadicDefinition(name.to_string()))
+            .with_source(name_span, source_meta));
+    }
+    if params.len() > MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyParameters(name.to_string()))
+            .with_source(name_span, source_meta));
+    }
+    let mut state = IRGenState {
+        warning_options: options.warnings,
+        standard: options.standard,
+        declared_functions: core::mem::take(declared_functions),
+        symbols: core::mem::take(symbols),
+        return_type: return_type.clone(),
+        ..IRGenState::default()
+    };
+    // the code that isn't part of any statement, like storing the parameters, comes from the
+    // definition itself
+    let mut binding_counter = BindingCounter {
+        source: Some(name_span),
+        budget: options.binding_budget,
+        ..BindingCounter::default()
+    };
+    let mut env = VariableTracker::with_globals(globals.clone());
+    let mut entry = state.new_block();
+
+    // a struct returned through memory goes where `x8` points when the function is called, which
+    // is kept before anything else can overwrite it
+    if let Some(ty @ ast::Type::Struct(_)) = return_type {
+        let result_address = (ty.size() > MAX_STRUCT_IN_REGISTERS).then(|| {
+            let memory = binding_counter.next_binding();
+            let address = binding_counter.next_binding();
+            entry.allocate(memory, 8, 8);
+            entry.assign(address, Value::ResultAddress { size: ty.size() });
+            entry.store(address, memory, ByteSize::U64);
+            memory
+        });
+        state.returned_struct = Some((ty, result_address));
+    }
+
+    // parameters live in memory like any other variable, in the outermost scope of the body
+    let mut param_memories = Vec::with_capacity(params.len());
+    // the bindings of the parameters, which aren't in `locals`
+    let mut variables = HashMap::new();
+    // integers and floating-point values are passed in different registers, each counted apart
+    let (mut integers, mut floats) = (0, 0);
+    // the structs passed as a pointer to them, copied once all the arguments have been read, since
+    // a copy may call `memcpy`
+    let mut copies = Vec::new();
+    for ast::Parameter { ty, name } in params {
+        let memory = binding_counter.next_binding();
+        let arguments = match ty.unqualified() {
+            ast::Type::Struct(_) if ty.size() <= MAX_STRUCT_IN_REGISTERS => {
+                let arguments = aggregate::receive_in_registers(
+                    &mut entry,
+                    &mut binding_counter,
+                    (memory, &ty),
+                    integers,
+                );
+                integers += arguments.len();
+                arguments
+            }
+            ast::Type::Struct(_) => {
+                let argument = binding_counter.next_binding();
+                entry.allocate(memory, ty.size(), ty.align());
+                entry.assign(argument, Value::Argument(integers));
+                integers += 1;
+                copies.push((argument, memory, ty.clone()));
+                vec![argument]
+            }
+            _ => {
+                let argument = binding_counter.next_binding();
+                entry.allocate(memory, ty.size(), ty.align());
+                entry.assign(
+                    argument,
+                    if ty.is_floating() {
+                        floats += 1;
+                        Value::FloatArgument(floats - 1)
+                    } else {
+                        integers += 1;
+                        Value::Argument(integers - 1)
+                    },
+                );
+                entry.store_object(argument, memory, &ty);
+                vec![argument]
+            }
+        };
+        let scope = env.variables_at_depth(0);
+        if scope.contains_key(name.source) {
+            return Err(VarE::new(VarError::Redeclared(name.source.to_string()))
+                .with_source(name.span, source_meta));
+        }
+        scope.insert(
+            name.source,
+            Symbol::Variable(Variable {
+                memory,
+                ty,
+                declared_at: name.span,
+            }),
+        );
+        state
+            .symbols
+            .define(name.source, SymbolKind::Parameter, name.span);
+        variables.insert(memory, name.source.to_string());
+        for argument in arguments {
+            variables.insert(argument, name.source.to_string());
+        }
+        param_memories.push((memory, name));
+    }
+    if integers > MAX_REGISTER_ARGUMENTS {
+        return Err(VarE::new(VarError::TooManyParameters(name.to_string()))
+            .with_source(name_span, source_meta));
+    }
+    for (pointer, memory, ty) in copies {
+        aggregate::copy_memory(&mut entry, &mut binding_counter, (pointer, memory), &ty);
+    }
+    // the statements the entry starts with set the parameters up, storing what was passed
+    let setup = (entry.block_id, entry.current_block.len());
+
     let mut end = block::compile_block(
         &mut state,
         entry,
@@ -63,9 +934,18 @@ pub fn compile_function<'code>(
         0,
         source_meta,
     )?;
+    // reaching the end of `main` returns 0 since C99. Other functions do the same, although using
+    // their value is undefined then, and `void` ones leave the 0 in a register no one reads.
     let ret = binding_counter.next_binding();
     end.assign(ret, 0);
     end.finish_block(&mut state, ret);
+    *declared_functions = core::mem::take(&mut state.declared_functions);
+    *symbols = core::mem::take(&mut state.symbols);
+    let mut warnings = core::mem::take(&mut state.warnings);
+    let conditions = core::mem::take(&mut state.conditions);
+    let locals = core::mem::take(&mut state.locals);
+    let read_only = core::mem::take(&mut state.read_only);
+    let warning_options = state.warning_options;
     let ir: IRCode = state.release().collect();
     let (forward_map, backwards_map) = generate_branching_graphs(&ir);
 
@@ -75,12 +955,134 @@ pub fn compile_function<'code>(
         forward_map,
     };
 
+    // memory that was never written holds whatever was there before
+    let mut uninitialized = Vec::new();
+    for (address, memory) in super::analysis::uninitialized::uninitialized_loads(&ir) {
+        if let Statement::Assign { value, .. } =
+            &mut ir[address.block].statements[address.statement]
+        {
+            // undefined values are integers, so floating-point ones are frozen to zero right away.
+            // A volatile load is kept, as the memory could have changed by itself.
+            *value = match value {
+                Value::Load { volatile: true, .. } => value.clone(),
+                Value::Load {
+                    byte_size: ByteSize::F32,
+                    ..
+                } => Value::FloatConstant {
+                    bits: 0,
+                    bit_size: BitSize::Bit32,
+                },
+                Value::Load {
+                    byte_size: ByteSize::F64,
+                    ..
+                } => Value::FloatConstant {
+                    bits: 0,
+                    bit_size: BitSize::Bit64,
+                },
+                _ => Value::Undefined,
+            };
+        }
+        if let Some(local) = locals.get(&memory) {
+            uninitialized.push(local);
+        }
+    }
+    if warning_options.uninitialized {
+        uninitialized.sort_by_key(|(_, span)| span.offset);
+        uninitialized.dedup();
+        warnings.extend(uninitialized.into_iter().map(|(name, span)| {
+            VarW::new(VarWarning::Uninitialized(name.clone())).with_source(*span, source_meta)
+        }));
+    }
+
+    if warning_options.constant_condition {
+        warnings.extend(find_constant_conditions(&ir, &conditions, source_meta));
+    }
+
+    if warning_options.unused_parameter {
+        // a parameter is read if, once it's set up, anything but a store to it uses its memory:
+        // loading it, taking its address, storing that somewhere or passing it to a call
+        let mut used = HashSet::new();
+        for (index, block) in ir.code.iter().enumerate() {
+            let skipped = if index == setup.0 { setup.1 } else { 0 };
+            for statement in &block.statements[skipped..] {
+                match statement {
+                    Statement::Store { binding, .. } | Statement::AtomicStore { binding, .. } => {
+                        used.insert(*binding);
+                    }
+                    statement => used.extend(statement.binding_deps()),
+                }
+            }
+            match block.end {
+                BlockEnd::Return(binding)
+                | BlockEnd::Branch(Branch::Conditional { flag: binding, .. }) => {
+                    used.insert(binding);
+                }
+                BlockEnd::Branch(_) => (),
+            }
+        }
+        warnings.extend(
+            param_memories
+                .into_iter()
+                .filter(|(memory, _)| !used.contains(memory))
+                .map(|(_, Source { source, span })| {
+                    VarW::new(VarWarning::UnusedParameter(source.to_string()))
+                        .with_source(span, source_meta)
+                }),
+        );
+    }
+
     // run some cleanup on the generated code, because we might have generated
     // too much garbage
     super::cleanup::run_safe_cleanup(&mut ir);
     super::cleanup::prune_unreached_blocks(&mut ir);
 
-    Ok((name, ir))
+    variables.extend(locals.into_iter().map(|(memory, (name, _))| (memory, name)));
+    Ok((
+        FunctionIR {
+            name,
+            span: name_span,
+            linkage,
+            inline,
+            ir,
+            read_only,
+            merged_read_only: HashMap::new(),
+            spans: binding_counter.spans,
+            variables,
+            optimizations: Vec::new(),
+            remarks: Vec::new(),
+        },
+        warnings,
+    ))
+}
+
+/// Find the source conditions that always take the same branch, by propagating the constants of a
+/// copy of the freshly generated code, with its local variables promoted so that what's known
+/// about them is too. At this point the blocks still have the bindings the generator gave them.
+fn find_constant_conditions(
+    ir: &IR,
+    conditions: &[(BlockBinding, error::Span)],
+    source_meta: &SourceMetadata,
+) -> Vec<VarW> {
+    if conditions.is_empty() {
+        return Vec::new();
+    }
+    let mut folded = ir.clone();
+    super::mem2reg::promote_variables(&mut folded);
+    super::cleanup::run_safe_cleanup(&mut folded);
+    super::cleanup::sccp::propagate_constants(&mut folded);
+    conditions
+        .iter()
+        .filter_map(|&(block, span)| match (ir[block].end, folded[block].end) {
+            (
+                BlockEnd::Branch(Branch::Conditional { target_true, .. }),
+                BlockEnd::Branch(Branch::Unconditional { target }),
+            ) => Some(
+                VarW::new(VarWarning::ConstantCondition(target == target_true))
+                    .with_source(span, source_meta),
+            ),
+            _ => None,
+        })
+        .collect()
 }
 
 // TODO: make block builder struct
@@ -90,6 +1092,9 @@ pub fn compile_function<'code>(
 pub struct BlockBuilder {
     current_block: Vec<Statement>,
     block_id: usize,
+    /// the pure expressions of the statement being compiled that this block has computed, by
+    /// their [`expr::reuse_key`], so that repeating one reuses its binding
+    reused: HashMap<String, Binding>,
 }
 
 impl fmt::Debug for BlockBuilder {
@@ -109,10 +1114,11 @@ impl fmt::Debug for BlockBuilder {
 }
 
 impl BlockBuilder {
-    const fn new(block_id: usize) -> Self {
+    fn new(block_id: usize) -> Self {
         Self {
             current_block: Vec::new(),
             block_id,
+            reused: HashMap::new(),
         }
     }
     pub fn assign(&mut self, binding: Binding, value: impl Into<Value>) {
@@ -121,9 +1127,8 @@ impl BlockBuilder {
             value: value.into(),
         })
     }
-    // TODO: use type ids and allocate by alignment, not by size (values have to be aligned)
-    pub fn allocate(&mut self, target: Binding, size: usize) {
-        self.assign(target, Value::Allocate { size })
+    pub fn allocate(&mut self, target: Binding, size: usize, align: usize) {
+        self.assign(target, Value::Allocate { size, align })
     }
     pub fn load(&mut self, target: Binding, from_mem: Binding, size: ByteSize) {
         self.assign(
@@ -131,6 +1136,7 @@ impl BlockBuilder {
             Value::Load {
                 mem_binding: from_mem,
                 byte_size: size,
+                volatile: false,
             },
         )
     }
@@ -139,10 +1145,49 @@ impl BlockBuilder {
             mem_binding: mem,
             binding: from,
             byte_size: size,
+            volatile: false,
+        })
+    }
+    /// Load an object of type `ty`, which is a volatile access if the type is `volatile`
+    pub fn load_object(&mut self, target: Binding, from_mem: Binding, ty: &ast::Type) {
+        self.assign(
+            target,
+            Value::Load {
+                mem_binding: from_mem,
+                byte_size: ty.into(),
+                volatile: ty.is_volatile(),
+            },
+        )
+    }
+    /// Store an object of type `ty`, which is a volatile access if the type is `volatile`
+    pub fn store_object(&mut self, from: Binding, mem: Binding, ty: &ast::Type) {
+        self.push(Statement::Store {
+            mem_binding: mem,
+            binding: from,
+            byte_size: ty.into(),
+            volatile: ty.is_volatile(),
         })
     }
     pub fn push(&mut self, item: impl Into<Statement>) {
-        self.current_block.push(item.into())
+        let item = item.into();
+        // whatever writes memory or a register may change what the expressions computed so far
+        // would give now
+        if matches!(
+            item,
+            Statement::Assign {
+                value: Value::Call { .. } | Value::AtomicFetchAdd { .. },
+                ..
+            } | Statement::Store { .. }
+                | Statement::AtomicStore { .. }
+                | Statement::SetRegister { .. }
+        ) {
+            self.reused.clear();
+        }
+        self.current_block.push(item)
+    }
+    /// Stop reusing the expressions computed so far, as a new statement starts
+    pub fn forget_expressions(&mut self) {
+        self.reused.clear();
     }
     pub fn block(&self) -> BlockBinding {
         BlockBinding(self.block_id)
@@ -167,19 +1212,77 @@ impl BlockBuilder {
 pub struct IRGenState {
     blocks: Vec<MaybeUninit<BasicBlock>>,
     given_builders: usize,
+    warnings: Vec<VarW>,
+    warning_options: WarningOptions,
+    /// blocks that end in a branch on a source condition, used to check for constant conditions
+    conditions: Vec<(BlockBinding, error::Span)>,
+    standard: ast::Standard,
+    /// functions that can be called
+    declared_functions: DeclaredFunctions,
+    /// the names declared and used so far
+    symbols: SymbolRecorder,
+    /// what the function being compiled returns, or `None` if it returns `void`
+    return_type: Option<ast::Type>,
+    /// the struct the function being compiled returns, if it returns one, along with the memory
+    /// that holds where the caller wants it if it's returned through memory
+    returned_struct: Option<(ast::Type, Option<Binding>)>,
+    /// the labels of the `switch` statements being compiled, innermost last
+    switches: Vec<SwitchLabels>,
+    /// the name and declaration of the local variable each memory binding holds
+    locals: HashMap<Binding, (String, error::Span)>,
+    /// the read-only data of the function so far
+    read_only: Vec<ReadOnlyData>,
+}
+
+/// Where the labels of a `switch` body jump to
+struct SwitchLabels {
+    /// the block after the `switch`, where `break` goes
+    end: BlockBinding,
+    /// the (promoted) type of the controlling expression, which the `case` values are converted to
+    scrutinee: ast::Type,
+    /// the `case` values, in source order
+    cases: Vec<(i64, BlockBinding)>,
+    default: Option<BlockBinding>,
 }
 
-#[repr(transparent)]
 #[derive(Default)]
 pub struct BindingCounter {
     latest_binding: usize,
+    /// the statement being compiled
+    source: Option<error::Span>,
+    /// the statement each binding was made for
+    spans: HashMap<Binding, error::Span>,
+    /// the most bindings that may be made, see [`GenerateOptions::binding_budget`]
+    budget: Option<usize>,
 }
 
 impl BindingCounter {
     pub fn next_binding(&mut self) -> Binding {
-        let current = self.latest_binding;
-        self.latest_binding += 1;
-        Binding(current)
+        let current = Binding(self.latest_binding);
+        self.latest_binding = self
+            .latest_binding
+            .checked_add(1)
+            .expect("the function has more bindings than can be counted");
+        if let Some(span) = self.source {
+            self.spans.insert(current, span);
+        }
+        current
+    }
+    /// The budget, if more bindings were made than it allows. The code generating many bindings
+    /// in a loop stops early then, leaving the IR unfinished for the error that follows.
+    pub fn over_budget(&self) -> Option<usize> {
+        self.budget.filter(|&budget| self.latest_binding > budget)
+    }
+    /// Compile a statement, with the bindings it makes pointing at its span
+    pub fn compile_from<T>(
+        &mut self,
+        span: error::Span,
+        compile: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let outer = self.source.replace(span);
+        let compiled = compile(self);
+        self.source = outer;
+        compiled
     }
 }
 
@@ -192,6 +1295,24 @@ impl IRGenState {
         self.given_builders -= 1;
         BlockBinding(block_id)
     }
+    fn warn(&mut self, warning: VarW) {
+        self.warnings.push(warning);
+    }
+    /// Add read-only data for the code to refer to, giving its index. The same bytes are only
+    /// kept once, as strictly aligned as any of their uses needs.
+    fn add_read_only(&mut self, data: ReadOnlyData) -> usize {
+        let read_only = &mut self.read_only;
+        match read_only.iter().position(|kept| kept.bytes == data.bytes) {
+            Some(index) => {
+                read_only[index].align = read_only[index].align.max(data.align);
+                index
+            }
+            None => {
+                read_only.push(data);
+                read_only.len() - 1
+            }
+        }
+    }
     // get a new block and a reference to it
     fn new_block(&mut self) -> BlockBuilder {
         let index = self.blocks.len();
@@ -212,23 +1333,112 @@ impl IRGenState {
     }
 }
 
-type VariableMemories<'code> = HashMap<&'code str, (Binding, ByteSize)>;
+/// What is known about a declared variable
+#[derive(Debug, Clone)]
+pub struct Variable {
+    /// the binding of its memory allocation
+    pub memory: Binding,
+    pub ty: ast::Type,
+    /// where the variable's name was declared
+    pub declared_at: error::Span,
+}
+
+/// A variable declared at file scope, which lives in memory for the whole program
+#[derive(Debug, Clone)]
+pub struct GlobalVariable {
+    pub ty: ast::Type,
+    /// where the variable's name was first declared
+    pub declared_at: error::Span,
+}
+
+/// A global variable that lives in a register for the whole program
+#[derive(Debug, Clone)]
+pub struct RegisterVariable {
+    pub register: u8,
+    pub ty: ast::Type,
+    pub declared_at: error::Span,
+}
+
+/// What a name in scope refers to
+#[derive(Debug, Clone)]
+pub enum Symbol {
+    Variable(Variable),
+    Global(GlobalVariable),
+    Register(RegisterVariable),
+    /// an enumerator, which is an `int` constant
+    Constant {
+        value: i32,
+        declared_at: error::Span,
+    },
+    /// a function, whose name is a pointer to it as a value
+    Function(ast::FunctionType),
+}
+
+impl Symbol {
+    /// Where the name was declared, unless it's a function, which may be declared many times
+    pub const fn declared_at(&self) -> Option<error::Span> {
+        match self {
+            Self::Variable(Variable { declared_at, .. })
+            | Self::Global(GlobalVariable { declared_at, .. })
+            | Self::Register(RegisterVariable { declared_at, .. })
+            | Self::Constant { declared_at, .. } => Some(*declared_at),
+            Self::Function(_) => None,
+        }
+    }
+}
+
+pub type VariableMemories<'code> = HashMap<&'code str, Symbol>;
 
 pub struct VariableTracker<'code> {
     memories: Vec<VariableMemories<'code>>,
+    /// what is declared at file scope, in scope unless something local shadows it
+    globals: VariableMemories<'code>,
 }
 
 impl<'code> VariableTracker<'code> {
     pub fn new() -> Self {
+        Self::with_globals(HashMap::new())
+    }
+    pub fn with_globals(globals: VariableMemories<'code>) -> Self {
         Self {
             memories: Vec::new(),
+            globals,
         }
     }
-    pub fn get(&self, name: &str) -> Option<&(Binding, ByteSize)> {
+    /// What the name refers to in the innermost scope that declares it
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
         self.memories
             .iter()
             .rev()
-            .fold(None, |acc, next| acc.or_else(|| next.get(name)))
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+    }
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        match self.lookup(name)? {
+            Symbol::Variable(variable) => Some(variable),
+            _ => None,
+        }
+    }
+    /// The global register variable a name refers to, if it isn't shadowed
+    pub fn get_register(&self, name: &str) -> Option<&RegisterVariable> {
+        match self.lookup(name)? {
+            Symbol::Register(variable) => Some(variable),
+            _ => None,
+        }
+    }
+    /// The value of the enumerator a name refers to, if it isn't shadowed
+    pub fn get_constant(&self, name: &str) -> Option<i32> {
+        match self.lookup(name)? {
+            Symbol::Constant { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+    /// The scope at the given depth, or file scope for `None`
+    pub fn scope_mut(&mut self, depth: Option<usize>) -> &mut VariableMemories<'code> {
+        match depth {
+            Some(depth) => self.variables_at_depth(depth),
+            None => &mut self.globals,
+        }
     }
     pub fn variables_at_depth(&mut self, depth: usize) -> &mut VariableMemories<'code> {
         // depth is not going to be an arbitrary amount longer, this just has
@@ -252,6 +1462,932 @@ pub enum VarError {
     UnknownVariable(String),
     #[error("variable {0:?} was already declared")]
     Redeclared(String),
+    #[error("variable {0:?} was already defined")]
+    VariableRedefined(String),
+    #[error("variable {0:?} was declared before with a different type")]
+    ConflictingGlobalType(String),
+    #[error("initializer of a global variable is not a constant")]
+    NonConstantInitializer,
+    #[error("the program has no functions to compile")]
+    NoFunctions,
+    #[error("the function makes more than {0} IR bindings, which is too many to check")]
+    OverBudget(usize),
+    #[error("function {0:?} was already defined")]
+    FunctionRedefined(String),
+    #[error("weak declaration of {0:?} must be public")]
+    WeakInternal(String),
+    #[error("the target of the alias, {0:?}, is not defined in this file")]
+    AliasUndefined(String),
+    #[error("function {0:?} has more than 8 parameters, which is not supported yet")]
+    TooManyParameters(String),
+    #[error("call to {0:?} has more than 8 arguments, which is not supported yet")]
+    TooManyArguments(String),
+    #[error("function {function:?} takes {expected} arguments, but the call has {found}")]
+    ArgumentCount {
+        function: Box<str>,
+        expected: u32,
+        found: u32,
+    },
+    #[error("function {function:?} takes at least {expected} arguments, but the call has {found}")]
+    VariadicArgumentCount {
+        function: Box<str>,
+        expected: u32,
+        found: u32,
+    },
+    #[error("variadic function {0:?} can't be defined yet, only declared and called")]
+    VariadicDefinition(String),
+    #[error("function {0:?} was declared before with a different return type")]
+    ConflictingReturnType(String),
+    #[error("function {0:?} returns `void`, so its call has no value to use")]
+    VoidValue(String),
+    #[error("`return` with a value in a function returning `void`")]
+    ReturnValueInVoid,
+    #[error("`return` with no value in a function returning `int`")]
+    ReturnWithoutValue,
+    #[error("function {0:?} was declared before with a different number of parameters")]
+    ConflictingDeclaration(String),
+    #[error("subscripted value has type `{0}`, which is not an array")]
+    NotAnArray(ast::Type),
+    #[error("indirection requires a pointer, but the operand has type `{0}`")]
+    NotAPointer(ast::Type),
+    #[error("called object has type `{0}`, which is not a function or a function pointer")]
+    NotAFunction(ast::Type),
+    #[error(
+        "pointers can only have integers added or subtracted, or be subtracted from pointers of \
+         the same type"
+    )]
+    InvalidPointerArithmetic,
+    #[error("casting `{0}` to a pointer is not supported yet")]
+    IntegerToPointer(ast::Type),
+    #[error("member access requires a struct or union, but the operand has type `{0}`")]
+    NotAStruct(ast::Type),
+    #[error("`{struct_type}` has no member named {member:?}")]
+    UnknownMember {
+        struct_type: alloc::sync::Arc<ast::StructType>,
+        member: Box<str>,
+    },
+    #[error("`{0}` can't be used as a whole value here, only its members can")]
+    StructValue(ast::Type),
+    #[error("expected `{expected}`, found `{found}`")]
+    MismatchedStruct {
+        expected: Box<ast::Type>,
+        found: Box<ast::Type>,
+    },
+    #[error("`{0}` is passed in floating-point registers, which is not supported yet")]
+    FloatAggregate(ast::Type),
+    #[error("{0:?} can't hold a global register variable, only `x18` to `x28` can")]
+    InvalidGlobalRegister(String),
+    #[error("global register variables can only be `int`s or pointers, not `{0}`")]
+    GlobalRegisterType(ast::Type),
+    #[error("register {0:?} already holds a global variable")]
+    RegisterTaken(String),
+    #[error("address of global register variable {0:?} requested")]
+    RegisterAddress(String),
+    #[error("operand of type `{0}` where an integer is required")]
+    FloatOperand(ast::Type),
+    #[error("`{0}` can't be converted to or from a pointer")]
+    FloatPointerCast(ast::Type),
+    #[error("an array can only be initialized with a braced list")]
+    ArrayInitializer,
+    #[error("excess elements in initializer")]
+    ExcessInitializers,
+    #[error("too many braces around scalar initializer")]
+    ScalarBraces,
+    #[error("expression is not assignable")]
+    NotAssignable,
+    #[error(
+        "implicit declaration of function {function:?} is invalid since C99; declare it before \
+         calling it, like `{}`",
+        implicit_prototype(.function, .arguments)
+    )]
+    ImplicitDeclaration { function: String, arguments: usize },
+    #[error("`case` label not within a switch statement")]
+    CaseOutsideSwitch,
+    #[error("`default` label not within a switch statement")]
+    DefaultOutsideSwitch,
+    #[error("multiple `default` labels in one switch")]
+    MultipleDefaults,
+    #[error("duplicate case value {0}")]
+    DuplicateCase(i64),
+    #[error("case label does not reduce to an integer constant")]
+    NonConstantCase,
+    #[error("value of enumerator {0:?} is not an integer constant")]
+    NonConstantEnumerator(String),
+    #[error("value of enumerator {0:?} doesn't fit in an `int`")]
+    EnumeratorRange(String),
+    #[error("`break` statement not within a loop or switch")]
+    BreakOutsideSwitch,
+    #[error("requested alignment is not an integer constant")]
+    NonConstantAlignment,
+    #[error("requested alignment {0} is not a power of 2 up to 2^28")]
+    InvalidAlignment(i64),
+    #[error("`_Alignas` cannot reduce the alignment of {0:?}")]
+    AlignmentReduced(String),
+    #[error(
+        "{function:?} needs a pointer to an integer or a pointer of 4 or 8 bytes, but the operand \
+         has type `{operand}`"
+    )]
+    AtomicOperand {
+        function: Box<str>,
+        operand: Box<ast::Type>,
+    },
 }
 
 type VarE = error::Error<VarError>;
+
+#[derive(Error, Debug, Clone)]
+pub enum VarWarning {
+    #[error(
+        "implicit conversion from `{}` to `{}` may change its value",
+        .from.with_explicit_signedness(),
+        .to.with_explicit_signedness()
+    )]
+    Narrowing { from: ast::Type, to: ast::Type },
+    #[error("declaration of {0:?} shadows a variable from an outer scope")]
+    Shadow(String),
+    #[error("condition is always {0}")]
+    ConstantCondition(bool),
+    #[error("all paths through {0:?} call it again, so it will overflow the stack")]
+    InfiniteRecursion(String),
+    #[error("static function {0:?} is never called")]
+    UnusedFunction(String),
+    #[error("parameter {0:?} is never read")]
+    UnusedParameter(String),
+    #[error("variable {0:?} is read before anything is written to it")]
+    Uninitialized(String),
+    #[error(
+        "implicit declaration of function {function:?}; declare it before calling it, like `{}`",
+        implicit_prototype(.function, .arguments)
+    )]
+    ImplicitDeclaration { function: String, arguments: usize },
+    #[error(
+        "declaration of function {0:?} isn't a prototype; write `(void)` if it takes no parameters"
+    )]
+    StrictPrototypes(String),
+    #[error("shift count {count} is at least the width of `{ty}`")]
+    ShiftCountOverflow { count: i64, ty: ast::Type },
+    #[error("shift count {0} is negative")]
+    ShiftCountNegative(i64),
+    #[error("`main` should be `int main(void)` or `int main(int argc, char **argv)`")]
+    MainSignature,
+    #[error("suggest parentheses around `{inner}` within `{outer}`, like `{fix}`")]
+    Parentheses {
+        inner: ast::BinaryOp,
+        outer: ast::BinaryOp,
+        fix: String,
+    },
+}
+
+impl VarWarning {
+    /// The name of the `-W` flag that controls this warning
+    pub const fn flag(&self) -> &'static str {
+        match self {
+            Self::Narrowing { .. } => "conversion",
+            Self::Shadow(_) => "shadow",
+            Self::ConstantCondition(_) => "constant-condition",
+            Self::InfiniteRecursion(_) => "infinite-recursion",
+            Self::UnusedFunction(_) => "unused-function",
+            Self::UnusedParameter(_) => "unused-parameter",
+            Self::Uninitialized(_) => "uninitialized",
+            Self::ImplicitDeclaration { .. } => "implicit-function-declaration",
+            Self::StrictPrototypes(_) => "strict-prototypes",
+            Self::ShiftCountOverflow { .. } => "shift-count-overflow",
+            Self::ShiftCountNegative(_) => "shift-count-negative",
+            Self::Parentheses { .. } => "parentheses",
+            Self::MainSignature => "main",
+        }
+    }
+}
+
+/// The prototype that an implicitly declared function is called with, taking `int` for every
+/// argument
+fn implicit_prototype(function: &str, arguments: &usize) -> String {
+    let params = if *arguments == 0 {
+        "void".to_string()
+    } else {
+        vec!["int"; *arguments].join(", ")
+    };
+    format!("int {}({});", function, params)
+}
+
+pub type VarW = error::Warning<VarWarning>;
+
+impl From<&ast::Type> for ByteSize {
+    fn from(ty: &ast::Type) -> Self {
+        match ty {
+            ast::Type::Float => Self::F32,
+            ast::Type::Double => Self::F64,
+            // integers and pointers as wide as the target makes them
+            ast::Type::Bool
+            | ast::Type::Char
+            | ast::Type::SignedChar
+            | ast::Type::Short
+            | ast::Type::UnsignedShort
+            | ast::Type::Int
+            | ast::Type::UnsignedInt
+            | ast::Type::Long
+            | ast::Type::UnsignedLong
+            | ast::Type::Pointer(_)
+            | ast::Type::FunctionPointer(_) => match ty.size() {
+                1 if !ty.is_unsigned() => Self::I8,
+                1 => Self::U8,
+                2 if ty.is_unsigned() => Self::U16,
+                2 => Self::I16,
+                4 => Self::U32,
+                _ => Self::U64,
+            },
+            ast::Type::Volatile(ty) => Self::from(&**ty),
+            ast::Type::Array { .. } | ast::Type::Struct(_) => {
+                unreachable!("arrays and structs are never loaded or stored as a whole")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::intermediate::{interpret, CouldBeConstant};
+
+    /// The module of a program that compiles, with the default options
+    pub(crate) fn compiled<'code>(meta: &'code SourceMetadata<'code>) -> Module<'code> {
+        compiled_with(meta, GenerateOptions::default()).0
+    }
+
+    /// The module of a program that compiles, and its warnings
+    fn compiled_with<'code>(
+        meta: &'code SourceMetadata<'code>,
+        options: GenerateOptions,
+    ) -> (Module<'code>, Vec<VarW>) {
+        let program = crate::grammar::Parser::new(meta)
+            .parse()
+            .expect("the source parses");
+        compile_program(program, meta, options).expect("the source is fine")
+    }
+
+    /// What the last function of a program that compiles returns, run on the first sample inputs
+    pub(crate) fn returned(source: &str) -> i64 {
+        let meta = SourceMetadata::new(source);
+        let module = compiled(&meta);
+        let function = module.functions.last().expect("there is a function");
+        let inputs = interpret::sample_inputs()
+            .next()
+            .expect("there are samples");
+        interpret::run(&function.ir, &function.read_only, &inputs)
+            .expect("the function runs")
+            .returned
+    }
+
+    /// The messages of the warnings of a program that compiles
+    fn warnings(source: &str, warning_options: WarningOptions) -> Vec<String> {
+        let meta = SourceMetadata::new(source);
+        let options = GenerateOptions {
+            warnings: warning_options,
+            ..GenerateOptions::default()
+        };
+        let (_, warnings) = compiled_with(&meta, options);
+        warnings
+            .iter()
+            .map(|warning| warning.kind().to_string())
+            .collect()
+    }
+
+    /// The values assigned by the only function of a program that compiles
+    fn assigned_values(source: &str) -> Vec<Value> {
+        let meta = SourceMetadata::new(source);
+        compiled(&meta).functions[0]
+            .ir
+            .code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter_map(|statement| match statement {
+                Statement::Assign { value, .. } => Some(value.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inner_declarations_hiding_outer_ones_are_warned_about() {
+        let mut shadow = WarningOptions::default();
+        assert!(shadow.apply_flag("shadow"));
+        let source = "int main() {\n\
+                      \x20   int x = 1;\n\
+                      \x20   {\n\
+                      \x20       int x = 2;\n\
+                      \x20       int y = x;\n\
+                      \x20   }\n\
+                      \x20   return x;\n\
+                      }\n";
+        assert_eq!(
+            warnings(source, shadow),
+            ["declaration of \"x\" shadows a variable from an outer scope"]
+        );
+        // the warning is opt-in
+        assert!(warnings(source, WarningOptions::default()).is_empty());
+        // names that differ, or variables of blocks that are over, don't hide anything
+        let distinct = "int main() {\n\
+                        \x20   int x = 1;\n\
+                        \x20   {\n\
+                        \x20       int y = x;\n\
+                        \x20   }\n\
+                        \x20   {\n\
+                        \x20       int y = 2;\n\
+                        \x20   }\n\
+                        \x20   return x;\n\
+                        }\n";
+        assert!(warnings(distinct, shadow).is_empty());
+    }
+
+    #[test]
+    fn conditions_that_never_change_are_warned_about() {
+        // what's known about the local variables counts, through their assignments
+        let same = "int main() {\n\
+                    \x20   int i = 4;\n\
+                    \x20   if (i == i)\n\
+                    \x20       return 1;\n\
+                    \x20   return 0;\n\
+                    }\n";
+        assert_eq!(
+            warnings(same, WarningOptions::default()),
+            ["condition is always true"]
+        );
+        let assigned = "int main() {\n\
+                        \x20   int x = 3;\n\
+                        \x20   if (x == 3)\n\
+                        \x20       return 1;\n\
+                        \x20   return 0;\n\
+                        }\n";
+        assert_eq!(
+            warnings(assigned, WarningOptions::default()),
+            ["condition is always true"]
+        );
+        // a condition on what the function is given, or on a variable whose value depends on it,
+        // varies
+        let varying = "int f(int a) {\n\
+                       \x20   int x = 0;\n\
+                       \x20   if (a > 2)\n\
+                       \x20       x = 1;\n\
+                       \x20   if (x == 1)\n\
+                       \x20       return 1;\n\
+                       \x20   return 0;\n\
+                       }\n\
+                       int main() { return f(4); }\n";
+        assert!(warnings(varying, WarningOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn functions_that_call_themselves_on_every_path_are_warned_about() {
+        let source = "int f(int x) { return f(x - 1) + 1; }\n\
+                      int h(int x);\n\
+                      int g(int x) { return h(x) * 2; }\n\
+                      int h(int x) { int y = g(x); return y; }\n\
+                      int countdown(int x) { if (x) return countdown(x - 1); return 0; }\n";
+        assert_eq!(
+            warnings(source, WarningOptions::default()),
+            [
+                "all paths through \"f\" call it again, so it will overflow the stack",
+                "all paths through \"g\" call it again, so it will overflow the stack",
+                "all paths through \"h\" call it again, so it will overflow the stack",
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_to_undeclared_functions_suggest_a_prototype() {
+        let source = "int main(void) { return helper(1, 2) + setup(); }\n";
+        let meta = SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source parses");
+        let error = compile_program(program, &meta, GenerateOptions::default())
+            .err()
+            .expect("C99 has no implicit declarations");
+        assert!(matches!(
+            error.kind,
+            VarError::ImplicitDeclaration { ref function, arguments: 2 } if function == "helper"
+        ));
+        assert!(error.to_string().contains("like `int helper(int, int);`"));
+        // C89 declares them as returning `int`, and only warns
+        let c89 = GenerateOptions {
+            standard: ast::Standard::C89,
+            ..GenerateOptions::default()
+        };
+        let (_, warnings) = compiled_with(&meta, c89);
+        let messages: Vec<_> = warnings.iter().map(|w| w.kind().to_string()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].contains("like `int setup(void);`"));
+        // a declaration after the call is too late
+        let late = "int main(void) { return helper(); }\nint helper(void);\n";
+        assert!(!crate::check_str(late).is_empty());
+    }
+
+    #[test]
+    fn empty_parameter_lists_leave_the_parameters_unspecified() {
+        let error = |source| {
+            let diagnostics = crate::check_str(source);
+            let message = diagnostics.iter().next().map(|error| error.message.clone());
+            message
+        };
+        // calls to `f()` aren't checked, and a later declaration may give its parameters
+        let unspecified = "int f();\n\
+                           int main(void) { return f(1, 2) + f(); }\n\
+                           int f(int a, int b);\n";
+        assert_eq!(error(unspecified), None);
+        let none = error("int f(void);\nint main(void) { return f(1); }\n");
+        assert!(none.is_some_and(|error| error.contains("takes 0 arguments, but the call has 1")));
+        let one = error("int f(int a);\nint main(void) { return f(); }\n");
+        assert!(one.is_some_and(|error| error.contains("takes 1 arguments, but the call has 0")));
+        let conflicting = "int f(int a);\n\
+                           int f(int a, int b);\n\
+                           int main(void) { return 0; }\n";
+        let conflicting = error(conflicting);
+        assert!(conflicting.is_some_and(|error| error.contains("different number")));
+
+        let mut strict = WarningOptions::default();
+        assert!(strict.apply_flag("strict-prototypes"));
+        let source = "int f();\nint g(void);\nint main(void) { return 0; }\n";
+        let warned = warnings(source, strict);
+        assert_eq!(warned.len(), 1);
+        assert!(warned[0].starts_with("declaration of function \"f\" isn't a prototype"));
+        assert!(warnings(source, WarningOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn exit_ends_the_program_from_anywhere() {
+        // falling off the end of `main` returns 0
+        assert_eq!(returned("int main(void) { int x = 2; }"), 0);
+        // `exit` needs no declaration, but takes its one argument
+        assert!(crate::check_str("int main(void) { exit(3); }").is_empty());
+        let error = crate::check_str("int main(void) { exit(); return 0; }");
+        let error = error.iter().next().map(|error| error.message.clone());
+        assert!(error.is_some_and(|error| error.contains("takes 1 arguments")));
+        // calling it leaves the function, so the recursion can end
+        let recursion = "int f(int x) { if (x) exit(1); return f(x); }\n\
+                         int g(int x) { if (x) x = 1; return g(x); }\n";
+        assert_eq!(
+            warnings(recursion, WarningOptions::default()),
+            ["all paths through \"g\" call it again, so it will overflow the stack"]
+        );
+    }
+
+    #[test]
+    fn static_functions_never_called_are_warned_about() {
+        let mut unused = WarningOptions::default();
+        assert!(unused.apply_flag("unused-function"));
+        let source = "static int helper(int a) { return a; }\n\
+                      static int recursive(int a) { if (a) return recursive(a - 1); return 0; }\n\
+                      static int called(int a) { return a; }\n\
+                      static int ping(int a);\n\
+                      static int pong(int a) { return a ? ping(a - 1) : helper(a); }\n\
+                      static int ping(int a) { return pong(a); }\n\
+                      static int through_pointer(int a) { return a; }\n\
+                      static int indirect(int a) { int (*f)(int) = through_pointer; return f(a); }\n\
+                      int exported(int a) { return indirect(a); }\n\
+                      int main() { return called(1); }\n";
+        // `main` and other external functions may be called from outside, so they never count,
+        // while functions that only call each other are as unused as those no one calls
+        assert_eq!(
+            warnings(source, unused),
+            [
+                "static function \"helper\" is never called",
+                "static function \"recursive\" is never called",
+                "static function \"pong\" is never called",
+                "static function \"ping\" is never called",
+            ]
+        );
+        // the warning is opt-in
+        assert!(warnings(source, WarningOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn parameters_never_read_are_warned_about() {
+        let mut unused = WarningOptions::default();
+        assert!(unused.apply_flag("unused-parameter"));
+        let source = "int f(int used, int unused) { return used; }\n\
+                      int main() { return f(1, 2); }\n";
+        assert_eq!(
+            warnings(source, unused),
+            ["parameter \"unused\" is never read"]
+        );
+        assert!(warnings(source, WarningOptions::default()).is_empty());
+        // evaluating a parameter for nothing still reads it
+        let discarded = "int f(int a) { a; return 0; }\n\
+                         int main() { return f(1); }\n";
+        assert!(warnings(discarded, unused).is_empty());
+        // so does taking its address, whatever is done with it then
+        let addressed = "int g(int *p);\n\
+                         int f(int a) { int *p = &a; return *p; }\n\
+                         int h(int a) { return g(&a); }\n\
+                         int main() { return f(1) + h(2); }\n";
+        assert!(warnings(addressed, unused).is_empty());
+        // while only assigning it doesn't, even for a struct
+        let written = "struct pair { long a; long b; };\n\
+                       int f(int a) { a = 2; return 0; }\n\
+                       int g(struct pair p) { return 0; }\n\
+                       int main() { struct pair p = {1, 2}; return f(1) + g(p); }\n";
+        assert_eq!(
+            warnings(written, unused),
+            [
+                "parameter \"a\" is never read",
+                "parameter \"p\" is never read"
+            ]
+        );
+    }
+
+    #[test]
+    fn pointer_arithmetic_is_scaled_by_the_pointee_size() {
+        let element_sizes = |source| -> Vec<usize> {
+            assigned_values(source)
+                .into_iter()
+                .filter_map(|value| match value {
+                    Value::ElementAddress { element_size, .. } => Some(element_size),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            element_sizes("int f(char *c, int n) { return *(c + n) + *(n + c); }"),
+            [1, 1]
+        );
+        assert_eq!(
+            element_sizes("int f(int *p, int n) { return *(p + n) + *(p - n); }"),
+            [4, 4]
+        );
+        // subtracting goes through the negated index
+        let values = assigned_values("int f(int *p, int n) { return *(p - n); }");
+        assert!(values
+            .iter()
+            .any(|value| matches!(value, Value::Negate { .. })));
+        // the difference of two pointers counts elements, not bytes
+        let values = assigned_values("int f(int *p, int *q) { return p - q; }");
+        assert!(values.iter().any(|value| matches!(
+            value,
+            Value::Divide {
+                rhs: CouldBeConstant::Constant(4),
+                is_signed: true,
+                ..
+            }
+        )));
+        let values = assigned_values("int f(char *p, char *q) { return p - q; }");
+        assert!(!values
+            .iter()
+            .any(|value| matches!(value, Value::Divide { .. })));
+
+        let meta = SourceMetadata::new("int f(int *p, char *q) { return p - q; }");
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source parses");
+        let error = compile_program(program, &meta, Default::default())
+            .err()
+            .expect("pointers of different types can't be subtracted");
+        assert!(error.to_string().contains("pointers of the same type"));
+    }
+
+    #[test]
+    fn implicit_conversions_that_may_change_the_value_are_warned_about() {
+        let mut conversion = WarningOptions::default();
+        assert!(conversion.apply_flag("conversion"));
+        let narrowing = "int f(int x, long l, double d) {\n\
+                         \x20   char c = x;\n\
+                         \x20   unsigned char u;\n\
+                         \x20   u = x;\n\
+                         \x20   signed char s = 300;\n\
+                         \x20   short h = 70000;\n\
+                         \x20   int i = l;\n\
+                         \x20   int j = d;\n\
+                         \x20   float g = d;\n\
+                         \x20   return c + u + s + h + i + j + g;\n\
+                         }\n";
+        assert_eq!(
+            warnings(narrowing, conversion),
+            [
+                "implicit conversion from `int` to `unsigned char` may change its value",
+                "implicit conversion from `int` to `unsigned char` may change its value",
+                "implicit conversion from `int` to `signed char` may change its value",
+                "implicit conversion from `int` to `short` may change its value",
+                "implicit conversion from `long` to `int` may change its value",
+                "implicit conversion from `double` to `int` may change its value",
+                "implicit conversion from `double` to `float` may change its value",
+            ]
+        );
+        // casts, constants that fit and conversions that widen keep the value
+        let keeping = "int f(int x, float y) {\n\
+                       \x20   char c = (char)x;\n\
+                       \x20   signed char s = -128;\n\
+                       \x20   unsigned short h = 65535;\n\
+                       \x20   long l = x;\n\
+                       \x20   _Bool b = x;\n\
+                       \x20   double d = y;\n\
+                       \x20   float g = 1.5;\n\
+                       \x20   return c + s + h + l + b + d + g;\n\
+                       }\n";
+        assert!(warnings(keeping, conversion).is_empty());
+    }
+
+    #[test]
+    fn constant_shift_counts_out_of_range_are_warned_about() {
+        let source = "int f(int a, long l, char c) {\n\
+                      \x20   a <<= 33;\n\
+                      \x20   return (a << 32) + (a >> -1) + (a << 31) + (l << 40) + (c << 8);\n\
+                      }\n";
+        assert_eq!(
+            warnings(source, WarningOptions::default()),
+            [
+                "shift count 33 is at least the width of `int`",
+                "shift count 32 is at least the width of `int`",
+                "shift count -1 is negative",
+            ]
+        );
+    }
+
+    #[test]
+    fn operators_whose_precedence_is_often_misread_are_warned_about() {
+        let source = "int f(int a, int b, int c) {\n\
+                      \x20   int x = a || b && c;\n\
+                      \x20   int y = a << b + c;\n\
+                      \x20   int z = a & b == c;\n\
+                      \x20   return x | y & z;\n\
+                      }\n";
+        assert_eq!(
+            warnings(source, WarningOptions::default()),
+            [
+                "suggest parentheses around `&&` within `||`, like `(b && c)`",
+                "suggest parentheses around `+` within `<<`, like `(b + c)`",
+                "suggest parentheses around `==` within `&`, like `(b == c)`",
+                "suggest parentheses around `&` within `|`, like `(y & z)`",
+            ]
+        );
+        // explicit parentheses, and groupings nobody misreads, are fine
+        let clear = "int f(int a, int b, int c) {\n\
+                     \x20   int x = a || (b && c);\n\
+                     \x20   int y = (a << b) + c;\n\
+                     \x20   return x + y * c || a == b;\n\
+                     }\n";
+        assert!(warnings(clear, WarningOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn main_only_takes_the_parameters_the_standard_defines() {
+        for signature in [
+            "",
+            "void",
+            "int argc, char **argv",
+            "int argc, char *argv[]",
+        ] {
+            let source = format!("int main({signature}) {{ return 0; }}");
+            assert!(
+                warnings(&source, WarningOptions::default()).is_empty(),
+                "{signature}"
+            );
+        }
+        for signature in [
+            "long n",
+            "int argc, char *argv",
+            "int argc, char **argv, int extra",
+        ] {
+            let source = format!("int main({signature}) {{ return 0; }}");
+            assert_eq!(
+                warnings(&source, WarningOptions::default()),
+                ["`main` should be `int main(void)` or `int main(int argc, char **argv)`"],
+                "{signature}"
+            );
+        }
+        // other functions take whatever they want
+        let source = "int f(long n) { return n; }\nint main(void) { return f(1); }\n";
+        assert!(warnings(source, WarningOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn weak_functions_and_aliases_are_told_to_the_linker() {
+        let source = "int impl(int a) { return a; }\n\
+                      int api(int a) __attribute__((alias(\"impl\")));\n\
+                      __attribute__((weak)) int hook(void);\n\
+                      __attribute__((__weak__)) int fallback(void) { return 0; }\n\
+                      int main(void) { return api(1) + fallback() + hook(); }\n";
+        let meta = SourceMetadata::new(source);
+        let (module, warnings) = compiled_with(&meta, GenerateOptions::default());
+        assert!(warnings.is_empty());
+        assert_eq!(
+            module.aliases,
+            [Alias {
+                name: "api",
+                target: "impl",
+                linkage: ast::Linkage::External,
+            }]
+        );
+        // only the weak functions that aren't defined are references
+        assert_eq!(module.weak_references, ["hook"]);
+        let fallback = module
+            .functions
+            .iter()
+            .find(|function| function.name == "fallback")
+            .expect("fallback is defined");
+        assert_eq!(fallback.linkage, ast::Linkage::Weak);
+
+        let error = |source: &str| {
+            let meta = SourceMetadata::new(source);
+            let program = crate::grammar::Parser::new(&meta)
+                .parse()
+                .expect("the source parses");
+            compile_program(program, &meta, Default::default())
+                .err()
+                .map(|error| error.kind)
+        };
+        assert!(matches!(
+            error("int f(void) __attribute__((alias(\"g\")));\nint main(void) { return 0; }"),
+            Some(VarError::AliasUndefined(target)) if target == "g"
+        ));
+        assert!(matches!(
+            error("static int f(void) __attribute__((weak));\nint main(void) { return 0; }"),
+            Some(VarError::WeakInternal(name)) if name == "f"
+        ));
+        // an alias is a definition of its own
+        assert!(matches!(
+            error(
+                "int g(void) { return 0; }\n\
+                 int f(void) { return 1; }\n\
+                 int f(void) __attribute__((alias(\"g\")));\n"
+            ),
+            Some(VarError::FunctionRedefined(name)) if name == "f"
+        ));
+    }
+
+    #[test]
+    fn global_variables_are_defined_once_and_start_zeroed_unless_initialized() {
+        let source = "int counter;\n\
+                      static int zeroed = 0;\n\
+                      int table[4] = {1, 2};\n\
+                      extern int elsewhere;\n\
+                      int counter;\n\
+                      int main(void) { return counter + zeroed + table[1] + elsewhere; }\n";
+        let meta = SourceMetadata::new(source);
+        let (module, warnings) = compiled_with(&meta, GenerateOptions::default());
+        assert!(warnings.is_empty());
+        // a tentative definition is zero, and the `extern` declaration defines nothing
+        assert_eq!(
+            module.globals,
+            [
+                GlobalData {
+                    name: "counter",
+                    linkage: ast::Linkage::External,
+                    bytes: vec![0; 4],
+                    align: 4,
+                },
+                GlobalData {
+                    name: "zeroed",
+                    linkage: ast::Linkage::Internal,
+                    bytes: vec![0; 4],
+                    align: 4,
+                },
+                GlobalData {
+                    name: "table",
+                    linkage: ast::Linkage::External,
+                    bytes: vec![1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                    align: 4,
+                },
+            ]
+        );
+        let zeroed: Vec<_> = module.globals.iter().map(GlobalData::is_zeroed).collect();
+        assert_eq!(zeroed, [true, true, false]);
+
+        let error = |source: &str| {
+            let meta = SourceMetadata::new(source);
+            let program = crate::grammar::Parser::new(&meta)
+                .parse()
+                .expect("the source parses");
+            compile_program(program, &meta, Default::default())
+                .err()
+                .map(|error| error.kind)
+        };
+        assert!(matches!(
+            error("int x = 1;\nint x = 2;\nint main(void) { return 0; }"),
+            Some(VarError::VariableRedefined(name)) if name == "x"
+        ));
+        assert!(matches!(
+            error("int x;\nlong x;\nint main(void) { return 0; }"),
+            Some(VarError::ConflictingGlobalType(name)) if name == "x"
+        ));
+        assert!(matches!(
+            error("int x;\nint x(void);\nint main(void) { return 0; }"),
+            Some(VarError::Redeclared(name)) if name == "x"
+        ));
+        assert!(matches!(
+            error("int y;\nint x = y;\nint main(void) { return 0; }"),
+            Some(VarError::NonConstantInitializer)
+        ));
+        // a global variable is only visible after its declaration
+        assert!(matches!(
+            error("int main(void) { return later; }\nint later;\n"),
+            Some(VarError::UnknownVariable(name)) if name == "later"
+        ));
+        // the declarators of a global declaration each take the pointers they're written with
+        assert!(error(
+            "int *p, q = 2;\n\
+             int (*call)(int);\n\
+             int main(void) {\n\
+             \x20   q = *p;\n\
+             \x20   return call(q);\n\
+             }\n"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn functions_return_values_of_the_type_they_declare() {
+        let cases = [
+            ("_Bool f(void) { return 7; }", 1),
+            ("char f(void) { return 300; }", 44),
+            ("signed char f(void) { return 200; }", -56),
+            ("short f(void) { return 70000; }", 4464),
+            ("unsigned short f(void) { return -1; }", 65535),
+            ("unsigned f(void) { return 7u; }", 7),
+            ("long f(void) { return 5000000000; }", 5_000_000_000),
+            ("unsigned long f(void) { return -1; }", -1),
+            ("long f(void) { int i = -3; return i; }", -3),
+            ("char *f(void) { return 0; }", 0),
+            ("long *f(long *p) { return p + 1; }", 8),
+            ("double f(void) { return 1; }", 1f64.to_bits() as i64),
+            ("float f(void) { return 1.5; }", 1.5f32.to_bits().into()),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(returned(source), expected, "{source}");
+        }
+        // the callers get the value as what the function returns, narrowed again
+        let values = assigned_values("char g(void);\nint f(void) { return g() + 1; }\n");
+        assert!(values.iter().any(|value| matches!(
+            value,
+            Value::And {
+                rhs: CouldBeConstant::Constant(255),
+                ..
+            }
+        )));
+        let values = assigned_values("long g(void);\nint f(void) { return g() > 4000000000; }\n");
+        assert!(values.iter().any(|value| matches!(
+            value,
+            Value::Cmp {
+                bit_size: BitSize::Bit64,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn same_constants_are_kept_once() {
+        let source = "int puts(char *s);\n\
+                      int f() { puts(\"hi\"); return puts(\"hi\"); }\n\
+                      int main() { puts(\"bye\"); return puts(\"hi\"); }\n";
+        let meta = SourceMetadata::new(source);
+        let mut module = compiled(&meta);
+        let kept: Vec<_> = module
+            .functions
+            .iter()
+            .map(|function| function.read_only.len())
+            .collect();
+        assert_eq!(kept, [1, 2]);
+        module.merge_constants();
+        let merged = &module.functions[1].merged_read_only;
+        assert_eq!(merged.get(&1), Some(&(0, 0)));
+        assert_eq!(merged.len(), 1);
+    }
+
+    #[test]
+    fn repeated_expressions_of_a_statement_are_computed_once() {
+        let cases = [
+            ("return a * b + a * b;", 1, 24),
+            ("return (a * b) - a * (b);", 1, 0),
+            // other statements compute it again
+            ("int c = a * b; return c + a * b;", 2, 24),
+            // a write in between may change the operands
+            ("return a * b + ((a = 5) & 0) + a * b;", 2, 32),
+            ("return a * b + ((*p = 1) & 0) + a * b;", 2, 16),
+            // a call may write any memory
+            ("return a * b + (next() & 0) + a * b;", 2, 24),
+            ("volatile int v = 3; return v * b + v * b;", 2, 24),
+        ];
+        for (body, multiplications, expected) in cases {
+            let source = format!(
+                "int next(void) {{ return 1; }}\n\
+                 int main() {{ int a = 3, b = 4, *p = &a; {body} }}"
+            );
+            let meta = SourceMetadata::new(&source);
+            let count = compiled(&meta).functions[1]
+                .ir
+                .code
+                .iter()
+                .flat_map(|block| &block.statements)
+                .filter(|statement| {
+                    matches!(
+                        statement,
+                        Statement::Assign {
+                            value: Value::Multiply { .. },
+                            ..
+                        }
+                    )
+                })
+                .count();
+            assert_eq!(count, multiplications, "{body}");
+            assert_eq!(returned(&source), expected, "{body}");
+        }
+    }
+}
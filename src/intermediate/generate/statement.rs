@@ -1,6 +1,13 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
 use super::*;
 use crate::ast;
 
+/// Switches with up to this many cases compare the value against each of them in turn. More are
+/// searched for in a balanced tree of comparisons, which is quicker however sparse the values are.
+const LINEAR_SWITCH_MAX_CASES: usize = 4;
+
 pub fn compile_statement<'code>(
     state: &mut IRGenState,
     mut builder: BlockBuilder,
@@ -10,59 +17,248 @@ pub fn compile_statement<'code>(
     block_depth: usize,
     source_meta: &SourceMetadata,
 ) -> Result<BlockBuilder, VarE> {
+    builder.forget_expressions();
     match statement {
-        ast::Statement::LoopBreak | ast::Statement::Loop { .. } | ast::Statement::LoopContinue => {
+        ast::Statement::Loop { .. } | ast::Statement::LoopContinue => {
             todo!("loops")
         }
-        ast::Statement::Return((expr, expr_span)) => {
+        ast::Statement::LoopBreak => match state.switches.last() {
+            Some(labels) => {
+                let target = labels.end;
+                builder.finish_block(state, Branch::Unconditional { target });
+                Ok(state.new_block())
+            }
+            None => Err(VarE::new(VarError::BreakOutsideSwitch)),
+        },
+        ast::Statement::Switch {
+            scrutinee: (scrutinee_expr, scrutinee_span),
+            body: (body, body_span),
+        } => {
+            let ty = expr::promoted(&expr::expr_type(&scrutinee_expr, variables));
+            if ty.is_floating() {
+                return Err(
+                    VarE::new(VarError::FloatOperand(ty)).with_source(scrutinee_span, source_meta)
+                );
+            }
+            let (mut dispatch, scrutinee_value) = expr::compile_expr(
+                state,
+                builder,
+                scrutinee_expr,
+                bindings,
+                variables,
+                source_meta,
+            )
+            .map_err(|e| e.with_backup_source(scrutinee_span, source_meta))?;
+            // the value is compared in several blocks, so it's kept in memory like a variable
+            let scrutinee_memory = bindings.next_binding();
+            let scrutinee = bindings.next_binding();
+            dispatch.allocate(scrutinee_memory, ty.size(), ty.align());
+            dispatch.assign(scrutinee, scrutinee_value);
+            dispatch.store(scrutinee, scrutinee_memory, (&ty).into());
+
+            let end_block = state.new_block();
+            state.switches.push(SwitchLabels {
+                end: end_block.block(),
+                scrutinee: ty,
+                cases: Vec::new(),
+                default: None,
+            });
+            // whatever comes before the first label can't be reached
+            let body_start = state.new_block();
+            let body_end = bindings
+                .compile_from(body_span, |bindings| {
+                    compile_statement(
+                        state,
+                        body_start,
+                        bindings,
+                        *body,
+                        variables,
+                        block_depth,
+                        source_meta,
+                    )
+                })
+                .map_err(|e| e.with_backup_source(body_span, source_meta))?;
+            let labels = state
+                .switches
+                .pop()
+                .expect("the switch's labels were pushed");
+            body_end.finish_block(state, Branch::Unconditional { target: labels.end });
+
+            let mut cases = labels.cases;
+            if cases.len() > LINEAR_SWITCH_MAX_CASES {
+                cases.sort_unstable_by_key(|&(value, _)| value);
+            }
+            dispatch_cases(
+                state,
+                dispatch,
+                bindings,
+                (scrutinee_memory, &labels.scrutinee),
+                &cases,
+                labels.default.unwrap_or(labels.end),
+            );
+            Ok(end_block)
+        }
+        ast::Statement::Case((value_expr, value_span)) => {
+            state.symbols.refer_all(&value_expr, variables);
+            let value = expr::reduce_expr(&value_expr, variables).ok_or_else(|| {
+                VarE::new(VarError::NonConstantCase).with_source(value_span, source_meta)
+            })?;
+            let labels = state
+                .switches
+                .last()
+                .ok_or_else(|| VarE::new(VarError::CaseOutsideSwitch))?;
+            // the value is converted to the (promoted) type of the controlling expression
+            let value = expr::wrap_integer(value, &labels.scrutinee);
+            if labels.cases.iter().any(|(other, _)| *other == value) {
+                return Err(VarE::new(VarError::DuplicateCase(value)));
+            }
+            let label = state.new_block();
+            builder.finish_block(
+                state,
+                Branch::Unconditional {
+                    target: label.block(),
+                },
+            );
+            let labels = state.switches.last_mut().expect("checked above");
+            labels.cases.push((value, label.block()));
+            Ok(label)
+        }
+        ast::Statement::DeclareEnum(enumerators) => {
+            declare_enumerators(
+                enumerators,
+                variables,
+                Some(block_depth),
+                &mut state.symbols,
+                source_meta,
+            )?;
+            Ok(builder)
+        }
+        ast::Statement::Default => {
+            let labels = state
+                .switches
+                .last()
+                .ok_or_else(|| VarE::new(VarError::DefaultOutsideSwitch))?;
+            if labels.default.is_some() {
+                return Err(VarE::new(VarError::MultipleDefaults));
+            }
+            let label = state.new_block();
+            builder.finish_block(
+                state,
+                Branch::Unconditional {
+                    target: label.block(),
+                },
+            );
+            let labels = state.switches.last_mut().expect("checked above");
+            labels.default = Some(label.block());
+            Ok(label)
+        }
+        ast::Statement::Return(None) => {
+            if state.return_type.is_some() && state.standard >= ast::Standard::C99 {
+                return Err(VarE::new(VarError::ReturnWithoutValue));
+            }
+            // what a function that returns no value leaves in `w0` isn't used
             let ret_value = bindings.next_binding();
-            {
+            builder.assign(ret_value, 0);
+            builder.finish_block(state, ret_value);
+            Ok(state.new_block())
+        }
+        ast::Statement::Return(Some((expr, expr_span))) => {
+            let Some(return_type) = state.return_type.clone() else {
+                return Err(
+                    VarE::new(VarError::ReturnValueInVoid).with_source(expr_span, source_meta)
+                );
+            };
+            if let Some((ty, result_address)) = state.returned_struct.clone() {
+                let (block, ret_value) = aggregate::return_struct(
+                    state,
+                    builder,
+                    (expr, expr_span),
+                    (&ty, result_address),
+                    bindings,
+                    variables,
+                    source_meta,
+                )?;
+                block.finish_block(state, ret_value);
+                return Ok(state.new_block());
+            }
+            let ty = expr::expr_type(&expr, variables);
+            let ret_value = bindings.next_binding();
+            let (block, ret_value) = {
                 let (mut block, resulting_value) =
                     expr::compile_expr(state, builder, expr, bindings, variables, source_meta)?;
                 block.assign(ret_value, resulting_value);
-                Ok(block)
+                // the value is converted to the type the function returns, and narrower integers
+                // come back narrowed like the values of their variables
+                let return_type = return_type.unqualified();
+                let ret_value = expr::convert(&mut block, bindings, ret_value, &ty, return_type);
+                let ret_value = expr::narrow(&mut block, bindings, ret_value, return_type);
+                Ok((block, ret_value))
             }
-            .map_err(|e: VarE| e.with_backup_source(expr_span, source_meta))?
-            .finish_block(state, ret_value);
+            .map_err(|e: VarE| e.with_backup_source(expr_span, source_meta))?;
+            block.finish_block(state, ret_value);
             Ok(state.new_block())
         }
         ast::Statement::SingleExpr((expr, expr_span)) => {
             // create a dummy target that may or may not be cleaned up later,
             // depending on what it does
-            let (mut block, result_expr) =
-                expr::compile_expr(state, builder, expr, bindings, variables, source_meta)
-                    .map_err(|e| e.with_backup_source(expr_span, source_meta))?;
+            // the value of a call is discarded, so it may be `void`
+            let (mut block, result_expr) = match expr.into_without_parens() {
+                // a struct is assigned by copying it, which leaves no value
+                ast::Expr::Binary {
+                    operator: ast::BinaryOp::Assignment { op: None },
+                    lhs,
+                    rhs,
+                } if matches!(expr::expr_type(&lhs.0, variables), ast::Type::Struct(_)) => {
+                    return aggregate::assign_struct(
+                        state,
+                        builder,
+                        (*lhs.0, lhs.1),
+                        (*rhs.0, rhs.1),
+                        bindings,
+                        variables,
+                        source_meta,
+                    )
+                    .map_err(|e| e.with_backup_source(expr_span, source_meta));
+                }
+                ast::Expr::Call { function, args } => expr::compile_call(
+                    state,
+                    builder,
+                    function,
+                    args,
+                    bindings,
+                    variables,
+                    source_meta,
+                ),
+                ast::Expr::CallPointer { pointer, args } => expr::compile_pointer_call(
+                    state,
+                    builder,
+                    (*pointer.0, pointer.1),
+                    args,
+                    bindings,
+                    variables,
+                    source_meta,
+                ),
+                expr => expr::compile_expr(state, builder, expr, bindings, variables, source_meta),
+            }
+            .map_err(|e| e.with_backup_source(expr_span, source_meta))?;
             let dummy = bindings.next_binding();
             block.assign(dummy, result_expr);
             Ok(block)
         }
-        ast::Statement::DeclareVar {
-            name: Source { source: name, span },
-            init,
-        } => {
-            let memory = bindings.next_binding();
-            builder.allocate(memory, 4); // all variables are 4-byte right now
-                                         // compile init
-            let builder = if let Some((init, init_span)) = init {
-                let (mut builder, expr) =
-                    expr::compile_expr(state, builder, init, bindings, variables, source_meta)
-                        .map_err(|e| e.with_backup_source(init_span, source_meta))?;
-                let compute_init = bindings.next_binding();
-                builder.assign(compute_init, expr);
-                builder.store(compute_init, memory, ByteSize::U32);
-                builder
-            } else {
-                builder
-            };
-            // add the variable to the index
-            {
-                let ctx = variables.variables_at_depth(block_depth);
-                if ctx.contains_key(name) {
-                    return Err(VarE::new(VarError::Redeclared(name.to_string()))
-                        .with_source(span, source_meta));
-                } else {
-                    ctx.insert(name, (memory, ByteSize::U32));
-                }
+        ast::Statement::DeclareVars { declarators, align } => {
+            for (expr, _) in &align {
+                state.symbols.refer_all(expr, variables);
+            }
+            for declarator in declarators {
+                builder = declare_variable(
+                    state,
+                    builder,
+                    bindings,
+                    (declarator, &align),
+                    variables,
+                    block_depth,
+                    source_meta,
+                )?;
             }
             Ok(builder)
         }
@@ -80,35 +276,40 @@ pub fn compile_statement<'code>(
             true_branch: (true_stmt, true_span),
             false_branch,
         } => {
-            let (compute_condition, cond_flag) = {
-                let (mut compute, cond_value) = expr::compile_expr(
-                    state,
-                    builder, // continue the builder we had previously
-                    condition_expr,
-                    bindings,
-                    variables,
-                    source_meta,
-                )
-                .map_err(|e| e.with_backup_source(condition_span, source_meta))?;
-                let value_binding = bindings.next_binding();
-                compute.assign(value_binding, cond_value);
-                (compute, value_binding)
-            };
+            // literal conditions are constant on purpose
+            let is_literal = expr::literal_value(&condition_expr).is_some();
+            let (compute_condition, cond_flag) = expr::compile_condition(
+                state,
+                builder, // continue the builder we had previously
+                condition_expr,
+                bindings,
+                variables,
+                source_meta,
+            )
+            .map_err(|e| e.with_backup_source(condition_span, source_meta))?;
+            if !is_literal {
+                state
+                    .conditions
+                    .push((compute_condition.block(), condition_span));
+            }
 
             let (compute_if_true, true_head) = {
                 let block = state.new_block();
                 let head = block.block();
                 (
-                    compile_statement(
-                        state,
-                        block,
-                        bindings,
-                        *true_stmt,
-                        variables,
-                        block_depth,
-                        source_meta,
-                    )
-                    .map_err(|e| e.with_backup_source(true_span, source_meta))?,
+                    bindings
+                        .compile_from(true_span, |bindings| {
+                            compile_statement(
+                                state,
+                                block,
+                                bindings,
+                                *true_stmt,
+                                variables,
+                                block_depth,
+                                source_meta,
+                            )
+                        })
+                        .map_err(|e| e.with_backup_source(true_span, source_meta))?,
                     head,
                 )
             };
@@ -118,16 +319,19 @@ pub fn compile_statement<'code>(
                 let head = block.block();
                 (
                     if let Some((false_stmt, false_span)) = false_branch {
-                        compile_statement(
-                            state,
-                            block,
-                            bindings,
-                            *false_stmt,
-                            variables,
-                            block_depth,
-                            source_meta,
-                        )
-                        .map_err(|e| e.with_backup_source(false_span, source_meta))?
+                        bindings
+                            .compile_from(false_span, |bindings| {
+                                compile_statement(
+                                    state,
+                                    block,
+                                    bindings,
+                                    *false_stmt,
+                                    variables,
+                                    block_depth,
+                                    source_meta,
+                                )
+                            })
+                            .map_err(|e| e.with_backup_source(false_span, source_meta))?
                     } else {
                         block
                     },
@@ -148,6 +352,176 @@ pub fn compile_statement<'code>(
     }
 }
 
+/// Branch to the case whose value the scrutinee in `scrutinee_memory` has, or to `default` if
+/// there's none. Few cases are compared against in turn, and more are sorted by value and split
+/// in halves with a comparison each, so finding a case takes a logarithmic number of branches.
+fn dispatch_cases(
+    state: &mut IRGenState,
+    mut dispatch: BlockBuilder,
+    bindings: &mut BindingCounter,
+    (scrutinee_memory, ty): (Binding, &ast::Type),
+    cases: &[(i64, BlockBinding)],
+    default: BlockBinding,
+) {
+    let mut compare = |dispatch: &mut BlockBuilder, condition, value: i64| {
+        let scrutinee = bindings.next_binding();
+        let flag = bindings.next_binding();
+        dispatch.load(scrutinee, scrutinee_memory, ty.into());
+        dispatch.assign(
+            flag,
+            Value::Cmp {
+                condition,
+                lhs: scrutinee,
+                rhs: value.into(),
+                bit_size: expr::bit_size(ty),
+            },
+        );
+        flag
+    };
+    if cases.len() > LINEAR_SWITCH_MAX_CASES {
+        // the values are signed, and the ones below the middle one go to the lower half
+        let (lower, upper) = cases.split_at(cases.len() / 2);
+        let flag = compare(&mut dispatch, Condition::LessThan, upper[0].0);
+        let (lower_block, upper_block) = (state.new_block(), state.new_block());
+        dispatch.finish_block(
+            state,
+            Branch::Conditional {
+                flag,
+                target_true: lower_block.block(),
+                target_false: upper_block.block(),
+            },
+        );
+        dispatch_cases(
+            state,
+            lower_block,
+            bindings,
+            (scrutinee_memory, ty),
+            lower,
+            default,
+        );
+        dispatch_cases(
+            state,
+            upper_block,
+            bindings,
+            (scrutinee_memory, ty),
+            upper,
+            default,
+        );
+        return;
+    }
+    for &(value, target) in cases {
+        let flag = compare(&mut dispatch, Condition::Equals, value);
+        let next = state.new_block();
+        dispatch.finish_block(
+            state,
+            Branch::Conditional {
+                flag,
+                target_true: target,
+                target_false: next.block(),
+            },
+        );
+        dispatch = next;
+    }
+    dispatch.finish_block(state, Branch::Unconditional { target: default });
+}
+
+/// Declare a variable in the scope at `block_depth`, and compile its initializer
+fn declare_variable<'code>(
+    state: &mut IRGenState,
+    mut builder: BlockBuilder,
+    bindings: &mut BindingCounter,
+    (declarator, align): (ast::Declarator<'code>, &[(ast::Expr, crate::error::Span)]),
+    variables: &mut VariableTracker<'code>,
+    block_depth: usize,
+    source_meta: &SourceMetadata,
+) -> Result<BlockBuilder, VarE> {
+    let ast::Declarator {
+        ty,
+        name: Source { source: name, span },
+        init,
+    } = declarator;
+    let align = declared_align(&ty, name, align, variables, source_meta)?;
+    let memory = bindings.next_binding();
+    builder.allocate(memory, ty.size(), align);
+    state.locals.insert(memory, (name.to_string(), span));
+    // the variable is in scope from the end of its declarator on, so its initializer already
+    // refers to it rather than to a variable it shadows
+    {
+        let shadowed = variables.get(name).map(|outer| outer.declared_at);
+        let ctx = variables.variables_at_depth(block_depth);
+        if ctx.contains_key(name) {
+            return Err(
+                VarE::new(VarError::Redeclared(name.to_string())).with_source(span, source_meta)
+            );
+        }
+        ctx.insert(
+            name,
+            Symbol::Variable(Variable {
+                memory,
+                ty: ty.clone(),
+                declared_at: span,
+            }),
+        );
+        state.symbols.define(name, SymbolKind::Variable, span);
+        if let Some(outer) = shadowed.filter(|_| state.warning_options.shadow) {
+            state.warn(
+                VarW::new(VarWarning::Shadow(name.to_string()))
+                    .with_source(span, source_meta)
+                    .with_note("shadowed declaration is here", outer, source_meta),
+            );
+        }
+    }
+    let builder = match init {
+        Some(init) => initializer::compile_initializer(
+            state,
+            builder,
+            (memory, &ty),
+            init,
+            bindings,
+            variables,
+            source_meta,
+        )?,
+        None => builder,
+    };
+    Ok(builder)
+}
+
+/// The alignment of a variable: the strictest of its `_Alignas` specifiers, or its type's own if
+/// there are none. Zero asks for nothing.
+pub fn declared_align(
+    ty: &ast::Type,
+    name: &str,
+    align: &[(ast::Expr, crate::error::Span)],
+    variables: &VariableTracker,
+    source_meta: &SourceMetadata,
+) -> Result<usize, VarE> {
+    let mut strictest = None;
+    for (expr, span) in align {
+        let value = expr::reduce_expr(expr, variables).ok_or_else(|| {
+            VarE::new(VarError::NonConstantAlignment).with_source(*span, source_meta)
+        })?;
+        if value == 0 {
+            continue;
+        }
+        if !(1..=MAX_ALIGN).contains(&value) || value.count_ones() != 1 {
+            return Err(
+                VarE::new(VarError::InvalidAlignment(value)).with_source(*span, source_meta)
+            );
+        }
+        if strictest.is_none_or(|(current, _)| current < value as usize) {
+            strictest = Some((value as usize, *span));
+        }
+    }
+    match strictest {
+        Some((align, span)) if align < ty.align() => {
+            Err(VarE::new(VarError::AlignmentReduced(name.to_string()))
+                .with_source(span, source_meta))
+        }
+        Some((align, _)) => Ok(align),
+        None => Ok(ty.align()),
+    }
+}
+
 /// Wire up branches so that they are merged in
 pub fn merge_branches(
     state: &mut IRGenState,
@@ -184,3 +558,93 @@ pub fn merge_branches(
 
     end_block
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{compiled, returned};
+    use super::*;
+    use crate::intermediate::interpret::{self, Inputs};
+
+    #[test]
+    fn declarations_declare_each_of_their_variables_in_turn() {
+        let source = "int main() {\n\
+                      \x20   int a, b = 2, *c = &b, d[2] = {b, 3};\n\
+                      \x20   a = *c + d[1];\n\
+                      \x20   return a;\n\
+                      }\n";
+        assert_eq!(returned(source), 5);
+        let missing_comma = "int main() { int a b; return 0; }\n";
+        let error = crate::check_str(missing_comma).iter().next().cloned();
+        assert!(error.is_some_and(|error| error.message.contains("`,` or `;`")));
+    }
+
+    #[test]
+    fn shadowing_variables_have_their_own_memory() {
+        let source = "int main() {\n\
+                      \x20   int x = 1, *outer = &x;\n\
+                      \x20   {\n\
+                      \x20       int x = 10;\n\
+                      \x20       x = x + *outer;\n\
+                      \x20       *outer = x;\n\
+                      \x20   }\n\
+                      \x20   return x;\n\
+                      }\n";
+        assert_eq!(returned(source), 11);
+    }
+
+    #[test]
+    fn void_functions_return_nothing() {
+        let source = "void store(int *p, int x) {\n\
+                      \x20   if (x < 0)\n\
+                      \x20       return;\n\
+                      \x20   *p = x;\n\
+                      }\n\
+                      void nothing(void) {}\n\
+                      int main(void) {\n\
+                      \x20   int x = 1;\n\
+                      \x20   store(&x, 2);\n\
+                      \x20   nothing();\n\
+                      \x20   return x;\n\
+                      }\n";
+        assert!(crate::check_str(source).is_empty());
+        let error = |source| {
+            let diagnostics = crate::check_str(source);
+            let error = diagnostics.iter().next().expect("the source has an error");
+            error.message.clone()
+        };
+        assert!(error("void f(void) { return 1; }").contains("with a value in a function"));
+        assert!(error("int f(void) { return; }").contains("with no value in a function"));
+        let used = "void f(void);\nint main(void) { return f() + 1; }";
+        assert!(error(used).contains("so its call has no value to use"));
+        let conflicting = "void f(void);\nint f(void);\nint main(void) { return 0; }";
+        assert!(error(conflicting).contains("different return type"));
+    }
+
+    #[test]
+    fn sparse_switches_find_their_case() {
+        let source = "int f(int x) {\n\
+                      \x20   switch (x) {\n\
+                      \x20   case 100000: return 1;\n\
+                      \x20   case -5000: return 2;\n\
+                      \x20   case 7: return 3;\n\
+                      \x20   case 4097: return 4;\n\
+                      \x20   case 12: return 5;\n\
+                      \x20   case -1: return 6;\n\
+                      \x20   default: return 9;\n\
+                      \x20   }\n\
+                      }\n";
+        let meta = SourceMetadata::new(source);
+        let module = compiled(&meta);
+        let function = &module.functions[0];
+        let results = [100000, -5000, 7, 4097, 12, -1, 8, -4999, 100001].map(|x| {
+            let mut integers = [0; 8];
+            integers[0] = x;
+            let inputs = Inputs {
+                integers,
+                floats: [0; 8],
+            };
+            interpret::run(&function.ir, &function.read_only, &inputs).map(|run| run.returned)
+        });
+        assert_eq!(results, [1, 2, 3, 4, 5, 6, 9, 9, 9].map(Ok));
+    }
+}
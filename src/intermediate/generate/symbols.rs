@@ -0,0 +1,222 @@
+//! The names a program declares and where each of them is used, for tools to go from a use of a
+//! name to its declaration, or from a declaration to its uses. Names are resolved while the IR is
+//! generated, so each use refers to the declaration that is in scope there.
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{Symbol, VariableTracker};
+use crate::ast;
+use crate::error::{Position, SourceMetadata, Span};
+use crate::grammar::lexer::Source;
+use hashbrown::HashMap;
+
+/// What a name was declared as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    GlobalVariable,
+    GlobalRegister,
+    Enumerator,
+    Parameter,
+    Variable,
+}
+
+/// Where a name is written, in the file it's in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: Option<String>,
+    pub position: Position,
+    pub len: usize,
+}
+
+impl Location {
+    fn new(span: Span, source_meta: &SourceMetadata) -> Option<Self> {
+        let (file, snippet) = source_meta.locate(span);
+        Some(Self {
+            file,
+            position: snippet?.position(),
+            len: span.len,
+        })
+    }
+
+    /// Whether the name covers the position of the file
+    pub fn contains(&self, file: Option<&str>, position: Position) -> bool {
+        self.file.as_deref() == file
+            && self.position.line == position.line
+            && (self.position.col..self.position.col + self.len).contains(&position.col)
+    }
+}
+
+/// A declaration of a name. A function is declared where it's defined, or where it's first
+/// declared if it isn't defined; its other declarations are uses of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub location: Location,
+}
+
+/// A use of a name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub location: Location,
+    /// the index of what it refers to in [`SymbolIndex::definitions`]
+    pub definition: usize,
+}
+
+/// The declarations of a program and the uses of their names, each in source order
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+impl SymbolIndex {
+    /// The declaration of the name at a position of a file, which may be a use of the name or the
+    /// name being declared
+    pub fn definition_at(&self, file: Option<&str>, position: Position) -> Option<&Definition> {
+        let used = self
+            .references
+            .iter()
+            .find(|reference| reference.location.contains(file, position))
+            .map(|reference| &self.definitions[reference.definition]);
+        used.or_else(|| {
+            self.definitions
+                .iter()
+                .find(|definition| definition.location.contains(file, position))
+        })
+    }
+
+    /// The uses of the declaration at an index of [`Self::definitions`]
+    pub fn references_to(&self, definition: usize) -> impl Iterator<Item = &Reference> + '_ {
+        self.references
+            .iter()
+            .filter(move |reference| reference.definition == definition)
+    }
+}
+
+/// What a use of a name refers to. Functions are found by name once they are all declared, the
+/// rest by where they were declared.
+#[derive(Debug, Clone)]
+enum Target {
+    Declared(Span),
+    Function(String),
+}
+
+/// The declarations and uses found while generating the IR
+#[derive(Debug, Default)]
+pub struct SymbolRecorder {
+    definitions: Vec<(String, SymbolKind, Span)>,
+    references: Vec<(Span, Target)>,
+}
+
+impl SymbolRecorder {
+    pub fn define(&mut self, name: &str, kind: SymbolKind, span: Span) {
+        self.definitions.push((name.to_string(), kind, span));
+    }
+
+    /// Record a use of a name, with what it resolved to
+    pub fn refer(&mut self, name: Source, symbol: Option<&Symbol>) {
+        match symbol {
+            Some(Symbol::Function(_)) => self.refer_function(name),
+            Some(symbol) => {
+                if let Some(declared_at) = symbol.declared_at() {
+                    self.references
+                        .push((name.span, Target::Declared(declared_at)));
+                }
+            }
+            None => (),
+        }
+    }
+
+    pub fn refer_function(&mut self, name: Source) {
+        self.references
+            .push((name.span, Target::Function(name.source.to_string())));
+    }
+
+    /// Record the uses of the names in an expression that is evaluated without generating code
+    /// for it, like a `case` label
+    pub fn refer_all(&mut self, expr: &ast::Expr, variables: &VariableTracker) {
+        let mut pending = alloc::vec![expr];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                ast::Expr::Variable { name } => self.refer(*name, variables.lookup(name.source)),
+                ast::Expr::Call { function, args } => {
+                    self.refer(*function, variables.lookup(function.source));
+                    pending.extend(args.iter().map(|(arg, _)| arg));
+                }
+                ast::Expr::CallPointer { pointer, args } => {
+                    pending.push(&pointer.0);
+                    pending.extend(args.iter().map(|(arg, _)| arg));
+                }
+                ast::Expr::Unary { expr, .. }
+                | ast::Expr::Cast { expr, .. }
+                | ast::Expr::Parenthesized { expr } => pending.push(&expr.0),
+                ast::Expr::Binary { lhs, rhs, .. } => pending.extend([&*lhs.0, &*rhs.0]),
+                ast::Expr::Ternary {
+                    condition,
+                    value_true,
+                    value_false,
+                } => pending.extend([&*condition.0, &*value_true.0, &*value_false.0]),
+                ast::Expr::Index { array, index } => pending.extend([&*array.0, &*index.0]),
+                ast::Expr::Member { base: operand, .. }
+                | ast::Expr::Arrow {
+                    pointer: operand, ..
+                } => pending.push(&operand.0),
+                ast::Expr::Constant(_)
+                | ast::Expr::LongConstant(_)
+                | ast::Expr::UnsignedConstant(_)
+                | ast::Expr::UnsignedLongConstant(_)
+                | ast::Expr::FloatConstant { .. }
+                | ast::Expr::StringLiteral(_)
+                | ast::Expr::AlignOf(_) => (),
+            }
+        }
+    }
+
+    /// Resolve the uses to the declarations, now that all of them are known. An expression may be
+    /// looked at more than once, so each use is kept once.
+    pub fn finish(mut self, source_meta: &SourceMetadata) -> SymbolIndex {
+        self.definitions.sort_by_key(|(_, _, span)| span.offset);
+        let mut definitions = Vec::with_capacity(self.definitions.len());
+        let (mut declared, mut functions) = (HashMap::new(), HashMap::new());
+        for (name, kind, span) in self.definitions {
+            let Some(location) = Location::new(span, source_meta) else {
+                continue;
+            };
+            declared.insert(span.offset, definitions.len());
+            if kind == SymbolKind::Function {
+                functions.insert(name.clone(), definitions.len());
+            }
+            definitions.push(Definition {
+                name,
+                kind,
+                span,
+                location,
+            });
+        }
+        self.references.sort_by_key(|(span, _)| span.offset);
+        self.references.dedup_by_key(|(span, _)| span.offset);
+        let references = self
+            .references
+            .into_iter()
+            .filter_map(|(span, target)| {
+                let definition = match target {
+                    Target::Declared(declared_at) => *declared.get(&declared_at.offset)?,
+                    Target::Function(name) => *functions.get(&name)?,
+                };
+                Some(Reference {
+                    span,
+                    location: Location::new(span, source_meta)?,
+                    definition,
+                })
+            })
+            .collect();
+        SymbolIndex {
+            definitions,
+            references,
+        }
+    }
+}
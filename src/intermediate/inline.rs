@@ -0,0 +1,372 @@
+//! Inlining: the calls to the functions declared `inline` are replaced by a copy of their body, so
+//! that the caller computes what they return itself. A `static inline` function that is called by
+//! no other function afterwards needs no body of its own, and is left out of the module.
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::generate::{FunctionIR, Module};
+use super::refactor::redefine::Rename;
+use super::remarks::Remark;
+use super::{
+    analysis, BasicBlock, Binding, BlockBinding, BlockEnd, Branch, PhiDescriptor, Statement, Value,
+};
+use crate::ast;
+
+/// Replace the calls to the inline functions of the module by their bodies, and remove the
+/// `static inline` functions nothing refers to anymore. A function that calls itself is never
+/// inlined, and the body inlined is the one the callee had before, so inlining always ends.
+pub fn inline_functions(module: &mut Module) {
+    let inlinable: HashMap<&str, usize> = module
+        .functions
+        .iter()
+        .enumerate()
+        .filter(|(_, function)| function.inline && not_inlinable(function).is_none())
+        .map(|(index, function)| (function.name, index))
+        .collect();
+    remark_missed_calls(module, &inlinable);
+    if inlinable.is_empty() {
+        return;
+    }
+    for caller in 0..module.functions.len() {
+        // only the blocks of the caller itself are searched for calls, not the inlined ones
+        let mut pending: Vec<_> = (0..module.functions[caller].ir.code.len())
+            .map(BlockBinding)
+            .collect();
+        while let Some(block) = pending.pop() {
+            let call = module.functions[caller].ir[block]
+                .statements
+                .iter()
+                .enumerate()
+                .find_map(|(statement, current)| match current {
+                    Statement::Assign {
+                        index,
+                        value: Value::Call { function, args },
+                    } => {
+                        let &callee = inlinable.get(function.as_str())?;
+                        let fits = callee != caller
+                            && highest_argument(&module.functions[callee])
+                                .is_none_or(|highest| highest < args.len());
+                        fits.then(|| (statement, *index, args.clone(), callee))
+                    }
+                    _ => None,
+                });
+            let Some((statement, result, args, callee)) = call else {
+                continue;
+            };
+            let callee = module.functions[callee].clone();
+            let rest = inline_call(
+                &mut module.functions[caller],
+                (block, statement),
+                result,
+                &args,
+                &callee,
+            );
+            let caller = &mut module.functions[caller];
+            caller.note_optimization("inlining");
+            let message = format!("`{}` inlined into `{}`", callee.name, caller.name);
+            caller
+                .remarks
+                .push(Remark::passed("inlining", "Inlined", message).at(result));
+            // the call may have been followed by others
+            pending.push(rest);
+        }
+    }
+
+    let needed: Vec<bool> = {
+        let referenced: HashSet<&str> = module
+            .functions
+            .iter()
+            .flat_map(|function| {
+                analysis::called_functions(&function.ir)
+                    .chain(analysis::addressed_functions(&function.ir))
+                    .filter(move |&name| name != function.name)
+            })
+            .chain(module.aliases.iter().map(|alias| alias.target))
+            .collect();
+        module
+            .functions
+            .iter()
+            .map(|function| {
+                !function.inline
+                    || function.linkage != ast::Linkage::Internal
+                    || referenced.contains(function.name)
+            })
+            .collect()
+    };
+    let mut needed = needed.into_iter();
+    module.functions.retain(|_| needed.next().unwrap_or(true));
+}
+
+/// Why the calls to the function can't be replaced by its body, if they can't: it has to be
+/// declared `inline`, can't be replaced when linking, and has to take and return its values in
+/// general purpose registers
+fn not_inlinable(function: &FunctionIR) -> Option<&'static str> {
+    if !function.inline {
+        Some("it isn't declared `inline`")
+    } else if function.linkage == ast::Linkage::Weak {
+        Some("it's weak, so another definition may replace it when linking")
+    } else if analysis::called_functions(&function.ir).any(|callee| callee == function.name) {
+        Some("it calls itself")
+    } else if function
+        .ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .any(|statement| {
+            matches!(
+                statement,
+                Statement::Assign {
+                    value: Value::FloatArgument(_)
+                        | Value::ResultAddress { .. }
+                        | Value::ReturnedStruct { .. },
+                    ..
+                }
+            )
+        })
+    {
+        Some("it takes or returns floating point values or structs")
+    } else {
+        None
+    }
+}
+
+/// Tell why the calls to the `inline` functions that won't be inlined aren't
+fn remark_missed_calls(module: &mut Module, inlinable: &HashMap<&str, usize>) {
+    let missed: Vec<Vec<Remark>> = module
+        .functions
+        .iter()
+        .map(|caller| {
+            caller
+                .ir
+                .code
+                .iter()
+                .flat_map(|block| &block.statements)
+                .filter_map(|statement| {
+                    let Statement::Assign {
+                        index,
+                        value: Value::Call { function, args },
+                    } = statement
+                    else {
+                        return None;
+                    };
+                    let callee = module
+                        .functions
+                        .iter()
+                        .find(|callee| callee.name == function && callee.inline)?;
+                    let reason = if !inlinable.contains_key(callee.name) {
+                        not_inlinable(callee)?
+                    } else if highest_argument(callee).is_some_and(|highest| highest >= args.len())
+                    {
+                        "the call passes fewer arguments than the function reads"
+                    } else {
+                        return None;
+                    };
+                    let message = format!(
+                        "`{}` not inlined into `{}`: {}",
+                        callee.name, caller.name, reason
+                    );
+                    Some(Remark::missed("inlining", "NotInlined", message).at(*index))
+                })
+                .collect()
+        })
+        .collect();
+    for (function, missed) in module.functions.iter_mut().zip(missed) {
+        function.remarks.extend(missed);
+    }
+}
+
+/// The highest integer argument the function reads, which a call must pass to be inlined
+fn highest_argument(function: &FunctionIR) -> Option<usize> {
+    function
+        .ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                value: Value::Argument(argument),
+                ..
+            } => Some(*argument),
+            _ => None,
+        })
+        .max()
+}
+
+/// Replace the call at the address by the body of the callee. The statements after the call are
+/// moved to a new block, which the returns of the callee branch to, and whose index is given.
+fn inline_call(
+    caller: &mut FunctionIR,
+    (block, statement): (BlockBinding, usize),
+    result: Binding,
+    args: &[Binding],
+    callee: &FunctionIR,
+) -> BlockBinding {
+    let binding_offset = analysis::binding_bound(&caller.ir.code);
+    let block_offset = caller.ir.code.len();
+    let rest = BlockBinding(block_offset + callee.ir.code.len());
+
+    // the read-only data of the callee joins the caller's, kept different from the rest
+    let read_only: Vec<usize> = callee
+        .read_only
+        .iter()
+        .map(|data| {
+            match caller
+                .read_only
+                .iter()
+                .position(|own| own.bytes == data.bytes)
+            {
+                Some(own) => {
+                    caller.read_only[own].align = caller.read_only[own].align.max(data.align);
+                    own
+                }
+                None => {
+                    caller.read_only.push(data.clone());
+                    caller.read_only.len() - 1
+                }
+            }
+        })
+        .collect();
+
+    let moved = caller.ir[block].statements.split_off(statement + 1);
+    caller.ir[block].statements.pop();
+    let end = core::mem::replace(
+        &mut caller.ir[block].end,
+        BlockEnd::Branch(Branch::Unconditional {
+            target: BlockBinding(block_offset),
+        }),
+    );
+    // what came from the block now comes from the statements moved out of it
+    let successors: HashSet<_> = end.branch_list().collect();
+    for successor in successors {
+        for statement in &mut caller.ir[successor].statements {
+            if let Statement::Assign {
+                value: Value::Phi { nodes },
+                ..
+            } = statement
+            {
+                for node in nodes.iter_mut().filter(|node| node.block_from == block) {
+                    node.block_from = rest;
+                }
+            }
+        }
+    }
+
+    let mut returns = Vec::new();
+    for (index, callee_block) in callee.ir.code.iter().enumerate() {
+        let mut copy = callee_block.clone();
+        copy.substitute_bindings(&mut |binding| binding.0 += binding_offset);
+        for statement in &mut copy.statements {
+            let Statement::Assign { index, value } = statement else {
+                continue;
+            };
+            index.0 += binding_offset;
+            match value {
+                Value::Argument(argument) => *value = Value::Binding(args[*argument]),
+                Value::ReadOnlyAddress(data) => *data = read_only[*data],
+                Value::Phi { nodes } => {
+                    for node in nodes {
+                        node.block_from.0 += block_offset;
+                    }
+                }
+                _ => (),
+            }
+        }
+        copy.end = match copy.end {
+            BlockEnd::Return(value) => {
+                returns.push(PhiDescriptor {
+                    value,
+                    block_from: BlockBinding(block_offset + index),
+                });
+                BlockEnd::Branch(Branch::Unconditional { target: rest })
+            }
+            BlockEnd::Branch(Branch::Unconditional { target }) => {
+                BlockEnd::Branch(Branch::Unconditional {
+                    target: BlockBinding(target.0 + block_offset),
+                })
+            }
+            BlockEnd::Branch(Branch::Conditional {
+                flag,
+                target_true,
+                target_false,
+            }) => BlockEnd::Branch(Branch::Conditional {
+                flag,
+                target_true: BlockBinding(target_true.0 + block_offset),
+                target_false: BlockBinding(target_false.0 + block_offset),
+            }),
+        };
+        caller.ir.code.push(copy);
+    }
+
+    let returned = match returns.as_slice() {
+        [] => Value::Undefined,
+        [single] => Value::Binding(single.value),
+        _ => Value::Phi { nodes: returns },
+    };
+    let mut statements = vec![Statement::Assign {
+        index: result,
+        value: returned,
+    }];
+    statements.extend(moved);
+    caller.ir.code.push(BasicBlock { statements, end });
+    (caller.ir.forward_map, caller.ir.backwards_map) =
+        super::generate::generate_branching_graphs(&caller.ir.code);
+
+    let offset = |binding: &Binding| Binding(binding.0 + binding_offset);
+    caller.spans.extend(
+        callee
+            .spans
+            .iter()
+            .map(|(binding, span)| (offset(binding), *span)),
+    );
+    caller.variables.extend(
+        callee
+            .variables
+            .iter()
+            .map(|(binding, name)| (offset(binding), name.clone())),
+    );
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SourceMetadata;
+    use crate::intermediate::generate::tests::compiled;
+    use crate::intermediate::interpret;
+
+    #[test]
+    fn calls_to_inline_functions_are_replaced_by_their_body() {
+        let source = "static inline int twice(int x) { return x + x; }\n\
+                      static inline int magnitude(int x) {\n\
+                      \x20   if (x < 0)\n\
+                      \x20       return -x;\n\
+                      \x20   return x;\n\
+                      }\n\
+                      inline int shared(int x) { return twice(x) + 1; }\n\
+                      static inline int countdown(int n) { return n ? countdown(n - 1) : 0; }\n\
+                      int main() {\n\
+                      \x20   return magnitude(-4) * 100 + shared(3) * 10 + countdown(2);\n\
+                      }\n";
+        let meta = SourceMetadata::new(source);
+        let mut module = compiled(&meta);
+        inline_functions(&mut module);
+        let names: Vec<_> = module
+            .functions
+            .iter()
+            .map(|function| function.name)
+            .collect();
+        // the recursive function is still called, and the interpreter has calls return 0
+        assert_eq!(names, ["shared", "countdown", "main"]);
+        let main = module.functions.last().expect("main is kept");
+        let inputs = interpret::sample_inputs()
+            .next()
+            .expect("there are samples");
+        let run = interpret::run(&main.ir, &main.read_only, &inputs);
+        assert_eq!(run.map(|run| run.returned), Ok(470));
+        let variable = crate::check_str("inline int x;\n").iter().next().cloned();
+        assert!(variable.is_some_and(|error| error.message.contains("only applies to functions")));
+    }
+}
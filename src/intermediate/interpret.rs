@@ -0,0 +1,844 @@
+//! An interpreter for the IR of a function, to check that the passes don't change what it does.
+//! Each binding holds 64 bits: integers sign extended, like `Value::Constant`, and floating-point
+//! values as their bits. Calls to other functions are not followed: they are recorded, and give
+//! back zero. Calls through a pointer are recorded the same way, with the name of the function the
+//! pointer holds the address of. A struct returned in registers is returned as its first 8 bytes.
+//! The global variables start zeroed, as the function doesn't know what they're defined with, and
+//! the ones it writes are part of what it does.
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::generate::{ReadOnlyData, MAX_REGISTER_ARGUMENTS};
+use super::{Binding, BlockBinding, BlockEnd, Branch, ByteSize, Callee, CouldBeConstant, FloatOp};
+use super::{Statement, Value, IR};
+use crate::codegen::assembly::{BitSize, Condition};
+use crate::error::Error;
+use core::fmt;
+use hashbrown::HashMap;
+use thiserror::Error;
+
+/// Statements run before giving up on a function that seems to never return
+const MAX_STEPS: usize = 1 << 16;
+
+/// The integer arguments the samples are made from
+const SAMPLE_INTEGERS: [i64; 8] = [0, 1, -1, 2, 7, 100, i32::MAX as i64, i32::MIN as i64];
+
+/// The floating-point arguments the samples are made from
+const SAMPLE_FLOATS: [f64; 4] = [0.0, 1.5, -2.25, 1e10];
+
+/// The arguments a function is run with
+#[derive(Debug, Clone)]
+pub struct Inputs {
+    pub integers: [i64; MAX_REGISTER_ARGUMENTS],
+    /// the bits of a `double`
+    pub floats: [u64; MAX_REGISTER_ARGUMENTS],
+}
+
+/// Everything a run of a function can be told apart by
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub returned: i64,
+    /// the functions called, in order, with their arguments
+    pub calls: Vec<(String, Vec<i64>)>,
+    /// the global register variables written, with their last values
+    pub registers: Vec<(u8, i64)>,
+    /// the global variables written, by name, with the bytes they were left with
+    pub globals: Vec<(String, Vec<u8>)>,
+}
+
+/// Why a run didn't finish
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// the code is wrong: it uses a binding that isn't defined, or a phi node has no value for
+    /// the block it was reached from
+    Malformed(String),
+    /// the code does something undefined, like dividing by zero, or takes too long. Nothing can be
+    /// said of what it does.
+    Undefined,
+}
+
+/// The samples of the inputs every function is checked with
+pub fn sample_inputs() -> impl Iterator<Item = Inputs> {
+    (0..SAMPLE_INTEGERS.len()).map(|sample| Inputs {
+        integers: core::array::from_fn(|arg| {
+            SAMPLE_INTEGERS[(sample + arg * 3) % SAMPLE_INTEGERS.len()]
+        }),
+        floats: core::array::from_fn(|arg| {
+            SAMPLE_FLOATS[(sample + arg) % SAMPLE_FLOATS.len()].to_bits()
+        }),
+    })
+}
+
+/// Where the memory of each allocation starts: allocations (and pieces of read-only data) are
+/// apart by 4GB, so that their addresses don't depend on the order they are made in
+fn allocation_base(binding: Binding) -> i64 {
+    (binding.0 as i64 + 1) << 32
+}
+
+fn read_only_base(index: usize) -> i64 {
+    (1 << 62) | ((index as i64) << 32)
+}
+
+/// The address of the function whose address was taken `index`-th
+fn function_address(index: usize) -> i64 {
+    (1 << 61) | ((index as i64) << 32)
+}
+
+/// The address of the global variable whose address was taken `index`-th
+fn global_address(index: usize) -> i64 {
+    (1 << 59) | ((index as i64) << 32)
+}
+
+/// The bytes of each global variable, as its size isn't known: what's past them is undefined
+const GLOBAL_SIZE: usize = 1 << 12;
+
+/// Where the caller wants the struct the function returns through memory
+const RESULT_BASE: i64 = 1 << 60;
+
+struct Machine<'ir> {
+    inputs: &'ir Inputs,
+    bindings: HashMap<Binding, i64>,
+    /// the bytes of each allocation, by their base address
+    memory: HashMap<i64, Vec<u8>>,
+    /// the functions whose address was taken, in order
+    functions: Vec<String>,
+    /// the global variables whose address was taken, in order, and whether they were written
+    globals: Vec<(String, bool)>,
+    outcome: Outcome,
+}
+
+/// Run the code of a function with the given arguments
+pub fn run(ir: &IR, read_only: &[ReadOnlyData], inputs: &Inputs) -> Result<Outcome, Stop> {
+    let mut machine = Machine {
+        inputs,
+        bindings: HashMap::new(),
+        memory: read_only
+            .iter()
+            .enumerate()
+            .map(|(index, data)| (read_only_base(index), data.bytes.clone()))
+            .collect(),
+        functions: Vec::new(),
+        globals: Vec::new(),
+        outcome: Outcome {
+            returned: 0,
+            calls: Vec::new(),
+            registers: Vec::new(),
+            globals: Vec::new(),
+        },
+    };
+    let (mut block, mut from) = (BlockBinding(0), None);
+    let mut steps = 0;
+    loop {
+        let code = ir
+            .code
+            .get(block.0)
+            .ok_or_else(|| Stop::Malformed(format!("branch to {}, which doesn't exist", block)))?;
+        for statement in &code.statements {
+            steps += 1;
+            if steps > MAX_STEPS {
+                return Err(Stop::Undefined);
+            }
+            machine.execute(statement, from)?;
+        }
+        let next = match code.end {
+            BlockEnd::Return(binding) => {
+                machine.outcome.returned = machine.get(binding)?;
+                machine.outcome.registers.sort_unstable();
+                for (index, (name, written)) in machine.globals.iter().enumerate() {
+                    if *written {
+                        let bytes = machine.memory[&global_address(index)].clone();
+                        machine.outcome.globals.push((name.clone(), bytes));
+                    }
+                }
+                machine.outcome.globals.sort_unstable();
+                return Ok(machine.outcome);
+            }
+            BlockEnd::Branch(Branch::Unconditional { target }) => target,
+            BlockEnd::Branch(Branch::Conditional {
+                flag,
+                target_true,
+                target_false,
+            }) => {
+                if machine.get(flag)? != 0 {
+                    target_true
+                } else {
+                    target_false
+                }
+            }
+        };
+        (block, from) = (next, Some(block));
+    }
+}
+
+/// The value of an operation on `bit_size` bits, sign extended like the constants
+fn wrap(value: i64, bit_size: BitSize) -> i64 {
+    match bit_size {
+        BitSize::Bit32 => value as i32 as i64,
+        BitSize::Bit64 => value,
+    }
+}
+
+/// The value of `bit_size` bits, read as unsigned
+fn unsigned(value: i64, bit_size: BitSize) -> u64 {
+    match bit_size {
+        BitSize::Bit32 => value as u32 as u64,
+        BitSize::Bit64 => value as u64,
+    }
+}
+
+fn float(bits: i64, bit_size: BitSize) -> f64 {
+    match bit_size {
+        BitSize::Bit32 => f32::from_bits(bits as u32).into(),
+        BitSize::Bit64 => f64::from_bits(bits as u64),
+    }
+}
+
+fn float_bits(value: f64, bit_size: BitSize) -> i64 {
+    match bit_size {
+        BitSize::Bit32 => (value as f32).to_bits() as i64,
+        BitSize::Bit64 => value.to_bits() as i64,
+    }
+}
+
+impl Machine<'_> {
+    fn get(&self, binding: Binding) -> Result<i64, Stop> {
+        self.bindings
+            .get(&binding)
+            .copied()
+            .ok_or_else(|| Stop::Malformed(format!("{} is used before it's defined", binding)))
+    }
+
+    fn operand(&self, operand: CouldBeConstant) -> Result<i64, Stop> {
+        match operand {
+            CouldBeConstant::Binding(binding) => self.get(binding),
+            CouldBeConstant::Constant(constant) => Ok(constant),
+        }
+    }
+
+    fn binary(&self, lhs: Binding, rhs: CouldBeConstant) -> Result<(i64, i64), Stop> {
+        Ok((self.get(lhs)?, self.operand(rhs)?))
+    }
+
+    /// The bytes at an address, which must be inside a single allocation
+    fn bytes(&mut self, address: i64, len: usize) -> Result<&mut [u8], Stop> {
+        let (base, offset) = (address & !0xFFFF_FFFF, (address & 0xFFFF_FFFF) as usize);
+        self.memory
+            .get_mut(&base)
+            .and_then(|bytes| bytes.get_mut(offset..offset + len))
+            .ok_or(Stop::Undefined)
+    }
+
+    /// The value of `byte_size` bytes at an address, extended the way loading it does
+    fn read(&mut self, address: i64, byte_size: ByteSize) -> Result<i64, Stop> {
+        let mut bytes = [0; 8];
+        let len = byte_size.bytes();
+        bytes[..len].copy_from_slice(self.bytes(address, len)?);
+        let value = i64::from_le_bytes(bytes);
+        Ok(match byte_size {
+            ByteSize::I8 => value as i8 as i64,
+            ByteSize::I16 => value as i16 as i64,
+            ByteSize::U32 => value as i32 as i64,
+            _ => value,
+        })
+    }
+
+    /// Write the lowest `byte_size` bytes of a value at an address
+    fn write(&mut self, address: i64, value: i64, byte_size: ByteSize) -> Result<(), Stop> {
+        let len = byte_size.bytes();
+        self.bytes(address, len)?
+            .copy_from_slice(&value.to_le_bytes()[..len]);
+        let base = address & !0xFFFF_FFFF;
+        if let Some(index) = (0..self.globals.len()).find(|index| global_address(*index) == base) {
+            self.globals[index].1 = true;
+        }
+        Ok(())
+    }
+
+    /// The name of the function a pointer points to
+    fn pointed_function(&self, pointer: Binding) -> Result<String, Stop> {
+        let pointer = self.get(pointer)?;
+        (0..self.functions.len())
+            .find(|index| function_address(*index) == pointer)
+            .map(|index| self.functions[index].clone())
+            .ok_or(Stop::Undefined)
+    }
+
+    fn execute(&mut self, statement: &Statement, from: Option<BlockBinding>) -> Result<(), Stop> {
+        match statement {
+            Statement::Assign { index, value } => {
+                let value = self.evaluate(*index, value, from)?;
+                self.bindings.insert(*index, value);
+            }
+            // volatile and atomic accesses behave the same here, as nothing else touches the
+            // memory
+            Statement::Store {
+                mem_binding,
+                binding,
+                byte_size,
+                ..
+            }
+            | Statement::AtomicStore {
+                mem_binding,
+                binding,
+                byte_size,
+            } => {
+                let (address, value) = (self.get(*mem_binding)?, self.get(*binding)?);
+                self.write(address, value, *byte_size)?;
+            }
+            Statement::SetRegister { register, binding } => {
+                let value = self.get(*binding)?;
+                let registers = &mut self.outcome.registers;
+                registers.retain(|(written, _)| written != register);
+                registers.push((*register, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(
+        &mut self,
+        index: Binding,
+        value: &Value,
+        from: Option<BlockBinding>,
+    ) -> Result<i64, Stop> {
+        Ok(match value {
+            Value::Allocate { size, .. } => {
+                let base = allocation_base(index);
+                self.memory.insert(base, vec![0; *size]);
+                base
+            }
+            Value::Phi { nodes } => {
+                let node = nodes
+                    .iter()
+                    .find(|node| Some(node.block_from) == from)
+                    .ok_or_else(|| {
+                        Stop::Malformed(format!(
+                            "the phi node of {} has no value for where it was reached from",
+                            index
+                        ))
+                    })?;
+                self.get(node.value)?
+            }
+            Value::Select {
+                flag,
+                if_true,
+                if_false,
+            } => {
+                if self.get(*flag)? != 0 {
+                    self.operand(*if_true)?
+                } else {
+                    self.operand(*if_false)?
+                }
+            }
+            Value::Cmp {
+                condition,
+                lhs,
+                rhs,
+                bit_size,
+            } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                let (lhs, rhs) = (wrap(lhs, *bit_size), wrap(rhs, *bit_size));
+                let (unsigned_lhs, unsigned_rhs) =
+                    (unsigned(lhs, *bit_size), unsigned(rhs, *bit_size));
+                match condition {
+                    Condition::Equals => lhs == rhs,
+                    Condition::NotEquals => lhs != rhs,
+                    Condition::GreaterThan => lhs > rhs,
+                    Condition::GreaterEqual => lhs >= rhs,
+                    Condition::LessThan => lhs < rhs,
+                    Condition::LessEqual => lhs <= rhs,
+                    Condition::Higher => unsigned_lhs > unsigned_rhs,
+                    Condition::HigherSame => unsigned_lhs >= unsigned_rhs,
+                    Condition::Lower => unsigned_lhs < unsigned_rhs,
+                    Condition::LowerSame => unsigned_lhs <= unsigned_rhs,
+                }
+                .into()
+            }
+            Value::Load {
+                mem_binding,
+                byte_size,
+                ..
+            }
+            | Value::AtomicLoad {
+                mem_binding,
+                byte_size,
+            } => {
+                let address = self.get(*mem_binding)?;
+                self.read(address, *byte_size)?
+            }
+            Value::AtomicFetchAdd {
+                mem_binding,
+                binding,
+                byte_size,
+            } => {
+                let address = self.get(*mem_binding)?;
+                let old = self.read(address, *byte_size)?;
+                self.write(address, old.wrapping_add(self.get(*binding)?), *byte_size)?;
+                old
+            }
+            Value::Negate { binding, bit_size } => {
+                wrap(self.get(*binding)?.wrapping_neg(), *bit_size)
+            }
+            Value::FlipBits { binding, bit_size } => wrap(!self.get(*binding)?, *bit_size),
+            Value::Add { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs.wrapping_add(rhs), *bit_size)
+            }
+            Value::Subtract { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs.wrapping_sub(rhs), *bit_size)
+            }
+            Value::Multiply { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs.wrapping_mul(rhs), *bit_size)
+            }
+            Value::Divide {
+                lhs,
+                rhs,
+                is_signed,
+                bit_size,
+            } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                let (lhs, rhs) = (wrap(lhs, *bit_size), wrap(rhs, *bit_size));
+                if rhs == 0 {
+                    return Err(Stop::Undefined);
+                }
+                match (is_signed, bit_size) {
+                    (true, _) => wrap(lhs.wrapping_div(rhs), *bit_size),
+                    (false, BitSize::Bit32) => wrap((lhs as u32 / rhs as u32).into(), *bit_size),
+                    (false, BitSize::Bit64) => (lhs as u64 / rhs as u64) as i64,
+                }
+            }
+            Value::Lsl { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs << (rhs as u32 & (bit_size.bits() - 1)), *bit_size)
+            }
+            Value::Lsr { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                let amount = rhs as u32 & (bit_size.bits() - 1);
+                match bit_size {
+                    BitSize::Bit32 => wrap((lhs as u32 >> amount).into(), *bit_size),
+                    BitSize::Bit64 => (lhs as u64 >> amount) as i64,
+                }
+            }
+            Value::Asr { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs, *bit_size) >> (rhs as u32 & (bit_size.bits() - 1))
+            }
+            Value::And { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs & rhs, *bit_size)
+            }
+            Value::Or { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs | rhs, *bit_size)
+            }
+            Value::Xor { lhs, rhs, bit_size } => {
+                let (lhs, rhs) = self.binary(*lhs, *rhs)?;
+                wrap(lhs ^ rhs, *bit_size)
+            }
+            Value::SignExtend { binding, from } => {
+                let value = self.get(*binding)?;
+                match from {
+                    ByteSize::U8 | ByteSize::I8 => value as i8 as i64,
+                    ByteSize::I16 | ByteSize::U16 => value as i16 as i64,
+                    ByteSize::U32 | ByteSize::F32 => value as i32 as i64,
+                    ByteSize::U64 | ByteSize::F64 => value,
+                }
+            }
+            Value::FloatConstant { bits, .. } => *bits as i64,
+            Value::FloatArithmetic {
+                op,
+                lhs,
+                rhs,
+                bit_size,
+            } => {
+                let lhs = float(self.get(*lhs)?, *bit_size);
+                let rhs = float(self.get(*rhs)?, *bit_size);
+                let result = match op {
+                    FloatOp::Add => lhs + rhs,
+                    FloatOp::Subtract => lhs - rhs,
+                    FloatOp::Multiply => lhs * rhs,
+                    FloatOp::Divide => lhs / rhs,
+                };
+                float_bits(result, *bit_size)
+            }
+            Value::FloatNegate { binding, bit_size } => {
+                float_bits(-float(self.get(*binding)?, *bit_size), *bit_size)
+            }
+            Value::FloatCmp {
+                condition,
+                lhs,
+                rhs,
+                bit_size,
+            } => {
+                let lhs = float(self.get(*lhs)?, *bit_size);
+                let rhs = float(self.get(*rhs)?, *bit_size);
+                match condition {
+                    Condition::Equals => lhs == rhs,
+                    Condition::NotEquals => lhs != rhs,
+                    Condition::GreaterThan => lhs > rhs,
+                    Condition::GreaterEqual => lhs >= rhs,
+                    Condition::LessThan => lhs < rhs,
+                    Condition::LessEqual => lhs <= rhs,
+                    Condition::Higher
+                    | Condition::HigherSame
+                    | Condition::Lower
+                    | Condition::LowerSame => {
+                        unreachable!("floating-point values are never compared as unsigned")
+                    }
+                }
+                .into()
+            }
+            Value::IntToFloat { binding, from, to } => {
+                float_bits(wrap(self.get(*binding)?, *from) as f64, *to)
+            }
+            // the conversion saturates, like `fcvtzs` does
+            Value::FloatToInt { binding, from, to } => {
+                let value = float(self.get(*binding)?, *from);
+                match to {
+                    BitSize::Bit32 => (value as i32).into(),
+                    BitSize::Bit64 => value as i64,
+                }
+            }
+            Value::FloatConvert { binding, from, to } => {
+                float_bits(float(self.get(*binding)?, *from), *to)
+            }
+            Value::Constant(constant) => *constant,
+            Value::Undefined => 0,
+            Value::Binding(binding) => self.get(*binding)?,
+            Value::Call { function, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.get(*arg))
+                    .collect::<Result<_, _>>()?;
+                self.outcome.calls.push((function.clone(), args));
+                0
+            }
+            Value::CallPointer { pointer, args } => {
+                let function = self.pointed_function(*pointer)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.get(*arg))
+                    .collect::<Result<_, _>>()?;
+                self.outcome.calls.push((function, args));
+                0
+            }
+            // the value the callee returns is a zero of either width, which has no bits set
+            Value::CallFloat { callee, args, .. } => {
+                let function = match callee {
+                    Callee::Function(function) => function.clone(),
+                    Callee::Pointer(pointer) => self.pointed_function(*pointer)?,
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| self.get(*arg))
+                    .collect::<Result<_, _>>()?;
+                self.outcome.calls.push((function, args));
+                0
+            }
+            // the struct the callee returns is all zeros
+            Value::CallStruct {
+                callee,
+                args,
+                result,
+                size,
+            } => {
+                let function = match callee {
+                    Callee::Function(function) => function.clone(),
+                    Callee::Pointer(pointer) => self.pointed_function(*pointer)?,
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| self.get(*arg))
+                    .collect::<Result<_, _>>()?;
+                self.outcome.calls.push((function, args));
+                let result = self.get(*result)?;
+                self.bytes(result, *size)?.fill(0);
+                0
+            }
+            Value::ReturnedStruct { memory, size } => {
+                let memory = self.get(*memory)?;
+                let mut word = [0; 8];
+                let len = (*size).min(8);
+                word[..len].copy_from_slice(self.bytes(memory, len)?);
+                i64::from_le_bytes(word)
+            }
+            Value::ResultAddress { size } => {
+                self.memory.insert(RESULT_BASE, vec![0; *size]);
+                RESULT_BASE
+            }
+            Value::FunctionAddress(function) => {
+                let index = match self.functions.iter().position(|name| name == function) {
+                    Some(index) => index,
+                    None => {
+                        self.functions.push(function.clone());
+                        self.functions.len() - 1
+                    }
+                };
+                function_address(index)
+            }
+            Value::GlobalAddress(variable) => {
+                let index = match self.globals.iter().position(|(name, _)| name == variable) {
+                    Some(index) => index,
+                    None => {
+                        self.globals.push((variable.clone(), false));
+                        self.memory
+                            .insert(global_address(self.globals.len() - 1), vec![0; GLOBAL_SIZE]);
+                        self.globals.len() - 1
+                    }
+                };
+                global_address(index)
+            }
+            Value::Argument(index) => self.inputs.integers[*index],
+            Value::FloatArgument(index) => self.inputs.floats[*index] as i64,
+            Value::GlobalRegister(register) => self
+                .outcome
+                .registers
+                .iter()
+                .find(|(written, _)| written == register)
+                .map_or(0, |(_, value)| *value),
+            Value::Address { mem_binding } => self.get(*mem_binding)?,
+            Value::ReadOnlyAddress(index) => read_only_base(*index),
+            Value::ElementAddress {
+                base,
+                index,
+                element_size,
+                bit_size,
+            } => {
+                let index = wrap(self.get(*index)?, *bit_size);
+                self.get(*base)?
+                    .wrapping_add(index.wrapping_mul(*element_size as i64))
+            }
+        })
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "returns {}", self.returned)?;
+        for (function, args) in &self.calls {
+            write!(f, ", calls {}{:?}", function, args)?;
+        }
+        for (register, value) in &self.registers {
+            write!(f, ", leaves {} in x{}", value, register)?;
+        }
+        // the zeros the global ends with are left out
+        for (variable, bytes) in &self.globals {
+            let len = bytes
+                .iter()
+                .rposition(|&byte| byte != 0)
+                .map_or(0, |last| last + 1);
+            write!(f, ", leaves {:?} in @{}", &bytes[..len], variable)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Malformed(problem) => write!(f, "is malformed: {}", problem),
+            Stop::Undefined => f.write_str("has undefined behavior"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum PassError {
+    #[error("internal compiler error: the {pass} pass changed what {function:?} does with the integer arguments {args:?}: it {before} before, and {after} after")]
+    Miscompiled {
+        pass: &'static str,
+        function: String,
+        args: Vec<i64>,
+        before: String,
+        after: String,
+    },
+}
+
+pub type PassE = Error<PassError>;
+
+/// Checks that each pass run on the IR of a function leaves it doing the same as it did before,
+/// with all the sample inputs
+pub struct PassVerifier<'data> {
+    function: String,
+    read_only: &'data [ReadOnlyData],
+    /// what the last correct IR did with each sample
+    expected: Vec<(Inputs, Result<Outcome, Stop>)>,
+    pub errors: Vec<PassE>,
+}
+
+impl<'data> PassVerifier<'data> {
+    pub fn new(function: &str, ir: &IR, read_only: &'data [ReadOnlyData]) -> Self {
+        Self {
+            function: function.to_string(),
+            read_only,
+            expected: sample_inputs()
+                .map(|inputs| {
+                    let outcome = run(ir, read_only, &inputs);
+                    (inputs, outcome)
+                })
+                .collect(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Compare what the IR left by the pass does with what the IR before it did. Undefined
+    /// behavior before the pass allows anything after it. A mismatch is reported for the first
+    /// sample that shows it, and the IR after the pass is expected from then on, so that each
+    /// miscompilation is blamed on the pass that caused it.
+    pub fn check(&mut self, pass: &'static str, ir: &IR) {
+        let mut mismatch = None;
+        for (inputs, expected) in &mut self.expected {
+            let outcome = run(ir, self.read_only, inputs);
+            if mismatch.is_none() && *expected != Err(Stop::Undefined) && outcome != *expected {
+                let describe = |outcome: &Result<Outcome, Stop>| match outcome {
+                    Ok(outcome) => outcome.to_string(),
+                    Err(stop) => stop.to_string(),
+                };
+                mismatch = Some(PassError::Miscompiled {
+                    pass,
+                    function: self.function.clone(),
+                    args: inputs.integers.to_vec(),
+                    before: describe(expected),
+                    after: describe(&outcome),
+                });
+            }
+            *expected = outcome;
+        }
+        self.errors.extend(mismatch.map(PassE::new));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{BasicBlock, PhiDescriptor};
+
+    fn assign(index: usize, value: Value) -> Statement {
+        Statement::Assign {
+            index: Binding(index),
+            value,
+        }
+    }
+
+    /// The first argument, through a phi node after comparing it with `constant`
+    fn first_argument(constant: i64) -> IR {
+        vec![
+            BasicBlock {
+                statements: vec![
+                    assign(0, Value::Argument(0)),
+                    assign(1, Value::Constant(constant)),
+                    assign(
+                        2,
+                        Value::Cmp {
+                            condition: Condition::GreaterThan,
+                            lhs: Binding(0),
+                            rhs: Binding(1).into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    ),
+                ],
+                end: Branch::Conditional {
+                    flag: Binding(2),
+                    target_true: BlockBinding(1),
+                    target_false: BlockBinding(1),
+                }
+                .into(),
+            },
+            BasicBlock {
+                statements: vec![assign(
+                    3,
+                    Value::Phi {
+                        nodes: vec![PhiDescriptor {
+                            value: Binding(0),
+                            block_from: BlockBinding(0),
+                        }],
+                    },
+                )],
+                end: Binding(3).into(),
+            },
+        ]
+        .into()
+    }
+
+    #[test]
+    fn phi_nodes_take_the_value_from_the_block_reached_from() {
+        let inputs = sample_inputs().next().unwrap();
+        let outcome = run(&first_argument(1), &[], &inputs).unwrap();
+        assert_eq!(outcome.returned, inputs.integers[0]);
+    }
+
+    #[test]
+    fn calls_through_pointers_are_recorded_by_the_function_pointed_to() {
+        let call = |pointer| -> IR {
+            vec![BasicBlock {
+                statements: vec![
+                    assign(0, Value::FunctionAddress("f".to_string())),
+                    assign(1, Value::Constant(pointer)),
+                    assign(2, Value::Argument(0)),
+                    assign(
+                        3,
+                        Value::CallPointer {
+                            pointer: Binding(pointer as usize),
+                            args: vec![Binding(2)],
+                        },
+                    ),
+                ],
+                end: Binding(3).into(),
+            }]
+            .into()
+        };
+        let inputs = sample_inputs().nth(1).unwrap();
+        let outcome = run(&call(0), &[], &inputs).unwrap();
+        assert_eq!(outcome.calls, [("f".to_string(), vec![inputs.integers[0]])]);
+        // the constant 1 isn't the address of any function
+        assert_eq!(run(&call(1), &[], &inputs), Err(Stop::Undefined));
+    }
+
+    #[test]
+    fn written_global_variables_are_part_of_what_the_function_does() {
+        let ir: IR = vec![BasicBlock {
+            statements: vec![
+                assign(0, Value::GlobalAddress("counter".to_string())),
+                assign(1, Value::Constant(5)),
+                Statement::Store {
+                    mem_binding: Binding(0),
+                    binding: Binding(1),
+                    byte_size: ByteSize::U32,
+                    volatile: false,
+                },
+                // a global that is only read starts zeroed
+                assign(2, Value::GlobalAddress("other".to_string())),
+                assign(
+                    3,
+                    Value::Load {
+                        mem_binding: Binding(2),
+                        byte_size: ByteSize::U32,
+                        volatile: false,
+                    },
+                ),
+            ],
+            end: Binding(3).into(),
+        }]
+        .into();
+        let inputs = sample_inputs().next().unwrap();
+        let outcome = run(&ir, &[], &inputs).unwrap();
+        assert_eq!(outcome.to_string(), "returns 0, leaves [5] in @counter");
+    }
+
+    #[test]
+    fn passes_are_blamed_for_what_they_change() {
+        let mut verifier = PassVerifier::new("first_argument", &first_argument(1), &[]);
+        verifier.check("harmless", &first_argument(1));
+        assert!(verifier.errors.is_empty());
+
+        let mut broken = first_argument(1);
+        broken.code[1].statements.clear();
+        verifier.check("broken", &broken);
+        assert_eq!(verifier.errors.len(), 1);
+        assert!(verifier.errors[0].to_string().contains("the broken pass"));
+    }
+}
@@ -0,0 +1,438 @@
+//! Promotion of local variables to bindings. IR generation gives each variable a piece of stack
+//! memory, which is stored to when the variable is assigned and loaded from when it's read. The
+//! variables whose memory is only ever loaded and stored whole, without its address going anywhere
+//! else, don't need to be in memory: each load is replaced by the value of the last store before
+//! it, cut down to its lowest bytes when the variable is smaller than a register. Where the stores
+//! of different paths meet, a phi node merges them. Those go in the iterated dominance frontiers
+//! of the blocks that store, as in the construction of Cytron et al., and the values are found
+//! walking the dominator tree.
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::analysis::{self, iterated_frontier, BindingUsage, DominatorTree};
+use super::refactor::redefine::{Rename, RenameMap};
+use super::{
+    Binding, BlockBinding, BlockEnd, Branch, ByteSize, CouldBeConstant, PhiDescriptor, Statement,
+    Value, IR,
+};
+use crate::codegen::assembly::BitSize;
+
+/// Promote the variables that can be, giving back the allocations that were promoted
+pub fn promote_variables(ir: &mut IR) -> Vec<Binding> {
+    let variables = promotable(ir);
+    if variables.is_empty() {
+        return Vec::new();
+    }
+    let tree = DominatorTree::new(ir);
+    let frontiers = tree.frontiers(ir);
+    let mut next_binding = analysis::binding_bound(&ir.code);
+
+    // the phi nodes each variable needs, by the block they go in
+    let mut phis: HashMap<BlockBinding, Vec<(Binding, Binding)>> = HashMap::new();
+    let mut ordered: Vec<_> = variables.keys().copied().collect();
+    ordered.sort_unstable();
+    for &variable in &ordered {
+        let stored_in = stores(ir, variable).filter(|block| tree.is_reached(*block));
+        let mut blocks: Vec<_> = iterated_frontier(&frontiers, stored_in)
+            .into_iter()
+            .collect();
+        blocks.sort_unstable();
+        for block in blocks {
+            phis.entry(block)
+                .or_default()
+                .push((variable, Binding(next_binding)));
+            next_binding += 1;
+        }
+    }
+
+    // what a variable holds before it's stored to, defined at the start of the function
+    let undefined: HashMap<Binding, Binding> = ordered
+        .iter()
+        .enumerate()
+        .map(|(index, variable)| (*variable, Binding(next_binding + index)))
+        .collect();
+
+    let mut renamer = Renamer {
+        variables: &variables,
+        phis: &phis,
+        undefined: &undefined,
+        values: ordered.iter().map(|variable| (*variable, vec![])).collect(),
+        renames: RenameMap::new(),
+        nodes: HashMap::new(),
+    };
+    renamer.rename(ir, &tree);
+    let Renamer {
+        renames, mut nodes, ..
+    } = renamer;
+
+    for (block, block_phis) in phis {
+        let statements = &mut ir[block].statements;
+        statements.splice(
+            0..0,
+            block_phis.into_iter().map(|(_, phi)| Statement::Assign {
+                index: phi,
+                value: Value::Phi {
+                    nodes: nodes.remove(&phi).unwrap_or_default(),
+                },
+            }),
+        );
+    }
+    ir.code[0].statements.splice(
+        0..0,
+        ordered.iter().map(|variable| Statement::Assign {
+            index: undefined[variable],
+            value: match variables[variable] {
+                ByteSize::F32 => Value::FloatConstant {
+                    bits: 0,
+                    bit_size: BitSize::Bit32,
+                },
+                ByteSize::F64 => Value::FloatConstant {
+                    bits: 0,
+                    bit_size: BitSize::Bit64,
+                },
+                _ => Value::Undefined,
+            },
+        }),
+    );
+    ir.code.rename_all(&renames);
+    ordered
+}
+
+/// What loading a value stored with a size smaller than a register gives: only its lowest bytes,
+/// extended the way the load does. The other sizes load back what was stored.
+fn loaded(binding: Binding, byte_size: ByteSize) -> Option<Value> {
+    let mask = |rhs| Value::And {
+        lhs: binding,
+        rhs: CouldBeConstant::Constant(rhs),
+        bit_size: BitSize::Bit32,
+    };
+    match byte_size {
+        ByteSize::U8 => Some(mask(0xFF)),
+        ByteSize::U16 => Some(mask(0xFFFF)),
+        ByteSize::I8 | ByteSize::I16 => Some(Value::SignExtend {
+            binding,
+            from: byte_size,
+        }),
+        ByteSize::U32 | ByteSize::U64 | ByteSize::F32 | ByteSize::F64 => None,
+    }
+}
+
+/// The allocations that can be promoted, with the size they're accessed with: the ones only used
+/// by loads and stores of all of them at once that aren't volatile, always with the same size
+fn promotable(ir: &IR) -> HashMap<Binding, ByteSize> {
+    let mut sizes: HashMap<Binding, (usize, Option<ByteSize>)> = ir
+        .code
+        .iter()
+        .flat_map(|block| &block.statements)
+        .filter_map(|statement| match statement {
+            Statement::Assign {
+                index,
+                value: Value::Allocate { size, .. },
+            } => Some((*index, (*size, None))),
+            _ => None,
+        })
+        .collect();
+    let mut escaped = HashSet::new();
+    let mut access = |mem_binding: Binding, byte_size: ByteSize, escaped: &mut HashSet<_>| {
+        if let Some((size, accessed)) = sizes.get_mut(&mem_binding) {
+            if byte_size.bytes() != *size || accessed.is_some_and(|a| a != byte_size) {
+                escaped.insert(mem_binding);
+            }
+            *accessed = Some(byte_size);
+        }
+    };
+    for block in &ir.code {
+        for statement in &block.statements {
+            match statement {
+                Statement::Assign {
+                    value:
+                        Value::Load {
+                            mem_binding,
+                            byte_size,
+                            volatile: false,
+                        },
+                    ..
+                } => access(*mem_binding, *byte_size, &mut escaped),
+                Statement::Store {
+                    mem_binding,
+                    binding,
+                    byte_size,
+                    volatile: false,
+                } => {
+                    access(*mem_binding, *byte_size, &mut escaped);
+                    // storing the address somewhere lets it be used from there
+                    escaped.insert(*binding);
+                }
+                statement => escaped.extend(statement.binding_deps()),
+            }
+        }
+        escaped.extend(match block.end {
+            BlockEnd::Return(binding)
+            | BlockEnd::Branch(Branch::Conditional { flag: binding, .. }) => Some(binding),
+            BlockEnd::Branch(Branch::Unconditional { .. }) => None,
+        });
+    }
+    sizes
+        .into_iter()
+        .filter(|(allocation, _)| !escaped.contains(allocation))
+        // the memory that's never accessed goes away all the same
+        .map(|(allocation, (_, accessed))| (allocation, accessed.unwrap_or(ByteSize::U64)))
+        .collect()
+}
+
+/// The blocks that store to the variable
+fn stores(ir: &IR, variable: Binding) -> impl Iterator<Item = BlockBinding> + '_ {
+    analysis::iterate_with_bindings(&ir.code)
+        .filter(move |(_, block)| {
+            block.statements.iter().any(|statement| {
+                matches!(statement, Statement::Store { mem_binding, .. } if *mem_binding == variable)
+            })
+        })
+        .map(|(block, _)| block)
+}
+
+struct Renamer<'p> {
+    variables: &'p HashMap<Binding, ByteSize>,
+    phis: &'p HashMap<BlockBinding, Vec<(Binding, Binding)>>,
+    undefined: &'p HashMap<Binding, Binding>,
+    /// the values each variable was given on the way from the entry to the current block, the
+    /// last one being the one it holds
+    values: HashMap<Binding, Vec<Binding>>,
+    /// the loads, to be renamed as the values they load
+    renames: RenameMap,
+    /// the values of the new phi nodes, as they're found
+    nodes: HashMap<Binding, Vec<PhiDescriptor>>,
+}
+
+impl Renamer<'_> {
+    fn value(&self, variable: Binding) -> Binding {
+        self.values[&variable]
+            .last()
+            .copied()
+            .unwrap_or(self.undefined[&variable])
+    }
+
+    /// Walk the dominator tree, so that the value of a variable when entering a block is the
+    /// last one it was given in the blocks that dominate it. The blocks the entry doesn't reach
+    /// only see undefined values.
+    fn rename(&mut self, ir: &mut IR, tree: &DominatorTree) {
+        // the blocks to go into, and the variables to take a value out of when leaving them
+        enum Step {
+            Enter(BlockBinding),
+            Leave(Vec<Binding>),
+        }
+        let mut steps: Vec<_> = tree
+            .reverse_postorder()
+            .first()
+            .map(|entry| Step::Enter(*entry))
+            .into_iter()
+            .collect();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(block) => {
+                    let assigned = self.rename_block(ir, block);
+                    steps.push(Step::Leave(assigned));
+                    steps.extend(tree.children(block).iter().map(|child| Step::Enter(*child)));
+                }
+                Step::Leave(assigned) => {
+                    for variable in assigned {
+                        self.values.get_mut(&variable).unwrap().pop();
+                    }
+                }
+            }
+        }
+        for block in 0..ir.code.len() {
+            if !tree.is_reached(BlockBinding(block)) {
+                self.values.values_mut().for_each(Vec::clear);
+                self.rename_block(ir, BlockBinding(block));
+            }
+        }
+    }
+
+    /// Take the loads and stores of the variables out of the block, giving the variables it
+    /// assigns a value to, once for each value
+    fn rename_block(&mut self, ir: &mut IR, block: BlockBinding) -> Vec<Binding> {
+        let mut assigned = Vec::new();
+        for &(variable, phi) in self.phis.get(&block).into_iter().flatten() {
+            self.values.get_mut(&variable).unwrap().push(phi);
+            assigned.push(variable);
+        }
+        let statements = core::mem::take(&mut ir[block].statements);
+        ir[block].statements = statements
+            .into_iter()
+            .filter_map(|statement| match statement {
+                Statement::Assign {
+                    index,
+                    value: Value::Allocate { .. },
+                } if self.variables.contains_key(&index) => None,
+                Statement::Assign {
+                    index,
+                    value: Value::Load { mem_binding, .. },
+                } if self.variables.contains_key(&mem_binding) => {
+                    let value = self.value(mem_binding);
+                    match loaded(value, self.variables[&mem_binding]) {
+                        Some(value) => Some(Statement::Assign { index, value }),
+                        None => {
+                            self.renames.insert(index, value);
+                            None
+                        }
+                    }
+                }
+                Statement::Store {
+                    mem_binding,
+                    binding,
+                    ..
+                } if self.variables.contains_key(&mem_binding) => {
+                    // the loads the value comes from dominate the store, so they were renamed
+                    let value = self.renames.get(&binding).copied().unwrap_or(binding);
+                    self.values.get_mut(&mem_binding).unwrap().push(value);
+                    assigned.push(mem_binding);
+                    None
+                }
+                statement => Some(statement),
+            })
+            .collect();
+
+        let mut successors = ir.forward_map.get(&block).cloned().unwrap_or_default();
+        successors.sort_unstable();
+        successors.dedup();
+        for successor in successors {
+            for &(variable, phi) in self.phis.get(&successor).into_iter().flatten() {
+                let value = self.value(variable);
+                self.nodes.entry(phi).or_default().push(PhiDescriptor {
+                    value,
+                    block_from: block,
+                });
+            }
+        }
+        assigned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::{interpret, verify};
+
+    fn compile(source: &str) -> IR {
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta)
+            .parse()
+            .expect("the source is fine");
+        let (mut module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())
+                .expect("the source is fine");
+        module.functions.remove(0).ir
+    }
+
+    fn accesses_memory(ir: &IR) -> bool {
+        ir.code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .any(|statement| {
+                matches!(
+                    statement,
+                    Statement::Store { .. }
+                        | Statement::Assign {
+                            value: Value::Load { .. } | Value::Allocate { .. },
+                            ..
+                        }
+                )
+            })
+    }
+
+    fn returns(ir: &IR) -> Vec<i64> {
+        interpret::sample_inputs()
+            .map(|inputs| interpret::run(ir, &[], &inputs).map(|outcome| outcome.returned))
+            .map(|returned| returned.expect("the function returns"))
+            .collect()
+    }
+
+    #[test]
+    fn variables_become_phi_nodes_where_paths_meet() {
+        let mut ir = compile(
+            "int f(int a, int c) {\n\
+             \x20   int x = a;\n\
+             \x20   if (c) x = 2; else if (a) x = x + 1;\n\
+             \x20   return x + a;\n\
+             }\n",
+        );
+        let before = returns(&ir);
+        let promoted = promote_variables(&mut ir);
+        assert_eq!(promoted.len(), 3);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert!(!accesses_memory(&ir));
+        assert_eq!(returns(&ir), before);
+        let phis = ir
+            .code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter(|statement| {
+                matches!(
+                    statement,
+                    Statement::Assign {
+                        value: Value::Phi { .. },
+                        ..
+                    }
+                )
+            });
+        assert!(phis.count() >= 2);
+    }
+
+    #[test]
+    fn variables_whose_address_is_taken_stay_in_memory() {
+        let mut ir = compile(
+            "int g(int *p);\n\
+             int f(int a) {\n\
+             \x20   int kept = a;\n\
+             \x20   char small = 1;\n\
+             \x20   long promoted;\n\
+             \x20   g(&kept);\n\
+             \x20   promoted = kept;\n\
+             \x20   return promoted + small;\n\
+             }\n",
+        );
+        let before = returns(&ir);
+        let promoted = promote_variables(&mut ir);
+        // all but `kept`, whose address is taken
+        assert_eq!(promoted.len(), 3);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert_eq!(returns(&ir), before);
+        let allocations = ir
+            .code
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter(|statement| {
+                matches!(
+                    statement,
+                    Statement::Assign {
+                        value: Value::Allocate { .. },
+                        ..
+                    }
+                )
+            });
+        assert_eq!(allocations.count(), 1);
+    }
+
+    #[test]
+    fn narrow_variables_keep_only_their_lowest_bytes() {
+        let mut ir = compile(
+            "int f(int a) {\n\
+             \x20   char c = a + 300;\n\
+             \x20   signed char s = 200;\n\
+             \x20   short h = 70000;\n\
+             \x20   _Bool b = a;\n\
+             \x20   if (a) c = c - 1;\n\
+             \x20   return c + s + h + b;\n\
+             }\n",
+        );
+        let before = returns(&ir);
+        let promoted = promote_variables(&mut ir);
+        assert_eq!(promoted.len(), 5);
+        assert_eq!(verify::verify(&ir), Ok(()));
+        assert!(!accesses_memory(&ir));
+        assert_eq!(returns(&ir), before);
+    }
+}
@@ -1,26 +1,52 @@
-use std::collections::HashMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
 
 pub mod analysis;
+pub mod block_args;
 pub mod cleanup;
 mod convert;
 pub mod fold;
 mod format;
 pub mod generate;
+pub mod inline;
+pub mod interpret;
+pub mod mem2reg;
+pub mod parse;
+pub mod passes;
 pub mod refactor;
+pub mod remarks;
+pub mod verify;
 
-use crate::codegen::assembly::Condition;
+use crate::codegen::assembly::{BitSize, Condition};
+pub use format::Named;
 // IR: everything is divided into basic blocks
 
 pub type BranchingMap = HashMap<BlockBinding, Vec<BlockBinding>>;
 
 pub type IRCode = Vec<BasicBlock>;
 
+/// What a whole translation unit was compiled from and for, so that the backends can record it
+/// in their output
+#[derive(Debug, Clone, Default)]
+pub struct ModuleMetadata {
+    /// the file the source was read from
+    pub source_file: Option<String>,
+    /// the architecture the code is generated for
+    pub target: String,
+    /// the options that change the generated code, as given in the command line
+    pub options: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct IR {
     pub code: IRCode,
     pub backwards_map: BranchingMap,
     pub forward_map: BranchingMap,
 }
 
+#[derive(Clone, PartialEq)]
 pub struct BasicBlock {
     pub statements: Vec<Statement>,
     pub end: BlockEnd,
@@ -49,7 +75,7 @@ pub enum Branch {
 }
 
 // assign, store, load, alloc, free
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     Assign {
         index: Binding,
@@ -59,17 +85,45 @@ pub enum Statement {
         mem_binding: Binding,
         binding: Binding,
         byte_size: ByteSize,
+        // a volatile store is never removed, merged with others or moved past another volatile
+        // access
+        volatile: bool,
+    },
+    // Store to memory that other threads may be reading, like `Value::AtomicLoad` loads
+    AtomicStore {
+        mem_binding: Binding,
+        binding: Binding,
+        byte_size: ByteSize,
+    },
+    // Write the whole register that holds a global register variable
+    SetRegister {
+        register: u8,
+        binding: Binding,
     },
 }
 
+impl Statement {
+    /// The binding the statement is about: the one it defines, or the one it writes somewhere
+    pub const fn binding(&self) -> Binding {
+        match self {
+            Self::Assign { index, .. } => *index,
+            Self::Store { binding, .. }
+            | Self::AtomicStore { binding, .. }
+            | Self::SetRegister { binding, .. } => *binding,
+        }
+    }
+}
+
 // TODO: merge binary ops from `Value` into the same value kind, same for unops
 
-// phi, cmp, add, sub, neg.... all operations
-#[derive(Debug, PartialEq)]
+// phi, cmp, add, sub, neg.... all operations. The ones that compute on integers do it on 32 or 64
+// bits, as told by their `bit_size`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
-    // allocate memory
+    // allocate memory, its address a multiple of `align`
     Allocate {
         size: usize,
+        align: usize,
     },
     Phi {
         nodes: Vec<PhiDescriptor>,
@@ -78,67 +132,259 @@ pub enum Value {
         condition: Condition,
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
+    },
+    // `if_true` when the flag isn't zero and `if_false` when it is, without branching. Only
+    // integers are selected, there's no select of floating-point registers.
+    Select {
+        flag: Binding,
+        if_true: CouldBeConstant,
+        if_false: CouldBeConstant,
     },
     // load from memory 1-4-8 bytes
     Load {
         mem_binding: Binding,
         byte_size: ByteSize,
+        // a volatile load is never removed, merged with others or moved past another volatile
+        // access, even if its value isn't used
+        volatile: bool,
+    },
+    // Load from memory other threads may be writing to, all of it at once and sequentially
+    // consistent with the other atomic accesses. Only 4 and 8 bytes can be accessed like this, and
+    // `mem_binding` is always a pointer, never an allocation.
+    AtomicLoad {
+        mem_binding: Binding,
+        byte_size: ByteSize,
+    },
+    // Add `binding` to the memory like the atomic accesses, the value being what it held before
+    AtomicFetchAdd {
+        mem_binding: Binding,
+        binding: Binding,
+        byte_size: ByteSize,
     },
     // -x
     Negate {
         binding: Binding,
+        bit_size: BitSize,
     },
     // ~x
     FlipBits {
         binding: Binding,
+        bit_size: BitSize,
     },
     Add {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     Subtract {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     Multiply {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     // NOTE: `rhs` should be a binding since `udiv` only accepts registers.
     Divide {
         lhs: Binding,
         rhs: CouldBeConstant,
         is_signed: bool,
+        bit_size: BitSize,
     },
     // logic shift left
     Lsl {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     // Logic shift right
     Lsr {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
+    },
+    // Arithmetic shift right, which keeps the sign
+    Asr {
+        lhs: Binding,
+        rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     // bitwise AND
     And {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     // bitwise OR
     Or {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
     },
     // bitwise XOR
     Xor {
         lhs: Binding,
         rhs: CouldBeConstant,
+        bit_size: BitSize,
+    },
+    // Widen the lowest `from` of a value to 64 bits, keeping its sign
+    SignExtend {
+        binding: Binding,
+        from: ByteSize,
+    },
+    // Floating-point constant, the bits of an `f32` or an `f64` as told by `bit_size`
+    FloatConstant {
+        bits: u64,
+        bit_size: BitSize,
+    },
+    // Arithmetic on single (32-bit) or double (64-bit) precision values
+    FloatArithmetic {
+        op: FloatOp,
+        lhs: Binding,
+        rhs: Binding,
+        bit_size: BitSize,
+    },
+    FloatNegate {
+        binding: Binding,
+        bit_size: BitSize,
+    },
+    // Ordered comparison: it doesn't hold if either side is NaN, unless it's `NotEquals`
+    FloatCmp {
+        condition: Condition,
+        lhs: Binding,
+        rhs: Binding,
+        bit_size: BitSize,
     },
-    // Constant value
-    Constant(i32),
+    // Convert a signed integer to the nearest floating-point value
+    IntToFloat {
+        binding: Binding,
+        from: BitSize,
+        to: BitSize,
+    },
+    // Convert a floating-point value to a signed integer, rounding towards zero
+    FloatToInt {
+        binding: Binding,
+        from: BitSize,
+        to: BitSize,
+    },
+    // Change the precision of a floating-point value
+    FloatConvert {
+        binding: Binding,
+        from: BitSize,
+        to: BitSize,
+    },
+    // Constant value. One that fits in 32 bits is the same for both sizes of operations, since
+    // it's kept sign extended
+    Constant(i64),
+    // An arbitrary value, from reading memory that was never written. It is frozen: every use of
+    // the binding it's assigned to sees the same value, so folding picks zero for it and codegen
+    // materializes it as zero too.
+    Undefined,
     // Other binding. Used by frontend, then cleaned up by next stage
     Binding(Binding),
+    // Call a function with the given arguments, in order. The value is what the function returns
+    Call {
+        function: String,
+        args: Vec<Binding>,
+    },
+    // Call the function `pointer` points to, like `Call` does
+    CallPointer {
+        pointer: Binding,
+        args: Vec<Binding>,
+    },
+    // Call a function that returns a struct of `size` bytes, which it leaves in the memory
+    // `result`. Up to 16 bytes come back in `x0` and `x1`, and larger ones are written to the
+    // address passed in `x8`.
+    CallStruct {
+        callee: Callee,
+        args: Vec<Binding>,
+        result: Binding,
+        size: usize,
+    },
+    // Call a function that returns a floating-point value of `bit_size` bits, which comes back in
+    // `v0` instead of `x0`
+    CallFloat {
+        callee: Callee,
+        args: Vec<Binding>,
+        bit_size: BitSize,
+    },
+    // The struct of `size` bytes in `memory`, to be returned in `x0` and `x1`
+    ReturnedStruct {
+        memory: Binding,
+        size: usize,
+    },
+    // Where the caller wants the struct of `size` bytes the current function returns, if it's too
+    // large for registers
+    ResultAddress {
+        size: usize,
+    },
+    // The n-th integer argument the current function was called with
+    Argument(usize),
+    // The n-th floating-point argument, since those are passed in registers of their own
+    FloatArgument(usize),
+    // The current value of the register that holds a global register variable
+    GlobalRegister(u8),
+    // Address of a memory allocation, for when it's used as a pointer
+    Address {
+        mem_binding: Binding,
+    },
+    // Address of the n-th piece of read-only data of the function
+    ReadOnlyAddress(usize),
+    // Address of a function, for when it's used as a pointer
+    FunctionAddress(String),
+    // Address of a variable defined at file scope, which is where its memory is
+    GlobalAddress(String),
+    // Address of the `index`-th element of the memory `base` points to: base + index * size. The
+    // index has `bit_size` bits, and is signed.
+    ElementAddress {
+        base: Binding,
+        index: Binding,
+        element_size: usize,
+        bit_size: BitSize,
+    },
+}
+
+impl Value {
+    /// Whether computing the value does something besides giving the value, so it can't be
+    /// removed even if nobody uses it
+    pub const fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            Self::Call { .. }
+                | Self::CallPointer { .. }
+                | Self::CallStruct { .. }
+                | Self::CallFloat { .. }
+                | Self::Load { volatile: true, .. }
+                | Self::AtomicLoad { .. }
+                | Self::AtomicFetchAdd { .. }
+        )
+    }
+
+    /// The function the value calls by its name, if it's such a call
+    pub fn called_function(&self) -> Option<&str> {
+        match self {
+            Self::Call { function, .. }
+            | Self::CallStruct {
+                callee: Callee::Function(function),
+                ..
+            }
+            | Self::CallFloat {
+                callee: Callee::Function(function),
+                ..
+            } => Some(function),
+            _ => None,
+        }
+    }
+}
+
+/// What a call calls: a function by its name, or the one a pointer points to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    Function(String),
+    Pointer(Binding),
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -147,14 +393,44 @@ pub struct Binding(pub usize);
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CouldBeConstant {
     Binding(Binding),
-    Constant(i32),
+    Constant(i64),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ByteSize {
     U8,
+    /// a `signed char`, which is sign extended when it's loaded
+    I8,
+    /// a `short`, which is sign extended when it's loaded
+    I16,
+    /// an `unsigned short`
+    U16,
     U32,
     U64,
+    /// a `float`, which is loaded into a floating-point register
+    F32,
+    /// a `double`, which is loaded into a floating-point register
+    F64,
+}
+
+impl ByteSize {
+    /// How many bytes of memory are accessed
+    pub const fn bytes(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::U32 | Self::F32 => 4,
+            Self::U64 | Self::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -186,7 +462,7 @@ impl BlockEnd {
 impl Branch {
     pub fn branch_list(&self) -> impl Iterator<Item = BlockBinding> + '_ {
         let mut iteration = 0usize;
-        std::iter::from_fn(move || {
+        core::iter::from_fn(move || {
             let last_iteration = iteration;
             iteration += 1;
             match self {
@@ -0,0 +1,791 @@
+//! Reading back the IR as it's written (see `--emit=ir`), so that a pass can be tested on a few
+//! blocks written by hand instead of on whatever a C function compiles to. Lines are one label or
+//! one statement each, and the ones that are empty or start with `;` are left out.
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use crate::codegen::assembly::{BitSize, Condition};
+
+use super::{
+    BasicBlock, Binding, BlockBinding, BlockEnd, Branch, ByteSize, Callee, CouldBeConstant,
+    FloatOp, PhiDescriptor, Statement, Value, IR,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("line {line}: expected {expected}, found `{found}`")]
+    Unexpected {
+        line: usize,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("line {line}: unknown instruction `{name}`")]
+    UnknownInstruction { line: usize, name: String },
+    #[error("line {line}: the block labeled {label} is in the place of {expected}")]
+    MisplacedBlock {
+        line: usize,
+        label: BlockBinding,
+        expected: BlockBinding,
+    },
+    #[error("line {line}: the statement isn't in a block, since the one before has ended")]
+    OutsideBlock { line: usize },
+    #[error("{block} doesn't end with a branch or a return")]
+    Unterminated { block: BlockBinding },
+    #[error("there are no blocks")]
+    Empty,
+}
+
+impl FromStr for IR {
+    type Err = ParseError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_ir(text)
+    }
+}
+
+/// The IR of a function written as text, with its branching graphs generated from the branches
+pub fn parse_ir(text: &str) -> Result<IR, ParseError> {
+    let mut code: Vec<BasicBlock> = Vec::new();
+    // the statements of the last block, until its end is found
+    let mut open: Option<Vec<Statement>> = None;
+    for (index, text) in text.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with(';') {
+            continue;
+        }
+        let mut line = Line::new(index + 1, text);
+        if let Some(label) = text.strip_suffix(':') {
+            let label = block_binding(&line, label)?;
+            let expected = BlockBinding(code.len());
+            if open.is_some() {
+                return Err(ParseError::Unterminated { block: expected });
+            }
+            if label != expected {
+                return Err(ParseError::MisplacedBlock {
+                    line: line.number,
+                    label,
+                    expected,
+                });
+            }
+            open = Some(Vec::new());
+            continue;
+        }
+        let statements = open
+            .as_mut()
+            .ok_or(ParseError::OutsideBlock { line: line.number })?;
+        match line.block_end()? {
+            Some(end) => code.push(BasicBlock {
+                statements: open.take().unwrap_or_default(),
+                end,
+            }),
+            None => statements.push(line.statement()?),
+        }
+        line.finish()?;
+    }
+    if open.is_some() {
+        return Err(ParseError::Unterminated {
+            block: BlockBinding(code.len()),
+        });
+    }
+    if code.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(code.into())
+}
+
+/// What's left to read of a line
+struct Line<'text> {
+    number: usize,
+    rest: &'text str,
+}
+
+/// The characters that are tokens by themselves, besides separating the others
+const PUNCTUATION: &[char] = &[',', '(', ')', '[', ']', '=', '*'];
+
+impl<'text> Line<'text> {
+    const fn new(number: usize, rest: &'text str) -> Self {
+        Self { number, rest }
+    }
+
+    /// The next token, if the line has any left
+    fn next_token(&mut self) -> Option<&'text str> {
+        self.rest = self.rest.trim_start();
+        let first = self.rest.chars().next()?;
+        let len = if PUNCTUATION.contains(&first) {
+            first.len_utf8()
+        } else {
+            self.rest
+                .find(|c: char| c.is_whitespace() || PUNCTUATION.contains(&c))
+                .unwrap_or(self.rest.len())
+        };
+        let (token, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(token)
+    }
+    fn peek_token(&self) -> Option<&'text str> {
+        Self::new(self.number, self.rest).next_token()
+    }
+    fn token(&mut self, expected: &'static str) -> Result<&'text str, ParseError> {
+        self.next_token().ok_or(ParseError::Unexpected {
+            line: self.number,
+            expected,
+            found: String::new(),
+        })
+    }
+    fn unexpected<T>(&self, expected: &'static str, found: &str) -> Result<T, ParseError> {
+        Err(ParseError::Unexpected {
+            line: self.number,
+            expected,
+            found: found.to_string(),
+        })
+    }
+    fn expect(&mut self, punctuation: &'static str) -> Result<(), ParseError> {
+        match self.token(punctuation)? {
+            token if token == punctuation => Ok(()),
+            token => self.unexpected(punctuation, token),
+        }
+    }
+    fn comma(&mut self) -> Result<(), ParseError> {
+        self.expect(",")
+    }
+    /// Nothing is left after what was read
+    fn finish(&mut self) -> Result<(), ParseError> {
+        match self.next_token() {
+            Some(token) => self.unexpected("the end of the line", token),
+            None => Ok(()),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let token = self.token("a number")?;
+        token
+            .parse()
+            .or_else(|_| self.unexpected("a number", token))
+    }
+    /// A binding, as `%index` or named as `%name.index`
+    fn binding(&mut self) -> Result<Binding, ParseError> {
+        let token = self.token("a binding")?;
+        let index = token
+            .strip_prefix('%')
+            .map(|binding| binding.rsplit_once('.').map_or(binding, |(_, index)| index));
+        match index.map(str::parse) {
+            Some(Ok(index)) => Ok(Binding(index)),
+            _ => self.unexpected("a binding", token),
+        }
+    }
+    fn block(&mut self) -> Result<BlockBinding, ParseError> {
+        let token = self.token("a block")?;
+        block_binding(self, token)
+    }
+    fn could_be_constant(&mut self) -> Result<CouldBeConstant, ParseError> {
+        match self.peek_token() {
+            Some(token) if token.starts_with('%') => Ok(self.binding()?.into()),
+            _ => Ok(CouldBeConstant::Constant(self.number()?)),
+        }
+    }
+    fn function_name(&mut self) -> Result<String, ParseError> {
+        self.symbol("a function")
+    }
+    /// The name of a symbol, which is written after an `@`
+    fn symbol(&mut self, expected: &'static str) -> Result<String, ParseError> {
+        let token = self.token(expected)?;
+        match token.strip_prefix('@') {
+            Some(name) if !name.is_empty() => Ok(name.to_string()),
+            _ => self.unexpected(expected, token),
+        }
+    }
+    fn register(&mut self) -> Result<u8, ParseError> {
+        let token = self.token("a register")?;
+        match token.strip_prefix('x').map(str::parse) {
+            Some(Ok(register)) => Ok(register),
+            _ => self.unexpected("a register", token),
+        }
+    }
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        Ok(match self.token("a condition")? {
+            "eq" => Condition::Equals,
+            "ne" => Condition::NotEquals,
+            "gt" => Condition::GreaterThan,
+            "ge" => Condition::GreaterEqual,
+            "lt" => Condition::LessThan,
+            "le" => Condition::LessEqual,
+            "hi" => Condition::Higher,
+            "hs" => Condition::HigherSame,
+            "lo" => Condition::Lower,
+            "ls" => Condition::LowerSame,
+            token => return self.unexpected("a condition", token),
+        })
+    }
+    fn byte_size(&mut self) -> Result<ByteSize, ParseError> {
+        Ok(match self.token("a memory size")? {
+            "u8" => ByteSize::U8,
+            "i8" => ByteSize::I8,
+            "i16" => ByteSize::I16,
+            "u16" => ByteSize::U16,
+            "u32" => ByteSize::U32,
+            "u64" => ByteSize::U64,
+            "f32" => ByteSize::F32,
+            "f64" => ByteSize::F64,
+            token => return self.unexpected("a memory size", token),
+        })
+    }
+    /// The size of an integer type, `i32` or `i64`
+    fn int_type(&mut self) -> Result<BitSize, ParseError> {
+        match self.token("an integer type")? {
+            "i32" => Ok(BitSize::Bit32),
+            "i64" => Ok(BitSize::Bit64),
+            token => self.unexpected("an integer type", token),
+        }
+    }
+    /// The precision of a floating-point type, `f32` or `f64`
+    fn float_type(&mut self) -> Result<BitSize, ParseError> {
+        let token = self.token("a floating-point type")?;
+        float_type(token).map_or_else(|| self.unexpected("a floating-point type", token), Ok)
+    }
+    /// The bindings between parentheses, separated by commas
+    fn binding_list(&mut self) -> Result<Vec<Binding>, ParseError> {
+        self.expect("(")?;
+        let mut bindings = Vec::new();
+        if self.peek_token() == Some(")") {
+            self.next_token();
+            return Ok(bindings);
+        }
+        loop {
+            bindings.push(self.binding()?);
+            match self.token("`,` or `)`")? {
+                "," => continue,
+                ")" => return Ok(bindings),
+                token => return self.unexpected("`,` or `)`", token),
+            }
+        }
+    }
+
+    /// The end of a block, if the line is one
+    fn block_end(&mut self) -> Result<Option<BlockEnd>, ParseError> {
+        let end = match self.peek_token() {
+            Some("ret") => {
+                self.next_token();
+                BlockEnd::Return(self.binding()?)
+            }
+            Some("br") => {
+                self.next_token();
+                Branch::Unconditional {
+                    target: self.block()?,
+                }
+                .into()
+            }
+            Some("br-cond") => {
+                self.next_token();
+                let flag = self.binding()?;
+                self.comma()?;
+                let target_true = self.block()?;
+                self.comma()?;
+                Branch::Conditional {
+                    flag,
+                    target_true,
+                    target_false: self.block()?,
+                }
+                .into()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(end))
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.token("a statement")?;
+        if token.starts_with('%') {
+            let index = Line::new(self.number, token).binding()?;
+            self.expect("=")?;
+            return Ok(Statement::Assign {
+                index,
+                value: self.value()?,
+            });
+        }
+        let (name, suffix) = split_suffix(token);
+        match (name, suffix) {
+            ("store", None | Some("volatile")) => {
+                let mem_binding = self.binding()?;
+                self.comma()?;
+                let byte_size = self.byte_size()?;
+                Ok(Statement::Store {
+                    mem_binding,
+                    binding: self.binding()?,
+                    byte_size,
+                    volatile: suffix.is_some(),
+                })
+            }
+            ("atomic_store", None) => {
+                let mem_binding = self.binding()?;
+                self.comma()?;
+                let byte_size = self.byte_size()?;
+                Ok(Statement::AtomicStore {
+                    mem_binding,
+                    binding: self.binding()?,
+                    byte_size,
+                })
+            }
+            ("set_register", None) => {
+                let register = self.register()?;
+                self.comma()?;
+                Ok(Statement::SetRegister {
+                    register,
+                    binding: self.binding()?,
+                })
+            }
+            _ => Err(ParseError::UnknownInstruction {
+                line: self.number,
+                name: token.to_string(),
+            }),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let token = self.token("a value")?;
+        let (name, suffix) = split_suffix(token);
+        // the bit size of an operation on integers, which is only marked when it's 64
+        let int_size = || match suffix {
+            None => Ok(BitSize::Bit32),
+            Some("64") => Ok(BitSize::Bit64),
+            Some(_) => self.unknown(token),
+        };
+        let float_size = || {
+            suffix
+                .and_then(float_type)
+                .map_or_else(|| self.unknown(token), Ok)
+        };
+        let value = match (name, suffix) {
+            ("phi", None) => {
+                let mut nodes = Vec::new();
+                loop {
+                    self.expect("[")?;
+                    let value = self.binding()?;
+                    self.comma()?;
+                    let block_from = self.block()?;
+                    self.expect("]")?;
+                    nodes.push(PhiDescriptor { value, block_from });
+                    if self.peek_token() != Some(",") {
+                        break Value::Phi { nodes };
+                    }
+                    self.next_token();
+                }
+            }
+            ("cmp", _) => {
+                let bit_size = int_size()?;
+                let condition = self.condition()?;
+                self.comma()?;
+                let lhs = self.binding()?;
+                self.comma()?;
+                Value::Cmp {
+                    condition,
+                    lhs,
+                    rhs: self.could_be_constant()?,
+                    bit_size,
+                }
+            }
+            ("select", None) => {
+                let flag = self.binding()?;
+                self.comma()?;
+                let if_true = self.could_be_constant()?;
+                self.comma()?;
+                Value::Select {
+                    flag,
+                    if_true,
+                    if_false: self.could_be_constant()?,
+                }
+            }
+            ("load", None | Some("volatile")) => {
+                let mem_binding = self.binding()?;
+                self.comma()?;
+                Value::Load {
+                    mem_binding,
+                    byte_size: self.byte_size()?,
+                    volatile: suffix.is_some(),
+                }
+            }
+            ("atomic_load", None) => {
+                let mem_binding = self.binding()?;
+                self.comma()?;
+                Value::AtomicLoad {
+                    mem_binding,
+                    byte_size: self.byte_size()?,
+                }
+            }
+            ("atomic_fetch_add", None) => {
+                let mem_binding = self.binding()?;
+                self.comma()?;
+                let byte_size = self.byte_size()?;
+                Value::AtomicFetchAdd {
+                    mem_binding,
+                    binding: self.binding()?,
+                    byte_size,
+                }
+            }
+            ("neg", _) => Value::Negate {
+                bit_size: int_size()?,
+                binding: self.binding()?,
+            },
+            ("flip_bits", _) => Value::FlipBits {
+                bit_size: int_size()?,
+                binding: self.binding()?,
+            },
+            (
+                "add" | "sub" | "mul" | "idiv" | "udiv" | "lsl" | "lsr" | "asr" | "and" | "or"
+                | "xor",
+                _,
+            ) => {
+                let bit_size = int_size()?;
+                let lhs = self.binding()?;
+                self.comma()?;
+                let rhs = self.could_be_constant()?;
+                match name {
+                    "add" => Value::Add { lhs, rhs, bit_size },
+                    "sub" => Value::Subtract { lhs, rhs, bit_size },
+                    "mul" => Value::Multiply { lhs, rhs, bit_size },
+                    "idiv" | "udiv" => Value::Divide {
+                        lhs,
+                        rhs,
+                        is_signed: name == "idiv",
+                        bit_size,
+                    },
+                    "lsl" => Value::Lsl { lhs, rhs, bit_size },
+                    "lsr" => Value::Lsr { lhs, rhs, bit_size },
+                    "asr" => Value::Asr { lhs, rhs, bit_size },
+                    "and" => Value::And { lhs, rhs, bit_size },
+                    "or" => Value::Or { lhs, rhs, bit_size },
+                    _ => Value::Xor { lhs, rhs, bit_size },
+                }
+            }
+            ("element_address", _) => {
+                let bit_size = int_size()?;
+                let base = self.binding()?;
+                self.comma()?;
+                let index = self.binding()?;
+                self.comma()?;
+                Value::ElementAddress {
+                    base,
+                    index,
+                    element_size: self.number()?,
+                    bit_size,
+                }
+            }
+            ("fadd" | "fsub" | "fmul" | "fdiv", _) => {
+                let bit_size = float_size()?;
+                let op = match name {
+                    "fadd" => FloatOp::Add,
+                    "fsub" => FloatOp::Subtract,
+                    "fmul" => FloatOp::Multiply,
+                    _ => FloatOp::Divide,
+                };
+                let lhs = self.binding()?;
+                self.comma()?;
+                Value::FloatArithmetic {
+                    op,
+                    lhs,
+                    rhs: self.binding()?,
+                    bit_size,
+                }
+            }
+            ("fneg", _) => Value::FloatNegate {
+                bit_size: float_size()?,
+                binding: self.binding()?,
+            },
+            ("fcmp", _) => {
+                let bit_size = float_size()?;
+                let condition = self.condition()?;
+                self.comma()?;
+                let lhs = self.binding()?;
+                self.comma()?;
+                Value::FloatCmp {
+                    condition,
+                    lhs,
+                    rhs: self.binding()?,
+                    bit_size,
+                }
+            }
+            ("itof", None) => {
+                let binding = self.binding()?;
+                self.comma()?;
+                let from = self.int_type()?;
+                self.comma()?;
+                Value::IntToFloat {
+                    binding,
+                    from,
+                    to: self.float_type()?,
+                }
+            }
+            ("ftoi", None) => {
+                let binding = self.binding()?;
+                self.comma()?;
+                let from = self.float_type()?;
+                self.comma()?;
+                Value::FloatToInt {
+                    binding,
+                    from,
+                    to: self.int_type()?,
+                }
+            }
+            ("fconv", None) => {
+                let binding = self.binding()?;
+                self.comma()?;
+                let from = self.float_type()?;
+                self.comma()?;
+                Value::FloatConvert {
+                    binding,
+                    from,
+                    to: self.float_type()?,
+                }
+            }
+            ("sext", None) => {
+                let binding = self.binding()?;
+                self.comma()?;
+                Value::SignExtend {
+                    binding,
+                    from: self.byte_size()?,
+                }
+            }
+            ("alloca", None) => {
+                let size = self.number()?;
+                self.comma()?;
+                Value::Allocate {
+                    size,
+                    align: self.number()?,
+                }
+            }
+            ("retstruct", None) => {
+                let memory = self.binding()?;
+                self.comma()?;
+                Value::ReturnedStruct {
+                    memory,
+                    size: self.number()?,
+                }
+            }
+            ("call", None) => self.call(None)?,
+            ("call", _) => self.call(Some(float_size()?))?,
+            ("address", None) => Value::Address {
+                mem_binding: self.binding()?,
+            },
+            ("resultaddr", None) => Value::ResultAddress {
+                size: self.number()?,
+            },
+            ("fnaddr", None) => Value::FunctionAddress(self.function_name()?),
+            ("global", None) => Value::GlobalAddress(self.symbol("a global variable")?),
+            ("arg", None) => Value::Argument(self.number()?),
+            ("farg", None) => Value::FloatArgument(self.number()?),
+            ("register", None) => Value::GlobalRegister(self.register()?),
+            ("rodata", None) => Value::ReadOnlyAddress(self.number()?),
+            ("undef", None) => Value::Undefined,
+            _ if token.starts_with('%') => Value::Binding(Line::new(self.number, token).binding()?),
+            _ => constant(token).map_or_else(|| self.unknown(token), Ok)?,
+        };
+        Ok(value)
+    }
+    fn unknown<T>(&self, token: &str) -> Result<T, ParseError> {
+        Err(ParseError::UnknownInstruction {
+            line: self.number,
+            name: token.to_string(),
+        })
+    }
+
+    /// A call, either to a function by name (`@name`) or through a pointer (`*%binding`), and of
+    /// one that returns a struct, the memory it's left in and its size. A call that returns a
+    /// floating-point value of `float_size` bits has nothing after the arguments.
+    fn call(&mut self, float_size: Option<BitSize>) -> Result<Value, ParseError> {
+        let callee = if self.peek_token() == Some("*") {
+            self.next_token();
+            Callee::Pointer(self.binding()?)
+        } else {
+            Callee::Function(self.function_name()?)
+        };
+        let args = self.binding_list()?;
+        if let Some(bit_size) = float_size {
+            return Ok(Value::CallFloat {
+                callee,
+                args,
+                bit_size,
+            });
+        }
+        if self.peek_token() != Some("into") {
+            return Ok(match callee {
+                Callee::Function(function) => Value::Call { function, args },
+                Callee::Pointer(pointer) => Value::CallPointer { pointer, args },
+            });
+        }
+        self.next_token();
+        let result = self.binding()?;
+        self.comma()?;
+        Ok(Value::CallStruct {
+            callee,
+            args,
+            result,
+            size: self.number()?,
+        })
+    }
+}
+
+fn block_binding(line: &Line, token: &str) -> Result<BlockBinding, ParseError> {
+    match token.strip_prefix("BB").map(str::parse) {
+        Some(Ok(index)) => Ok(BlockBinding(index)),
+        _ => line.unexpected("a block", token),
+    }
+}
+
+/// The name of an instruction, and what's after its dot, like the `64` of `add.64`. Numbers, like
+/// the `1.5` of a floating-point constant, aren't split.
+fn split_suffix(token: &str) -> (&str, Option<&str>) {
+    match token.split_once('.') {
+        Some((name, suffix)) if name.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+            (name, Some(suffix))
+        }
+        _ => (token, None),
+    }
+}
+
+fn float_type(token: &str) -> Option<BitSize> {
+    match token {
+        "f32" => Some(BitSize::Bit32),
+        "f64" => Some(BitSize::Bit64),
+        _ => None,
+    }
+}
+
+/// An integer constant, or a floating-point one: a `double`, or a `float` when it ends in `f`
+fn constant(token: &str) -> Option<Value> {
+    if let Ok(constant) = token.parse() {
+        return Some(Value::Constant(constant));
+    }
+    if let Some(Ok(float)) = token.strip_suffix('f').map(f32::from_str) {
+        return Some(Value::FloatConstant {
+            bits: float.to_bits().into(),
+            bit_size: BitSize::Bit32,
+        });
+    }
+    let double = f64::from_str(token).ok()?;
+    Some(Value::FloatConstant {
+        bits: double.to_bits(),
+        bit_size: BitSize::Bit64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::cleanup;
+
+    #[test]
+    fn written_ir_reads_back_the_same() -> anyhow::Result<()> {
+        let source = "struct big { long a; long b; long c; };\n\
+                      struct big make(long x) { struct big b = {x, x << 2, x >> 1}; return b; }\n\
+                      int apply(int (*f)(int), int x) { return f(x) + 1; }\n\
+                      int calls;\n\
+                      int twice(int x) { calls = calls + 1; return x * 2; }\n\
+                      double mean(double a, float b) { return (a + b) / 2.0; }\n\
+                      int main(void) {\n\
+                      \x20   volatile int ready = 0;\n\
+                      \x20   unsigned u = 40;\n\
+                      \x20   char *name = \"tracc\";\n\
+                      \x20   int digits[3] = {1, 2, 3};\n\
+                      \x20   int i = 0, sum = 0;\n\
+                      \x20   if (i < 5 && name[i]) {\n\
+                      \x20       sum = sum + (name[i] ^ ~digits[i]);\n\
+                      \x20       i = i + 1;\n\
+                      \x20   }\n\
+                      \x20   __atomic_store_n(&sum, __atomic_fetch_add(&i, 1, 5), 3);\n\
+                      \x20   struct big b = make(__atomic_load_n(&sum, 2));\n\
+                      \x20   return apply(twice, ready) + mean(u / 3, -1.5f) + (int)b.c % 7;\n\
+                      }\n";
+        let meta = crate::error::SourceMetadata::new(source);
+        let program = crate::grammar::Parser::new(&meta).parse()?;
+        let (module, _) =
+            crate::intermediate::generate::compile_program(program, &meta, Default::default())?;
+        for function in &module.functions {
+            let text = function.ir.to_string();
+            let read: IR = text.parse()?;
+            assert_eq!(read.code, function.ir.code, "{}", text);
+            assert_eq!(read.forward_map, function.ir.forward_map);
+            assert_eq!(read.backwards_map, function.ir.backwards_map);
+            // `--emit=ir` names the bindings, which only adds to how they're written
+            let named = function.ir.named(&function.variables).to_string();
+            let read: IR = named.parse()?;
+            assert_eq!(read.code, function.ir.code, "{}", named);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn passes_can_be_run_on_written_ir() -> anyhow::Result<()> {
+        let mut ir: IR = "
+            ; a copy of the argument, and a value nobody uses
+            BB0:
+              %0 = arg 0
+              %1 = %0
+              %2 = mul.64 %0, 3
+              %3 = cmp.64 gt, %1, 10
+              br-cond %3, BB1, BB2
+            BB1:
+              %4 = add.64 %1, 1
+              br  BB2
+            BB2:
+              %5 = phi [ %4, BB1 ], [ %1, BB0 ]
+              ret %5
+        "
+        .parse()?;
+        cleanup::run_safe_cleanup(&mut ir);
+        let expected: IR = "
+            BB0:
+              %0 = arg 0
+              %3 = cmp.64 gt, %0, 10
+              br-cond %3, BB1, BB2
+            BB1:
+              %4 = add.64 %0, 1
+              br  BB2
+            BB2:
+              %5 = phi [ %4, BB1 ], [ %0, BB0 ]
+              ret %5
+        "
+        .parse()?;
+        assert_eq!(ir.code, expected.code, "{}", ir);
+        Ok(())
+    }
+
+    #[test]
+    fn mistakes_are_told_by_line() {
+        let error = |text: &str| parse_ir(text).unwrap_err();
+        assert_eq!(
+            error("BB0:\n  %0 = 1\n  %1 = frob %0\n  ret %1"),
+            ParseError::UnknownInstruction {
+                line: 3,
+                name: "frob".into()
+            }
+        );
+        assert_eq!(
+            error("BB0:\n  %0 = add %0\n  ret %0"),
+            ParseError::Unexpected {
+                line: 2,
+                expected: ",",
+                found: String::new()
+            }
+        );
+        assert_eq!(
+            error("BB0:\n  %0 = 1\n  ret %0\nBB2:\n  ret %0"),
+            ParseError::MisplacedBlock {
+                line: 4,
+                label: BlockBinding(2),
+                expected: BlockBinding(1)
+            }
+        );
+        assert_eq!(
+            error("BB0:\n  %0 = 1\n  ret %0\n  %1 = 2"),
+            ParseError::OutsideBlock { line: 4 }
+        );
+        assert_eq!(
+            error("BB0:\n  %0 = 1\nBB1:\n  ret %0"),
+            ParseError::Unterminated {
+                block: BlockBinding(0)
+            }
+        );
+        assert_eq!(error("; nothing\n"), ParseError::Empty);
+    }
+}
@@ -0,0 +1,470 @@
+//! The pass manager: the optimizations of the IR are passes registered by name, along with the
+//! lowest optimization level that runs them and whether they keep the branching graphs up to date.
+//! The manager runs them in the order they were added, some of them as a group that's repeated
+//! until it changes nothing anymore.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::remarks::Remark;
+use super::{cleanup, fold, generate, mem2reg, verify, BlockEnd, Branch, IR};
+
+/// How much the IR is optimized (`-O<n>`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// only what code generation needs: the cleanup, and folding the constants once
+    O0,
+    /// variables in registers, constant folding and propagation, and the simplification of the
+    /// control flow they enable
+    #[default]
+    O1,
+}
+
+impl core::str::FromStr for OptLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "0" => Self::O0,
+            // there's nothing more to optimize yet, so `-O2` is an alias for `-O1`
+            "1" | "2" => Self::O1,
+            _ => return Err(format!("unknown optimization level `{}`", s)),
+        })
+    }
+}
+
+/// A pass over the IR of a function
+#[derive(Clone, Copy)]
+pub struct Pass {
+    /// the name observers are told about, as in `-fverbose-asm` and `--verify-passes`
+    pub name: &'static str,
+    /// run the pass, telling whether it changed the IR, and adding remarks on what it did
+    pub run: fn(&mut IR, &mut Vec<Remark>) -> bool,
+    /// the lowest level the pass runs at
+    pub level: OptLevel,
+    /// whether the pass leaves the branching graphs right for the code it changed. The manager
+    /// generates them again after the ones that don't.
+    pub preserves_graphs: bool,
+}
+
+impl Pass {
+    /// Remove the copies of bindings, and the bindings nothing uses
+    pub const CLEANUP: Self = Self {
+        name: "cleanup",
+        run: |ir, _| {
+            let before = statement_count(ir);
+            cleanup::run_safe_cleanup(ir);
+            statement_count(ir) != before
+        },
+        level: OptLevel::O0,
+        preserves_graphs: true,
+    };
+    /// Keep the local variables in bindings instead of memory, with phi nodes where the paths
+    /// that assign them meet
+    pub const MEM2REG: Self = Self {
+        name: "memory to register promotion",
+        run: |ir, remarks| {
+            let promoted = mem2reg::promote_variables(ir);
+            remarks.extend(promoted.iter().map(|&variable| {
+                Remark::passed(
+                    "memory to register promotion",
+                    "Promoted",
+                    "the variable is kept in a register instead of the stack".into(),
+                )
+                .at(variable)
+            }));
+            !promoted.is_empty()
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Replace the copies of bindings by what they copy, through phi nodes and across blocks
+    pub const COPY_PROPAGATION: Self = Self {
+        name: "copy propagation",
+        run: |ir, _| cleanup::propagate_copies(&mut ir.code),
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Remove the stores to stack slots that nothing reads before they're overwritten
+    pub const DEAD_STORE_ELIMINATION: Self = Self {
+        name: "dead store elimination",
+        run: |ir, remarks| {
+            let removed = cleanup::dead_stores::remove_dead_stores(ir);
+            if removed > 0 {
+                remarks.push(Remark::passed(
+                    "dead store elimination",
+                    "DeadStore",
+                    format!("removed {} stores to the stack that nothing reads", removed),
+                ));
+            }
+            removed > 0
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Make the branches to blocks that only pass them on go straight to where those go
+    pub const JUMP_THREADING: Self = Self {
+        name: "jump threading",
+        run: |ir, remarks| {
+            let threaded = cleanup::threading::thread_jumps(ir);
+            if threaded > 0 {
+                remarks.push(Remark::passed(
+                    "jump threading",
+                    "JumpThreaded",
+                    format!(
+                        "{} branches skip a block that only passed them on",
+                        threaded
+                    ),
+                ));
+            }
+            threaded > 0
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Make the conditional branches that can only go one way unconditional
+    pub const BRANCH_SIMPLIFICATION: Self = Self {
+        name: "branch simplification",
+        run: |ir, remarks| {
+            let before: Vec<_> = ir.code.iter().map(|block| block.end).collect();
+            if !cleanup::simplify_branches(ir) {
+                return false;
+            }
+            for (old, new) in before.into_iter().zip(&ir.code) {
+                let BlockEnd::Branch(Branch::Conditional {
+                    flag,
+                    target_true,
+                    target_false,
+                }) = old
+                else {
+                    continue;
+                };
+                if old == new.end {
+                    continue;
+                }
+                let (name, message) = if target_true == target_false {
+                    (
+                        "SameTargets",
+                        "both sides of the branch go to the same block",
+                    )
+                } else {
+                    (
+                        "ConstantCondition",
+                        "the condition is always the same, so only that side is kept",
+                    )
+                };
+                remarks
+                    .push(Remark::passed("branch simplification", name, message.into()).at(flag));
+            }
+            true
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Turn the branches whose sides only compute the values of phi nodes into selects
+    pub const IF_CONVERSION: Self = Self {
+        name: "if-conversion",
+        run: |ir, remarks| {
+            let converted = cleanup::if_conversion::convert_to_selects(ir);
+            if converted > 0 {
+                remarks.push(Remark::passed(
+                    "if-conversion",
+                    "IfConverted",
+                    format!(
+                        "{} branches became selects of what their sides compute",
+                        converted
+                    ),
+                ));
+            }
+            converted > 0
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Remove the blocks no branch leads to
+    pub const UNREACHED_BLOCK_PRUNING: Self = Self {
+        name: "unreached block pruning",
+        run: |ir, remarks| {
+            let before = ir.code.len();
+            cleanup::prune_unreached_blocks(ir);
+            let pruned = before - ir.code.len();
+            if pruned > 0 {
+                remarks.push(Remark::passed(
+                    "unreached block pruning",
+                    "UnreachedCode",
+                    format!("removed {} blocks of code that never run", pruned),
+                ));
+            }
+            pruned > 0
+        },
+        level: OptLevel::O0,
+        preserves_graphs: true,
+    };
+    /// Compute what's known at compile time, and take the branches on known conditions
+    pub const CONSTANT_FOLDING: Self = Self {
+        name: "constant folding",
+        run: |ir, remarks| {
+            let before = ir.code.clone();
+            fold::fold_ir_blocks(ir);
+            for (old, new) in before.iter().zip(&ir.code) {
+                if let (
+                    BlockEnd::Branch(Branch::Conditional {
+                        flag, target_true, ..
+                    }),
+                    BlockEnd::Branch(Branch::Unconditional { target }),
+                ) = (old.end, new.end)
+                {
+                    let side = if target == target_true {
+                        "true"
+                    } else {
+                        "false"
+                    };
+                    remarks.push(
+                        Remark::passed(
+                            "constant folding",
+                            "ConstantCondition",
+                            format!(
+                                "the condition is always {}, so only that side is kept",
+                                side
+                            ),
+                        )
+                        .at(flag),
+                    );
+                }
+            }
+            ir.code != before
+        },
+        // register allocation doesn't give constants a register, so it can't handle a phi node
+        // taking one that wasn't folded into its block
+        level: OptLevel::O0,
+        preserves_graphs: true,
+    };
+    /// Find the bindings that are always constant along the branches that can be taken, through
+    /// phi nodes, and take the branches on the flags that are
+    pub const SPARSE_CONSTANT_PROPAGATION: Self = Self {
+        name: "sparse conditional constant propagation",
+        run: |ir, remarks| {
+            let before: Vec<_> = ir.code.iter().map(|block| block.end).collect();
+            let changed = cleanup::sccp::propagate_constants(ir);
+            for (old, new) in before.into_iter().zip(&ir.code) {
+                if let (BlockEnd::Branch(Branch::Conditional { flag, .. }), BlockEnd::Branch(_)) =
+                    (old, new.end)
+                {
+                    if old != new.end {
+                        remarks.push(
+                            Remark::passed(
+                                "sparse conditional constant propagation",
+                                "ConstantCondition",
+                                "the condition is always the same, so only that side is kept"
+                                    .into(),
+                            )
+                            .at(flag),
+                        );
+                    }
+                }
+            }
+            changed
+        },
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+    /// Merge the blocks that are the only child of their only parent into it
+    pub const BLOCK_MERGING: Self = Self {
+        name: "block merging",
+        run: |ir, _| cleanup::merge::merge_unique_jumps(ir),
+        level: OptLevel::O1,
+        preserves_graphs: true,
+    };
+}
+
+fn statement_count(ir: &IR) -> usize {
+    ir.code.iter().map(|block| block.statements.len()).sum()
+}
+
+/// A group repeated until it changes nothing stops after this many rounds even if it still does,
+/// in case some passes undo what others do
+const MAX_ROUNDS: usize = 64;
+
+enum Step {
+    Once(Pass),
+    /// passes repeated in order until a round where none of them changes the IR
+    Fixpoint(Vec<Pass>),
+}
+
+/// The passes run at an optimization level, in order
+pub struct PassManager {
+    level: OptLevel,
+    steps: Vec<Step>,
+}
+
+impl PassManager {
+    /// A manager without passes, that leaves out the ones added above `level`
+    pub const fn new(level: OptLevel) -> Self {
+        Self {
+            level,
+            steps: Vec::new(),
+        }
+    }
+    /// The passes tracc runs at `level`
+    pub fn for_level(level: OptLevel) -> Self {
+        let mut manager = Self::new(level);
+        if level == OptLevel::O0 {
+            manager
+                .add(Pass::CLEANUP)
+                .add(Pass::CONSTANT_FOLDING)
+                .add(Pass::CLEANUP)
+                .add(Pass::UNREACHED_BLOCK_PRUNING);
+            return manager;
+        }
+        manager
+            .add(Pass::CLEANUP)
+            .add(Pass::MEM2REG)
+            .add(Pass::CLEANUP)
+            .add_fixpoint([
+                Pass::CONSTANT_FOLDING,
+                Pass::SPARSE_CONSTANT_PROPAGATION,
+                Pass::COPY_PROPAGATION,
+                Pass::DEAD_STORE_ELIMINATION,
+                Pass::CLEANUP,
+                Pass::JUMP_THREADING,
+                Pass::BRANCH_SIMPLIFICATION,
+                Pass::IF_CONVERSION,
+                Pass::UNREACHED_BLOCK_PRUNING,
+                Pass::BLOCK_MERGING,
+            ])
+            .add(Pass::UNREACHED_BLOCK_PRUNING)
+            .add(Pass::CLEANUP);
+        manager
+    }
+    /// Run `pass` once, after the passes added before
+    pub fn add(&mut self, pass: Pass) -> &mut Self {
+        if pass.level <= self.level {
+            self.steps.push(Step::Once(pass));
+        }
+        self
+    }
+    /// Run `passes` in order, again and again until they change nothing
+    pub fn add_fixpoint(&mut self, passes: impl IntoIterator<Item = Pass>) -> &mut Self {
+        let passes: Vec<_> = passes
+            .into_iter()
+            .filter(|pass| pass.level <= self.level)
+            .collect();
+        if !passes.is_empty() {
+            self.steps.push(Step::Fixpoint(passes));
+        }
+        self
+    }
+    /// Run the passes over `ir`, showing `after_pass` the name of each pass run and the IR it
+    /// leaves
+    pub fn run(&self, ir: IR, after_pass: &mut dyn FnMut(&'static str, &IR)) -> IR {
+        self.run_with_remarks(ir, after_pass, &mut Vec::new())
+    }
+    /// Run the passes like [`PassManager::run`] does, adding the remarks of the passes to
+    /// `remarks`
+    pub fn run_with_remarks(
+        &self,
+        mut ir: IR,
+        after_pass: &mut dyn FnMut(&'static str, &IR),
+        remarks: &mut Vec<Remark>,
+    ) -> IR {
+        if cfg!(debug_assertions) {
+            if let Err(malformed) = verify::verify(&ir) {
+                panic!("IR generation left malformed IR: {}", malformed);
+            }
+        }
+        for step in &self.steps {
+            match step {
+                Step::Once(pass) => {
+                    run_pass(pass, &mut ir, after_pass, remarks);
+                }
+                Step::Fixpoint(passes) => {
+                    for _ in 0..MAX_ROUNDS {
+                        let mut changed = false;
+                        for pass in passes {
+                            changed |= run_pass(pass, &mut ir, after_pass, remarks);
+                        }
+                        if !changed {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        ir
+    }
+}
+
+fn run_pass(
+    pass: &Pass,
+    ir: &mut IR,
+    after_pass: &mut dyn FnMut(&'static str, &IR),
+    remarks: &mut Vec<Remark>,
+) -> bool {
+    let changed = (pass.run)(ir, remarks);
+    if changed && !pass.preserves_graphs {
+        (ir.forward_map, ir.backwards_map) = generate::generate_branching_graphs(&ir.code);
+    }
+    // a pass that breaks the invariants is caught in debug builds, before the next pass is run
+    // on what it left
+    if cfg!(debug_assertions) {
+        if let Err(malformed) = verify::verify(ir) {
+            panic!("the {} pass left malformed IR: {}", pass.name, malformed);
+        }
+    }
+    after_pass(pass.name, ir);
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SourceMetadata;
+    use crate::intermediate::generate::tests::compiled;
+    use crate::intermediate::{interpret, Statement, Value};
+
+    #[test]
+    fn the_optimization_level_picks_the_passes() {
+        let meta = SourceMetadata::new("int main() { if (2 > 1) return 2 * 3 + 1; return 0; }");
+        let main = compiled(&meta).functions.remove(0);
+        for level in [OptLevel::O0, OptLevel::O1] {
+            let mut passes_run = Vec::new();
+            let ir = PassManager::for_level(level)
+                .run(main.ir.clone(), &mut |pass, _| passes_run.push(pass));
+            let multiplications = ir
+                .code
+                .iter()
+                .flat_map(|block| &block.statements)
+                .filter(|statement| {
+                    matches!(
+                        statement,
+                        Statement::Assign {
+                            value: Value::Multiply { .. },
+                            ..
+                        }
+                    )
+                })
+                .count();
+            let inputs = interpret::sample_inputs().next().unwrap();
+            let returned = interpret::run(&ir, &main.read_only, &inputs).map(|run| run.returned);
+            assert_eq!(returned, Ok(7), "{level:?}");
+            match level {
+                OptLevel::O0 => {
+                    // the constants are folded, but the blocks are left alone
+                    assert!(!passes_run.contains(&"block merging"));
+                    assert!(ir.code.len() > 1);
+                }
+                _ => {
+                    // the passes are repeated until merging the blocks leaves one
+                    let merges = passes_run.iter().filter(|&&pass| pass == "block merging");
+                    assert!(merges.count() >= 2);
+                    assert_eq!((ir.code.len(), multiplications), (1, 0));
+                }
+            }
+        }
+        // code generation takes what the least optimization leaves, where the blocks between a
+        // constant and the phi node that takes it aren't merged
+        let meta = SourceMetadata::new("int f() { return 1 != 3 * 4 + 6 && 6 == 0; }");
+        let mut function = compiled(&meta).functions.remove(0);
+        function.ir = PassManager::for_level(OptLevel::O0).run(function.ir, &mut |_, _| ());
+        crate::codegen::codegen_function(function, 0, &[], Default::default(), &meta);
+    }
+}
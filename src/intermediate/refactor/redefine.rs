@@ -1,132 +1,338 @@
+use alloc::vec;
+
+use hashbrown::HashMap;
+
 use crate::intermediate::{
-    BasicBlock, Binding, BlockEnd, Branch, CouldBeConstant, IRCode, PhiDescriptor, Statement, Value,
+    BasicBlock, Binding, BlockEnd, Branch, Callee, CouldBeConstant, IRCode, PhiDescriptor,
+    Statement, Value,
 };
 
+/// A set of substitutions to be applied in a single walk over the code: each key is renamed
+/// as its value.
+pub type RenameMap = HashMap<Binding, Binding>;
+
 /// Mechanism used by cleanup code to rename bindings
 pub trait Rename {
-    fn rename(&mut self, target: Binding, rename_as: Binding);
-}
+    /// Visit every binding that is *used* (not defined) by the item, allowing to replace it.
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding));
 
-impl Rename for Binding {
     fn rename(&mut self, target: Binding, rename_as: Binding) {
-        if self.0 == target.0 {
-            *self = rename_as;
+        self.substitute_bindings(&mut |binding| {
+            if *binding == target {
+                *binding = rename_as;
+            }
+        })
+    }
+
+    /// Apply all the substitutions in the map in one walk. Chains of substitutions (`a -> b`,
+    /// `b -> c`) are followed, so that `a` ends up renamed as `c`. A chain that comes back to
+    /// where it started (`a -> b`, `b -> a`) stops before it does, so that `a` and `b` swap, and
+    /// one that goes round a cycle further down stops once it has taken as many steps as there
+    /// are substitutions.
+    fn rename_all(&mut self, substitutions: &RenameMap) {
+        if substitutions.is_empty() {
+            return;
         }
+        self.substitute_bindings(&mut |binding| {
+            let start = *binding;
+            let mut steps = 0;
+            while let Some(next) = substitutions.get(binding) {
+                steps += 1;
+                if *next == start || steps > substitutions.len() {
+                    break;
+                }
+                *binding = *next;
+            }
+        })
+    }
+}
+
+impl Rename for Binding {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
+        substitute(self)
     }
 }
 
 impl Rename for CouldBeConstant {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         match self {
-            CouldBeConstant::Binding(binding) => binding.rename(target, rename_as),
+            CouldBeConstant::Binding(binding) => binding.substitute_bindings(substitute),
             CouldBeConstant::Constant(_) => (),
         }
     }
 }
 
 impl Rename for PhiDescriptor {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
-        self.value.rename(target, rename_as)
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
+        self.value.substitute_bindings(substitute)
     }
 }
 
 impl Rename for Value {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         match self {
-            Value::Allocate { size: _ } => (),
+            Value::Allocate { .. } => (),
             Value::Phi { nodes } => nodes
                 .iter_mut()
-                .for_each(|node| node.rename(target, rename_as)),
-            Value::Cmp {
-                condition: _,
-                lhs,
-                rhs,
+                .for_each(|node| node.substitute_bindings(substitute)),
+            Value::Cmp { lhs, rhs, .. } => {
+                lhs.substitute_bindings(substitute);
+                rhs.substitute_bindings(substitute);
+            }
+            Value::Select {
+                flag,
+                if_true,
+                if_false,
             } => {
-                lhs.rename(target, rename_as);
-                rhs.rename(target, rename_as);
+                flag.substitute_bindings(substitute);
+                if_true.substitute_bindings(substitute);
+                if_false.substitute_bindings(substitute);
             }
             Value::Load {
                 mem_binding,
                 byte_size: _,
+                volatile: _,
+            }
+            | Value::AtomicLoad {
+                mem_binding,
+                byte_size: _,
+            }
+            | Value::Address { mem_binding } => {
+                mem_binding.substitute_bindings(substitute);
+            }
+            Value::AtomicFetchAdd {
+                mem_binding,
+                binding,
+                byte_size: _,
             } => {
-                mem_binding.rename(target, rename_as);
+                mem_binding.substitute_bindings(substitute);
+                binding.substitute_bindings(substitute);
             }
 
-            Value::Negate { binding } | Value::FlipBits { binding } | Value::Binding(binding) => {
-                binding.rename(target, rename_as)
+            Value::Negate { binding, .. }
+            | Value::FlipBits { binding, .. }
+            | Value::SignExtend { binding, .. }
+            | Value::FloatNegate { binding, .. }
+            | Value::IntToFloat { binding, .. }
+            | Value::FloatToInt { binding, .. }
+            | Value::FloatConvert { binding, .. }
+            | Value::Binding(binding) => binding.substitute_bindings(substitute),
+            Value::Add { lhs, rhs, .. }
+            | Value::Subtract { lhs, rhs, .. }
+            | Value::Multiply { lhs, rhs, .. }
+            | Value::Divide { lhs, rhs, .. }
+            | Value::Lsl { lhs, rhs, .. }
+            | Value::Lsr { lhs, rhs, .. }
+            | Value::Asr { lhs, rhs, .. }
+            | Value::And { lhs, rhs, .. }
+            | Value::Or { lhs, rhs, .. }
+            | Value::Xor { lhs, rhs, .. } => {
+                lhs.substitute_bindings(substitute);
+                rhs.substitute_bindings(substitute);
+            }
+            Value::FloatArithmetic { lhs, rhs, .. } | Value::FloatCmp { lhs, rhs, .. } => {
+                lhs.substitute_bindings(substitute);
+                rhs.substitute_bindings(substitute);
+            }
+            Value::Call { function: _, args } => args
+                .iter_mut()
+                .for_each(|arg| arg.substitute_bindings(substitute)),
+            Value::CallPointer { pointer, args } => {
+                pointer.substitute_bindings(substitute);
+                args.iter_mut()
+                    .for_each(|arg| arg.substitute_bindings(substitute));
+            }
+            Value::CallStruct {
+                callee,
+                args,
+                result,
+                ..
+            } => {
+                if let Callee::Pointer(pointer) = callee {
+                    pointer.substitute_bindings(substitute);
+                }
+                args.iter_mut()
+                    .for_each(|arg| arg.substitute_bindings(substitute));
+                result.substitute_bindings(substitute);
             }
-            Value::Add { lhs, rhs }
-            | Value::Subtract { lhs, rhs }
-            | Value::Multiply { lhs, rhs }
-            | Value::Divide {
-                lhs,
-                rhs,
-                is_signed: _,
+            Value::CallFloat { callee, args, .. } => {
+                if let Callee::Pointer(pointer) = callee {
+                    pointer.substitute_bindings(substitute);
+                }
+                args.iter_mut()
+                    .for_each(|arg| arg.substitute_bindings(substitute));
             }
-            | Value::Lsl { lhs, rhs }
-            | Value::Lsr { lhs, rhs }
-            | Value::And { lhs, rhs }
-            | Value::Or { lhs, rhs }
-            | Value::Xor { lhs, rhs } => {
-                lhs.rename(target, rename_as);
-                rhs.rename(target, rename_as);
+            Value::ReturnedStruct { memory, .. } => memory.substitute_bindings(substitute),
+            Value::ElementAddress { base, index, .. } => {
+                base.substitute_bindings(substitute);
+                index.substitute_bindings(substitute);
             }
-            Value::Constant(_) => (),
+            Value::Constant(_)
+            | Value::FloatConstant { .. }
+            | Value::Undefined
+            | Value::Argument(_)
+            | Value::FloatArgument(_)
+            | Value::GlobalRegister(_)
+            | Value::ReadOnlyAddress(_)
+            | Value::FunctionAddress(_)
+            | Value::GlobalAddress(_)
+            | Value::ResultAddress { .. } => (),
         }
     }
 }
 
 impl Rename for Statement {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         match self {
-            Statement::Assign { index: _, value } => value.rename(target, rename_as),
+            Statement::Assign { index: _, value } => value.substitute_bindings(substitute),
             Statement::Store {
                 mem_binding,
                 binding,
                 byte_size: _,
+                volatile: _,
+            }
+            | Statement::AtomicStore {
+                mem_binding,
+                binding,
+                byte_size: _,
             } => {
-                mem_binding.rename(target, rename_as);
-                binding.rename(target, rename_as);
+                mem_binding.substitute_bindings(substitute);
+                binding.substitute_bindings(substitute);
             }
+            Statement::SetRegister {
+                register: _,
+                binding,
+            } => binding.substitute_bindings(substitute),
         }
     }
 }
 
 impl Rename for BlockEnd {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         match self {
-            BlockEnd::Branch(branch) => branch.rename(target, rename_as),
-            BlockEnd::Return(value) => value.rename(target, rename_as),
+            BlockEnd::Branch(branch) => branch.substitute_bindings(substitute),
+            BlockEnd::Return(value) => value.substitute_bindings(substitute),
         }
     }
 }
 
 impl Rename for Branch {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         if let Branch::Conditional {
             flag,
             target_true: _,
             target_false: _,
         } = self
         {
-            flag.rename(target, rename_as);
+            flag.substitute_bindings(substitute);
         }
     }
 }
 
 impl Rename for BasicBlock {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         for stmt in self.statements.iter_mut() {
-            stmt.rename(target, rename_as);
+            stmt.substitute_bindings(substitute);
         }
-        self.end.rename(target, rename_as);
+        self.end.substitute_bindings(substitute);
     }
 }
 
 impl Rename for IRCode {
-    fn rename(&mut self, target: Binding, rename_as: Binding) {
+    fn substitute_bindings(&mut self, substitute: &mut impl FnMut(&mut Binding)) {
         for block in self.iter_mut() {
-            block.rename(target, rename_as);
+            block.substitute_bindings(substitute);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::assembly::BitSize;
+    use crate::intermediate::ByteSize;
+
+    #[test]
+    fn rename_all_follows_chains() {
+        let mut block = BasicBlock {
+            statements: vec![
+                Statement::Assign {
+                    index: Binding(3),
+                    value: Value::Add {
+                        lhs: Binding(0),
+                        rhs: Binding(1).into(),
+                        bit_size: BitSize::Bit32,
+                    },
+                },
+                Statement::Store {
+                    mem_binding: Binding(4),
+                    binding: Binding(1),
+                    byte_size: ByteSize::U32,
+                    volatile: false,
+                },
+            ],
+            end: BlockEnd::Return(Binding(0)),
+        };
+        let substitutions: RenameMap = vec![(Binding(0), Binding(1)), (Binding(1), Binding(2))]
+            .into_iter()
+            .collect();
+        block.rename_all(&substitutions);
+        assert_eq!(
+            block.statements,
+            vec![
+                Statement::Assign {
+                    index: Binding(3),
+                    value: Value::Add {
+                        lhs: Binding(2),
+                        rhs: Binding(2).into(),
+                        bit_size: BitSize::Bit32,
+                    },
+                },
+                Statement::Store {
+                    mem_binding: Binding(4),
+                    binding: Binding(2),
+                    byte_size: ByteSize::U32,
+                    volatile: false,
+                },
+            ]
+        );
+        assert_eq!(block.end, BlockEnd::Return(Binding(2)));
+    }
+
+    #[test]
+    fn rename_all_stops_at_cycles() {
+        let add = |lhs, rhs| Statement::Assign {
+            index: Binding(9),
+            value: Value::Add {
+                lhs: Binding(lhs),
+                rhs: Binding(rhs).into(),
+                bit_size: BitSize::Bit32,
+            },
+        };
+        let mut block = BasicBlock {
+            statements: vec![add(0, 1), add(2, 3)],
+            end: BlockEnd::Return(Binding(2)),
+        };
+        // 0 and 1 swap, while 2 goes into the cycle between 3 and 4
+        let substitutions: RenameMap = vec![
+            (Binding(0), Binding(1)),
+            (Binding(1), Binding(0)),
+            (Binding(2), Binding(3)),
+            (Binding(3), Binding(4)),
+            (Binding(4), Binding(3)),
+        ]
+        .into_iter()
+        .collect();
+        block.rename_all(&substitutions);
+        assert_eq!(block.statements[0], add(1, 0));
+        let Statement::Assign {
+            value: Value::Add { lhs, rhs, .. },
+            ..
+        } = &block.statements[1]
+        else {
+            unreachable!()
+        };
+        assert!([Binding(3), Binding(4)].contains(lhs), "{lhs:?}");
+        assert_eq!(*rhs, Binding(4).into());
+    }
+}
@@ -0,0 +1,159 @@
+//! Optimization remarks: what the passes did to each function and why, or why they couldn't, for
+//! users tuning their code (`-fopt-remarks=<file>`). They're written as YAML documents shaped like
+//! LLVM's remarks, so that the tools that read those can read them too.
+use alloc::format;
+use alloc::string::String;
+
+use hashbrown::HashMap;
+
+use super::Binding;
+use crate::error::{SourceMetadata, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemarkKind {
+    /// the pass changed the code
+    Passed,
+    /// the pass would have changed the code, but something kept it from doing it
+    Missed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    pub kind: RemarkKind,
+    /// the name of the pass, as `-fverbose-asm` lists it
+    pub pass: &'static str,
+    /// what happened, in a word that stays the same for all the remarks of the same kind
+    pub name: &'static str,
+    /// the binding the remark is about, whose statement locates it in the source. Without one,
+    /// the remark points at the function.
+    pub at: Option<Binding>,
+    pub message: String,
+}
+
+impl Remark {
+    pub fn passed(pass: &'static str, name: &'static str, message: String) -> Self {
+        Self {
+            kind: RemarkKind::Passed,
+            pass,
+            name,
+            at: None,
+            message,
+        }
+    }
+    pub fn missed(pass: &'static str, name: &'static str, message: String) -> Self {
+        Self {
+            kind: RemarkKind::Missed,
+            ..Self::passed(pass, name, message)
+        }
+    }
+    #[must_use]
+    pub const fn at(mut self, binding: Binding) -> Self {
+        self.at = Some(binding);
+        self
+    }
+
+    /// The remark as a YAML document, located by the statement its binding was made for if
+    /// `spans` has it, or else by the function's name
+    pub fn to_yaml(
+        &self,
+        function: &str,
+        (spans, function_span): (&HashMap<Binding, Span>, Span),
+        source_meta: &SourceMetadata,
+    ) -> String {
+        let span = self
+            .at
+            .and_then(|binding| spans.get(&binding))
+            .copied()
+            .unwrap_or(function_span);
+        let (file, snippet) = source_meta.locate(span);
+        let location = match snippet {
+            Some(snippet) => format!(
+                "DebugLoc:        {{ File: {}, Line: {}, Column: {} }}\n",
+                yaml_string(file.as_deref().unwrap_or("<unknown source>")),
+                snippet.position().line + 1,
+                snippet.position().col + 1
+            ),
+            None => String::new(),
+        };
+        format!(
+            "--- !{:?}\n\
+             Pass:            {}\n\
+             Name:            {}\n\
+             {}\
+             Function:        {}\n\
+             Args:\n\
+             \x20 - String:          {}\n\
+             ...\n",
+            self.kind,
+            yaml_string(self.pass),
+            self.name,
+            location,
+            yaml_string(function),
+            yaml_string(&self.message)
+        )
+    }
+}
+
+/// A single-quoted YAML scalar, where only the quote itself has to be escaped
+fn yaml_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intermediate::generate::tests::compiled;
+    use crate::intermediate::inline::inline_functions;
+    use crate::intermediate::passes::{OptLevel, PassManager};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn passes_remark_on_what_they_did_and_why() {
+        let source = "static inline int twice(int x) { return x + x; }\n\
+                      inline int fact(int n) { if (n) return n * fact(n - 1); return 1; }\n\
+                      int f(int a) {\n\
+                      \x20   if (2 > 1) return twice(a) + fact(a);\n\
+                      \x20   return 0;\n\
+                      }\n";
+        let meta = SourceMetadata::new(source).with_file("r.c".into());
+        let mut module = compiled(&meta);
+        inline_functions(&mut module);
+        let mut function = module.functions.pop().unwrap();
+        assert_eq!(function.name, "f");
+        function.ir = PassManager::for_level(OptLevel::O1).run_with_remarks(
+            function.ir,
+            &mut |_, _| (),
+            &mut function.remarks,
+        );
+        let remarks: Vec<_> = function
+            .remarks
+            .iter()
+            .map(|remark| (remark.kind, remark.name))
+            .collect();
+        assert_eq!(
+            remarks,
+            [
+                (RemarkKind::Missed, "NotInlined"),
+                (RemarkKind::Passed, "Inlined"),
+                // `a`, and the parameter of the inlined `twice`
+                (RemarkKind::Passed, "Promoted"),
+                (RemarkKind::Passed, "Promoted"),
+                (RemarkKind::Passed, "ConstantCondition"),
+                (RemarkKind::Passed, "JumpThreaded"),
+                (RemarkKind::Passed, "UnreachedCode"),
+            ]
+        );
+        let missed = function.remarks[0].to_yaml("f", (&function.spans, function.span), &meta);
+        assert_eq!(
+            missed,
+            "--- !Missed\n\
+             Pass:            'inlining'\n\
+             Name:            NotInlined\n\
+             DebugLoc:        { File: 'r.c', Line: 4, Column: 16 }\n\
+             Function:        'f'\n\
+             Args:\n\
+             \x20 - String:          '`fact` not inlined into `f`: it calls itself'\n\
+             ...\n"
+        );
+    }
+}
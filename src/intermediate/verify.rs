@@ -0,0 +1,363 @@
+//! The structural invariants of the IR, which every pass has to keep: bindings are defined once
+//! and before they're used, phi nodes come from the blocks that reach them, and the branching
+//! graphs are the ones the branches make. A pass that breaks them leaves code the later ones and
+//! codegen can't be trusted with, even if it still runs right.
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+use thiserror::Error;
+
+use super::analysis::{BindingUsage, DominatorTree};
+use super::generate::generate_branching_graphs;
+use super::{Binding, BlockBinding, BlockEnd, Branch, BranchingMap, Statement, Value, IR};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Malformed {
+    #[error("{block} branches to {target}, which doesn't exist")]
+    MissingTarget {
+        block: BlockBinding,
+        target: BlockBinding,
+    },
+    #[error("the {graph} map says {block} goes to {listed:?}, but its branches go to {actual:?}")]
+    StaleGraph {
+        graph: &'static str,
+        block: BlockBinding,
+        listed: Vec<BlockBinding>,
+        actual: Vec<BlockBinding>,
+    },
+    #[error("{binding} is defined more than once")]
+    Redefined { binding: Binding },
+    #[error("{binding} is used in {block}, but never defined")]
+    Undefined {
+        binding: Binding,
+        block: BlockBinding,
+    },
+    #[error("{binding} is used in {block}, where its definition doesn't always run before")]
+    NotDominated {
+        binding: Binding,
+        block: BlockBinding,
+    },
+    #[error("the phi node of {binding} in {block} comes from {listed:?}, but the block is reached from {predecessors:?}")]
+    PhiPredecessors {
+        binding: Binding,
+        block: BlockBinding,
+        listed: Vec<BlockBinding>,
+        predecessors: Vec<BlockBinding>,
+    },
+}
+
+/// Check the invariants of the IR of a function, telling the first one broken
+pub fn verify(ir: &IR) -> Result<(), Malformed> {
+    verify_branches(ir)?;
+    let definitions = definitions(ir)?;
+    verify_phi_nodes(ir)?;
+    verify_dominance(ir, &definitions)
+}
+
+/// The blocks that are branched to exist, and the graphs list the branches that are there
+fn verify_branches(ir: &IR) -> Result<(), Malformed> {
+    for (index, block) in ir.code.iter().enumerate() {
+        if let Some(target) = block
+            .end
+            .branch_list()
+            .find(|target| target.0 >= ir.code.len())
+        {
+            return Err(Malformed::MissingTarget {
+                block: BlockBinding(index),
+                target,
+            });
+        }
+    }
+    let (forward_map, backwards_map) = generate_branching_graphs(&ir.code);
+    compare_graphs("forward", &ir.forward_map, &forward_map)?;
+    compare_graphs("backwards", &ir.backwards_map, &backwards_map)
+}
+
+/// The graphs are compared as sets of edges: the order they were listed in doesn't matter, and
+/// neither does a block going to another through both sides of a conditional branch
+fn compare_graphs(
+    graph: &'static str,
+    listed: &BranchingMap,
+    actual: &BranchingMap,
+) -> Result<(), Malformed> {
+    let edges = |map: &BranchingMap, block: &BlockBinding| {
+        let mut edges = map.get(block).cloned().unwrap_or_default();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    };
+    for block in listed.keys().chain(actual.keys()) {
+        let (listed, actual) = (edges(listed, block), edges(actual, block));
+        if listed != actual {
+            return Err(Malformed::StaleGraph {
+                graph,
+                block: *block,
+                listed,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Where each binding is defined: its block, and the index of the statement there
+fn definitions(ir: &IR) -> Result<HashMap<Binding, (BlockBinding, usize)>, Malformed> {
+    let mut definitions = HashMap::new();
+    for (block_index, block) in ir.code.iter().enumerate() {
+        for (index, statement) in block.statements.iter().enumerate() {
+            if let Statement::Assign { index: binding, .. } = statement {
+                if definitions
+                    .insert(*binding, (BlockBinding(block_index), index))
+                    .is_some()
+                {
+                    return Err(Malformed::Redefined { binding: *binding });
+                }
+            }
+        }
+    }
+    Ok(definitions)
+}
+
+/// Each phi node takes a value from each of the blocks that branch to its own, and only from them
+fn verify_phi_nodes(ir: &IR) -> Result<(), Malformed> {
+    for (block_index, block) in ir.code.iter().enumerate() {
+        let block = BlockBinding(block_index);
+        let mut predecessors = ir.backwards_map.get(&block).cloned().unwrap_or_default();
+        predecessors.sort_unstable();
+        predecessors.dedup();
+        for statement in &ir[block].statements {
+            if let Statement::Assign {
+                index,
+                value: Value::Phi { nodes },
+            } = statement
+            {
+                let mut listed: Vec<_> = nodes.iter().map(|node| node.block_from).collect();
+                listed.sort_unstable();
+                if listed != predecessors {
+                    return Err(Malformed::PhiPredecessors {
+                        binding: *index,
+                        block,
+                        listed,
+                        predecessors,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Each use of a binding runs after its definition on every path from the entry. Phi nodes use
+/// their values at the end of the blocks they come from. Blocks the entry doesn't reach can use
+/// anything, since they never run.
+fn verify_dominance(
+    ir: &IR,
+    definitions: &HashMap<Binding, (BlockBinding, usize)>,
+) -> Result<(), Malformed> {
+    let dominators = DominatorTree::new(ir);
+    let available_at = |binding: Binding, block: BlockBinding, index: usize| {
+        let &(defined_in, defined_at) = definitions
+            .get(&binding)
+            .ok_or(Malformed::Undefined { binding, block })?;
+        let dominates = if defined_in == block {
+            defined_at < index
+        } else {
+            dominators.dominates(defined_in, block)
+        };
+        if dominates {
+            Ok(())
+        } else {
+            Err(Malformed::NotDominated { binding, block })
+        }
+    };
+    for (block_index, block) in ir.code.iter().enumerate() {
+        let block_binding = BlockBinding(block_index);
+        if !dominators.is_reached(block_binding) {
+            continue;
+        }
+        for (index, statement) in block.statements.iter().enumerate() {
+            match statement {
+                Statement::Assign {
+                    value: Value::Phi { nodes },
+                    ..
+                } => {
+                    for node in nodes {
+                        // a predecessor the entry doesn't reach never passes its value
+                        if dominators.is_reached(node.block_from) {
+                            let end = ir[node.block_from].statements.len();
+                            available_at(node.value, node.block_from, end)?;
+                        }
+                    }
+                }
+                statement => {
+                    for binding in statement.binding_deps() {
+                        available_at(binding, block_binding, index)?;
+                    }
+                }
+            }
+        }
+        let end_use = match block.end {
+            BlockEnd::Branch(Branch::Conditional { flag, .. }) => Some(flag),
+            BlockEnd::Return(returned) => Some(returned),
+            BlockEnd::Branch(Branch::Unconditional { .. }) => None,
+        };
+        if let Some(binding) = end_use {
+            available_at(binding, block_binding, block.statements.len())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::assembly::{BitSize, Condition};
+    use crate::intermediate::{BasicBlock, PhiDescriptor};
+    use alloc::vec;
+
+    fn assign(index: usize, value: Value) -> Statement {
+        Statement::Assign {
+            index: Binding(index),
+            value,
+        }
+    }
+
+    /// Branches on the first argument to a block that computes a constant or one that doesn't,
+    /// and returns what the phi node at the end takes from them
+    fn diamond() -> IR {
+        let jump_to_end = Branch::Unconditional {
+            target: BlockBinding(3),
+        };
+        vec![
+            BasicBlock {
+                statements: vec![
+                    assign(0, Value::Argument(0)),
+                    assign(
+                        1,
+                        Value::Cmp {
+                            condition: Condition::Equals,
+                            lhs: Binding(0),
+                            rhs: 0.into(),
+                            bit_size: BitSize::Bit32,
+                        },
+                    ),
+                ],
+                end: Branch::Conditional {
+                    flag: Binding(1),
+                    target_true: BlockBinding(1),
+                    target_false: BlockBinding(2),
+                }
+                .into(),
+            },
+            BasicBlock {
+                statements: vec![assign(2, Value::Constant(7))],
+                end: jump_to_end.into(),
+            },
+            BasicBlock {
+                statements: vec![],
+                end: jump_to_end.into(),
+            },
+            BasicBlock {
+                statements: vec![assign(
+                    3,
+                    Value::Phi {
+                        nodes: vec![
+                            PhiDescriptor {
+                                value: Binding(2),
+                                block_from: BlockBinding(1),
+                            },
+                            PhiDescriptor {
+                                value: Binding(0),
+                                block_from: BlockBinding(2),
+                            },
+                        ],
+                    },
+                )],
+                end: Binding(3).into(),
+            },
+        ]
+        .into()
+    }
+
+    #[test]
+    fn well_formed_ir_passes() {
+        assert_eq!(verify(&diamond()), Ok(()));
+    }
+
+    #[test]
+    fn stale_graphs_are_caught() {
+        let mut ir = diamond();
+        ir.backwards_map.remove(&BlockBinding(3));
+        assert!(matches!(
+            verify(&ir),
+            Err(Malformed::StaleGraph {
+                graph: "backwards",
+                ..
+            })
+        ));
+
+        let mut ir = diamond();
+        ir[BlockBinding(2)].end = Branch::Unconditional {
+            target: BlockBinding(4),
+        }
+        .into();
+        assert_eq!(
+            verify(&ir),
+            Err(Malformed::MissingTarget {
+                block: BlockBinding(2),
+                target: BlockBinding(4),
+            })
+        );
+    }
+
+    #[test]
+    fn phi_nodes_come_from_the_predecessors() {
+        let mut ir = diamond();
+        if let Statement::Assign {
+            value: Value::Phi { nodes },
+            ..
+        } = &mut ir[BlockBinding(3)].statements[0]
+        {
+            nodes.pop();
+        }
+        assert!(matches!(
+            verify(&ir),
+            Err(Malformed::PhiPredecessors { .. })
+        ));
+    }
+
+    #[test]
+    fn uses_are_dominated_by_their_definition() {
+        // the block the constant is computed in doesn't always run before the return
+        let mut ir = diamond();
+        ir[BlockBinding(3)].end = Binding(2).into();
+        assert_eq!(
+            verify(&ir),
+            Err(Malformed::NotDominated {
+                binding: Binding(2),
+                block: BlockBinding(3),
+            })
+        );
+
+        let mut ir = diamond();
+        ir[BlockBinding(0)].statements.swap(0, 1);
+        assert_eq!(
+            verify(&ir),
+            Err(Malformed::NotDominated {
+                binding: Binding(0),
+                block: BlockBinding(0),
+            })
+        );
+
+        let mut ir = diamond();
+        ir[BlockBinding(1)]
+            .statements
+            .push(assign(2, Value::Constant(8)));
+        assert_eq!(
+            verify(&ir),
+            Err(Malformed::Redefined {
+                binding: Binding(2)
+            })
+        );
+    }
+}
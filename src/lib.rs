@@ -1,3 +1,12 @@
+//! The compiler as a library. Without the `std` feature it only needs `alloc`, so it can be
+//! embedded where there is no operating system: the parts that read files or run the system
+//! assembler are left out then.
+#![no_std]
+
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
 #[allow(unused)]
 pub mod allocators;
 #[allow(unused)]
@@ -10,3 +19,186 @@ pub mod error;
 pub mod grammar;
 #[allow(unused)]
 pub mod intermediate;
+pub mod stats;
+
+use alloc::string::{String, ToString};
+
+use error::{DiagnosticFilter, Diagnostics, SourceMetadata, WarningOptions};
+use hashbrown::HashMap;
+use intermediate::generate::symbols::SymbolIndex;
+use intermediate::generate::{GenerateOptions, Module};
+
+/// The most IR bindings a function may make in [`check_str`]. Real functions stay far below it,
+/// while code that would make many more (like zeroing a huge local array) is stopped early.
+pub const CHECK_BINDING_BUDGET: usize = 1 << 16;
+
+/// Parse a program and generate its IR. The warnings the filter lets through are collected,
+/// sorted, into the diagnostics, which also get the error that stopped the compilation if there
+/// was one. Statements and expressions nested deeper than `nesting_limit` are rejected, since the
+/// compiler recurses through them. With `no_builtin`, the library functions the compiler knows
+/// are treated like any other.
+pub fn compile_to_ir<'code>(
+    source: &'code SourceMetadata<'code>,
+    standard: ast::Standard,
+    filter: &DiagnosticFilter,
+    nesting_limit: usize,
+    no_builtin: bool,
+) -> Result<(Module<'code>, Diagnostics), Diagnostics> {
+    generate(source, standard, filter, (None, nesting_limit, no_builtin))
+}
+
+/// Look for the errors and warnings of a program without compiling it, for an editor to call as
+/// the code is typed. The source is preprocessed (with no files to include), parsed and its IR
+/// generated, which is where the rest of the errors are found, but the IR isn't optimized nor
+/// compiled, and the functions that make more IR than [`CHECK_BINDING_BUDGET`] are given up on.
+/// The default standard and warnings are used, along with the `#pragma`s of the source.
+pub fn check_str(source: &str) -> Diagnostics {
+    checked(source, |_| ()).1
+}
+
+/// Find where the names of a program are declared and where they are used, for an editor to go
+/// to the declaration of a name or list its uses. The source is looked at as in [`check_str`],
+/// and the errors that stop it from being indexed are given instead.
+pub fn index_str(source: &str) -> Result<SymbolIndex, Diagnostics> {
+    match checked(source, |module| module.symbols) {
+        (Some(symbols), _) => Ok(symbols),
+        (None, diagnostics) => Err(diagnostics),
+    }
+}
+
+/// Generate the IR of a source as [`check_str`] does, giving what `f` takes from it if there were
+/// no errors, along with the diagnostics
+fn checked<T>(source: &str, f: impl FnOnce(Module) -> T) -> (Option<T>, Diagnostics) {
+    let preprocessed = grammar::preprocess::preprocess(
+        "<input>".to_string(),
+        source.to_string(),
+        &HashMap::<String, String>::new(),
+        grammar::preprocess::predefined_macros(),
+    );
+    let preprocessed = match preprocessed {
+        Ok(preprocessed) => preprocessed,
+        Err(error) => return (None, error.into()),
+    };
+    let meta = preprocessed.metadata();
+    let filter = DiagnosticFilter::new(
+        WarningOptions::default(),
+        grammar::pragma::diagnostic_pragmas(meta.input()),
+    );
+    match generate(
+        &meta,
+        Default::default(),
+        &filter,
+        (Some(CHECK_BINDING_BUDGET), grammar::DEFAULT_NESTING_LIMIT, false),
+    ) {
+        Ok((module, diagnostics)) => (Some(f(module)), diagnostics),
+        Err(diagnostics) => (None, diagnostics),
+    }
+}
+
+fn generate<'code>(
+    source: &'code SourceMetadata<'code>,
+    standard: ast::Standard,
+    filter: &DiagnosticFilter,
+    (binding_budget, nesting_limit, no_builtin): (Option<usize>, usize, bool),
+) -> Result<(Module<'code>, Diagnostics), Diagnostics> {
+    let program = grammar::Parser::new(source)
+        .with_nesting_limit(nesting_limit)
+        .parse()?;
+    let options = GenerateOptions {
+        standard,
+        warnings: filter.check_options(),
+        binding_budget,
+        no_builtin,
+    };
+    let (module, warnings) = intermediate::generate::compile_program(program, source, options)?;
+    let mut diagnostics = Diagnostics::new();
+    for warning in &warnings {
+        let flag = warning.kind().flag();
+        if filter.is_enabled(flag, warning.span().map_or(0, |span| span.offset)) {
+            diagnostics.push_warning(warning, flag);
+        }
+    }
+    Ok((module, diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checking_reports_errors_and_gives_up_on_huge_functions() {
+        let diagnostics = check_str("int main() {\n    return x;\n}\n");
+        let error = diagnostics.iter().next().expect("the variable is unknown");
+        assert_eq!(error.position.map(|position| position.line), Some(1));
+        assert!(check_str("int main() {\n    return 0;\n}\n").is_empty());
+
+        let huge = "int main(int argc) {\n    int a[1000000] = {argc};\n    return a[0];\n}\n";
+        let diagnostics = check_str(huge);
+        let error = diagnostics
+            .iter()
+            .next()
+            .expect("the array is zeroed with too much IR");
+        assert!(error.message.contains("too many to check"));
+    }
+
+    #[test]
+    fn uses_of_names_lead_to_the_declaration_in_scope() {
+        use error::Position;
+        use intermediate::generate::symbols::SymbolKind;
+        let source = "int twice(int x);\n\
+                      enum { ONE = 1 };\n\
+                      int main() {\n\
+                      \x20   int x = ONE;\n\
+                      \x20   {\n\
+                      \x20       int x = twice(x);\n\
+                      \x20       return x;\n\
+                      \x20   }\n\
+                      }\n\
+                      int twice(int x) {\n\
+                      \x20   return x + x;\n\
+                      }\n";
+        let symbols = index_str(source).expect("the source is fine");
+        let at = |line, col| symbols.definition_at(Some("<input>"), Position { line, col });
+        // the inner `x` is in scope in its own initializer, where it shadows the outer one
+        let inner = at(5, 22).expect("the argument is the inner `x`");
+        assert_eq!(
+            (inner.kind, inner.location.position.line),
+            (SymbolKind::Variable, 5)
+        );
+        assert_eq!(at(6, 15).unwrap().location.position.line, 5);
+        assert_eq!(at(3, 12).unwrap().kind, SymbolKind::Enumerator);
+        // the call and the prototype go to the definition
+        let twice = at(5, 17).expect("the call is a use of `twice`");
+        assert_eq!(
+            (twice.kind, twice.location.position.line),
+            (SymbolKind::Function, 9)
+        );
+        assert_eq!(at(0, 5), Some(twice));
+        let index = symbols.definitions.iter().position(|d| d == twice).unwrap();
+        assert_eq!(symbols.references_to(index).count(), 2);
+        assert!(index_str("int main() { return y; }").is_err());
+    }
+
+    #[test]
+    fn without_builtins_library_names_are_like_any_other() {
+        let generate = |source, no_builtin| {
+            let meta = SourceMetadata::new(source);
+            let filter = DiagnosticFilter::new(WarningOptions::default(), Default::default());
+            compile_to_ir(&meta, Default::default(), &filter, 128, no_builtin)
+                .map(|(_, diagnostics)| diagnostics.iter().count())
+                .map_err(|_| ())
+        };
+        // `exit` may be called undeclared, and its own definition may take other parameters
+        let call = "int main() { exit(1); return 0; }";
+        assert_eq!(generate(call, false), Ok(0));
+        assert_eq!(generate(call, true), Err(()));
+        let definition = "void exit(void) {}\nint main() { exit(); return 0; }";
+        assert_eq!(generate(definition, false), Err(()));
+        assert_eq!(generate(definition, true), Ok(0));
+        // nor is it assumed to never return, so the recursion can't be left through it
+        let recursion = "void exit(int status);\n\
+                         int f(int x) { if (x) exit(1); return f(x); }";
+        assert_eq!(generate(recursion, false), Ok(0));
+        assert_eq!(generate(recursion, true), Ok(1));
+    }
+}
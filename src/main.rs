@@ -1,9 +1,13 @@
 use std::error::Error;
 use structopt::StructOpt;
-use tracc::ast::Program;
 
-use tracc::error::SourceMetadata;
-use tracc::grammar::Parser;
+use tracc::error::{DiagnosticFilter, Diagnostics, SourceMetadata, WarningOptions};
+use tracc::stats::{CountingAllocator, MemoryStats};
+
+/// Counts the memory in use, for the stages reported by `--stats`
+#[global_allocator]
+static ALLOCATOR: CountingAllocator<std::alloc::System> =
+    CountingAllocator::new(std::alloc::System);
 
 // TODO(#3): structured formatting lib (error,warning,note,help, etc)
 // TODO(#4): create test crate
@@ -16,38 +20,388 @@ fn main() {
 }
 
 fn run() -> Result<(), Box<dyn Error>> {
+    use std::fmt::Write;
     use std::fs;
-    use std::io::Write;
 
     let opt = Opt::from_args();
+    let mut memory = MemoryStats::default();
     let filename = opt.file;
-    let file = fs::read_to_string(&filename)?;
-    let out_file = opt.output.unwrap_or_else(|| filename.with_extension("s"));
-    let meta = SourceMetadata::new(&file).with_file(filename);
-    let program: Program = Parser::new(&meta).parse()?;
-    let (function_name, ir) = tracc::intermediate::generate::compile_function(program.0.into_iter().next().unwrap(), &meta)?;
-
-    let output = tracc::codegen::codegen_function(
-        function_name.to_string(),
-        dbg!(tracc::intermediate::fold::constant_fold(dbg!(ir))),
+    let out_file = opt.output.unwrap_or_else(|| match opt.emit {
+        Emit::Assembly => filename.with_extension("s"),
+        Emit::Ir => filename.with_extension("ir"),
+        Emit::Executable => "a.out".into(),
+    });
+    let target = tracc::codegen::target::by_name(&opt.target)
+        .ok_or_else(|| format!("unknown target `{}`", opt.target))?;
+    tracc::codegen::target::select(target);
+    let mut branch_protection = tracc::codegen::BranchProtection::default();
+    let mut cpu = tracc::codegen::target::Cpu::default();
+    let mut attributes = Vec::new();
+    for option in &opt.machine {
+        match option.split_once('=') {
+            Some(("branch-protection", value)) => branch_protection = value.parse()?,
+            Some(("cpu", value)) => cpu = tracc::codegen::target::Cpu::by_name(value)?,
+            Some(("arch", value)) => cpu = tracc::codegen::target::Cpu::by_architecture(value)?,
+            Some(("attr", value)) => attributes.push(value),
+            _ => return Err(format!("unknown machine option `-m{}`", option).into()),
+        }
+    }
+    // the features are added to or taken out of the CPU, whichever order they're given in
+    for attributes in attributes {
+        cpu.apply_attributes(attributes)?;
+    }
+    tracc::codegen::target::select_cpu(cpu);
+    let mut sections = tracc::codegen::SectionPlacement::default();
+    let mut error_limit = Some(tracc::error::DEFAULT_ERROR_LIMIT);
+    let mut json_diagnostics = false;
+    let mut merge_constants = false;
+    let mut trapped_functions = Vec::new();
+    let mut verbose_asm = false;
+    let mut no_builtin = false;
+    let mut remarks_file = None;
+    let mut charset = tracc::grammar::preprocess::Charset::default();
+    let mut nesting_limit = tracc::grammar::DEFAULT_NESTING_LIMIT;
+    for option in &opt.features {
+        match option.as_str() {
+            "function-sections" => sections.function_sections = true,
+            "no-function-sections" => sections.function_sections = false,
+            "data-sections" => sections.data_sections = true,
+            "no-data-sections" => sections.data_sections = false,
+            "merge-all-constants" => merge_constants = true,
+            "no-merge-all-constants" => merge_constants = false,
+            "diagnostics-format=text" => json_diagnostics = false,
+            "diagnostics-format=json" => json_diagnostics = true,
+            "debug-trap-main" => trapped_functions.push("main"),
+            "builtin" => no_builtin = false,
+            "no-builtin" => no_builtin = true,
+            "verbose-asm" => verbose_asm = true,
+            "no-verbose-asm" => verbose_asm = false,
+            _ => {
+                if let Some(limit) = option.strip_prefix("max-errors=") {
+                    error_limit = match limit.parse()? {
+                        0 => None,
+                        limit => Some(limit),
+                    }
+                } else if let Some(limit) = option.strip_prefix("bracket-depth=") {
+                    nesting_limit = limit.parse()?;
+                } else if let Some(function) = option.strip_prefix("debug-trap=") {
+                    trapped_functions.push(function);
+                } else if let Some(file) = option.strip_prefix("opt-remarks=") {
+                    remarks_file = Some(file);
+                } else if let Some(name) = option.strip_prefix("input-charset=") {
+                    charset = name.parse()?;
+                } else {
+                    return Err(format!("unknown option `-f{}`", option).into());
+                }
+            }
+        }
+    }
+    let mut warning_options = WarningOptions::default();
+    for flag in &opt.warnings {
+        if !warning_options.apply_flag(flag) {
+            return Err(format!("unknown warning option `-W{}`", flag).into());
+        }
+    }
+    let fail = |diagnostics: Diagnostics| -> Box<dyn Error> {
+        let diagnostics = diagnostics.with_error_limit(error_limit);
+        if json_diagnostics {
+            diagnostics.to_json().into()
+        } else {
+            diagnostics.into()
+        }
+    };
+    let include_paths = tracc::grammar::preprocess::IncludePaths {
+        user: opt.include_dirs,
+        system: opt.system_include_dirs,
+        charset,
+    };
+    let file = tracc::grammar::preprocess::decode_source(
+        &filename.display().to_string(),
+        fs::read(&filename)?,
+        charset,
     )
-    .cons(tracc::codegen::assembly::Directive::Architecture(
-        "armv8-a".into(),
-    ));
+    .map_err(|error| fail(error.into()))?;
+    let mut macros = tracc::grammar::preprocess::predefined_macros();
+    for definition in opt.defines {
+        let (name, value) = definition.split_once('=').unwrap_or((&definition, "1"));
+        macros.insert(name.to_string(), value.to_string());
+    }
+    for name in &opt.undefines {
+        macros.remove(name);
+    }
+    let preprocessed = memory
+        .measure(&ALLOCATOR, "preprocessing", || {
+            tracc::grammar::preprocess::preprocess(
+                filename.display().to_string(),
+                file,
+                &include_paths,
+                macros,
+            )
+        })
+        .map_err(|error| fail(error.into()))?;
+    let meta = preprocessed.metadata();
+    let filter = DiagnosticFilter::new(
+        warning_options,
+        tracc::grammar::pragma::diagnostic_pragmas(meta.input()),
+    );
+    let report = |diagnostics: &Diagnostics| {
+        if json_diagnostics {
+            eprintln!("{}", diagnostics.to_json());
+        } else if !diagnostics.is_empty() {
+            eprintln!("{}", diagnostics);
+        }
+    };
+    let compiled = memory.measure(&ALLOCATOR, "parsing and IR generation", || {
+        tracc::compile_to_ir(&meta, opt.standard, &filter, nesting_limit, no_builtin)
+    });
+    let (mut module, diagnostics) = match compiled {
+        Ok(compiled) => compiled,
+        Err(diagnostics) => return Err(fail(diagnostics)),
+    };
+    let mut diagnostics = diagnostics.with_error_limit(error_limit);
+    // before the constants are merged, which refers to the functions by their place in the module
+    memory.measure(&ALLOCATOR, "inlining", || {
+        tracc::intermediate::inline::inline_functions(&mut module)
+    });
+    if merge_constants {
+        module.merge_constants();
+    }
+    let functions = module.functions;
+
+    let options = tracc::codegen::CodegenOptions {
+        branch_protection,
+        sections,
+        warnings: filter.check_options(),
+        explain: opt.explain_ir,
+        trap_on_entry: false,
+        verbose_asm,
+    };
+    let passes = tracc::intermediate::passes::PassManager::for_level(opt.opt_level);
+    let mut frame_sizes = Vec::with_capacity(functions.len());
+    let mut ir_text = String::new();
+    let mut remarks = String::new();
+    let mut miscompilations = Vec::new();
+    let mut output = functions
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut function)| {
+            let ir = function.ir;
+            let mut optimizations = Vec::new();
+            let ir = memory.measure(&ALLOCATOR, "optimization", || {
+                use tracc::intermediate::{block_args::ArgIR, interpret::PassVerifier, IR};
+                let mut verifier = opt.verify_passes.then(|| {
+                    let mut verifier = PassVerifier::new(function.name, &ir, &function.read_only);
+                    verifier.check(
+                        "block argument round trip",
+                        &IR::from(ArgIR::from(ir.clone())),
+                    );
+                    verifier
+                });
+                // a pass changed the function if it left other code than it was given
+                let mut last_seen = (verbose_asm || opt.print_ir_after_all).then(|| ir.to_string());
+                if opt.print_ir_after_all {
+                    eprint!(
+                        "; @{} before the passes\n{}",
+                        function.name,
+                        ir.named(&function.variables)
+                    );
+                }
+                let mut after_pass = |pass, ir: &IR| {
+                    if let Some(verifier) = &mut verifier {
+                        verifier.check(pass, ir);
+                    }
+                    if let Some(last_seen) = &mut last_seen {
+                        let seen = ir.to_string();
+                        if seen != *last_seen {
+                            if opt.print_ir_after_all {
+                                eprint!(
+                                    "; @{} after {}\n{}",
+                                    function.name,
+                                    pass,
+                                    ir.named(&function.variables)
+                                );
+                            }
+                            optimizations.push(pass);
+                            *last_seen = seen;
+                        }
+                    }
+                };
+                let ir = passes.run_with_remarks(ir, &mut after_pass, &mut function.remarks);
+                if let Some(verifier) = verifier {
+                    miscompilations.extend(
+                        verifier
+                            .errors
+                            .into_iter()
+                            .map(|error| error.with_source(function.span, &meta)),
+                    );
+                }
+                ir
+            });
+            function.ir = ir;
+            for optimization in optimizations {
+                function.note_optimization(optimization);
+            }
+            if remarks_file.is_some() {
+                for remark in &function.remarks {
+                    remarks.push_str(&remark.to_yaml(
+                        function.name,
+                        (&function.spans, function.span),
+                        &meta,
+                    ));
+                }
+            }
+            if let Emit::Ir = opt.emit {
+                // the IR is what's asked for, even of the functions code generation can't handle
+                writeln!(ir_text, "{}", function).expect("writing to a string can't fail");
+                return tracc::codegen::SectionedOutput::default();
+            }
+            let name = function.name;
+            let explained = opt
+                .explain_ir
+                .then(|| (function.ir.clone(), function.spans.clone()));
+            let compiled = memory.measure(&ALLOCATOR, "code generation", || {
+                tracc::codegen::codegen_function(
+                    function,
+                    index,
+                    &module.reserved_registers,
+                    tracc::codegen::CodegenOptions {
+                        trap_on_entry: trapped_functions.contains(&name),
+                        ..options
+                    },
+                    &meta,
+                )
+            });
+            if let Some((ir, spans)) = explained {
+                print!(
+                    "{}",
+                    tracc::codegen::explain::explain_function(
+                        name,
+                        &ir,
+                        &spans,
+                        &compiled.explained,
+                        &meta
+                    )
+                );
+            }
+            for warning in &compiled.warnings {
+                let flag = warning.kind().flag();
+                if filter.is_enabled(flag, warning.span().map_or(0, |span| span.offset)) {
+                    diagnostics.push_warning(warning, flag);
+                }
+            }
+            frame_sizes.push((name, compiled.frame_size));
+            compiled.assembly
+        })
+        .collect::<tracc::codegen::SectionedOutput>();
+    output.merge(tracc::codegen::module_globals(&module.globals, sections));
+    let output: Vec<_> = tracc::codegen::module_prologue(&module.metadata)
+        .chain(output)
+        .chain(tracc::codegen::module_symbols(
+            &module.aliases,
+            &module.weak_references,
+        ))
+        .chain(tracc::codegen::module_epilogue(branch_protection))
+        .into_iter()
+        .collect();
 
     //tracc::codegen::registers::debug_what_im_doing(&ir);
     // dbg!(memory_map, stack_size);
 
     // let program = tracc::variables::convert_program(program, &meta)?;
     // let output = program.compile();
-    let mut file = fs::File::create(out_file)?;
-    for x in output {
-        writeln!(file, "{}", x)?;
+    let text = memory.measure(&ALLOCATOR, "assembly output", || {
+        let mut text = String::new();
+        for x in &output {
+            writeln!(text, "{}", x)?;
+        }
+        Ok::<_, std::fmt::Error>(text)
+    })?;
+    match opt.emit {
+        Emit::Assembly => fs::write(&out_file, &text)?,
+        Emit::Ir => fs::write(&out_file, &ir_text)?,
+        Emit::Executable => {
+            let executable = tracc::codegen::link::link_executable(&output)
+                .map_err(|error| fail(error.into()))?;
+            fs::write(&out_file, executable)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&out_file, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+    }
+
+    if let Some(file) = remarks_file {
+        fs::write(file, &remarks)?;
+    }
+
+    if !miscompilations.is_empty() {
+        for error in &miscompilations {
+            diagnostics.push_error(error);
+        }
+        report(&diagnostics);
+        return Err(format!(
+            "{} pass(es) changed what the code does",
+            miscompilations.len()
+        )
+        .into());
+    }
+
+    if opt.verify_asm {
+        let assembly = SourceMetadata::new(&text).with_file(out_file.display().to_string());
+        match tracc::codegen::verify::verify_assembly(&assembly) {
+            Some(errors) if !errors.is_empty() => {
+                for error in &errors {
+                    diagnostics.push_error(error);
+                }
+                report(&diagnostics);
+                return Err(format!(
+                    "the assembler rejected {} line(s) of the generated code",
+                    errors.len()
+                )
+                .into());
+            }
+            Some(_) => (),
+            None => eprintln!(
+                "warning: no assembler for the target was found, `--verify-asm` did nothing"
+            ),
+        }
+    }
+    report(&diagnostics);
+
+    if opt.stats {
+        eprintln!("stack frame sizes:");
+        for (name, size) in frame_sizes {
+            eprintln!("  {}: {} bytes", name, size);
+        }
+        eprintln!("{}", memory);
     }
 
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Emit {
+    Assembly,
+    Ir,
+    Executable,
+}
+
+impl std::str::FromStr for Emit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(Self::Assembly),
+            "ir" => Ok(Self::Ir),
+            "exe" => Ok(Self::Executable),
+            _ => Err(format!(
+                "unknown output kind `{}`, expected `asm`, `ir` or `exe`",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 struct Opt {
     /// The file to compile
@@ -56,4 +410,81 @@ struct Opt {
     /// The (optional) output file
     #[structopt(short = "o", long = "output", parse(from_os_str))]
     output: Option<std::path::PathBuf>,
+    /// What to write: `asm` for the assembly, `ir` for the IR of each function once optimized,
+    /// with its bindings named after the variables they come from, or `exe` for a static
+    /// executable linked by tracc itself, for freestanding programs that only use what they define
+    #[structopt(long = "emit", default_value = "asm")]
+    emit: Emit,
+    /// Add a directory to search for included files (`-I<dir>`). Files named between quotes are
+    /// looked for next to the file that includes them first.
+    #[structopt(short = "I", number_of_values = 1, parse(from_os_str))]
+    include_dirs: Vec<std::path::PathBuf>,
+    /// Add a directory to search for included files after the `-I` ones
+    #[structopt(long = "isystem", number_of_values = 1, parse(from_os_str))]
+    system_include_dirs: Vec<std::path::PathBuf>,
+    /// Define a macro for the conditional directives (`-D<name>` defines it as 1, and
+    /// `-D<name>=<value>` as the value)
+    #[structopt(short = "D", number_of_values = 1)]
+    defines: Vec<String>,
+    /// Undefine a macro, even one predefined or defined with `-D`
+    #[structopt(short = "U", number_of_values = 1)]
+    undefines: Vec<String>,
+    /// The C standard to follow: c89 (or c90), c99 or c11
+    #[structopt(long = "std", default_value = "c99")]
+    standard: tracc::ast::Standard,
+    /// How much to optimize (`-O<n>`): 0 only folds the constants once, which code generation
+    /// needs, and 1 folds them until the control flow can't be simplified anymore. 2 is an alias
+    /// for 1
+    #[structopt(short = "O", default_value = "1")]
+    opt_level: tracc::intermediate::passes::OptLevel,
+    /// The machine to generate code for: `aarch64-linux-gnu`, or `aarch64-linux-gnu_ilp32` for
+    /// 32-bit `long`s and pointers
+    #[structopt(long = "target", default_value = "aarch64-linux-gnu")]
+    target: String,
+    /// Enable (`-W<name>`) or disable (`-Wno-<name>`) a warning
+    #[structopt(short = "W", number_of_values = 1)]
+    warnings: Vec<String>,
+    /// A machine-specific option (`-m<option>`): `-mbranch-protection=` takes `none`, `standard`
+    /// or a combination of `bti` and `pac-ret` joined by `+`, `-mcpu=` names the CPU the code runs
+    /// on (like `cortex-a76` or `neoverse-v2`), `-march=` the version of the architecture instead,
+    /// followed by the features added (`+lse`) or taken out (`+nolse`), and `-mattr=` adds
+    /// (`+cssc`) or takes out (`-pauth`) features of either, separated by commas
+    #[structopt(short = "m", number_of_values = 1)]
+    machine: Vec<String>,
+    /// A code generation option (`-f<option>`, or `-fno-<option>` to turn it off):
+    /// `-ffunction-sections` and `-fdata-sections` put the code and the data of each function in
+    /// sections of their own, `-fmerge-all-constants` emits the same strings and constant
+    /// aggregates of different functions once, `-fmax-errors=<n>` shows at most `n` errors (all of
+    /// them with 0), `-fdiagnostics-format=json` reports the errors and warnings as JSON,
+    /// `-fdebug-trap=<function>` (`-fdebug-trap-main` for `main`) starts the function with a
+    /// `brk #0`, for a debugger to stop at when it's called, `-fbracket-depth=<n>` rejects
+    /// statements and expressions nested more than `n` deep (128 by default), `-fverbose-asm`
+    /// sums up the frame, the saved registers, the spills and the optimizations of each function
+    /// in comments above it, `-fno-builtin` treats library functions like `exit` as any other
+    /// function, for freestanding code that defines its own, `-fopt-remarks=<file>` writes what
+    /// the optimizations did to each function and why, or why they couldn't, to the file as YAML
+    /// remarks like LLVM's, and `-finput-charset=latin1` reads the sources as Latin-1 instead of
+    /// UTF-8
+    #[structopt(short = "f", number_of_values = 1)]
+    features: Vec<String>,
+    /// Check the generated assembly with an assembler for the target, if there is one, and report
+    /// what it rejects as internal compiler errors
+    #[structopt(long = "verify-asm")]
+    verify_asm: bool,
+    /// Run the IR of each function with some sample arguments after each pass, and report the
+    /// passes that change what it does as internal compiler errors
+    #[structopt(long = "verify-passes")]
+    verify_passes: bool,
+    /// Print the IR of each function before the passes, and again after each pass that changes
+    /// it, to the standard error
+    #[structopt(long = "print-ir-after-all")]
+    print_ir_after_all: bool,
+    /// Print each source line of the functions, followed by the IR statements generated for it
+    /// and the instructions they compiled to
+    #[structopt(long = "explain-ir")]
+    explain_ir: bool,
+    /// Report the size of the stack frame of each function, and the memory each stage of the
+    /// compilation used
+    #[structopt(long = "stats")]
+    stats: bool,
 }
@@ -0,0 +1,203 @@
+//! Memory usage of the compilation, for `--stats`. The binary installs a [`CountingAllocator`] as
+//! its global allocator, so every allocation is seen: the boxes of the AST, the vectors of the IR
+//! and the buffers of the backend alike. [`MemoryStats`] then keeps the peak of each stage.
+use alloc::vec::Vec;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// An allocator that counts the bytes in use, their peak and the allocations made, handing the
+/// allocations themselves to another one
+pub struct CountingAllocator<A> {
+    inner: A,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+}
+
+impl<A> CountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// The bytes allocated and not freed yet
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// The most bytes that were in use at once since the last [`Self::reset_peak`]
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// The allocations made so far, growing a block in place or not
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+
+    /// Start measuring the peak again from the bytes in use now
+    pub fn reset_peak(&self) {
+        self.peak.store(self.current(), Ordering::Relaxed);
+    }
+
+    fn grow(&self, bytes: usize) {
+        let current = self.current.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.current.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+// UNSAFE: safe, every call is forwarded to the inner allocator as is, only the sizes are looked at
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = self.inner.alloc(layout);
+        if !pointer.is_null() {
+            self.grow(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let pointer = self.inner.alloc_zeroed(layout);
+        if !pointer.is_null() {
+            self.grow(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        self.inner.dealloc(pointer, layout);
+        self.shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let moved = self.inner.realloc(pointer, layout, new_size);
+        if !moved.is_null() {
+            self.shrink(layout.size());
+            self.grow(new_size);
+        }
+        moved
+    }
+}
+
+/// What a stage of the compilation used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageUsage {
+    pub name: &'static str,
+    /// the most bytes in use at once while it ran, counting what earlier stages left allocated
+    pub peak: usize,
+    /// the bytes it left allocated for the following stages (negative if it freed more)
+    pub retained: isize,
+    pub allocations: usize,
+}
+
+/// The memory used by each stage, in the order they first ran. A stage run several times (once
+/// per function) is kept once, with the highest peak and the sum of the rest.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStats {
+    pub stages: Vec<StageUsage>,
+}
+
+impl MemoryStats {
+    /// Run a stage, measuring it with the allocator
+    pub fn measure<A, T>(
+        &mut self,
+        allocator: &CountingAllocator<A>,
+        name: &'static str,
+        stage: impl FnOnce() -> T,
+    ) -> T {
+        allocator.reset_peak();
+        let (before, allocations) = (allocator.current(), allocator.allocations());
+        let result = stage();
+        let usage = StageUsage {
+            name,
+            peak: allocator.peak(),
+            retained: allocator.current() as isize - before as isize,
+            allocations: allocator.allocations() - allocations,
+        };
+        self.record(usage);
+        result
+    }
+
+    fn record(&mut self, usage: StageUsage) {
+        match self
+            .stages
+            .iter_mut()
+            .find(|stage| stage.name == usage.name)
+        {
+            Some(stage) => {
+                stage.peak = stage.peak.max(usage.peak);
+                stage.retained += usage.retained;
+                stage.allocations += usage.allocations;
+            }
+            None => self.stages.push(usage),
+        }
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "memory usage:")?;
+        for stage in &self.stages {
+            write!(
+                f,
+                "\n  {}: {} bytes at peak, {:+} bytes retained, {} allocations",
+                stage.name, stage.peak, stage.retained, stage.allocations
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    #[test]
+    fn stages_keep_their_peak_and_what_they_retained() {
+        let allocator = CountingAllocator::new(System);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let mut stats = MemoryStats::default();
+        // UNSAFE: safe, the layouts aren't zero sized, and each block is freed with the layout it had
+        let kept = stats.measure(&allocator, "first", || unsafe {
+            let scratch = allocator.alloc(layout);
+            let kept = allocator.alloc(layout);
+            allocator.dealloc(scratch, layout);
+            kept
+        });
+        stats.measure(&allocator, "second", || unsafe {
+            let grown = allocator.realloc(kept, layout, 256);
+            allocator.dealloc(grown, Layout::from_size_align(256, 8).unwrap());
+        });
+        stats.measure(&allocator, "first", || ());
+        assert_eq!(
+            stats.stages,
+            [
+                StageUsage {
+                    name: "first",
+                    peak: 128,
+                    retained: 64,
+                    allocations: 2,
+                },
+                StageUsage {
+                    name: "second",
+                    peak: 256,
+                    retained: -64,
+                    allocations: 1,
+                },
+            ]
+        );
+        assert_eq!(allocator.current(), 0);
+    }
+}